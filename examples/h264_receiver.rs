@@ -0,0 +1,24 @@
+use std::time::Duration;
+use rtp_transceive::prelude::*;
+
+// How long to record the stream sent by the h264_transmitter example.
+const RECORD_DURATION: Duration = Duration::from_secs(30);
+
+fn main() -> Result<(), RtpError> {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:7032")?;
+    println!("Listening on {}", receiver.local_addr()?);
+
+    // A Ctrl-C handler (e.g. from the ctrlc crate) would call cancel() on a
+    // clone of this token to stop the recording with the file intact.
+    let cancel = CancelToken::new();
+    let frames = match receiver.save_to_file("./received.h264", RECORD_DURATION, Some(&cancel)) {
+        Ok(frames) => frames,
+        Err(RtpError::Cancelled) => {
+            println!("Recording cancelled");
+            return Ok(());
+        }
+        Err(err) => return Err(err)
+    };
+    println!("Wrote {} frames to ./received.h264", frames);
+    Ok(())
+}
@@ -1,104 +1,56 @@
 use std::{fs::File, io::Read};
-use rtp_transceive::H264RtpPusher;
-use std::thread;
-use std::time::Duration;
+use rtp_transceive::annexb::nal_units;
+use rtp_transceive::prelude::*;
 
-fn main() {
-    let mut file = match File::open("./test.h264") {
-        Ok(f) => f,
-        Err(_) => {
-            println!("File could not open");
-            return;
-        }
-    };
+// 30 fps in 90 kHz ticks.
+const FRAME_DURATION_90K: u32 = 3000;
+
+fn main() -> Result<(), RtpError> {
+    let mut file = File::open("./test.h264")?;
 
-    let mut pusher = H264RtpPusher::new("127.0.0.1:7032");
+    let mut pusher = H264RtpPusher::new("127.0.0.1:7032")?;
+    pusher.warm_up(true)?;
 
     let mut buffer: Vec<u8> = Vec::new();
-    let _ = file.read_to_end(&mut buffer);
+    file.read_to_end(&mut buffer)?;
     println!("Size of buffer {}", buffer.len());
 
-    let mut remaining = &buffer[..];
-
-    loop {
-        match extract_nal(remaining) {
-            Some((nal_buf, is_last)) => {
-                remaining = &remaining[nal_buf.len()..];
-                println!("Nal found with size : {}", nal_buf.len());
-                pusher.send_frame(nal_buf);
-                thread::sleep(Duration::from_millis(33));
-
-                if is_last {
-                    println!("Last nal");
-                    break;
-                }
-            }
-            None => {
-                println!("No nal found");
-                break;
-            }
-        }
-    }
+    let access_units = split_access_units(&buffer);
+    println!("Access units found : {}", access_units.len());
+
+    let summary = pusher.stream_access_units(access_units, true, None)?;
+    println!(
+        "Sent {} frames in {} packets ({} bytes) over {:?}",
+        summary.frames, summary.packets, summary.bytes, summary.elapsed
+    );
+    let stats = pusher.payload_stats();
+    println!(
+        "IDR frames: {} ({} bytes), other frames: {} ({} bytes)",
+        stats.idr_frames, stats.idr_frame_bytes, stats.non_idr_frames, stats.non_idr_frame_bytes
+    );
+    Ok(())
 }
 
-fn extract_nal(input_buffer: &[u8]) -> Option<(&[u8], bool)> {
-    const MAX_START_CODE_LENGTH: usize = 4;
-
-    let mut is_start_found: bool = false;
-    let mut is_end_found: bool = false;
-    let mut start_code: usize = 0;
-    let mut nal_start_index: usize = 0;
-    let mut nal_end_index: usize = 0;
-
-    // Find the first nal unit.
-    for index in 0..input_buffer.len() - MAX_START_CODE_LENGTH {
-        if input_buffer[index] == 0 && input_buffer[index + 1] == 0 && input_buffer[index + 2] == 1
-        {
-            start_code = 3;
-        } else if input_buffer[index] == 0
-            && input_buffer[index + 1] == 0
-            && input_buffer[index + 2] == 0
-            && input_buffer[index + 3] == 1
-        {
-            start_code = 4;
-        } else {
-            continue;
+// Groups NALs into access units, assuming one slice per frame: SPS, PPS and
+// SEI are sent together with the slice that follows them.
+fn split_access_units(buffer: &[u8]) -> Vec<(Vec<u8>, u32)> {
+    let mut access_units = Vec::new();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut pts: u32 = 0;
+
+    for nal in nal_units(buffer) {
+        // Keep the start code: the pusher takes Annex B access units.
+        pending.extend_from_slice(&buffer[nal.offset..nal.end()]);
+
+        // Slice NALs (types 1 to 5) close the access unit.
+        if (1..=5).contains(&nal.h264_type()) {
+            access_units.push((std::mem::take(&mut pending), pts));
+            pts = pts.wrapping_add(FRAME_DURATION_90K);
         }
-        nal_start_index = index; // Keep start code because library expects Nals with start code.
-        is_start_found = true;
-        break;
     }
 
-    // If there is no start, no need to look for next one.
-    if !is_start_found {
-        return None;
+    if !pending.is_empty() {
+        access_units.push((pending, pts));
     }
-
-    // Find second Nal unit.
-    for index in nal_start_index + start_code..input_buffer.len() - MAX_START_CODE_LENGTH {
-        if input_buffer[index] == 0 && input_buffer[index + 1] == 0 && input_buffer[index + 2] == 1
-        {
-            // Check if we found a valid nal.
-            is_end_found = true;
-            nal_end_index = index;
-            break;
-        } else if input_buffer[index] == 0
-            && input_buffer[index + 1] == 0
-            && input_buffer[index + 2] == 0
-            && input_buffer[index + 3] == 1
-        {
-            // Check if we found a valid nal.
-            is_end_found = true;
-            nal_end_index = index;
-            break;
-        }
-    }
-
-    if is_start_found && is_end_found {
-        return Some((&input_buffer[nal_start_index..nal_end_index], false));
-    } else if is_start_found && !is_end_found {
-        return Some((&input_buffer[nal_start_index..], true));
-    }
-
-    return None;
-}
\ No newline at end of file
+    access_units
+}
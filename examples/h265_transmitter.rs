@@ -0,0 +1,50 @@
+use std::{fs::File, io::Read};
+use rtp_transceive::annexb::nal_units;
+use rtp_transceive::prelude::*;
+
+// 30 fps in 90 kHz ticks.
+const FRAME_DURATION_90K: u32 = 3000;
+
+fn main() -> Result<(), RtpError> {
+    let mut file = File::open("./test.h265")?;
+
+    let mut pusher = H265RtpPusher::new("127.0.0.1:7032")?;
+    pusher.set_aggregation(true);
+
+    let mut buffer: Vec<u8> = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    println!("Size of buffer {}", buffer.len());
+
+    let access_units = split_access_units(&buffer);
+    println!("Access units found : {}", access_units.len());
+
+    let summary = pusher.stream_access_units(access_units, true, None)?;
+    println!(
+        "Sent {} frames in {} packets ({} bytes) over {:?}",
+        summary.frames, summary.packets, summary.bytes, summary.elapsed
+    );
+    Ok(())
+}
+
+// Groups NALs into access units, assuming one slice per frame: VPS, SPS, PPS
+// and SEI are sent together with the slice that follows them.
+fn split_access_units(buffer: &[u8]) -> Vec<(Vec<u8>, u32)> {
+    let mut access_units = Vec::new();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut pts: u32 = 0;
+
+    for nal in nal_units(buffer) {
+        pending.extend_from_slice(&buffer[nal.offset..nal.end()]);
+
+        // VCL NALs (types 0 to 31) close the access unit.
+        if nal.h265_type() < 32 {
+            access_units.push((std::mem::take(&mut pending), pts));
+            pts = pts.wrapping_add(FRAME_DURATION_90K);
+        }
+    }
+
+    if !pending.is_empty() {
+        access_units.push((pending, pts));
+    }
+    access_units
+}
@@ -0,0 +1,43 @@
+use std::net::Ipv4Addr;
+use std::thread;
+use std::time::Duration;
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 1, 2, 3);
+const PORT: u16 = 5004;
+// Everything stays on this host; use a LAN address (and a TTL above 1 to
+// cross routers) to reach other machines. With a LAN interface the local
+// receivers only get the stream while `loopback` is on.
+const INTERFACE: Ipv4Addr = Ipv4Addr::LOCALHOST;
+const FRAMES: usize = 60;
+
+// One pusher sends to GROUP:PORT, two receivers on the same host take the
+// stream from the group.
+fn main() -> Result<(), RtpError> {
+    let receivers: Vec<_> = (0..2)
+        .map(|id| -> Result<_, RtpError> {
+            let mut receiver = H264RtpReceiver::multicast_v4(GROUP, PORT, INTERFACE)?;
+            receiver.set_idle_timeout(Some(Duration::from_secs(2)))?;
+            Ok(thread::spawn(move || {
+                let frames = receiver.frames().count();
+                println!("receiver {}: {} frames", id, frames);
+            }))
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut pusher = H264RtpPusher::new(&format!("{}:{}", GROUP, PORT))?;
+    pusher.set_multicast(MulticastOptions {
+        ttl: 1,
+        interface: Some(INTERFACE),
+        loopback: true
+    })?;
+    let frames = SyntheticH264::new(1).frame_rate(30).take(FRAMES).collect::<Vec<_>>();
+    let summary = pusher.stream_access_units(frames, true, None)?;
+    println!("sent {} frames to {}:{}", summary.frames, GROUP, PORT);
+
+    for receiver in receivers {
+        let _ = receiver.join();
+    }
+    Ok(())
+}
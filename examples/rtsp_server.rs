@@ -0,0 +1,46 @@
+use std::{fs::File, io::Read, thread, time::Duration};
+use rtp_transceive::annexb::nal_units;
+use rtp_transceive::prelude::*;
+use rtp_transceive::rtsp::RtspServer;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+// Plays ./test.h264 in a loop at rtsp://127.0.0.1:8554/stream, e.g. for
+// `ffplay rtsp://127.0.0.1:8554/stream`.
+fn main() -> Result<(), RtpError> {
+    let mut file = File::open("./test.h264")?;
+    let mut buffer: Vec<u8> = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let access_units = split_access_units(&buffer);
+    println!("Access units found : {}", access_units.len());
+
+    let server = RtspServer::bind("0.0.0.0:8554", "stream")?;
+    println!("Serving rtsp://127.0.0.1:{}/stream", server.local_addr().port());
+    let sink = server.frame_sink();
+    loop {
+        for access_unit in &access_units {
+            // Frames are dropped until a client sends PLAY, but still feed
+            // the parameter sets of the SDP.
+            sink.send_frame(access_unit)?;
+            thread::sleep(FRAME_INTERVAL);
+        }
+    }
+}
+
+// Groups NALs into access units, assuming one slice per frame, as the
+// h264_transmitter example does.
+fn split_access_units(buffer: &[u8]) -> Vec<Vec<u8>> {
+    let mut access_units = Vec::new();
+    let mut pending: Vec<u8> = Vec::new();
+    for nal in nal_units(buffer) {
+        pending.extend_from_slice(&buffer[nal.offset..nal.end()]);
+        if (1..=5).contains(&nal.h264_type()) {
+            access_units.push(std::mem::take(&mut pending));
+        }
+    }
+    if !pending.is_empty() {
+        access_units.push(pending);
+    }
+    access_units
+}
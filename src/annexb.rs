@@ -0,0 +1,268 @@
+//! Splitting Annex B byte streams (ITU-T H.264/H.265 Annex B) into NAL
+//! units.
+//!
+//! [`nal_units`] finds 3-byte (`00 00 01`) and 4-byte (`00 00 00 01`) start
+//! codes anywhere in the buffer, so leading zero padding and a start code at
+//! offset 0 both work. Zero bytes before a start code are trailing padding of
+//! the previous NAL (a NAL never ends in 0x00) and are not part of its data.
+//! Start codes followed by nothing but padding yield no NAL unit. The
+//! splitter does not look at NAL types, so it serves H.264 and H.265 alike.
+//!
+//! [`AnnexBWriter`] goes the other way for H.264, e.g. to record received
+//! frames to a file.
+//!
+//! With the `mmap` feature (unix only), [`MmapAnnexBSource`] splits a file
+//! through a memory map instead of a buffer read into memory.
+
+use std::io::{self, Write};
+
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
+
+#[cfg(all(unix, feature = "mmap"))]
+pub use mmap::{MappedAccessUnits, MappedNalUnits, MmapAnnexBSource};
+
+/// One NAL unit of an Annex B buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NalUnit<'a> {
+    /// Offset of the start code in the buffer.
+    pub offset: usize,
+    /// 3 or 4.
+    pub start_code_len: usize,
+    /// Header and payload, without the start code or trailing zero bytes.
+    /// Never empty.
+    pub data: &'a [u8]
+}
+
+impl<'a> NalUnit<'a> {
+    /// Offset of the NAL header in the buffer.
+    pub fn data_offset(&self) -> usize {
+        self.offset + self.start_code_len
+    }
+
+    /// Offset just past the NAL data, so `offset..end()` is the unit with
+    /// its start code.
+    pub fn end(&self) -> usize {
+        self.data_offset() + self.data.len()
+    }
+
+    /// nal_unit_type read as an H.264 header (bits 0-4 of the first byte).
+    pub fn h264_type(&self) -> u8 {
+        self.data[0] & 0x1F
+    }
+
+    /// nal_unit_type read as an H.265 header (bits 1-6 of the first byte).
+    pub fn h265_type(&self) -> u8 {
+        (self.data[0] >> 1) & 0x3F
+    }
+}
+
+/// Start codes [`AnnexBWriter`] puts before NAL units that may take either.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartCodeStyle {
+    /// 00 00 00 01 everywhere, as the receiver's frames have it.
+    #[default]
+    Long,
+    /// 00 00 01 where the stream allows it, 1 byte less per slice.
+    Short
+}
+
+const LONG_START_CODE: [u8; 4] = [0, 0, 0, 1];
+const H264_IDR: u8 = 5;
+const H264_SPS: u8 = 7;
+const H264_PPS: u8 = 8;
+
+/// Writes H.264 NAL units as an Annex B byte stream. The first NAL unit
+/// and every SPS, PPS and IDR slice get a 4-byte start code whatever the
+/// style: ffmpeg probes a stream by its first start code, and decoders
+/// look for the long form at random access points.
+#[derive(Debug)]
+pub struct AnnexBWriter<W> {
+    out: W,
+    style: StartCodeStyle,
+    started: bool
+}
+
+impl<W: Write> AnnexBWriter<W> {
+    pub fn new(out: W, style: StartCodeStyle) -> Self {
+        Self {
+            out,
+            style,
+            started: false
+        }
+    }
+
+    /// Writes the NAL units of `frame`, an Annex B buffer with start codes
+    /// of either length, such as a received frame.
+    pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        for nal in nal_units(frame) {
+            self.write_nal(nal.data)?;
+        }
+        Ok(())
+    }
+
+    /// Writes one NAL unit, header included, behind a start code.
+    pub fn write_nal(&mut self, nal: &[u8]) -> io::Result<()> {
+        let long = match self.style {
+            StartCodeStyle::Long => true,
+            StartCodeStyle::Short => {
+                !self.started || nal.first().is_some_and(|&header| matches!(header & 0x1F, H264_IDR | H264_SPS | H264_PPS))
+            }
+        };
+        let start_code = if long { &LONG_START_CODE[..] } else { &LONG_START_CODE[1..] };
+        self.out.write_all(start_code)?;
+        self.out.write_all(nal)?;
+        self.started = true;
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// The NAL units of `buffer`, in order.
+pub fn nal_units(buffer: &[u8]) -> NalUnits<'_> {
+    NalUnits { buffer, position: 0 }
+}
+
+/// Iterator returned by [`nal_units`].
+#[derive(Clone, Debug)]
+pub struct NalUnits<'a> {
+    buffer: &'a [u8],
+    // Where the search for the next start code begins.
+    position: usize
+}
+
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = NalUnit<'a>;
+
+    fn next(&mut self) -> Option<NalUnit<'a>> {
+        loop {
+            let (offset, start_code_len) = find_start_code(self.buffer, self.position)?;
+            let data_start = offset + start_code_len;
+            let end = find_start_code(self.buffer, data_start).map_or(self.buffer.len(), |(next, _)| next);
+            self.position = end;
+
+            let mut data_end = end;
+            while data_end > data_start && self.buffer[data_end - 1] == 0 {
+                data_end -= 1;
+            }
+            if data_end > data_start {
+                return Some(NalUnit {
+                    offset,
+                    start_code_len,
+                    data: &self.buffer[data_start..data_end]
+                });
+            }
+        }
+    }
+}
+
+// Offset and length of the first start code at or after `from`: 00 00 01,
+// or 00 00 00 01 when a zero byte precedes it.
+fn find_start_code(buffer: &[u8], from: usize) -> Option<(usize, usize)> {
+    let index = from + buffer.get(from..)?.windows(3).position(|window| window == [0, 0, 1])?;
+    if index > from && buffer[index - 1] == 0 {
+        Some((index - 1, 4))
+    } else {
+        Some((index, 3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 3] = [0x67, 0x42, 0x1E];
+    const PPS: [u8; 2] = [0x68, 0xCE];
+    const IDR: [u8; 3] = [0x65, 0x88, 0x84];
+    const SLICE: [u8; 3] = [0x41, 0x9A, 0x02];
+
+    fn frame(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter().flat_map(|nal| LONG_START_CODE.iter().chain(nal.iter()).copied()).collect()
+    }
+
+    fn written(style: StartCodeStyle, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut writer = AnnexBWriter::new(Vec::new(), style);
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn split(buffer: &[u8]) -> Vec<(usize, usize, &[u8])> {
+        nal_units(buffer).map(|nal| (nal.offset, nal.start_code_len, nal.data)).collect()
+    }
+
+    #[test]
+    fn the_last_nal_unit_runs_to_the_end_of_the_buffer() {
+        let buffer = [&[0, 0, 0, 1][..], &SPS, &[0, 0, 1], &SLICE].concat();
+        assert_eq!(split(&buffer), [(0, 4, &SPS[..]), (7, 3, &SLICE[..])]);
+        // Two bytes, then one, of NAL data after the last start code.
+        let buffer = [0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xCE];
+        assert_eq!(split(&buffer), [(0, 4, &[0x67, 0x42][..]), (6, 3, &[0x68, 0xCE][..])]);
+        assert_eq!(split(&[0, 0, 1, 0x65]), [(0, 3, &[0x65][..])]);
+        // A start code in the last four bytes is still found.
+        assert_eq!(split(&[0, 0, 1, 0x41, 0x9A, 0, 0, 1, 0x65]), [(0, 3, &[0x41, 0x9A][..]), (5, 3, &[0x65][..])]);
+    }
+
+    #[test]
+    fn trailing_zeros_and_bare_start_codes_at_the_end_are_dropped() {
+        let buffer = [&[0, 0, 0, 1][..], &SLICE, &[0, 0, 0, 0]].concat();
+        assert_eq!(split(&buffer), [(0, 4, &SLICE[..])]);
+        for tail in [&[0, 0, 1][..], &[0, 0, 0, 1], &[0, 0, 1, 0, 0], &[0, 0], &[0]] {
+            let buffer = [&[0, 0, 1][..], &IDR, tail].concat();
+            assert_eq!(split(&buffer), [(0, 3, &IDR[..])], "{:?}", tail);
+        }
+    }
+
+    #[test]
+    fn buffers_without_nal_data_yield_nothing() {
+        for buffer in [&[][..], &[0], &[0, 0, 1], &[0, 0, 0, 1], &[0, 0, 1, 0, 0, 1], &[0x65, 0x88], &[0, 0, 0, 0, 0]] {
+            assert_eq!(split(buffer), [], "{:?}", buffer);
+        }
+    }
+
+    #[test]
+    fn leading_padding_and_short_codes_mid_buffer() {
+        let buffer = [&[0, 0, 0, 0, 0, 1][..], &SPS, &[0, 0, 1], &PPS, &[0, 0, 0, 1], &IDR].concat();
+        assert_eq!(split(&buffer), [(2, 4, &SPS[..]), (9, 3, &PPS[..]), (14, 4, &IDR[..])]);
+    }
+
+    #[test]
+    fn parameter_sets_and_idr_slices_keep_long_start_codes() {
+        let stream = written(StartCodeStyle::Short, &[frame(&[&SPS, &PPS, &IDR]), frame(&[&SLICE, &SLICE])]);
+        assert_eq!(
+            stream,
+            [
+                &[0, 0, 0, 1][..], &SPS,
+                &[0, 0, 0, 1], &PPS,
+                &[0, 0, 0, 1], &IDR,
+                &[0, 0, 1], &SLICE,
+                &[0, 0, 1], &SLICE
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn the_first_nal_unit_keeps_a_long_start_code() {
+        // A recording that joined mid-GOP starts on a non-IDR slice.
+        let stream = written(StartCodeStyle::Short, &[frame(&[&SLICE]), frame(&[&SLICE]), frame(&[&SPS])]);
+        assert_eq!(stream, [&[0, 0, 0, 1][..], &SLICE, &[0, 0, 1], &SLICE, &[0, 0, 0, 1], &SPS].concat());
+    }
+
+    #[test]
+    fn long_style_and_short_input_codes() {
+        // Input start codes may be short; the style alone decides.
+        let input = [&[0, 0, 1][..], &SLICE, &[0, 0, 1], &IDR, &[0, 0, 1], &SLICE].concat();
+        let stream = written(StartCodeStyle::Long, &[input]);
+        assert_eq!(stream, frame(&[&SLICE, &IDR, &SLICE]));
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::path::Path;
+
+use super::{nal_units, NalUnit, NalUnits};
+use crate::payload::h264::slice_position;
+use crate::platform::FileMap;
+use crate::RtpError;
+
+// See set_window.
+const DEFAULT_WINDOW: usize = 8 << 20;
+const MIN_WINDOW: usize = 1 << 20;
+
+/// An Annex B file read through a memory map, for recordings too large to
+/// read into memory. NAL units and access units borrow the map, so nothing
+/// is copied before the pusher packetizes them.
+///
+/// As the iterators move through the file they ask the kernel to read the
+/// next window ahead and to drop what lies more than a window behind, so
+/// the resident set stays at a few windows however large the file is.
+/// Bytes read again are read in again from the file.
+///
+/// The file must not be truncated while it is mapped: reading a page past
+/// its new end raises SIGBUS.
+pub struct MmapAnnexBSource {
+    map: FileMap,
+    window: usize
+}
+
+impl MmapAnnexBSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RtpError> {
+        let file = File::open(path)?;
+        Ok(Self {
+            map: FileMap::new(&file)?,
+            window: DEFAULT_WINDOW
+        })
+    }
+
+    /// Bytes read ahead of the iterators and kept resident behind them, 8
+    /// MiB by default and at least 1 MiB. Applies to iterators made after
+    /// the call.
+    pub fn set_window(&mut self, bytes: usize) {
+        self.window = bytes.max(MIN_WINDOW);
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The whole file, without read-ahead or release hints.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.map.bytes()
+    }
+
+    /// The NAL units of the file, in order, as [`nal_units`] splits them.
+    pub fn nal_units(&self) -> MappedNalUnits<'_> {
+        MappedNalUnits {
+            nals: nal_units(self.map.bytes()),
+            map: &self.map,
+            window: self.window,
+            prefetched: 0,
+            released: 0
+        }
+    }
+
+    /// The H.264 access units of the file with their start codes, ready for
+    /// `H264RtpPusher::stream_access_units`, paired with PTS that start at
+    /// 0 and step by `frame_duration` 90 kHz ticks.
+    ///
+    /// An access unit ends before an access unit delimiter, SEI, SPS, PPS or
+    /// NAL type 14 to 18, or before a slice with first_mb_in_slice 0, once
+    /// it has a slice (H.264 section 7.4.1.2.3); other NAL units join the
+    /// access unit they follow. The PTS assume the file is in presentation
+    /// order, as a stream without B-frames is.
+    pub fn access_units(&self, frame_duration: u32) -> MappedAccessUnits<'_> {
+        MappedAccessUnits {
+            bytes: self.map.bytes(),
+            nals: self.nal_units(),
+            pending: None,
+            has_slice: false,
+            pts: 0,
+            frame_duration
+        }
+    }
+}
+
+/// Iterator returned by [`MmapAnnexBSource::nal_units`].
+pub struct MappedNalUnits<'a> {
+    nals: NalUnits<'a>,
+    map: &'a FileMap,
+    window: usize,
+    // Ends of the ranges asked for ahead and given back behind.
+    prefetched: usize,
+    released: usize
+}
+
+impl<'a> Iterator for MappedNalUnits<'a> {
+    type Item = NalUnit<'a>;
+
+    fn next(&mut self) -> Option<NalUnit<'a>> {
+        let nal = self.nals.next()?;
+        let position = nal.end();
+        if position + self.window > self.prefetched {
+            self.prefetched = position + 2 * self.window;
+            self.map.prefetch(position, self.prefetched);
+        }
+        if position > self.released + 2 * self.window {
+            let behind = position - self.window;
+            self.map.release(self.released, behind);
+            self.released = behind;
+        }
+        Some(nal)
+    }
+}
+
+/// Iterator returned by [`MmapAnnexBSource::access_units`].
+pub struct MappedAccessUnits<'a> {
+    bytes: &'a [u8],
+    nals: MappedNalUnits<'a>,
+    // Range of the access unit being gathered, start codes included.
+    pending: Option<(usize, usize)>,
+    has_slice: bool,
+    pts: u32,
+    frame_duration: u32
+}
+
+impl<'a> MappedAccessUnits<'a> {
+    fn emit(&mut self, (start, end): (usize, usize)) -> (&'a [u8], u32) {
+        let pts = self.pts;
+        self.pts = self.pts.wrapping_add(self.frame_duration);
+        (&self.bytes[start..end], pts)
+    }
+}
+
+impl<'a> Iterator for MappedAccessUnits<'a> {
+    type Item = (&'a [u8], u32);
+
+    fn next(&mut self) -> Option<(&'a [u8], u32)> {
+        loop {
+            let nal = match self.nals.next() {
+                Some(nal) => nal,
+                None => {
+                    let pending = self.pending.take()?;
+                    return Some(self.emit(pending));
+                }
+            };
+            let nal_type = nal.h264_type();
+            let slice = (1..=5).contains(&nal_type);
+            let starts_access_unit = self.has_slice
+                && if slice {
+                    slice_position(nal.data).is_some_and(|(first_mb_in_slice, _)| first_mb_in_slice == 0)
+                } else {
+                    matches!(nal_type, 6..=9 | 14..=18)
+                };
+            if starts_access_unit {
+                self.has_slice = slice;
+                let done = self.pending.replace((nal.offset, nal.end()));
+                return done.map(|done| self.emit(done));
+            }
+            match &mut self.pending {
+                Some((_, end)) => *end = nal.end(),
+                None => self.pending = Some((nal.offset, nal.end()))
+            }
+            self.has_slice |= slice;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::SyntheticH264;
+
+    #[test]
+    fn access_units_come_back_as_written() {
+        let frames: Vec<(Vec<u8>, u32)> = SyntheticH264::new(4).gop_length(5).slices_per_frame(3).take(12).collect();
+        let path = std::env::temp_dir().join(format!("rtp_transceive_mmap_unit_{}.h264", std::process::id()));
+        std::fs::write(&path, frames.iter().flat_map(|(frame, _)| frame.iter().copied()).collect::<Vec<u8>>()).unwrap();
+
+        let source = MmapAnnexBSource::open(&path).unwrap();
+        let access_units: Vec<(&[u8], u32)> = source.access_units(3000).collect();
+        assert_eq!(access_units.len(), frames.len());
+        for (index, ((mapped, pts), (frame, _))) in access_units.iter().zip(&frames).enumerate() {
+            assert_eq!(mapped, frame);
+            assert_eq!(*pts, index as u32 * 3000);
+        }
+        drop(source);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn an_empty_file_has_no_access_units() {
+        let path = std::env::temp_dir().join(format!("rtp_transceive_mmap_empty_{}.h264", std::process::id()));
+        std::fs::write(&path, []).unwrap();
+        let source = MmapAnnexBSource::open(&path).unwrap();
+        assert!(source.is_empty());
+        assert_eq!(source.access_units(3000).count(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::RtpError;
+
+/// Aborts blocking pusher and receiver operations from another thread.
+///
+/// Clones share one flag. Cancelling wakes every wait on the token right
+/// away, so a blocked call returns `RtpError::Cancelled` within thread
+/// wake-up latency plus, at most, the time to finish the packet or frame in
+/// flight (see the individual APIs). A token stays cancelled; use a new one
+/// for the next operation.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: Mutex<bool>,
+    wake: Condvar
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        *self.lock() = true;
+        self.inner.wake.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.lock()
+    }
+
+    // Sleeps for `duration` unless cancelled first.
+    pub(crate) fn sleep(&self, duration: Duration) -> Result<(), RtpError> {
+        let deadline = Instant::now() + duration;
+        let mut cancelled = self.lock();
+        loop {
+            if *cancelled {
+                return Err(RtpError::Cancelled);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            cancelled = match self.inner.wake.wait_timeout(cancelled, deadline - now) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => poisoned.into_inner().0
+            };
+        }
+    }
+
+    pub(crate) fn check(&self) -> Result<(), RtpError> {
+        if self.is_cancelled() {
+            Err(RtpError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    // `sleep` for code that reports errors as io::Error; see `is_io_cancelled`.
+    pub(crate) fn sleep_io(&self, duration: Duration) -> io::Result<()> {
+        self.sleep(duration)
+            .map_err(|err| io::Error::new(io::ErrorKind::Interrupted, err))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, bool> {
+        // The flag is a plain bool, so a panic elsewhere cannot leave it
+        // inconsistent.
+        self.inner.cancelled.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Whether an io::Error is a cancellation from `CancelToken::sleep_io`.
+pub(crate) fn is_io_cancelled(err: &io::Error) -> bool {
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<RtpError>())
+        .is_some_and(|err| matches!(err, RtpError::Cancelled))
+}
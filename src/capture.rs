@@ -0,0 +1,339 @@
+//! Packet captures in pcap format, for looking at a stream in Wireshark
+//! without running tcpdump next to the application, and for feeding a
+//! recorded stream back through a receiver.
+//!
+//! `H264RtpPusher::enable_capture` and `H264RtpReceiver::enable_capture`
+//! write every RTP packet they send or receive; [`PcapWriter`] can also be
+//! used on its own. Packets are stored as raw IP (link type 101) with the
+//! IPv4 or IPv6 and UDP headers filled in, checksums included, so
+//! "Decode As... RTP" on the UDP port is all Wireshark needs.
+//!
+//! [`PcapSource`] reads pcap and pcapng files, from this crate or any
+//! capture tool, and through `H264RtpReceiver::from_source` turns a
+//! capture of a misbehaving stream into a reproducible input.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::RtpError;
+
+mod reader;
+pub use reader::{CapturedPacket, PcapSource};
+
+const PCAP_MAGIC: u32 = 0xA1B2_C3D4;
+const PCAP_VERSION: (u16, u16) = (2, 4);
+const SNAPLEN: u32 = 65_535;
+// LINKTYPE_RAW: each record starts at the IP header, version 4 or 6.
+const LINKTYPE_RAW: u32 = 101;
+const FILE_HEADER_SIZE: u64 = 24;
+const RECORD_HEADER_SIZE: usize = 16;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+const IP_PROTOCOL_UDP: u8 = 17;
+const TTL: u8 = 64;
+// Stands in for an address the owner of a capture does not know.
+pub(crate) const UNKNOWN: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+
+/// Starts a new capture file once the current one would grow past
+/// `max_bytes`. The full file is renamed with a number before its
+/// extension (`out.pcap` becomes `out.1.pcap`, `out.1.pcap` becomes
+/// `out.2.pcap`, and so on) and only the `files` most recent ones are
+/// kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureRotation {
+    pub max_bytes: u64,
+    pub files: usize
+}
+
+/// Writes RTP packets into a pcap file as UDP datagrams, stamped with the
+/// wall-clock time they are written. Output is buffered; `flush` or
+/// dropping the writer gets it to disk.
+pub struct PcapWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    // Bytes in the current file, its header included.
+    bytes: u64,
+    rotation: Option<CaptureRotation>,
+    // IPv4 identification field, one per packet.
+    ip_id: u16,
+    // Reused for each record.
+    record: Vec<u8>
+}
+
+impl PcapWriter {
+    /// Creates (or truncates) the capture file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, RtpError> {
+        let path = path.as_ref().to_path_buf();
+        let file = open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            bytes: FILE_HEADER_SIZE,
+            rotation: None,
+            ip_id: 0,
+            record: Vec::new()
+        })
+    }
+
+    /// `None` (the default) lets the file grow without limit.
+    pub fn set_rotation(&mut self, rotation: Option<CaptureRotation>) {
+        self.rotation = rotation;
+    }
+
+    /// Appends `packet` as the payload of a UDP datagram from `source` to
+    /// `destination`. An IPv4 address paired with an IPv6 one is written
+    /// in its IPv4-mapped form. A packet too large for a UDP datagram is
+    /// rejected with `InvalidInput` before anything is written.
+    pub fn write_packet(
+        &mut self,
+        source: SocketAddr,
+        destination: SocketAddr,
+        packet: &[u8],
+    ) -> Result<(), RtpError> {
+        self.build_record(source, destination, packet)?;
+        if let Some(rotation) = self.rotation {
+            let full = self.bytes + self.record.len() as u64 > rotation.max_bytes;
+            // A record larger than max_bytes still gets a file of its own.
+            if full && self.bytes > FILE_HEADER_SIZE {
+                self.rotate(rotation.files)?;
+            }
+        }
+        self.file.write_all(&self.record)?;
+        self.bytes += self.record.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), RtpError> {
+        Ok(self.file.flush()?)
+    }
+
+    fn build_record(&mut self, source: SocketAddr, destination: SocketAddr, packet: &[u8]) -> io::Result<()> {
+        let udp_len = UDP_HEADER_SIZE + packet.len();
+        let (source_ip, destination_ip) = match (source.ip(), destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => (IpAddr::V4(source), IpAddr::V4(destination)),
+            (source, destination) => (IpAddr::V6(to_v6(source)), IpAddr::V6(to_v6(destination)))
+        };
+        let ip_header_size = match source_ip {
+            IpAddr::V4(_) => IPV4_HEADER_SIZE,
+            IpAddr::V6(_) => IPV6_HEADER_SIZE
+        };
+        // IPv4 counts its header in the total length, IPv6 does not.
+        let length_field = match source_ip {
+            IpAddr::V4(_) => ip_header_size + udp_len,
+            IpAddr::V6(_) => udp_len
+        };
+        if length_field > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "packet too large for a UDP datagram"));
+        }
+        let captured = ip_header_size + udp_len;
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let record = &mut self.record;
+        record.clear();
+        record.extend_from_slice(&(elapsed.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&elapsed.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(captured as u32).to_le_bytes());
+        record.extend_from_slice(&(captured as u32).to_le_bytes());
+
+        let ip_start = record.len();
+        match (source_ip, destination_ip) {
+            (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+                record.extend_from_slice(&[0x45, 0]);
+                record.extend_from_slice(&(length_field as u16).to_be_bytes());
+                record.extend_from_slice(&self.ip_id.to_be_bytes());
+                // Don't Fragment, as the pusher sends.
+                record.extend_from_slice(&[0x40, 0, TTL, IP_PROTOCOL_UDP, 0, 0]);
+                record.extend_from_slice(&source_ip.octets());
+                record.extend_from_slice(&destination_ip.octets());
+                let checksum = !fold(sum(&record[ip_start..]));
+                record[ip_start + 10..ip_start + 12].copy_from_slice(&checksum.to_be_bytes());
+                self.ip_id = self.ip_id.wrapping_add(1);
+            }
+            (source_ip, destination_ip) => {
+                record.extend_from_slice(&[0x60, 0, 0, 0]);
+                record.extend_from_slice(&(length_field as u16).to_be_bytes());
+                record.extend_from_slice(&[IP_PROTOCOL_UDP, TTL]);
+                record.extend_from_slice(&to_v6(source_ip).octets());
+                record.extend_from_slice(&to_v6(destination_ip).octets());
+            }
+        }
+
+        let udp_start = record.len();
+        record.extend_from_slice(&source.port().to_be_bytes());
+        record.extend_from_slice(&destination.port().to_be_bytes());
+        record.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        record.extend_from_slice(&[0, 0]);
+        record.extend_from_slice(packet);
+
+        // The pseudo-header: both addresses, the protocol and the UDP
+        // length (RFC 768, RFC 8200 section 8.1).
+        let addresses = match source_ip {
+            IpAddr::V4(_) => ip_start + 12..udp_start,
+            IpAddr::V6(_) => ip_start + 8..udp_start
+        };
+        let pseudo_header = sum(&record[addresses]) + IP_PROTOCOL_UDP as u32 + udp_len as u32;
+        let checksum = match !fold(pseudo_header + sum(&record[udp_start..])) {
+            // Zero means "no checksum"; its one's complement twin is sent.
+            0 => 0xFFFF,
+            checksum => checksum
+        };
+        record[udp_start + 6..udp_start + 8].copy_from_slice(&checksum.to_be_bytes());
+        debug_assert_eq!(record.len(), RECORD_HEADER_SIZE + captured);
+        Ok(())
+    }
+
+    // Moves the full file aside, oldest numbers first so nothing is
+    // overwritten, and starts a new one at `path`.
+    fn rotate(&mut self, files: usize) -> io::Result<()> {
+        self.file.flush()?;
+        if files == 0 {
+            self.file = open(&self.path)?;
+            self.bytes = FILE_HEADER_SIZE;
+            return Ok(());
+        }
+        ignore_missing(fs::remove_file(numbered(&self.path, files)))?;
+        for number in (1..files).rev() {
+            ignore_missing(fs::rename(numbered(&self.path, number), numbered(&self.path, number + 1)))?;
+        }
+        fs::rename(&self.path, numbered(&self.path, 1))?;
+        self.file = open(&self.path)?;
+        self.bytes = FILE_HEADER_SIZE;
+        Ok(())
+    }
+}
+
+// A writer attached to a pusher or receiver. Capturing is best effort: a
+// failed write ends the capture, never the stream, and the error is kept
+// for the owner to report.
+pub(crate) struct Capture {
+    writer: Option<PcapWriter>,
+    // The owner's address and that of the other end, looked up once per
+    // writer.
+    local: SocketAddr,
+    peer: SocketAddr,
+    error: Option<RtpError>
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self {
+            writer: None,
+            local: UNKNOWN,
+            peer: UNKNOWN,
+            error: None
+        }
+    }
+
+    pub fn set(&mut self, writer: Option<PcapWriter>, local: SocketAddr, peer: SocketAddr) {
+        self.writer = writer;
+        self.local = local;
+        self.peer = peer;
+        self.error = None;
+    }
+
+    // The other end moved, e.g. to a new destination.
+    pub fn set_peer(&mut self, peer: SocketAddr) {
+        self.peer = peer;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn local(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn error(&self) -> Option<&RtpError> {
+        self.error.as_ref()
+    }
+
+    pub fn write(&mut self, source: SocketAddr, destination: SocketAddr, packet: &[u8]) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(err) = writer.write_packet(source, destination, packet) {
+                self.stop(err);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(err) = writer.flush() {
+                self.stop(err);
+            }
+        }
+    }
+
+    fn stop(&mut self, err: RtpError) {
+        self.writer = None;
+        self.error = Some(err);
+    }
+}
+
+// The unspecified address of `local`'s family, for a peer that is not
+// known.
+pub(crate) fn unknown_peer(local: SocketAddr) -> SocketAddr {
+    match local {
+        SocketAddr::V4(_) => UNKNOWN,
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+    }
+}
+
+fn open(path: &Path) -> io::Result<BufWriter<File>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(&PCAP_MAGIC.to_le_bytes())?;
+    file.write_all(&PCAP_VERSION.0.to_le_bytes())?;
+    file.write_all(&PCAP_VERSION.1.to_le_bytes())?;
+    // Time zone offset and timestamp accuracy, both always 0.
+    file.write_all(&[0; 8])?;
+    file.write_all(&SNAPLEN.to_le_bytes())?;
+    file.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+    Ok(file)
+}
+
+// out.pcap -> out.<number>.pcap
+fn numbered(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, number)
+    };
+    path.with_file_name(name)
+}
+
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result
+    }
+}
+
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip
+    }
+}
+
+// One's complement sum of big-endian 16-bit words, odd byte padded.
+fn sum(bytes: &[u8]) -> u32 {
+    bytes
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | word.get(1).copied().map_or(0, u32::from))
+        .sum()
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
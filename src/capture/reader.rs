@@ -0,0 +1,552 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::receiver::RtpSource;
+use crate::RtpError;
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPTION_END: u16 = 0;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+// Larger blocks mean a corrupt file rather than a packet.
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+const IP_PROTOCOL_UDP: u8 = 17;
+// IPv6 extension headers that may sit before UDP (RFC 8200 section 4).
+const IPV6_HOP_BY_HOP: u8 = 0;
+const IPV6_ROUTING: u8 = 43;
+const IPV6_DESTINATION_OPTIONS: u8 = 60;
+
+/// A UDP datagram read from a capture file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CapturedPacket {
+    /// When it was captured, as recorded in the file.
+    pub timestamp: SystemTime,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// The UDP payload, i.e. the RTP packet.
+    pub payload: Vec<u8>
+}
+
+/// Reads the UDP datagrams of a pcap or pcapng file, e.g. one saved by
+/// Wireshark or written by [`PcapWriter`](super::PcapWriter), in file
+/// order.
+///
+/// Ethernet (VLAN tags included), Linux cooked, BSD loopback and raw IP
+/// captures are understood; packets of other link types, IP fragments and
+/// datagrams cut short by the snapshot length are skipped. As an iterator
+/// it yields each datagram with its capture time. Given to
+/// `H264RtpReceiver::from_source`, it feeds the payloads through the
+/// receiver as if they had just arrived.
+pub struct PcapSource {
+    input: BufReader<File>,
+    format: Format,
+    port: Option<u16>,
+    real_time: bool,
+    // The first packet's capture time and when it was replayed.
+    anchor: Option<(SystemTime, Instant)>,
+    // Read, but not yet due when replaying in real time.
+    pending: Option<CapturedPacket>,
+    done: bool
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanos: bool,
+        link_type: u16
+    },
+    PcapNg {
+        big_endian: bool,
+        // Of the current section, by interface id.
+        interfaces: Vec<Interface>
+    }
+}
+
+struct Interface {
+    link_type: u16,
+    // Timestamp units per second, as if_tsresol sets them.
+    resolution: TimestampResolution
+}
+
+#[derive(Clone, Copy)]
+enum TimestampResolution {
+    PowerOfTen(u32),
+    PowerOfTwo(u32)
+}
+
+impl PcapSource {
+    /// Opens the capture at `path`. With `port`, only datagrams sent to
+    /// that UDP port are read, which leaves out the RTCP on the next port
+    /// and any other traffic; `None` reads every UDP datagram.
+    pub fn open<P: AsRef<Path>>(path: P, port: Option<u16>) -> Result<Self, RtpError> {
+        let mut input = BufReader::new(File::open(path)?);
+        let magic = read_array::<4>(&mut input)?;
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            Format::PcapNg {
+                big_endian: read_section_header(&mut input)?,
+                interfaces: Vec::new()
+            }
+        } else {
+            let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (PCAP_MAGIC_MICROS, _) => (false, false),
+                (PCAP_MAGIC_NANOS, _) => (false, true),
+                (_, PCAP_MAGIC_MICROS) => (true, false),
+                (_, PCAP_MAGIC_NANOS) => (true, true),
+                _ => return Err(invalid("not a pcap or pcapng file").into())
+            };
+            let header = read_array::<20>(&mut input)?;
+            // The upper bits of the link type field carry FCS information.
+            let link_type = u32_at(&header, 16, big_endian) as u16;
+            Format::Pcap {
+                big_endian,
+                nanos,
+                link_type
+            }
+        };
+        Ok(Self {
+            input,
+            format,
+            port,
+            real_time: false,
+            anchor: None,
+            pending: None,
+            done: false
+        })
+    }
+
+    /// Hands out packets no faster than they were captured, spaced as in
+    /// the file from the first one read on. Off (the default), they come
+    /// as fast as they can be read.
+    pub fn set_real_time(&mut self, enabled: bool) {
+        self.real_time = enabled;
+    }
+
+    // The next matching datagram in the file, None at its end.
+    fn read_packet(&mut self) -> io::Result<Option<CapturedPacket>> {
+        loop {
+            let record = match &mut self.format {
+                Format::Pcap {
+                    big_endian,
+                    nanos,
+                    link_type
+                } => read_pcap_record(&mut self.input, *big_endian, *nanos, *link_type)?,
+                Format::PcapNg {
+                    big_endian,
+                    interfaces
+                } => read_pcapng_record(&mut self.input, big_endian, interfaces)?
+            };
+            let (timestamp, link_type, frame) = match record {
+                Some(Record::Packet {
+                    timestamp,
+                    link_type,
+                    frame
+                }) => (timestamp, link_type, frame),
+                Some(Record::Other) => continue,
+                None => return Ok(None)
+            };
+            let datagram = match parse_frame(link_type, &frame) {
+                Some(datagram) => datagram,
+                None => continue
+            };
+            if self.port.is_some_and(|port| port != datagram.1.port()) {
+                continue;
+            }
+            return Ok(Some(CapturedPacket {
+                timestamp,
+                source: datagram.0,
+                destination: datagram.1,
+                payload: frame[datagram.2].to_vec()
+            }));
+        }
+    }
+
+    // When a packet captured at `timestamp` is due in real time.
+    fn due(&mut self, timestamp: SystemTime) -> Instant {
+        let (first, replayed_at) = *self.anchor.get_or_insert((timestamp, Instant::now()));
+        // A capture time before the first one is due at once.
+        replayed_at + timestamp.duration_since(first).unwrap_or_default()
+    }
+
+    // The next packet once it is due, or TimedOut if that is further off
+    // than `timeout`; UnexpectedEof at the end of the file. A file cut
+    // short inside a record ends after the last whole one, as captures of
+    // a crashing process often are.
+    fn next_due(&mut self, timeout: Option<Duration>) -> io::Result<CapturedPacket> {
+        let packet = match self.pending.take() {
+            Some(packet) => packet,
+            None if self.done => return Err(io::ErrorKind::UnexpectedEof.into()),
+            None => match self.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.done = true;
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Err(err) => {
+                    // Nothing after a malformed record can be trusted.
+                    self.done = true;
+                    return Err(err);
+                }
+            }
+        };
+        if self.real_time {
+            let wait = self.due(packet.timestamp).saturating_duration_since(Instant::now());
+            if let Some(timeout) = timeout.filter(|&timeout| timeout < wait) {
+                thread::sleep(timeout);
+                self.pending = Some(packet);
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            thread::sleep(wait);
+        }
+        Ok(packet)
+    }
+}
+
+impl Iterator for PcapSource {
+    type Item = Result<CapturedPacket, RtpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_due(None) {
+            Ok(packet) => Some(Ok(packet)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(err) => Some(Err(err.into()))
+        }
+    }
+}
+
+impl std::iter::FusedIterator for PcapSource {}
+
+impl RtpSource for PcapSource {
+    fn recv_packet(&mut self, out: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let packet = self.next_due(timeout)?;
+        let len = packet.payload.len().min(out.len());
+        out[..len].copy_from_slice(&packet.payload[..len]);
+        Ok(len)
+    }
+}
+
+enum Record {
+    Packet {
+        timestamp: SystemTime,
+        link_type: u16,
+        frame: Vec<u8>
+    },
+    // A block that holds no packet.
+    Other
+}
+
+fn read_pcap_record(
+    input: &mut impl Read,
+    big_endian: bool,
+    nanos: bool,
+    link_type: u16,
+) -> io::Result<Option<Record>> {
+    let header = match read_array_or_end::<16>(input)? {
+        Some(header) => header,
+        None => return Ok(None)
+    };
+    let seconds = u32_at(&header, 0, big_endian) as u64;
+    let fraction = u32_at(&header, 4, big_endian);
+    let captured = u32_at(&header, 8, big_endian) as usize;
+    let original = u32_at(&header, 12, big_endian) as usize;
+    if captured > MAX_BLOCK_SIZE {
+        return Err(invalid("pcap record too large"));
+    }
+    let mut frame = vec![0; captured];
+    input.read_exact(&mut frame)?;
+    if captured < original {
+        return Ok(Some(Record::Other));
+    }
+    let subsec = if nanos { fraction } else { fraction.saturating_mul(1000) };
+    Ok(Some(Record::Packet {
+        timestamp: UNIX_EPOCH + Duration::new(seconds, 0) + Duration::from_nanos(subsec as u64),
+        link_type,
+        frame
+    }))
+}
+
+fn read_pcapng_record(
+    input: &mut impl Read,
+    big_endian: &mut bool,
+    interfaces: &mut Vec<Interface>,
+) -> io::Result<Option<Record>> {
+    let block_type = match read_array_or_end::<4>(input)? {
+        Some(bytes) => bytes,
+        None => return Ok(None)
+    };
+    // The section header is recognized in either byte order, and sets the
+    // order of everything after it.
+    if u32::from_le_bytes(block_type) == PCAPNG_SECTION_HEADER {
+        *big_endian = read_section_header(input)?;
+        interfaces.clear();
+        return Ok(Some(Record::Other));
+    }
+    let block_type = u32_at(&block_type, 0, *big_endian);
+    let body = read_block_body(input, *big_endian)?;
+
+    match block_type {
+        PCAPNG_INTERFACE_DESCRIPTION => {
+            if body.len() < 8 {
+                return Err(invalid("truncated pcapng interface block"));
+            }
+            interfaces.push(Interface {
+                link_type: u16_at(&body, 0, *big_endian),
+                resolution: timestamp_resolution(&body[8..], *big_endian)
+            });
+            Ok(Some(Record::Other))
+        }
+        PCAPNG_ENHANCED_PACKET => {
+            if body.len() < 20 {
+                return Err(invalid("truncated pcapng packet block"));
+            }
+            let interface = interfaces
+                .get(u32_at(&body, 0, *big_endian) as usize)
+                .ok_or_else(|| invalid("pcapng packet on an undescribed interface"))?;
+            let units = (u32_at(&body, 4, *big_endian) as u64) << 32 | u32_at(&body, 8, *big_endian) as u64;
+            let captured = u32_at(&body, 12, *big_endian) as usize;
+            let original = u32_at(&body, 16, *big_endian) as usize;
+            let frame = body.get(20..20 + captured).ok_or_else(|| invalid("truncated pcapng packet block"))?;
+            if captured < original {
+                return Ok(Some(Record::Other));
+            }
+            Ok(Some(Record::Packet {
+                timestamp: UNIX_EPOCH + interface.resolution.to_duration(units),
+                link_type: interface.link_type,
+                frame: frame.to_vec()
+            }))
+        }
+        PCAPNG_SIMPLE_PACKET => {
+            // No timestamp and no record of truncation beyond the length;
+            // always on the first interface.
+            let interface = interfaces.first().ok_or_else(|| invalid("pcapng packet on an undescribed interface"))?;
+            if body.len() < 4 {
+                return Err(invalid("truncated pcapng packet block"));
+            }
+            let original = u32_at(&body, 0, *big_endian) as usize;
+            match body.get(4..4 + original) {
+                Some(frame) => Ok(Some(Record::Packet {
+                    timestamp: UNIX_EPOCH,
+                    link_type: interface.link_type,
+                    frame: frame.to_vec()
+                })),
+                None => Ok(Some(Record::Other))
+            }
+        }
+        _ => Ok(Some(Record::Other))
+    }
+}
+
+// After a section header's block type: returns the section's byte order
+// (true for big endian) and skips the rest of the block.
+fn read_section_header(input: &mut impl Read) -> io::Result<bool> {
+    let length = read_array::<4>(input)?;
+    let magic = read_array::<4>(input)?;
+    let big_endian = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+        (PCAPNG_BYTE_ORDER_MAGIC, _) => false,
+        (_, PCAPNG_BYTE_ORDER_MAGIC) => true,
+        _ => return Err(invalid("bad pcapng byte-order magic"))
+    };
+    let length = u32_at(&length, 0, big_endian) as usize;
+    if !(28..=MAX_BLOCK_SIZE).contains(&length) || !length.is_multiple_of(4) {
+        return Err(invalid("bad pcapng block length"));
+    }
+    // Block type, length and magic are read; the rest includes the
+    // trailing length.
+    io::copy(&mut input.take(length as u64 - 12), &mut io::sink())?;
+    Ok(big_endian)
+}
+
+// After a block type: the block's body, without the trailing length.
+fn read_block_body(input: &mut impl Read, big_endian: bool) -> io::Result<Vec<u8>> {
+    let length = u32_at(&read_array::<4>(input)?, 0, big_endian) as usize;
+    if !(12..=MAX_BLOCK_SIZE).contains(&length) || !length.is_multiple_of(4) {
+        return Err(invalid("bad pcapng block length"));
+    }
+    let mut body = vec![0; length - 12];
+    input.read_exact(&mut body)?;
+    read_array::<4>(input)?;
+    Ok(body)
+}
+
+// The if_tsresol option among an interface block's options, microseconds
+// without it.
+fn timestamp_resolution(mut options: &[u8], big_endian: bool) -> TimestampResolution {
+    while options.len() >= 4 {
+        let code = u16_at(options, 0, big_endian);
+        let length = u16_at(options, 2, big_endian) as usize;
+        if code == PCAPNG_OPTION_END {
+            break;
+        }
+        let value = match options.get(4..4 + length) {
+            Some(value) => value,
+            None => break
+        };
+        if code == PCAPNG_OPTION_TSRESOL && length == 1 {
+            return match value[0] {
+                exponent if exponent & 0x80 == 0 => TimestampResolution::PowerOfTen(exponent as u32),
+                exponent => TimestampResolution::PowerOfTwo((exponent & 0x7F) as u32)
+            };
+        }
+        // Option values are padded to 32 bits.
+        let padded = 4 + length.div_ceil(4) * 4;
+        options = options.get(padded..).unwrap_or_default();
+    }
+    TimestampResolution::PowerOfTen(6)
+}
+
+impl TimestampResolution {
+    fn to_duration(self, units: u64) -> Duration {
+        let (per_second, units) = match self {
+            TimestampResolution::PowerOfTen(exponent) => (10u128.checked_pow(exponent), units as u128),
+            TimestampResolution::PowerOfTwo(exponent) => (1u128.checked_shl(exponent), units as u128)
+        };
+        match per_second {
+            Some(per_second) if per_second > 0 => {
+                let nanos = units * 1_000_000_000 / per_second;
+                Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32)
+            }
+            _ => Duration::ZERO
+        }
+    }
+}
+
+// (source, destination, range of the UDP payload in `frame`) of a UDP
+// datagram in a link-layer frame; None for anything else.
+fn parse_frame(link_type: u16, frame: &[u8]) -> Option<(SocketAddr, SocketAddr, std::ops::Range<usize>)> {
+    let ip_start = match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => 0,
+        // A 4-byte address family in the capturing host's byte order; the
+        // IP version tells the same.
+        LINKTYPE_NULL => 4,
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16_at(frame.get(..offset + 2)?, offset, true);
+            while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+                offset += 4;
+                ethertype = u16_at(frame.get(..offset + 2)?, offset, true);
+            }
+            if ethertype != ETHERTYPE_IPV4 && ethertype != ETHERTYPE_IPV6 {
+                return None;
+            }
+            offset + 2
+        }
+        LINKTYPE_LINUX_SLL => 16,
+        LINKTYPE_LINUX_SLL2 => 20,
+        _ => return None
+    };
+    let ip = frame.get(ip_start..)?;
+    let (source, destination, udp_start) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0F) as usize) * 4;
+            if header_len < 20 {
+                return None;
+            }
+            let header = ip.get(..header_len)?;
+            let fragmented = u16_at(header, 6, true) & 0x3FFF != 0;
+            if header[9] != IP_PROTOCOL_UDP || fragmented {
+                return None;
+            }
+            let source = Ipv4Addr::from(<[u8; 4]>::try_from(&header[12..16]).ok()?);
+            let destination = Ipv4Addr::from(<[u8; 4]>::try_from(&header[16..20]).ok()?);
+            (IpAddr::V4(source), IpAddr::V4(destination), header_len)
+        }
+        6 => {
+            let header = ip.get(..40)?;
+            let source = Ipv6Addr::from(<[u8; 16]>::try_from(&header[8..24]).ok()?);
+            let destination = Ipv6Addr::from(<[u8; 16]>::try_from(&header[24..40]).ok()?);
+            let mut next_header = header[6];
+            let mut offset = 40;
+            while matches!(next_header, IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DESTINATION_OPTIONS) {
+                let extension = ip.get(offset..offset + 2)?;
+                next_header = extension[0];
+                offset += (extension[1] as usize + 1) * 8;
+            }
+            // A fragment header (44) ends up here too.
+            if next_header != IP_PROTOCOL_UDP {
+                return None;
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination), offset)
+        }
+        _ => return None
+    };
+    let udp = ip.get(udp_start..udp_start + 8)?;
+    let udp_len = u16_at(udp, 4, true) as usize;
+    if udp_len < 8 {
+        return None;
+    }
+    // Ethernet pads short frames; the UDP length says where data ends.
+    let payload = ip_start + udp_start + 8..ip_start + udp_start + udp_len;
+    frame.get(payload.clone())?;
+    Some((
+        SocketAddr::new(source, u16_at(udp, 0, true)),
+        SocketAddr::new(destination, u16_at(udp, 2, true)),
+        payload
+    ))
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// None at a clean end of the input: nothing left before the array.
+fn read_array_or_end<const N: usize>(input: &mut impl Read) -> io::Result<Option<[u8; N]>> {
+    let mut bytes = [0; N];
+    let mut filled = 0;
+    while filled < N {
+        match input.read(&mut bytes[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => filled += len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(Some(bytes))
+}
+
+fn u16_at(bytes: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = [bytes[offset], bytes[offset + 1]];
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = [bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]];
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
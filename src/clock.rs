@@ -0,0 +1,163 @@
+//! Time sources for pushers and receivers.
+//!
+//! A pusher reads time through a `Clock`: its RTP clock anchor, pacing,
+//! keepalive intervals and the realtime schedule of `stream_access_units`
+//! all follow it. A receiver stamps arrivals and runs its jitter buffer
+//! and RTCP on one. `SystemClock`, the default, reads the OS clocks;
+//! `ManualClock` only moves when told to, so tests can fast-forward through
+//! timing behaviour and step the wall clock.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{CancelToken, RtpError};
+
+pub trait Clock: Send + Sync {
+    /// Monotonic time, never moving backwards.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time. It may be stepped either way, e.g. by NTP.
+    fn wall_clock(&self) -> SystemTime;
+
+    /// Returns once `duration` has passed on this clock, or with
+    /// `RtpError::Cancelled` as soon as `cancel` is cancelled.
+    fn sleep(&self, duration: Duration, cancel: Option<&CancelToken>) -> Result<(), RtpError>;
+}
+
+/// The operating system's monotonic and wall clocks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_clock(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration, cancel: Option<&CancelToken>) -> Result<(), RtpError> {
+        match cancel {
+            Some(token) => token.sleep(duration),
+            None => {
+                thread::sleep(duration);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A clock that stands still until `advance` is called. Clones share one
+/// time, so a test keeps a clone to drive the clock it gave a pusher.
+/// `sleep` advances the clock by the slept duration and returns at once.
+#[derive(Clone)]
+pub struct ManualClock {
+    inner: Arc<Mutex<ManualTime>>
+}
+
+struct ManualTime {
+    now: Instant,
+    wall_clock: SystemTime
+}
+
+impl ManualClock {
+    /// Starts at the current system time.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    pub fn starting_at(wall_clock: SystemTime) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ManualTime { now: Instant::now(), wall_clock }))
+        }
+    }
+
+    /// Moves both clocks forward.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.lock();
+        time.now += duration;
+        time.wall_clock += duration;
+    }
+
+    /// Steps only the wall clock, as an NTP correction would. The monotonic
+    /// clock is not affected.
+    pub fn set_wall_clock(&self, wall_clock: SystemTime) {
+        self.lock().wall_clock = wall_clock;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ManualTime> {
+        // Plain values, consistent after every statement.
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.lock();
+        f.debug_struct("ManualClock").field("wall_clock", &time.wall_clock).finish()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.lock().now
+    }
+
+    fn wall_clock(&self) -> SystemTime {
+        self.lock().wall_clock
+    }
+
+    fn sleep(&self, duration: Duration, cancel: Option<&CancelToken>) -> Result<(), RtpError> {
+        if let Some(token) = cancel {
+            token.check()?;
+        }
+        self.advance(duration);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_when_told() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let wall_start = clock.wall_clock();
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_millis(40));
+        clock.sleep(Duration::from_millis(10), None).unwrap();
+        assert_eq!(clock.now() - start, Duration::from_millis(50));
+        assert_eq!(clock.wall_clock().duration_since(wall_start).unwrap(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn wall_clock_steps_leave_the_monotonic_clock_alone() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let earlier = clock.wall_clock() - Duration::from_secs(3600);
+        clock.set_wall_clock(earlier);
+        assert_eq!(clock.wall_clock(), earlier);
+        assert_eq!(clock.now(), start);
+    }
+
+    #[test]
+    fn cancelled_sleep_does_not_advance() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let token = CancelToken::new();
+        token.cancel();
+        assert!(matches!(clock.sleep(Duration::from_secs(1), Some(&token)), Err(RtpError::Cancelled)));
+        assert_eq!(clock.now(), start);
+    }
+}
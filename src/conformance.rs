@@ -0,0 +1,218 @@
+//! Checks a sequence of H.264 RTP packets against the RFC 6184
+//! packetization rules, for the crate's own output or any other sender's.
+//!
+//! Packets are expected in sending order from a single stream. Each
+//! violation names the index of the offending packet and the rule broken.
+use crate::rtp::RtpPacket;
+use crate::sender::MarkerPolicy;
+
+const NAL_TYPE_STAP_A: u8 = 24;
+const NAL_TYPE_FU_A: u8 = 28;
+const FU_START: u8 = 1 << 7;
+const FU_END: u8 = 1 << 6;
+
+/// A broken packetization rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rule {
+    /// Not a parseable version 2 RTP packet.
+    MalformedRtp,
+    /// Empty payload, or a NAL header with forbidden_zero_bit set.
+    MalformedNalHeader,
+    /// A payload type other than 1-23 (single NAL), STAP-A or FU-A.
+    UnsupportedPacketType(u8),
+    /// A fragmented NAL that would have fit in one packet, including a
+    /// single FU-A with both Start and End set.
+    UnnecessaryFragmentation,
+    /// FU-A with the Start bit while a fragmented NAL is still open.
+    RepeatedFuStart,
+    /// FU-A continuation or end without a preceding Start.
+    MissingFuStart,
+    /// A fragmented NAL interrupted by another packet, or the FU type
+    /// changed between fragments.
+    UnterminatedFu,
+    /// FU-A carrying no NAL bytes.
+    EmptyFuPayload,
+    /// STAP-A whose unit sizes do not add up to the payload, or that
+    /// contains an empty unit.
+    InconsistentStapLength,
+    /// Fragments of one NAL with different timestamps.
+    TimestampChangedWithinNal,
+    /// A packet the marker policy says must carry the marker does not.
+    MissingMarker,
+    /// A marker where the marker policy allows none.
+    UnexpectedMarker
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub packet_index: usize,
+    pub rule: Rule
+}
+
+/// Checks `packets` (full RTP packets). `max_payload_size`, the largest RTP
+/// payload the sender could use, enables the check that NALs are only
+/// fragmented when they must be. Markers are checked against
+/// `marker_policy`; only `AccessUnitEnd` is RFC 6184 behavior. Access units
+/// are delimited by timestamp changes, so the last packet of the sequence
+/// is not checked for a marker.
+pub fn check_h264<P: AsRef<[u8]>>(
+    packets: &[P],
+    max_payload_size: Option<usize>,
+    marker_policy: MarkerPolicy,
+) -> Vec<Violation> {
+    let mut checker = Checker {
+        max_payload_size,
+        marker_policy,
+        violations: Vec::new(),
+        open_fu: None,
+        previous: None
+    };
+    for (index, packet) in packets.iter().enumerate() {
+        checker.packet(index, packet.as_ref());
+    }
+    checker.violations
+}
+
+// A fragmented NAL in progress.
+struct OpenFu {
+    nal_type: u8,
+    ts: u32,
+    // NAL size so far, header byte included.
+    size: usize
+}
+
+struct Checker {
+    max_payload_size: Option<usize>,
+    marker_policy: MarkerPolicy,
+    violations: Vec<Violation>,
+    open_fu: Option<OpenFu>,
+    // Timestamp, marker and NAL-end flag of the last parseable packet.
+    previous: Option<Previous>
+}
+
+struct Previous {
+    index: usize,
+    ts: u32,
+    marker: bool,
+    ends_nal: bool
+}
+
+impl Checker {
+    fn flag(&mut self, packet_index: usize, rule: Rule) {
+        self.violations.push(Violation { packet_index, rule });
+    }
+
+    fn packet(&mut self, index: usize, data: &[u8]) {
+        let packet = match RtpPacket::parse(data) {
+            Some(packet) => packet,
+            None => return self.flag(index, Rule::MalformedRtp)
+        };
+
+        // Whether the previous packet needed a marker is only known now that
+        // we see whether the timestamp moved on.
+        if let Some(previous) = self.previous.take() {
+            let ends_access_unit = previous.ts != packet.ts;
+            let expected = match self.marker_policy {
+                MarkerPolicy::AccessUnitEnd => ends_access_unit,
+                MarkerPolicy::EveryNal => previous.ends_nal,
+                MarkerPolicy::Never => false
+            };
+            if expected && !previous.marker {
+                self.flag(previous.index, Rule::MissingMarker);
+            } else if !expected && previous.marker {
+                self.flag(previous.index, Rule::UnexpectedMarker);
+            }
+        }
+
+        let header = match packet.payload.first() {
+            Some(&header) if header & 0x80 == 0 => header,
+            _ => return self.flag(index, Rule::MalformedNalHeader)
+        };
+        // Every packet but a FU-A start or middle fragment ends a NAL.
+        let ends_nal = header & 0x1F != NAL_TYPE_FU_A
+            || packet.payload.get(1).is_some_and(|fu_header| fu_header & FU_END != 0);
+        self.previous = Some(Previous {
+            index,
+            ts: packet.ts,
+            marker: packet.marker,
+            ends_nal
+        });
+
+        match header & 0x1F {
+            NAL_TYPE_FU_A => self.fu_a(index, packet.ts, packet.payload),
+            nal_type => {
+                if self.open_fu.take().is_some() {
+                    self.flag(index, Rule::UnterminatedFu);
+                }
+                match nal_type {
+                    1..=23 => {}
+                    NAL_TYPE_STAP_A => self.stap_a(index, packet.payload),
+                    other => self.flag(index, Rule::UnsupportedPacketType(other))
+                }
+            }
+        }
+    }
+
+    fn fu_a(&mut self, index: usize, ts: u32, payload: &[u8]) {
+        let fu_header = match payload.get(1) {
+            Some(&fu_header) => fu_header,
+            None => return self.flag(index, Rule::EmptyFuPayload)
+        };
+        if payload.len() == 2 {
+            self.flag(index, Rule::EmptyFuPayload);
+        }
+        let nal_type = fu_header & 0x1F;
+        let fragment = payload.len() - 2;
+
+        if fu_header & FU_START != 0 {
+            if self.open_fu.is_some() {
+                self.flag(index, Rule::RepeatedFuStart);
+            }
+            self.open_fu = Some(OpenFu { nal_type, ts, size: 1 + fragment });
+        } else {
+            match self.open_fu.as_mut() {
+                None => return self.flag(index, Rule::MissingFuStart),
+                Some(open) => {
+                    let changed_type = open.nal_type != nal_type;
+                    let changed_ts = open.ts != ts;
+                    open.size += fragment;
+                    if changed_type {
+                        self.flag(index, Rule::UnterminatedFu);
+                    }
+                    if changed_ts {
+                        self.flag(index, Rule::TimestampChangedWithinNal);
+                    }
+                }
+            }
+        }
+
+        if fu_header & FU_END != 0 {
+            if let Some(open) = self.open_fu.take() {
+                let fits = self.max_payload_size.is_some_and(|max| open.size <= max);
+                if fu_header & FU_START != 0 || fits {
+                    self.flag(index, Rule::UnnecessaryFragmentation);
+                }
+            }
+        }
+    }
+
+    fn stap_a(&mut self, index: usize, payload: &[u8]) {
+        let mut rest = &payload[1..];
+        let mut units = 0;
+        while !rest.is_empty() {
+            let size = match rest {
+                [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+                _ => return self.flag(index, Rule::InconsistentStapLength)
+            };
+            if size == 0 || rest.len() < 2 + size {
+                return self.flag(index, Rule::InconsistentStapLength);
+            }
+            rest = &rest[2 + size..];
+            units += 1;
+        }
+        if units == 0 {
+            self.flag(index, Rule::InconsistentStapLength);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "net")]
+use crate::receiver::Codec;
+#[cfg(feature = "net")]
+use crate::sender::{ConfigField, PacingPolicy};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RtpError {
+    Io(io::Error),
+    // The local UDP socket could not be bound.
+    Bind(io::Error),
+    // A packet could not be sent (kernel buffer drops are counted instead).
+    Send(io::Error),
+    // The TCP connection carrying the stream failed or was closed.
+    Disconnected(io::Error),
+    // The frame is not usable Annex B input.
+    InvalidNal(&'static str),
+    // A NAL needs more FU-A fragments than half the sequence number space.
+    NalTooLarge(usize),
+    // The requested MTU leaves no room for headers or exceeds a datagram.
+    InvalidMtu(usize),
+    // RTP payload types fit in 7 bits.
+    InvalidPayloadType(u8),
+    // Nothing was received within the receiver's idle timeout.
+    IdleTimeout,
+    // The requested socket option has no equivalent on this OS.
+    UnsupportedOnPlatform(&'static str),
+    // Replay was requested but the window holds no IDR to start from.
+    NoKeyframeInWindow,
+    // Replay was requested but enable_replay_window was never called.
+    ReplayWindowDisabled,
+    // Frame rejected because the pusher is paused.
+    Paused,
+    // Frame rejected after unpause because it does not contain an IDR.
+    AwaitingKeyframe,
+    // Frame rejected because the pusher was closed.
+    Closed,
+    // The call is only allowed between frames, and a push_nal frame is
+    // still missing its last NAL.
+    FrameInProgress,
+    // A blocking operation was aborted through its CancelToken.
+    Cancelled,
+    // apply_config was given fields that can't change on a live stream.
+    #[cfg(feature = "net")]
+    NotLiveConfigurable(Vec<ConfigField>),
+    // A pacing policy with a zero rate or burst.
+    #[cfg(feature = "net")]
+    InvalidPacing(PacingPolicy),
+    // Send batches hold at least one packet.
+    InvalidBatchSize(usize),
+    // A one-byte header extension id is outside 1..=14 or already in use.
+    InvalidExtensionId(u8),
+    // One-byte header extension data must be 1 to 16 bytes long.
+    InvalidExtensionLength(usize),
+    // A lossless receiver got another packet than the next in sequence:
+    // the expected and the received sequence number.
+    SequenceGap(u16, u16),
+    // The packets carry another codec than the receiver takes apart: the
+    // configured and the detected one.
+    #[cfg(feature = "net")]
+    CodecMismatch(Codec, Codec),
+    // SRTP key material could not be used.
+    #[cfg(feature = "srtp")]
+    InvalidSrtpKey(&'static str)
+}
+
+impl fmt::Display for RtpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RtpError::Io(err) => write!(f, "io error: {}", err),
+            RtpError::Bind(err) => write!(f, "could not bind local socket: {}", err),
+            RtpError::Send(err) => write!(f, "could not send packet: {}", err),
+            RtpError::Disconnected(err) => write!(f, "connection lost: {}", err),
+            RtpError::InvalidNal(reason) => write!(f, "invalid NAL input: {}", reason),
+            RtpError::NalTooLarge(size) => write!(f, "NAL of {} bytes is too large to packetize", size),
+            RtpError::InvalidMtu(mtu) => write!(f, "MTU of {} bytes is out of range", mtu),
+            RtpError::InvalidPayloadType(payload_type) => write!(f, "payload type {} is not in 0..=127", payload_type),
+            RtpError::IdleTimeout => write!(f, "no packet received within the idle timeout"),
+            RtpError::UnsupportedOnPlatform(option) => {
+                write!(f, "{} is not supported on this platform", option)
+            }
+            RtpError::NoKeyframeInWindow => write!(f, "no keyframe in the replay window"),
+            RtpError::ReplayWindowDisabled => write!(f, "replay window is not enabled"),
+            RtpError::Paused => write!(f, "pusher is paused"),
+            RtpError::AwaitingKeyframe => write!(f, "waiting for a keyframe after unpause"),
+            RtpError::Closed => write!(f, "pusher is closed"),
+            RtpError::FrameInProgress => write!(f, "a frame is partly sent through push_nal"),
+            RtpError::Cancelled => write!(f, "operation cancelled"),
+            #[cfg(feature = "net")]
+            RtpError::NotLiveConfigurable(fields) => {
+                write!(f, "cannot change on a live stream: {:?}", fields)
+            }
+            #[cfg(feature = "net")]
+            RtpError::InvalidPacing(policy) => write!(
+                f,
+                "pacing needs a positive rate and burst, got {} bit/s and {} bytes",
+                policy.bits_per_second, policy.max_burst_bytes
+            ),
+            RtpError::InvalidBatchSize(size) => write!(f, "send batch size must be positive, got {}", size),
+            RtpError::InvalidExtensionId(id) => {
+                write!(f, "header extension id {} is outside 1..=14 or already in use", id)
+            }
+            RtpError::InvalidExtensionLength(len) => {
+                write!(f, "header extension data of {} bytes is not 1 to 16 bytes long", len)
+            }
+            RtpError::SequenceGap(expected, received) => {
+                write!(f, "expected RTP sequence number {} on a lossless path, got {}", expected, received)
+            }
+            #[cfg(feature = "net")]
+            RtpError::CodecMismatch(configured, detected) => {
+                write!(f, "the stream carries {:?} but the receiver is set up for {:?}", detected, configured)
+            }
+            #[cfg(feature = "srtp")]
+            RtpError::InvalidSrtpKey(reason) => write!(f, "invalid SRTP key: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RtpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RtpError::Io(err) | RtpError::Bind(err) | RtpError::Send(err) | RtpError::Disconnected(err) => {
+                Some(err)
+            }
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for RtpError {
+    fn from(err: io::Error) -> Self {
+        RtpError::Io(err)
+    }
+}
@@ -0,0 +1,368 @@
+//! RTP header extensions in the one-byte format of RFC 8285.
+//!
+//! A pusher adds a list of [`HeaderExtension`]s to every media packet with
+//! `H264RtpPusher::set_header_extensions`, each with a fixed value or one
+//! filled in per packet. A receiver hands back what it found, per packet,
+//! from `H264RtpReceiver::recv_frame_with_extensions`.
+//!
+//! One extension is built in: abs-send-time
+//! (`http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time`), the
+//! send time for receiver-side bandwidth estimation, enabled with
+//! `H264RtpPusher::set_abs_send_time` and read back with
+//! [`PacketExtensions::abs_send_time`].
+//!
+//! Wire layout: the X bit in the RTP header, then the 0xBEDE profile, the
+//! block length in 32-bit words, and elements made of a byte with the id
+//! in the high nibble and the data length minus one in the low nibble,
+//! followed by the data. Zero bytes pad the block to a word boundary.
+//! Which id means what is agreed out of band, e.g. with SDP `a=extmap`.
+
+use std::fmt;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::rtp::RTP_HEADER_SIZE;
+use crate::time::NtpTimestamp;
+use crate::RtpError;
+
+const ONE_BYTE_PROFILE: u16 = 0xBEDE;
+// Profile and length words ahead of the elements.
+const BLOCK_HEADER_SIZE: usize = 4;
+// Id 15 is reserved: a receiver stops parsing at it.
+const ID_RESERVED: u8 = 15;
+const MAX_ELEMENT_LEN: usize = 16;
+// 6.18 fixed-point seconds: the middle 24 bits of a 64-bit NTP timestamp.
+pub(crate) const ABS_SEND_TIME_SIZE: usize = 3;
+const ABS_SEND_TIME_FRACTION_BITS: u32 = 18;
+// Where a pusher's abs-send-time data sits: its element comes first.
+const ABS_SEND_TIME_OFFSET: usize = RTP_HEADER_SIZE + BLOCK_HEADER_SIZE + 1;
+
+/// What a per-packet extension is filled in for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PacketInfo {
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub marker: bool,
+    /// The packet is the first of its access unit.
+    pub frame_start: bool,
+    /// Type of the NAL the packet carries (or starts, or ends) in the low
+    /// 5 bits; the most important one for a STAP-A.
+    pub nal_type: u8
+}
+
+type Fill = Box<dyn FnMut(&PacketInfo, &mut [u8]) + Send>;
+
+enum Value {
+    Fixed(Vec<u8>),
+    PerPacket { len: usize, fill: Fill }
+}
+
+/// One element a pusher puts in every media packet's header extension.
+pub struct HeaderExtension {
+    id: u8,
+    value: Value
+}
+
+impl HeaderExtension {
+    /// The same `data` in every packet. Fails with `InvalidExtensionId`
+    /// unless `id` is in 1..=14, and with `InvalidExtensionLength` unless
+    /// `data` is 1 to 16 bytes long.
+    pub fn fixed(id: u8, data: &[u8]) -> Result<Self, RtpError> {
+        check_element(id, data.len())?;
+        Ok(Self {
+            id,
+            value: Value::Fixed(data.to_vec())
+        })
+    }
+
+    /// `len` bytes that `fill` writes for each packet just before it is
+    /// sent, e.g. a capture timestamp or a frame-marking byte. The buffer
+    /// holds the previous packet's value. Fails like `fixed` unless `id`
+    /// is in 1..=14 and `len` in 1..=16.
+    pub fn per_packet<F>(id: u8, len: usize, fill: F) -> Result<Self, RtpError>
+    where
+        F: FnMut(&PacketInfo, &mut [u8]) + Send + 'static,
+    {
+        check_element(id, len)?;
+        Ok(Self {
+            id,
+            value: Value::PerPacket {
+                len,
+                fill: Box::new(fill)
+            }
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Length of the element's data.
+    pub fn data_len(&self) -> usize {
+        match &self.value {
+            Value::Fixed(data) => data.len(),
+            Value::PerPacket { len, .. } => *len
+        }
+    }
+}
+
+impl fmt::Debug for HeaderExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("HeaderExtension");
+        debug.field("id", &self.id);
+        match &self.value {
+            Value::Fixed(data) => debug.field("data", data),
+            Value::PerPacket { len, .. } => debug.field("per_packet_len", len)
+        };
+        debug.finish()
+    }
+}
+
+fn check_element(id: u8, len: usize) -> Result<(), RtpError> {
+    check_id(id, std::iter::empty())?;
+    if !(1..=MAX_ELEMENT_LEN).contains(&len) {
+        return Err(RtpError::InvalidExtensionLength(len));
+    }
+    Ok(())
+}
+
+// Fails unless `id` is a valid one-byte id that `in_use` does not hold.
+pub(crate) fn check_id(id: u8, mut in_use: impl Iterator<Item = u8>) -> Result<(), RtpError> {
+    if !(1..ID_RESERVED).contains(&id) || in_use.any(|other| other == id) {
+        return Err(RtpError::InvalidExtensionId(id));
+    }
+    Ok(())
+}
+
+/// The one-byte header extension elements of one received packet, in
+/// packet order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PacketExtensions {
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub marker: bool,
+    /// When the packet was read from the socket.
+    pub arrival: Instant,
+    /// Id and data of each element.
+    pub elements: Vec<(u8, Vec<u8>)>
+}
+
+impl PacketExtensions {
+    /// Data of the first element with `id`.
+    pub fn get(&self, id: u8) -> Option<&[u8]> {
+        self.elements
+            .iter()
+            .find(|(element_id, _)| *element_id == id)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// The abs-send-time element with `id`: when the sender handed the
+    /// packet to its socket, as seconds of its NTP clock modulo 64, to
+    /// 1/262144 s (about 4 µs). Only differences between packets mean
+    /// anything without a synchronized clock.
+    pub fn abs_send_time(&self, id: u8) -> Option<Duration> {
+        let data = self.get(id)?;
+        let value = match data {
+            [a, b, c] => u32::from_be_bytes([0, *a, *b, *c]),
+            _ => return None
+        };
+        let fraction = value & ((1 << ABS_SEND_TIME_FRACTION_BITS) - 1);
+        let nanos = (fraction as u64 * 1_000_000_000) >> ABS_SEND_TIME_FRACTION_BITS;
+        Some(Duration::new((value >> ABS_SEND_TIME_FRACTION_BITS) as u64, nanos as u32))
+    }
+}
+
+// Writes `now` into the abs-send-time element of a packet built by a
+// pusher, where it is the first element. Packets without one, such as
+// keepalives and probes, are left alone.
+pub(crate) fn stamp_abs_send_time(packet: &mut [u8], id: u8, now: NtpTimestamp) {
+    let has_element = packet.len() >= ABS_SEND_TIME_OFFSET + ABS_SEND_TIME_SIZE
+        && packet[0] & 0x1F == 0x10 // X bit, no CSRCs
+        && packet[RTP_HEADER_SIZE..RTP_HEADER_SIZE + 2] == ONE_BYTE_PROFILE.to_be_bytes()
+        && packet[ABS_SEND_TIME_OFFSET - 1] == (id << 4) | (ABS_SEND_TIME_SIZE as u8 - 1);
+    if has_element {
+        let value = (now.0 >> (32 - ABS_SEND_TIME_FRACTION_BITS)) as u32;
+        packet[ABS_SEND_TIME_OFFSET..ABS_SEND_TIME_OFFSET + ABS_SEND_TIME_SIZE]
+            .copy_from_slice(&value.to_be_bytes()[1..]);
+    }
+}
+
+// Bytes the elements of `extensions` take, their id/length bytes included.
+pub(crate) fn elements_size(extensions: &[HeaderExtension]) -> usize {
+    extensions.iter().map(|extension| 1 + extension.data_len()).sum()
+}
+
+// Size of a block holding `elements_size` bytes of elements, 0 for none.
+pub(crate) const fn block_size(elements_size: usize) -> usize {
+    if elements_size == 0 {
+        return 0;
+    }
+    BLOCK_HEADER_SIZE + elements_size.div_ceil(4) * 4
+}
+
+// Writes the block for `extensions`, between `first` and `last` (id and
+// data) if given, into `out`, which is exactly as long as `block_size` says
+// the block is.
+pub(crate) fn write_block(
+    out: &mut [u8],
+    first: Option<(u8, &[u8])>,
+    extensions: &mut [HeaderExtension],
+    info: &PacketInfo,
+    last: Option<(u8, &[u8])>
+) {
+    let words = (out.len() - BLOCK_HEADER_SIZE) / 4;
+    out[..2].copy_from_slice(&ONE_BYTE_PROFILE.to_be_bytes());
+    out[2..4].copy_from_slice(&(words as u16).to_be_bytes());
+
+    let mut offset = BLOCK_HEADER_SIZE;
+    if let Some((id, value)) = first {
+        let data = start_element(out, &mut offset, id, value.len());
+        out[data].copy_from_slice(value);
+    }
+    for extension in extensions.iter_mut() {
+        let data = start_element(out, &mut offset, extension.id, extension.data_len());
+        match &mut extension.value {
+            Value::Fixed(value) => out[data].copy_from_slice(value),
+            Value::PerPacket { fill, .. } => fill(info, &mut out[data])
+        }
+    }
+    if let Some((id, value)) = last {
+        let data = start_element(out, &mut offset, id, value.len());
+        out[data].copy_from_slice(value);
+    }
+    out[offset..].fill(0);
+}
+
+// Writes an element's id/length byte at `offset` and moves it past the
+// element; returns where its data goes.
+fn start_element(out: &mut [u8], offset: &mut usize, id: u8, len: usize) -> Range<usize> {
+    out[*offset] = (id << 4) | (len as u8 - 1);
+    *offset += 1 + len;
+    *offset - len..*offset
+}
+
+// Elements of a received extension block (profile word included), or None
+// if it is not in the one-byte format. A malformed element ends the list.
+pub(crate) fn parse_block(block: &[u8]) -> Option<Vec<(u8, Vec<u8>)>> {
+    if block.len() < BLOCK_HEADER_SIZE || u16::from_be_bytes([block[0], block[1]]) != ONE_BYTE_PROFILE {
+        return None;
+    }
+    let mut elements = Vec::new();
+    let mut rest = &block[BLOCK_HEADER_SIZE..];
+    while let [header, tail @ ..] = rest {
+        // Padding may sit between elements too.
+        if *header == 0 {
+            rest = tail;
+            continue;
+        }
+        let (id, len) = (header >> 4, (header & 0x0F) as usize + 1);
+        if id == ID_RESERVED || len > tail.len() {
+            break;
+        }
+        elements.push((id, tail[..len].to_vec()));
+        rest = &tail[len..];
+    }
+    Some(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(elements: Vec<(u8, Vec<u8>)>) -> PacketExtensions {
+        PacketExtensions {
+            sequence: 0,
+            timestamp: 0,
+            marker: false,
+            arrival: Instant::now(),
+            elements
+        }
+    }
+
+    #[test]
+    fn abs_send_time_is_the_middle_24_bits_of_the_ntp_time() {
+        // 1.5 s plus 1/262144 s: 6.18 fixed point 0x060001.
+        let ntp = NtpTimestamp((1 << 32) | (1 << 31) | (1 << 14));
+        let mut packet = vec![0x90, 96, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0xBE, 0xDE, 0, 1, 0x32, 0, 0, 0];
+        stamp_abs_send_time(&mut packet, 3, ntp);
+        assert_eq!(packet[17..20], [0x06, 0x00, 0x01]);
+
+        let extensions = received(vec![(3, packet[17..20].to_vec())]);
+        assert_eq!(extensions.abs_send_time(3), Some(Duration::new(1, 500_003_814)));
+        assert_eq!(extensions.abs_send_time(4), None);
+    }
+
+    #[test]
+    fn abs_send_time_keeps_seconds_modulo_64() {
+        let mut packet = vec![0x90, 96, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0xBE, 0xDE, 0, 1, 0x32, 0, 0, 0];
+        stamp_abs_send_time(&mut packet, 3, NtpTimestamp(65 << 32));
+        let extensions = received(vec![(3, packet[17..20].to_vec())]);
+        assert_eq!(extensions.abs_send_time(3), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn stamping_skips_packets_without_the_element() {
+        // A keepalive: no X bit.
+        let mut keepalive = vec![0x80, 96, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let before = keepalive.clone();
+        stamp_abs_send_time(&mut keepalive, 3, NtpTimestamp(u64::MAX));
+        assert_eq!(keepalive, before);
+
+        // An element with another id first.
+        let mut other = vec![0x90, 96, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0xBE, 0xDE, 0, 1, 0x42, 0, 0, 0];
+        let before = other.clone();
+        stamp_abs_send_time(&mut other, 3, NtpTimestamp(u64::MAX));
+        assert_eq!(other, before);
+    }
+
+    #[test]
+    fn block_is_padded_to_a_word_boundary() {
+        let mut extensions = vec![
+            HeaderExtension::fixed(1, &[0xAA]).unwrap(),
+            HeaderExtension::per_packet(2, 2, |info, data| data.copy_from_slice(&info.sequence.to_be_bytes())).unwrap(),
+        ];
+        let info = PacketInfo {
+            sequence: 0x1234,
+            timestamp: 0,
+            marker: false,
+            frame_start: true,
+            nal_type: 5
+        };
+        let elements = elements_size(&extensions) + 1 + ABS_SEND_TIME_SIZE;
+        let mut out = vec![0xFF; block_size(elements)];
+        write_block(&mut out, Some((3, &[7, 8, 9])), &mut extensions, &info, None);
+        assert_eq!(out, [0xBE, 0xDE, 0, 3, 0x32, 7, 8, 9, 0x10, 0xAA, 0x21, 0x12, 0x34, 0, 0, 0]);
+    }
+
+    #[test]
+    fn block_sizes_round_up_to_words() {
+        assert_eq!(block_size(0), 0);
+        assert_eq!(block_size(1), 8);
+        assert_eq!(block_size(4), 8);
+        assert_eq!(block_size(5), 12);
+    }
+
+    #[test]
+    fn parsing_skips_padding_and_stops_at_malformed_elements() {
+        let block = [0xBE, 0xDE, 0, 3, 0x10, 0xAA, 0, 0, 0x21, 1, 2, 0xF0, 0x55, 0, 0, 0];
+        assert_eq!(parse_block(&block), Some(vec![(1, vec![0xAA]), (2, vec![1, 2])]));
+
+        // Data running past the end of the block.
+        let block = [0xBE, 0xDE, 0, 1, 0x13, 1, 2, 3];
+        assert_eq!(parse_block(&block), Some(vec![]));
+
+        // The two-byte format is not parsed.
+        assert_eq!(parse_block(&[0x10, 0x00, 0, 1, 1, 1, 0xAA, 0]), None);
+    }
+
+    #[test]
+    fn ids_outside_1_to_14_or_in_use_are_rejected() {
+        assert!(check_id(1, [].into_iter()).is_ok());
+        assert!(check_id(14, [2, 3].into_iter()).is_ok());
+        assert!(matches!(check_id(0, [].into_iter()), Err(RtpError::InvalidExtensionId(0))));
+        assert!(matches!(check_id(15, [].into_iter()), Err(RtpError::InvalidExtensionId(15))));
+        assert!(matches!(check_id(3, [2, 3].into_iter()), Err(RtpError::InvalidExtensionId(3))));
+    }
+}
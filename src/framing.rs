@@ -0,0 +1,96 @@
+// Framing of RTP packets on a TCP byte stream, shared by the pusher and the
+// receiver.
+
+/// How RTP packets are delimited on a TCP connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpFraming {
+    /// RFC 4571: each packet is preceded by its length as a 16-bit
+    /// big-endian integer.
+    Rfc4571,
+    /// RTSP interleaving (RFC 2326 section 10.12): `$`, a channel byte, then
+    /// the 16-bit length. A receiver skips packets on other channels, such
+    /// as RTCP on `channel + 1`.
+    Interleaved { channel: u8 }
+}
+
+impl TcpFraming {
+    fn header_len(self) -> usize {
+        match self {
+            TcpFraming::Rfc4571 => 2,
+            TcpFraming::Interleaved { .. } => 4
+        }
+    }
+}
+
+// Appends `packet` with its frame header to `out`, so the frame can be
+// written in one call. Packets are at most an MTU, far below 64 KiB.
+pub(crate) fn encode(framing: TcpFraming, packet: &[u8], out: &mut Vec<u8>) {
+    if let TcpFraming::Interleaved { channel } = framing {
+        out.extend_from_slice(&[b'$', channel]);
+    }
+    out.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+    out.extend_from_slice(packet);
+}
+
+// Splits the byte stream back into packets, however TCP split or joined the
+// reads.
+pub(crate) struct Deframer {
+    framing: TcpFraming,
+    buffer: Vec<u8>,
+    // Start of the first byte not yet consumed.
+    start: usize
+}
+
+impl Deframer {
+    pub fn new(framing: TcpFraming) -> Self {
+        Self {
+            framing,
+            buffer: Vec::new(),
+            start: 0
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        // Drop consumed bytes before growing, so the buffer stays about one
+        // frame long.
+        if self.start > 0 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    // The next complete packet, if the bytes pushed so far hold one.
+    pub fn next_packet(&mut self) -> Option<&[u8]> {
+        loop {
+            let pending = &self.buffer[self.start..];
+            if let TcpFraming::Interleaved { .. } = self.framing {
+                // Anything between frames (an RTSP message sharing the
+                // connection) is skipped up to the next `$`.
+                match pending.iter().position(|&byte| byte == b'$') {
+                    Some(dollar) => self.start += dollar,
+                    None => {
+                        self.start = self.buffer.len();
+                        return None;
+                    }
+                }
+            }
+            let pending = &self.buffer[self.start..];
+            let header_len = self.framing.header_len();
+            if pending.len() < header_len {
+                return None;
+            }
+            let len = u16::from_be_bytes([pending[header_len - 2], pending[header_len - 1]]) as usize;
+            if pending.len() < header_len + len {
+                return None;
+            }
+            let frame_channel = pending[1];
+            let packet_start = self.start + header_len;
+            self.start = packet_start + len;
+            match self.framing {
+                TcpFraming::Interleaved { channel } if frame_channel != channel => continue,
+                _ => return Some(&self.buffer[packet_start..packet_start + len])
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Per-frame CRC32 carried in an RFC 8285 one-byte header extension.
+//
+// The element is the extension id with len-1 = 3, then the CRC in big
+// endian; it joins the pusher's other header extensions, if any.
+pub const CRC_SIZE: usize = 4;
+// The element with its id/length byte.
+pub const ELEMENT_SIZE: usize = 1 + CRC_SIZE;
+
+// IEEE 802.3 polynomial, reflected.
+const CRC_TABLE: [u32; 256] = make_crc_table();
+
+const fn make_crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub struct Crc32 {
+    value: u32
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { value: 0xFFFF_FFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.value = CRC_TABLE[((self.value ^ byte as u32) & 0xFF) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.value
+    }
+}
@@ -0,0 +1,363 @@
+//! H.264 packetization without sockets, for targets that bring their own
+//! network stack: [`H264Packetizer`] cuts Annex B access units into RTP
+//! packets and hands each to an [`RtpSink`]. This is all of the crate that
+//! builds without the `net` feature.
+//!
+//! It sends single NAL unit and FU-A packets (RFC 6184), with the marker
+//! bit on the last packet of each access unit. Pacing, RTCP, header
+//! extensions, SRTP and the other pusher features need
+//! `sender::H264RtpPusher`, which packetizes the same way.
+use std::io;
+use std::sync::mpsc;
+
+use crate::payload::h264::{nal_units, H264NalType};
+use crate::rtp::{RtpHeader, RTP_HEADER_SIZE};
+use crate::RtpError;
+
+pub(crate) const FU_A_SIZE: usize = 2;
+const NAL_TYPE_FU_A: u8 = 28;
+// Start and End bits of the FU header.
+const FU_START: u8 = 1 << 7;
+pub(crate) const FU_END: u8 = 1 << 6;
+// Room for the RTP header, a FU-A header and at least one byte of NAL.
+const MIN_MTU: usize = RTP_HEADER_SIZE + FU_A_SIZE + 1;
+// Largest UDP payload over IPv4.
+pub(crate) const MAX_MTU: usize = 65_507;
+
+/// Where RTP packets go: a pusher's instead of its UDP socket (see
+/// `H264RtpPusher::set_sink`), e.g. an encrypting wrapper, a datagram
+/// channel of another protocol or an in-memory queue, and every packet of
+/// an [`H264Packetizer`].
+///
+/// Every packet of a pusher goes through the sink, duplicates and
+/// keepalives included, after the packet transform. RTCP, path MTU probes
+/// and replays stay on the pusher's UDP sockets.
+pub trait RtpSink: Send {
+    /// Sends one complete RTP packet and returns the bytes accepted.
+    /// `WouldBlock` and ENOBUFS count as kernel drops, as they do for UDP;
+    /// any other error fails the send.
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize>;
+
+    /// Sends several packets, in order, with send batching on (see
+    /// `H264RtpPusher::set_send_batching`). Returns how many were sent,
+    /// counting from the first; the pusher passes the rest again. An error
+    /// means the first packet failed. The default sends them one by one.
+    fn send_batch(&mut self, packets: &[&[u8]]) -> io::Result<usize> {
+        for (sent, packet) in packets.iter().enumerate() {
+            if let Err(err) = self.send_packet(packet) {
+                return if sent == 0 { Err(err) } else { Ok(sent) };
+            }
+        }
+        Ok(packets.len())
+    }
+}
+
+/// Queues a copy of every packet, for inspecting what a pusher produces
+/// without a network. Fails with `BrokenPipe` once the receiving end is
+/// dropped.
+impl RtpSink for mpsc::Sender<Vec<u8>> {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.send(packet.to_vec())
+            .map(|()| packet.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "packet queue receiver dropped"))
+    }
+}
+
+/// How a NAL too large for one packet is cut into FU-A fragments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FragmentSizing {
+    /// Every fragment but the last fills the packet; the last gets what is
+    /// left, possibly only a few bytes.
+    #[default]
+    Greedy,
+    /// Fragments of equal size (differing by at most one byte), using as
+    /// few packets as `Greedy`.
+    Balanced,
+    /// Like `Greedy`, but the last fragment gets at least this many bytes,
+    /// taken from the one before it. Never more than half of what the last
+    /// two fragments carry, so the one before does not become the runt.
+    MinLastFragment(usize)
+}
+
+/// Packetizes H.264 access units into RTP packets of one SSRC.
+///
+/// ```
+/// use std::sync::mpsc;
+/// use rtp_transceive::packetizer::H264Packetizer;
+///
+/// let mut packetizer = H264Packetizer::new(0x1234_5678, 96, 1200).unwrap();
+/// let (mut sink, packets) = mpsc::channel();
+/// packetizer.send_frame(&[0, 0, 0, 1, 0x65, 0x88, 0x84], 3000, &mut sink).unwrap();
+/// assert_eq!(packets.try_recv().unwrap()[12..], [0x65, 0x88, 0x84]);
+/// ```
+pub struct H264Packetizer {
+    ssrc: u32,
+    payload_type: u8,
+    mtu: usize,
+    seq: u16,
+    fragment_sizing: FragmentSizing,
+    // Packet under construction, `mtu` bytes.
+    buffer: Vec<u8>
+}
+
+impl H264Packetizer {
+    /// Builds packets of at most `mtu` bytes, headers included, starting
+    /// at sequence number 0. Payload types above 127 and MTUs below 15
+    /// (RTP and FU-A headers plus one byte) or above 65507 are rejected.
+    pub fn new(ssrc: u32, payload_type: u8, mtu: usize) -> Result<Self, RtpError> {
+        if payload_type > 127 {
+            return Err(RtpError::InvalidPayloadType(payload_type));
+        }
+        if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+            return Err(RtpError::InvalidMtu(mtu));
+        }
+        Ok(Self {
+            ssrc,
+            payload_type,
+            mtu,
+            seq: 0,
+            fragment_sizing: FragmentSizing::Greedy,
+            buffer: vec![0; mtu]
+        })
+    }
+
+    /// How NALs larger than a packet are split, `FragmentSizing::Greedy`
+    /// by default.
+    pub fn set_fragment_sizing(&mut self, sizing: FragmentSizing) {
+        self.fragment_sizing = sizing;
+    }
+
+    /// Sequence number of the next packet.
+    pub fn next_sequence(&self) -> u16 {
+        self.seq
+    }
+
+    /// Continues from `seq`, e.g. where another packetizer or pusher of the
+    /// same stream stopped.
+    pub fn set_next_sequence(&mut self, seq: u16) {
+        self.seq = seq;
+    }
+
+    /// Packetizes one Annex B access unit with RTP timestamp `timestamp`
+    /// and hands its packets to `sink` in order. Returns how many there
+    /// were. The frame is checked before any packet goes out, as the
+    /// pusher does; a sink error (`RtpError::Send`) abandons the rest of
+    /// the frame, and the packetizer is ready for the next one.
+    pub fn send_frame(&mut self, frame: &[u8], timestamp: u32, sink: &mut dyn RtpSink) -> Result<usize, RtpError> {
+        let nals: Vec<_> = nal_units(frame).collect();
+        validate_frame(&nals, self.mtu)?;
+
+        let mut packets = 0;
+        for (index, &(_, nal)) in nals.iter().enumerate() {
+            let last_nal = index == nals.len() - 1;
+            if RTP_HEADER_SIZE + nal.len() <= self.mtu {
+                let end = RTP_HEADER_SIZE + nal.len();
+                self.buffer[RTP_HEADER_SIZE..end].copy_from_slice(nal);
+                self.send_packet(end, timestamp, last_nal, sink)?;
+                packets += 1;
+                continue;
+            }
+            let budget = self.mtu - RTP_HEADER_SIZE - FU_A_SIZE;
+            for (fu_a, fragment) in fu_a_fragments(nal[0], nal, budget, self.fragment_sizing) {
+                let end = RTP_HEADER_SIZE + FU_A_SIZE + fragment.len();
+                self.buffer[RTP_HEADER_SIZE..RTP_HEADER_SIZE + FU_A_SIZE].copy_from_slice(&fu_a);
+                self.buffer[RTP_HEADER_SIZE + FU_A_SIZE..end].copy_from_slice(fragment);
+                self.send_packet(end, timestamp, last_nal && fu_a[1] & FU_END != 0, sink)?;
+                packets += 1;
+            }
+        }
+        Ok(packets)
+    }
+
+    // Writes the RTP header in front of the `len - 12` payload bytes
+    // already in the buffer and sends the packet.
+    fn send_packet(
+        &mut self,
+        len: usize,
+        timestamp: u32,
+        marker: bool,
+        sink: &mut dyn RtpSink,
+    ) -> Result<(), RtpError> {
+        let header = RtpHeader {
+            byte1: 2 << 6,
+            byte2: (marker as u8) << 7 | self.payload_type,
+            seq: self.seq,
+            ts: timestamp,
+            ssrc: self.ssrc
+        };
+        self.buffer[..RTP_HEADER_SIZE].copy_from_slice(&header.copy_into_array());
+        self.seq = self.seq.wrapping_add(1);
+        sink.send_packet(&self.buffer[..len]).map_err(RtpError::Send)?;
+        Ok(())
+    }
+}
+
+// Rejects frames the packetizer can't send sensibly, before any packet of
+// them goes out.
+pub(crate) fn validate_frame(nals: &[(H264NalType, &[u8])], max_packet_size: usize) -> Result<(), RtpError> {
+    for &(_, nal_buf) in nals {
+        validate_nal(nal_buf, max_packet_size)?;
+    }
+
+    if nals.is_empty() {
+        Err(RtpError::InvalidNal("no NAL unit in the frame"))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn validate_nal(nal_buf: &[u8], max_packet_size: usize) -> Result<(), RtpError> {
+    // Beyond this a single NAL would wrap the sequence number space halfway,
+    // and receivers could no longer order its fragments.
+    let max_nal_size = (u16::MAX as usize / 2) * (max_packet_size - RTP_HEADER_SIZE - FU_A_SIZE);
+
+    if nal_buf[0] & 0x80 != 0 {
+        return Err(RtpError::InvalidNal("forbidden_zero_bit is set"));
+    }
+    if nal_buf.len() > max_nal_size {
+        return Err(RtpError::NalTooLarge(nal_buf.len()));
+    }
+    Ok(())
+}
+
+// FU-A fragments of `nal`, each as its FU indicator and FU header and the
+// NAL bytes it carries, at most `budget` of them. The NAL header is not
+// sent itself: F, NRI and type come from `nal_header` (the original, or
+// with the NRI rewritten) and go into the FU-A headers.
+pub(crate) fn fu_a_fragments(nal_header: u8, nal: &[u8], budget: usize, sizing: FragmentSizing) -> FuAFragments<'_> {
+    FuAFragments {
+        nal_header,
+        rest: &nal[1..],
+        budget,
+        sizing,
+        first: true
+    }
+}
+
+pub(crate) struct FuAFragments<'a> {
+    nal_header: u8,
+    rest: &'a [u8],
+    budget: usize,
+    sizing: FragmentSizing,
+    first: bool
+}
+
+impl<'a> Iterator for FuAFragments<'a> {
+    type Item = ([u8; FU_A_SIZE], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let size = fragment_size(self.rest.len(), self.budget, self.sizing);
+        let (fragment, rest) = self.rest.split_at(size);
+        let mut fu_header = self.nal_header & 0x1F;
+        if self.first {
+            fu_header |= FU_START;
+        }
+        if rest.is_empty() {
+            fu_header |= FU_END;
+        }
+        self.rest = rest;
+        self.first = false;
+        Some(([(self.nal_header & 0b1110_0000) | NAL_TYPE_FU_A, fu_header], fragment))
+    }
+}
+
+// Size of the next FU-A fragment when `remaining` NAL bytes are left and a
+// fragment may carry at most `budget`.
+fn fragment_size(remaining: usize, budget: usize, sizing: FragmentSizing) -> usize {
+    if remaining <= budget {
+        return remaining;
+    }
+    match sizing {
+        FragmentSizing::Greedy => budget,
+        FragmentSizing::Balanced => {
+            let fragments = remaining.div_ceil(budget);
+            remaining.div_ceil(fragments)
+        }
+        FragmentSizing::MinLastFragment(min_last) => {
+            let min_last = min_last.min(remaining / 2);
+            let last = remaining - budget;
+            if last < min_last {
+                remaining - min_last
+            } else {
+                budget
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packetize(packetizer: &mut H264Packetizer, frame: &[u8], timestamp: u32) -> Vec<Vec<u8>> {
+        let (mut sink, packets) = mpsc::channel();
+        let count = packetizer.send_frame(frame, timestamp, &mut sink).unwrap();
+        let packets: Vec<_> = packets.try_iter().collect();
+        assert_eq!(packets.len(), count);
+        packets
+    }
+
+    #[test]
+    fn small_nals_go_out_whole_and_large_ones_in_fu_a() {
+        let mut packetizer = H264Packetizer::new(0xCAFE_F00D, 96, 100).unwrap();
+        packetizer.set_next_sequence(u16::MAX);
+        let idr: Vec<u8> = [0x65].into_iter().chain((0..200).map(|byte| byte as u8 | 1)).collect();
+        let frame = [&[0, 0, 0, 1, 0x67, 0x42][..], &[0, 0, 1], &idr].concat();
+        let packets = packetize(&mut packetizer, &frame, 9000);
+
+        // The SPS, then the 201-byte IDR in fragments of at most 86 bytes.
+        assert_eq!(packets.len(), 4);
+        assert_eq!(packets[0][12..], [0x67, 0x42]);
+        let fragments: Vec<&[u8]> = packets[1..].iter().map(|packet| &packet[14..]).collect();
+        assert_eq!(fragments.iter().map(|fragment| fragment.len()).collect::<Vec<_>>(), [86, 86, 28]);
+        assert_eq!(fragments.concat(), idr[1..]);
+        let fu_headers: Vec<[u8; 2]> = packets[1..].iter().map(|packet| [packet[12], packet[13]]).collect();
+        assert_eq!(fu_headers, [[0x7C, 0x85], [0x7C, 0x05], [0x7C, 0x45]]);
+
+        for (index, packet) in packets.iter().enumerate() {
+            assert!(packet.len() <= 100);
+            assert_eq!(packet[0], 0x80);
+            // The marker goes on the last packet only.
+            assert_eq!(packet[1], if index == 3 { 0x80 | 96 } else { 96 });
+            assert_eq!(u16::from_be_bytes([packet[2], packet[3]]), u16::MAX.wrapping_add(index as u16));
+            assert_eq!(packet[4..8], 9000u32.to_be_bytes());
+            assert_eq!(packet[8..12], 0xCAFE_F00Du32.to_be_bytes());
+        }
+        assert_eq!(packetizer.next_sequence(), 3);
+    }
+
+    #[test]
+    fn fragments_follow_the_sizing() {
+        let nal: Vec<u8> = [0x65].into_iter().chain([7; 200]).collect();
+        let sizes = |sizing| {
+            fu_a_fragments(0x65, &nal, 90, sizing)
+                .map(|(_, fragment)| fragment.len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(FragmentSizing::Greedy), [90, 90, 20]);
+        assert_eq!(sizes(FragmentSizing::Balanced), [67, 67, 66]);
+        assert_eq!(sizes(FragmentSizing::MinLastFragment(50)), [90, 60, 50]);
+    }
+
+    #[test]
+    fn bad_settings_and_frames_are_refused() {
+        assert!(matches!(H264Packetizer::new(1, 128, 1200), Err(RtpError::InvalidPayloadType(128))));
+        assert!(matches!(H264Packetizer::new(1, 96, 14), Err(RtpError::InvalidMtu(14))));
+        assert!(matches!(H264Packetizer::new(1, 96, 65_508), Err(RtpError::InvalidMtu(65_508))));
+
+        let mut packetizer = H264Packetizer::new(1, 96, 1200).unwrap();
+        let (mut sink, packets) = mpsc::channel();
+        let result = packetizer.send_frame(&[0, 0, 0, 1, 0x41, 1, 0, 0, 1, 0xE5, 2], 0, &mut sink);
+        assert!(matches!(result, Err(RtpError::InvalidNal(_))));
+        assert!(matches!(packetizer.send_frame(&[0, 0, 1], 0, &mut sink), Err(RtpError::InvalidNal(_))));
+        // Neither frame sent a packet or used a sequence number.
+        assert!(packets.try_recv().is_err());
+        assert_eq!(packetizer.next_sequence(), 0);
+
+        drop(packets);
+        assert!(matches!(packetizer.send_frame(&[0, 0, 1, 0x41, 1], 0, &mut sink), Err(RtpError::Send(_))));
+    }
+}
@@ -0,0 +1,2 @@
+pub(crate) mod h264;
+pub(crate) mod h265;
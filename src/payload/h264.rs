@@ -0,0 +1,105 @@
+use crate::{annexb, RtpError};
+
+mod params;
+pub(crate) use params::{pps_ids, slice_position, sps_frame_size, ParameterSetCache};
+
+pub(crate) fn parameter_set_type(nal: &[u8]) -> H264NalType {
+    if nal[0] & 0x1F == H264NalType::Sps as u8 {
+        H264NalType::Sps
+    } else {
+        H264NalType::Pps
+    }
+}
+
+// NAL header byte to send, with the NRI bits (5-6) optionally replaced.
+pub(crate) fn nal_header(original: u8, nal_type: &H264NalType, rewrite_nri: bool) -> u8 {
+    if !rewrite_nri {
+        return original;
+    }
+    (original & 0b1001_1111) | (nal_type.nri() << 5)
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum H264NalType {
+    UnKnown = 0,
+    NonIdr = 1,
+    Idr = 5,
+    Sei = 6,
+    Sps = 7,
+    Pps = 8,
+    Aud = 9,
+    EndOfSeq = 10,
+    EndOfStream = 11,
+    Filler = 12,
+}
+
+impl H264NalType {
+    pub fn from_header(header: u8) -> Self {
+        match header & 0x1F {
+            1 => H264NalType::NonIdr,
+            5 => H264NalType::Idr,
+            6 => H264NalType::Sei,
+            7 => H264NalType::Sps,
+            8 => H264NalType::Pps,
+            9 => H264NalType::Aud,
+            10 => H264NalType::EndOfSeq,
+            11 => H264NalType::EndOfStream,
+            12 => H264NalType::Filler,
+            _ => H264NalType::UnKnown,
+        }
+    }
+
+    // Importance of the NAL type as carried in the NRI bits.
+    pub fn nri(&self) -> u8 {
+        match self {
+            H264NalType::Sps | H264NalType::Pps | H264NalType::Idr => 3,
+            H264NalType::NonIdr | H264NalType::UnKnown => 2,
+            H264NalType::Sei
+            | H264NalType::Aud
+            | H264NalType::EndOfSeq
+            | H264NalType::EndOfStream
+            | H264NalType::Filler => 0,
+        }
+    }
+}
+
+// NAL units of an Annex B frame that can go on the wire. Types 0 and 24-31
+// are unspecified in H.264, and receivers would take 24-29 for RTP payload
+// structures (RFC 6184 section 5.4), so they are skipped.
+pub(crate) fn nal_units(frame_buffer: &[u8]) -> impl Iterator<Item = (H264NalType, &[u8])> {
+    annexb::nal_units(frame_buffer)
+        .filter(|unit| is_sendable(unit.data[0]))
+        .map(|unit| (H264NalType::from_header(unit.data[0]), unit.data))
+}
+
+// Types 0 and 24-31 can't go on the wire as single NAL packets.
+pub(crate) fn is_sendable(header: u8) -> bool {
+    !matches!(header & 0x1F, 0 | 24..=31)
+}
+
+// NAL units of an AVCC frame, each preceded by its big-endian length in
+// `length_size` bytes. Empty NALs and the types nal_units skips are left
+// out.
+pub(crate) fn avcc_nal_units(frame: &[u8], length_size: u8) -> Result<Vec<(H264NalType, &[u8])>, RtpError> {
+    let length_size = match length_size {
+        1 | 2 | 4 => length_size as usize,
+        _ => return Err(RtpError::InvalidNal("AVCC length size must be 1, 2 or 4"))
+    };
+    let mut nals = Vec::new();
+    let mut remaining = frame;
+    while !remaining.is_empty() {
+        let (prefix, rest) = remaining
+            .split_at_checked(length_size)
+            .ok_or(RtpError::InvalidNal("truncated AVCC length prefix"))?;
+        let length = prefix.iter().fold(0usize, |length, &byte| (length << 8) | byte as usize);
+        let (nal, rest) = rest
+            .split_at_checked(length)
+            .ok_or(RtpError::InvalidNal("AVCC NAL length runs past the frame"))?;
+        if nal.first().is_some_and(|&header| is_sendable(header)) {
+            nals.push((H264NalType::from_header(nal[0]), nal));
+        }
+        remaining = rest;
+    }
+    Ok(nals)
+}
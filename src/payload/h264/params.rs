@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+// Only the first bytes of a NAL are needed to read the ids we care about.
+const MAX_HEADER_BYTES: usize = 32;
+// Enough for an SPS up to its frame_mbs_only_flag, scaling lists included.
+const MAX_SPS_BYTES: usize = 256;
+// Profiles whose SPS carry chroma_format_idc and the fields after it (H.264
+// section 7.3.2.1.1).
+const HIGH_PROFILES: [u8; 12] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134];
+
+// SPS and PPS seen so far, keyed by their ids so an IDR can be given exactly
+// the pair it references.
+#[derive(Default)]
+pub struct ParameterSetCache {
+    sps: BTreeMap<u32, Vec<u8>>,
+    // pps id -> (referenced sps id, NAL)
+    pps: BTreeMap<u32, (u32, Vec<u8>)>
+}
+
+impl ParameterSetCache {
+    // Parameter sets whose ids can't be parsed are not cached.
+    pub fn insert_sps(&mut self, nal: &[u8]) {
+        if let Some(id) = sps_id(nal) {
+            self.sps.insert(id, nal.to_vec());
+        }
+    }
+
+    pub fn insert_pps(&mut self, nal: &[u8]) {
+        if let Some((pps_id, sps_id)) = pps_ids(nal) {
+            self.pps.insert(pps_id, (sps_id, nal.to_vec()));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sps.is_empty() && self.pps.is_empty()
+    }
+
+    // Parameter sets to send ahead of `idr`, always SPS before PPS. When the
+    // slice header names a PPS we have (and we have its SPS) only that pair
+    // is returned; otherwise every cached SPS, then every cached PPS.
+    pub fn select_for_idr(&self, idr: &[u8]) -> Vec<&[u8]> {
+        let referenced = slice_pps_id(idr).and_then(|pps_id| {
+            let (sps_id, pps) = self.pps.get(&pps_id)?;
+            let sps = self.sps.get(sps_id)?;
+            Some(vec![sps.as_slice(), pps.as_slice()])
+        });
+
+        referenced.unwrap_or_else(|| {
+            self.sps
+                .values()
+                .map(Vec::as_slice)
+                .chain(self.pps.values().map(|(_, pps)| pps.as_slice()))
+                .collect()
+        })
+    }
+}
+
+fn sps_id(nal: &[u8]) -> Option<u32> {
+    let rbsp = rbsp_header(nal, MAX_HEADER_BYTES);
+    // profile_idc, constraint flags and level_idc come before the id.
+    let mut reader = BitReader::new(rbsp.get(3..)?);
+    reader.read_ue()
+}
+
+pub fn pps_ids(nal: &[u8]) -> Option<(u32, u32)> {
+    let rbsp = rbsp_header(nal, MAX_HEADER_BYTES);
+    let mut reader = BitReader::new(&rbsp);
+    let pps_id = reader.read_ue()?;
+    let sps_id = reader.read_ue()?;
+    Some((pps_id, sps_id))
+}
+
+fn slice_pps_id(nal: &[u8]) -> Option<u32> {
+    slice_position(nal).map(|(_, pps_id)| pps_id)
+}
+
+// first_mb_in_slice and the PPS id of a slice header.
+pub fn slice_position(nal: &[u8]) -> Option<(u32, u32)> {
+    let rbsp = rbsp_header(nal, MAX_HEADER_BYTES);
+    let mut reader = BitReader::new(&rbsp);
+    let first_mb_in_slice = reader.read_ue()?;
+    let _slice_type = reader.read_ue()?;
+    Some((first_mb_in_slice, reader.read_ue()?))
+}
+
+// The id of an SPS and the number of macroblocks in a frame it describes,
+// PicSizeInMbs of a frame (H.264 sections 7.3.2.1.1 and 7.4.2.1.1).
+pub fn sps_frame_size(nal: &[u8]) -> Option<(u32, u32)> {
+    let rbsp = rbsp_header(nal, MAX_SPS_BYTES);
+    let profile_idc = *rbsp.first()?;
+    let mut reader = BitReader::new(rbsp.get(3..)?);
+    let id = reader.read_ue()?;
+    if HIGH_PROFILES.contains(&profile_idc) {
+        let chroma_format_idc = reader.read_ue()?;
+        if chroma_format_idc == 3 {
+            let _separate_colour_plane_flag = reader.read_bit()?;
+        }
+        let _bit_depth_luma_minus8 = reader.read_ue()?;
+        let _bit_depth_chroma_minus8 = reader.read_ue()?;
+        let _qpprime_y_zero_transform_bypass_flag = reader.read_bit()?;
+        if reader.read_bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for list in 0..lists {
+                if reader.read_bit()? == 1 {
+                    reader.skip_scaling_list(if list < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    let _log2_max_frame_num_minus4 = reader.read_ue()?;
+    match reader.read_ue()? {
+        0 => {
+            let _log2_max_pic_order_cnt_lsb_minus4 = reader.read_ue()?;
+        }
+        1 => {
+            let _delta_pic_order_always_zero_flag = reader.read_bit()?;
+            let _offset_for_non_ref_pic = reader.read_se()?;
+            let _offset_for_top_to_bottom_field = reader.read_se()?;
+            for _ in 0..reader.read_ue()? {
+                reader.read_se()?;
+            }
+        }
+        _ => {}
+    }
+    let _max_num_ref_frames = reader.read_ue()?;
+    let _gaps_in_frame_num_value_allowed_flag = reader.read_bit()?;
+    let width_in_mbs = reader.read_ue()?.checked_add(1)?;
+    let height_in_map_units = reader.read_ue()?.checked_add(1)?;
+    // Map units are macroblock pairs unless every picture is a frame.
+    let frame_height_in_mbs = (2 - reader.read_bit()?) * height_in_map_units;
+    Some((id, width_in_mbs.checked_mul(frame_height_in_mbs)?))
+}
+
+// Start of the NAL payload (header byte skipped), up to `max_bytes`, with
+// emulation prevention bytes (00 00 03) removed.
+fn rbsp_header(nal: &[u8], max_bytes: usize) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(max_bytes);
+    let mut zeros = 0;
+    for &byte in nal.iter().skip(1) {
+        if rbsp.len() == max_bytes {
+            break;
+        }
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+// Reads exp-Golomb coded values (H.264 section 9.1).
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit_pos: usize
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bit_pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.bit_pos / 8)?;
+        let bit = (byte >> (7 - self.bit_pos % 8)) & 1;
+        self.bit_pos += 1;
+        Some(bit as u32)
+    }
+
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.read_bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+
+        let mut suffix: u64 = 0;
+        for _ in 0..leading_zeros {
+            suffix = (suffix << 1) | self.read_bit()? as u64;
+        }
+        u32::try_from((1u64 << leading_zeros) - 1 + suffix).ok()
+    }
+
+    fn read_se(&mut self) -> Option<i32> {
+        let code = self.read_ue()? as i64;
+        let magnitude = (code + 1) / 2;
+        i32::try_from(if code % 2 == 1 { magnitude } else { -magnitude }).ok()
+    }
+
+    // scaling_list() of H.264 section 7.3.2.1.1.1, read for its length.
+    fn skip_scaling_list(&mut self, size: usize) -> Option<()> {
+        let mut last_scale: i64 = 8;
+        let mut next_scale: i64 = 8;
+        for _ in 0..size {
+            if next_scale != 0 {
+                next_scale = (last_scale + self.read_se()? as i64).rem_euclid(256);
+            }
+            if next_scale != 0 {
+                last_scale = next_scale;
+            }
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,24 @@
+// H.265 NAL units (ITU-T H.265 section 7.3.1.2) and their RTP payload
+// structures (RFC 7798).
+
+pub(crate) const NAL_HEADER_SIZE: usize = 2;
+
+pub(crate) const NAL_TYPE_VPS: u8 = 32;
+const NAL_TYPE_SPS: u8 = 33;
+const NAL_TYPE_PPS: u8 = 34;
+pub(crate) const NAL_TYPE_AP: u8 = 48;
+pub(crate) const NAL_TYPE_FU: u8 = 49;
+
+// nal_unit_type: bits 1-6 of the first header byte.
+pub(crate) fn nal_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3F
+}
+
+// BLA, IDR and CRA pictures: decoding can start here.
+pub(crate) fn is_irap(nal_type: u8) -> bool {
+    (16..=21).contains(&nal_type)
+}
+
+pub(crate) fn is_parameter_set(nal_type: u8) -> bool {
+    matches!(nal_type, NAL_TYPE_VPS | NAL_TYPE_SPS | NAL_TYPE_PPS)
+}
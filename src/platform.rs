@@ -0,0 +1,893 @@
+// Socket options std does not expose. Each OS maps them to its native
+// equivalent where one exists; everything else returns
+// RtpError::UnsupportedOnPlatform instead of silently doing nothing.
+//
+// Linux:   DSCP via IP_TOS, bind-to-device via SO_BINDTODEVICE, DF via
+//          IP(V6)_MTU_DISCOVER.
+// macOS:   DSCP via IP_TOS, bind-to-device via IP_BOUND_IF, DF via
+//          IP(V6)_DONTFRAG.
+// Other unix: DSCP via IP_TOS only.
+// Windows: none (DSCP needs the QoS2 API and admin policy).
+// SO_SNDBUF and IP_MULTICAST_IF are available on every unix.
+// Waiting on several sockets uses poll(2) on every unix; none on Windows.
+// Binding with SO_REUSEADDR, so several receivers share a multicast port,
+// uses socket(2) and bind(2) on every unix; Windows binds without it.
+// Interface names in IPv6 zones use if_nametoindex(3) on every unix;
+// Windows only takes numeric zones.
+// Batched sends use sendmmsg(2) on Linux; elsewhere one send per packet.
+// Batched receives use recvmmsg(2) on Linux, recv(2) with MSG_DONTWAIT on
+// other unix and one recv per call on Windows. Only Linux reports kernel
+// drops (SO_RXQ_OVFL).
+// Memory-mapped files (the mmap feature) use mmap(2) and madvise(2) on
+// every unix; the feature is not built on Windows.
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::Duration;
+
+use crate::RtpError;
+
+#[cfg(all(unix, feature = "mmap"))]
+pub use map::FileMap;
+
+pub fn set_dscp(socket: &UdpSocket, dscp: u8) -> Result<(), RtpError> {
+    if dscp > 63 {
+        return Err(RtpError::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "DSCP must fit in 6 bits",
+        )));
+    }
+    imp::set_tos(socket, dscp << 2)
+}
+
+pub fn bind_to_device(socket: &UdpSocket, interface: &str) -> Result<(), RtpError> {
+    imp::bind_to_device(socket, interface)
+}
+
+// Returns the size the kernel actually applied, which may differ from the
+// request (Linux doubles it, limits cap it).
+pub fn set_send_buffer_size(socket: &UdpSocket, bytes: usize) -> Result<usize, RtpError> {
+    imp::set_send_buffer_size(socket, bytes)
+}
+
+// Sets the Don't Fragment bit on outgoing packets, so datagrams larger than
+// the known path MTU fail with EMSGSIZE instead of being fragmented.
+pub fn set_dont_fragment(socket: &UdpSocket) -> Result<(), RtpError> {
+    let ipv6 = socket.local_addr()?.is_ipv6();
+    imp::set_dont_fragment(socket, ipv6)
+}
+
+// Sends IPv4 multicast out of the interface with address `interface`
+// rather than the one the routing table picks.
+pub fn set_multicast_interface_v4(socket: &UdpSocket, interface: Ipv4Addr) -> Result<(), RtpError> {
+    imp::set_multicast_interface_v4(socket, interface)
+}
+
+// Index of the network interface `name`, e.g. the scope id for
+// "fe80::1%eth0".
+#[cfg(unix)]
+pub fn interface_index(name: &str) -> io::Result<u32> {
+    sys::interface_index(name)
+}
+
+#[cfg(not(unix))]
+pub fn interface_index(name: &str) -> io::Result<u32> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("interface names in IPv6 zones are not supported here, use the index instead of {:?}", name),
+    ))
+}
+
+// Binds `local` with SO_REUSEADDR set, so other sockets (in this or another
+// process) can bind the same port to receive the same multicast group.
+#[cfg(unix)]
+pub fn bind_shared_v4(local: SocketAddrV4) -> Result<UdpSocket, RtpError> {
+    sys::bind_reusable(&imp::sockaddr_in(local), imp::SOL_SOCKET, imp::SO_REUSEADDR).map_err(RtpError::Bind)
+}
+
+#[cfg(not(unix))]
+pub fn bind_shared_v4(local: SocketAddrV4) -> Result<UdpSocket, RtpError> {
+    UdpSocket::bind(local).map_err(RtpError::Bind)
+}
+
+// Sends `packets` as datagrams to `destination`, or to the peer of a
+// connected socket when it is None, in as few system calls as the OS
+// allows. Returns how many went out, counting from the first; an error
+// means not even the first one did.
+#[cfg(target_os = "linux")]
+pub fn send_batch(socket: &UdpSocket, destination: Option<SocketAddr>, packets: &[&[u8]]) -> io::Result<usize> {
+    imp::send_batch(socket, destination, packets)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send_batch(socket: &UdpSocket, destination: Option<SocketAddr>, packets: &[&[u8]]) -> io::Result<usize> {
+    for (sent, packet) in packets.iter().enumerate() {
+        let result = match destination {
+            Some(destination) => socket.send_to(packet, destination),
+            None => socket.send(packet)
+        };
+        if let Err(err) = result {
+            return if sent == 0 { Err(err) } else { Ok(sent) };
+        }
+    }
+    Ok(packets.len())
+}
+
+// Reads the datagrams queued on `socket` into consecutive `slot_size` slots
+// of `buffer`, waiting (up to the read timeout) only for the first, and
+// stores their lengths in `lens`. Returns how many were read and the
+// socket's drop counter if the kernel attached one (see
+// report_kernel_drops).
+#[cfg(target_os = "linux")]
+pub fn recv_batch(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+    slot_size: usize,
+    lens: &mut [usize]
+) -> io::Result<(usize, Option<u32>)> {
+    imp::recv_batch(socket, buffer, slot_size, lens)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn recv_batch(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+    slot_size: usize,
+    lens: &mut [usize]
+) -> io::Result<(usize, Option<u32>)> {
+    let mut slots = buffer.chunks_mut(slot_size).zip(lens.iter_mut());
+    let mut count = 0;
+    if let Some((slot, len)) = slots.next() {
+        *len = socket.recv(slot)?;
+        count = 1;
+    }
+    // Whatever else is already queued, without waiting. A failure here
+    // ends the batch; a lasting one shows up on the next blocking read.
+    for (slot, len) in slots {
+        match sys::recv_dontwait(socket, slot, imp::MSG_DONTWAIT) {
+            Ok(received) => *len = received,
+            Err(_) => break
+        }
+        count += 1;
+    }
+    Ok((count, None))
+}
+
+// Windows has no per-call non-blocking flag, and switching the socket to
+// non-blocking mode would also affect a pusher sharing it: one datagram
+// per call.
+#[cfg(not(unix))]
+pub fn recv_batch(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+    slot_size: usize,
+    lens: &mut [usize]
+) -> io::Result<(usize, Option<u32>)> {
+    match lens.first_mut() {
+        Some(len) => {
+            *len = socket.recv(&mut buffer[..slot_size])?;
+            Ok((1, None))
+        }
+        None => Ok((0, None))
+    }
+}
+
+// Makes the kernel attach the socket's count of dropped datagrams to what
+// recv_batch reads (SO_RXQ_OVFL). False where the OS cannot.
+#[cfg(target_os = "linux")]
+pub fn report_kernel_drops(socket: &UdpSocket) -> bool {
+    imp::report_kernel_drops(socket)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn report_kernel_drops(_socket: &UdpSocket) -> bool {
+    false
+}
+
+// True for a send rejected because the datagram exceeds the path MTU.
+pub fn is_message_too_large(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(imp::EMSGSIZE)
+}
+
+// True for errors meaning the kernel could not queue the datagram right now
+// (ENOBUFS, EAGAIN) as opposed to a real send failure.
+pub fn is_kernel_drop(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.raw_os_error() == Some(imp::ENOBUFS)
+}
+
+// Blocks until at least one of `sockets` has a datagram (or an error) to
+// read, and returns the indexes of those that do; empty after `timeout`.
+#[cfg(unix)]
+pub fn wait_readable(sockets: &[&UdpSocket], timeout: Option<Duration>) -> Result<Vec<usize>, RtpError> {
+    Ok(sys::wait_readable(sockets, timeout)?)
+}
+
+#[cfg(not(unix))]
+pub fn wait_readable(_sockets: &[&UdpSocket], _timeout: Option<Duration>) -> Result<Vec<usize>, RtpError> {
+    Err(RtpError::UnsupportedOnPlatform("waiting on several sockets"))
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_short, c_uint, c_void, CString};
+    use std::io;
+    use std::net::UdpSocket;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::time::Duration;
+
+    const POLLIN: c_short = 0x1;
+    const AF_INET: c_int = 2;
+    const SOCK_DGRAM: c_int = 2;
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short
+    }
+
+    #[cfg(target_os = "linux")]
+    type NfdsT = std::ffi::c_ulong;
+    #[cfg(not(target_os = "linux"))]
+    type NfdsT = std::ffi::c_uint;
+
+    extern "C" {
+        fn poll(fds: *mut PollFd, nfds: NfdsT, timeout: c_int) -> c_int;
+        fn socket(domain: c_int, kind: c_int, protocol: c_int) -> c_int;
+        fn bind(fd: c_int, address: *const c_void, len: u32) -> c_int;
+        fn if_nametoindex(name: *const c_char) -> c_uint;
+        #[cfg(not(target_os = "linux"))]
+        fn recv(fd: c_int, buffer: *mut c_void, len: usize, flags: c_int) -> isize;
+        fn setsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *const c_void,
+            len: u32,
+        ) -> c_int;
+        fn getsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *mut c_void,
+            len: *mut u32,
+        ) -> c_int;
+    }
+
+    pub fn set_option(socket: &UdpSocket, level: c_int, name: c_int, value: &[u8]) -> io::Result<()> {
+        // SAFETY: value points to value.len() readable bytes for the whole call.
+        let ret = unsafe {
+            setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                value.as_ptr() as *const c_void,
+                value.len() as u32,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn interface_index(name: &str) -> io::Result<u32> {
+        let name = CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        // SAFETY: name is a valid NUL-terminated string for the whole call.
+        let index = unsafe { if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(index)
+    }
+
+    // `address` is a sockaddr_in in the OS's layout.
+    pub fn bind_reusable(address: &[u8; 16], sol_socket: c_int, so_reuseaddr: c_int) -> io::Result<UdpSocket> {
+        // SAFETY: plain system call without pointer arguments.
+        let fd = unsafe { socket(AF_INET, SOCK_DGRAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: fd is a fresh socket nothing else owns; the UdpSocket
+        // closes it on every path from here.
+        let udp = unsafe { UdpSocket::from_raw_fd(fd) };
+        set_option(&udp, sol_socket, so_reuseaddr, &(1 as c_int).to_ne_bytes())?;
+        // SAFETY: address points to 16 readable bytes for the whole call.
+        let ret = unsafe { bind(fd, address.as_ptr() as *const c_void, address.len() as u32) };
+        if ret == 0 {
+            Ok(udp)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn recv_dontwait(socket: &UdpSocket, buffer: &mut [u8], msg_dontwait: c_int) -> io::Result<usize> {
+        loop {
+            // SAFETY: buffer is valid for writes of buffer.len() bytes for
+            // the whole call.
+            let ret = unsafe {
+                recv(socket.as_raw_fd(), buffer.as_mut_ptr() as *mut c_void, buffer.len(), msg_dontwait)
+            };
+            if ret >= 0 {
+                return Ok(ret as usize);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn get_int_option(socket: &UdpSocket, level: c_int, name: c_int) -> io::Result<c_int> {
+        let mut value: c_int = 0;
+        let mut len = std::mem::size_of::<c_int>() as u32;
+        // SAFETY: value and len are valid for writes for the whole call.
+        let ret = unsafe {
+            getsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &mut value as *mut c_int as *mut c_void,
+                &mut len,
+            )
+        };
+        if ret == 0 {
+            Ok(value)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn wait_readable(sockets: &[&UdpSocket], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+        let mut fds: Vec<PollFd> = sockets
+            .iter()
+            .map(|socket| PollFd {
+                fd: socket.as_raw_fd(),
+                events: POLLIN,
+                revents: 0
+            })
+            .collect();
+        // Rounded up, so a short timeout does not turn into a busy loop.
+        let timeout_ms = match timeout {
+            Some(timeout) => c_int::try_from(timeout.as_nanos().div_ceil(1_000_000)).unwrap_or(c_int::MAX),
+            None => -1
+        };
+        loop {
+            // SAFETY: fds points to fds.len() initialized pollfd structs for
+            // the whole call.
+            let ret = unsafe { poll(fds.as_mut_ptr(), fds.len() as NfdsT, timeout_ms) };
+            if ret >= 0 {
+                break;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        Ok(fds
+            .iter()
+            .enumerate()
+            .filter(|(_, fd)| fd.revents != 0)
+            .map(|(index, _)| index)
+            .collect())
+    }
+
+    pub fn set_send_buffer_size(
+        socket: &UdpSocket,
+        sol_socket: c_int,
+        so_sndbuf: c_int,
+        bytes: usize,
+    ) -> io::Result<usize> {
+        let requested = c_int::try_from(bytes).unwrap_or(c_int::MAX);
+        set_option(socket, sol_socket, so_sndbuf, &requested.to_ne_bytes())?;
+        Ok(get_int_option(socket, sol_socket, so_sndbuf)?.max(0) as usize)
+    }
+}
+
+#[cfg(all(unix, feature = "mmap"))]
+mod map {
+    use std::ffi::{c_int, c_void};
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+    const MADV_WILLNEED: c_int = 3;
+    const MADV_DONTNEED: c_int = 4;
+
+    // off_t: long on Linux (glibc and musl without _FILE_OFFSET_BITS), 64
+    // bits on the BSDs and macOS.
+    #[cfg(target_os = "linux")]
+    type OffT = std::ffi::c_long;
+    #[cfg(not(target_os = "linux"))]
+    type OffT = i64;
+
+    extern "C" {
+        fn mmap(address: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: OffT) -> *mut c_void;
+        fn munmap(address: *mut c_void, len: usize) -> c_int;
+        fn madvise(address: *mut c_void, len: usize, advice: c_int) -> c_int;
+    }
+
+    // madvise takes ranges from a page boundary. A multiple of every page
+    // size in use (4, 16 and 64 KiB) saves asking for it.
+    const ADVICE_ALIGN: usize = 64 << 10;
+
+    // A whole file mapped read-only. Pages are read in as they are touched
+    // and can be given back with `release`.
+    pub struct FileMap {
+        address: *mut c_void,
+        len: usize
+    }
+
+    // SAFETY: the mapping is read-only and owned by the FileMap alone.
+    unsafe impl Send for FileMap {}
+    unsafe impl Sync for FileMap {}
+
+    impl FileMap {
+        pub fn new(file: &File) -> io::Result<Self> {
+            let len = usize::try_from(file.metadata()?.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+            if len == 0 {
+                // mmap refuses empty mappings.
+                return Ok(Self {
+                    address: ptr::null_mut(),
+                    len
+                });
+            }
+            // SAFETY: a fresh private read-only mapping of an open file;
+            // nothing else refers to the address range.
+            let address = unsafe { mmap(ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+            if address as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { address, len })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: the mapping covers len readable bytes until drop. The
+            // file may change under it; the bytes are then whatever it
+            // holds, never unmapped memory.
+            unsafe { std::slice::from_raw_parts(self.address as *const u8, self.len) }
+        }
+
+        // Hints that `start..end` is about to be read.
+        pub fn prefetch(&self, start: usize, end: usize) {
+            self.advise(start, end, MADV_WILLNEED);
+        }
+
+        // Gives the pages of `start..end` back to the kernel. They stay
+        // mapped: reading them again reads them in from the file again.
+        pub fn release(&self, start: usize, end: usize) {
+            self.advise(start, end, MADV_DONTNEED);
+        }
+
+        fn advise(&self, start: usize, end: usize, advice: c_int) {
+            // Rounded up: the bytes just before `start` may still be in use.
+            let start = start.next_multiple_of(ADVICE_ALIGN);
+            let end = end.min(self.len);
+            if start >= end {
+                return;
+            }
+            // SAFETY: start..end lies within the mapping. The advice is a
+            // hint; a failure changes nothing and is ignored.
+            unsafe {
+                madvise(self.address.add(start), end - start, advice);
+            }
+        }
+    }
+
+    impl Drop for FileMap {
+        fn drop(&mut self) {
+            if self.len != 0 {
+                // SAFETY: unmaps what new mapped; no borrow of it outlives self.
+                unsafe {
+                    munmap(self.address, self.len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::{c_int, c_uint, c_void};
+    use std::io;
+    use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+    use std::os::unix::io::AsRawFd;
+
+    use super::sys::set_option;
+    use crate::RtpError;
+
+    // sendmmsg takes at most UIO_MAXIOV messages per call.
+    const MAX_BATCH: usize = 1024;
+    const MSG_NOSIGNAL: c_int = 0x4000;
+
+    #[repr(C)]
+    struct IoVec {
+        base: *const c_void,
+        len: usize
+    }
+
+    #[repr(C)]
+    struct MsgHdr {
+        name: *const c_void,
+        name_len: u32,
+        iov: *const IoVec,
+        iov_len: usize,
+        control: *const c_void,
+        control_len: usize,
+        flags: c_int
+    }
+
+    #[repr(C)]
+    struct MMsgHdr {
+        header: MsgHdr,
+        len: c_uint
+    }
+
+    extern "C" {
+        fn sendmmsg(fd: c_int, messages: *mut MMsgHdr, count: c_uint, flags: c_int) -> c_int;
+        fn recvmmsg(fd: c_int, messages: *mut MMsgHdr, count: c_uint, flags: c_int, timeout: *mut c_void) -> c_int;
+    }
+
+    // Blocks for the first datagram only; the rest are what is queued.
+    const MSG_WAITFORONE: c_int = 0x10000;
+    const SO_RXQ_OVFL: c_int = 40;
+    // Room for one cmsghdr (16 bytes on 64-bit) and its 4-byte counter,
+    // padded, with some to spare on 32-bit.
+    const CONTROL_SIZE: usize = 32;
+    // Messages per recvmmsg call, kept on the stack.
+    const MAX_RECV_BATCH: usize = 64;
+    const CMSG_HEADER_SIZE: usize = std::mem::size_of::<usize>() + 8;
+
+    pub const SOL_SOCKET: c_int = 1;
+    pub const SO_REUSEADDR: c_int = 2;
+    const SO_SNDBUF: c_int = 7;
+    const SO_BINDTODEVICE: c_int = 25;
+    const IPPROTO_IP: c_int = 0;
+    const IP_TOS: c_int = 1;
+    const IP_MTU_DISCOVER: c_int = 10;
+    const IP_MULTICAST_IF: c_int = 32;
+    const IPPROTO_IPV6: c_int = 41;
+    const IPV6_MTU_DISCOVER: c_int = 23;
+    // Same value for IPv4 and IPv6: always set DF, honour the cached path MTU.
+    const PMTUDISC_DO: c_int = 2;
+    pub const ENOBUFS: i32 = 105;
+    pub const EMSGSIZE: i32 = 90;
+
+    pub fn set_tos(socket: &UdpSocket, tos: u8) -> Result<(), RtpError> {
+        let value = (tos as c_int).to_ne_bytes();
+        Ok(set_option(socket, IPPROTO_IP, IP_TOS, &value)?)
+    }
+
+    pub fn bind_to_device(socket: &UdpSocket, interface: &str) -> Result<(), RtpError> {
+        Ok(set_option(socket, SOL_SOCKET, SO_BINDTODEVICE, interface.as_bytes())?)
+    }
+
+    pub fn set_dont_fragment(socket: &UdpSocket, ipv6: bool) -> Result<(), RtpError> {
+        let value = PMTUDISC_DO.to_ne_bytes();
+        if ipv6 {
+            Ok(set_option(socket, IPPROTO_IPV6, IPV6_MTU_DISCOVER, &value)?)
+        } else {
+            Ok(set_option(socket, IPPROTO_IP, IP_MTU_DISCOVER, &value)?)
+        }
+    }
+
+    pub fn set_send_buffer_size(socket: &UdpSocket, bytes: usize) -> Result<usize, RtpError> {
+        Ok(super::sys::set_send_buffer_size(socket, SOL_SOCKET, SO_SNDBUF, bytes)?)
+    }
+
+    pub fn set_multicast_interface_v4(socket: &UdpSocket, interface: Ipv4Addr) -> Result<(), RtpError> {
+        Ok(set_option(socket, IPPROTO_IP, IP_MULTICAST_IF, &interface.octets())?)
+    }
+
+    // struct sockaddr_in: family, port, address, 8 bytes of padding.
+    pub fn sockaddr_in(address: SocketAddrV4) -> [u8; 16] {
+        let mut raw = [0u8; 16];
+        raw[..2].copy_from_slice(&2u16.to_ne_bytes());
+        raw[2..4].copy_from_slice(&address.port().to_be_bytes());
+        raw[4..8].copy_from_slice(&address.ip().octets());
+        raw
+    }
+
+    // struct sockaddr_in6: family, port, flow info, address, scope id.
+    fn sockaddr_in6(address: SocketAddrV6) -> [u8; 28] {
+        let mut raw = [0u8; 28];
+        raw[..2].copy_from_slice(&10u16.to_ne_bytes());
+        raw[2..4].copy_from_slice(&address.port().to_be_bytes());
+        raw[4..8].copy_from_slice(&address.flowinfo().to_be_bytes());
+        raw[8..24].copy_from_slice(&address.ip().octets());
+        raw[24..].copy_from_slice(&address.scope_id().to_ne_bytes());
+        raw
+    }
+
+    pub fn send_batch(socket: &UdpSocket, destination: Option<SocketAddr>, packets: &[&[u8]]) -> io::Result<usize> {
+        let mut name = [0u8; 28];
+        let name_len = match destination {
+            Some(SocketAddr::V4(v4)) => {
+                name[..16].copy_from_slice(&sockaddr_in(v4));
+                16
+            }
+            Some(SocketAddr::V6(v6)) => {
+                name.copy_from_slice(&sockaddr_in6(v6));
+                28
+            }
+            None => 0
+        };
+        let packets = &packets[..packets.len().min(MAX_BATCH)];
+        let iovs: Vec<IoVec> = packets
+            .iter()
+            .map(|packet| IoVec {
+                base: packet.as_ptr() as *const c_void,
+                len: packet.len()
+            })
+            .collect();
+        let mut messages: Vec<MMsgHdr> = iovs
+            .iter()
+            .map(|iov| MMsgHdr {
+                header: MsgHdr {
+                    name: if name_len == 0 { std::ptr::null() } else { name.as_ptr() as *const c_void },
+                    name_len,
+                    iov,
+                    iov_len: 1,
+                    control: std::ptr::null(),
+                    control_len: 0,
+                    flags: 0
+                },
+                len: 0
+            })
+            .collect();
+        loop {
+            // SAFETY: every message points at `name`, one iovec and the
+            // packet it describes, all alive and unmodified for the call.
+            let ret = unsafe {
+                sendmmsg(socket.as_raw_fd(), messages.as_mut_ptr(), messages.len() as c_uint, MSG_NOSIGNAL)
+            };
+            if ret >= 0 {
+                return Ok(ret as usize);
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn report_kernel_drops(socket: &UdpSocket) -> bool {
+        set_option(socket, SOL_SOCKET, SO_RXQ_OVFL, &(1 as c_int).to_ne_bytes()).is_ok()
+    }
+
+    pub fn recv_batch(
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+        slot_size: usize,
+        lens: &mut [usize]
+    ) -> io::Result<(usize, Option<u32>)> {
+        let mut control = [[0u8; CONTROL_SIZE]; MAX_RECV_BATCH];
+        let mut slots = buffer.chunks_mut(slot_size);
+        let iovs: [IoVec; MAX_RECV_BATCH] = std::array::from_fn(|_| match slots.next() {
+            Some(slot) => IoVec {
+                base: slot.as_mut_ptr() as *const c_void,
+                len: slot.len()
+            },
+            None => IoVec {
+                base: std::ptr::null(),
+                len: 0
+            }
+        });
+        let count = lens.len().min(buffer.len() / slot_size).min(MAX_RECV_BATCH);
+        let mut controls = control.iter_mut();
+        let mut messages: [MMsgHdr; MAX_RECV_BATCH] = std::array::from_fn(|index| MMsgHdr {
+            header: MsgHdr {
+                name: std::ptr::null(),
+                name_len: 0,
+                iov: &iovs[index],
+                iov_len: 1,
+                control: controls.next().map_or(std::ptr::null(), |control| control.as_mut_ptr() as *const c_void),
+                control_len: CONTROL_SIZE,
+                flags: 0
+            },
+            len: 0
+        });
+        let received = loop {
+            // SAFETY: the first `count` messages each point at one iovec
+            // describing its own slot of `buffer` and at its own
+            // CONTROL_SIZE bytes of `control`, all alive and otherwise
+            // untouched for the call.
+            let ret = unsafe {
+                recvmmsg(
+                    socket.as_raw_fd(),
+                    messages.as_mut_ptr(),
+                    count as c_uint,
+                    MSG_WAITFORONE,
+                    std::ptr::null_mut()
+                )
+            };
+            if ret >= 0 {
+                break ret as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+
+        let mut drops = None;
+        for ((message, control), len) in messages.iter().zip(&control).zip(lens.iter_mut()).take(received) {
+            *len = message.len as usize;
+            // The kernel sets control_len to what it wrote.
+            let control_len = message.header.control_len.min(CONTROL_SIZE);
+            drops = find_drop_counter(&control[..control_len]).or(drops);
+        }
+        Ok((received, drops))
+    }
+
+    // The SO_RXQ_OVFL value among the control messages of one datagram.
+    fn find_drop_counter(mut control: &[u8]) -> Option<u32> {
+        let word = std::mem::size_of::<usize>();
+        while control.len() >= CMSG_HEADER_SIZE {
+            let len = usize::from_ne_bytes(control[..word].try_into().ok()?);
+            let level = c_int::from_ne_bytes(control[word..word + 4].try_into().ok()?);
+            let kind = c_int::from_ne_bytes(control[word + 4..word + 8].try_into().ok()?);
+            if len < CMSG_HEADER_SIZE || len > control.len() {
+                return None;
+            }
+            if level == SOL_SOCKET && kind == SO_RXQ_OVFL && len >= CMSG_HEADER_SIZE + 4 {
+                let value = &control[CMSG_HEADER_SIZE..CMSG_HEADER_SIZE + 4];
+                return Some(u32::from_ne_bytes(value.try_into().ok()?));
+            }
+            // Each message is padded to the word size.
+            let next = len.div_ceil(word) * word;
+            if next >= control.len() {
+                return None;
+            }
+            control = &control[next..];
+        }
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::ffi::c_int;
+    use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+    use super::sys::{interface_index, set_option};
+    use crate::RtpError;
+
+    pub const SOL_SOCKET: c_int = 0xffff;
+    pub const SO_REUSEADDR: c_int = 0x4;
+    const SO_SNDBUF: c_int = 0x1001;
+    const IPPROTO_IP: c_int = 0;
+    const IP_TOS: c_int = 3;
+    const IP_MULTICAST_IF: c_int = 9;
+    const IP_BOUND_IF: c_int = 25;
+    const IP_DONTFRAG: c_int = 28;
+    const IPPROTO_IPV6: c_int = 41;
+    const IPV6_DONTFRAG: c_int = 62;
+    pub const ENOBUFS: i32 = 55;
+    pub const EMSGSIZE: i32 = 40;
+    pub const MSG_DONTWAIT: c_int = 0x80;
+
+    pub fn set_tos(socket: &UdpSocket, tos: u8) -> Result<(), RtpError> {
+        let value = (tos as c_int).to_ne_bytes();
+        Ok(set_option(socket, IPPROTO_IP, IP_TOS, &value)?)
+    }
+
+    pub fn bind_to_device(socket: &UdpSocket, interface: &str) -> Result<(), RtpError> {
+        let value = (interface_index(interface)? as c_int).to_ne_bytes();
+        Ok(set_option(socket, IPPROTO_IP, IP_BOUND_IF, &value)?)
+    }
+
+    pub fn set_dont_fragment(socket: &UdpSocket, ipv6: bool) -> Result<(), RtpError> {
+        let value = (1 as c_int).to_ne_bytes();
+        if ipv6 {
+            Ok(set_option(socket, IPPROTO_IPV6, IPV6_DONTFRAG, &value)?)
+        } else {
+            Ok(set_option(socket, IPPROTO_IP, IP_DONTFRAG, &value)?)
+        }
+    }
+
+    pub fn set_send_buffer_size(socket: &UdpSocket, bytes: usize) -> Result<usize, RtpError> {
+        Ok(super::sys::set_send_buffer_size(socket, SOL_SOCKET, SO_SNDBUF, bytes)?)
+    }
+
+    pub fn set_multicast_interface_v4(socket: &UdpSocket, interface: Ipv4Addr) -> Result<(), RtpError> {
+        Ok(set_option(socket, IPPROTO_IP, IP_MULTICAST_IF, &interface.octets())?)
+    }
+
+    // struct sockaddr_in: length, family, port, address, 8 bytes of
+    // padding.
+    pub fn sockaddr_in(address: SocketAddrV4) -> [u8; 16] {
+        let mut raw = [16u8, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        raw[2..4].copy_from_slice(&address.port().to_be_bytes());
+        raw[4..8].copy_from_slice(&address.ip().octets());
+        raw
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+mod imp {
+    use std::ffi::c_int;
+    use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+    use super::sys::set_option;
+    use crate::RtpError;
+
+    pub const SOL_SOCKET: c_int = 0xffff;
+    pub const SO_REUSEADDR: c_int = 0x4;
+    const SO_SNDBUF: c_int = 0x1001;
+    const IPPROTO_IP: c_int = 0;
+    const IP_TOS: c_int = 3;
+    const IP_MULTICAST_IF: c_int = 9;
+    pub const ENOBUFS: i32 = 55;
+    pub const EMSGSIZE: i32 = 40;
+    pub const MSG_DONTWAIT: c_int = 0x80;
+
+    pub fn set_tos(socket: &UdpSocket, tos: u8) -> Result<(), RtpError> {
+        let value = (tos as c_int).to_ne_bytes();
+        Ok(set_option(socket, IPPROTO_IP, IP_TOS, &value)?)
+    }
+
+    pub fn bind_to_device(_socket: &UdpSocket, _interface: &str) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("bind to device"))
+    }
+
+    pub fn set_dont_fragment(_socket: &UdpSocket, _ipv6: bool) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("don't fragment"))
+    }
+
+    pub fn set_send_buffer_size(socket: &UdpSocket, bytes: usize) -> Result<usize, RtpError> {
+        Ok(super::sys::set_send_buffer_size(socket, SOL_SOCKET, SO_SNDBUF, bytes)?)
+    }
+
+    pub fn set_multicast_interface_v4(socket: &UdpSocket, interface: Ipv4Addr) -> Result<(), RtpError> {
+        Ok(set_option(socket, IPPROTO_IP, IP_MULTICAST_IF, &interface.octets())?)
+    }
+
+    // struct sockaddr_in: length, family, port, address, 8 bytes of
+    // padding.
+    pub fn sockaddr_in(address: SocketAddrV4) -> [u8; 16] {
+        let mut raw = [16u8, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        raw[2..4].copy_from_slice(&address.port().to_be_bytes());
+        raw[4..8].copy_from_slice(&address.ip().octets());
+        raw
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    use crate::RtpError;
+
+    // WSAENOBUFS, WSAEMSGSIZE
+    pub const ENOBUFS: i32 = 10055;
+    pub const EMSGSIZE: i32 = 10040;
+
+    pub fn set_tos(_socket: &UdpSocket, _tos: u8) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("DSCP"))
+    }
+
+    pub fn bind_to_device(_socket: &UdpSocket, _interface: &str) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("bind to device"))
+    }
+
+    pub fn set_dont_fragment(_socket: &UdpSocket, _ipv6: bool) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("don't fragment"))
+    }
+
+    pub fn set_send_buffer_size(_socket: &UdpSocket, _bytes: usize) -> Result<usize, RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("send buffer size"))
+    }
+
+    pub fn set_multicast_interface_v4(_socket: &UdpSocket, _interface: Ipv4Addr) -> Result<(), RtpError> {
+        Err(RtpError::UnsupportedOnPlatform("multicast interface"))
+    }
+}
@@ -0,0 +1,1593 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::annexb::{self, AnnexBWriter, StartCodeStyle};
+use crate::capture::{self, Capture, PcapWriter};
+use crate::clock::{Clock, SystemClock};
+use crate::extension::{self, PacketExtensions};
+use crate::rtp::RtpPacket;
+use crate::sender::destination::{self, AddressPreference};
+use crate::sender::{random_u32, RtpSink};
+use crate::stats::{Finding, Goodbye, HeaderStats, JitterAdaptation, KeyframeRequest, LatencyEstimate, MemberEvent, MetricsSummary, PathStats, PayloadErrors, ReceivedFrameStats, ReceiverStats, ReceptionReport, RelayStats, SenderReport, SessionMember};
+use crate::trace::{Trace, TraceSource, TraceWriter};
+use crate::{platform, rtcp, CancelToken, PacketTransform, RtpError, TcpFraming};
+
+mod adaptive;
+mod assembler;
+mod burst_gap;
+mod continuity;
+mod doctor;
+mod header_check;
+mod jitter;
+mod members;
+mod merge;
+mod pool;
+mod reception;
+mod refresh;
+mod relay;
+mod snapshot;
+mod sniff;
+mod source;
+mod tcp;
+pub use source::RtpSource;
+
+// Largest UDP payload; packets are never truncated.
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+// Longest a blocking call waits before looking at its CancelToken again.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives H.264 over RTP and reassembles Annex B access units.
+///
+/// FU-A fragments are joined back into NAL units, STAP-A packets are split
+/// into theirs, and every NAL gets a 4-byte start code. A NAL that lost a
+/// fragment is dropped; the rest of its frame is still delivered. Late and
+/// duplicate packets are ignored; duplicates are counted in
+/// [`stats`](Self::stats).
+///
+/// The receiver starts no threads. Dropping it, also while a panic
+/// unwinds, closes every socket it bound (RTP, RTCP, relay RTCP) and
+/// flushes an attached trace.
+pub struct H264RtpReceiver {
+    socket: UdpSocket,
+    pool: pool::PacketPool,
+    // The socket's drop counter as last reported, if the OS reports one.
+    kernel_drops: Option<u32>,
+    assembler: assembler::FrameAssembler,
+    header_check: header_check::HeaderCheck,
+    // Whether Receiver Reports carry an XR VoIP Metrics block.
+    xr_reports: bool,
+    // Everyone whose RTCP arrived, whichever SSRC is the source.
+    members: members::SessionMembers,
+    packet_transform: Option<PacketTransform>,
+    transform_drops: u64,
+    idle_timeout: Option<Duration>,
+    clock: Arc<dyn Clock>,
+
+    // Our SSRC as a report sender (RFC 3550 section 8.1: random).
+    ssrc: u32,
+    reception: reception::ReceptionStatistics,
+    receiver_reports: Option<ReceiverReports>,
+    // End-to-end latency, smoothed over the frames delivered.
+    smoothed_latency: Option<Duration>,
+    // Where RTCP is received and sent from; None for the RTP port + 1.
+    rtcp_port: Option<u16>,
+
+    redundancy: Option<Redundancy>,
+    jitter: Option<jitter::JitterBuffer>,
+    reliability: Reliability,
+    // Checks the first packets for H.265, when enabled.
+    sniffer: Option<sniff::CodecSniffer>,
+    // SSRC and sequence number of the last packet, kept when lossless.
+    last_seq: Option<(u32, u16)>,
+    // Clock time of the last read, when a datagram arrived or the wait
+    // ended. Decisions between reads use it rather than the clock, so they
+    // depend on nothing a trace does not record.
+    now: Instant,
+
+    // Unless RTP arrives on the UDP socket, it then only serves RTCP.
+    transport: Transport,
+    // Set once a source reported the end of its packets.
+    source_ended: bool,
+
+    capture: Capture,
+    trace: Trace,
+    relay: Option<relay::Relay>,
+    #[cfg(feature = "srtp")]
+    srtp: Option<crate::srtp::Context>,
+    #[cfg(feature = "srtp")]
+    srtp_auth_failures: u64
+}
+
+/// An access unit and what the receiver learned about it, from
+/// `H264RtpReceiver::recv_annotated_frame`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Frame {
+    /// Annex B, every NAL with a 4-byte start code.
+    pub data: Vec<u8>,
+    /// RTP timestamp of the frame's packets.
+    pub timestamp: u32,
+    /// One-byte header extensions of the frame's packets that had any, in
+    /// the order they were reassembled.
+    pub extensions: Vec<PacketExtensions>,
+    pub integrity: IntegrityResult,
+    /// Frames went missing right before this one, by the step in RTP
+    /// timestamps (see `set_gap_tolerance`).
+    pub preceded_by_gap: bool,
+    /// Capture-to-delivery latency by the source's last Sender Report;
+    /// None before one arrived (see `enable_receiver_reports`).
+    pub latency: Option<LatencyEstimate>
+}
+
+/// The latest whole IDR frame, from `H264RtpReceiver::latest_keyframe`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KeyframeSnapshot {
+    /// The frame as Annex B, preceded by the SPS and PPS it needs when it
+    /// did not carry them itself, so it decodes on its own.
+    pub annexb: Vec<u8>,
+    /// RTP timestamp of the frame's packets.
+    pub timestamp: u32,
+    /// When the frame's last packet was received.
+    pub received_at: Instant
+}
+
+/// Called with each new keyframe snapshot, see
+/// `H264RtpReceiver::set_keyframe_callback`.
+pub type KeyframeCallback = Box<dyn FnMut(&KeyframeSnapshot) + Send>;
+
+/// A frame lent to the callback of `H264RtpReceiver::recv_frame_with`. It
+/// borrows the receiver's buffer, which holds a later frame once the
+/// callback returns, so it cannot outlive the call:
+///
+/// ```compile_fail
+/// # use rtp_transceive::receiver::H264RtpReceiver;
+/// # let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+/// let first_nal = receiver.recv_frame_with(|frame| frame.nals().next()).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct FrameRef<'a> {
+    /// Annex B, every NAL with a 4-byte start code.
+    pub data: &'a [u8],
+    pub timestamp: u32,
+    pub extensions: &'a [PacketExtensions],
+    pub integrity: IntegrityResult,
+    pub preceded_by_gap: bool
+}
+
+impl<'a> FrameRef<'a> {
+    /// The frame's NAL units, header included and start codes left out.
+    pub fn nals(&self) -> impl Iterator<Item = &'a [u8]> {
+        annexb::nal_units(self.data).map(|nal| nal.data)
+    }
+}
+
+/// Whether a frame matches the CRC its pusher sent (see
+/// `H264RtpReceiver::set_frame_integrity`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityResult {
+    Ok,
+    /// The frame was damaged on the way, or lost a NAL to packet loss.
+    Mismatch,
+    /// No CRC came with the frame, or checking is off.
+    Absent
+}
+
+/// How long the jitter buffer waits for a missing packet before giving up
+/// on it (see `H264RtpReceiver::set_jitter_buffer`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JitterDepth {
+    /// Until this many later packets are held.
+    Packets(usize),
+    /// Until the first packet held behind the gap has waited this long.
+    Time(Duration),
+    /// Like `Time`, with a wait the receiver adapts to the delay variation
+    /// it measures, between `min` and `max`: it grows at once when packets
+    /// arrive less regularly and comes down slowly, over seconds, once they
+    /// settle. It starts at `min`. See `H264RtpReceiver::jitter_adaptations`.
+    Adaptive { min: Duration, max: Duration }
+}
+
+/// When the receiver asks the sender for a keyframe by itself (see
+/// `H264RtpReceiver::set_refresh_policy`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RefreshPolicy {
+    /// Ask once more than this share of the frames, in percent, finished
+    /// within `window` were incomplete.
+    pub incomplete_percent: u8,
+    pub window: Duration,
+    /// Also ask once a non-IDR frame that later frames refer to (non-zero
+    /// NRI) lost packets: the decoder shows artifacts until the next IDR.
+    pub on_reference_loss: bool,
+    /// Wait before asking again when no keyframe came, doubled each time
+    /// up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration
+}
+
+impl Default for RefreshPolicy {
+    /// 10% of the frames over 2 s, or a lost reference; asked again after
+    /// 500 ms, then up to every 8 s.
+    fn default() -> Self {
+        Self {
+            incomplete_percent: 10,
+            window: Duration::from_secs(2),
+            on_reference_loss: true,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8)
+        }
+    }
+}
+
+/// What the relay does with RTCP in one direction (see
+/// `H264RtpReceiver::enable_relay_rtcp`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RtcpRelayMode {
+    /// Compound packets are passed on whole, with the SSRCs in them
+    /// rewritten as the relayed RTP is.
+    #[default]
+    PassThrough,
+    /// The relay ends the direction's reports and speaks for itself:
+    /// towards the receivers, a Sender Report of its own with the origin's
+    /// timing and the relay's counts for each one from the origin; towards
+    /// the origin, the relay's own Receiver Reports alone.
+    Regenerate
+}
+
+/// A video codec, as `RtpError::CodecMismatch` names them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Codec {
+    H264,
+    H265
+}
+
+/// What the receiver may assume about the path packets take to it (see
+/// `H264RtpReceiver::set_reliability`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Reliability {
+    /// A network: packets may be lost, repeated or reordered. They are
+    /// counted, put back in order by the jitter buffer if there is one, and
+    /// reassembled around.
+    #[default]
+    BestEffort,
+    /// A transport that cannot lose or reorder packets, such as a Unix
+    /// socket or a channel between processes on one host. There is no
+    /// jitter buffer, so nothing waits for a packet that cannot be late,
+    /// and a packet that is not the next in sequence fails the read with
+    /// `RtpError::SequenceGap`: it means a bug on this host, not loss.
+    Lossless
+}
+
+// Where RTP packets come from: the UDP socket, unless the receiver was
+// built on a TCP connection or an RtpSource.
+enum Transport {
+    Udp,
+    Tcp(tcp::TcpSource),
+    Source {
+        source: Box<dyn RtpSource>,
+        // The read timeout, passed along with each read.
+        timeout: Option<Duration>
+    }
+}
+
+// Second socket receiving the same stream over another network path.
+struct Redundancy {
+    socket: UdpSocket,
+    merger: merge::PathMerger
+}
+
+struct ReceiverReports {
+    socket: UdpSocket,
+    interval: Duration,
+    // Fixed destination, else the address Sender Reports come from.
+    destination: Option<SocketAddr>,
+    cname: String,
+    sent_at: Option<Instant>,
+    last_report: Option<ReceptionReport>
+}
+
+// What a receive loop came up with: an assembled frame, or one left in the
+// receive pool, with where its NAL is in the datagram popped last.
+enum Received {
+    Frame(Frame),
+    Lent(assembler::LentFrame, Range<usize>)
+}
+
+impl H264RtpReceiver {
+    /// Binds `local`, e.g. `0.0.0.0:7032`, `[::]:7032` or a scoped
+    /// link-local address such as `[fe80::1%eth0]:7032`. It is resolved
+    /// like an `H264RtpPusher` destination. `[::]` also receives IPv4 where
+    /// the OS makes IPv6 sockets dual-stack (the Linux default).
+    pub fn new(local: &str) -> Result<Self, RtpError> {
+        Self::with_socket_addr(destination::resolve(local, AddressPreference::First)?)
+    }
+
+    /// Receives on `socket`, which the caller has already bound (and maybe
+    /// connected), e.g. after ICE. Apart from enabling drop reporting for
+    /// `kernel_drops`, no socket option is changed until the caller sets an
+    /// idle timeout or records with `save_to_file`. For
+    /// symmetric RTP, give a `try_clone` of the same socket to
+    /// `H264RtpPusher::from_socket`; both then use one 5-tuple.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self::build(socket, Transport::Udp)
+    }
+
+    /// Like `new`, for a local address that is already resolved.
+    pub fn with_socket_addr(local: SocketAddr) -> Result<Self, RtpError> {
+        let socket = UdpSocket::bind(local).map_err(RtpError::Bind)?;
+        Ok(Self::build(socket, Transport::Udp))
+    }
+
+    /// Receives the IPv4 multicast group `group` on `port`, joined on the
+    /// interface with address `interface` (`Ipv4Addr::UNSPECIFIED` lets the
+    /// kernel choose). The port is bound with SO_REUSEADDR on unix, so
+    /// several receivers on one host can take the same stream.
+    pub fn multicast_v4(group: Ipv4Addr, port: u16, interface: Ipv4Addr) -> Result<Self, RtpError> {
+        let socket = platform::bind_shared_v4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))?;
+        socket.join_multicast_v4(&group, &interface)?;
+        Ok(Self::build(socket, Transport::Udp))
+    }
+
+    /// Listens for TCP on `local` and blocks until a sender connects, e.g.
+    /// an `H264RtpPusher` after `connect_tcp` with the same `framing`. RTP
+    /// is then read from that connection. A UDP socket is still bound to
+    /// the same address for `local_addr` and the RTCP port derived from it.
+    /// The connection closing fails `recv_frame` with
+    /// `RtpError::Disconnected`.
+    pub fn accept_tcp(local: &str, framing: TcpFraming) -> Result<Self, RtpError> {
+        let local = destination::resolve(local, AddressPreference::First)?;
+        let listener = TcpListener::bind(local).map_err(RtpError::Bind)?;
+        let local = listener.local_addr()?;
+        let socket = UdpSocket::bind(local).map_err(RtpError::Bind)?;
+        let (stream, _) = listener.accept()?;
+        Ok(Self::build(socket, Transport::Tcp(tcp::TcpSource::new(stream, framing))))
+    }
+
+    /// Reads RTP from a connection that is already open, e.g. the RTSP
+    /// connection of a session using `TcpFraming::Interleaved`. The UDP
+    /// socket gets an ephemeral port on the connection's local address.
+    pub fn from_tcp_stream(stream: TcpStream, framing: TcpFraming) -> Result<Self, RtpError> {
+        let local = SocketAddr::new(stream.local_addr()?.ip(), 0);
+        let socket = UdpSocket::bind(local).map_err(RtpError::Bind)?;
+        Ok(Self::build(socket, Transport::Tcp(tcp::TcpSource::new(stream, framing))))
+    }
+
+    /// Reads RTP from `source` instead of the network, e.g. a
+    /// `capture::PcapSource` replaying a recorded stream. Packets take the
+    /// same path as datagrams read from a UDP socket. The source ending
+    /// (`UnexpectedEof`) counts as an idle timeout: held packets are
+    /// released, then `recv_frame` fails with `RtpError::IdleTimeout`. Any
+    /// other read error fails it with `RtpError::Disconnected`. A UDP
+    /// socket is still bound, to an ephemeral loopback port, for
+    /// `local_addr` and RTCP.
+    pub fn from_source(source: Box<dyn RtpSource>) -> Result<Self, RtpError> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).map_err(RtpError::Bind)?;
+        Ok(Self::build(socket, Transport::Source { source, timeout: None }))
+    }
+
+    /// Replays a trace recorded with `enable_trace` (see the `trace`
+    /// module): reads come from `source` and the receiver runs on its
+    /// clock. Give it the settings the recorded receiver had, before the
+    /// first frame is read, and it delivers the same frames with the same
+    /// statistics. At the end of the trace held packets are released, as
+    /// for any source that ended.
+    pub fn from_trace(source: TraceSource) -> Result<Self, RtpError> {
+        let clock = source.clock();
+        let mut receiver = Self::from_source(Box::new(source))?;
+        receiver.set_clock(Arc::new(clock));
+        Ok(receiver)
+    }
+
+    fn build(socket: UdpSocket, transport: Transport) -> Self {
+        let reports_drops = matches!(transport, Transport::Udp) && platform::report_kernel_drops(&socket);
+        Self {
+            socket,
+            pool: pool::PacketPool::new(MAX_DATAGRAM_SIZE),
+            kernel_drops: reports_drops.then_some(0),
+            assembler: assembler::FrameAssembler::default(),
+            header_check: header_check::HeaderCheck::default(),
+            xr_reports: false,
+            members: members::SessionMembers::default(),
+            packet_transform: None,
+            transform_drops: 0,
+            idle_timeout: None,
+            clock: Arc::new(SystemClock),
+            ssrc: random_u32(),
+            reception: reception::ReceptionStatistics::new(Instant::now()),
+            receiver_reports: None,
+            smoothed_latency: None,
+            rtcp_port: None,
+            redundancy: None,
+            jitter: None,
+            reliability: Reliability::BestEffort,
+            sniffer: None,
+            last_seq: None,
+            now: Instant::now(),
+            transport,
+            source_ended: false,
+            capture: Capture::new(),
+            trace: Trace::new(),
+            relay: None,
+            #[cfg(feature = "srtp")]
+            srtp: None,
+            #[cfg(feature = "srtp")]
+            srtp_auth_failures: 0
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, RtpError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Replaces the time source (see the `clock` module), e.g. with a
+    /// `ManualClock` in tests. Arrival times, the jitter buffer, RTCP
+    /// intervals and latency estimates follow it; socket reads still wait
+    /// on the OS clock, so the idle timeout and cancellation do too. Call
+    /// this before the first packet.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.reception = reception::ReceptionStatistics::new(clock.now());
+        self.now = clock.now();
+        self.clock = clock;
+    }
+
+    /// Also receives the IPv4 multicast group `group`, joined on the
+    /// interface with address `interface`. The socket must be bound to the
+    /// port the group is sent to, on 0.0.0.0 or the group address.
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<(), RtpError> {
+        Ok(self.socket.join_multicast_v4(&group, &interface)?)
+    }
+
+    /// Makes `recv_frame` give up with `RtpError::IdleTimeout` once no
+    /// datagram arrived for `timeout`. `None` (the default) waits forever.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) -> Result<(), RtpError> {
+        self.set_read_timeout(timeout)?;
+        self.idle_timeout = timeout;
+        Ok(())
+    }
+
+    // On the socket RTP arrives on.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match &mut self.transport {
+            Transport::Udp => self.socket.set_read_timeout(timeout),
+            Transport::Tcp(tcp) => tcp.set_read_timeout(timeout),
+            Transport::Source { timeout: current, .. } => {
+                *current = timeout;
+                Ok(())
+            }
+        }
+    }
+
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match &self.transport {
+            Transport::Udp => self.socket.read_timeout(),
+            Transport::Tcp(tcp) => tcp.read_timeout(),
+            Transport::Source { timeout, .. } => Ok(*timeout)
+        }
+    }
+
+    /// Puts packets that arrive out of order (common on Wi-Fi) back in
+    /// sequence before they are reassembled. At a gap, later packets are
+    /// held for up to `depth` in case the missing one is only late; after
+    /// that it counts as lost and the held packets go on. Packets arriving
+    /// after their place was passed are dropped. `ReceiverStats::reordered`
+    /// and `ReceiverStats::late` count both. `None` (the default) passes
+    /// packets on as they arrive, dropping any older than the last one.
+    ///
+    /// Set it right after construction: packets held when the depth changes
+    /// are dropped. The first packet of a stream is held for the depth as
+    /// well, in case earlier ones are still coming. Held packets never wait
+    /// beyond the idle timeout, and at most 4096 are held whatever the
+    /// depth.
+    /// Has no effect on a lossless receiver (see `set_reliability`).
+    pub fn set_jitter_buffer(&mut self, depth: Option<JitterDepth>) {
+        if self.reliability == Reliability::BestEffort {
+            self.jitter = depth.map(jitter::JitterBuffer::new);
+        }
+    }
+
+    /// The changes `JitterDepth::Adaptive` made to the jitter buffer's wait
+    /// since the last call, oldest first; up to the latest 1024 are kept.
+    /// `stats` has the current wait.
+    pub fn jitter_adaptations(&mut self) -> Vec<JitterAdaptation> {
+        self.jitter.as_mut().map(jitter::JitterBuffer::take_adaptations).unwrap_or_default()
+    }
+
+    /// `Reliability::Lossless` removes the jitter buffer and checks that
+    /// every packet follows the one before it from the same SSRC. A packet
+    /// that does not is still reassembled, as after loss, and the read
+    /// that took it fails with `RtpError::SequenceGap`; the next read goes
+    /// on from it. Set it right after construction, like the jitter buffer.
+    /// `BestEffort` is the default.
+    pub fn set_reliability(&mut self, reliability: Reliability) {
+        self.reliability = reliability;
+        self.last_seq = None;
+        if reliability == Reliability::Lossless {
+            self.jitter = None;
+        }
+    }
+
+    /// Checks the payloads of the first 8 packets for the codec they carry.
+    /// If they look like H.265 (two-byte NAL headers, FU type 49), the read
+    /// that took the last of them fails once with
+    /// `RtpError::CodecMismatch(Codec::H264, Codec::H265)`: the stream
+    /// was labeled as H.264 but this receiver cannot take it apart. Packets
+    /// are handled as usual meanwhile, so nothing waits for the check. Off
+    /// by default; enabling it again starts a new check.
+    pub fn set_codec_sniffing(&mut self, enabled: bool) {
+        self.sniffer = enabled.then(sniff::CodecSniffer::default);
+    }
+
+    /// Watches the packets reaching reassembly for sender bugs cameras are
+    /// known for: FU-A fragments without their Start fragment,
+    /// forbidden_zero_bit set, slices outside the frame their SPS gives and
+    /// timestamps frozen while the sequence goes on. What it finds is read
+    /// with `diagnostics`; packets are handled as usual either way. Off by
+    /// default; enabling it again starts over.
+    pub fn set_stream_doctor(&mut self, enabled: bool) {
+        self.assembler.set_doctor(enabled.then(doctor::StreamDoctor::default));
+    }
+
+    /// The stream doctor's findings, in the order first seen, each pathology
+    /// once with its occurrences counted. Empty while it is off.
+    pub fn diagnostics(&self) -> Vec<Finding> {
+        self.assembler.doctor().map(doctor::StreamDoctor::findings).unwrap_or_default()
+    }
+
+    /// Keeps the latest whole IDR frame, with the SPS and PPS it needs, for
+    /// `latest_keyframe`, e.g. for a preview. Each new one replaces the last,
+    /// so at most one is held. IDR frames that lost packets, or that arrive
+    /// before any SPS and PPS did, are passed over. Off by default; enabling
+    /// it again starts over.
+    pub fn set_keyframe_snapshots(&mut self, enabled: bool) {
+        self.assembler.set_snapshots(enabled.then(snapshot::KeyframeSnapshots::default));
+    }
+
+    /// The snapshot kept by `set_keyframe_snapshots`; None while it is off
+    /// and before the first whole IDR frame.
+    pub fn latest_keyframe(&self) -> Option<KeyframeSnapshot> {
+        self.assembler.latest_snapshot().cloned()
+    }
+
+    /// Calls `callback` with each new snapshot of `set_keyframe_snapshots`,
+    /// during the receive call that completed the frame, so it should be
+    /// quick. `None` (the default) removes it.
+    pub fn set_keyframe_callback(&mut self, callback: Option<KeyframeCallback>) {
+        self.assembler.set_snapshot_callback(callback);
+    }
+
+    /// Records the stream to an Annex B file at `path` for `duration` and
+    /// returns the number of frames written. Frames are written whole, so
+    /// the file stays playable however the call ends. `cancel` stops the
+    /// recording within 100 ms with `RtpError::Cancelled` (after flushing
+    /// what was written); call `cancel()` on it from a Ctrl-C handler for a
+    /// clean shutdown. The idle timeout does not apply during the call.
+    pub fn save_to_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        duration: Duration,
+        cancel: Option<&CancelToken>,
+    ) -> Result<u64, RtpError> {
+        let mut file = AnnexBWriter::new(BufWriter::new(File::create(path)?), StartCodeStyle::Long);
+        let mut frames = 0;
+        let result = self.write_frames(&mut file, Instant::now() + duration, cancel, &mut frames);
+        self.set_read_timeout(self.idle_timeout)?;
+        file.get_mut().flush()?;
+        result.map(|()| frames)
+    }
+
+    fn write_frames(
+        &mut self,
+        out: &mut AnnexBWriter<impl Write>,
+        deadline: Instant,
+        cancel: Option<&CancelToken>,
+        frames: &mut u64,
+    ) -> Result<(), RtpError> {
+        loop {
+            if let Some(token) = cancel {
+                token.check()?;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+
+            self.set_read_timeout(Some(remaining.min(CANCEL_POLL_INTERVAL)))?;
+            match self.recv_frame() {
+                Ok(frame) => {
+                    out.write_frame(&frame)?;
+                    *frames += 1;
+                }
+                Err(RtpError::IdleTimeout) => {}
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    /// Runs `transform` on every received datagram before it is parsed,
+    /// e.g. to strip a trailer the sender's transform added. Returning false
+    /// drops the datagram; drops are counted in `transform_drops`.
+    pub fn set_packet_transform(&mut self, transform: Option<PacketTransform>) {
+        self.packet_transform = transform;
+    }
+
+    pub fn transform_drops(&self) -> u64 {
+        self.transform_drops
+    }
+
+    /// Expects every RTP packet from now on to be protected with SRTP under
+    /// `key` (see the `srtp` module). Each packet's tag is checked and its
+    /// payload decrypted before anything else, the packet transform
+    /// included; packets that fail the check are dropped and counted in
+    /// `ReceiverStats::srtp_auth_failures`. Repeats of authentic packets are
+    /// not rejected here: duplicates are dropped later anyway (see
+    /// `ReceiverStats::duplicates`). `None` goes back to plain RTP.
+    #[cfg(feature = "srtp")]
+    pub fn set_srtp(&mut self, key: Option<&crate::srtp::SrtpKey>) {
+        self.srtp = key.map(crate::srtp::Context::new);
+        self.srtp_auth_failures = 0;
+    }
+
+    /// Writes every RTP packet received from now on into a pcap file at
+    /// `path` (see the `capture` module), for opening in Wireshark.
+    /// Replaces a running capture.
+    pub fn enable_capture<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RtpError> {
+        self.set_capture(Some(PcapWriter::create(path)?));
+        Ok(())
+    }
+
+    /// Captures into `writer`, e.g. one with size rotation set up; `None`
+    /// stops capturing and closes the file. Packets are captured as they
+    /// are read, before the packet transform, from every path, and are
+    /// written as UDP to the local address. The sender's address is only
+    /// known over TCP; UDP packets show 0.0.0.0 (or ::) port 0 as their
+    /// source.
+    /// The file is flushed after every frame. A failed write stops the
+    /// capture but not the stream; see `capture_error`.
+    pub fn set_capture(&mut self, writer: Option<PcapWriter>) {
+        let addresses = match &self.transport {
+            Transport::Tcp(tcp) => tcp.addresses(),
+            _ => self.socket.local_addr().map(|local| (local, capture::unknown_peer(local)))
+        };
+        let (local, peer) = addresses.unwrap_or((capture::UNKNOWN, capture::UNKNOWN));
+        self.capture.set(writer, local, peer);
+    }
+
+    /// Why the capture stopped, if a write to it failed.
+    pub fn capture_error(&self) -> Option<&RtpError> {
+        self.capture.error()
+    }
+
+    /// Records every datagram read from now on, with when it was read, and
+    /// every read that timed out into a trace at `path`, for
+    /// `from_trace` to replay. Replaces a running trace.
+    pub fn enable_trace<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RtpError> {
+        self.set_trace(Some(TraceWriter::create(path)?));
+        Ok(())
+    }
+
+    /// Traces into `writer`; `None` stops tracing and closes the file.
+    /// Times count from the receiver's start on its clock (creation, or
+    /// `set_clock`), as a replay's do; start the trace before the first
+    /// packet for the replay to match from the start. Datagrams are traced
+    /// as they are read, like a capture; those of redundant paths and RTCP
+    /// are not. The file is flushed after every frame. A failed write stops
+    /// the trace but not the stream; see `trace_error`.
+    pub fn set_trace(&mut self, writer: Option<TraceWriter>) {
+        self.trace.set(writer, self.reception.clock_start(), &*self.clock);
+    }
+
+    /// Why the trace stopped, if a write to it failed.
+    pub fn trace_error(&self) -> Option<&RtpError> {
+        self.trace.error()
+    }
+
+    /// Forwards every datagram received from now on to `sink` exactly as it
+    /// was read, e.g. for compliance recording next to live viewing: SSRC
+    /// (unless `set_relay_ssrc` says otherwise), header extensions, padding
+    /// and SRTP protection are left as they are. Datagrams go out in arrival
+    /// order, before the jitter buffer, the packet transform or any check,
+    /// from every path. A datagram the sink refuses is counted in
+    /// `relay_stats` and skipped; frames are received as before. `None`
+    /// stops relaying.
+    pub fn set_relay(&mut self, sink: Option<Box<dyn RtpSink>>) {
+        self.relay = sink.map(relay::Relay::new);
+    }
+
+    /// Puts `ssrc` in place of the source's SSRC in the datagrams the relay
+    /// forwards, and in the RTCP it relays, so the receivers behind it see
+    /// one consistent source; `None` leaves it as sent. This breaks SRTP
+    /// authentication, so it is for plain RTP only. Applies to the relay of
+    /// the last `set_relay`, and fails without one.
+    pub fn set_relay_ssrc(&mut self, ssrc: Option<u32>) -> Result<(), RtpError> {
+        let relay = self.relay.as_mut().ok_or_else(no_relay)?;
+        relay.set_ssrc(ssrc);
+        Ok(())
+    }
+
+    /// Relays RTCP in both directions too, so the receivers behind the relay
+    /// get the origin's Sender Reports for lip sync and the origin gets
+    /// their Receiver Reports for bitrate adaptation. RTCP from the receivers
+    /// is read on `local`, which they must send to, and the origin's goes to
+    /// `downstream`; each direction passes packets through or regenerates
+    /// them, as its `RtcpRelayMode` says. Compound packets are passed on
+    /// whole or not at all. The origin side uses the receiver report socket,
+    /// so it needs `enable_receiver_reports`. Applies to the relay of the
+    /// last `set_relay`, and fails without one.
+    ///
+    /// Both addresses take the forms `H264RtpPusher::new` does. A name that
+    /// resolves to several addresses picks one of `local`'s family, and a
+    /// `downstream` of the other family fails here.
+    pub fn enable_relay_rtcp(
+        &mut self,
+        local: &str,
+        downstream: &str,
+        to_receivers: RtcpRelayMode,
+        to_origin: RtcpRelayMode,
+    ) -> Result<(), RtpError> {
+        let relay = self.relay.as_mut().ok_or_else(no_relay)?;
+        let local = destination::resolve(local, AddressPreference::First)?;
+        let socket = UdpSocket::bind(local).map_err(RtpError::Bind)?;
+        socket.set_nonblocking(true)?;
+        let local = socket.local_addr()?;
+        let downstream = destination::resolve(downstream, same_family(local))?;
+        destination::check_families(local, downstream)?;
+        relay.set_rtcp(Some(relay::RtcpRelay::new(socket, downstream, to_receivers, to_origin)));
+        Ok(())
+    }
+
+    /// Where the relay reads RTCP from the receivers behind it, once
+    /// `enable_relay_rtcp` was called.
+    pub fn relay_rtcp_local_addr(&self) -> Option<SocketAddr> {
+        self.relay.as_ref().and_then(relay::Relay::rtcp_local_addr)
+    }
+
+    /// Counters of the relay; all zero without one.
+    pub fn relay_stats(&self) -> RelayStats {
+        self.relay.as_ref().map_or_else(RelayStats::default, relay::Relay::stats)
+    }
+
+    /// Datagrams the kernel dropped on the socket, typically because they
+    /// arrived faster than they were read and the receive buffer filled up.
+    /// The count covers the socket's whole life. The kernel stamps it on
+    /// each datagram as it is queued, so drops show up once a datagram
+    /// queued after them has been read. `None` where the OS does not report
+    /// it (only Linux does, via SO_RXQ_OVFL) or when RTP arrives over TCP
+    /// or from an `RtpSource`.
+    pub fn kernel_drops(&self) -> Option<u64> {
+        self.kernel_drops.map(u64::from)
+    }
+
+    /// Listens for RTCP on the RTP port + 1 (or the port given to
+    /// `set_rtcp_port`) and sends a Receiver Report about the stream every
+    /// `interval` (5 s is the usual choice) while frames are being
+    /// received, from that same port. Sender Reports that arrive there fill
+    /// in LSR/DLSR, so the sender can compute the round-trip time, and are
+    /// kept for `last_sender_report`; a BYE from the source shows up in
+    /// `goodbye`.
+    ///
+    /// Reports go to `destination`, or by default back to wherever the
+    /// Sender Reports come from, which is where `H264RtpPusher` listens for
+    /// them. Without a destination no report is sent before the first SR.
+    /// `destination` takes the forms `H264RtpPusher::new` does; of several
+    /// resolved addresses one of the RTP socket's family is picked, and
+    /// one of the other family fails here.
+    pub fn enable_receiver_reports(
+        &mut self,
+        interval: Duration,
+        destination: Option<&str>,
+    ) -> Result<(), RtpError> {
+        let local = self.socket.local_addr()?;
+        let port = match self.rtcp_port {
+            Some(port) => port,
+            None => local.port().checked_add(1).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "RTP port 65535 has no RTCP port")
+            })?
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(local.ip(), port)).map_err(RtpError::Bind)?;
+        socket.set_nonblocking(true)?;
+        let local = socket.local_addr()?;
+        let destination = match destination {
+            Some(destination) => {
+                let destination = destination::resolve(destination, same_family(local))?;
+                destination::check_families(local, destination)?;
+                Some(destination)
+            }
+            None => None
+        };
+        self.receiver_reports = Some(ReceiverReports {
+            socket,
+            interval,
+            destination,
+            cname: format!("{:08x}@rtp-transceive", self.ssrc),
+            sent_at: None,
+            last_report: None
+        });
+        Ok(())
+    }
+
+    /// The port `enable_receiver_reports` binds for RTCP, for senders that
+    /// do not use the RTP port + 1 (the default, `None`). Port 0 takes an
+    /// ephemeral one; `rtcp_local_addr` tells which. Takes effect at the
+    /// next `enable_receiver_reports`.
+    pub fn set_rtcp_port(&mut self, port: Option<u16>) {
+        self.rtcp_port = port;
+    }
+
+    /// Where RTCP is received, once `enable_receiver_reports` was called.
+    pub fn rtcp_local_addr(&self) -> Option<SocketAddr> {
+        self.receiver_reports.as_ref().and_then(|reports| reports.socket.local_addr().ok())
+    }
+
+    /// The last Sender Report of the current source, with the mapping from
+    /// its RTP timestamps to the sender's wall clock. Needs
+    /// `enable_receiver_reports`.
+    pub fn last_sender_report(&self) -> Option<SenderReport> {
+        self.reception.last_sender_report()
+    }
+
+    /// The BYE the current source sent to say it left the session, if any.
+    /// Frames still in flight are received as usual; a new source clears
+    /// it. Needs `enable_receiver_reports`.
+    pub fn goodbye(&self) -> Option<&Goodbye> {
+        self.reception.goodbye()
+    }
+
+    /// Every participant of the session whose RTCP arrived, by SSRC: the
+    /// source, and e.g. the other receivers of a multicast group. BYE
+    /// removes one, as does five report intervals without RTCP (at least
+    /// 25 s). Needs `enable_receiver_reports`.
+    pub fn session_members(&self) -> Vec<SessionMember> {
+        self.members.members()
+    }
+
+    /// The joins and leaves since the last call, oldest first; up to the
+    /// latest 1024 are kept.
+    pub fn member_events(&mut self) -> Vec<MemberEvent> {
+        self.members.take_events()
+    }
+
+    /// Caps how many members `session_members` tracks; 256 by default and
+    /// at least 1. Past it, the least recently seen member is dropped.
+    pub fn set_member_limit(&mut self, max_members: usize) {
+        self.members.set_max_members(max_members);
+    }
+
+    pub fn disable_receiver_reports(&mut self) {
+        self.receiver_reports = None;
+    }
+
+    /// The report block of the last Receiver Report sent.
+    pub fn last_receiver_report(&self) -> Option<ReceptionReport> {
+        self.receiver_reports.as_ref().and_then(|reports| reports.last_report)
+    }
+
+    /// Asks the sender for a keyframe by itself when loss has corrupted
+    /// the picture, as `policy` judges it from the frames as they are
+    /// reassembled, instead of waiting for the application to notice. Each
+    /// request sends an RTCP PLI if receiver reports are on (see
+    /// `request_keyframe`) and shows up in `keyframe_requests` either way.
+    /// While no keyframe comes it asks again, backing off; a whole IDR
+    /// frame, or the frames coming whole again, ends it. `None`, the
+    /// default, never asks.
+    pub fn set_refresh_policy(&mut self, policy: Option<RefreshPolicy>) {
+        self.assembler.set_refresh(policy.map(refresh::KeyframeRefresh::new));
+    }
+
+    /// The requests `set_refresh_policy` made since the last call, oldest
+    /// first; up to the latest 1024 are kept.
+    pub fn keyframe_requests(&mut self) -> Vec<KeyframeRequest> {
+        self.assembler.refresh_mut().map(refresh::KeyframeRefresh::take_events).unwrap_or_default()
+    }
+
+    /// Sends an RTCP PLI (RFC 4585 section 6.3.1) asking the source for a
+    /// keyframe, from the receiver report socket to where the reports go.
+    /// Returns whether it was sent: it needs `enable_receiver_reports`, a
+    /// destination, and a source to name.
+    pub fn request_keyframe(&mut self) -> bool {
+        let (reports, media_ssrc) = match (self.receiver_reports.as_ref(), self.reception.source_ssrc()) {
+            (Some(reports), Some(media_ssrc)) => (reports, media_ssrc),
+            _ => return false
+        };
+        let destination = match reports.destination {
+            Some(destination) => destination,
+            None => return false
+        };
+        let packet = rtcp::picture_loss(self.ssrc, media_ssrc, &reports.cname);
+        reports.socket.send_to(&packet, destination).is_ok()
+    }
+
+    // Sends the keyframe request the refresh policy finds due, if any.
+    fn poll_refresh(&mut self) {
+        let now = self.clock.now();
+        let reason = match self.assembler.refresh_mut().and_then(|refresh| refresh.poll(now)) {
+            Some(reason) => reason,
+            None => return
+        };
+        let sent = self.request_keyframe();
+        if let Some(refresh) = self.assembler.refresh_mut() {
+            refresh.record(now, reason, sent);
+        }
+    }
+
+    // Takes in pending Sender Reports and sends a Receiver Report if one is
+    // due. RTCP is best effort: errors are ignored so they never cost a
+    // frame.
+    fn poll_rtcp(&mut self) {
+        let reports = match self.receiver_reports.as_mut() {
+            Some(reports) => reports,
+            None => return
+        };
+
+        let mut buffer = [0u8; 1500];
+        while let Ok((len, from)) = reports.socket.recv_from(&mut buffer) {
+            let compound = &buffer[..len];
+            self.members.on_rtcp(rtcp::memberships(compound), self.clock.now(), self.ssrc);
+            if let Some(relay) = self.relay.as_mut() {
+                relay.on_origin_rtcp(compound, self.clock.now());
+            }
+            if let Some(report) = rtcp::find_sender_report(compound, self.clock.now()) {
+                self.reception.on_sender_report(report);
+                if reports.destination.is_none() {
+                    reports.destination = Some(from);
+                }
+            }
+            if let Some(goodbye) = self.reception.source_ssrc().and_then(|ssrc| rtcp::find_goodbye(compound, ssrc)) {
+                self.reception.on_goodbye(goodbye);
+            }
+        }
+
+        if let (Some(relay), Some(origin)) = (self.relay.as_mut(), reports.destination) {
+            for compound in relay.take_downstream_rtcp() {
+                let _ = reports.socket.send_to(&compound, origin);
+            }
+        }
+
+        let now = self.clock.now();
+        self.members.expire(now, reports.interval);
+        let due = reports.sent_at.is_none_or(|sent_at| now.saturating_duration_since(sent_at) >= reports.interval);
+        let destination = match reports.destination {
+            Some(destination) if due => destination,
+            _ => return
+        };
+        if let Some(report) = self.reception.report(self.ssrc, now) {
+            let mut packet = rtcp::receiver_report(self.ssrc, &report, &reports.cname);
+            if self.xr_reports {
+                let summary = metrics_summary(&self.reception, &self.jitter, &self.assembler, now);
+                packet.extend(rtcp::voip_metrics(self.ssrc, &summary));
+            }
+            let _ = reports.socket.send_to(&packet, destination);
+            reports.sent_at = Some(now);
+            reports.last_report = Some(report);
+        }
+    }
+
+    /// Also receives the stream on `local`, for senders that send every
+    /// packet over two network paths (see
+    /// `H264RtpPusher::set_redundant_destination`). The two feeds are
+    /// merged by sequence number: the first copy of each packet is used,
+    /// and when one is missing, later packets are held for up to
+    /// `alignment_window` in case it arrives over the other path. The
+    /// window should cover the difference in delay between the paths.
+    ///
+    /// Only available on unix.
+    pub fn add_redundant_path(&mut self, local: &str, alignment_window: Duration) -> Result<(), RtpError> {
+        if !cfg!(unix) {
+            return Err(RtpError::UnsupportedOnPlatform("redundant paths"));
+        }
+        let socket = UdpSocket::bind(local).map_err(RtpError::Bind)?;
+        socket.set_nonblocking(true)?;
+        self.redundancy = Some(Redundancy {
+            socket,
+            merger: merge::PathMerger::new(alignment_window, 2)
+        });
+        Ok(())
+    }
+
+    /// Checks every frame against the CRC32 a pusher sends with
+    /// `H264RtpPusher::set_frame_integrity` under the same `extension_id`,
+    /// for catching corruption anywhere between encoder and decoder. The
+    /// result comes with `recv_annotated_frame`, and mismatches are counted
+    /// in `stats`. Fails with `InvalidExtensionId` unless the id is in
+    /// 1..=14. `None` (the default) stops checking.
+    pub fn set_frame_integrity(&mut self, extension_id: Option<u8>) -> Result<(), RtpError> {
+        if let Some(id) = extension_id {
+            extension::check_id(id, std::iter::empty())?;
+        }
+        self.assembler.set_integrity_id(extension_id);
+        Ok(())
+    }
+
+    /// Per-path counters, the primary socket first; empty without a
+    /// redundant path.
+    pub fn path_stats(&self) -> Vec<PathStats> {
+        self.redundancy.as_ref().map_or_else(Vec::new, |redundancy| redundancy.merger.path_stats())
+    }
+
+    /// Accept the header quirks of some legacy encoders instead of dropping
+    /// their packets, off by default:
+    ///
+    /// - version 0 or 1, before the first version 2 packet;
+    /// - a header extension of 0 words;
+    /// - the padding bit with a padding count of 0, taken as one byte.
+    ///
+    /// Accepted packets are rewritten as standard ones and counted in
+    /// `header_stats`; packets that are malformed otherwise stay dropped.
+    pub fn set_lenient_headers(&mut self, lenient: bool) {
+        self.header_check.set_lenient(lenient);
+    }
+
+    /// Datagrams dropped as malformed RTP, and quirky packets dropped or,
+    /// with `set_lenient_headers`, accepted.
+    pub fn header_stats(&self) -> HeaderStats {
+        self.header_check.stats()
+    }
+
+    /// Packets dropped for breaking the H.264 payload format's structure,
+    /// e.g. aggregates nested in aggregates.
+    pub fn payload_errors(&self) -> PayloadErrors {
+        self.assembler.payload_errors()
+    }
+
+    /// GOP structure and loss per frame type of the frames delivered so far.
+    pub fn frame_stats(&self) -> ReceivedFrameStats {
+        self.assembler.frame_stats()
+    }
+
+    /// Caps the bytes a frame may reach, header extensions included, at 8
+    /// MiB by default. A frame growing past it, from a sender that neither
+    /// sets the marker nor moves the timestamp, is delivered incomplete
+    /// and counted in `frame_stats().evicted_frames`; its remaining
+    /// packets start a new frame.
+    pub fn set_max_frame_size(&mut self, bytes: usize) {
+        self.assembler.set_max_frame_size(bytes);
+    }
+
+    /// How far beyond the nominal frame interval a step in RTP timestamps
+    /// may go, in percent, before the frames that fit in it count as
+    /// missing. The interval is the most common recent step; the default of
+    /// 50 suits constant frame rate, variable frame rate needs 100 or more.
+    pub fn set_gap_tolerance(&mut self, percent: u32) {
+        self.assembler.set_gap_tolerance_percent(percent);
+    }
+
+    /// Loss and discards, split into bursts and gaps as RTCP XR reports
+    /// them (see `set_xr_reports`). Bursts and gaps are counted since the
+    /// receiver was created, loss and discards as in `stats`.
+    pub fn metrics_summary(&self) -> MetricsSummary {
+        metrics_summary(&self.reception, &self.jitter, &self.assembler, self.clock.now())
+    }
+
+    /// Sets Gmin, the number of packets in a row that must arrive for a
+    /// burst of loss to end (RFC 3611 section 4.7.2); 16 by default, as
+    /// the RFC recommends, and at least 1.
+    pub fn set_burst_gap_threshold(&mut self, gmin: u8) {
+        self.assembler.set_burst_gmin(gmin);
+    }
+
+    /// Appends an RTCP XR packet with a VoIP Metrics block (RFC 3611
+    /// section 4.7) to every Receiver Report, with the figures of
+    /// `metrics_summary`. Fields about voice quality and delay are sent as
+    /// unavailable. Off by default; Receiver Reports must be enabled too.
+    pub fn set_xr_reports(&mut self, enabled: bool) {
+        self.xr_reports = enabled;
+    }
+
+    /// Loss, reordering, duplicates, jitter and bitrate of the current
+    /// source, as packets arrived. Receiver Reports carry the same figures.
+    pub fn stats(&self) -> ReceiverStats {
+        let mut stats = self.reception.stats(self.clock.now());
+        if let Some(jitter) = self.jitter.as_ref() {
+            stats.reordered = jitter.reordered();
+            stats.late = jitter.late();
+            stats.jitter_buffer_delay = jitter.delay();
+        }
+        #[cfg(feature = "srtp")]
+        {
+            stats.srtp_auth_failures = self.srtp_auth_failures;
+        }
+        stats.crc_mismatches = self.assembler.crc_mismatches();
+        stats.end_to_end_latency = self.smoothed_latency;
+        stats
+    }
+
+    /// Iterates over complete access units. The iterator ends after the idle
+    /// timeout elapses without traffic, or after yielding any other error.
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames {
+            receiver: self,
+            done: false
+        }
+    }
+
+    /// Blocks until a complete access unit is available. A frame is complete
+    /// at its marker packet, or when a packet of the next frame shows up.
+    /// Datagrams that are not RTP are skipped.
+    pub fn recv_frame(&mut self) -> Result<Vec<u8>, RtpError> {
+        self.recv_frame_with_extensions().map(|(frame, _)| frame)
+    }
+
+    /// Like `recv_frame`, returning `RtpError::Cancelled` within 100 ms once
+    /// `cancel` is cancelled from another thread. The idle timeout still
+    /// applies, counted from the last datagram. Packets of a frame still
+    /// being received are kept, so the receiver can carry on with the next
+    /// call.
+    pub fn recv_frame_cancellable(&mut self, cancel: &CancelToken) -> Result<Vec<u8>, RtpError> {
+        let result = self.poll_frame(cancel);
+        self.set_read_timeout(self.idle_timeout)?;
+        result.map(|frame| frame.data)
+    }
+
+    // recv_annotated_frame in slices of at most CANCEL_POLL_INTERVAL.
+    fn poll_frame(&mut self, cancel: &CancelToken) -> Result<Frame, RtpError> {
+        let mut idle_since = Instant::now();
+        let mut received = self.reception.stats(idle_since).packets_received;
+        loop {
+            cancel.check()?;
+            let wait = match self.idle_timeout {
+                Some(timeout) => {
+                    let left = (idle_since + timeout).saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(RtpError::IdleTimeout);
+                    }
+                    left.min(CANCEL_POLL_INTERVAL)
+                }
+                None => CANCEL_POLL_INTERVAL
+            };
+            self.set_read_timeout(Some(wait))?;
+            match self.recv_annotated_frame() {
+                Err(RtpError::IdleTimeout) if !self.source_ended => {
+                    let now = Instant::now();
+                    let packets = self.reception.stats(now).packets_received;
+                    if packets != received {
+                        (idle_since, received) = (now, packets);
+                    }
+                }
+                result => return result
+            }
+        }
+    }
+
+    /// Like `recv_frame`, but also returns the one-byte header extensions
+    /// (RFC 8285, see the `extension` module) of the frame's packets, for
+    /// those that carried any, in the order they were reassembled. The
+    /// pusher's frame CRC shows up here too. Two-byte extensions are
+    /// skipped.
+    pub fn recv_frame_with_extensions(&mut self) -> Result<(Vec<u8>, Vec<PacketExtensions>), RtpError> {
+        self.recv_annotated_frame().map(|frame| (frame.data, frame.extensions))
+    }
+
+    /// Like `recv_annotated_frame`, lending the frame to `f` instead of
+    /// handing it over, and returning what `f` returns. A frame that came
+    /// as a single NAL in one packet, the usual case for all but large
+    /// frames, is lent from the buffer the packet was read into, with its
+    /// start code written over the end of the RTP header: its payload is
+    /// not copied at all. Other frames are joined from their packets into
+    /// a buffer that is then reused for later frames, so a receiver kept
+    /// busy this way stops allocating per frame once that buffer fits the
+    /// largest frame. Packets that are decrypted, transformed, repaired by
+    /// the lenient header check, held by the jitter buffer or merged from
+    /// a redundant path are copied whatever their frames.
+    pub fn recv_frame_with<T>(&mut self, f: impl FnOnce(FrameRef<'_>) -> T) -> Result<T, RtpError> {
+        match self.receive(true)? {
+            Received::Frame(frame) => {
+                let result = f(FrameRef {
+                    data: &frame.data,
+                    timestamp: frame.timestamp,
+                    extensions: &frame.extensions,
+                    integrity: frame.integrity,
+                    preceded_by_gap: frame.preceded_by_gap
+                });
+                self.assembler.recycle(frame.data);
+                Ok(result)
+            }
+            Received::Lent(frame, nal) => Ok(f(FrameRef {
+                data: self.pool.lend_last(nal),
+                timestamp: frame.timestamp,
+                extensions: &frame.extensions,
+                integrity: frame.integrity,
+                preceded_by_gap: frame.preceded_by_gap
+            }))
+        }
+    }
+
+    /// Like `recv_frame`, with the frame's RTP timestamp, header extensions
+    /// and CRC check result.
+    pub fn recv_annotated_frame(&mut self) -> Result<Frame, RtpError> {
+        match self.receive(false)? {
+            Received::Frame(frame) => Ok(frame),
+            // Only frames lent to recv_frame_with stay in the pool.
+            Received::Lent(..) => unreachable!()
+        }
+    }
+
+    // The next frame, which with `lend` may be left in the receive pool.
+    fn receive(&mut self, lend: bool) -> Result<Received, RtpError> {
+        loop {
+            self.poll_rtcp();
+            if let Some(jitter) = self.jitter.as_mut() {
+                while let Some((datagram, arrival)) = jitter.pop(self.now) {
+                    if let Some(packet) = RtpPacket::parse(&datagram) {
+                        self.assembler.push(&packet, arrival);
+                    }
+                }
+            }
+            self.poll_refresh();
+            if let Some(mut frame) = self.assembler.pop_frame() {
+                self.capture.flush();
+                self.trace.flush();
+                frame.latency = self.estimate_latency(frame.timestamp);
+                return Ok(Received::Frame(frame));
+            }
+            // Datagrams of the last read are depacketized straight from the
+            // pool; only a packet transform needs its own copy.
+            if let Some((datagram, arrival)) = self.pool.pop() {
+                if self.capture.is_active() {
+                    self.capture.write(self.capture.peer(), self.capture.local(), datagram);
+                }
+                self.trace.datagram(arrival, datagram);
+                if let Some(relay) = self.relay.as_mut() {
+                    relay.forward(datagram);
+                }
+                #[cfg(feature = "srtp")]
+                let lend = lend && self.srtp.is_none();
+                #[cfg(feature = "srtp")]
+                let datagram = match self.srtp.as_mut() {
+                    None => datagram,
+                    Some(srtp) => match srtp.unprotect(datagram) {
+                        Some(datagram) => datagram,
+                        None => {
+                            self.srtp_auth_failures += 1;
+                            continue;
+                        }
+                    }
+                };
+                match self.packet_transform.as_mut() {
+                    None => {
+                        if let Some(datagram) = self.header_check.screen(datagram) {
+                            let order = check_order(self.reliability, &mut self.last_seq, &datagram)
+                                .and(check_codec(&mut self.sniffer, &datagram));
+                            let lent = if lend && order.is_ok() && matches!(datagram, Cow::Borrowed(_)) {
+                                accept_lendable(
+                                    &mut self.reception,
+                                    &mut self.jitter,
+                                    &mut self.assembler,
+                                    &datagram,
+                                    arrival
+                                )
+                            } else {
+                                let (reception, jitter) = (&mut self.reception, &mut self.jitter);
+                                accept_packet(reception, jitter, &mut self.assembler, &datagram, arrival);
+                                None
+                            };
+                            if let Some((frame, nal)) = lent {
+                                self.capture.flush();
+                                self.trace.flush();
+                                self.estimate_latency(frame.timestamp);
+                                return Ok(Received::Lent(frame, nal));
+                            }
+                            order?;
+                        }
+                    }
+                    Some(transform) => {
+                        let mut datagram = datagram.to_vec();
+                        if !transform(&mut datagram) {
+                            self.transform_drops += 1;
+                        } else if let Some(datagram) = self.header_check.screen(&datagram) {
+                            let order = check_order(self.reliability, &mut self.last_seq, &datagram)
+                                .and(check_codec(&mut self.sniffer, &datagram));
+                            accept_packet(&mut self.reception, &mut self.jitter, &mut self.assembler, &datagram, arrival);
+                            order?;
+                        }
+                    }
+                }
+                continue;
+            }
+            // Redundant paths are UDP and only merge with a UDP stream.
+            if self.redundancy.is_some() && matches!(self.transport, Transport::Udp) {
+                self.receive_redundant()?;
+                continue;
+            }
+
+            // A gap in the jitter buffer may time out before the next
+            // datagram arrives.
+            let deadline = self.jitter.as_ref().and_then(|jitter| jitter.deadline());
+            let idle_timeout = match deadline {
+                Some(_) => self.read_timeout()?,
+                None => None
+            };
+            let until_deadline = deadline
+                .map(|deadline| deadline.saturating_duration_since(self.now).max(jitter::MIN_WAIT))
+                .filter(|&wait| idle_timeout.is_none_or(|idle| wait < idle));
+            if until_deadline.is_some() {
+                self.set_read_timeout(until_deadline)?;
+            }
+            let received = match &mut self.transport {
+                Transport::Udp => self.pool.recv_from(&self.socket, &*self.clock).map(|drops| {
+                    if self.kernel_drops.is_some() && drops.is_some() {
+                        self.kernel_drops = drops;
+                    }
+                }),
+                Transport::Tcp(tcp) => self.pool.recv_with(&*self.clock, |slot| tcp.recv(slot)),
+                Transport::Source { source, timeout } => {
+                    self.pool.recv_with(&*self.clock, |slot| source.recv_packet(slot, *timeout))
+                }
+            };
+            if until_deadline.is_some() {
+                self.set_read_timeout(idle_timeout)?;
+            }
+            self.now = match received {
+                Ok(()) => self.pool.received_at(),
+                Err(_) => self.clock.now()
+            };
+            let ended = matches!(self.transport, Transport::Source { .. })
+                && matches!(&received, Err(err) if err.kind() == io::ErrorKind::UnexpectedEof);
+            self.source_ended |= ended;
+            match received {
+                Ok(()) => {}
+                // Unix reports an elapsed read timeout as WouldBlock.
+                Err(err) if ended || matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    if !ended {
+                        self.trace.timeout(self.now);
+                    }
+                    // An ended source has nothing to wait for.
+                    if until_deadline.is_some() && !ended {
+                        continue;
+                    }
+                    // Nothing more is coming for now: what is held goes
+                    // out before giving up.
+                    match self.jitter.as_mut() {
+                        Some(jitter) if !jitter.is_empty() => jitter.release_all(),
+                        _ => return Err(RtpError::IdleTimeout)
+                    }
+                }
+                Err(err) if !matches!(self.transport, Transport::Udp) => return Err(RtpError::Disconnected(err)),
+                Err(err) => return Err(err.into())
+            }
+        }
+    }
+
+    fn estimate_latency(&mut self, timestamp: u32) -> Option<LatencyEstimate> {
+        let report = self.reception.last_sender_report()?;
+        let estimate = LatencyEstimate::new(report, timestamp, self.clock.wall_clock());
+        if let Some(latency) = estimate.latency() {
+            // S += (L - S) / 16, as for the interarrival jitter.
+            self.smoothed_latency = Some(match self.smoothed_latency {
+                Some(smoothed) => (smoothed * 15 + latency) / 16,
+                None => latency
+            });
+        }
+        Some(estimate)
+    }
+
+    fn deliver(&mut self, datagram: &[u8], arrival: Instant) -> Result<(), RtpError> {
+        let order = check_order(self.reliability, &mut self.last_seq, datagram).and(check_codec(&mut self.sniffer, datagram));
+        accept_packet(&mut self.reception, &mut self.jitter, &mut self.assembler, datagram, arrival);
+        order
+    }
+
+    // Moves packets through the path merger: releases one if it can,
+    // otherwise waits for datagrams on both sockets (or a merge deadline).
+    fn receive_redundant(&mut self) -> Result<(), RtpError> {
+        let redundancy = match self.redundancy.as_mut() {
+            Some(redundancy) => redundancy,
+            None => return Ok(())
+        };
+        if let Some((datagram, arrival)) = redundancy.merger.pop(self.clock.now()) {
+            return self.deliver(&datagram, arrival);
+        }
+
+        // The idle timeout lives on the primary socket, see set_idle_timeout.
+        let idle_timeout = self.socket.read_timeout()?;
+        let until_deadline = redundancy
+            .merger
+            .deadline()
+            .map(|deadline| deadline.saturating_duration_since(self.clock.now()));
+        let timeout = match (idle_timeout, until_deadline) {
+            (Some(idle), Some(deadline)) => Some(idle.min(deadline)),
+            (idle, deadline) => idle.or(deadline)
+        };
+        let ready = platform::wait_readable(&[&self.socket, &redundancy.socket], timeout)?;
+        if ready.is_empty() {
+            return match until_deadline {
+                Some(deadline) if idle_timeout.is_none_or(|idle| deadline < idle) => Ok(()),
+                _ => Err(RtpError::IdleTimeout)
+            };
+        }
+
+        let arrival = self.clock.now();
+        for path in ready {
+            let socket = if path == 0 { &self.socket } else { &redundancy.socket };
+            match self.pool.recv_with(&*self.clock, |slot| socket.recv(slot)) {
+                Ok(()) => {}
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+                Err(err) => return Err(err.into())
+            }
+
+            // The merger keeps datagrams until both paths had their chance.
+            let mut datagram = match self.pool.pop() {
+                Some((datagram, _)) => datagram.to_vec(),
+                None => continue
+            };
+            if self.capture.is_active() {
+                let local = socket.local_addr().unwrap_or(capture::UNKNOWN);
+                self.capture.write(capture::unknown_peer(local), local, &datagram);
+            }
+            if let Some(relay) = self.relay.as_mut() {
+                relay.forward(&datagram);
+            }
+            #[cfg(feature = "srtp")]
+            if let Some(srtp) = self.srtp.as_mut() {
+                match srtp.unprotect(&datagram) {
+                    Some(plain) => datagram = plain.to_vec(),
+                    None => {
+                        self.srtp_auth_failures += 1;
+                        continue;
+                    }
+                }
+            }
+            if let Some(transform) = self.packet_transform.as_mut() {
+                if !transform(&mut datagram) {
+                    self.transform_drops += 1;
+                    continue;
+                }
+            }
+            // Screened before the merger, which needs the sequence number.
+            let fixed = match self.header_check.screen(&datagram) {
+                Some(Cow::Owned(fixed)) => Some(fixed),
+                Some(Cow::Borrowed(_)) => None,
+                None => continue
+            };
+            if let Some(fixed) = fixed {
+                datagram = fixed;
+            }
+            if let Some(seq) = RtpPacket::parse(&datagram).map(|packet| packet.seq) {
+                redundancy.merger.push(path, seq, datagram, arrival);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn metrics_summary(
+    reception: &reception::ReceptionStatistics,
+    jitter: &Option<jitter::JitterBuffer>,
+    assembler: &assembler::FrameAssembler,
+    now: Instant
+) -> MetricsSummary {
+    let mut summary = assembler.burst_gap();
+    summary.source_ssrc = reception.source_ssrc();
+    summary.packets_lost = reception.stats(now).packets_lost;
+    if let Some(jitter) = jitter.as_ref() {
+        summary.packets_discarded = jitter.late();
+        summary.jitter_buffer_delay = jitter.delay();
+    }
+    summary
+}
+
+// Under Reliability::Lossless, fails for a packet that does not follow the
+// last one from its SSRC. Either way it becomes the last one.
+fn check_order(reliability: Reliability, last_seq: &mut Option<(u32, u16)>, datagram: &[u8]) -> Result<(), RtpError> {
+    if reliability != Reliability::Lossless {
+        return Ok(());
+    }
+    let packet = match RtpPacket::parse(datagram) {
+        Some(packet) => packet,
+        None => return Ok(())
+    };
+    match last_seq.replace((packet.ssrc, packet.seq)) {
+        Some((ssrc, seq)) if ssrc == packet.ssrc && packet.seq != seq.wrapping_add(1) => {
+            Err(RtpError::SequenceGap(seq.wrapping_add(1), packet.seq))
+        }
+        _ => Ok(())
+    }
+}
+
+// Of the addresses a name resolves to, prefers those a socket bound to
+// `local` can send to.
+fn same_family(local: SocketAddr) -> AddressPreference {
+    if local.is_ipv4() { AddressPreference::V4First } else { AddressPreference::V6First }
+}
+
+fn no_relay() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "no relay: call set_relay first")
+}
+
+// Fails once the sniffer has seen enough packets to find them H.265.
+fn check_codec(sniffer: &mut Option<sniff::CodecSniffer>, datagram: &[u8]) -> Result<(), RtpError> {
+    let payload = match (sniffer.as_mut(), RtpPacket::parse(datagram)) {
+        (Some(_), Some(packet)) => packet.payload,
+        _ => return Ok(())
+    };
+    match sniffer.as_mut().and_then(|sniffer| sniffer.on_payload(payload)) {
+        Some(Codec::H265) => Err(RtpError::CodecMismatch(Codec::H264, Codec::H265)),
+        _ => Ok(())
+    }
+}
+
+// Reception statistics see each packet as it arrives; the assembler gets
+// it through the jitter buffer, if there is one, unless it is a duplicate.
+fn accept_packet(
+    reception: &mut reception::ReceptionStatistics,
+    jitter: &mut Option<jitter::JitterBuffer>,
+    assembler: &mut assembler::FrameAssembler,
+    datagram: &[u8],
+    arrival: Instant
+) {
+    let packet = match RtpPacket::parse(datagram) {
+        Some(packet) => packet,
+        None => return
+    };
+    if !reception.on_packet(&packet, datagram.len(), arrival) {
+        return;
+    }
+    match jitter.as_mut().map(|jitter| jitter.push(packet.ssrc, packet.seq, packet.ts, datagram, arrival)) {
+        None | Some(jitter::Push::InOrder) => assembler.push(&packet, arrival),
+        Some(jitter::Push::Taken) => {}
+    }
+}
+
+// Like accept_packet, except that a packet the assembler can deliver as a
+// frame of its own is not copied into it: that frame comes back, with
+// where its NAL is in `datagram`.
+fn accept_lendable(
+    reception: &mut reception::ReceptionStatistics,
+    jitter: &mut Option<jitter::JitterBuffer>,
+    assembler: &mut assembler::FrameAssembler,
+    datagram: &[u8],
+    arrival: Instant
+) -> Option<(assembler::LentFrame, Range<usize>)> {
+    let packet = RtpPacket::parse(datagram)?;
+    if !reception.on_packet(&packet, datagram.len(), arrival) {
+        return None;
+    }
+    match jitter.as_mut().map(|jitter| jitter.push(packet.ssrc, packet.seq, packet.ts, datagram, arrival)) {
+        None | Some(jitter::Push::InOrder) if assembler.can_lend(&packet) => {
+            let nal = packet.payload_offset..packet.payload_offset + packet.payload.len();
+            Some((assembler.push_lent(&packet, arrival), nal))
+        }
+        None | Some(jitter::Push::InOrder) => {
+            assembler.push(&packet, arrival);
+            None
+        }
+        Some(jitter::Push::Taken) => None
+    }
+}
+
+/// Access units from `H264RtpReceiver::frames`.
+pub struct Frames<'a> {
+    receiver: &'a mut H264RtpReceiver,
+    done: bool
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Vec<u8>, RtpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.receiver.recv_frame() {
+            Ok(frame) => Some(Ok(frame)),
+            Err(RtpError::IdleTimeout) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for Frames<'_> {}
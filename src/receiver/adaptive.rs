@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::stats::JitterAdaptation;
+use crate::time::{wire_diff, VIDEO_CLOCK_RATE};
+
+// Transit deltas the percentile is taken over.
+const RECENT_DELTAS: usize = 128;
+const PERCENTILE: usize = 95;
+// The wait covers this many times the RFC 3550 estimate.
+const JITTER_MULTIPLE: f64 = 3.0;
+// The target shrinks only once less than 3/4 of it has been needed for
+// SHRINK_AFTER, and then halfway to what is needed, one step per period,
+// the last step all the way.
+const SHRINK_BELOW: (u32, u32) = (3, 4);
+const SHRINK_AFTER: Duration = Duration::from_secs(2);
+// Adaptations kept for jitter_adaptations; older ones are dropped first.
+const MAX_EVENTS: usize = 1024;
+
+// How long the jitter buffer waits at a gap under JitterDepth::Adaptive,
+// from the delay variation of the packets' transit times: as much as the
+// 95th percentile of recent deltas or three times the RFC 3550 jitter
+// estimate ask, whichever is more, within min..=max. Spikes raise it at
+// once; it comes down slowly.
+pub struct AdaptiveDepth {
+    min: Duration,
+    max: Duration,
+    target: Duration,
+    // Interarrival jitter (RFC 3550 section 6.4.1), in seconds.
+    jitter: f64,
+    // |D| of the latest packets, in seconds, oldest first.
+    deltas: VecDeque<f64>,
+    // RTP timestamp and arrival of the last packet.
+    last: Option<(u32, Instant)>,
+    // Since when the target has been more than needed.
+    calm_since: Option<Instant>,
+    events: VecDeque<JitterAdaptation>
+}
+
+impl AdaptiveDepth {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            target: min,
+            jitter: 0.0,
+            deltas: VecDeque::with_capacity(RECENT_DELTAS),
+            last: None,
+            calm_since: None,
+            events: VecDeque::new()
+        }
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    // A new source: its transit times do not compare with the old one's.
+    // The target stays until the new packets say otherwise.
+    pub fn restart(&mut self) {
+        self.last = None;
+    }
+
+    pub fn on_packet(&mut self, timestamp: u32, arrival: Instant) {
+        if let Some((last_timestamp, last_arrival)) = self.last.replace((timestamp, arrival)) {
+            let elapsed = arrival.saturating_duration_since(last_arrival).as_secs_f64();
+            let media = wire_diff(timestamp, last_timestamp) as f64 / VIDEO_CLOCK_RATE as f64;
+            let delta = (elapsed - media).abs();
+            self.jitter += (delta - self.jitter) / 16.0;
+            if self.deltas.len() == RECENT_DELTAS {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(delta);
+        }
+
+        let needed = self.needed();
+        if needed > self.target {
+            self.change(arrival, needed);
+            self.calm_since = None;
+        } else if needed * SHRINK_BELOW.1 < self.target * SHRINK_BELOW.0 {
+            match self.calm_since {
+                None => self.calm_since = Some(arrival),
+                Some(since) if arrival.saturating_duration_since(since) >= SHRINK_AFTER => {
+                    let mut lower = needed + (self.target - needed) / 2;
+                    if lower * SHRINK_BELOW.0 <= needed * SHRINK_BELOW.1 {
+                        // Close enough that it would stop short of it.
+                        lower = needed;
+                    }
+                    self.change(arrival, lower);
+                    self.calm_since = Some(arrival);
+                }
+                Some(_) => {}
+            }
+        } else {
+            self.calm_since = None;
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<JitterAdaptation> {
+        self.events.drain(..).collect()
+    }
+
+    fn needed(&self) -> Duration {
+        let mut deltas: Vec<f64> = self.deltas.iter().copied().collect();
+        deltas.sort_by(f64::total_cmp);
+        let percentile = match deltas.len() {
+            0 => 0.0,
+            len => deltas[(len - 1) * PERCENTILE / 100]
+        };
+        Duration::from_secs_f64(percentile.max(JITTER_MULTIPLE * self.jitter)).clamp(self.min, self.max)
+    }
+
+    fn change(&mut self, at: Instant, target: Duration) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(JitterAdaptation {
+            at,
+            from: self.target,
+            to: target,
+            jitter: Duration::from_secs_f64(self.jitter)
+        });
+        self.target = target;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 100 fps, 900 ticks apart.
+    const PACKET_INTERVAL: Duration = Duration::from_millis(10);
+    const MIN: Duration = Duration::from_millis(10);
+    const MAX: Duration = Duration::from_millis(200);
+
+    // Feeds `count` packets sent PACKET_INTERVAL apart from `*index` on,
+    // each delayed by `delay(index)` on the way, and returns the target
+    // after each.
+    fn play(depth: &mut AdaptiveDepth, start: Instant, index: &mut u32, count: u32, delay: impl Fn(u32) -> Duration) -> Vec<Duration> {
+        (0..count)
+            .map(|_| {
+                let sent = PACKET_INTERVAL * *index;
+                depth.on_packet(*index * 900, start + sent + delay(*index));
+                *index += 1;
+                depth.target()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_steady_stream_keeps_the_minimum() {
+        let mut depth = AdaptiveDepth::new(MIN, MAX);
+        let targets = play(&mut depth, Instant::now(), &mut 0, 1_000, |_| Duration::from_millis(30));
+        assert!(targets.iter().all(|&target| target == MIN));
+        assert!(depth.take_events().is_empty());
+    }
+
+    #[test]
+    fn bursts_raise_it_at_once_within_the_bounds() {
+        let mut depth = AdaptiveDepth::new(MIN, MAX);
+        let start = Instant::now();
+        let mut index = 0;
+        play(&mut depth, start, &mut index, 100, |_| Duration::ZERO);
+        // Every tenth packet is held up 60 ms on the way.
+        let spiky = |index: u32| if index.is_multiple_of(10) { Duration::from_millis(60) } else { Duration::ZERO };
+        let targets = play(&mut depth, start, &mut index, 300, spiky);
+        assert!(targets[0] > MIN, "{:?}", targets[0]);
+        assert!(targets.iter().all(|&target| target <= MAX));
+        // Enough to wait out a held-up packet, as 3x the jitter estimate.
+        let settled = *targets.last().unwrap();
+        assert!(settled >= Duration::from_millis(40) && settled <= Duration::from_millis(80), "{:?}", settled);
+        // A spike a thousand times the bound stops at the bound.
+        play(&mut depth, start, &mut index, 1, |_| Duration::from_secs(200));
+        assert_eq!(depth.target(), MAX);
+    }
+
+    #[test]
+    fn a_step_down_in_jitter_lowers_it_slowly_and_without_oscillating() {
+        let mut depth = AdaptiveDepth::new(MIN, MAX);
+        let start = Instant::now();
+        let mut index = 0;
+        let jittery = |index: u32| Duration::from_millis([0, 40][index as usize % 2]);
+        let high = *play(&mut depth, start, &mut index, 500, jittery).last().unwrap();
+        assert!(high >= Duration::from_millis(60), "{:?}", high);
+        depth.take_events();
+
+        // The network calms down for 20 s.
+        let targets = play(&mut depth, start, &mut index, 2_000, |_| Duration::from_millis(40));
+        // Nothing changes for the first 2 s, then it steps down, never up.
+        assert!(targets[..200].iter().all(|&target| target == high));
+        assert!(targets.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(*targets.last().unwrap(), MIN);
+        let events = depth.take_events();
+        assert!(events.len() >= 3 && events.iter().all(|event| event.to < event.from), "{:?}", events);
+        // One step per two seconds at most.
+        assert!(events.windows(2).all(|pair| pair[1].at - pair[0].at >= SHRINK_AFTER));
+    }
+}
@@ -0,0 +1,717 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use super::burst_gap::BurstGap;
+use super::continuity::FrameContinuity;
+use super::doctor::StreamDoctor;
+use super::refresh::KeyframeRefresh;
+use super::snapshot::KeyframeSnapshots;
+use super::{Frame, IntegrityResult, KeyframeCallback, KeyframeSnapshot};
+use crate::extension::{self, PacketExtensions};
+use crate::integrity;
+use crate::rtp::RtpPacket;
+use crate::stats::{FrameAccounting, MetricsSummary, PayloadErrors, ReceivedFrameStats};
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+const NAL_TYPE_IDR: u8 = 5;
+const NAL_TYPE_STAP_A: u8 = 24;
+const NAL_TYPE_FU_A: u8 = 28;
+const FU_START: u8 = 1 << 7;
+const FU_END: u8 = 1 << 6;
+// Single NAL unit types (RFC 6184 section 5.2); the only ones that may be
+// aggregated or fragmented.
+const SINGLE_NAL_TYPES: std::ops::RangeInclusive<u8> = 1..=23;
+// Units one STAP-A packet may carry; more is not a real encoder's output.
+const MAX_AGGREGATION_UNITS: usize = 64;
+// Bytes a frame may grow to, header extensions included, unless
+// set_max_frame_size says otherwise. Four times a large 4K IDR frame.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 << 20;
+
+// Why a packet's payload was refused. The packet adds nothing to its frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PayloadError {
+    // A STAP-A unit that is not a single NAL, e.g. another aggregate or a
+    // fragment.
+    InvalidAggregatedNal,
+    // A STAP-A unit of length 0 or longer than the rest of the packet.
+    MalformedAggregationUnit,
+    TooManyAggregationUnits,
+    // A FU-A header naming an aggregate or fragment as the NAL type.
+    InvalidFragmentedNal,
+    // Types 0, 25-27 and 29-31, or a FU-A without a FU header.
+    UnsupportedPacket
+}
+
+// Turns RTP packets into Annex B access units. A frame ends at a packet with
+// the marker bit or, if that packet was lost, when the timestamp changes.
+// Only one frame is open at a time, so a sender changing the timestamp on
+// every packet gets one-packet frames rather than a pile of open ones; a
+// sender never changing it nor setting the marker runs into the size cap.
+#[derive(Default)]
+pub struct FrameAssembler {
+    expected_seq: Option<u16>,
+    frame: Vec<u8>,
+    frame_ts: Option<u32>,
+    // Where the NAL being rebuilt from FU-A fragments starts in `frame`,
+    // start code included. Fragments are appended to the frame as they come
+    // and cut off again if one goes missing.
+    fragmented_nal: Option<usize>,
+    // Where each NAL of `frame` starts, start code included.
+    nal_starts: Vec<usize>,
+    // One-byte header extensions of the frame's packets that had any.
+    extensions: Vec<PacketExtensions>,
+    // What `extensions` holds, counted against the frame size cap.
+    extension_bytes: usize,
+    // None for DEFAULT_MAX_FRAME_SIZE.
+    max_frame_size: Option<usize>,
+    evicted_frames: u64,
+    ready: VecDeque<Frame>,
+    // A delivered frame's buffer, handed back to hold a later frame.
+    spare: Option<Vec<u8>>,
+
+    // Header extension id of the pusher's frame CRC, and the CRC the
+    // current frame carried.
+    integrity_id: Option<u8>,
+    frame_crc: Option<u32>,
+    crc_mismatches: u64,
+
+    frame_has_idr: bool,
+    // A packet was lost while this frame was being received.
+    frame_incomplete: bool,
+    accounting: FrameAccounting,
+    continuity: FrameContinuity,
+    burst_gap: BurstGap,
+    payload_errors: PayloadErrors,
+    doctor: Option<StreamDoctor>,
+    refresh: Option<KeyframeRefresh>,
+    snapshots: Option<KeyframeSnapshots>,
+    snapshot_callback: Option<KeyframeCallback>
+}
+
+// A frame of one packet holding one NAL, left in the packet instead of
+// copied into a frame (see FrameAssembler::push_lent).
+pub struct LentFrame {
+    pub timestamp: u32,
+    pub extensions: Vec<PacketExtensions>,
+    pub integrity: IntegrityResult,
+    pub preceded_by_gap: bool
+}
+
+impl FrameAssembler {
+    pub fn push(&mut self, packet: &RtpPacket, arrival: Instant) {
+        if !self.track(packet, arrival) {
+            return;
+        }
+
+        // Keepalives and probes carry no payload.
+        if let Some(&header) = packet.payload.first() {
+            let pushed = match header & 0x1F {
+                NAL_TYPE_FU_A => self.push_fragment(packet.payload),
+                NAL_TYPE_STAP_A => self.push_aggregate(&packet.payload[1..]),
+                nal_type if SINGLE_NAL_TYPES.contains(&nal_type) => {
+                    self.push_nal(packet.payload);
+                    Ok(())
+                }
+                _ => Err(PayloadError::UnsupportedPacket)
+            };
+            if let Err(err) = pushed {
+                // Like a lost packet: a NAL being reassembled lacks it.
+                self.record_payload_error(err);
+                self.abandon_fragment();
+                self.frame_incomplete = true;
+            }
+        }
+
+        if packet.marker {
+            self.finish_frame(arrival);
+        } else if self.frame.len() + self.extension_bytes > self.max_frame_size.unwrap_or(DEFAULT_MAX_FRAME_SIZE) {
+            // Out it goes as it is; the rest of it starts another frame.
+            self.evicted_frames += 1;
+            self.abandon_fragment();
+            self.frame_incomplete = true;
+            self.finish_frame(arrival);
+        }
+    }
+
+    // Whether `packet` is a frame of its own made of one NAL, with nothing
+    // before it left to deliver, so push_lent can take it.
+    pub fn can_lend(&self, packet: &RtpPacket) -> bool {
+        let in_order = self.expected_seq.is_none_or(|expected| packet.seq.wrapping_sub(expected) as i16 >= 0);
+        packet.marker
+            && in_order
+            && self.frame_ts.is_none()
+            && self.ready.is_empty()
+            && packet.payload.first().is_some_and(|&header| SINGLE_NAL_TYPES.contains(&(header & 0x1F)))
+    }
+
+    // Like push for a packet can_lend accepted, except that its NAL is not
+    // copied: the caller hands the frame on from the packet itself.
+    pub fn push_lent(&mut self, packet: &RtpPacket, arrival: Instant) -> LentFrame {
+        self.track(packet, arrival);
+        let nal = packet.payload;
+        self.frame_ts = None;
+        self.frame_has_idr = nal[0] & 0x1F == NAL_TYPE_IDR;
+        let frame_len = START_CODE.len() + nal.len();
+        let (integrity, preceded_by_gap) = self.close_frame(std::iter::once(nal), frame_len, packet.ts, arrival);
+        let extensions = std::mem::take(&mut self.extensions);
+        self.clear_frame();
+        LentFrame {
+            timestamp: packet.ts,
+            extensions,
+            integrity,
+            preceded_by_gap
+        }
+    }
+
+    // Sequence, loss and header extension bookkeeping for a packet, which
+    // also ends the open frame if the packet belongs to another one. False
+    // for a duplicate or late packet, which is to be ignored.
+    fn track(&mut self, packet: &RtpPacket, arrival: Instant) -> bool {
+        let mut lost = false;
+        let mut missing = 0;
+        if let Some(expected) = self.expected_seq {
+            let distance = packet.seq.wrapping_sub(expected) as i16;
+            if distance < 0 {
+                // Duplicate or too late: its frame may already be out.
+                return false;
+            }
+            missing = distance as u64;
+            if distance > 0 {
+                // A packet was lost; a NAL missing a fragment is worse than
+                // no NAL, so drop the partial one.
+                self.abandon_fragment();
+                lost = true;
+            }
+        }
+        self.expected_seq = Some(packet.seq.wrapping_add(1));
+        self.burst_gap.on_packet(missing, arrival);
+        if let Some(doctor) = self.doctor.as_mut() {
+            doctor.on_packet(packet, lost, arrival);
+        }
+
+        if self.frame_ts.is_some_and(|ts| ts != packet.ts) {
+            // Without the marker there is no telling whether the lost
+            // packets ended the old frame or started the new one: both count
+            // as incomplete.
+            if lost || self.abandon_fragment() {
+                self.frame_incomplete = true;
+            }
+            self.finish_frame(arrival);
+        }
+        self.frame_ts = Some(packet.ts);
+        self.frame_incomplete |= lost;
+
+        // Padding-only blocks (MTU probes) have nothing to hand on.
+        if let Some(elements) = packet.extension.and_then(extension::parse_block) {
+            if !elements.is_empty() {
+                if let Some(crc) = self.integrity_id.and_then(|id| find_crc(&elements, id)) {
+                    self.frame_crc = Some(crc);
+                }
+                self.extension_bytes += elements.iter().map(|(_, data)| 1 + data.len()).sum::<usize>();
+                self.extensions.push(PacketExtensions {
+                    sequence: packet.seq,
+                    timestamp: packet.ts,
+                    marker: packet.marker,
+                    arrival,
+                    elements
+                });
+            }
+        }
+        true
+    }
+
+    pub fn set_doctor(&mut self, doctor: Option<StreamDoctor>) {
+        self.doctor = doctor;
+    }
+
+    pub fn doctor(&self) -> Option<&StreamDoctor> {
+        self.doctor.as_ref()
+    }
+
+    pub fn set_refresh(&mut self, refresh: Option<KeyframeRefresh>) {
+        self.refresh = refresh;
+    }
+
+    pub fn refresh_mut(&mut self) -> Option<&mut KeyframeRefresh> {
+        self.refresh.as_mut()
+    }
+
+    pub fn set_snapshots(&mut self, snapshots: Option<KeyframeSnapshots>) {
+        self.snapshots = snapshots;
+    }
+
+    pub fn set_snapshot_callback(&mut self, callback: Option<KeyframeCallback>) {
+        self.snapshot_callback = callback;
+    }
+
+    pub fn latest_snapshot(&self) -> Option<&KeyframeSnapshot> {
+        self.snapshots.as_ref().and_then(KeyframeSnapshots::latest)
+    }
+
+    pub fn pop_frame(&mut self) -> Option<Frame> {
+        self.ready.pop_front()
+    }
+
+    // Keeps `buffer` for the next frame, so frames lent out with
+    // recv_frame_with cost no allocation once buffers are large enough.
+    pub fn recycle(&mut self, buffer: Vec<u8>) {
+        if self.spare.as_ref().is_none_or(|spare| spare.capacity() < buffer.capacity()) {
+            self.spare = Some(buffer);
+        }
+    }
+
+    pub fn set_integrity_id(&mut self, id: Option<u8>) {
+        self.integrity_id = id;
+    }
+
+    pub fn crc_mismatches(&self) -> u64 {
+        self.crc_mismatches
+    }
+
+    pub fn frame_stats(&self) -> ReceivedFrameStats {
+        let mut stats = self.accounting.snapshot();
+        stats.missing_frames = self.continuity.missing_frames();
+        stats.duplicate_frames = self.continuity.duplicate_frames();
+        stats.nominal_frame_interval = self.continuity.nominal_interval();
+        stats.evicted_frames = self.evicted_frames;
+        stats
+    }
+
+    pub fn burst_gap(&self) -> MetricsSummary {
+        self.burst_gap.summary()
+    }
+
+    pub fn set_burst_gmin(&mut self, gmin: u8) {
+        self.burst_gap.set_gmin(gmin);
+    }
+
+    pub fn set_max_frame_size(&mut self, bytes: usize) {
+        self.max_frame_size = Some(bytes);
+    }
+
+    pub fn set_gap_tolerance_percent(&mut self, percent: u32) {
+        self.continuity.set_tolerance_percent(percent);
+    }
+
+    pub fn payload_errors(&self) -> PayloadErrors {
+        self.payload_errors
+    }
+
+    fn record_payload_error(&mut self, err: PayloadError) {
+        let counter = match err {
+            PayloadError::InvalidAggregatedNal => &mut self.payload_errors.invalid_aggregated_nals,
+            PayloadError::MalformedAggregationUnit => &mut self.payload_errors.malformed_aggregation_units,
+            PayloadError::TooManyAggregationUnits => &mut self.payload_errors.oversized_aggregates,
+            PayloadError::InvalidFragmentedNal => &mut self.payload_errors.invalid_fragmented_nals,
+            PayloadError::UnsupportedPacket => &mut self.payload_errors.unsupported_packets
+        };
+        *counter += 1;
+    }
+
+    fn push_fragment(&mut self, payload: &[u8]) -> Result<(), PayloadError> {
+        let (indicator, fu_header, data) = match payload {
+            [indicator, fu_header, data @ ..] => (*indicator, *fu_header, data),
+            _ => return Err(PayloadError::UnsupportedPacket)
+        };
+        if !SINGLE_NAL_TYPES.contains(&(fu_header & 0x1F)) {
+            return Err(PayloadError::InvalidFragmentedNal);
+        }
+
+        if fu_header & FU_START != 0 {
+            self.abandon_fragment();
+            self.fragmented_nal = Some(self.frame.len());
+            self.nal_starts.push(self.frame.len());
+            self.frame.extend_from_slice(&START_CODE);
+            // F and NRI come from the indicator, the type from the FU header.
+            self.frame.push((indicator & 0xE0) | (fu_header & 0x1F));
+            self.frame.extend_from_slice(data);
+        } else if self.fragmented_nal.is_some() {
+            self.frame.extend_from_slice(data);
+        }
+
+        if fu_header & FU_END != 0 {
+            if let Some(start) = self.fragmented_nal.take() {
+                self.frame_has_idr |= self.frame[start + START_CODE.len()] & 0x1F == NAL_TYPE_IDR;
+            }
+        }
+        Ok(())
+    }
+
+    // Cuts an unfinished FU-A NAL off the frame; true if there was one.
+    fn abandon_fragment(&mut self) -> bool {
+        match self.fragmented_nal.take() {
+            Some(start) => {
+                self.frame.truncate(start);
+                self.nal_starts.pop();
+                true
+            }
+            None => false
+        }
+    }
+
+    // STAP-A units, each preceded by its 16-bit size. The whole packet is
+    // checked before any unit is taken.
+    fn push_aggregate(&mut self, units: &[u8]) -> Result<(), PayloadError> {
+        let mut nals = [&[][..]; MAX_AGGREGATION_UNITS];
+        let mut count = 0;
+        let mut rest = units;
+        while !rest.is_empty() {
+            let (size, after) = match rest {
+                [high, low, after @ ..] => (u16::from_be_bytes([*high, *low]) as usize, after),
+                _ => return Err(PayloadError::MalformedAggregationUnit)
+            };
+            if size == 0 || size > after.len() {
+                return Err(PayloadError::MalformedAggregationUnit);
+            }
+            if !SINGLE_NAL_TYPES.contains(&(after[0] & 0x1F)) {
+                return Err(PayloadError::InvalidAggregatedNal);
+            }
+            if count == MAX_AGGREGATION_UNITS {
+                return Err(PayloadError::TooManyAggregationUnits);
+            }
+            nals[count] = &after[..size];
+            count += 1;
+            rest = &after[size..];
+        }
+        if count == 0 {
+            return Err(PayloadError::MalformedAggregationUnit);
+        }
+        for nal in &nals[..count] {
+            self.push_nal(nal);
+        }
+        Ok(())
+    }
+
+    fn push_nal(&mut self, nal: &[u8]) {
+        // Fragments of a NAL are sent back to back (RFC 6184 section
+        // 5.8); anything in between means its end is not coming.
+        self.abandon_fragment();
+        self.frame_has_idr |= nal[0] & 0x1F == NAL_TYPE_IDR;
+        self.nal_starts.push(self.frame.len());
+        self.frame.extend_from_slice(&START_CODE);
+        self.frame.extend_from_slice(nal);
+    }
+
+    // `arrival` is that of the packet that ended the frame.
+    fn finish_frame(&mut self, arrival: Instant) {
+        let timestamp = self.frame_ts.take();
+        self.abandon_fragment();
+        if let Some(timestamp) = timestamp.filter(|_| !self.frame.is_empty()) {
+            let (frame, nal_starts) = (std::mem::take(&mut self.frame), std::mem::take(&mut self.nal_starts));
+            let nals = nal_starts.iter().enumerate().map(|(index, &start)| {
+                let end = nal_starts.get(index + 1).copied().unwrap_or(frame.len());
+                &frame[start + START_CODE.len()..end]
+            });
+            let (integrity, preceded_by_gap) = self.close_frame(nals, frame.len(), timestamp, arrival);
+            (self.frame, self.nal_starts) = (frame, nal_starts);
+            // The next frame is likely about as large: allocate once for it,
+            // unless a recycled buffer is at hand.
+            let capacity = self.frame.len();
+            let next = match self.spare.take() {
+                Some(mut spare) => {
+                    spare.clear();
+                    spare.reserve(capacity);
+                    spare
+                }
+                None => Vec::with_capacity(capacity)
+            };
+            self.ready.push_back(Frame {
+                data: std::mem::replace(&mut self.frame, next),
+                timestamp,
+                extensions: std::mem::take(&mut self.extensions),
+                integrity,
+                preceded_by_gap,
+                latency: None
+            });
+        }
+        self.frame.clear();
+        self.nal_starts.clear();
+        self.clear_frame();
+    }
+
+    // Hands a delivered frame, as its NAL units without start codes and
+    // its Annex B size, to the frame analyzers. Returns its CRC check
+    // result and whether frames went missing before it.
+    fn close_frame<'n>(
+        &mut self,
+        nals: impl Iterator<Item = &'n [u8]> + Clone,
+        frame_len: usize,
+        timestamp: u32,
+        arrival: Instant,
+    ) -> (IntegrityResult, bool) {
+        self.accounting.record_frame(frame_len, self.frame_has_idr, self.frame_incomplete);
+        if let Some(refresh) = self.refresh.as_mut() {
+            let reference_loss = self.frame_incomplete && !self.frame_has_idr && !is_disposable(nals.clone());
+            refresh.on_frame(arrival, self.frame_has_idr, self.frame_incomplete, reference_loss);
+        }
+        if let Some(snapshots) = self.snapshots.as_mut() {
+            let whole_idr = self.frame_has_idr && !self.frame_incomplete;
+            let snapshot = snapshots.on_frame(nals.clone(), timestamp, arrival, whole_idr);
+            if let (Some(snapshot), Some(callback)) = (snapshot, self.snapshot_callback.as_mut()) {
+                callback(snapshot);
+            }
+        }
+        let preceded_by_gap = self.continuity.on_frame(timestamp);
+        let integrity = match self.frame_crc {
+            None => IntegrityResult::Absent,
+            Some(crc) if crc == frame_crc(nals) => IntegrityResult::Ok,
+            Some(_) => {
+                self.crc_mismatches += 1;
+                IntegrityResult::Mismatch
+            }
+        };
+        (integrity, preceded_by_gap)
+    }
+
+    // Forgets what was gathered about the frame just delivered.
+    fn clear_frame(&mut self) {
+        self.extensions.clear();
+        self.extension_bytes = 0;
+        self.frame_crc = None;
+        self.frame_has_idr = false;
+        self.frame_incomplete = false;
+    }
+}
+
+// CRC32 of a frame's NAL units without their start codes, as the pusher
+// computes it.
+fn frame_crc<'n>(nals: impl Iterator<Item = &'n [u8]>) -> u32 {
+    let mut crc = integrity::Crc32::new();
+    for nal in nals {
+        crc.update(nal);
+    }
+    crc.finish()
+}
+
+fn find_crc(elements: &[(u8, Vec<u8>)], id: u8) -> Option<u32> {
+    elements.iter().find_map(|(element_id, data)| match data[..] {
+        [a, b, c, d] if *element_id == id => Some(u32::from_be_bytes([a, b, c, d])),
+        _ => None
+    })
+}
+
+// Whether no later frame refers to this one: it has slices, all with NRI 0.
+fn is_disposable<'n>(nals: impl Iterator<Item = &'n [u8]>) -> bool {
+    let mut slices = nals.map(|nal| nal[0]).filter(|header| matches!(header & 0x1F, 1..=5)).peekable();
+    slices.peek().is_some() && slices.all(|header| header & 0x60 == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 3] = [0x67, 0x42, 0x1E];
+    const SLICE: [u8; 3] = [0x41, 0x9A, 0x02];
+
+    fn packet(seq: u16, ts: u32, marker: bool, payload: &[u8]) -> RtpPacket<'_> {
+        RtpPacket {
+            marker,
+            seq,
+            ts,
+            ssrc: 1,
+            extension: None,
+            payload,
+            payload_offset: 12
+        }
+    }
+
+    // Feeds one packet per payload, in sequence after the last one and the
+    // last with the marker, and returns the frame if one came out.
+    fn assemble(assembler: &mut FrameAssembler, payloads: &[Vec<u8>]) -> Option<Frame> {
+        let now = Instant::now();
+        let first = assembler.expected_seq.unwrap_or(0);
+        let ts = first as u32 * 3_000;
+        for (index, payload) in payloads.iter().enumerate() {
+            let seq = first.wrapping_add(index as u16);
+            assembler.push(&packet(seq, ts, index + 1 == payloads.len(), payload), now);
+        }
+        assembler.pop_frame()
+    }
+
+    fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter().flat_map(|nal| START_CODE.iter().chain(nal.iter()).copied()).collect()
+    }
+
+    fn stap_a(units: &[&[u8]]) -> Vec<u8> {
+        let mut payload = vec![0x18];
+        for unit in units {
+            payload.extend_from_slice(&(unit.len() as u16).to_be_bytes());
+            payload.extend_from_slice(unit);
+        }
+        payload
+    }
+
+    #[test]
+    fn well_formed_aggregate_and_fragments_pass() {
+        let mut assembler = FrameAssembler::default();
+        let payloads = [stap_a(&[&SPS, &SLICE]), vec![0x5C, 0x81, 1, 2], vec![0x5C, 0x41, 3]];
+        let frame = assemble(&mut assembler, &payloads).unwrap();
+        assert_eq!(frame.data, annex_b(&[&SPS, &SLICE, &[0x41, 1, 2, 3]]));
+        assert_eq!(assembler.payload_errors(), PayloadErrors::default());
+    }
+
+    #[test]
+    fn aggregated_aggregates_and_fragments_are_refused() {
+        let mut assembler = FrameAssembler::default();
+        let nested = stap_a(&[&SLICE]);
+        let frame = assemble(&mut assembler, &[stap_a(&[&SPS, &nested]), SLICE.to_vec()]).unwrap();
+        // Nothing of the refused packet is kept, not even its valid unit.
+        assert_eq!(frame.data, annex_b(&[&SLICE]));
+
+        assemble(&mut assembler, &[stap_a(&[&[0x7C, 0x85, 1]]), SLICE.to_vec()]);
+        assemble(&mut assembler, &[stap_a(&[&[0x00, 1]]), SLICE.to_vec()]);
+        assert_eq!(assembler.payload_errors().invalid_aggregated_nals, 3);
+        assert_eq!(assembler.frame_stats().incomplete_non_idr_frames, 3);
+    }
+
+    #[test]
+    fn zero_length_and_truncated_units_are_refused() {
+        let mut assembler = FrameAssembler::default();
+        // A zero size that would loop forever if taken as progress.
+        let zero = vec![0x18, 0, 0, 0, 0, 0, 0];
+        let truncated = vec![0x18, 0, 9, 0x41, 1];
+        let dangling_size = vec![0x18, 0, 3, 0x41, 1, 2, 0];
+        let empty = vec![0x18];
+        for payload in [zero, truncated, dangling_size, empty] {
+            let frame = assemble(&mut assembler, &[payload, SLICE.to_vec()]).unwrap();
+            assert_eq!(frame.data, annex_b(&[&SLICE]));
+        }
+        assert_eq!(assembler.payload_errors().malformed_aggregation_units, 4);
+    }
+
+    #[test]
+    fn aggregates_are_limited_to_64_units() {
+        let mut assembler = FrameAssembler::default();
+        let units = vec![&SLICE[..]; MAX_AGGREGATION_UNITS];
+        let frame = assemble(&mut assembler, &[stap_a(&units)]).unwrap();
+        assert_eq!(frame.data.len(), MAX_AGGREGATION_UNITS * (4 + SLICE.len()));
+
+        let units = vec![&SLICE[..]; MAX_AGGREGATION_UNITS + 1];
+        assert!(assemble(&mut assembler, &[stap_a(&units)]).is_none());
+        assert_eq!(assembler.payload_errors().oversized_aggregates, 1);
+    }
+
+    #[test]
+    fn fragments_of_aggregates_are_refused() {
+        let mut assembler = FrameAssembler::default();
+        // FU-A start carrying a STAP-A, then a FU-A carrying a FU-A.
+        let payloads = [vec![0x5C, 0x98, 1, 2], vec![0x5C, 0x5C, 3], SLICE.to_vec()];
+        let frame = assemble(&mut assembler, &payloads).unwrap();
+        assert_eq!(frame.data, annex_b(&[&SLICE]));
+        assert_eq!(assembler.payload_errors().invalid_fragmented_nals, 2);
+    }
+
+    #[test]
+    fn refused_packet_cuts_the_nal_being_reassembled() {
+        let mut assembler = FrameAssembler::default();
+        let payloads = [vec![0x5C, 0x81, 1, 2], vec![0x5C, 0x1F, 3], vec![0x5C, 0x41, 4], SLICE.to_vec()];
+        let frame = assemble(&mut assembler, &payloads).unwrap();
+        assert_eq!(frame.data, annex_b(&[&SLICE]));
+        assert_eq!(assembler.payload_errors().invalid_fragmented_nals, 1);
+    }
+
+    #[test]
+    fn unsupported_packet_types_are_counted() {
+        let mut assembler = FrameAssembler::default();
+        // Type 0, STAP-B, MTAP16, MTAP24, FU-B, 30, 31, and a bare FU
+        // indicator.
+        for header in [0x00, 0x19, 0x1A, 0x1B, 0x1D, 0x1E, 0x1F] {
+            assemble(&mut assembler, &[vec![header, 0, 1, 2], SLICE.to_vec()]);
+        }
+        assemble(&mut assembler, &[vec![0x5C], SLICE.to_vec()]);
+        assert_eq!(assembler.payload_errors().unsupported_packets, 8);
+        assert_eq!(assembler.frame_stats().frames, 8);
+    }
+
+    #[test]
+    fn a_timestamp_per_packet_keeps_one_frame_open() {
+        let mut assembler = FrameAssembler::default();
+        let now = Instant::now();
+        let mut frames = 0;
+        for seq in 0..10_000u16 {
+            // No marker ever, and a new timestamp on every packet.
+            assembler.push(&packet(seq, seq as u32 * 90, false, &SLICE), now);
+            while let Some(frame) = assembler.pop_frame() {
+                assert_eq!(frame.data, annex_b(&[&SLICE]));
+                frames += 1;
+            }
+            assert!(assembler.frame.len() <= START_CODE.len() + SLICE.len());
+        }
+        assert_eq!(frames, 9_999);
+        assert_eq!(assembler.frame_stats().evicted_frames, 0);
+    }
+
+    #[test]
+    fn frames_that_never_end_are_evicted_at_the_size_cap() {
+        let mut assembler = FrameAssembler::default();
+        assembler.set_max_frame_size(100);
+        let now = Instant::now();
+        let mut sizes = Vec::new();
+        for seq in 0..100u16 {
+            // One timestamp and no marker: nothing else would end the frame.
+            assembler.push(&packet(seq, 3_000, false, &SLICE), now);
+            sizes.extend(std::iter::from_fn(|| assembler.pop_frame()).map(|frame| frame.data.len()));
+        }
+        // 15 NALs of 7 bytes pass 100 bytes.
+        assert_eq!(sizes, [105; 6]);
+        let stats = assembler.frame_stats();
+        assert_eq!((stats.evicted_frames, stats.incomplete_non_idr_frames), (6, 6));
+    }
+
+    #[test]
+    fn ipbb_timestamps_in_decode_order_assemble_every_frame() {
+        let mut assembler = FrameAssembler::default();
+        assembler.set_max_frame_size(1_000);
+        let idr = [0x65, 0x88, 0x84];
+        let now = Instant::now();
+        let mut seq = 0;
+        let mut delivered = Vec::new();
+        for gop in 0..10u32 {
+            // I0 P3 B1 B2 in decode order, two packets per frame.
+            for (index, (offset, nal)) in [(0, &idr), (3, &SLICE), (1, &SLICE), (2, &SLICE)].into_iter().enumerate() {
+                let first_nal = if index == 0 { &SPS[..] } else { &nal[..] };
+                let ts = (gop * 4 + offset) * 3_000;
+                assembler.push(&packet(seq, ts, false, first_nal), now);
+                assembler.push(&packet(seq + 1, ts, true, nal), now);
+                seq += 2;
+                delivered.extend(assembler.pop_frame().map(|frame| frame.timestamp));
+            }
+        }
+        let expected: Vec<u32> = (0..10).flat_map(|gop| [0, 3, 1, 2].map(|offset| (gop * 4 + offset) * 3_000)).collect();
+        assert_eq!(delivered, expected);
+        let stats = assembler.frame_stats();
+        assert_eq!((stats.frames, stats.idr_frames, stats.evicted_frames), (40, 10, 0));
+        assert_eq!(stats.incomplete_idr_frames + stats.incomplete_non_idr_frames, 0);
+    }
+
+    #[test]
+    fn frames_of_one_single_nal_packet_are_lent_uncopied() {
+        let mut assembler = FrameAssembler::default();
+        let now = Instant::now();
+        assert!(!assembler.can_lend(&packet(0, 0, false, &SPS)));
+        assembler.push(&packet(0, 0, false, &SPS), now);
+        // Part of the open frame.
+        assert!(!assembler.can_lend(&packet(1, 0, true, &SLICE)));
+        assembler.push(&packet(1, 0, true, &SLICE), now);
+        // Not ahead of a frame waiting to be delivered.
+        assert!(!assembler.can_lend(&packet(2, 3_000, true, &SLICE)));
+        assert_eq!(assembler.pop_frame().unwrap().data, annex_b(&[&SPS, &SLICE]));
+
+        let single = packet(2, 3_000, true, &SLICE);
+        assert!(assembler.can_lend(&single));
+        let lent = assembler.push_lent(&single, now);
+        assert_eq!((lent.timestamp, lent.integrity), (3_000, IntegrityResult::Absent));
+        assert!(assembler.frame.is_empty() && assembler.pop_frame().is_none());
+
+        // Duplicates, aggregates and fragments are not.
+        assert!(!assembler.can_lend(&packet(2, 3_000, true, &SLICE)));
+        assert!(!assembler.can_lend(&packet(3, 6_000, true, &stap_a(&[&SPS, &SLICE]))));
+        assert!(!assembler.can_lend(&packet(3, 6_000, true, &[0x7C, 0x41, 0x9A])));
+        // A packet lost before one still counts.
+        let late = packet(5, 9_000, true, &SLICE);
+        assert!(assembler.can_lend(&late));
+        assembler.push_lent(&late, now);
+        let stats = assembler.frame_stats();
+        assert_eq!((stats.frames, stats.incomplete_non_idr_frames), (3, 1));
+    }
+}
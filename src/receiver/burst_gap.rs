@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use crate::stats::MetricsSummary;
+
+// Gmin of RFC 3611 section 4.7.2, the value it recommends.
+pub const DEFAULT_GMIN: u8 = 16;
+
+// Splits the stream into bursts and gaps (RFC 3611 section 4.7.2) by the
+// packets that made it to reassembly and those that did not, lost or
+// discarded. A burst runs from a missing packet to the last missing one
+// before Gmin packets in a row arrive; the rest is gap. A single missing
+// packet with Gmin received ones on both sides is a gap loss.
+pub struct BurstGap {
+    gmin: u8,
+    // Packets received since the last missing one. The stream starts as
+    // though a whole gap came before it.
+    received_run: u64,
+    burst: Option<Burst>,
+    first_arrival: Option<Instant>,
+    last_arrival: Option<Instant>,
+    packets: u64,
+    missing: u64,
+    // Bursts that ended.
+    bursts: u64,
+    burst_packets: u64,
+    burst_losses: u64,
+    burst_time: Duration
+}
+
+#[derive(Clone, Copy)]
+struct Burst {
+    packets: u64,
+    losses: u64,
+    // Arrival of the packet before the first missing one, and of the first
+    // one after the last missing one.
+    started: Option<Instant>,
+    ended: Option<Instant>
+}
+
+impl Default for BurstGap {
+    fn default() -> Self {
+        Self {
+            gmin: DEFAULT_GMIN,
+            received_run: DEFAULT_GMIN as u64,
+            burst: None,
+            first_arrival: None,
+            last_arrival: None,
+            packets: 0,
+            missing: 0,
+            bursts: 0,
+            burst_packets: 0,
+            burst_losses: 0,
+            burst_time: Duration::ZERO
+        }
+    }
+}
+
+impl BurstGap {
+    pub fn set_gmin(&mut self, gmin: u8) {
+        self.gmin = gmin.max(1);
+    }
+
+    // A packet reached reassembly `missing` sequence numbers after the one
+    // expected.
+    pub fn on_packet(&mut self, missing: u64, arrival: Instant) {
+        if missing > 0 {
+            self.packets += missing;
+            self.missing += missing;
+            match self.burst.as_mut() {
+                Some(burst) => {
+                    burst.packets += self.received_run + missing;
+                    burst.losses += missing;
+                    burst.ended = None;
+                }
+                None => {
+                    self.burst = Some(Burst {
+                        packets: missing,
+                        losses: missing,
+                        started: self.last_arrival,
+                        ended: None
+                    })
+                }
+            }
+            self.received_run = 0;
+        }
+
+        self.packets += 1;
+        self.received_run += 1;
+        self.first_arrival.get_or_insert(arrival);
+        self.last_arrival = Some(arrival);
+        if let Some(burst) = self.burst.as_mut() {
+            burst.ended.get_or_insert(arrival);
+            if self.received_run >= self.gmin as u64 {
+                let burst = *burst;
+                self.burst = None;
+                self.close(burst);
+            }
+        }
+    }
+
+    fn close(&mut self, burst: Burst) {
+        if burst.losses < 2 {
+            return;
+        }
+        self.bursts += 1;
+        self.burst_packets += burst.packets;
+        self.burst_losses += burst.losses;
+        if let (Some(started), Some(ended)) = (burst.started, burst.ended) {
+            self.burst_time += ended.saturating_duration_since(started);
+        }
+    }
+
+    // Burst and gap fields of the summary. A burst still open counts as
+    // though it ended now.
+    pub fn summary(&self) -> MetricsSummary {
+        let mut totals = BurstGap { burst: None, ..*self };
+        if let Some(burst) = self.burst {
+            totals.close(burst);
+        }
+        let elapsed = match (self.first_arrival, self.last_arrival) {
+            (Some(first), Some(last)) => last.saturating_duration_since(first),
+            _ => Duration::ZERO
+        };
+        let gaps = if self.packets == 0 { 0 } else { totals.bursts + 1 };
+        MetricsSummary {
+            gmin: self.gmin,
+            packets_expected: self.packets,
+            bursts: totals.bursts,
+            burst_packets: totals.burst_packets,
+            burst_losses: totals.burst_losses,
+            gap_packets: self.packets - totals.burst_packets,
+            gap_losses: self.missing - totals.burst_losses,
+            mean_burst_duration: mean(totals.burst_time, totals.bursts),
+            mean_gap_duration: mean(elapsed.saturating_sub(totals.burst_time), gaps),
+            ..MetricsSummary::default()
+        }
+    }
+}
+
+fn mean(total: Duration, count: u64) -> Duration {
+    match u32::try_from(count) {
+        Ok(0) => Duration::ZERO,
+        Ok(count) => total / count,
+        Err(_) => Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays a pattern, one character per packet 10 ms apart: '.' arrived,
+    // 'x' missing. Every run of 'x' reaches reassembly with the packet
+    // after it, so the pattern must end in '.'.
+    fn play(gmin: u8, pattern: &str) -> MetricsSummary {
+        let mut tracker = BurstGap::default();
+        tracker.set_gmin(gmin);
+        let start = Instant::now();
+        let mut missing = 0;
+        for (index, packet) in pattern.chars().enumerate() {
+            match packet {
+                'x' => missing += 1,
+                _ => {
+                    tracker.on_packet(missing, start + Duration::from_millis(10 * index as u64));
+                    missing = 0;
+                }
+            }
+        }
+        tracker.summary()
+    }
+
+    fn counts(summary: &MetricsSummary) -> [u64; 6] {
+        [
+            summary.packets_expected,
+            summary.bursts,
+            summary.burst_packets,
+            summary.burst_losses,
+            summary.gap_packets,
+            summary.gap_losses
+        ]
+    }
+
+    #[test]
+    fn no_loss_is_one_long_gap() {
+        let summary = play(4, &".".repeat(20));
+        assert_eq!(counts(&summary), [20, 0, 0, 0, 20, 0]);
+        assert_eq!(summary.mean_gap_duration, Duration::from_millis(190));
+        assert_eq!(summary.mean_burst_duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn isolated_losses_are_gap_losses() {
+        // Each loss has at least Gmin = 4 received packets on both sides.
+        let summary = play(4, "....x.....x....");
+        assert_eq!(counts(&summary), [15, 0, 0, 0, 15, 2]);
+    }
+
+    #[test]
+    fn losses_closer_than_gmin_form_one_burst() {
+        // The burst is "xx..x.x": from the first to the last loss, with
+        // fewer than 4 received packets between losses.
+        let summary = play(4, "......xx..x.x........");
+        assert_eq!(counts(&summary), [21, 1, 7, 4, 14, 0]);
+        // From the packet before the first loss (5) to the one after the
+        // last (13).
+        assert_eq!(summary.mean_burst_duration, Duration::from_millis(80));
+        // 200 ms in all, over the gaps before and after the burst.
+        assert_eq!(summary.mean_gap_duration, Duration::from_millis(60));
+    }
+
+    #[test]
+    fn gmin_received_packets_end_a_burst() {
+        // Four received packets end the first burst; three would not.
+        let summary = play(4, "..xx....xxx...xx....");
+        assert_eq!(counts(&summary), [20, 2, 10, 7, 10, 0]);
+        let summary = play(5, "..xx....xxx...xx....");
+        assert_eq!(counts(&summary), [20, 1, 14, 7, 6, 0]);
+    }
+
+    #[test]
+    fn a_burst_still_open_is_counted() {
+        let summary = play(16, "....x.x.x.");
+        assert_eq!(counts(&summary), [10, 1, 5, 3, 5, 0]);
+    }
+}
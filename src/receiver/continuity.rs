@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+
+use crate::time;
+
+// Timestamp steps the nominal frame interval is taken from.
+const DELTA_WINDOW: usize = 32;
+// Steps needed before gaps are looked for at all.
+const MIN_DELTAS: usize = 4;
+pub const DEFAULT_TOLERANCE_PERCENT: u32 = 50;
+
+// Frame-level loss and repeats from the timestamps of delivered frames. The
+// nominal frame interval is the most common step among the recent ones; a
+// step well beyond it means frames never arrived, and a step of zero that
+// the sender sent a frame twice.
+pub struct FrameContinuity {
+    tolerance_percent: u32,
+    last_timestamp: Option<u32>,
+    recent_deltas: VecDeque<u32>,
+    missing_frames: u64,
+    duplicate_frames: u64
+}
+
+impl Default for FrameContinuity {
+    fn default() -> Self {
+        Self {
+            tolerance_percent: DEFAULT_TOLERANCE_PERCENT,
+            last_timestamp: None,
+            recent_deltas: VecDeque::with_capacity(DELTA_WINDOW),
+            missing_frames: 0,
+            duplicate_frames: 0
+        }
+    }
+}
+
+impl FrameContinuity {
+    pub fn set_tolerance_percent(&mut self, percent: u32) {
+        self.tolerance_percent = percent;
+    }
+
+    // Takes the next frame's timestamp; true if frames are missing before
+    // it.
+    pub fn on_frame(&mut self, timestamp: u32) -> bool {
+        let previous = match self.last_timestamp.replace(timestamp) {
+            Some(previous) => previous,
+            None => return false
+        };
+        let delta = time::wire_diff(timestamp, previous);
+        if delta == 0 {
+            self.duplicate_frames += 1;
+            return false;
+        }
+        // Steps back (B-frames in decode order, a restarted sender) say
+        // nothing about the frame rate.
+        if delta < 0 {
+            return false;
+        }
+        let delta = delta as u32;
+        let missing = self.nominal_interval().map_or(0, |nominal| self.missing_before(delta, nominal));
+        self.missing_frames += missing;
+        // A gap is not a frame interval; it would skew the mode otherwise.
+        if missing == 0 {
+            if self.recent_deltas.len() == DELTA_WINDOW {
+                self.recent_deltas.pop_front();
+            }
+            self.recent_deltas.push_back(delta);
+        }
+        missing > 0
+    }
+
+    // Frames that fit in a step of `delta` ticks beyond the one expected,
+    // once the step exceeds the interval by more than the tolerance.
+    fn missing_before(&self, delta: u32, nominal: u32) -> u64 {
+        let limit = nominal as u64 * (100 + self.tolerance_percent as u64) / 100;
+        if (delta as u64) <= limit {
+            return 0;
+        }
+        let frames = (delta as u64 + nominal as u64 / 2) / nominal as u64;
+        frames.saturating_sub(1).max(1)
+    }
+
+    // The most common recent step, the smaller one on a tie.
+    pub fn nominal_interval(&self) -> Option<u32> {
+        if self.recent_deltas.len() < MIN_DELTAS {
+            return None;
+        }
+        // Counted in place: the window is small and this runs per frame.
+        let count = |delta: u32| self.recent_deltas.iter().filter(|&&other| other == delta).count();
+        self.recent_deltas.iter().copied().max_by(|&a, &b| count(a).cmp(&count(b)).then(b.cmp(&a)))
+    }
+
+    pub fn missing_frames(&self) -> u64 {
+        self.missing_frames
+    }
+
+    pub fn duplicate_frames(&self) -> u64 {
+        self.duplicate_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds the timestamps and returns which frames followed a gap.
+    fn gaps(continuity: &mut FrameContinuity, timestamps: &[u32]) -> Vec<usize> {
+        (0..timestamps.len()).filter(|&index| continuity.on_frame(timestamps[index])).collect()
+    }
+
+    fn steady(frames: u32, interval: u32) -> Vec<u32> {
+        (0..frames).map(|frame| frame * interval).collect()
+    }
+
+    #[test]
+    fn steady_30_fps_has_no_gaps() {
+        let mut continuity = FrameContinuity::default();
+        // Across the 32-bit wrap, too.
+        let timestamps: Vec<u32> = steady(100, 3_000).iter().map(|ts| ts.wrapping_sub(150_000)).collect();
+        assert!(gaps(&mut continuity, &timestamps).is_empty());
+        assert_eq!(continuity.nominal_interval(), Some(3_000));
+        assert_eq!((continuity.missing_frames(), continuity.duplicate_frames()), (0, 0));
+    }
+
+    #[test]
+    fn dropped_frames_are_counted_and_flagged() {
+        let mut continuity = FrameContinuity::default();
+        let mut timestamps = steady(10, 3_000);
+        // Frame 10 is lost, then frames 14 to 16.
+        timestamps.extend([11, 12, 13, 17, 18].map(|frame| frame * 3_000));
+        assert_eq!(gaps(&mut continuity, &timestamps), [10, 13]);
+        assert_eq!(continuity.missing_frames(), 4);
+        assert_eq!(continuity.nominal_interval(), Some(3_000));
+    }
+
+    #[test]
+    fn repeated_timestamps_are_duplicates() {
+        let mut continuity = FrameContinuity::default();
+        let timestamps = [0, 3_000, 6_000, 6_000, 9_000, 12_000, 12_000];
+        assert!(gaps(&mut continuity, &timestamps).is_empty());
+        assert_eq!(continuity.duplicate_frames(), 2);
+    }
+
+    #[test]
+    fn variable_frame_rate_needs_a_wider_tolerance() {
+        // Mostly 30 fps, with the encoder slowing to 15 fps at times.
+        let steps = [3_000, 3_000, 3_000, 3_000, 6_000, 3_000, 3_000, 6_000, 6_000, 3_000];
+        let mut timestamps = vec![0];
+        for step in steps {
+            timestamps.push(timestamps[timestamps.len() - 1] + step);
+        }
+
+        let mut strict = FrameContinuity::default();
+        assert_eq!(gaps(&mut strict, &timestamps).len(), 3);
+        let mut lenient = FrameContinuity::default();
+        lenient.set_tolerance_percent(100);
+        assert!(gaps(&mut lenient, &timestamps).is_empty());
+        assert_eq!(lenient.missing_frames(), 0);
+    }
+
+    #[test]
+    fn nothing_is_flagged_before_the_interval_is_known() {
+        let mut continuity = FrameContinuity::default();
+        assert!(gaps(&mut continuity, &[0, 3_000, 30_000, 33_000]).is_empty());
+        assert_eq!(continuity.nominal_interval(), None);
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::payload::h264::{pps_ids, slice_position, sps_frame_size};
+use crate::rtp::RtpPacket;
+use crate::stats::{Finding, Pathology};
+
+const NAL_TYPE_NON_IDR: u8 = 1;
+const NAL_TYPE_IDR: u8 = 5;
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+const NAL_TYPE_STAP_A: u8 = 24;
+const NAL_TYPE_FU_A: u8 = 28;
+const FORBIDDEN_ZERO_BIT: u8 = 1 << 7;
+const FU_START: u8 = 1 << 7;
+const FU_END: u8 = 1 << 6;
+// Largest ids an SPS and a PPS may have (H.264 section 7.4.2).
+const MAX_SPS_ID: u32 = 31;
+const MAX_PPS_ID: u32 = 255;
+// One timestamp over more packets than this is a frozen clock even without
+// markers; no frame takes that long to send.
+const FROZEN_AFTER: Duration = Duration::from_secs(2);
+// Indices of the pathologies in StreamDoctor::open.
+const FU_START_MISSING: usize = 0;
+const FORBIDDEN_ZERO_BIT_SET: usize = 1;
+const SLICE_OUTSIDE_SPS: usize = 2;
+const FROZEN_TIMESTAMP: usize = 3;
+
+// Where the FU-A fragments of the current NAL stand.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fragment {
+    None,
+    Started,
+    // Its start is missing: lost, or never sent.
+    Orphan
+}
+
+// Watches the packets reaching reassembly, in order, for sender bugs. Each
+// pathology's occurrence window opens at the first packet showing it and
+// closes at one that does not, so a camera that gets it wrong all the time
+// is one occurrence, not one per packet.
+pub struct StreamDoctor {
+    // In the order first seen.
+    findings: Vec<Finding>,
+    // Which pathologies are in an occurrence now.
+    open: [bool; 4],
+    fragment: Fragment,
+    // PicSizeInMbs by SPS id, and the SPS id by PPS id.
+    frame_sizes: BTreeMap<u32, u32>,
+    pps_sps: BTreeMap<u32, u32>,
+    // Timestamp of the frame whose slices are being checked, and whether
+    // one of them fell outside its SPS.
+    slice_frame: Option<(u32, bool)>,
+    // Timestamp and marker of the last packet, and the arrival of the first
+    // packet with that timestamp.
+    last: Option<(u32, bool)>,
+    timestamp_since: Option<Instant>
+}
+
+impl Default for StreamDoctor {
+    fn default() -> Self {
+        Self {
+            findings: Vec::new(),
+            open: [false; 4],
+            fragment: Fragment::None,
+            frame_sizes: BTreeMap::new(),
+            pps_sps: BTreeMap::new(),
+            slice_frame: None,
+            last: None,
+            timestamp_since: None
+        }
+    }
+}
+
+impl StreamDoctor {
+    // Takes the next packet in sequence; `lost` says packets before it went
+    // missing, which excuses fragments without their start.
+    pub fn on_packet(&mut self, packet: &RtpPacket, lost: bool, arrival: Instant) {
+        // Keepalives and probes carry no payload.
+        let header = match packet.payload.first() {
+            Some(&header) => header,
+            None => return
+        };
+        let nals = nals(packet.payload);
+        self.check(packet, arrival, Pathology::ForbiddenZeroBit, header & FORBIDDEN_ZERO_BIT != 0 || nals.iter().any(|nal| nal[0] & FORBIDDEN_ZERO_BIT != 0));
+        self.check_fragment(packet, lost, arrival);
+        for nal in &nals {
+            self.check_nal(packet, arrival, nal);
+        }
+        self.check_timestamp(packet, arrival);
+    }
+
+    pub fn findings(&self) -> Vec<Finding> {
+        self.findings.clone()
+    }
+
+    fn check_fragment(&mut self, packet: &RtpPacket, lost: bool, arrival: Instant) {
+        let fu_header = match packet.payload {
+            [indicator, fu_header, ..] if indicator & 0x1F == NAL_TYPE_FU_A => *fu_header,
+            _ => {
+                self.fragment = Fragment::None;
+                self.check(packet, arrival, Pathology::FuStartMissing, false);
+                return;
+            }
+        };
+        if fu_header & FU_START != 0 {
+            self.fragment = Fragment::Started;
+            self.check(packet, arrival, Pathology::FuStartMissing, false);
+        } else if lost {
+            self.fragment = Fragment::Orphan;
+        } else if self.fragment == Fragment::None {
+            self.fragment = Fragment::Orphan;
+            self.check(packet, arrival, Pathology::FuStartMissing, true);
+        } else if self.fragment == Fragment::Orphan && self.open[FU_START_MISSING] {
+            self.check(packet, arrival, Pathology::FuStartMissing, true);
+        }
+        if fu_header & FU_END != 0 {
+            self.fragment = Fragment::None;
+        }
+    }
+
+    fn check_nal(&mut self, packet: &RtpPacket, arrival: Instant, nal: &[u8]) {
+        match nal[0] & 0x1F {
+            NAL_TYPE_SPS => {
+                if let Some((id, size)) = sps_frame_size(nal).filter(|&(id, _)| id <= MAX_SPS_ID) {
+                    self.frame_sizes.insert(id, size);
+                }
+            }
+            NAL_TYPE_PPS => {
+                if let Some((pps_id, sps_id)) = pps_ids(nal).filter(|&(pps_id, _)| pps_id <= MAX_PPS_ID) {
+                    self.pps_sps.insert(pps_id, sps_id);
+                }
+            }
+            NAL_TYPE_NON_IDR | NAL_TYPE_IDR => {
+                let (first_mb_in_slice, pps_id) = match slice_position(nal) {
+                    Some(position) => position,
+                    None => return
+                };
+                let size = self.pps_sps.get(&pps_id).and_then(|sps_id| self.frame_sizes.get(sps_id));
+                let outside = size.is_some_and(|&size| first_mb_in_slice >= size);
+                // A frame with every slice inside ends the occurrence.
+                match self.slice_frame {
+                    Some((ts, _)) if ts == packet.ts => {}
+                    Some((_, false)) => {
+                        self.open[SLICE_OUTSIDE_SPS] = false;
+                        self.slice_frame = Some((packet.ts, false));
+                    }
+                    _ => self.slice_frame = Some((packet.ts, false))
+                }
+                if let (true, Some(&pic_size_in_mbs)) = (outside, size) {
+                    self.slice_frame = Some((packet.ts, true));
+                    let pathology = Pathology::SliceOutsideSps { first_mb_in_slice, pic_size_in_mbs };
+                    self.check(packet, arrival, pathology, true);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_timestamp(&mut self, packet: &RtpPacket, arrival: Instant) {
+        match self.last {
+            Some((ts, marker)) if ts == packet.ts => {
+                let frozen = self.open[FROZEN_TIMESTAMP]
+                    || marker
+                    || self.timestamp_since.is_some_and(|since| arrival.saturating_duration_since(since) > FROZEN_AFTER);
+                if frozen {
+                    self.check(packet, arrival, Pathology::FrozenTimestamp, true);
+                }
+            }
+            _ => {
+                self.timestamp_since = Some(arrival);
+                self.check(packet, arrival, Pathology::FrozenTimestamp, false);
+            }
+        }
+        self.last = Some((packet.ts, packet.marker));
+    }
+
+    // Counts `packet` for `pathology` if it shows it, starting an occurrence
+    // unless one is open; otherwise closes the occurrence.
+    fn check(&mut self, packet: &RtpPacket, arrival: Instant, pathology: Pathology, shown: bool) {
+        let kind = kind(pathology);
+        if !shown {
+            self.open[kind] = false;
+            return;
+        }
+        let index = match self.findings.iter().position(|finding| self::kind(finding.pathology) == kind) {
+            Some(index) => index,
+            None => {
+                self.findings.push(Finding {
+                    pathology,
+                    occurrences: 0,
+                    packets: 0,
+                    first_sequence: packet.seq,
+                    first_timestamp: packet.ts,
+                    first_seen: arrival
+                });
+                self.findings.len() - 1
+            }
+        };
+        let finding = &mut self.findings[index];
+        if !self.open[kind] {
+            self.open[kind] = true;
+            finding.occurrences += 1;
+        }
+        finding.packets += 1;
+    }
+}
+
+fn kind(pathology: Pathology) -> usize {
+    match pathology {
+        Pathology::FuStartMissing => FU_START_MISSING,
+        Pathology::ForbiddenZeroBit => FORBIDDEN_ZERO_BIT_SET,
+        Pathology::SliceOutsideSps { .. } => SLICE_OUTSIDE_SPS,
+        Pathology::FrozenTimestamp => FROZEN_TIMESTAMP
+    }
+}
+
+// The NALs of a packet whose headers can be read: a single NAL, the units
+// of a STAP-A, or the NAL a FU-A Start fragment begins, its header rebuilt
+// from the FU indicator and header. Malformed aggregates give what parsed.
+fn nals(payload: &[u8]) -> Vec<std::borrow::Cow<'_, [u8]>> {
+    let mut nals = Vec::new();
+    match payload[0] & 0x1F {
+        NAL_TYPE_STAP_A => {
+            let mut rest = &payload[1..];
+            while let [high, low, tail @ ..] = rest {
+                let len = u16::from_be_bytes([*high, *low]) as usize;
+                if len == 0 || len > tail.len() {
+                    break;
+                }
+                nals.push(tail[..len].into());
+                rest = &tail[len..];
+            }
+        }
+        NAL_TYPE_FU_A => {
+            if let Some(&fu_header) = payload.get(1).filter(|&&fu_header| fu_header & FU_START != 0) {
+                let header = (payload[0] & 0xE0) | (fu_header & 0x1F);
+                nals.push([&[header][..], &payload[2..]].concat().into());
+            }
+        }
+        _ => nals.push(payload.into())
+    }
+    nals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SPS of testsupport's 320x240 stream: 20x15 macroblocks, and a
+    // PPS referencing it.
+    const SPS: [u8; 8] = [0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x05, 0x07, 0xE4];
+    const PPS: [u8; 4] = [0x68, 0xCE, 0x3C, 0x80];
+    // P slices with first_mb_in_slice 0 and 300.
+    const SLICE: [u8; 2] = [0x41, 0x9B];
+    const SLICE_PAST_END: [u8; 5] = [0x41, 0x00, 0x96, 0x9B, 0x80];
+
+    // Feeds (timestamp, marker, payload) packets in sequence, 10 ms apart,
+    // `None` standing for a lost packet.
+    fn play(packets: &[Option<(u32, bool, Vec<u8>)>]) -> Vec<Finding> {
+        let mut doctor = StreamDoctor::default();
+        let start = Instant::now();
+        let mut lost = false;
+        for (index, packet) in packets.iter().enumerate() {
+            let (ts, marker, payload) = match packet {
+                Some(packet) => packet,
+                None => {
+                    lost = true;
+                    continue;
+                }
+            };
+            let packet = RtpPacket {
+                marker: *marker,
+                seq: index as u16,
+                ts: *ts,
+                ssrc: 1,
+                extension: None,
+                payload,
+                payload_offset: 12
+            };
+            doctor.on_packet(&packet, lost, start + Duration::from_millis(10 * index as u64));
+            lost = false;
+        }
+        doctor.findings()
+    }
+
+    // The last packet of its frame, and one ahead of it.
+    fn single(ts: u32, payload: &[u8]) -> Option<(u32, bool, Vec<u8>)> {
+        Some((ts, true, payload.to_vec()))
+    }
+
+    fn ahead(ts: u32, payload: &[u8]) -> Option<(u32, bool, Vec<u8>)> {
+        Some((ts, false, payload.to_vec()))
+    }
+
+    // A FU-A fragment of a non-IDR slice; `start` and `end` set its S and E
+    // bits.
+    fn fragment(ts: u32, start: bool, end: bool) -> Option<(u32, bool, Vec<u8>)> {
+        let fu_header = (start as u8) << 7 | (end as u8) << 6 | 1;
+        Some((ts, end, vec![0x5C, fu_header, 0xAA, 0xBB]))
+    }
+
+    fn summary(findings: &[Finding]) -> Vec<(Pathology, u64, u64, u16)> {
+        findings.iter().map(|finding| (finding.pathology, finding.occurrences, finding.packets, finding.first_sequence)).collect()
+    }
+
+    #[test]
+    fn the_test_stream_sps_and_slices_parse() {
+        assert_eq!(sps_frame_size(&SPS), Some((0, 300)));
+        assert_eq!(pps_ids(&PPS), Some((0, 0)));
+        assert_eq!(slice_position(&SLICE), Some((0, 0)));
+        assert_eq!(slice_position(&SLICE_PAST_END), Some((300, 0)));
+    }
+
+    #[test]
+    fn a_clean_stream_has_no_findings() {
+        let packets = [
+            ahead(0, &SPS),
+            ahead(0, &PPS),
+            fragment(0, true, false),
+            fragment(0, false, true),
+            single(3_000, &SLICE),
+            single(6_000, &SLICE)
+        ];
+        assert!(play(&packets).is_empty());
+    }
+
+    #[test]
+    fn fragments_without_a_start_fire_once_per_run() {
+        let packets = [
+            // Two NALs back to back without their Start fragment: one run.
+            fragment(0, false, false),
+            fragment(0, false, true),
+            fragment(3_000, false, true),
+            // A whole NAL ends it, and the next orphan starts another.
+            fragment(6_000, true, false),
+            fragment(6_000, false, true),
+            fragment(9_000, false, true),
+            // After a loss the start may have been sent: no finding.
+            single(12_000, &SLICE),
+            None,
+            fragment(15_000, false, false),
+            fragment(15_000, false, true)
+        ];
+        assert_eq!(summary(&play(&packets)), [(Pathology::FuStartMissing, 2, 4, 0)]);
+    }
+
+    #[test]
+    fn forbidden_zero_bit_fires_once_per_run() {
+        let packets = [
+            single(0, &SLICE),
+            single(3_000, &[0xC1, 0x9B]),
+            single(6_000, &[0xC1, 0x9B]),
+            single(9_000, &SLICE),
+            // In an aggregated unit, and in a FU indicator.
+            single(12_000, &[0x18, 0x00, 0x02, 0xC1, 0x9B]),
+            single(15_000, &SLICE),
+            Some((18_000, true, vec![0xDC, 0x81, 0xAA]))
+        ];
+        assert_eq!(summary(&play(&packets)), [(Pathology::ForbiddenZeroBit, 3, 4, 1)]);
+    }
+
+    #[test]
+    fn slices_past_the_sps_frame_size_fire_once_per_run_of_frames() {
+        let outside = Pathology::SliceOutsideSps { first_mb_in_slice: 300, pic_size_in_mbs: 300 };
+        let packets = [
+            ahead(0, &SPS),
+            ahead(0, &PPS),
+            // Two frames of two slices, the second past the end each time:
+            // one run.
+            ahead(0, &SLICE),
+            single(0, &SLICE_PAST_END),
+            ahead(3_000, &SLICE),
+            single(3_000, &SLICE_PAST_END),
+            // A frame with every slice inside ends it.
+            single(6_000, &SLICE),
+            ahead(9_000, &SLICE),
+            single(9_000, &SLICE_PAST_END)
+        ];
+        assert_eq!(summary(&play(&packets)), [(outside, 2, 3, 3)]);
+    }
+
+    #[test]
+    fn slices_without_their_sps_are_not_judged() {
+        assert!(play(&[single(0, &SLICE_PAST_END)]).is_empty());
+    }
+
+    #[test]
+    fn timestamps_frozen_across_frames_fire_once_per_run() {
+        let packets = [
+            single(0, &SLICE),
+            // Two more frames with the first one's timestamp: one run.
+            single(0, &SLICE),
+            ahead(0, &SLICE),
+            single(0, &SLICE),
+            single(3_000, &SLICE),
+            single(3_000, &SLICE),
+            // Packets of one frame share it legitimately.
+            ahead(6_000, &SLICE),
+            single(6_000, &SLICE)
+        ];
+        assert_eq!(summary(&play(&packets)), [(Pathology::FrozenTimestamp, 2, 4, 1)]);
+    }
+
+    #[test]
+    fn one_timestamp_for_too_long_is_frozen_without_markers() {
+        // 10 ms apart: the 202nd packet is more than 2 s after the first.
+        let packets: Vec<_> = (0..210).map(|_| ahead(0, &SLICE)).collect();
+        assert_eq!(summary(&play(&packets)), [(Pathology::FrozenTimestamp, 1, 9, 201)]);
+    }
+}
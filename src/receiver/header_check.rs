@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+
+use crate::rtp::{RtpPacket, RTP_HEADER_SIZE};
+use crate::stats::HeaderStats;
+
+const FLAG_PADDING: u8 = 0x20;
+const FLAG_EXTENSION: u8 = 0x10;
+
+// Header quirks of legacy encoders that leave the packet's meaning clear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Quirk {
+    // Version 0 or 1, before the first version 2 packet.
+    LegacyVersion,
+    // A header extension of 0 words.
+    EmptyExtension,
+    // The padding bit with a padding count of 0, which cannot count the
+    // count byte itself. Taken as one byte of padding.
+    ZeroPadding
+}
+
+// Decides, before anything else reads it, whether a datagram is taken as
+// RTP. Strict by default: quirky packets are dropped and counted. Lenient,
+// they are rewritten into standard packets, so the rest of the receiver
+// never sees a quirk.
+#[derive(Default)]
+pub struct HeaderCheck {
+    lenient: bool,
+    seen_version_2: bool,
+    stats: HeaderStats
+}
+
+impl HeaderCheck {
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    pub fn stats(&self) -> HeaderStats {
+        self.stats
+    }
+
+    // The datagram to depacketize, or None if it is dropped.
+    pub fn screen<'a>(&mut self, datagram: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let quirks = match self.quirks(datagram) {
+            Some(quirks) => quirks,
+            None => {
+                self.stats.malformed_packets += 1;
+                return None;
+            }
+        };
+        if quirks.is_empty() {
+            if RtpPacket::parse(datagram).is_none() {
+                self.stats.malformed_packets += 1;
+                return None;
+            }
+            self.seen_version_2 = true;
+            return Some(Cow::Borrowed(datagram));
+        }
+        if !self.lenient {
+            self.stats.quirks_rejected += 1;
+            return None;
+        }
+
+        let mut fixed = datagram.to_vec();
+        for quirk in quirks {
+            match quirk {
+                Quirk::LegacyVersion => fixed[0] = fixed[0] & 0x3F | 2 << 6,
+                // Legal as it is, once the version is.
+                Quirk::EmptyExtension => {}
+                Quirk::ZeroPadding => {
+                    fixed[0] &= !FLAG_PADDING;
+                    fixed.pop();
+                }
+            }
+        }
+        // Lenience covers the quirks, not what else may be wrong.
+        if RtpPacket::parse(&fixed).is_none() {
+            self.stats.malformed_packets += 1;
+            return None;
+        }
+        self.stats.quirks_accepted += 1;
+        Some(Cow::Owned(fixed))
+    }
+
+    // The packet's quirks, None if its version rules it out.
+    fn quirks(&self, datagram: &[u8]) -> Option<Vec<Quirk>> {
+        let first = *datagram.first()?;
+        let mut quirks = Vec::new();
+        match first >> 6 {
+            2 => {}
+            0 | 1 if !self.seen_version_2 => quirks.push(Quirk::LegacyVersion),
+            _ => return None
+        }
+        if first & FLAG_EXTENSION != 0 {
+            let offset = RTP_HEADER_SIZE + 4 * (first & 0x0F) as usize;
+            if let Some(&[_, _, 0, 0]) = datagram.get(offset..offset + 4) {
+                quirks.push(Quirk::EmptyExtension);
+            }
+        }
+        if first & FLAG_PADDING != 0 && datagram.len() > RTP_HEADER_SIZE && datagram.last() == Some(&0) {
+            quirks.push(Quirk::ZeroPadding);
+        }
+        Some(quirks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Version 2, payload type 96, seq 7, ts 3000, SSRC 1, a 3-byte payload.
+    fn packet() -> Vec<u8> {
+        let mut packet = vec![0x80, 96, 0, 7, 0, 0, 0x0B, 0xB8, 0, 0, 0, 1];
+        packet.extend([0x65, 0x88, 0x84]);
+        packet
+    }
+
+    fn with_version(version: u8) -> Vec<u8> {
+        let mut packet = packet();
+        packet[0] = packet[0] & 0x3F | version << 6;
+        packet
+    }
+
+    fn with_empty_extension() -> Vec<u8> {
+        let mut packet = packet();
+        packet[0] |= FLAG_EXTENSION;
+        packet.splice(RTP_HEADER_SIZE..RTP_HEADER_SIZE, [0xBE, 0xDE, 0, 0]);
+        packet
+    }
+
+    fn with_zero_padding() -> Vec<u8> {
+        let mut packet = packet();
+        packet[0] |= FLAG_PADDING;
+        packet.push(0);
+        packet
+    }
+
+    fn check(lenient: bool) -> HeaderCheck {
+        let mut check = HeaderCheck::default();
+        check.set_lenient(lenient);
+        check
+    }
+
+    fn stats(malformed_packets: u64, quirks_rejected: u64, quirks_accepted: u64) -> HeaderStats {
+        HeaderStats {
+            malformed_packets,
+            quirks_rejected,
+            quirks_accepted
+        }
+    }
+
+    #[test]
+    fn strict_mode_drops_and_counts_every_quirk() {
+        let mut check = check(false);
+        for quirky in [with_version(0), with_version(1), with_empty_extension(), with_zero_padding()] {
+            assert!(check.screen(&quirky).is_none());
+        }
+        assert!(check.screen(&packet()).is_some());
+        assert_eq!(check.stats(), stats(0, 4, 0));
+    }
+
+    #[test]
+    fn lenient_mode_rewrites_quirky_packets_into_standard_ones() {
+        let mut check = check(true);
+        for version in [0, 1] {
+            assert_eq!(check.screen(&with_version(version)).as_deref(), Some(&packet()[..]));
+        }
+        let extended = with_empty_extension();
+        let accepted = check.screen(&extended).unwrap();
+        assert_eq!(RtpPacket::parse(&accepted).unwrap().extension, Some(&[0xBE, 0xDE, 0, 0][..]));
+        assert_eq!(check.screen(&with_zero_padding()).as_deref(), Some(&packet()[..]));
+        assert_eq!(check.stats(), stats(0, 0, 4));
+    }
+
+    #[test]
+    fn standard_packets_pass_untouched_in_both_modes() {
+        for lenient in [false, true] {
+            let mut check = check(lenient);
+            let packet = packet();
+            assert!(matches!(check.screen(&packet), Some(Cow::Borrowed(_))));
+            assert_eq!(check.stats(), HeaderStats::default());
+        }
+    }
+
+    #[test]
+    fn legacy_versions_are_only_a_quirk_before_version_2() {
+        let mut check = check(true);
+        assert!(check.screen(&packet()).is_some());
+        assert!(check.screen(&with_version(1)).is_none());
+        assert_eq!(check.stats(), stats(1, 0, 0));
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected_in_both_modes() {
+        let mut truncated_extension = with_empty_extension();
+        truncated_extension[RTP_HEADER_SIZE + 3] = 9;
+        let mut oversized_padding = with_zero_padding();
+        *oversized_padding.last_mut().unwrap() = 200;
+        let malformed = [vec![0x80, 96, 0], with_version(3), truncated_extension, oversized_padding];
+        for lenient in [false, true] {
+            let mut check = check(lenient);
+            for packet in &malformed {
+                assert!(check.screen(packet).is_none());
+            }
+            assert_eq!(check.stats(), stats(4, 0, 0));
+        }
+
+        // A legacy version does not excuse a broken extension.
+        let mut check = check(true);
+        let mut legacy_truncated = with_version(1);
+        legacy_truncated[0] |= FLAG_EXTENSION;
+        legacy_truncated.truncate(RTP_HEADER_SIZE + 2);
+        assert!(check.screen(&legacy_truncated).is_none());
+        assert_eq!(check.stats(), stats(1, 0, 0));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use super::adaptive::AdaptiveDepth;
+use super::JitterDepth;
+use crate::stats::JitterAdaptation;
+
+// Sequence jumps back further than this mean the sender started over.
+const MAX_MISORDER: u64 = 3000;
+// Packets held back at most, whatever the depth.
+const MAX_PENDING: usize = 4096;
+// Shortest wait for a deadline, so a due release never turns into a busy
+// loop on a timer that rounds down.
+pub const MIN_WAIT: Duration = Duration::from_millis(1);
+
+// Puts reordered packets back in sequence order before reassembly. At a
+// gap, later packets are held until the depth is used up; then the missing
+// ones are given up on. Packets arriving after their place was released
+// are dropped.
+pub struct JitterBuffer {
+    depth: JitterDepth,
+    // The wait of JitterDepth::Adaptive.
+    adaptive: Option<AdaptiveDepth>,
+    ssrc: Option<u32>,
+    // Extended (wrap-counted) sequence number to release next. None until
+    // the first release: the first packet to arrive need not be the first
+    // sent, so it waits like one behind a gap.
+    next_seq: Option<u64>,
+    highest_seq: Option<u64>,
+    // Extended sequence number -> (arrival, datagram).
+    pending: BTreeMap<u64, (Instant, Vec<u8>)>,
+    // Released by a resync, ahead of anything pending.
+    ready: VecDeque<(Instant, Vec<u8>)>,
+    reordered: u64,
+    late: u64
+}
+
+// What push did with a packet.
+pub enum Push {
+    // Next in sequence with nothing held: the caller passes it on as is.
+    InOrder,
+    // Kept, or dropped as a duplicate or late packet; pop releases what it
+    // can.
+    Taken
+}
+
+impl JitterBuffer {
+    pub fn new(depth: JitterDepth) -> Self {
+        let adaptive = match depth {
+            JitterDepth::Adaptive { min, max } => Some(AdaptiveDepth::new(min, max)),
+            _ => None
+        };
+        Self {
+            depth,
+            adaptive,
+            ssrc: None,
+            next_seq: None,
+            highest_seq: None,
+            pending: BTreeMap::new(),
+            ready: VecDeque::new(),
+            reordered: 0,
+            late: 0
+        }
+    }
+
+    pub fn push(&mut self, ssrc: u32, seq: u16, timestamp: u32, datagram: &[u8], arrival: Instant) -> Push {
+        if self.ssrc != Some(ssrc) {
+            // A new source numbers its packets afresh.
+            self.release_all();
+            self.ssrc = Some(ssrc);
+            if let Some(adaptive) = self.adaptive.as_mut() {
+                adaptive.restart();
+            }
+        }
+        // Far from 0, so sequence numbers before the first one still fit.
+        let highest = *self.highest_seq.get_or_insert((1 << 32) | seq as u64);
+        let extended = highest.wrapping_add_signed(seq.wrapping_sub(highest as u16) as i16 as i64);
+
+        if extended + MAX_MISORDER < highest {
+            // Restarted stream: resynchronize on this packet.
+            self.release_all();
+            if let Some(adaptive) = self.adaptive.as_mut() {
+                adaptive.restart();
+            }
+            return self.push(ssrc, seq, timestamp, datagram, arrival);
+        }
+        if let Some(adaptive) = self.adaptive.as_mut() {
+            // Late packets too: they are what the wait is for.
+            adaptive.on_packet(timestamp, arrival);
+        }
+        if self.next_seq.is_some_and(|next| extended < next) {
+            self.late += 1;
+            return Push::Taken;
+        }
+        if self.pending.contains_key(&extended) {
+            // A second copy of a held packet.
+            return Push::Taken;
+        }
+        if extended < highest {
+            self.reordered += 1;
+        }
+        self.highest_seq = Some(highest.max(extended));
+
+        if self.next_seq == Some(extended) && self.pending.is_empty() && self.ready.is_empty() {
+            self.next_seq = Some(extended + 1);
+            return Push::InOrder;
+        }
+        self.pending.insert(extended, (arrival, datagram.to_vec()));
+        Push::Taken
+    }
+
+    // The next packet in sequence order: the one due next, or after a gap
+    // once the depth is used up, with its arrival time.
+    pub fn pop(&mut self, now: Instant) -> Option<(Vec<u8>, Instant)> {
+        if let Some((arrival, datagram)) = self.ready.pop_front() {
+            return Some((datagram, arrival));
+        }
+        let (&seq, &(arrival, _)) = self.pending.iter().next()?;
+        let in_order = Some(seq) == self.next_seq;
+        let waited_out = match (self.depth, self.delay()) {
+            (JitterDepth::Packets(packets), _) => self.pending.len() > packets,
+            (_, delay) => delay.is_some_and(|delay| now.saturating_duration_since(arrival) >= delay)
+        };
+        if !in_order && !waited_out && self.pending.len() < MAX_PENDING {
+            return None;
+        }
+        self.next_seq = Some(seq + 1);
+        self.pending.remove(&seq).map(|(arrival, datagram)| (datagram, arrival))
+    }
+
+    // When the packet held longest is released if its gap is not filled;
+    // None when nothing is held, or the depth is counted in packets.
+    pub fn deadline(&self) -> Option<Instant> {
+        let delay = self.delay()?;
+        self.pending.values().next().map(|(arrival, _)| *arrival + delay)
+    }
+
+    // How long a packet behind a gap waits, unless the depth is counted in
+    // packets.
+    pub fn delay(&self) -> Option<Duration> {
+        match (self.depth, self.adaptive.as_ref()) {
+            (JitterDepth::Time(time), _) => Some(time),
+            (_, Some(adaptive)) => Some(adaptive.target()),
+            _ => None
+        }
+    }
+
+    pub fn take_adaptations(&mut self) -> Vec<JitterAdaptation> {
+        self.adaptive.as_mut().map(AdaptiveDepth::take_events).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.ready.is_empty()
+    }
+
+    // Gives up on every gap: what is held becomes ready in order, and
+    // sequencing starts over.
+    pub fn release_all(&mut self) {
+        self.ready.extend(std::mem::take(&mut self.pending).into_values());
+        self.next_seq = None;
+        self.highest_seq = None;
+    }
+
+    // Packets that arrived after a later one and were put back in order.
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    // Packets dropped for arriving after their place was released. Repeats
+    // are filtered out before the buffer, unless too old to recognize.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::rtcp::Membership;
+use crate::stats::{LeaveReason, MemberEvent, MemberRole, SessionMember};
+
+pub const DEFAULT_MAX_MEMBERS: usize = 256;
+// Events not yet taken beyond this are dropped, oldest first.
+const MAX_EVENTS: usize = 1024;
+// RFC 3550 section 6.3.5: a member times out after this many report
+// intervals, each at least the 5 s minimum of section 6.2.
+const TIMEOUT_INTERVALS: u32 = 5;
+const MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+// Who is in the session, from every RTCP packet that arrives whichever
+// source it is about, e.g. the other participants of a multicast group.
+// Bounded: past the member limit the least recently seen goes.
+pub struct SessionMembers {
+    members: HashMap<u32, SessionMember>,
+    max_members: usize,
+    events: VecDeque<MemberEvent>
+}
+
+impl Default for SessionMembers {
+    fn default() -> Self {
+        Self {
+            members: HashMap::new(),
+            max_members: DEFAULT_MAX_MEMBERS,
+            events: VecDeque::new()
+        }
+    }
+}
+
+impl SessionMembers {
+    pub fn set_max_members(&mut self, max_members: usize) {
+        self.max_members = max_members.max(1);
+        while self.members.len() > self.max_members {
+            self.evict();
+        }
+    }
+
+    // Takes what one compound packet says. Packets about `own_ssrc` (the
+    // receiver's own reports, looped back by multicast) are not a member.
+    pub fn on_rtcp(&mut self, memberships: Vec<Membership>, arrival: Instant, own_ssrc: u32) {
+        for membership in memberships {
+            let ssrc = match &membership {
+                Membership::Report { ssrc, .. }
+                | Membership::Cname { ssrc, .. }
+                | Membership::App { ssrc }
+                | Membership::Bye { ssrc, .. } => *ssrc
+            };
+            if ssrc == own_ssrc {
+                continue;
+            }
+            if let Membership::Bye { reason, .. } = membership {
+                // A BYE from a stranger says nothing about the session.
+                if self.members.remove(&ssrc).is_some() {
+                    self.push_event(MemberEvent::Left {
+                        ssrc,
+                        reason: LeaveReason::Bye(reason)
+                    });
+                }
+                continue;
+            }
+
+            if !self.members.contains_key(&ssrc) {
+                if self.members.len() >= self.max_members {
+                    self.evict();
+                }
+                self.members.insert(ssrc, SessionMember {
+                    ssrc,
+                    cname: None,
+                    last_seen: arrival,
+                    role: MemberRole::Receiver
+                });
+                self.push_event(MemberEvent::Joined { ssrc });
+            }
+            let member = self.members.get_mut(&ssrc).expect("inserted above");
+            member.last_seen = arrival;
+            match membership {
+                Membership::Report { sender, .. } => {
+                    member.role = if sender { MemberRole::Sender } else { MemberRole::Receiver };
+                }
+                Membership::Cname { cname, .. } => member.cname = Some(cname),
+                _ => {}
+            }
+        }
+    }
+
+    // Drops the members that went quiet for too long, reports sent every
+    // `interval`.
+    pub fn expire(&mut self, now: Instant, interval: Duration) {
+        let timeout = interval.max(MIN_INTERVAL) * TIMEOUT_INTERVALS;
+        let mut expired: Vec<u32> = self
+            .members
+            .values()
+            .filter(|member| now.saturating_duration_since(member.last_seen) > timeout)
+            .map(|member| member.ssrc)
+            .collect();
+        expired.sort_unstable();
+        for ssrc in expired {
+            self.members.remove(&ssrc);
+            self.push_event(MemberEvent::Left {
+                ssrc,
+                reason: LeaveReason::Timeout
+            });
+        }
+    }
+
+    // By SSRC.
+    pub fn members(&self) -> Vec<SessionMember> {
+        let mut members: Vec<SessionMember> = self.members.values().cloned().collect();
+        members.sort_unstable_by_key(|member| member.ssrc);
+        members
+    }
+
+    pub fn take_events(&mut self) -> Vec<MemberEvent> {
+        self.events.drain(..).collect()
+    }
+
+    fn evict(&mut self) {
+        let oldest = self.members.values().min_by_key(|member| (member.last_seen, member.ssrc)).map(|member| member.ssrc);
+        if let Some(ssrc) = oldest {
+            self.members.remove(&ssrc);
+            self.push_event(MemberEvent::Left {
+                ssrc,
+                reason: LeaveReason::Evicted
+            });
+        }
+    }
+
+    fn push_event(&mut self, event: MemberEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN_SSRC: u32 = 99;
+
+    fn report(ssrc: u32, sender: bool) -> Vec<Membership> {
+        vec![Membership::Report { ssrc, sender }]
+    }
+
+    #[test]
+    fn reports_and_sdes_fill_in_members() {
+        let mut members = SessionMembers::default();
+        let start = Instant::now();
+        members.on_rtcp(report(1, true), start, OWN_SSRC);
+        members.on_rtcp(
+            vec![Membership::Report { ssrc: 2, sender: false }, Membership::Cname { ssrc: 2, cname: "b@host".into() }],
+            start,
+            OWN_SSRC
+        );
+        members.on_rtcp(report(OWN_SSRC, false), start, OWN_SSRC);
+
+        let list = members.members();
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[0].ssrc, list[0].role, list[0].cname.as_deref()), (1, MemberRole::Sender, None));
+        assert_eq!((list[1].ssrc, list[1].role, list[1].cname.as_deref()), (2, MemberRole::Receiver, Some("b@host")));
+        assert_eq!(members.take_events(), [MemberEvent::Joined { ssrc: 1 }, MemberEvent::Joined { ssrc: 2 }]);
+        assert!(members.take_events().is_empty());
+    }
+
+    #[test]
+    fn bye_leaves_and_strangers_are_ignored() {
+        let mut members = SessionMembers::default();
+        let start = Instant::now();
+        members.on_rtcp(report(1, false), start, OWN_SSRC);
+        members.on_rtcp(vec![Membership::Bye { ssrc: 7, reason: None }], start, OWN_SSRC);
+        members.on_rtcp(vec![Membership::Bye { ssrc: 1, reason: Some("bye".into()) }], start, OWN_SSRC);
+
+        assert!(members.members().is_empty());
+        assert_eq!(
+            members.take_events(),
+            [
+                MemberEvent::Joined { ssrc: 1 },
+                MemberEvent::Left {
+                    ssrc: 1,
+                    reason: LeaveReason::Bye(Some("bye".into()))
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn silent_members_time_out_after_five_intervals() {
+        let mut members = SessionMembers::default();
+        let start = Instant::now();
+        members.on_rtcp(report(1, false), start, OWN_SSRC);
+        members.on_rtcp(report(2, false), start + Duration::from_secs(20), OWN_SSRC);
+        members.take_events();
+
+        // A 1 s interval counts as the 5 s minimum: 25 s in all.
+        members.expire(start + Duration::from_secs(25), Duration::from_secs(1));
+        assert_eq!(members.members().len(), 2);
+        members.expire(start + Duration::from_secs(26), Duration::from_secs(1));
+        assert_eq!(members.members().len(), 1);
+        assert_eq!(
+            members.take_events(),
+            [MemberEvent::Left {
+                ssrc: 1,
+                reason: LeaveReason::Timeout
+            }]
+        );
+    }
+
+    #[test]
+    fn the_least_recently_seen_is_evicted_at_the_limit() {
+        let mut members = SessionMembers::default();
+        members.set_max_members(2);
+        let start = Instant::now();
+        members.on_rtcp(report(1, false), start, OWN_SSRC);
+        members.on_rtcp(report(2, false), start + Duration::from_secs(1), OWN_SSRC);
+        members.on_rtcp(report(1, false), start + Duration::from_secs(2), OWN_SSRC);
+        members.on_rtcp(report(3, false), start + Duration::from_secs(3), OWN_SSRC);
+
+        let ssrcs: Vec<u32> = members.members().iter().map(|member| member.ssrc).collect();
+        assert_eq!(ssrcs, [1, 3]);
+        assert!(members.take_events().contains(&MemberEvent::Left {
+            ssrc: 2,
+            reason: LeaveReason::Evicted
+        }));
+    }
+}
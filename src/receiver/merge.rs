@@ -0,0 +1,106 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::time::{Duration, Instant};
+
+use crate::stats::PathStats;
+
+// Sequence jumps back further than this mean the sender started over.
+const MAX_MISORDER: u64 = 3000;
+// Packets held back at most, whatever the window.
+const MAX_PENDING: usize = 4096;
+
+// Merges identical packet streams arriving over several paths (SMPTE 2022-7
+// style). The first copy of each sequence number wins and packets are
+// released in sequence order; at a gap, packets are held for `window` in
+// case the missing one is still coming over a slower path.
+pub struct PathMerger {
+    window: Duration,
+    // Extended (wrap-counted) sequence number to release next.
+    next_seq: Option<u64>,
+    // Extended sequence number -> (arrival, datagram).
+    pending: BTreeMap<u64, (Instant, Vec<u8>)>,
+    paths: Vec<PathCounter>
+}
+
+#[derive(Default)]
+struct PathCounter {
+    stats: PathStats,
+    highest_seq: Option<u64>
+}
+
+impl PathMerger {
+    pub fn new(window: Duration, paths: usize) -> Self {
+        Self {
+            window,
+            next_seq: None,
+            pending: BTreeMap::new(),
+            paths: (0..paths).map(|_| PathCounter::default()).collect()
+        }
+    }
+
+    pub fn push(&mut self, path: usize, seq: u16, datagram: Vec<u8>, arrival: Instant) {
+        // Far from 0, so sequence numbers before the first one still fit.
+        let next = *self.next_seq.get_or_insert((1 << 32) | seq as u64);
+        let extended = next.wrapping_add_signed(seq.wrapping_sub(next as u16) as i16 as i64);
+        if extended + MAX_MISORDER < next {
+            // Restarted stream: resynchronize on this packet.
+            self.pending.clear();
+            self.next_seq = Some(extended);
+            for counter in &mut self.paths {
+                counter.highest_seq = None;
+            }
+        } else if extended < next {
+            // Already released, or given up on.
+            self.count_path(path, extended);
+            self.paths[path].stats.duplicates += 1;
+            return;
+        }
+
+        self.count_path(path, extended);
+        match self.pending.entry(extended) {
+            Entry::Occupied(_) => self.paths[path].stats.duplicates += 1,
+            Entry::Vacant(entry) => {
+                entry.insert((arrival, datagram));
+            }
+        }
+    }
+
+    fn count_path(&mut self, path: usize, extended: u64) {
+        let counter = &mut self.paths[path];
+        counter.stats.packets_received += 1;
+        match counter.highest_seq {
+            Some(highest) if extended > highest => {
+                counter.stats.packets_lost += extended - highest - 1;
+                counter.highest_seq = Some(extended);
+            }
+            // Reordered on this path: it was counted lost when skipped.
+            Some(highest) if extended < highest => {
+                counter.stats.packets_lost = counter.stats.packets_lost.saturating_sub(1);
+            }
+            Some(_) => {}
+            None => counter.highest_seq = Some(extended)
+        }
+    }
+
+    // The next packet in sequence order: the one due next, or after a gap
+    // once the window has run out.
+    pub fn pop(&mut self, now: Instant) -> Option<(Vec<u8>, Instant)> {
+        let (&seq, &(arrival, _)) = self.pending.iter().next()?;
+        let in_order = Some(seq) == self.next_seq;
+        let waited_out = now.duration_since(arrival) >= self.window;
+        if !in_order && !waited_out && self.pending.len() < MAX_PENDING {
+            return None;
+        }
+        self.next_seq = Some(seq + 1);
+        self.pending.remove(&seq).map(|(arrival, datagram)| (datagram, arrival))
+    }
+
+    // When the packet held longest will be released if its gap is not
+    // filled, None when nothing is held back.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.values().next().map(|(arrival, _)| *arrival + self.window)
+    }
+
+    pub fn path_stats(&self) -> Vec<PathStats> {
+        self.paths.iter().map(|counter| counter.stats).collect()
+    }
+}
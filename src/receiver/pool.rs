@@ -0,0 +1,120 @@
+use std::io;
+use std::net::UdpSocket;
+use std::ops::Range;
+use std::time::Instant;
+
+use crate::clock::Clock;
+use crate::platform;
+
+// Datagrams read per wakeup.
+const SLOTS: usize = 32;
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+// Reusable buffers for the datagrams of one read, handed out in order. A
+// burst is drained from the socket in one call instead of one recv per
+// packet, and nothing is allocated per packet.
+pub struct PacketPool {
+    // SLOTS slots of slot_size bytes each.
+    buffer: Vec<u8>,
+    slot_size: usize,
+    lens: Vec<usize>,
+    count: usize,
+    next: usize,
+    // When the datagrams were read. They were all queued by then, so this
+    // stands in for the arrival of each.
+    received_at: Instant
+}
+
+impl PacketPool {
+    pub fn new(slot_size: usize) -> Self {
+        Self {
+            // Zeroed pages are mapped lazily: only the bytes datagrams
+            // actually reach take memory.
+            buffer: vec![0u8; SLOTS * slot_size],
+            slot_size,
+            lens: vec![0; SLOTS],
+            count: 0,
+            next: 0,
+            received_at: Instant::now()
+        }
+    }
+
+    // The next datagram not yet handed out, with its arrival time.
+    pub fn pop(&mut self) -> Option<(&[u8], Instant)> {
+        if self.next == self.count {
+            return None;
+        }
+        let start = self.next * self.slot_size;
+        let len = self.lens[self.next];
+        self.next += 1;
+        Some((&self.buffer[start..start + len], self.received_at))
+    }
+
+    // The NAL at `payload` of the datagram pop returned last, as an Annex
+    // B frame lent in place: its start code is written over the end of
+    // the RTP header, which has been read by then.
+    pub fn lend_last(&mut self, payload: Range<usize>) -> &[u8] {
+        let start = (self.next - 1) * self.slot_size;
+        let nal = start + payload.start..start + payload.end;
+        self.buffer[nal.start - START_CODE.len()..nal.start].copy_from_slice(&START_CODE);
+        &self.buffer[nal.start - START_CODE.len()..nal.end]
+    }
+
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    // Refills the pool from `socket`; see platform::recv_batch. Datagrams
+    // not yet popped are discarded, so only call this once pop is None.
+    // Arrival is read from `clock` once the read returns.
+    pub fn recv_from(&mut self, socket: &UdpSocket, clock: &dyn Clock) -> io::Result<Option<u32>> {
+        self.count = 0;
+        self.next = 0;
+        let (count, drops) = platform::recv_batch(socket, &mut self.buffer, self.slot_size, &mut self.lens)?;
+        self.count = count;
+        self.received_at = clock.now();
+        Ok(drops)
+    }
+
+    // Refills the pool with a single datagram that `read` puts into the
+    // first slot, for sources without a batched read.
+    pub fn recv_with(
+        &mut self,
+        clock: &dyn Clock,
+        read: impl FnOnce(&mut [u8]) -> io::Result<usize>,
+    ) -> io::Result<()> {
+        self.count = 0;
+        self.next = 0;
+        self.lens[0] = read(&mut self.buffer[..self.slot_size])?;
+        self.count = 1;
+        self.received_at = clock.now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    #[test]
+    fn a_lent_nal_stays_in_its_slot() {
+        let mut pool = PacketPool::new(64);
+        for payload in [&[0x41, 0x9A][..], &[0x65, 0x88, 0x84]] {
+            let datagram = [&[0x80, 0xE0, 0, 1, 0, 0, 0, 0, 0xCA, 0xFE, 0xBA, 0xBE][..], payload].concat();
+            pool.recv_with(&SystemClock, |slot| {
+                slot[..datagram.len()].copy_from_slice(&datagram);
+                Ok(datagram.len())
+            })
+            .unwrap();
+            assert_eq!(pool.pop().unwrap().0, &datagram[..]);
+
+            let slot = pool.buffer.as_ptr_range();
+            let frame = pool.lend_last(12..datagram.len());
+            assert_eq!(frame, &[&[0, 0, 0, 1][..], payload].concat()[..]);
+            // Where the header's SSRC was, in the pool's own buffer.
+            assert!(slot.contains(&frame.as_ptr()));
+            assert_eq!(frame.as_ptr(), slot.start.wrapping_add(8));
+        }
+    }
+}
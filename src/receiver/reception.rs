@@ -0,0 +1,244 @@
+use std::time::{Duration, Instant};
+
+use crate::rtp::RtpPacket;
+use crate::sender::DEFAULT_BITRATE_WINDOW;
+use crate::stats::{BitrateMeter, Goodbye, ReceiverStats, ReceptionReport, SenderReport};
+use crate::time::{MediaTimestamp, VIDEO_CLOCK_RATE};
+
+// Reception statistics of the current source (RFC 3550 appendix A.1, A.3
+// and A.8), for both Receiver Reports and ReceiverStats. A new SSRC starts
+// them over.
+pub struct ReceptionStatistics {
+    clock_start: Instant,
+    source: Option<Source>,
+    last_sender_report: Option<SenderReport>,
+    // The source's BYE, if it sent one.
+    goodbye: Option<Goodbye>
+}
+
+struct Source {
+    ssrc: u32,
+    base_seq: u16,
+    max_seq: u16,
+    // Sequence number wraps, shifted into the high 16 bits.
+    cycles: u32,
+    received: u64,
+    expected_prior: u64,
+    received_prior: u64,
+    // Relative transit time of the previous packet, in RTP units.
+    transit: Option<u32>,
+    // Jitter in RTP units, scaled by 16 as in appendix A.8.
+    jitter: u32,
+
+    out_of_order: u64,
+    duplicates: u64,
+    seen: SeenWindow,
+    bytes: u64,
+    bitrate: BitrateMeter
+}
+
+// Sequence numbers received among the last SEEN_WINDOW up to max_seq, one bit
+// each, to tell duplicates from late packets.
+const SEEN_WINDOW: u16 = 1024;
+
+struct SeenWindow([u64; SEEN_WINDOW as usize / 64]);
+
+impl SeenWindow {
+    // Marks `seq` received; false if it already was.
+    fn insert(&mut self, seq: u16) -> bool {
+        let (word, bit) = ((seq % SEEN_WINDOW) as usize / 64, seq % 64);
+        let fresh = self.0[word] & (1 << bit) == 0;
+        self.0[word] |= 1 << bit;
+        fresh
+    }
+
+    // Forgets the sequence numbers after `from` up to and including `to`,
+    // whose bits are about to be reused.
+    fn advance(&mut self, from: u16, to: u16) {
+        let distance = to.wrapping_sub(from);
+        if distance >= SEEN_WINDOW {
+            self.0 = [0; SEEN_WINDOW as usize / 64];
+            return;
+        }
+        for step in 1..=distance {
+            let seq = from.wrapping_add(step);
+            self.0[(seq % SEEN_WINDOW) as usize / 64] &= !(1 << (seq % 64));
+        }
+    }
+}
+
+impl ReceptionStatistics {
+    // Arrival times are measured from `clock_start`, on the receiver's
+    // clock.
+    pub fn new(clock_start: Instant) -> Self {
+        Self {
+            clock_start,
+            source: None,
+            last_sender_report: None,
+            goodbye: None
+        }
+    }
+
+    pub fn clock_start(&self) -> Instant {
+        self.clock_start
+    }
+
+    // Returns false for a repeat of a packet among the last SEEN_WINDOW, so
+    // the caller can drop it before the jitter buffer counts it as late.
+    pub fn on_packet(&mut self, packet: &RtpPacket, len: usize, arrival: Instant) -> bool {
+        if self.source.as_ref().is_some_and(|source| source.ssrc != packet.ssrc) {
+            self.goodbye = None;
+        }
+        let source = match self.source.as_mut() {
+            Some(source) if source.ssrc == packet.ssrc => source,
+            _ => self.source.insert(Source {
+                ssrc: packet.ssrc,
+                base_seq: packet.seq,
+                max_seq: packet.seq,
+                cycles: 0,
+                received: 0,
+                expected_prior: 0,
+                received_prior: 0,
+                transit: None,
+                jitter: 0,
+                out_of_order: 0,
+                duplicates: 0,
+                seen: SeenWindow([0; SEEN_WINDOW as usize / 64]),
+                bytes: 0,
+                bitrate: BitrateMeter::new(DEFAULT_BITRATE_WINDOW)
+            })
+        };
+
+        let delta = packet.seq.wrapping_sub(source.max_seq);
+        if delta < 0x8000 {
+            if packet.seq < source.max_seq {
+                source.cycles = source.cycles.wrapping_add(1 << 16);
+            }
+            source.seen.advance(source.max_seq, packet.seq);
+            source.max_seq = packet.seq;
+        }
+        // Older than SEEN_WINDOW, a packet can only be counted as late.
+        let duplicate = source.max_seq.wrapping_sub(packet.seq) < SEEN_WINDOW && !source.seen.insert(packet.seq);
+        if duplicate {
+            source.duplicates += 1;
+        } else if delta >= 0x8000 {
+            source.out_of_order += 1;
+        }
+        source.received += 1;
+        source.bytes += len as u64;
+        source.bitrate.record(len, arrival);
+
+        // Arrival time on the media clock; only differences matter.
+        let arrival = MediaTimestamp::from_duration(arrival.duration_since(self.clock_start), VIDEO_CLOCK_RATE);
+        let transit = arrival.wire().wrapping_sub(packet.ts);
+        if let Some(previous) = source.transit {
+            let d = (transit.wrapping_sub(previous) as i32).unsigned_abs();
+            // J += (|D| - J) / 16, kept scaled by 16.
+            source.jitter = source.jitter.wrapping_add(d).wrapping_sub((source.jitter + 8) >> 4);
+        }
+        source.transit = Some(transit);
+        !duplicate
+    }
+
+    pub fn stats(&self, now: Instant) -> ReceiverStats {
+        let source = match self.source.as_ref() {
+            Some(source) => source,
+            None => return ReceiverStats::default()
+        };
+        let expected = source.expected();
+        let unique = source.received - source.duplicates;
+        let jitter_units = (source.jitter >> 4) as u64;
+        ReceiverStats {
+            packets_received: source.received,
+            packets_lost: expected.saturating_sub(unique),
+            out_of_order: source.out_of_order,
+            duplicates: source.duplicates,
+            jitter: Duration::from_nanos(jitter_units * 1_000_000_000 / VIDEO_CLOCK_RATE as u64),
+            bytes_received: source.bytes,
+            bitrate: source.bitrate.bits_per_second(now),
+            reordered: 0,
+            late: 0,
+            srtp_auth_failures: 0,
+            crc_mismatches: 0,
+            end_to_end_latency: None,
+            jitter_buffer_delay: None
+        }
+    }
+
+    pub fn on_sender_report(&mut self, report: SenderReport) {
+        if self.source.as_ref().is_none_or(|source| source.ssrc == report.ssrc) {
+            self.last_sender_report = Some(report);
+        }
+    }
+
+    pub fn last_sender_report(&self) -> Option<SenderReport> {
+        self.last_sender_report
+    }
+
+    // The SSRC a BYE must name to end the current source.
+    pub fn source_ssrc(&self) -> Option<u32> {
+        self.source.as_ref().map(|source| source.ssrc)
+    }
+
+    pub fn on_goodbye(&mut self, goodbye: Goodbye) {
+        self.goodbye = Some(goodbye);
+    }
+
+    pub fn goodbye(&self) -> Option<&Goodbye> {
+        self.goodbye.as_ref()
+    }
+
+    // The report block for the next RR, None before the first packet. Moves
+    // the interval the fraction lost is measured over.
+    pub fn report(&mut self, reporter_ssrc: u32, now: Instant) -> Option<ReceptionReport> {
+        let source = self.source.as_mut()?;
+        let highest_sequence = source.highest_sequence();
+        let expected = source.expected();
+        // Duplicates count as received here, as RFC 3550 section 6.4.1
+        // specifies.
+        let cumulative_lost = expected as i64 - source.received as i64;
+
+        let expected_interval = expected - source.expected_prior;
+        let received_interval = source.received - source.received_prior;
+        source.expected_prior = expected;
+        source.received_prior = source.received;
+        let lost_interval = expected_interval as i64 - received_interval as i64;
+        let fraction_lost = if expected_interval == 0 || lost_interval <= 0 {
+            0
+        } else {
+            ((lost_interval << 8) / expected_interval as i64) as u8
+        };
+
+        // LSR is the middle of the SR's NTP timestamp.
+        let (last_sender_report, delay_since_last_sender_report) = match self.last_sender_report {
+            Some(report) => {
+                let delay = now.saturating_duration_since(report.arrival);
+                (report.ntp.short(), (delay.as_secs_f64() * 65536.0) as u32)
+            }
+            None => (0, 0)
+        };
+
+        Some(ReceptionReport {
+            reporter_ssrc,
+            source_ssrc: source.ssrc,
+            fraction_lost,
+            cumulative_lost: cumulative_lost.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            highest_sequence,
+            jitter: source.jitter >> 4,
+            last_sender_report,
+            delay_since_last_sender_report,
+            round_trip_time: None
+        })
+    }
+}
+
+impl Source {
+    // Extended highest sequence number: wraps in the high 16 bits.
+    fn highest_sequence(&self) -> u32 {
+        self.cycles | self.max_seq as u32
+    }
+
+    fn expected(&self) -> u64 {
+        (self.highest_sequence() as u64).wrapping_sub(self.base_seq as u64) + 1
+    }
+}
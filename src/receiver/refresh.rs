@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::RefreshPolicy;
+use crate::stats::{KeyframeRequest, RefreshReason};
+
+// Requests kept for keyframe_requests; older ones are dropped first.
+const MAX_EVENTS: usize = 1024;
+
+// Decides when the receiver asks for a keyframe: when too many recent
+// frames were incomplete or a reference frame lost packets. Once asked, it
+// asks again after the backoff, doubled each time, until a whole IDR frame
+// arrives or the frames come whole again.
+pub struct KeyframeRefresh {
+    policy: RefreshPolicy,
+    // When each frame of the window was finished, and whether it was
+    // incomplete.
+    frames: VecDeque<(Instant, bool)>,
+    // A reference frame lost packets since the last keyframe.
+    reference_lost: bool,
+    // When the last request went out and the wait before the next one.
+    pending: Option<(Instant, Duration)>,
+    attempt: u32,
+    events: VecDeque<KeyframeRequest>
+}
+
+impl KeyframeRefresh {
+    pub fn new(policy: RefreshPolicy) -> Self {
+        Self {
+            policy,
+            frames: VecDeque::new(),
+            reference_lost: false,
+            pending: None,
+            attempt: 0,
+            events: VecDeque::new()
+        }
+    }
+
+    pub fn on_frame(&mut self, at: Instant, idr: bool, incomplete: bool, reference_loss: bool) {
+        if idr && !incomplete {
+            // What the decoder needed: everything before it is moot.
+            self.frames.clear();
+            self.reference_lost = false;
+            self.disarm();
+        }
+        self.frames.push_back((at, incomplete));
+        self.reference_lost |= reference_loss && self.policy.on_reference_loss;
+    }
+
+    // The reason to ask for a keyframe now, if one is due; the request is
+    // counted as made.
+    pub fn poll(&mut self, now: Instant) -> Option<RefreshReason> {
+        while self.frames.front().is_some_and(|&(at, _)| now.saturating_duration_since(at) > self.policy.window) {
+            self.frames.pop_front();
+        }
+        let incomplete = self.frames.iter().filter(|&&(_, incomplete)| incomplete).count();
+        let reason = if self.reference_lost {
+            RefreshReason::ReferenceLoss
+        } else if incomplete * 100 > self.policy.incomplete_percent as usize * self.frames.len() {
+            RefreshReason::IncompleteFrames {
+                incomplete: incomplete as u32,
+                frames: self.frames.len() as u32
+            }
+        } else {
+            self.disarm();
+            return None;
+        };
+        let backoff = match self.pending {
+            None => self.policy.initial_backoff,
+            Some((sent, backoff)) if now.saturating_duration_since(sent) >= backoff => (backoff * 2).min(self.policy.max_backoff),
+            Some(_) => return None
+        };
+        self.pending = Some((now, backoff));
+        self.attempt += 1;
+        Some(reason)
+    }
+
+    pub fn record(&mut self, at: Instant, reason: RefreshReason, sent: bool) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(KeyframeRequest {
+            at,
+            reason,
+            attempt: self.attempt,
+            sent
+        });
+    }
+
+    pub fn take_events(&mut self) -> Vec<KeyframeRequest> {
+        self.events.drain(..).collect()
+    }
+
+    fn disarm(&mut self) {
+        self.pending = None;
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn policy() -> RefreshPolicy {
+        RefreshPolicy {
+            incomplete_percent: 20,
+            window: Duration::from_secs(1),
+            on_reference_loss: false,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(2)
+        }
+    }
+
+    // Plays one frame per character, FRAME_INTERVAL apart: '.' whole, 'x'
+    // incomplete, 'r' incomplete with a reference lost, 'I' a whole IDR.
+    // Returns the frame indices at which a request was due.
+    fn play(refresh: &mut KeyframeRefresh, start: Instant, pattern: &str) -> Vec<usize> {
+        let mut requests = Vec::new();
+        for (index, frame) in pattern.chars().enumerate() {
+            let at = start + FRAME_INTERVAL * index as u32;
+            refresh.on_frame(at, frame == 'I', matches!(frame, 'x' | 'r'), frame == 'r');
+            if refresh.poll(at).is_some() {
+                requests.push(index);
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn a_share_of_incomplete_frames_over_the_threshold_asks() {
+        let mut refresh = KeyframeRefresh::new(policy());
+        // Two of ten frames is 20%, not over it; the third one is.
+        assert_eq!(play(&mut refresh, Instant::now(), "....x....x....x"), [14]);
+    }
+
+    #[test]
+    fn requests_back_off_until_a_keyframe_arrives() {
+        let mut refresh = KeyframeRefresh::new(policy());
+        let start = Instant::now();
+        // Every frame is incomplete: asked at once, then after 500 ms,
+        // 1 s, and 2 s at most from then on. Frames are 100 ms apart.
+        let requests = play(&mut refresh, start, &"x".repeat(80));
+        assert_eq!(requests, [0, 5, 15, 35, 55, 75]);
+        // A whole IDR disarms it; the next trouble starts from scratch.
+        refresh.on_frame(start + FRAME_INTERVAL * 80, true, false, false);
+        assert_eq!(refresh.poll(start + FRAME_INTERVAL * 80), None);
+        assert_eq!(refresh.attempt, 0);
+    }
+
+    #[test]
+    fn whole_frames_disarm_it_without_a_keyframe() {
+        let mut refresh = KeyframeRefresh::new(policy());
+        let start = Instant::now();
+        assert_eq!(play(&mut refresh, start, "x"), [0]);
+        // Four whole frames bring the share down to 20%: no more requests,
+        // and the next one counts as the first attempt again.
+        assert_eq!(play(&mut refresh, start + FRAME_INTERVAL, &".".repeat(20)), []);
+        assert_eq!(refresh.attempt, 0);
+        assert!(refresh.pending.is_none());
+    }
+
+    #[test]
+    fn a_lost_reference_asks_until_a_keyframe() {
+        let mut refresh = KeyframeRefresh::new(RefreshPolicy { on_reference_loss: true, ..policy() });
+        let start = Instant::now();
+        // One damaged reference among whole frames is enough, and stays
+        // enough while no IDR comes.
+        let pattern = format!("{}r{}", ".".repeat(9), ".".repeat(9));
+        assert_eq!(play(&mut refresh, start, &pattern), [9, 14]);
+        assert_eq!(play(&mut refresh, start + FRAME_INTERVAL * 19, "I....."), []);
+        // Without it, one incomplete frame in ten stays under 20%.
+        let mut refresh = KeyframeRefresh::new(policy());
+        assert_eq!(play(&mut refresh, start, &pattern), []);
+    }
+}
@@ -0,0 +1,185 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+
+use super::RtcpRelayMode;
+use crate::rtcp;
+use crate::rtp::RtpPacket;
+use crate::sender::RtpSink;
+use crate::stats::RelayStats;
+
+// Offset of the SSRC in an RTP header.
+const SSRC_OFFSET: usize = 8;
+
+// Forwards datagrams as they are read, with the SSRC rewritten if asked.
+// Failures are counted and never reach the receive path.
+pub struct Relay {
+    sink: Box<dyn RtpSink>,
+    stats: RelayStats,
+    // Put in place of the origin's SSRC, which is the one last relayed.
+    ssrc: Option<u32>,
+    origin_ssrc: Option<u32>,
+    // Payload bytes relayed, for regenerated Sender Reports.
+    payload_bytes: u64,
+    // Reused for datagrams whose SSRC is rewritten.
+    scratch: Vec<u8>,
+    rtcp: Option<RtcpRelay>
+}
+
+// RTCP between the origin, whose packets come in on the receiver's RTCP
+// socket, and the receivers behind the relay, on a socket of its own.
+pub struct RtcpRelay {
+    socket: UdpSocket,
+    downstream: SocketAddr,
+    to_receivers: RtcpRelayMode,
+    to_origin: RtcpRelayMode
+}
+
+impl RtcpRelay {
+    pub fn new(socket: UdpSocket, downstream: SocketAddr, to_receivers: RtcpRelayMode, to_origin: RtcpRelayMode) -> Self {
+        Self {
+            socket,
+            downstream,
+            to_receivers,
+            to_origin
+        }
+    }
+}
+
+impl Relay {
+    pub fn new(sink: Box<dyn RtpSink>) -> Self {
+        Self {
+            sink,
+            stats: RelayStats::default(),
+            ssrc: None,
+            origin_ssrc: None,
+            payload_bytes: 0,
+            scratch: Vec::new(),
+            rtcp: None
+        }
+    }
+
+    pub fn set_ssrc(&mut self, ssrc: Option<u32>) {
+        self.ssrc = ssrc;
+    }
+
+    pub fn set_rtcp(&mut self, rtcp: Option<RtcpRelay>) {
+        self.rtcp = rtcp;
+    }
+
+    pub fn rtcp_local_addr(&self) -> Option<SocketAddr> {
+        self.rtcp.as_ref().and_then(|rtcp| rtcp.socket.local_addr().ok())
+    }
+
+    pub fn forward(&mut self, datagram: &[u8]) {
+        if let Some(packet) = RtpPacket::parse(datagram) {
+            self.origin_ssrc = Some(packet.ssrc);
+            self.payload_bytes += packet.payload.len() as u64;
+        }
+        let sent = match self.ssrc.filter(|_| datagram.len() >= SSRC_OFFSET + 4) {
+            None => self.sink.send_packet(datagram),
+            Some(ssrc) => {
+                self.scratch.clear();
+                self.scratch.extend_from_slice(datagram);
+                self.scratch[SSRC_OFFSET..SSRC_OFFSET + 4].copy_from_slice(&ssrc.to_be_bytes());
+                self.sink.send_packet(&self.scratch)
+            }
+        };
+        match sent {
+            Ok(_) => {
+                self.stats.packets_relayed += 1;
+                self.stats.bytes_relayed += datagram.len() as u64;
+            }
+            Err(err) => {
+                self.stats.send_errors += 1;
+                self.stats.last_error = Some(err.to_string());
+            }
+        }
+    }
+
+    // Passes a compound packet from the origin on to the receivers behind
+    // the relay, or answers it with a Sender Report of the relay's own.
+    pub fn on_origin_rtcp(&mut self, compound: &[u8], arrival: Instant) {
+        let rtcp = match self.rtcp.as_ref() {
+            Some(rtcp) => rtcp,
+            None => return
+        };
+        let sender_report = rtcp::find_sender_report(compound, arrival);
+        // Before the first RTP packet, the origin is whoever sends SRs.
+        let origin = self.origin_ssrc.or(sender_report.map(|report| report.ssrc));
+        let relayed = self.ssrc;
+        let packet = match rtcp.to_receivers {
+            RtcpRelayMode::PassThrough => rtcp::map_ssrcs(compound, |ssrc| match relayed {
+                Some(relayed) if origin == Some(ssrc) => relayed,
+                _ => ssrc
+            }),
+            // The origin's mapping of RTP to NTP time holds for the relayed
+            // stream, its timestamps being untouched; the counts are the
+            // relay's.
+            RtcpRelayMode::Regenerate => sender_report.map(|report| {
+                let ssrc = relayed.unwrap_or(report.ssrc);
+                rtcp::sender_report(
+                    ssrc,
+                    report.ntp,
+                    report.rtp_timestamp,
+                    self.stats.packets_relayed as u32,
+                    self.payload_bytes as u32,
+                    &format!("{:08x}@rtp-transceive", ssrc),
+                )
+            })
+        };
+        self.send_downstream(packet);
+    }
+
+    // Compound packets from the receivers behind the relay for the origin,
+    // with the relay's SSRC put back to the origin's. None are forwarded
+    // when the relay reports to the origin itself.
+    pub fn take_downstream_rtcp(&mut self) -> Vec<Vec<u8>> {
+        let rtcp = match self.rtcp.as_ref() {
+            Some(rtcp) => rtcp,
+            None => return Vec::new()
+        };
+        let (origin, relayed) = (self.origin_ssrc, self.ssrc);
+        let mut buffer = [0u8; 1500];
+        let mut compounds = Vec::new();
+        while let Ok(len) = rtcp.socket.recv(&mut buffer) {
+            if rtcp.to_origin == RtcpRelayMode::Regenerate {
+                self.stats.rtcp_terminated += 1;
+                continue;
+            }
+            let mapped = rtcp::map_ssrcs(&buffer[..len], |ssrc| match (relayed, origin) {
+                (Some(relayed), Some(origin)) if ssrc == relayed => origin,
+                _ => ssrc
+            });
+            match mapped {
+                Some(compound) => compounds.push(compound),
+                None => self.stats.rtcp_malformed += 1
+            }
+        }
+        self.stats.rtcp_to_origin += compounds.len() as u64;
+        compounds
+    }
+
+    fn send_downstream(&mut self, packet: Option<Vec<u8>>) {
+        let rtcp = match self.rtcp.as_ref() {
+            Some(rtcp) => rtcp,
+            None => return
+        };
+        match packet {
+            Some(packet) => match rtcp.socket.send_to(&packet, rtcp.downstream) {
+                Ok(_) => self.stats.rtcp_to_receivers += 1,
+                Err(err) => {
+                    self.stats.send_errors += 1;
+                    self.stats.last_error = Some(err.to_string());
+                }
+            },
+            // Regenerating, a compound without a Sender Report has nothing
+            // to pass on.
+            None if rtcp.to_receivers == RtcpRelayMode::Regenerate => self.stats.rtcp_terminated += 1,
+            None => self.stats.rtcp_malformed += 1
+        }
+    }
+
+    pub fn stats(&self) -> RelayStats {
+        self.stats.clone()
+    }
+}
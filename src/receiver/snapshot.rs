@@ -0,0 +1,121 @@
+use std::time::Instant;
+
+use super::KeyframeSnapshot;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+
+// Keeps the last whole IDR frame as a standalone Annex B blob. Parameter
+// sets the frame does not carry itself are taken from earlier frames; an
+// IDR frame before any SPS or PPS was seen makes no snapshot.
+#[derive(Default)]
+pub struct KeyframeSnapshots {
+    // The last SPS and PPS seen, start codes included.
+    sps: Vec<u8>,
+    pps: Vec<u8>,
+    latest: Option<KeyframeSnapshot>
+}
+
+impl KeyframeSnapshots {
+    // `nals` are the NAL units of a frame, without start codes. Returns
+    // the new snapshot if the frame made one.
+    pub fn on_frame<'n>(
+        &mut self,
+        nals: impl Iterator<Item = &'n [u8]> + Clone,
+        timestamp: u32,
+        received_at: Instant,
+        whole_idr: bool,
+    ) -> Option<&KeyframeSnapshot> {
+        let (mut has_sps, mut has_pps) = (false, false);
+        for nal in nals.clone() {
+            let stored = match nal[0] & 0x1F {
+                NAL_TYPE_SPS => {
+                    has_sps = true;
+                    &mut self.sps
+                }
+                NAL_TYPE_PPS => {
+                    has_pps = true;
+                    &mut self.pps
+                }
+                _ => continue
+            };
+            stored.clear();
+            stored.extend_from_slice(&START_CODE);
+            stored.extend_from_slice(nal);
+        }
+        if !whole_idr || self.sps.is_empty() || self.pps.is_empty() {
+            return None;
+        }
+
+        // The blob of the snapshot it replaces holds the new one.
+        let mut annexb = self.latest.take().map(|snapshot| snapshot.annexb).unwrap_or_default();
+        annexb.clear();
+        if !has_sps {
+            annexb.extend_from_slice(&self.sps);
+        }
+        if !has_pps {
+            annexb.extend_from_slice(&self.pps);
+        }
+        for nal in nals {
+            annexb.extend_from_slice(&START_CODE);
+            annexb.extend_from_slice(nal);
+        }
+        self.latest = Some(KeyframeSnapshot {
+            annexb,
+            timestamp,
+            received_at
+        });
+        self.latest.as_ref()
+    }
+
+    pub fn latest(&self) -> Option<&KeyframeSnapshot> {
+        self.latest.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 3] = [0x67, 0x42, 0x1E];
+    const PPS: [u8; 2] = [0x68, 0xCE];
+    const IDR: [u8; 3] = [0x65, 0x88, 0x84];
+    const SLICE: [u8; 3] = [0x41, 0x9A, 0x02];
+
+    fn frame(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter().flat_map(|nal| START_CODE.iter().chain(nal.iter()).copied()).collect()
+    }
+
+    fn push(snapshots: &mut KeyframeSnapshots, nals: &[&[u8]], timestamp: u32, whole_idr: bool) -> bool {
+        snapshots.on_frame(nals.iter().copied(), timestamp, Instant::now(), whole_idr).is_some()
+    }
+
+    #[test]
+    fn an_idr_frame_with_its_parameter_sets_is_kept_as_it_came() {
+        let mut snapshots = KeyframeSnapshots::default();
+        assert!(push(&mut snapshots, &[&SPS, &PPS, &IDR], 3000, true));
+        assert!(!push(&mut snapshots, &[&SLICE], 6000, false));
+        let latest = snapshots.latest().unwrap();
+        assert_eq!(latest.annexb, frame(&[&SPS, &PPS, &IDR]));
+        assert_eq!(latest.timestamp, 3000);
+    }
+
+    #[test]
+    fn parameter_sets_sent_apart_are_put_in_front() {
+        let mut snapshots = KeyframeSnapshots::default();
+        // Nothing to decode the first IDR with yet.
+        assert!(!push(&mut snapshots, &[&IDR], 0, true));
+        assert!(!push(&mut snapshots, &[&SPS, &PPS], 3000, false));
+        assert!(push(&mut snapshots, &[&IDR], 3000, true));
+        assert_eq!(snapshots.latest().unwrap().annexb, frame(&[&SPS, &PPS, &IDR]));
+    }
+
+    #[test]
+    fn an_incomplete_idr_frame_keeps_the_last_snapshot() {
+        let mut snapshots = KeyframeSnapshots::default();
+        assert!(push(&mut snapshots, &[&SPS, &PPS, &IDR], 0, true));
+        assert!(!push(&mut snapshots, &[&SPS, &PPS, &IDR, &IDR], 90_000, false));
+        assert_eq!(snapshots.latest().unwrap().timestamp, 0);
+    }
+}
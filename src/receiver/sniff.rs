@@ -0,0 +1,132 @@
+use super::Codec;
+
+// Packets looked at before deciding; the stream is not held up meanwhile.
+pub const SNIFF_PACKETS: u32 = 8;
+
+// Tells H.264 from H.265 payloads by their NAL headers. Each codec's
+// headers read as the other's are mostly invalid: an H.265 header's type
+// sits one bit up, so as H.264 it becomes a data partition, a reserved
+// type or an SEI with a non-zero NRI, and an H.264 header as H.265 has a
+// type above 40 or a non-zero layer id. Packets that pass as only one
+// codec are counted for it.
+#[derive(Default)]
+pub struct CodecSniffer {
+    packets: u32,
+    h264: u32,
+    h265: u32
+}
+
+impl CodecSniffer {
+    // Takes one more payload. After SNIFF_PACKETS returns the codec they
+    // mostly look like, once; None before and after.
+    pub fn on_payload(&mut self, payload: &[u8]) -> Option<Codec> {
+        if self.packets >= SNIFF_PACKETS {
+            return None;
+        }
+        self.packets += 1;
+        match (looks_h264(payload), looks_h265(payload)) {
+            (true, false) => self.h264 += 1,
+            (false, true) => self.h265 += 1,
+            _ => {}
+        }
+        if self.packets < SNIFF_PACKETS {
+            return None;
+        }
+        Some(if self.h265 > self.h264 { Codec::H265 } else { Codec::H264 })
+    }
+}
+
+fn looks_h264(payload: &[u8]) -> bool {
+    let header = match payload.first() {
+        Some(&header) if header & 0x80 == 0 => header,
+        _ => return false
+    };
+    let nri = header & 0x60;
+    match header & 0x1F {
+        1 => true,
+        5 | 7 | 8 => nri != 0,
+        // SEI, AUD, end of sequence and stream, filler: NRI 0 (7.4.1).
+        6 | 9..=12 => nri == 0,
+        24 => payload.len() > 3,
+        // FU-A: the FU header names a single NAL type, and a fragment
+        // never starts and ends a NAL at once.
+        28 => payload.get(1).is_some_and(|&fu| matches!(fu & 0x1F, 1..=23) && fu & 0xC0 != 0xC0),
+        // Data partitions are not in the profiles in use. The rest is
+        // reserved, or not sent by anyone.
+        _ => false
+    }
+}
+
+fn looks_h265(payload: &[u8]) -> bool {
+    if payload.len() < 3 || payload[0] & 0x80 != 0 {
+        return false;
+    }
+    let layer_id = ((payload[0] & 1) << 5) | (payload[1] >> 3);
+    let temporal_id_plus1 = payload[1] & 0x07;
+    if layer_id != 0 || temporal_id_plus1 == 0 {
+        return false;
+    }
+    match (payload[0] >> 1) & 0x3F {
+        // AP, and FU fragmenting a slice or parameter set.
+        48 => true,
+        49 => is_h265_nal_type(payload[2] & 0x3F) && payload[2] & 0xC0 != 0xC0,
+        nal_type => is_h265_nal_type(nal_type)
+    }
+}
+
+// Slices, IRAP pictures, VPS/SPS/PPS, AUD, end of sequence and stream,
+// filler and SEI (RFC 7798 section 1.1.4).
+fn is_h265_nal_type(nal_type: u8) -> bool {
+    matches!(nal_type, 0..=9 | 16..=21 | 32..=40)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniff(payloads: &[&[u8]]) -> Option<Codec> {
+        let mut sniffer = CodecSniffer::default();
+        let mut detected = None;
+        for _ in 0..SNIFF_PACKETS {
+            for payload in payloads {
+                detected = detected.or(sniffer.on_payload(payload));
+            }
+        }
+        detected
+    }
+
+    #[test]
+    fn h264_headers_are_told_apart() {
+        // IDR, P slice, SPS, PPS, STAP-A and an FU-A start.
+        let h264: [&[u8]; 6] =
+            [&[0x65, 0x88, 0x84], &[0x41, 0x9A, 0x02], &[0x67, 0x42, 0xC0], &[0x68, 0xCE, 0x3C], &[0x78, 0, 4, 0x67], &[0x7C, 0x85, 0x88]];
+        for payload in h264 {
+            assert!(looks_h264(payload) && !looks_h265(payload), "{:02x?}", payload);
+        }
+        // An SEI also reads as an H.265 slice header, so it counts for
+        // neither.
+        let sei: &[u8] = &[0x06, 0x05, 0x10];
+        assert!(looks_h264(sei) && looks_h265(sei));
+        assert_eq!(sniff(&[&h264[..], &[sei]].concat()), Some(Codec::H264));
+    }
+
+    #[test]
+    fn h265_headers_are_told_apart() {
+        // VPS, SPS, PPS, IDR_W_RADL, TRAIL_R, an AP and an FU start.
+        let h265: [&[u8]; 7] = [&[0x40, 0x01, 0x0C], &[0x42, 0x01, 0x01], &[0x44, 0x01, 0xC1], &[0x26, 0x01, 0xAF], &[0x02, 0x01, 0xD0], &[0x60, 0x01, 0x00], &[0x62, 0x01, 0x93]];
+        assert_eq!(sniff(&h265), Some(Codec::H265));
+        for payload in h265 {
+            assert!(looks_h265(payload) && !looks_h264(payload), "{:02x?}", payload);
+        }
+    }
+
+    #[test]
+    fn the_answer_comes_once_after_the_bound() {
+        let mut sniffer = CodecSniffer::default();
+        for _ in 1..SNIFF_PACKETS {
+            assert_eq!(sniffer.on_payload(&[0x26, 0x01, 0xAF]), None);
+        }
+        assert_eq!(sniffer.on_payload(&[0x26, 0x01, 0xAF]), Some(Codec::H265));
+        assert_eq!(sniffer.on_payload(&[0x26, 0x01, 0xAF]), None);
+    }
+}
@@ -0,0 +1,15 @@
+use std::io;
+use std::time::Duration;
+
+/// Where a receiver's RTP packets come from instead of its UDP socket,
+/// e.g. a recorded capture (`capture::PcapSource`) or a decrypting layer
+/// over another transport. See `H264RtpReceiver::from_source`.
+pub trait RtpSource: Send {
+    /// Copies the next RTP packet into `out` (large enough for any UDP
+    /// payload) and returns its length, like `UdpSocket::recv`. Waits at
+    /// most `timeout`, or as long as it takes for `None`; the receiver
+    /// passes its idle timeout, or less while the jitter buffer holds
+    /// packets. `WouldBlock` or `TimedOut` means nothing arrived in time,
+    /// `UnexpectedEof` that nothing more will.
+    fn recv_packet(&mut self, out: &mut [u8], timeout: Option<Duration>) -> io::Result<usize>;
+}
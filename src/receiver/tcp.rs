@@ -0,0 +1,55 @@
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use crate::framing::{Deframer, TcpFraming};
+
+// Bytes read from the connection at a time.
+const READ_SIZE: usize = 16 * 1024;
+
+// RTP packets arriving over TCP instead of the UDP socket.
+pub struct TcpSource {
+    stream: TcpStream,
+    deframer: Deframer,
+    read_buffer: Vec<u8>
+}
+
+impl TcpSource {
+    pub fn new(stream: TcpStream, framing: TcpFraming) -> Self {
+        Self {
+            stream,
+            deframer: Deframer::new(framing),
+            read_buffer: vec![0u8; READ_SIZE]
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.stream.read_timeout()
+    }
+
+    // (local, peer) addresses of the connection.
+    pub fn addresses(&self) -> io::Result<(SocketAddr, SocketAddr)> {
+        Ok((self.stream.local_addr()?, self.stream.peer_addr()?))
+    }
+
+    // Copies the next packet into `out` and returns its length, like
+    // UdpSocket::recv. A packet cut short by a read timeout stays buffered
+    // for the next call. The connection closing is UnexpectedEof.
+    pub fn recv(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(packet) = self.deframer.next_packet() {
+                out[..packet.len()].copy_from_slice(packet);
+                return Ok(packet.len());
+            }
+            let len = self.stream.read(&mut self.read_buffer)?;
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.deframer.push(&self.read_buffer[..len]);
+        }
+    }
+}
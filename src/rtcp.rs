@@ -0,0 +1,512 @@
+// RTCP packets (RFC 3550 section 6).
+use std::time::{Duration, Instant};
+
+use crate::stats::{Goodbye, MetricsSummary, ReceptionReport, SenderReport};
+use crate::time::NtpTimestamp;
+
+const PACKET_TYPE_SR: u8 = 200;
+const PACKET_TYPE_RR: u8 = 201;
+const PACKET_TYPE_SDES: u8 = 202;
+const PACKET_TYPE_BYE: u8 = 203;
+const PACKET_TYPE_APP: u8 = 204;
+const PACKET_TYPE_RTPFB: u8 = 205;
+const PACKET_TYPE_PSFB: u8 = 206;
+const PACKET_TYPE_XR: u8 = 207;
+const XR_VOIP_METRICS: u8 = 7;
+// "Unavailable" for the signal, noise, echo, R factor and MOS fields of the
+// VoIP Metrics block, none of which a video receiver measures.
+const XR_UNAVAILABLE: u8 = 127;
+// RX config: a non-adaptive jitter buffer.
+const XR_JITTER_BUFFER_FIXED: u8 = 0b10 << 4;
+const SDES_CNAME: u8 = 1;
+// FMT of a Generic NACK, RFC 4585 section 6.2.1.
+const FMT_GENERIC_NACK: u8 = 1;
+// FMT of a Picture Loss Indication, RFC 4585 section 6.3.1.
+const FMT_PLI: u8 = 1;
+
+// Compound packet of a Sender Report without report blocks, followed by an
+// SDES chunk with the CNAME, as section 6.1 requires.
+pub(crate) fn sender_report(
+    ssrc: u32,
+    ntp: NtpTimestamp,
+    rtp_timestamp: u32,
+    packet_count: u32,
+    octet_count: u32,
+    cname: &str,
+) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(28 + 12 + cname.len());
+    push_header(&mut packet, 0, PACKET_TYPE_SR, 6);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&ntp.0.to_be_bytes());
+    packet.extend_from_slice(&rtp_timestamp.to_be_bytes());
+    packet.extend_from_slice(&packet_count.to_be_bytes());
+    packet.extend_from_slice(&octet_count.to_be_bytes());
+    push_sdes(&mut packet, ssrc, cname);
+    packet
+}
+
+// Compound packet of a Receiver Report with one report block, followed by
+// the SDES CNAME chunk.
+pub(crate) fn receiver_report(ssrc: u32, report: &ReceptionReport, cname: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(32 + 12 + cname.len());
+    push_header(&mut packet, 1, PACKET_TYPE_RR, 7);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&report.source_ssrc.to_be_bytes());
+    // Cumulative lost is a 24-bit signed value.
+    let lost = report.cumulative_lost.clamp(-0x80_0000, 0x7F_FFFF) as u32 & 0xFF_FFFF;
+    packet.extend_from_slice(&(((report.fraction_lost as u32) << 24) | lost).to_be_bytes());
+    packet.extend_from_slice(&report.highest_sequence.to_be_bytes());
+    packet.extend_from_slice(&report.jitter.to_be_bytes());
+    packet.extend_from_slice(&report.last_sender_report.to_be_bytes());
+    packet.extend_from_slice(&report.delay_since_last_sender_report.to_be_bytes());
+    push_sdes(&mut packet, ssrc, cname);
+    packet
+}
+
+// An XR packet (RFC 3611) from `ssrc` with a VoIP Metrics block about the
+// summary's source, to append to a compound packet. Delays the receiver
+// cannot tell are 0.
+pub(crate) fn voip_metrics(ssrc: u32, summary: &MetricsSummary) -> Vec<u8> {
+    let millis = |duration: Duration| duration.as_millis().min(u16::MAX as u128) as u16;
+    let mut packet = Vec::with_capacity(8 + 36);
+    push_header(&mut packet, 0, PACKET_TYPE_XR, 10);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&[XR_VOIP_METRICS, 0, 0, 8]);
+    packet.extend_from_slice(&summary.source_ssrc.unwrap_or(0).to_be_bytes());
+    packet.extend_from_slice(&[summary.loss_rate(), summary.discard_rate(), summary.burst_density(), summary.gap_density()]);
+    packet.extend_from_slice(&millis(summary.mean_burst_duration).to_be_bytes());
+    packet.extend_from_slice(&millis(summary.mean_gap_duration).to_be_bytes());
+    // Round trip and end system delay.
+    packet.extend_from_slice(&[0; 4]);
+    packet.extend_from_slice(&[XR_UNAVAILABLE, XR_UNAVAILABLE, XR_UNAVAILABLE, summary.gmin]);
+    packet.extend_from_slice(&[XR_UNAVAILABLE; 4]);
+    let jitter_buffer = summary.jitter_buffer_delay.map_or(0, millis);
+    let rx_config = if summary.jitter_buffer_delay.is_some() { XR_JITTER_BUFFER_FIXED } else { 0 };
+    packet.extend_from_slice(&[rx_config, 0]);
+    // Nominal, maximum and absolute maximum delay: one fixed value.
+    for _ in 0..3 {
+        packet.extend_from_slice(&jitter_buffer.to_be_bytes());
+    }
+    packet
+}
+
+// Compound packet announcing that `ssrc` leaves the session: an empty
+// Receiver Report (a compound packet must start with SR or RR), the SDES
+// CNAME chunk, then the BYE with the optional reason (section 6.6).
+pub(crate) fn goodbye(ssrc: u32, cname: &str, reason: Option<&str>) -> Vec<u8> {
+    let reason = reason.map_or(&[][..], |reason| &reason.as_bytes()[..reason.len().min(255)]);
+    let mut packet = Vec::with_capacity(8 + 12 + cname.len() + 8 + reason.len());
+    push_header(&mut packet, 0, PACKET_TYPE_RR, 1);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    push_sdes(&mut packet, ssrc, cname);
+
+    let reason_len = if reason.is_empty() { 0 } else { (1 + reason.len()).div_ceil(4) * 4 };
+    push_header(&mut packet, 1, PACKET_TYPE_BYE, (1 + reason_len / 4) as u16);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    if !reason.is_empty() {
+        let end = packet.len() + reason_len;
+        packet.push(reason.len() as u8);
+        packet.extend_from_slice(reason);
+        packet.resize(end, 0);
+    }
+    packet
+}
+
+// Compound packet asking `media_ssrc` for a keyframe: an empty Receiver
+// Report, the SDES CNAME chunk, then a PLI, which has no FCI.
+pub(crate) fn picture_loss(ssrc: u32, media_ssrc: u32, cname: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(8 + 12 + cname.len() + 12);
+    push_header(&mut packet, 0, PACKET_TYPE_RR, 1);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    push_sdes(&mut packet, ssrc, cname);
+    push_header(&mut packet, FMT_PLI, PACKET_TYPE_PSFB, 2);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&media_ssrc.to_be_bytes());
+    packet
+}
+
+// One chunk: SSRC, CNAME item, then the end-of-list null byte and padding up
+// to a 32-bit boundary.
+fn push_sdes(packet: &mut Vec<u8>, ssrc: u32, cname: &str) {
+    let cname = &cname.as_bytes()[..cname.len().min(255)];
+    let chunk_len = 4 + 2 + cname.len() + 1;
+    let padded_len = chunk_len.div_ceil(4) * 4;
+
+    push_header(packet, 1, PACKET_TYPE_SDES, (padded_len / 4) as u16);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.push(SDES_CNAME);
+    packet.push(cname.len() as u8);
+    packet.extend_from_slice(cname);
+    packet.resize(packet.len() + padded_len - chunk_len + 1, 0);
+}
+
+// `length` is the packet length in 32-bit words minus one.
+fn push_header(packet: &mut Vec<u8>, count: u8, packet_type: u8, length: u16) {
+    packet.push((2 << 6) | count);
+    packet.push(packet_type);
+    packet.extend_from_slice(&length.to_be_bytes());
+}
+
+// APP packet (section 6.7). `data` must be a whole number of 32-bit words.
+pub(crate) fn app_packet(subtype: u8, ssrc: u32, name: [u8; 4], data: &[u8]) -> Vec<u8> {
+    debug_assert!(data.len().is_multiple_of(4));
+    let mut packet = Vec::with_capacity(12 + data.len());
+    push_header(&mut packet, subtype & 0x1F, PACKET_TYPE_APP, (2 + data.len() / 4) as u16);
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&name);
+    packet.extend_from_slice(data);
+    packet
+}
+
+pub(crate) struct AppPacket<'a> {
+    pub subtype: u8,
+    pub ssrc: u32,
+    pub data: &'a [u8]
+}
+
+// The first APP packet called `name` in a (possibly compound) RTCP packet.
+pub(crate) fn find_app(compound: &[u8], name: [u8; 4]) -> Option<AppPacket<'_>> {
+    packets(compound)
+        .find(|packet| packet[1] == PACKET_TYPE_APP && packet.len() >= 12 && packet[8..12] == name)
+        .map(|packet| AppPacket {
+            subtype: packet[0] & 0x1F,
+            ssrc: read_u32(packet, 4),
+            data: &packet[12..]
+        })
+}
+
+// Sender info of the first SR in a compound packet, stamped with `arrival`.
+pub(crate) fn find_sender_report(compound: &[u8], arrival: Instant) -> Option<SenderReport> {
+    packets(compound)
+        .find(|packet| packet[1] == PACKET_TYPE_SR && packet.len() >= 28)
+        .map(|packet| SenderReport {
+            ssrc: read_u32(packet, 4),
+            ntp: NtpTimestamp(((read_u32(packet, 8) as u64) << 32) | read_u32(packet, 12) as u64),
+            rtp_timestamp: read_u32(packet, 16),
+            packet_count: read_u32(packet, 20),
+            octet_count: read_u32(packet, 24),
+            arrival
+        })
+}
+
+// The first BYE in a compound packet naming `ssrc`, with its reason.
+pub(crate) fn find_goodbye(compound: &[u8], ssrc: u32) -> Option<Goodbye> {
+    packets(compound).filter(|packet| packet[1] == PACKET_TYPE_BYE).find_map(|packet| {
+        let count = (packet[0] & 0x1F) as usize;
+        let ssrcs = packet.get(4..4 + 4 * count)?;
+        if !ssrcs.chunks_exact(4).any(|chunk| read_u32(chunk, 0) == ssrc) {
+            return None;
+        }
+        let reason = packet.get(4 + 4 * count..).and_then(|rest| {
+            let (&len, text) = rest.split_first()?;
+            text.get(..len as usize).map(|text| String::from_utf8_lossy(text).into_owned())
+        });
+        Some(Goodbye {
+            ssrc,
+            reason: reason.filter(|reason| !reason.is_empty())
+        })
+    })
+}
+
+// The Generic NACKs about `media_ssrc` in a compound packet, each as the
+// sequence numbers it asks for: the PID of every FCI entry, then one for
+// each bit set in its BLP.
+pub(crate) fn nacks(compound: &[u8], media_ssrc: u32) -> Vec<Vec<u16>> {
+    packets(compound)
+        .filter(|packet| {
+            packet[1] == PACKET_TYPE_RTPFB
+                && packet[0] & 0x1F == FMT_GENERIC_NACK
+                && packet.len() >= 16
+                && read_u32(packet, 8) == media_ssrc
+        })
+        .map(|packet| {
+            let mut sequences = Vec::new();
+            for entry in packet[12..].chunks_exact(4) {
+                let pid = u16::from_be_bytes([entry[0], entry[1]]);
+                let blp = u16::from_be_bytes([entry[2], entry[3]]);
+                sequences.push(pid);
+                sequences.extend((0..16).filter(|bit| blp & (1 << bit) != 0).map(|bit| pid.wrapping_add(bit + 1)));
+            }
+            sequences
+        })
+        .collect()
+}
+
+// PLIs about `media_ssrc` in a compound packet.
+pub(crate) fn picture_losses(compound: &[u8], media_ssrc: u32) -> usize {
+    packets(compound)
+        .filter(|packet| {
+            packet[1] == PACKET_TYPE_PSFB && packet[0] & 0x1F == FMT_PLI && packet.len() >= 12 && read_u32(packet, 8) == media_ssrc
+        })
+        .count()
+}
+
+// Report blocks of all SRs and RRs in a compound packet, with the reporter's
+// SSRC filled in. The round-trip time is left for the caller.
+pub(crate) fn report_blocks(compound: &[u8]) -> Vec<ReceptionReport> {
+    let mut reports = Vec::new();
+    for packet in packets(compound) {
+        let blocks_start = match packet[1] {
+            PACKET_TYPE_SR => 28,
+            PACKET_TYPE_RR => 8,
+            _ => continue
+        };
+        if packet.len() < 8 {
+            continue;
+        }
+        let reporter_ssrc = read_u32(packet, 4);
+        let count = (packet[0] & 0x1F) as usize;
+        let blocks = packet.get(blocks_start..).unwrap_or_default();
+        for block in blocks.chunks_exact(24).take(count) {
+            let lost = read_u32(block, 4);
+            reports.push(ReceptionReport {
+                reporter_ssrc,
+                source_ssrc: read_u32(block, 0),
+                fraction_lost: (lost >> 24) as u8,
+                // Sign-extend the 24-bit count.
+                cumulative_lost: ((lost << 8) as i32) >> 8,
+                highest_sequence: read_u32(block, 8),
+                jitter: read_u32(block, 12),
+                last_sender_report: read_u32(block, 16),
+                delay_since_last_sender_report: read_u32(block, 20),
+                round_trip_time: None
+            });
+        }
+    }
+    reports
+}
+
+// What one RTCP packet says about a member of the session.
+pub(crate) enum Membership {
+    // An SR (`sender`) or RR from the member.
+    Report { ssrc: u32, sender: bool },
+    Cname { ssrc: u32, cname: String },
+    App { ssrc: u32 },
+    Bye { ssrc: u32, reason: Option<String> }
+}
+
+// Everything a compound packet says about members, whatever their SSRC, in
+// packet order. Unknown packet types are skipped. A BYE names each of its
+// SSRCs with the one reason.
+pub(crate) fn memberships(compound: &[u8]) -> Vec<Membership> {
+    let mut memberships = Vec::new();
+    for packet in packets(compound) {
+        let count = (packet[0] & 0x1F) as usize;
+        match packet[1] {
+            PACKET_TYPE_SR | PACKET_TYPE_RR | PACKET_TYPE_APP if packet.len() >= 8 => {
+                let ssrc = read_u32(packet, 4);
+                memberships.push(match packet[1] {
+                    PACKET_TYPE_APP => Membership::App { ssrc },
+                    packet_type => Membership::Report {
+                        ssrc,
+                        sender: packet_type == PACKET_TYPE_SR
+                    }
+                });
+            }
+            PACKET_TYPE_SDES => push_cnames(&packet[4..], count, &mut memberships),
+            PACKET_TYPE_BYE => {
+                let ssrcs = match packet.get(4..4 + 4 * count) {
+                    Some(ssrcs) => ssrcs,
+                    None => continue
+                };
+                let reason = packet.get(4 + 4 * count..).and_then(|rest| {
+                    let (&len, text) = rest.split_first()?;
+                    text.get(..len as usize).map(|text| String::from_utf8_lossy(text).into_owned())
+                });
+                for chunk in ssrcs.chunks_exact(4) {
+                    memberships.push(Membership::Bye {
+                        ssrc: read_u32(chunk, 0),
+                        reason: reason.clone().filter(|reason| !reason.is_empty())
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    memberships
+}
+
+// The CNAMEs of `count` SDES chunks (section 6.5): each an SSRC, then
+// items up to a null byte, padded to 32 bits. Stops at a malformed chunk.
+fn push_cnames(mut chunks: &[u8], count: usize, memberships: &mut Vec<Membership>) {
+    for _ in 0..count {
+        if chunks.len() < 4 {
+            return;
+        }
+        let ssrc = read_u32(chunks, 0);
+        let mut offset = 4;
+        loop {
+            match chunks.get(offset) {
+                None => return,
+                Some(0) => break,
+                Some(&item) => {
+                    let len = match chunks.get(offset + 1) {
+                        Some(&len) => len as usize,
+                        None => return
+                    };
+                    let text = match chunks.get(offset + 2..offset + 2 + len) {
+                        Some(text) => text,
+                        None => return
+                    };
+                    if item == SDES_CNAME {
+                        let cname = String::from_utf8_lossy(text).into_owned();
+                        memberships.push(Membership::Cname { ssrc, cname });
+                    }
+                    offset += 2 + len;
+                }
+            }
+        }
+        // The null byte, then padding to the next boundary.
+        chunks = chunks.get((offset + 1).div_ceil(4) * 4..).unwrap_or_default();
+    }
+}
+
+// A copy of a compound packet with every SSRC in it passed through `map`:
+// the reporter's or sender's, those of report blocks, SDES chunks, BYE,
+// feedback targets and XR blocks. Nothing changes size, so lengths and
+// padding stay valid. None unless the packets fill the datagram exactly
+// and only the last one is padded, as section 6.1 requires.
+pub(crate) fn map_ssrcs(compound: &[u8], map: impl Fn(u32) -> u32) -> Option<Vec<u8>> {
+    let mut mapped = compound.to_vec();
+    let mut offset = 0;
+    let rewrite = |packet: &mut [u8], at: usize| {
+        if let Some(field) = packet.get_mut(at..at + 4) {
+            field.copy_from_slice(&map(read_u32(field, 0)).to_be_bytes());
+        }
+    };
+    while offset < mapped.len() {
+        let rest = &mapped[offset..];
+        if rest.len() < 8 || rest[0] >> 6 != 2 {
+            return None;
+        }
+        let length = 4 * (u16::from_be_bytes([rest[2], rest[3]]) as usize + 1);
+        let padded = rest[0] & 0x20 != 0;
+        if length > rest.len() || (padded && length != rest.len()) {
+            return None;
+        }
+        let count = (rest[0] & 0x1F) as usize;
+        let packet = &mut mapped[offset..offset + length];
+        match packet[1] {
+            PACKET_TYPE_SR | PACKET_TYPE_RR => {
+                rewrite(packet, 4);
+                let blocks = if packet[1] == PACKET_TYPE_SR { 28 } else { 8 };
+                for block in 0..count {
+                    rewrite(packet, blocks + 24 * block);
+                }
+            }
+            PACKET_TYPE_SDES => {
+                let mut chunk = 4;
+                for _ in 0..count {
+                    rewrite(packet, chunk);
+                    let mut item = chunk + 4;
+                    while packet.get(item).is_some_and(|&item_type| item_type != 0) {
+                        item += 2 + *packet.get(item + 1)? as usize;
+                    }
+                    chunk = (item + 1).div_ceil(4) * 4;
+                }
+            }
+            PACKET_TYPE_BYE => {
+                for index in 0..count {
+                    rewrite(packet, 4 + 4 * index);
+                }
+            }
+            PACKET_TYPE_APP => rewrite(packet, 4),
+            PACKET_TYPE_RTPFB | PACKET_TYPE_PSFB => {
+                rewrite(packet, 4);
+                rewrite(packet, 8);
+            }
+            PACKET_TYPE_XR => {
+                rewrite(packet, 4);
+                let mut block = 8;
+                while block + 4 <= packet.len() {
+                    let block_length = 4 * (u16::from_be_bytes([packet[block + 2], packet[block + 3]]) as usize + 1);
+                    match packet[block] {
+                        // Loss and duplicate RLE, packet receipt times,
+                        // statistics summary and VoIP metrics (RFC 3611
+                        // section 4): the source's SSRC after the header.
+                        1 | 2 | 3 | 6 | XR_VOIP_METRICS => rewrite(packet, block + 4),
+                        // DLRR: one SSRC per 12-byte sub-block.
+                        5 => {
+                            for sub_block in (block + 4..block + block_length).step_by(12) {
+                                rewrite(packet, sub_block);
+                            }
+                        }
+                        _ => {}
+                    }
+                    block += block_length;
+                }
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    Some(mapped)
+}
+
+// The individual packets of a compound packet, each at least its 4-byte
+// header long. Stops at the first malformed one.
+fn packets(mut compound: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if compound.len() < 4 || compound[0] >> 6 != 2 {
+            return None;
+        }
+        let length = 4 * (u16::from_be_bytes([compound[2], compound[3]]) as usize + 1);
+        let packet = compound.get(..length)?;
+        compound = &compound[length..];
+        Some(packet)
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(ssrc: u32) -> u32 {
+        match ssrc {
+            1 => 100,
+            other => other
+        }
+    }
+
+    #[test]
+    fn every_ssrc_field_is_mapped() {
+        let report = ReceptionReport {
+            reporter_ssrc: 2,
+            source_ssrc: 1,
+            ..ReceptionReport::default()
+        };
+        let mut compound = receiver_report(2, &report, "two");
+        compound.extend(picture_loss(2, 1, "two"));
+        compound.extend(goodbye(1, "one", Some("done")));
+        let mapped = map_ssrcs(&compound, swap).unwrap();
+        assert_eq!(mapped.len(), compound.len());
+
+        assert_eq!(report_blocks(&mapped)[0].source_ssrc, 100);
+        assert_eq!(picture_losses(&mapped, 100), 1);
+        assert!(find_goodbye(&mapped, 100).is_some());
+        let cnames: Vec<(u32, String)> = memberships(&mapped)
+            .into_iter()
+            .filter_map(|membership| match membership {
+                Membership::Cname { ssrc, cname } => Some((ssrc, cname)),
+                _ => None
+            })
+            .collect();
+        assert!(cnames.contains(&(100, "one".into())) && cnames.contains(&(2, "two".into())), "{:?}", cnames);
+    }
+
+    #[test]
+    fn broken_compounds_are_refused() {
+        let compound = sender_report(1, NtpTimestamp(0), 0, 0, 0, "one");
+        // Cut short, trailing bytes, and padding before the last packet.
+        assert_eq!(map_ssrcs(&compound[..compound.len() - 4], swap), None);
+        assert_eq!(map_ssrcs(&[&compound[..], &[0x80, 0, 0]].concat(), swap), None);
+        let mut padded = compound.clone();
+        padded[0] |= 0x20;
+        assert_eq!(map_ssrcs(&padded, swap), None);
+        assert!(map_ssrcs(&compound, swap).is_some());
+    }
+}
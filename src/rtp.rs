@@ -0,0 +1,71 @@
+// Fixed 12-byte RTP header (RFC 3550 section 5.1), no CSRCs.
+pub(crate) const RTP_HEADER_SIZE: usize = 12;
+
+pub(crate) struct RtpHeader {
+    pub byte1: u8,
+    pub byte2: u8,
+    pub seq: u16,
+    pub ts: u32,
+    pub ssrc: u32
+}
+
+impl RtpHeader {
+    pub fn copy_into_array(&self) -> [u8; RTP_HEADER_SIZE] {
+        let mut array: [u8; RTP_HEADER_SIZE] = [0u8; RTP_HEADER_SIZE];
+        array[0] = self.byte1;
+        array[1] = self.byte2;
+        array[2..4].copy_from_slice(&self.seq.to_be_bytes());
+        array[4..8].copy_from_slice(&self.ts.to_be_bytes());
+        array[8..12].copy_from_slice(&self.ssrc.to_be_bytes());
+        array
+    }
+}
+
+// Fields of a received packet, with CSRCs, header extension and padding
+// stripped from the payload.
+pub(crate) struct RtpPacket<'a> {
+    pub marker: bool,
+    pub seq: u16,
+    pub ts: u32,
+    pub ssrc: u32,
+    // The header extension block, profile word included.
+    pub extension: Option<&'a [u8]>,
+    pub payload: &'a [u8],
+    // Where `payload` starts in the datagram.
+    pub payload_offset: usize
+}
+
+impl<'a> RtpPacket<'a> {
+    // None unless this is a well-formed version 2 packet.
+    pub fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() < RTP_HEADER_SIZE || packet[0] >> 6 != 2 {
+            return None;
+        }
+        let csrc_count = (packet[0] & 0x0F) as usize;
+        let mut offset = RTP_HEADER_SIZE + 4 * csrc_count;
+
+        let mut extension = None;
+        if packet[0] & 0x10 != 0 {
+            let header = packet.get(offset..offset + 4)?;
+            let words = u16::from_be_bytes([header[2], header[3]]) as usize;
+            extension = Some(packet.get(offset..offset + 4 + 4 * words)?);
+            offset += 4 + 4 * words;
+        }
+
+        let mut end = packet.len();
+        if packet[0] & 0x20 != 0 {
+            let padding = *packet.last()? as usize;
+            end = end.checked_sub(padding)?;
+        }
+
+        Some(RtpPacket {
+            marker: packet[1] & 0x80 != 0,
+            seq: u16::from_be_bytes([packet[2], packet[3]]),
+            ts: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+            ssrc: u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+            extension,
+            payload: packet.get(offset..end)?,
+            payload_offset: offset
+        })
+    }
+}
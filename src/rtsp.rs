@@ -0,0 +1,491 @@
+//! A minimal RTSP server (RFC 2326) in front of an H.264 pusher, for players
+//! that only take `rtsp://` URLs.
+//!
+//! [`RtspServer`] answers OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN and
+//! GET_PARAMETER for one stream path. DESCRIBE returns an SDP with the
+//! parameter sets seen so far; SETUP accepts unicast RTP over UDP and
+//! creates an `H264RtpPusher` toward the client's RTP port, with Sender
+//! Reports to its RTCP port. The application feeds access units to the
+//! [`FrameSink`]; they reach the client from PLAY on, starting at the next
+//! IDR. TEARDOWN, or the client closing its RTSP connection, ends the session
+//! with an RTCP BYE.
+//!
+//! With [`RtspServer::set_late_join_burst`], a client joining mid-GOP does
+//! not wait for the next IDR: PLAY first bursts the frames since the last
+//! one, faster than real time, then the live stream follows on the same
+//! pusher, so sequence numbers run on without a gap and RTP timestamps
+//! are those the frames would have had live.
+//!
+//! One client is served at a time: a second SETUP gets 453 until the first
+//! session ends. There is no authentication and no RTP over TCP.
+use std::collections::VecDeque;
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::sender::{random_u32, H264RtpPusher, DEFAULT_PAYLOAD_TYPE, DEFAULT_SENDER_REPORT_INTERVAL};
+use crate::{annexb, sdp, CancelToken, RtpError};
+
+mod message;
+
+use message::{Request, Response};
+
+// How long the accept loop sleeps when no connection is pending.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Advertised in the Session header. Sessions end with their connection,
+// not on this timeout.
+const SESSION_TIMEOUT_SECS: u32 = 60;
+// Control URL of the one media stream, relative to the stream URL.
+const TRACK_CONTROL: &str = "trackID=0";
+const PUBLIC_METHODS: &str = "OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN, GET_PARAMETER";
+// Frames since the last IDR kept for a late-join burst, in bytes. A GOP
+// beyond it is not kept: its burst would take too long anyway.
+const MAX_GOP_CACHE_BYTES: usize = 8 << 20;
+
+/// Serves one H.264 stream at `rtsp://<host>:<port>/<path>`. Requests are
+/// handled on background threads; dropping the server ends the session,
+/// closes the open RTSP connections and returns once all of its threads
+/// have finished.
+pub struct RtspServer {
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+    stop: CancelToken,
+    // Returns the threads of the connections still open when it stops.
+    accept_thread: Option<JoinHandle<Vec<JoinHandle<()>>>>
+}
+
+/// Where the application sends frames for the RTSP client. Clones feed the
+/// same server.
+#[derive(Clone)]
+pub struct FrameSink {
+    shared: Arc<Shared>
+}
+
+struct Shared {
+    path: String,
+    // Origin id of the SDP.
+    sdp_session_id: u32,
+    state: Mutex<State>
+}
+
+#[derive(Default)]
+struct State {
+    session: Option<Session>,
+    // Last parameter sets passed to the sink, for DESCRIBE.
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    // Open RTSP connections, shut down when the server is dropped.
+    connections: Vec<(u64, TcpStream)>,
+    next_connection_id: u64,
+    // Speed of the late-join burst, None when off.
+    burst_speed: Option<u32>,
+    // Frames from the last IDR on, with the time each was submitted. None
+    // before the first IDR and while the GOP is over the cache limit.
+    gop: Option<Vec<(Instant, Vec<u8>)>>,
+    gop_bytes: usize
+}
+
+struct Session {
+    id: String,
+    // The connection that set the session up; it ends with it.
+    connection_id: u64,
+    pusher: H264RtpPusher,
+    // Frames still to go out in the late-join burst. Live frames join the
+    // queue until it runs dry.
+    burst: Option<VecDeque<(Instant, Vec<u8>)>>
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // A panic while sending leaves at worst a half-sent frame, which the
+        // next frame recovers from.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Whether `uri` names the stream or its track, e.g.
+    // rtsp://host:8554/stream or rtsp://host:8554/stream/trackID=0.
+    fn serves(&self, uri: &str) -> bool {
+        let path = uri
+            .split_once("://")
+            .map_or(uri, |(_, rest)| rest.find('/').map_or("", |slash| &rest[slash..]));
+        let path = path.trim_start_matches('/').trim_end_matches('/');
+        match path.strip_prefix(self.path.as_str()) {
+            Some("") => true,
+            Some(rest) => rest.trim_start_matches('/') == TRACK_CONTROL,
+            None => false
+        }
+    }
+}
+
+impl RtspServer {
+    /// Listens on `local`, e.g. "0.0.0.0:8554", for requests to `path`
+    /// ("stream" for `rtsp://host:8554/stream`).
+    pub fn bind(local: &str, path: &str) -> Result<Self, RtpError> {
+        let listener = TcpListener::bind(local).map_err(RtpError::Bind)?;
+        // Polled so the accept loop notices the server being dropped.
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            path: path.trim_matches('/').to_owned(),
+            sdp_session_id: random_u32(),
+            state: Mutex::new(State::default())
+        });
+        let stop = CancelToken::new();
+        let accept_thread = {
+            let shared = Arc::clone(&shared);
+            let stop = stop.clone();
+            thread::spawn(move || accept_connections(listener, shared, stop))
+        };
+        Ok(Self {
+            local_addr,
+            shared,
+            stop,
+            accept_thread: Some(accept_thread)
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn frame_sink(&self) -> FrameSink {
+        FrameSink {
+            shared: Arc::clone(&self.shared)
+        }
+    }
+
+    /// Whether a client is between PLAY and TEARDOWN.
+    pub fn is_playing(&self) -> bool {
+        is_playing(&self.shared.state())
+    }
+
+    /// Keeps the frames since the last IDR (up to 8 MiB) and, on PLAY,
+    /// sends them to the new client at `speed` times real time, at least
+    /// 2, before the live stream; `None` (the default) starts clients at
+    /// the next IDR. The burst runs on the client's connection thread, so
+    /// its next request waits for it to end.
+    pub fn set_late_join_burst(&self, speed: Option<u32>) {
+        let mut state = self.shared.state();
+        state.burst_speed = speed.map(|speed| speed.max(2));
+        if speed.is_none() {
+            state.gop = None;
+            state.gop_bytes = 0;
+        }
+    }
+}
+
+impl Drop for RtspServer {
+    fn drop(&mut self) {
+        self.stop.cancel();
+        // Once the accept thread is gone no connection can be added. Had it
+        // panicked, the connection threads are left to end on their own
+        // once their connections are shut down below.
+        let connection_threads = self
+            .accept_thread
+            .take()
+            .and_then(|thread| thread.join().ok())
+            .unwrap_or_default();
+        {
+            let mut state = self.shared.state();
+            // Ends the connection threads' blocking reads.
+            for (_, connection) in state.connections.drain(..) {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+            state.session = None;
+        }
+        // They take the state lock on the way out.
+        for thread in connection_threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl FrameSink {
+    /// Sends an Annex B access unit to the playing client, as
+    /// `H264RtpPusher::send_frame` does. Without one the frame is dropped
+    /// and Ok returned. SPS and PPS are remembered for later DESCRIBEs
+    /// either way.
+    pub fn send_frame(&self, frame_buffer: &[u8]) -> Result<(), RtpError> {
+        let mut state = self.shared.state();
+        let mut idr = false;
+        for nal in annexb::nal_units(frame_buffer) {
+            match nal.h264_type() {
+                5 => idr = true,
+                7 => state.sps = Some(nal.data.to_vec()),
+                8 => state.pps = Some(nal.data.to_vec()),
+                _ => {}
+            }
+        }
+        let now = Instant::now();
+        if state.burst_speed.is_some() {
+            cache_frame(&mut state, now, frame_buffer, idr);
+        }
+        match state.session.as_mut() {
+            Some(session) if !session.pusher.is_paused() => match session.burst.as_mut() {
+                Some(queue) => {
+                    queue.push_back((now, frame_buffer.to_vec()));
+                    Ok(())
+                }
+                None => session.pusher.send_frame(frame_buffer)
+            },
+            _ => Ok(())
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        is_playing(&self.shared.state())
+    }
+}
+
+fn cache_frame(state: &mut State, submitted: Instant, frame: &[u8], idr: bool) {
+    if idr {
+        state.gop = Some(Vec::new());
+        state.gop_bytes = 0;
+    }
+    let gop = match state.gop.as_mut() {
+        Some(gop) => gop,
+        None => return
+    };
+    state.gop_bytes += frame.len();
+    if state.gop_bytes > MAX_GOP_CACHE_BYTES {
+        state.gop = None;
+        return;
+    }
+    gop.push((submitted, frame.to_vec()));
+}
+
+// The pusher is created paused by SETUP and unpaused by PLAY.
+fn is_playing(state: &State) -> bool {
+    state.session.as_ref().is_some_and(|session| !session.pusher.is_paused())
+}
+
+fn accept_connections(listener: TcpListener, shared: Arc<Shared>, stop: CancelToken) -> Vec<JoinHandle<()>> {
+    let mut connection_threads: Vec<JoinHandle<()>> = Vec::new();
+    while !stop.is_cancelled() {
+        // Threads of closed connections have nothing left to join for.
+        connection_threads.retain(|thread| !thread.is_finished());
+
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            // Also covers transient failures such as running out of file
+            // descriptors.
+            Err(_) => {
+                let _ = stop.sleep(ACCEPT_POLL_INTERVAL);
+                continue;
+            }
+        };
+        let registered = stream.set_nonblocking(false).and_then(|_| stream.try_clone());
+        let connection = match registered {
+            Ok(connection) => connection,
+            Err(_) => continue
+        };
+        let connection_id = {
+            let mut state = shared.state();
+            let connection_id = state.next_connection_id;
+            state.next_connection_id += 1;
+            state.connections.push((connection_id, connection));
+            connection_id
+        };
+        let shared = Arc::clone(&shared);
+        connection_threads.push(thread::spawn(move || serve_connection(stream, connection_id, shared)));
+    }
+    connection_threads
+}
+
+fn serve_connection(stream: TcpStream, connection_id: u64, shared: Arc<Shared>) {
+    let addresses = stream.peer_addr().and_then(|peer| Ok((peer, stream.local_addr()?.ip())));
+    let reader = stream.try_clone();
+    if let (Ok((peer, local)), Ok(reader)) = (addresses, reader) {
+        let mut reader = BufReader::new(reader);
+        let mut writer = stream;
+        // A malformed request or a write error ends the connection.
+        while let Ok(Some(request)) = message::read_request(&mut reader) {
+            let response = respond(&shared, connection_id, peer, local, &request);
+            if response.write_to(&mut writer, request.header("CSeq")).is_err() {
+                break;
+            }
+            // After the PLAY response, which announces the burst's first
+            // sequence number.
+            send_burst(&shared, connection_id);
+        }
+    }
+
+    let mut state = shared.state();
+    state.connections.retain(|(id, _)| *id != connection_id);
+    if state.session.as_ref().is_some_and(|session| session.connection_id == connection_id) {
+        state.session = None;
+    }
+}
+
+fn respond(shared: &Shared, connection_id: u64, peer: SocketAddr, local: IpAddr, request: &Request) -> Response {
+    if request.header("CSeq").is_none() {
+        return Response::new(400, "Bad Request");
+    }
+    if request.method != "OPTIONS" && !shared.serves(&request.uri) {
+        return Response::new(404, "Not Found");
+    }
+    match request.method.as_str() {
+        "OPTIONS" => Response::new(200, "OK").header("Public", PUBLIC_METHODS),
+        "DESCRIBE" => describe(shared, local, request),
+        "SETUP" => setup(shared, connection_id, peer, request),
+        "PLAY" => play(shared, request),
+        "TEARDOWN" => {
+            let mut state = shared.state();
+            if !owns_session(&state, request) {
+                return Response::new(454, "Session Not Found");
+            }
+            state.session = None;
+            Response::new(200, "OK")
+        }
+        // Keepalive of many clients.
+        "GET_PARAMETER" => Response::new(200, "OK"),
+        _ => Response::new(501, "Not Implemented").header("Public", PUBLIC_METHODS)
+    }
+}
+
+fn owns_session(state: &State, request: &Request) -> bool {
+    match (&state.session, request.session()) {
+        (Some(session), Some(id)) => session.id == id,
+        _ => false
+    }
+}
+
+fn describe(shared: &Shared, local: IpAddr, request: &Request) -> Response {
+    let state = shared.state();
+    let unspecified: IpAddr = match local {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into()
+    };
+    // The client picks the ports at SETUP, so the SDP names none.
+    let description = sdp::describe(&sdp::StreamDescription {
+        origin: local,
+        destination: SocketAddr::new(unspecified, 0),
+        rtcp_port: None,
+        payload_type: DEFAULT_PAYLOAD_TYPE,
+        ssrc: shared.sdp_session_id,
+        cname: None,
+        sps: state.sps.as_deref(),
+        pps: state.pps.as_deref(),
+        control: Some(TRACK_CONTROL),
+        rtx: None
+    });
+    Response::new(200, "OK")
+        .header("Content-Base", format!("{}/", request.uri.trim_end_matches('/')))
+        .body("application/sdp", description)
+}
+
+// `peer` keeps the scope id of a link-local client for the media ports.
+fn setup(shared: &Shared, connection_id: u64, peer: SocketAddr, request: &Request) -> Response {
+    let (rtp_port, rtcp_port) = match request.header("Transport").and_then(message::client_ports) {
+        Some(ports) => ports,
+        None => return Response::new(461, "Unsupported Transport")
+    };
+    let mut state = shared.state();
+    if state.session.is_some() {
+        return Response::new(453, "Not Enough Bandwidth");
+    }
+
+    let (mut rtp, mut rtcp) = (peer, peer);
+    rtp.set_port(rtp_port);
+    rtcp.set_port(rtcp_port);
+    let pusher = match start_pusher(rtp, rtcp) {
+        Ok(pusher) => pusher,
+        Err(_) => return Response::new(500, "Internal Server Error")
+    };
+    let server_rtp_port = pusher.local_addr().map_or(0, |local| local.port());
+    let server_rtcp_port = match pusher.rtcp_local_addr() {
+        Ok(Some(local)) => local.port(),
+        _ => 0
+    };
+    let transport = format!(
+        "RTP/AVP;unicast;client_port={}-{};server_port={}-{};ssrc={:08X}",
+        rtp_port,
+        rtcp_port,
+        server_rtp_port,
+        server_rtcp_port,
+        pusher.ssrc()
+    );
+    let session = Session {
+        id: format!("{:08X}{:08X}", random_u32(), random_u32()),
+        connection_id,
+        pusher,
+        burst: None
+    };
+    let session_header = format!("{};timeout={}", session.id, SESSION_TIMEOUT_SECS);
+    state.session = Some(session);
+    Response::new(200, "OK")
+        .header("Transport", transport)
+        .header("Session", session_header)
+}
+
+// Paused until PLAY; unpausing then holds frames back until an IDR, which
+// is where the client can start decoding.
+fn start_pusher(rtp: SocketAddr, rtcp: SocketAddr) -> Result<H264RtpPusher, RtpError> {
+    let mut pusher = H264RtpPusher::with_socket_addr(rtp)?;
+    pusher.enable_sender_reports(DEFAULT_SENDER_REPORT_INTERVAL, Some(&rtcp.to_string()))?;
+    pusher.set_parameter_set_injection(true);
+    pusher.pause();
+    Ok(pusher)
+}
+
+fn play(shared: &Shared, request: &Request) -> Response {
+    let mut state = shared.state();
+    if !owns_session(&state, request) {
+        return Response::new(454, "Session Not Found");
+    }
+    let state = &mut *state;
+    let session = match state.session.as_mut() {
+        Some(session) => session,
+        None => return Response::new(454, "Session Not Found")
+    };
+    session.pusher.unpause();
+    if session.burst.is_none() && state.burst_speed.is_some() {
+        if let Some(gop) = state.gop.as_ref().filter(|gop| !gop.is_empty()) {
+            session.burst = Some(gop.iter().cloned().collect());
+        }
+    }
+    let rtp_info = format!("url={};seq={}", request.uri, session.pusher.next_sequence());
+    let session_header = session.id.clone();
+    Response::new(200, "OK")
+        .header("Session", session_header)
+        .header("Range", "npt=0.000-")
+        .header("RTP-Info", rtp_info)
+}
+
+// Sends the session's burst queue, paced at the burst speed, until it runs
+// dry or the session ends. The lock is only held per frame, so the
+// application keeps submitting frames, which are queued behind.
+fn send_burst(shared: &Shared, connection_id: u64) {
+    loop {
+        let pause = {
+            let mut state = shared.state();
+            let speed = state.burst_speed.unwrap_or(2);
+            let session = match state.session.as_mut() {
+                Some(session) if session.connection_id == connection_id => session,
+                _ => return
+            };
+            let queue = match session.burst.as_mut() {
+                Some(queue) => queue,
+                None => return
+            };
+            let (submitted, frame) = match queue.pop_front() {
+                Some(next) => next,
+                None => {
+                    session.burst = None;
+                    return;
+                }
+            };
+            let pause = queue.front().map(|(next, _)| next.saturating_duration_since(submitted) / speed);
+            let timestamp = session.pusher.timestamp_at(submitted);
+            // Like a live frame, a failed one does not end the stream.
+            let _ = session.pusher.send_frame_with_timestamp(&frame, timestamp);
+            pause
+        };
+        if let Some(pause) = pause {
+            thread::sleep(pause);
+        }
+    }
+}
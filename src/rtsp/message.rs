@@ -0,0 +1,157 @@
+use std::io::{self, BufRead, Read, Write};
+
+// Requests with a larger header block are refused rather than buffered.
+const MAX_HEADER_LINES: usize = 64;
+
+// An RTSP/1.0 request (RFC 2326 section 6). Bodies are read and dropped:
+// none of the methods the server answers carries one.
+pub struct Request {
+    pub method: String,
+    pub uri: String,
+    headers: Vec<(String, String)>
+}
+
+impl Request {
+    // Header names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The session id without the ";timeout=" parameter.
+    pub fn session(&self) -> Option<&str> {
+        self.header("Session").map(|session| session.split(';').next().unwrap_or_default().trim())
+    }
+}
+
+// Ok(None) when the client closed the connection between requests.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    // Clients may send blank lines between requests.
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = line.split_whitespace();
+    let (method, uri) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(uri), Some(version)) if version.starts_with("RTSP/") => (method, uri),
+        _ => return Err(invalid("malformed request line"))
+    };
+    let mut request = Request {
+        method: method.to_owned(),
+        uri: uri.to_owned(),
+        headers: Vec::new()
+    };
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if request.headers.len() == MAX_HEADER_LINES {
+            return Err(invalid("too many header lines"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            request.headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let body_len = request.header("Content-Length").and_then(|len| len.parse().ok()).unwrap_or(0);
+    io::copy(&mut reader.by_ref().take(body_len), &mut io::sink())?;
+    Ok(Some(request))
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+pub struct Response {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Option<(&'static str, String)>
+}
+
+impl Response {
+    pub fn new(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: None
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn body(mut self, content_type: &'static str, body: String) -> Self {
+        self.body = Some((content_type, body));
+        self
+    }
+
+    // `cseq` is echoed from the request, as every response must.
+    pub fn write_to<W: Write>(&self, writer: &mut W, cseq: Option<&str>) -> io::Result<()> {
+        let mut message = format!("RTSP/1.0 {} {}\r\n", self.status, self.reason);
+        if let Some(cseq) = cseq {
+            message.push_str(&format!("CSeq: {}\r\n", cseq));
+        }
+        message.push_str("Server: rtp-transceive\r\n");
+        for (name, value) in &self.headers {
+            message.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match &self.body {
+            Some((content_type, body)) => {
+                message.push_str(&format!("Content-Type: {}\r\n", content_type));
+                message.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+                message.push_str(body);
+            }
+            None => message.push_str("\r\n")
+        }
+        writer.write_all(message.as_bytes())
+    }
+}
+
+// RTP and RTCP ports from a Transport header asking for unicast RTP over
+// UDP, e.g. "RTP/AVP;unicast;client_port=5000-5001". None for anything
+// else (TCP interleaving, multicast), which the server does not offer.
+pub fn client_ports(transport: &str) -> Option<(u16, u16)> {
+    // Clients may list alternatives; take the first one we can serve.
+    transport.split(',').find_map(|spec| {
+        let mut parameters = spec.split(';').map(str::trim);
+        let protocol = parameters.next()?;
+        if !(protocol.eq_ignore_ascii_case("RTP/AVP") || protocol.eq_ignore_ascii_case("RTP/AVP/UDP")) {
+            return None;
+        }
+        let mut ports = None;
+        for parameter in parameters {
+            if parameter.eq_ignore_ascii_case("multicast") {
+                return None;
+            }
+            if let Some(range) = parameter.strip_prefix("client_port=") {
+                ports = Some(match range.split_once('-') {
+                    Some((rtp, rtcp)) => (rtp.parse().ok()?, rtcp.parse().ok()?),
+                    None => {
+                        let rtp: u16 = range.parse().ok()?;
+                        (rtp, rtp.checked_add(1)?)
+                    }
+                });
+            }
+        }
+        ports
+    })
+}
@@ -0,0 +1,126 @@
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// What an SDP description of one H.264 stream needs from the pusher.
+pub struct StreamDescription<'a> {
+    pub origin: IpAddr,
+    pub destination: SocketAddr,
+    // Only when it is not the RTP port + 1.
+    pub rtcp_port: Option<u16>,
+    pub payload_type: u8,
+    pub ssrc: u32,
+    pub cname: Option<&'a str>,
+    pub sps: Option<&'a [u8]>,
+    pub pps: Option<&'a [u8]>,
+    // RTSP control URL of the media.
+    pub control: Option<&'a str>,
+    // Payload type and SSRC of the RTX stream (RFC 4588), if NACKs are
+    // answered.
+    pub rtx: Option<(u8, u32)>
+}
+
+// Session description (RFC 4566) with the H.264 media line and format
+// parameters of RFC 6184 section 8.1. Lines end in CRLF.
+pub fn describe(stream: &StreamDescription) -> String {
+    let mut sdp = String::new();
+    // Writing to a String can't fail.
+    let _ = write_description(&mut sdp, stream);
+    sdp
+}
+
+fn write_description(sdp: &mut String, stream: &StreamDescription) -> std::fmt::Result {
+    let pt = stream.payload_type;
+    write!(sdp, "v=0\r\n")?;
+    write!(sdp, "o=- {} 1 {} {}\r\n", stream.ssrc, address_type(stream.origin), stream.origin)?;
+    write!(sdp, "s=rtp-transceive\r\n")?;
+    let destination = stream.destination.ip();
+    write!(sdp, "c={} {}\r\n", address_type(destination), destination)?;
+    write!(sdp, "t=0 0\r\n")?;
+    match stream.rtx {
+        // NACK feedback needs the AVPF profile (RFC 4585).
+        Some((rtx_pt, _)) => write!(sdp, "m=video {} RTP/AVPF {} {}\r\n", stream.destination.port(), pt, rtx_pt)?,
+        None => write!(sdp, "m=video {} RTP/AVP {}\r\n", stream.destination.port(), pt)?
+    }
+    if let Some(port) = stream.rtcp_port {
+        write!(sdp, "a=rtcp:{}\r\n", port)?;
+    }
+    write!(sdp, "a=rtpmap:{} H264/90000\r\n", pt)?;
+
+    // FU-A and STAP-A need non-interleaved mode.
+    write!(sdp, "a=fmtp:{} packetization-mode=1", pt)?;
+    if let Some(sps) = stream.sps.filter(|sps| sps.len() >= 4) {
+        write!(sdp, ";profile-level-id={:02X}{:02X}{:02X}", sps[1], sps[2], sps[3])?;
+    }
+    if let (Some(sps), Some(pps)) = (stream.sps, stream.pps) {
+        write!(sdp, ";sprop-parameter-sets={},{}", base64(sps), base64(pps))?;
+    }
+    write!(sdp, "\r\n")?;
+    if let Some((rtx_pt, _)) = stream.rtx {
+        write!(sdp, "a=rtcp-fb:{} nack\r\n", pt)?;
+        write!(sdp, "a=rtpmap:{} rtx/90000\r\n", rtx_pt)?;
+        write!(sdp, "a=fmtp:{} apt={}\r\n", rtx_pt, pt)?;
+    }
+
+    if let Some(control) = stream.control {
+        write!(sdp, "a=control:{}\r\n", control)?;
+    }
+    if let Some(cname) = stream.cname {
+        write!(sdp, "a=ssrc:{} cname:{}\r\n", stream.ssrc, cname)?;
+        if let Some((_, rtx_ssrc)) = stream.rtx {
+            write!(sdp, "a=ssrc:{} cname:{}\r\n", rtx_ssrc, cname)?;
+            write!(sdp, "a=ssrc-group:FID {} {}\r\n", stream.ssrc, rtx_ssrc)?;
+        }
+    }
+    write!(sdp, "a=sendonly\r\n")
+}
+
+fn address_type(address: IpAddr) -> &'static str {
+    match address {
+        IpAddr::V4(_) => "IN IP4",
+        IpAddr::V6(_) => "IN IP6"
+    }
+}
+
+// Standard alphabet with padding (RFC 4648 section 4).
+#[cfg(feature = "srtp")]
+pub(crate) fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (index, chunk) in encoded.chunks(4).enumerate() {
+        let last = index == encoded.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut group = 0u32;
+        for &byte in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&symbol| symbol == byte)?;
+            group = (group << 6) | value as u32;
+        }
+        group <<= 6 * padding as u32;
+        decoded.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Some(decoded)
+}
+
+// Standard alphabet with padding (RFC 4648 section 4).
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * index)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,1761 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::payload::h264::{
+    avcc_nal_units, is_sendable, nal_header, nal_units, parameter_set_type, H264NalType, ParameterSetCache,
+};
+use crate::packetizer::{fu_a_fragments, validate_frame, validate_nal, FU_A_SIZE, FU_END, MAX_MTU};
+use crate::rtp::{RtpHeader, RTP_HEADER_SIZE};
+use crate::stats::{PayloadAccounting, PayloadStats, PriorityDrops, SenderAccounting, SenderStats, StreamSummary};
+use crate::time::{wire_diff, MediaTimestamp, NtpTimestamp, VIDEO_CLOCK_RATE};
+use crate::capture::{self, Capture, PcapWriter};
+use crate::clock::{Clock, SystemClock};
+use crate::extension::{self, HeaderExtension, PacketInfo};
+use crate::cancel::is_io_cancelled;
+use crate::{integrity, platform, sdp, CancelToken, PacketTransform, RtpError};
+
+mod batch;
+mod config;
+pub(crate) mod destination;
+mod duplication;
+mod extensions;
+mod fault;
+mod h265;
+mod keepalive;
+mod multicast;
+mod pacer;
+mod pmtu;
+mod preset;
+mod replay;
+mod rtcp;
+mod rtx;
+mod sink;
+mod state;
+mod tcp;
+pub use config::{AppliedChanges, ConfigDelta, ConfigField, ConfigWarning};
+pub use destination::AddressPreference;
+pub use duplication::DuplicationPolicy;
+pub use fault::{FaultControl, FaultInjector, FaultPolicy, FaultStats};
+pub use h265::H265RtpPusher;
+pub use crate::packetizer::{FragmentSizing, RtpSink};
+pub use sink::UdpSink;
+pub use multicast::MulticastOptions;
+pub use pacer::PacingPolicy;
+pub use preset::Preset;
+pub use state::SenderState;
+pub use tcp::ReconnectPolicy;
+
+const DEFAULT_MTU: usize = 1400;
+// Room for the RTP header, the integrity extension, a FU-A header and at
+// least one byte of NAL.
+const MIN_MTU: usize = RTP_HEADER_SIZE + extension::block_size(integrity::ELEMENT_SIZE) + 2 + 1;
+pub(crate) const DEFAULT_PAYLOAD_TYPE: u8 = 96;
+// Pause before retrying a send the kernel rejected for lack of buffer space.
+const DEFAULT_SEND_RETRY_DELAY: Duration = Duration::from_micros(50);
+/// Window `SenderStats::bitrate` is averaged over unless
+/// `set_bitrate_window` changes it.
+pub const DEFAULT_BITRATE_WINDOW: Duration = Duration::from_secs(1);
+const NAL_TYPE_END_OF_STREAM: u8 = 11;
+const NAL_TYPE_STAP_A: u8 = 24;
+// STAP-A NAL header, then a 16-bit size ahead of each aggregated NAL.
+const STAP_A_HEADER_SIZE: usize = 1;
+const STAP_A_UNIT_HEADER_SIZE: usize = 2;
+// NALs per STAP-A packet at most; receivers, ours included, refuse more.
+const MAX_STAP_A_UNITS: usize = 64;
+/// RTCP Sender Report interval suggested for `enable_sender_reports`.
+pub const DEFAULT_SENDER_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Packetizes H.264 access units and sends them as RTP, over UDP unless
+/// `connect_tcp` or `set_sink` says otherwise.
+///
+/// The pusher is `Send` but not `Sync`: move it to a dedicated sending
+/// thread, or share it behind a `Mutex`, and use a `CancelToken` to
+/// interrupt it from other threads.
+pub struct H264RtpPusher {
+    socket: UdpSocket,
+    // Resolved once at construction (and by apply_config).
+    destination: SocketAddr,
+    // Peer of a connected socket passed to from_socket.
+    socket_peer: Option<SocketAddr>,
+    address_preference: AddressPreference,
+    // Applied whenever the destination is a multicast group.
+    multicast: Option<MulticastOptions>,
+
+    // Always `mtu` bytes long.
+    rtp_buffer: Vec<u8>,
+    rtp_buffer_size: usize,
+    rtp_ts: u32,
+    rtp_seq: u16,
+    rtp_is_last: bool,
+    ssrc: u32,
+    payload_type: u8,
+    timestamp_offset: u32,
+
+    // Last parameter sets seen in the stream, kept so a resumed session can
+    // start with them.
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+
+    // Header extension id used for the per-frame CRC, None when disabled.
+    integrity_extension_id: Option<u8>,
+    frame_crc: Option<u32>,
+    rtp_is_last_nal: bool,
+    // Put in every media packet, see set_header_extensions.
+    header_extensions: Vec<HeaderExtension>,
+    // Header extension id of abs-send-time, None when disabled.
+    abs_send_time_id: Option<u8>,
+    // No packet of the current access unit went out yet.
+    rtp_is_frame_start: bool,
+
+    // Replace encoder NRI bits with values derived from the NAL type.
+    rewrite_nri: bool,
+
+    // Pack runs of small NALs into STAP-A packets.
+    aggregate_nals: bool,
+
+    // NAL type of the packet being built, recorded with it for replay.
+    rtp_nal_type: u8,
+    replay_buffer: Option<replay::ReplayBuffer>,
+    // Answers NACKs with an RTX stream.
+    retransmission: Option<rtx::Retransmission>,
+
+    keepalive_interval: Option<Duration>,
+    last_packet_sent_at: Option<Instant>,
+    keepalives_sent: u64,
+    stream_ended: bool,
+    // Set by close; the pusher sends nothing afterwards.
+    closed: bool,
+    bye_reason: Option<String>,
+    send_end_of_stream_nal: bool,
+
+    // Packets the kernel refused (ENOBUFS/EAGAIN) even after the retry.
+    send_retry_delay: Option<Duration>,
+    kernel_drops: u64,
+    // Under pacing, NALs are shed by importance once the frame would take
+    // longer than this to drain.
+    priority_dropping: Option<Duration>,
+    priority_drops: PriorityDrops,
+
+    paused: bool,
+    pause_policy: PausePolicy,
+    // Set by unpause: frames are held back until one carries an IDR.
+    awaiting_keyframe: bool,
+
+    // Send cached SPS/PPS ahead of IDR frames that arrive without them.
+    inject_parameter_sets: bool,
+    parameter_sets: ParameterSetCache,
+
+    // Frame being sent NAL by NAL through push_nal.
+    nal_frame: Option<NalFrame>,
+
+    // RTP timestamp for the frame being sent when the caller supplied one
+    // (send_frame_with_timestamp).
+    frame_ts: Option<u32>,
+    // When realtime streaming scheduled the frame being sent; otherwise it
+    // counts as submitted when the send starts.
+    frame_due: Option<Instant>,
+    packets_sent: u64,
+    bytes_sent: u64,
+    // Frames whose last packet would leave later than this after they were
+    // submitted are dropped.
+    latency_bound: Option<Duration>,
+    late_frames_dropped: u64,
+    // RTP payload bytes, for the Sender Report octet count.
+    payload_bytes_sent: u64,
+    // When the last media packet went out and its RTP timestamp, to map
+    // wall-clock time onto the media timeline for Sender Reports.
+    last_media_packet: Option<(Instant, u32)>,
+    sender_reports: Option<rtcp::SenderReports>,
+
+    // RTP clock anchor: wall-clock time at construction in 90 kHz ticks,
+    // advanced by the monotonic clock afterwards.
+    anchor_instant: Instant,
+    anchor_wall_clock: SystemTime,
+    anchor_ticks: MediaTimestamp,
+    // Source of both clocks above, and of every wait and interval.
+    clock: Arc<dyn Clock>,
+
+    payload_accounting: PayloadAccounting,
+    send_accounting: SenderAccounting,
+
+    duplicates: Option<duplication::DuplicateScheduler>,
+    duplicates_sent: u64,
+
+    marker_policy: MarkerPolicy,
+    fragment_sizing: FragmentSizing,
+
+    // Largest RTP packet to build, headers included.
+    mtu: usize,
+    path_mtu_interval: Option<Duration>,
+    // None when a (re-)probe is due before the next frame.
+    path_mtu_probed_at: Option<Instant>,
+
+    packet_transform: Option<PacketTransform>,
+    // Bytes the transform may add, kept free in every packet.
+    transform_overhead: usize,
+    transform_drops: u64,
+
+    // Second path every packet is also sent over, with its own socket.
+    redundant_path: Option<(UdpSocket, SocketAddr)>,
+    redundant_send_errors: u64,
+
+    transport: Transport,
+    tcp_reconnect: Option<ReconnectPolicy>,
+
+    pacer: Option<pacer::Pacer>,
+    // Rate of control packets allowed past the pacer's wait.
+    control_priority: Option<u64>,
+    // Total time packets were held back by the pacer.
+    pacing_wait: Duration,
+    // Interrupts the waits inside a send: set_cancel_token's, or that of
+    // the running stream_access_units.
+    cancel: Option<CancelToken>,
+
+    batch: Option<batch::SendBatch>,
+    // Set while a frame's packets are emitted, so they go into the batch.
+    queue_packets: bool,
+    send_batches: u64,
+
+    capture: Capture,
+    #[cfg(feature = "srtp")]
+    srtp: Option<crate::srtp::Context>
+}
+
+// Where RTP packets go: the UDP socket, unless connect_tcp or set_sink
+// replaced it.
+enum Transport {
+    Udp,
+    Tcp(tcp::TcpPath),
+    Sink(Box<dyn RtpSink>)
+}
+
+enum NalFrame {
+    // The running frame CRC when the integrity extension is on.
+    Sending { crc: Option<integrity::Crc32> },
+    // Refused by pause or the keyframe wait: its remaining NALs are dropped.
+    Dropping
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockAnchor {
+    pub wall_clock: SystemTime,
+    pub rtp_timestamp: u32
+}
+
+/// What `send_frame` does with frames while the pusher is paused or waiting
+/// for a keyframe after `unpause`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// Discard the frame and return Ok.
+    Drop,
+    /// Return `RtpError::Paused` or `RtpError::AwaitingKeyframe`.
+    Reject
+}
+
+/// Which packets carry the RTP marker bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkerPolicy {
+    /// The last packet of each access unit (RFC 6184).
+    AccessUnitEnd,
+    /// The last packet of every NAL. Some deployed decoders depend on it.
+    EveryNal,
+    /// No packet.
+    Never
+}
+
+impl H264RtpPusher {
+    /// `destination` is `host:port`, `a.b.c.d:port` or `[v6]:port`; the port
+    /// may be a UDP service name from /etc/services. It is resolved here, so
+    /// a bad destination fails construction rather than the first send.
+    /// The local socket gets an ephemeral port on the unspecified address of
+    /// the destination's family.
+    pub fn new(destination: &str) -> Result<Self, RtpError> {
+        Self::with_address_preference(destination, AddressPreference::First)
+    }
+
+    /// Like `new`, choosing among several resolved addresses by `preference`.
+    pub fn with_address_preference(
+        destination: &str,
+        preference: AddressPreference,
+    ) -> Result<Self, RtpError> {
+        let destination = destination::resolve(destination, preference)?;
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        Self::build(bind, destination, preference)
+    }
+
+    /// Like `new`, for a destination that is already an address, e.g. one
+    /// from `ToSocketAddrs` or with a numeric IPv6 scope id.
+    pub fn with_socket_addr(destination: SocketAddr) -> Result<Self, RtpError> {
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        Self::build(bind, destination, AddressPreference::First)
+    }
+
+    /// Like `new`, sending from the local address `bind`, e.g. `0.0.0.0:0`
+    /// or `192.168.1.20:5004`. Both must be of the same address family.
+    pub fn with_bind(bind: &str, destination: &str) -> Result<Self, RtpError> {
+        let bind = destination::resolve(bind, AddressPreference::First)?;
+        let destination = destination::resolve(destination, AddressPreference::First)?;
+        Self::build(bind, destination, AddressPreference::First)
+    }
+
+    /// Sends from `socket`, which the caller has already bound and may have
+    /// connected, e.g. one ICE hole-punched so the 5-tuple stays the same.
+    /// The pusher sets no socket options on it; only the calls the caller
+    /// makes (`set_dscp`, `probe_path_mtu`, ...) do. For symmetric RTP, pass
+    /// a `try_clone` of the socket given to `H264RtpReceiver::from_socket`.
+    pub fn from_socket(socket: UdpSocket, destination: SocketAddr) -> Result<Self, RtpError> {
+        destination::check_families(socket.local_addr()?, destination)?;
+        Self::from_parts(socket, destination, AddressPreference::First)
+    }
+
+    fn build(
+        bind: SocketAddr,
+        destination: SocketAddr,
+        preference: AddressPreference,
+    ) -> Result<Self, RtpError> {
+        destination::check_families(bind, destination)?;
+        let socket = UdpSocket::bind(bind).map_err(RtpError::Bind)?;
+        Self::from_parts(socket, destination, preference)
+    }
+
+    fn from_parts(
+        socket: UdpSocket,
+        destination: SocketAddr,
+        preference: AddressPreference,
+    ) -> Result<Self, RtpError> {
+        let socket_peer = socket.peer_addr().ok();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let anchor_instant = clock.now();
+        let anchor_wall_clock = clock.wall_clock();
+        Ok(Self {
+            socket,
+            destination,
+            socket_peer,
+            address_preference: preference,
+            multicast: None,
+            rtp_buffer: vec![0u8; DEFAULT_MTU],
+            rtp_buffer_size : 0,
+            rtp_ts: 0,
+            // Random starts (RFC 3550 section 5.1) keep a restarted pusher
+            // from looking like a late copy of its previous stream.
+            rtp_seq: random_u32() as u16,
+            rtp_is_last: false,
+            // RFC 3550 section 8.1: chosen at random.
+            ssrc: random_u32(),
+            payload_type: DEFAULT_PAYLOAD_TYPE,
+            timestamp_offset: 0,
+            sps: None,
+            pps: None,
+            integrity_extension_id: None,
+            frame_crc: None,
+            rtp_is_last_nal: false,
+            header_extensions: Vec::new(),
+            abs_send_time_id: None,
+            rtp_is_frame_start: true,
+            rewrite_nri: false,
+            aggregate_nals: false,
+            rtp_nal_type: 0,
+            replay_buffer: None,
+            retransmission: None,
+            keepalive_interval: None,
+            last_packet_sent_at: None,
+            keepalives_sent: 0,
+            stream_ended: false,
+            closed: false,
+            bye_reason: None,
+            send_end_of_stream_nal: false,
+            send_retry_delay: Some(DEFAULT_SEND_RETRY_DELAY),
+            kernel_drops: 0,
+            priority_dropping: None,
+            priority_drops: PriorityDrops::default(),
+            paused: false,
+            pause_policy: PausePolicy::Drop,
+            awaiting_keyframe: false,
+            inject_parameter_sets: false,
+            parameter_sets: ParameterSetCache::default(),
+            nal_frame: None,
+            frame_ts: None,
+            frame_due: None,
+            packets_sent: 0,
+            bytes_sent: 0,
+            latency_bound: None,
+            late_frames_dropped: 0,
+            payload_bytes_sent: 0,
+            last_media_packet: None,
+            sender_reports: None,
+            anchor_instant,
+            anchor_wall_clock,
+            anchor_ticks: anchor_ticks(anchor_wall_clock),
+            clock,
+            payload_accounting: PayloadAccounting::default(),
+            send_accounting: SenderAccounting::new(DEFAULT_BITRATE_WINDOW),
+            duplicates: None,
+            duplicates_sent: 0,
+            marker_policy: MarkerPolicy::AccessUnitEnd,
+            fragment_sizing: FragmentSizing::Greedy,
+            mtu: DEFAULT_MTU,
+            path_mtu_interval: None,
+            path_mtu_probed_at: None,
+            packet_transform: None,
+            transform_overhead: 0,
+            transform_drops: 0,
+            redundant_path: None,
+            redundant_send_errors: 0,
+            transport: Transport::Udp,
+            tcp_reconnect: None,
+            pacer: None,
+            control_priority: None,
+            pacing_wait: Duration::ZERO,
+            cancel: None,
+            batch: None,
+            queue_packets: false,
+            send_batches: 0,
+            capture: Capture::new(),
+            #[cfg(feature = "srtp")]
+            srtp: None
+        })
+    }
+
+    /// Some encoders mark every NAL with NRI 3, which hides how important a
+    /// NAL really is. When enabled, the NRI bits of each NAL header are
+    /// rewritten from the NAL type: 3 for SPS/PPS/IDR, 2 for other slices
+    /// and 0 for SEI, AUD, filler and end-of-sequence/stream NALs.
+    pub fn set_nri_rewrite(&mut self, enabled: bool) {
+        self.rewrite_nri = enabled;
+    }
+
+    /// When enabled, consecutive NALs of a frame that fit in one packet
+    /// together (typically SPS, PPS and SEI) are sent as a single STAP-A
+    /// packet (RFC 6184 section 5.7.1) instead of one packet each. The
+    /// receiver must support packetization-mode 1. NALs too large to share
+    /// a packet are still sent alone or as FU-A fragments.
+    pub fn set_stap_a_aggregation(&mut self, enabled: bool) {
+        self.aggregate_nals = enabled;
+    }
+
+    /// Pays the one-time costs of the send path before the first frame:
+    /// with `probe`, sends an empty datagram so ARP and route caches are
+    /// populated before the first IDR goes out. Calling it again is
+    /// harmless.
+    pub fn warm_up(&mut self, probe: bool) -> Result<(), RtpError> {
+        if probe {
+            self.send_udp(&[]).map_err(RtpError::Send)?;
+        }
+        Ok(())
+    }
+
+    /// When enabled, `close` first sends an End of Stream NAL unit in a
+    /// final RTP packet with the marker bit set, for decoders that flush on
+    /// it.
+    pub fn set_end_of_stream_nal(&mut self, enabled: bool) {
+        self.send_end_of_stream_nal = enabled;
+    }
+
+    /// Ends the session: optionally sends an End of Stream NAL (see
+    /// `set_end_of_stream_nal`), then, when Sender Reports are enabled, an
+    /// RTCP BYE for this SSRC on the Sender Report socket so players can
+    /// tear down without waiting for a timeout. Without them no RTCP is
+    /// sent, BYE included. Afterwards `send_frame` returns
+    /// `RtpError::Closed` and no keepalives or reports are sent.
+    ///
+    /// Dropping the pusher closes it, without the TCP reconnects of
+    /// `set_tcp_reconnect`, so a drop (during a panic, say) never waits on a
+    /// dead peer; its sockets are closed right after. Calling this again
+    /// does nothing; an error is reported once and the pusher counts as
+    /// closed anyway.
+    pub fn close(&mut self) -> Result<(), RtpError> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        self.stream_ended = true;
+
+        let end_of_stream = if self.send_end_of_stream_nal {
+            self.send_end_of_stream()
+        } else {
+            Ok(())
+        };
+        let bye = self.send_bye();
+        self.sender_reports = None;
+        end_of_stream.and(bye)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn send_end_of_stream(&mut self) -> Result<(), RtpError> {
+        let rtp_header = RtpHeader {
+            byte1: 2 << 6,
+            byte2: (1 << 7) | self.payload_type,
+            seq: self.rtp_seq,
+            ts: self.rtp_ts,
+            ssrc: self.ssrc
+        };
+        self.rtp_seq = self.rtp_seq.wrapping_add(1);
+
+        let mut packet = [0u8; RTP_HEADER_SIZE + 1];
+        packet[..RTP_HEADER_SIZE].copy_from_slice(&rtp_header.copy_into_array());
+        packet[RTP_HEADER_SIZE] = NAL_TYPE_END_OF_STREAM;
+        if let Err(err) = self.send_to_destination(&packet) {
+            let err = send_error(err);
+            self.send_accounting.record_error(&err);
+            return Err(err);
+        }
+        self.packets_sent += 1;
+        self.bytes_sent += packet.len() as u64;
+        self.payload_bytes_sent += 1;
+        self.send_accounting.record_packet(packet.len(), self.clock.now());
+        Ok(())
+    }
+
+    /// When enabled, an IDR frame that arrives without a PPS is preceded by
+    /// the parameter sets seen earlier in the stream, SPS first. If the IDR
+    /// slice header names a known PPS only that PPS and its SPS are sent,
+    /// otherwise every cached SPS and then every cached PPS.
+    pub fn set_parameter_set_injection(&mut self, enabled: bool) {
+        self.inject_parameter_sets = enabled;
+    }
+
+    /// Largest RTP packet currently built, headers included.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Sets the largest RTP packet (UDP payload) to build, 1400 by default.
+    /// NALs that don't fit are split into FU-A fragments of this size. Takes
+    /// effect from the next frame; path MTU discovery overrides it when
+    /// enabled. Values below 27 (RTP, integrity extension and FU-A headers
+    /// plus one byte, more with header extensions) or above the 65507-byte
+    /// UDP limit are rejected.
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), RtpError> {
+        self.check_mtu(mtu)?;
+        self.resize_packets(mtu);
+        Ok(())
+    }
+
+    fn check_mtu(&self, mtu: usize) -> Result<(), RtpError> {
+        if !(MIN_MTU..=MAX_MTU).contains(&mtu) || mtu < extensions::min_mtu(self.media_elements_size(), self.wire_overhead()) {
+            return Err(RtpError::InvalidMtu(mtu));
+        }
+        Ok(())
+    }
+
+    /// Runs `transform` on every outgoing RTP packet (media, keepalives,
+    /// duplicates and MTU probes) right before it is sent, e.g. to append a
+    /// site trailer. Returning false drops the packet; drops are counted in
+    /// `transform_drops`. Packets are built `max_overhead` bytes smaller than
+    /// the MTU so the transformed packet still fits. Fails with `InvalidMtu`
+    /// if that leaves too little room. `None` removes the transform.
+    pub fn set_packet_transform(
+        &mut self,
+        transform: Option<PacketTransform>,
+        max_overhead: usize,
+    ) -> Result<(), RtpError> {
+        let overhead = if transform.is_some() { max_overhead } else { 0 };
+        if self.mtu < extensions::min_mtu(self.media_elements_size(), overhead + self.srtp_overhead()) {
+            return Err(RtpError::InvalidMtu(self.mtu));
+        }
+        self.packet_transform = transform;
+        self.transform_overhead = overhead;
+        Ok(())
+    }
+
+    pub fn transform_drops(&self) -> u64 {
+        self.transform_drops
+    }
+
+    fn resize_packets(&mut self, mtu: usize) {
+        self.mtu = mtu;
+        self.rtp_buffer.resize(mtu, 0);
+    }
+
+    /// Chooses which packets get the marker bit. Defaults to
+    /// `AccessUnitEnd`: only the last packet of each frame passed to
+    /// `send_frame`.
+    pub fn set_marker_policy(&mut self, policy: MarkerPolicy) {
+        self.marker_policy = policy;
+    }
+
+    /// Chooses how NALs too large for one packet are cut into FU-A
+    /// fragments. Defaults to `FragmentSizing::Greedy`.
+    pub fn set_fragment_sizing(&mut self, sizing: FragmentSizing) {
+        self.fragment_sizing = sizing;
+    }
+
+    /// Stops emitting media without ending the session. Keepalives keep
+    /// flowing. Frames passed to `send_frame` meanwhile are handled per the
+    /// pause policy. Pausing twice is the same as pausing once.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Restarts media after `pause`. Frames are held back until one contains
+    /// an IDR; `needs_keyframe` reports that so the application can ask its
+    /// encoder for one. Does nothing if the pusher is not paused.
+    pub fn unpause(&mut self) {
+        if self.paused {
+            self.paused = false;
+            self.awaiting_keyframe = true;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// True after `unpause` until a frame with an IDR has been sent.
+    pub fn needs_keyframe(&self) -> bool {
+        self.awaiting_keyframe
+    }
+
+    pub fn set_pause_policy(&mut self, policy: PausePolicy) {
+        self.pause_policy = policy;
+    }
+
+    /// Sets SO_SNDBUF and returns the size the kernel actually applied.
+    /// A larger buffer absorbs IDR bursts that would otherwise fail with
+    /// ENOBUFS.
+    pub fn set_send_buffer_size(&self, bytes: usize) -> Result<usize, RtpError> {
+        platform::set_send_buffer_size(&self.socket, bytes)
+    }
+
+    /// When the kernel has no room for a packet (ENOBUFS/EAGAIN), wait this
+    /// long and retry once before counting it as a kernel drop. `None`
+    /// counts the drop without retrying.
+    pub fn set_send_retry_delay(&mut self, delay: Option<Duration>) {
+        self.send_retry_delay = delay;
+    }
+
+    /// Lets `token` interrupt a send from another thread: the pacing wait
+    /// (see `set_pacing`), the ENOBUFS retry delay and TCP reconnect
+    /// delays. Cancelling wakes the wait at once and the send returns
+    /// `RtpError::Cancelled`; the rest of the frame is not sent, so the
+    /// pusher then drops frames until one carries an IDR, as after
+    /// `unpause`. `stream_access_units` uses its own token instead while it
+    /// runs, if given one. `None` makes sends uninterruptible again.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
+    /// Drops a frame whole, rather than sending it late, when its last
+    /// packet would leave more than `bound` after it was submitted. A frame
+    /// is submitted when a send call gets it, or in realtime
+    /// `stream_access_units` at its scheduled moment, so a stream that fell
+    /// behind counts the delay too. The departure of the last packet is
+    /// predicted before anything is sent, from the pacer's backlog (see
+    /// `set_pacing`) and the frame's size on the wire, rounded up: every
+    /// NAL as the most FU-A fragments it could need, duplicates counted as
+    /// a second copy of the frame. So with pacing no packet leaves later
+    /// than the bound, and a large IDR that cannot drain in time is dropped
+    /// before its first fragment. Without pacing only the streaming delay
+    /// counts. A dropped frame never reaches the pacer, so the frames after
+    /// it find the bucket as it was; dropping an IDR also leaves the pusher
+    /// waiting for the next one (see `needs_keyframe`), since the frames in
+    /// between could not be decoded. `None` (the default) never drops.
+    ///
+    /// The time a frame takes to send is bounded only through the pacer:
+    /// a full kernel queue (`set_send_retry_delay`) or TCP reconnects can
+    /// still hold packets back.
+    pub fn set_latency_bound(&mut self, bound: Option<Duration>) {
+        self.latency_bound = bound;
+    }
+
+    /// Frames dropped by the latency bound, not counting those dropped
+    /// afterwards while waiting for an IDR.
+    pub fn late_frames_dropped(&self) -> u64 {
+        self.late_frames_dropped
+    }
+
+    /// Packets dropped because the kernel send queue was full.
+    pub fn kernel_drops(&self) -> u64 {
+        self.kernel_drops
+    }
+
+    /// Sends a copy of every RTP packet to `destination` as well, from a
+    /// separate socket, so the stream survives loss on either network path.
+    /// `H264RtpReceiver::add_redundant_path` merges the two copies again.
+    /// Both carry the same sequence numbers and bytes. Failures on the
+    /// second path never fail a send; they are counted in
+    /// `redundant_send_errors`. `None` stops the duplication.
+    pub fn set_redundant_destination(&mut self, destination: Option<&str>) -> Result<(), RtpError> {
+        let destination = match destination {
+            Some(destination) => destination::resolve(destination, self.address_preference)?,
+            None => {
+                self.redundant_path = None;
+                return Ok(());
+            }
+        };
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind).map_err(RtpError::Bind)?;
+        self.redundant_path = Some((socket, destination));
+        Ok(())
+    }
+
+    /// Packets that could not be sent over the redundant path.
+    pub fn redundant_send_errors(&self) -> u64 {
+        self.redundant_send_errors
+    }
+
+    /// Hands every RTP packet to `sink` instead of the UDP socket, e.g. to
+    /// encrypt it or to collect the packets in memory. Replaces a TCP
+    /// connection from `connect_tcp`; `None` goes back to UDP. The UDP
+    /// socket stays bound for RTCP, path MTU probes and `local_addr`.
+    pub fn set_sink(&mut self, sink: Option<Box<dyn RtpSink>>) {
+        self.transport = match sink {
+            Some(sink) => Transport::Sink(sink),
+            None => Transport::Udp
+        };
+    }
+
+    /// Protects every RTP packet from now on with SRTP under `key` (see the
+    /// `srtp` module): the payload is encrypted and a 10-byte tag appended
+    /// as the packet leaves, after the packet transform. Packets are built
+    /// that much smaller than the MTU; fails with `InvalidMtu` if that
+    /// leaves too little room. Replays and retransmissions are protected as
+    /// streams of their own. `None` goes back to plain RTP.
+    ///
+    /// The rollover counter starts at 0 and follows the sequence number.
+    /// Use a fresh key after `resume`, which does not carry it over,
+    /// and never move the sequence number back with `set_next_sequence`
+    /// under the same key: either would encrypt two packets alike.
+    #[cfg(feature = "srtp")]
+    pub fn set_srtp(&mut self, key: Option<&crate::srtp::SrtpKey>) -> Result<(), RtpError> {
+        let overhead = if key.is_some() { crate::srtp::TAG_SIZE } else { 0 };
+        if self.mtu < extensions::min_mtu(self.media_elements_size(), self.transform_overhead + overhead) {
+            return Err(RtpError::InvalidMtu(self.mtu));
+        }
+        self.srtp = key.map(crate::srtp::Context::new);
+        if let Some(retransmission) = self.retransmission.as_mut() {
+            retransmission.srtp = None;
+        }
+        Ok(())
+    }
+
+    /// Writes every RTP packet sent from now on into a pcap file at `path`
+    /// (see the `capture` module), for opening in Wireshark. Replaces a
+    /// running capture.
+    pub fn enable_capture<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RtpError> {
+        self.set_capture(Some(PcapWriter::create(path)?));
+        Ok(())
+    }
+
+    /// Captures into `writer`, e.g. one with size rotation set up; `None`
+    /// stops capturing and closes the file. Packets are captured as they
+    /// leave, after the packet transform, duplicates and keepalives
+    /// included, and are written as UDP from the socket's local address to
+    /// the destination whatever the transport. The file is flushed after
+    /// every frame. A failed write stops the capture but not the stream;
+    /// see `capture_error`.
+    pub fn set_capture(&mut self, writer: Option<PcapWriter>) {
+        let local = self.socket.local_addr().unwrap_or(capture::UNKNOWN);
+        self.capture.set(writer, local, self.destination);
+    }
+
+    /// Why the capture stopped, if a write to it failed.
+    pub fn capture_error(&self) -> Option<&RtpError> {
+        self.capture.error()
+    }
+
+    /// Marks outgoing packets with a DSCP code point (0-63), e.g. 46 for EF.
+    /// Returns `RtpError::UnsupportedOnPlatform` where the OS has no usable
+    /// IP_TOS option.
+    pub fn set_dscp(&self, dscp: u8) -> Result<(), RtpError> {
+        platform::set_dscp(&self.socket, dscp)
+    }
+
+    /// Restricts the socket to one network interface (SO_BINDTODEVICE on
+    /// Linux, IP_BOUND_IF on macOS). Linux needs CAP_NET_RAW for this.
+    pub fn bind_to_device(&self, interface: &str) -> Result<(), RtpError> {
+        platform::bind_to_device(&self.socket, interface)
+    }
+
+    /// The address the pusher sends from, with the actual port when an
+    /// ephemeral one was requested.
+    pub fn local_addr(&self) -> Result<SocketAddr, RtpError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// The SSRC, random unless set, for SDP and signalling.
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    /// Replaces the random SSRC picked at construction, e.g. with one
+    /// signalled in SDP. Change it only between sessions: receivers treat a
+    /// new SSRC as a new stream.
+    pub fn set_ssrc(&mut self, ssrc: u32) {
+        self.ssrc = ssrc;
+    }
+
+    pub fn payload_type(&self) -> u8 {
+        self.payload_type
+    }
+
+    /// Sets the RTP payload type, 96 by default. Use the dynamic type
+    /// negotiated for H.264 (96-127). Fails with `InvalidPayloadType` if
+    /// it does not fit in 7 bits.
+    pub fn set_payload_type(&mut self, payload_type: u8) -> Result<(), RtpError> {
+        self.payload_type = check_payload_type(payload_type)?;
+        Ok(())
+    }
+
+    /// A session description (RFC 4566) for players such as ffplay or VLC:
+    /// destination, payload type, `packetization-mode=1` and, once the
+    /// stream has carried them, `profile-level-id` and
+    /// `sprop-parameter-sets` from the last SPS and PPS sent. With Sender
+    /// Reports enabled it also names the CNAME and an RTCP port other than
+    /// RTP + 1.
+    pub fn sdp(&self) -> String {
+        let reports = self.sender_reports.as_ref();
+        let default_rtcp_port = self.destination.port().checked_add(1);
+        let origin = match self.socket.local_addr() {
+            Ok(local) => local.ip(),
+            Err(_) => Ipv4Addr::UNSPECIFIED.into()
+        };
+        sdp::describe(&sdp::StreamDescription {
+            origin,
+            destination: self.destination,
+            rtcp_port: reports
+                .map(|reports| reports.destination.port())
+                .filter(|&port| Some(port) != default_rtcp_port),
+            payload_type: self.payload_type,
+            ssrc: self.ssrc,
+            cname: reports.map(|reports| reports.cname.as_str()),
+            sps: self.sps.as_deref(),
+            pps: self.pps.as_deref(),
+            control: None,
+            rtx: self.retransmission.as_ref().map(|rtx| (rtx.payload_type, rtx.ssrc))
+        })
+    }
+
+    /// Sequence number the next packet will carry. The first one is
+    /// random, e.g. for an RTP-Info header.
+    pub fn next_sequence(&self) -> u16 {
+        self.rtp_seq
+    }
+
+    /// Overrides the sequence number of the next packet, for splicing or
+    /// failover between senders. Moving it backwards makes receivers treat
+    /// the following packets as duplicates or late arrivals, and a jump of
+    /// more than a few thousand looks like a new stream. Only allowed
+    /// between frames: while a `push_nal` frame still waits for its last
+    /// NAL this returns `RtpError::FrameInProgress` and changes nothing.
+    pub fn set_next_sequence(&mut self, seq: u16) -> Result<(), RtpError> {
+        self.check_between_frames()?;
+        self.rtp_seq = seq;
+        Ok(())
+    }
+
+    /// Value added to the media clock to form the RTP timestamp.
+    pub fn current_timestamp_offset(&self) -> u32 {
+        self.timestamp_offset
+    }
+
+    /// Changes the RTP timestamp offset from the next frame on. A step is
+    /// seen by receivers as a timing jump. `clock_anchor` follows the new
+    /// offset. Frames sent with their own PTS through
+    /// `send_frame_with_timestamp` or `stream_access_units` are not offset.
+    /// Like `set_next_sequence`, fails with `RtpError::FrameInProgress`
+    /// in the middle of a `push_nal` frame.
+    pub fn set_timestamp_offset(&mut self, offset: u32) -> Result<(), RtpError> {
+        self.check_between_frames()?;
+        self.timestamp_offset = offset;
+        Ok(())
+    }
+
+    fn check_between_frames(&self) -> Result<(), RtpError> {
+        if self.nal_frame.is_some() {
+            return Err(RtpError::FrameInProgress);
+        }
+        Ok(())
+    }
+
+    /// Sends access units paired with their 90 kHz PTS, which is used as the
+    /// RTP timestamp. They may be owned or borrowed, e.g. from
+    /// `annexb::MmapAnnexBSource`. With `realtime`, each frame goes out at
+    /// the wall-clock moment its PTS implies relative to the first frame, so
+    /// variable frame rate files keep their timing; otherwise frames are
+    /// sent back to back.
+    ///
+    /// `cancel` (or else the token from `set_cancel_token`) aborts the
+    /// stream with `RtpError::Cancelled`, waking the realtime wait between
+    /// frames or, within a frame, the waits `set_cancel_token` lists. The
+    /// call returns within thread wake-up latency of the cancel. A frame
+    /// cut short leaves the pusher waiting for an IDR, see
+    /// `set_cancel_token`; otherwise it can keep streaming.
+    pub fn stream_access_units<I, F>(
+        &mut self,
+        access_units: I,
+        realtime: bool,
+        cancel: Option<&CancelToken>,
+    ) -> Result<StreamSummary, RtpError>
+    where
+        I: IntoIterator<Item = (F, u32)>,
+        F: AsRef<[u8]>,
+    {
+        let own = cancel.map(|token| self.cancel.replace(token.clone()));
+        let result = self.stream_frames(access_units, realtime);
+        if let Some(own) = own {
+            self.cancel = own;
+        }
+        result
+    }
+
+    fn stream_frames<I, F>(&mut self, access_units: I, realtime: bool) -> Result<StreamSummary, RtpError>
+    where
+        I: IntoIterator<Item = (F, u32)>,
+        F: AsRef<[u8]>,
+    {
+        let cancel = self.cancel.clone();
+        let cancel = cancel.as_ref();
+        let start = self.clock.now();
+        let packets_before = self.packets_sent;
+        let bytes_before = self.bytes_sent;
+        let mut summary = StreamSummary::default();
+
+        let mut first_pts: Option<u32> = None;
+        let mut last_pts: u32 = 0;
+        // Ticks since the first frame, accumulated so PTS wrap is harmless.
+        // Signed because PTS may step back (B-frames in decode order).
+        let mut elapsed_ticks: i64 = 0;
+
+        for (frame, pts) in access_units {
+            if let Some(token) = cancel {
+                token.check()?;
+            }
+
+            match first_pts {
+                None => first_pts = Some(pts),
+                Some(_) => elapsed_ticks += wire_diff(pts, last_pts) as i64
+            }
+            last_pts = pts;
+
+            if realtime {
+                let offset = MediaTimestamp::new(elapsed_ticks.max(0) as u64, VIDEO_CLOCK_RATE);
+                let due = start + offset.to_duration();
+                self.wait_until(due, cancel)?;
+                self.frame_due = Some(due);
+            }
+
+            let packets_before_frame = self.packets_sent;
+            let result = self.send_frame_with_timestamp(frame.as_ref(), pts);
+            self.frame_due = None;
+            result?;
+            if self.packets_sent != packets_before_frame {
+                summary.frames += 1;
+            }
+        }
+
+        summary.packets = self.packets_sent - packets_before;
+        summary.bytes = self.bytes_sent - bytes_before;
+        summary.elapsed = self.clock.now().duration_since(start);
+        Ok(summary)
+    }
+
+    /// Packetizes and sends one Annex B access unit.
+    ///
+    /// Packets are built in a buffer owned by the pusher, so a frame must be
+    /// fully sent before the next one starts. User code does run mid-frame:
+    /// the packet transform, a sink transport and header extension closures
+    /// see every packet. None of them is handed the pusher, so a nested
+    /// `send_frame` does not compile. A closure that reaches the pusher
+    /// through a `RefCell` or `Mutex` finds it already borrowed, and panics
+    /// or deadlocks rather than interleaving two frames in the buffer.
+    pub fn send_frame(&mut self, frame_buffer: &[u8]) -> Result<(), RtpError> {
+        let nals: Vec<_> = nal_units(frame_buffer).collect();
+        self.send_nals(&nals)
+    }
+
+    /// Like `send_frame` for an access unit in AVCC form (ISO/IEC 14496-15),
+    /// as MP4 demuxers and many hardware encoders produce it: every NAL is
+    /// preceded by its big-endian length in `length_size` bytes (1, 2 or 4,
+    /// `lengthSizeMinusOne + 1` from the avcC box). The NALs are sent
+    /// straight from `frame`, without rewriting it to Annex B. A length that
+    /// runs past the end of the frame is `RtpError::InvalidNal` and nothing
+    /// is sent.
+    pub fn send_frame_avcc(&mut self, frame: &[u8], length_size: u8) -> Result<(), RtpError> {
+        let nals = avcc_nal_units(frame, length_size)?;
+        self.send_nals(&nals)
+    }
+
+    fn send_nals(&mut self, nals: &[(H264NalType, &[u8])]) -> Result<(), RtpError> {
+        // A frame left open by push_nal ends here.
+        self.nal_frame = None;
+        if !self.admit_frame(nals.iter().any(|&(nal_type, _)| nal_type == H264NalType::Idr))? {
+            return Ok(());
+        }
+
+        validate_frame(nals, self.max_packet_size())?;
+
+        let injected = if self.inject_parameter_sets {
+            self.parameter_sets_to_inject(nals)
+        } else {
+            Vec::new()
+        };
+
+        let kept = self.shed_nals(&injected, nals);
+        let nals = kept.as_deref().unwrap_or(nals);
+        if nals.is_empty() {
+            return Ok(());
+        }
+
+        if self.misses_latency_bound(&injected, nals) {
+            self.late_frames_dropped += 1;
+            if nals.iter().any(|&(nal_type, _)| nal_type == H264NalType::Idr) {
+                self.awaiting_keyframe = true;
+            }
+            return Ok(());
+        }
+
+        if self.integrity_extension_id.is_some() {
+            self.frame_crc = Some(extensions::frame_crc(&injected, nals, self.rewrite_nri));
+        }
+
+        // One timestamp for every packet of the access unit, sampled before
+        // the first one goes out.
+        self.rtp_ts = match self.frame_ts {
+            Some(ts) => ts,
+            None => self.get_timestamp().wrapping_add(self.timestamp_offset)
+        };
+
+        // A send error abandons the rest of the frame; the pusher is left
+        // ready for the next one.
+        let result = self.packetize_frame(&injected, nals);
+        self.finish_frame(result)
+    }
+
+    // Whether a frame may go out now, given whether it starts a keyframe.
+    // Also probes the path MTU when that is due.
+    fn admit_frame(&mut self, keyframe: bool) -> Result<bool, RtpError> {
+        if self.closed {
+            return Err(RtpError::Closed);
+        }
+        if self.paused {
+            return match self.pause_policy {
+                PausePolicy::Drop => Ok(false),
+                PausePolicy::Reject => Err(RtpError::Paused)
+            };
+        }
+        if self.awaiting_keyframe {
+            if !keyframe {
+                return match self.pause_policy {
+                    PausePolicy::Drop => Ok(false),
+                    PausePolicy::Reject => Err(RtpError::AwaitingKeyframe)
+                };
+            }
+            self.awaiting_keyframe = false;
+        }
+
+        if let Some(interval) = self.path_mtu_interval {
+            let now = self.clock.now();
+            if self.path_mtu_probed_at.is_none_or(|probed_at| now.duration_since(probed_at) >= interval) {
+                self.discover_path_mtu()?;
+            }
+        }
+        Ok(true)
+    }
+
+    // Whether the frame's last packet would leave more than the latency
+    // bound after the frame was submitted.
+    fn misses_latency_bound(&self, injected: &[Vec<u8>], nals: &[(H264NalType, &[u8])]) -> bool {
+        let bound = match self.latency_bound {
+            Some(bound) => bound,
+            None => return false
+        };
+        let now = self.clock.now();
+        let mut late = self.frame_due.map_or(Duration::ZERO, |due| now.saturating_duration_since(due));
+        if let Some(pacer) = &self.pacer {
+            late += pacer.drain_time(self.frame_wire_bytes(injected, nals), now);
+        }
+        late > bound
+    }
+
+    // At least the bytes the pacer is charged for the frame: each NAL as
+    // though it needed FU-A, in the most fragments it could take, each
+    // with the whole per-packet overhead. Duplicates count the frame twice.
+    // Aggregation only saves bytes.
+    pub(super) fn frame_wire_bytes(&self, injected: &[Vec<u8>], nals: &[(H264NalType, &[u8])]) -> usize {
+        let budget = self.max_packet_size() - RTP_HEADER_SIZE - FU_A_SIZE;
+        let overhead = RTP_HEADER_SIZE + FU_A_SIZE + self.extension_size() + self.wire_overhead();
+        let bytes = injected
+            .iter()
+            .map(Vec::as_slice)
+            .chain(nals.iter().map(|&(_, nal_buf)| nal_buf))
+            .map(|nal_buf| nal_buf.len() + nal_buf.len().div_ceil(budget).max(1) * overhead)
+            .sum::<usize>();
+        if self.duplicates.is_some() {
+            bytes * 2
+        } else {
+            bytes
+        }
+    }
+
+    // Per-frame bookkeeping once the frame's last packet was sent, or its
+    // sending failed.
+    fn finish_frame(&mut self, result: Result<(), RtpError>) -> Result<(), RtpError> {
+        // A frame cut short leaves the decoder without a reference.
+        if let Err(RtpError::Cancelled) = result {
+            self.awaiting_keyframe = true;
+        }
+        self.frame_crc = None;
+        self.rtp_is_frame_start = true;
+        self.payload_accounting.finish_frame();
+
+        // Duplicates never trail into the next frame.
+        if let Some(scheduler) = self.duplicates.as_mut() {
+            let pending = scheduler.drain();
+            self.send_duplicates(pending);
+        }
+        let flushed = self.flush_batch();
+        self.capture.flush();
+        result?;
+        flushed?;
+        self.send_accounting.record_frame();
+        // RTCP is best effort here: a lost report must not fail the frame.
+        // poll_sender_report surfaces the error to callers who care.
+        let _ = self.poll_sender_report();
+        Ok(())
+    }
+
+    /// Sends one NAL unit (no start code) of the current access unit, for
+    /// encoders that deliver NALs one at a time. It goes out in a single
+    /// packet or as FU-A fragments, exactly as in `send_frame`; pass
+    /// `is_last_in_frame` with the frame's final NAL to set the marker bit
+    /// and end the frame.
+    ///
+    /// The first NAL of a frame fixes its RTP timestamp: `timestamp` if
+    /// given (not offset, as with `send_frame_with_timestamp`), otherwise
+    /// the clock at that moment. Later NALs of the frame keep it.
+    ///
+    /// Pausing and the keyframe wait apply per frame, judged by its first
+    /// NAL: after `unpause` a frame must start with a parameter set or an
+    /// IDR slice. STAP-A aggregation and parameter-set injection need the
+    /// whole frame and are not done here; SPS and PPS are still cached for
+    /// later `send_frame` calls. Empty input and bytes that are not a NAL
+    /// header are `RtpError::InvalidNal`.
+    pub fn push_nal(&mut self, nal: &[u8], is_last_in_frame: bool, timestamp: Option<u32>) -> Result<(), RtpError> {
+        let nal_type = match nal.first() {
+            None => return Err(RtpError::InvalidNal("empty NAL")),
+            Some(&header) if !is_sendable(header) => {
+                return Err(RtpError::InvalidNal("NAL type is reserved for RTP payload structures"))
+            }
+            Some(&header) => H264NalType::from_header(header)
+        };
+        validate_nal(nal, self.max_packet_size())?;
+
+        if self.nal_frame.is_none() {
+            let keyframe = matches!(nal_type, H264NalType::Sps | H264NalType::Pps | H264NalType::Idr);
+            if !self.admit_frame(keyframe)? {
+                if !is_last_in_frame {
+                    self.nal_frame = Some(NalFrame::Dropping);
+                }
+                return Ok(());
+            }
+            self.rtp_ts = match timestamp {
+                Some(ts) => ts,
+                None => self.get_timestamp().wrapping_add(self.timestamp_offset)
+            };
+            let crc = self.integrity_extension_id.map(|_| integrity::Crc32::new());
+            self.nal_frame = Some(NalFrame::Sending { crc });
+        }
+
+        match self.nal_frame.as_mut() {
+            Some(NalFrame::Sending { crc }) => {
+                if let Some(crc) = crc.as_mut() {
+                    crc.update(&[nal_header(nal[0], &nal_type, self.rewrite_nri)]);
+                    crc.update(&nal[1..]);
+                    if is_last_in_frame {
+                        self.frame_crc = Some(crc.finish());
+                    }
+                }
+            }
+            _ => {
+                if is_last_in_frame {
+                    self.nal_frame = None;
+                }
+                return Ok(());
+            }
+        }
+
+        match nal_type {
+            H264NalType::Sps => self.parameter_sets.insert_sps(nal),
+            H264NalType::Pps => self.parameter_sets.insert_pps(nal),
+            _ => {}
+        }
+        self.rtp_is_last_nal = is_last_in_frame;
+        let result = self.handle_nal(nal, nal_type);
+        if is_last_in_frame {
+            self.nal_frame = None;
+            self.finish_frame(result)
+        } else {
+            // The caller may take a while to deliver the next NAL.
+            let flushed = self.flush_batch();
+            result?;
+            flushed
+        }
+    }
+
+    /// Like `send_frame`, but every packet of the frame carries `rtp_ts`
+    /// instead of a wall-clock timestamp, for sources with known PTS such
+    /// as files or recordings. `time::video_rtp_timestamp` converts a PTS to
+    /// 90 kHz units. The timestamp offset is not applied.
+    pub fn send_frame_with_timestamp(&mut self, frame_buffer: &[u8], rtp_ts: u32) -> Result<(), RtpError> {
+        self.frame_ts = Some(rtp_ts);
+        let result = self.send_frame(frame_buffer);
+        self.frame_ts = None;
+        result
+    }
+
+    fn packetize_frame(&mut self, injected: &[Vec<u8>], frame_nals: &[(H264NalType, &[u8])]) -> Result<(), RtpError> {
+        let mut nals: Vec<(H264NalType, &[u8], bool)> = injected
+            .iter()
+            .map(|nal| (parameter_set_type(nal), nal.as_slice(), false))
+            .collect();
+        nals.extend(frame_nals.iter().map(|&(nal_type, nal_buf)| (nal_type, nal_buf, false)));
+        // Only the frame's final NAL ends the access unit.
+        if let Some(last) = nals.last_mut() {
+            last.2 = true;
+        }
+
+        let mut rest = nals.as_slice();
+        while !rest.is_empty() {
+            let run = if self.aggregate_nals { self.stap_a_run(rest) } else { 0 };
+            if run > 1 {
+                self.send_stap_a(&rest[..run])?;
+                rest = &rest[run..];
+            } else {
+                let (nal_type, nal_buf, is_last) = rest[0];
+                self.rtp_is_last_nal = is_last;
+                self.handle_nal(nal_buf, nal_type)?;
+                rest = &rest[1..];
+            }
+        }
+        Ok(())
+    }
+
+    // Number of NALs from the start of `nals` that fit in one STAP-A packet.
+    fn stap_a_run(&self, nals: &[(H264NalType, &[u8], bool)]) -> usize {
+        let max_packet_size = self.max_packet_size();
+        let mut size = RTP_HEADER_SIZE + STAP_A_HEADER_SIZE;
+        nals.iter()
+            .take(MAX_STAP_A_UNITS)
+            .take_while(|(_, nal_buf, _)| {
+                size += STAP_A_UNIT_HEADER_SIZE + nal_buf.len();
+                size <= max_packet_size
+            })
+            .count()
+    }
+
+    // Sends `nals` as one STAP-A packet. Its F bit is set if any NAL has it
+    // and its NRI is the highest of theirs.
+    fn send_stap_a(&mut self, nals: &[(H264NalType, &[u8], bool)]) -> Result<(), RtpError> {
+        let (mut forbidden, mut nri) = (0, 0);
+        let mut offset = RTP_HEADER_SIZE + STAP_A_HEADER_SIZE;
+        for &(nal_type, nal_buf, _) in nals {
+            let nal_header = self.record_nal(nal_buf, nal_type);
+            forbidden |= nal_header & 0x80;
+            nri = nri.max(nal_header & 0x60);
+
+            let size = nal_buf.len();
+            self.rtp_buffer[offset..offset + STAP_A_UNIT_HEADER_SIZE]
+                .copy_from_slice(&(size as u16).to_be_bytes());
+            offset += STAP_A_UNIT_HEADER_SIZE;
+            self.rtp_buffer[offset..offset + size].copy_from_slice(nal_buf);
+            self.rtp_buffer[offset] = nal_header;
+            offset += size;
+        }
+        self.rtp_buffer[RTP_HEADER_SIZE] = forbidden | nri | NAL_TYPE_STAP_A;
+
+        // Replay and duplication treat the packet as its most important NAL:
+        // an IDR if it has one, else a parameter set.
+        let rank = |nal_type: &H264NalType| match nal_type {
+            H264NalType::Idr => 2,
+            H264NalType::Sps | H264NalType::Pps => 1,
+            _ => 0
+        };
+        let (first_type, _, _) = nals[0];
+        self.rtp_nal_type = nals
+            .iter()
+            .map(|&(nal_type, _, _)| nal_type)
+            .rev()
+            .max_by_key(rank)
+            .unwrap_or(first_type) as u8;
+
+        self.rtp_buffer_size = offset;
+        self.rtp_is_last = true;
+        self.rtp_is_last_nal = nals[nals.len() - 1].2;
+        self.emit_packet()
+    }
+
+    /// Payload bytes sent so far per NAL type and per IDR/non-IDR access
+    /// unit, for tuning the encoder's bandwidth split.
+    pub fn payload_stats(&self) -> PayloadStats {
+        self.payload_accounting.snapshot()
+    }
+
+    /// Packets, bytes, bitrate, frames and FU-A fragmented NALs sent since
+    /// construction or the last `reset_stats`, and the last send error.
+    pub fn stats(&self) -> SenderStats {
+        self.send_accounting.snapshot(self.clock.now())
+    }
+
+    /// Starts `stats` from zero again. RTCP Sender Reports keep counting
+    /// from the start of the stream, as RFC 3550 requires.
+    pub fn reset_stats(&mut self) {
+        self.send_accounting.reset();
+    }
+
+    /// Sets the window `SenderStats::bitrate` is averaged over, by default
+    /// `DEFAULT_BITRATE_WINDOW`. Shorter follows changes faster but is
+    /// noisier. The bitrate starts over from the new window.
+    pub fn set_bitrate_window(&mut self, window: Duration) {
+        self.send_accounting.set_bitrate_window(window);
+    }
+
+    // Caches the frame's own SPS/PPS and, for an IDR frame without a PPS,
+    // returns the cached ones it needs.
+    fn parameter_sets_to_inject(&mut self, nals: &[(H264NalType, &[u8])]) -> Vec<Vec<u8>> {
+        let mut idr: Option<&[u8]> = None;
+        let mut has_pps = false;
+
+        for &(nal_type, nal_buf) in nals {
+            match nal_type {
+                H264NalType::Sps => self.parameter_sets.insert_sps(nal_buf),
+                H264NalType::Pps => {
+                    self.parameter_sets.insert_pps(nal_buf);
+                    has_pps = true;
+                }
+                H264NalType::Idr if idr.is_none() => idr = Some(nal_buf),
+                _ => {}
+            }
+        }
+
+        match idr {
+            Some(idr) if !has_pps && !self.parameter_sets.is_empty() => self
+                .parameter_sets
+                .select_for_idr(idr)
+                .into_iter()
+                .map(<[u8]>::to_vec)
+                .collect(),
+            _ => Vec::new()
+        }
+    }
+
+    // Bookkeeping for a NAL about to be sent. Returns its NAL header byte.
+    fn record_nal(&mut self, nal_buf: &[u8], nal_type: H264NalType) -> u8 {
+        // Remember parameter sets for session persistence.
+        match nal_type {
+            H264NalType::Sps => self.sps = Some(nal_buf.to_vec()),
+            H264NalType::Pps => self.pps = Some(nal_buf.to_vec()),
+            _ => {}
+        }
+        // The raw type: H264NalType folds the ones it doesn't name into 0.
+        self.payload_accounting.record_nal(nal_buf[0] & 0x1F, nal_buf.len());
+        nal_header(nal_buf[0], &nal_type, self.rewrite_nri)
+    }
+
+    fn handle_nal(&mut self, nal_buf: &[u8], nal_type: H264NalType) -> Result<(), RtpError> {
+        let max_packet_size = self.max_packet_size();
+        let nal_header = self.record_nal(nal_buf, nal_type);
+        self.rtp_nal_type = nal_type as u8;
+
+        // Nal does not need FU-A fragmentation.
+        if nal_buf.len() + RTP_HEADER_SIZE <= max_packet_size {
+            self.rtp_buffer_size = nal_buf.len() + RTP_HEADER_SIZE;
+            self.rtp_is_last = true;
+
+            let offset = RTP_HEADER_SIZE; // start copying after the RTP header
+            let len = nal_buf.len();      // number of bytes to copy
+
+            // Slice the destination exactly the same length as the source
+            self.rtp_buffer[offset..offset + len].copy_from_slice(nal_buf);
+            self.rtp_buffer[offset] = nal_header;
+
+            // Send over UDP.
+            self.emit_packet()?;
+        } else {
+            self.send_accounting.record_fragmented_nal();
+            // Available size for fragment payload = max buffer - RTP header - FU-A header
+            let budget = max_packet_size - RTP_HEADER_SIZE - FU_A_SIZE;
+            for (fu_a, fragment) in fu_a_fragments(nal_header, nal_buf, budget, self.fragment_sizing) {
+                // Total RTP payload = FU-A header + fragment
+                self.rtp_buffer_size = RTP_HEADER_SIZE + FU_A_SIZE + fragment.len();
+                self.rtp_buffer[RTP_HEADER_SIZE..RTP_HEADER_SIZE + FU_A_SIZE].copy_from_slice(&fu_a);
+                self.rtp_buffer[RTP_HEADER_SIZE + FU_A_SIZE..self.rtp_buffer_size].copy_from_slice(fragment);
+                self.rtp_is_last = fu_a[1] & FU_END != 0;
+
+                // Send RTP packet
+                self.emit_packet()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Packet size available to handle_nal, leaving room for the header
+    // extension when there is one.
+    fn max_packet_size(&self) -> usize {
+        self.mtu - self.wire_overhead() - self.extension_size()
+    }
+
+    // Bytes added to a packet after it is built: the packet transform's,
+    // and the SRTP authentication tag.
+    fn wire_overhead(&self) -> usize {
+        self.transform_overhead + self.srtp_overhead()
+    }
+
+    fn srtp_overhead(&self) -> usize {
+        #[cfg(feature = "srtp")]
+        if self.srtp.is_some() {
+            return crate::srtp::TAG_SIZE;
+        }
+        0
+    }
+
+    fn emit_packet(&mut self) -> Result<(), RtpError> {
+        let mut rtp_header = RtpHeader {
+            byte1: 0,
+            byte2: 0,
+            seq: 0,
+            ssrc: 0,
+            ts: 0
+        };
+
+        let marker = match self.marker_policy {
+            MarkerPolicy::AccessUnitEnd => self.rtp_is_last && self.rtp_is_last_nal,
+            MarkerPolicy::EveryNal => self.rtp_is_last,
+            MarkerPolicy::Never => false
+        };
+        if marker {
+            rtp_header.byte2 |= 1 << 7;
+        }
+
+        rtp_header.byte2 |= self.payload_type;
+        rtp_header.byte1 |= 2 << 6;
+
+        rtp_header.seq = self.rtp_seq;
+        rtp_header.ts = self.rtp_ts;
+        rtp_header.ssrc = self.ssrc;
+
+        self.rtp_seq = self.rtp_seq.wrapping_add(1);
+
+        // Before the integrity extension, if any, is inserted.
+        let payload_size = self.rtp_buffer_size - RTP_HEADER_SIZE;
+
+        // FU-A packets after the first leave the Start bit clear.
+        let payload = &self.rtp_buffer[RTP_HEADER_SIZE..];
+        let first_fragment = payload[0] & 0x1F != 28 || payload[1] & (1 << 7) != 0;
+
+        // The frame CRC goes on the last packet of the last NAL only.
+        let crc = match self.integrity_extension_id {
+            Some(id) if self.rtp_is_last && self.rtp_is_last_nal => {
+                self.frame_crc.take().map(|crc| (id, crc.to_be_bytes()))
+            }
+            _ => None
+        };
+        let extension_size =
+            extension::block_size(self.media_elements_size() + crc.map_or(0, |_| integrity::ELEMENT_SIZE));
+        if extension_size > 0 {
+            rtp_header.byte1 |= 1 << 4; // X bit
+            let info = PacketInfo {
+                sequence: rtp_header.seq,
+                timestamp: rtp_header.ts,
+                marker,
+                frame_start: self.rtp_is_frame_start,
+                nal_type: self.rtp_nal_type
+            };
+            self.rtp_buffer
+                .copy_within(RTP_HEADER_SIZE..self.rtp_buffer_size, RTP_HEADER_SIZE + extension_size);
+            // abs-send-time is filled in as the packet goes out.
+            let send_time = [0; extension::ABS_SEND_TIME_SIZE];
+            extension::write_block(
+                &mut self.rtp_buffer[RTP_HEADER_SIZE..RTP_HEADER_SIZE + extension_size],
+                self.abs_send_time_id.map(|id| (id, &send_time[..])),
+                &mut self.header_extensions,
+                &info,
+                crc.as_ref().map(|(id, crc)| (*id, &crc[..])),
+            );
+            self.rtp_buffer_size += extension_size;
+        }
+        self.rtp_is_frame_start = false;
+
+        let rtp_header_buffer = rtp_header.copy_into_array();
+
+        self.rtp_buffer[..RTP_HEADER_SIZE].copy_from_slice(&rtp_header_buffer);
+
+        // Moved out for the call so the send path can borrow self mutably.
+        let packet = std::mem::take(&mut self.rtp_buffer);
+        self.queue_packets = self.pacer.is_none();
+        let sent = self.send_media(&packet[..self.rtp_buffer_size]);
+        self.queue_packets = false;
+        self.rtp_buffer = packet;
+        sent?;
+
+        let now = self.clock.now();
+        self.last_packet_sent_at = Some(now);
+        self.last_media_packet = Some((now, self.rtp_ts));
+        self.packets_sent += 1;
+        self.bytes_sent += self.rtp_buffer_size as u64;
+        self.payload_bytes_sent += payload_size as u64;
+        self.send_accounting.record_packet(self.rtp_buffer_size, now);
+
+        if let Some(replay_buffer) = self.replay_buffer.as_mut() {
+            replay_buffer.record(replay::RecordedPacket {
+                sent_at: now,
+                nal_type: self.rtp_nal_type,
+                data: self.rtp_buffer[..self.rtp_buffer_size].to_vec()
+            });
+        }
+
+        if let Some(retransmission) = self.retransmission.as_mut() {
+            retransmission.record(&self.rtp_buffer[..self.rtp_buffer_size]);
+        }
+
+        if let Some(scheduler) = self.duplicates.as_mut() {
+            let packet = &self.rtp_buffer[..self.rtp_buffer_size];
+            let due = scheduler.on_packet_sent(packet, self.rtp_nal_type, first_fragment);
+            self.send_duplicates(due);
+        }
+
+        Ok(())
+    }
+
+    // Sends a media packet, retrying once after a kernel buffer drop.
+    fn send_media(&mut self, packet: &[u8]) -> Result<(), RtpError> {
+        let err = match self.send_to_destination(packet) {
+            Ok(()) => return Ok(()),
+            Err(err) if is_io_cancelled(&err) => return Err(RtpError::Cancelled),
+            Err(err) => err
+        };
+        // TCP has already retried as far as the reconnect policy allows.
+        if let Transport::Tcp(_) = self.transport {
+            let err = RtpError::Disconnected(err);
+            self.send_accounting.record_error(&err);
+            return Err(err);
+        }
+        if !platform::is_kernel_drop(&err) {
+            if platform::is_message_too_large(&err) {
+                self.path_mtu_probed_at = None;
+            }
+            let err = RtpError::Send(err);
+            self.send_accounting.record_error(&err);
+            return Err(err);
+        }
+        let retried = match self.send_retry_delay {
+            Some(delay) => {
+                self.clock.sleep(delay, self.cancel.as_ref())?;
+                self.send_to_destination(packet).is_ok()
+            }
+            None => false
+        };
+        if !retried {
+            self.kernel_drops += 1;
+        }
+        Ok(())
+    }
+
+    fn send_to_destination(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        self.send_paced(packet, false)
+    }
+
+    // Like send_to_destination for a packet of the control class (see
+    // set_control_priority).
+    fn send_control(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        self.send_paced(packet, true)
+    }
+
+    // Every RTP packet to the destination goes through here, so the packet
+    // transform and SRTP see all of them.
+    fn send_paced(&mut self, packet: &[u8], control: bool) -> std::io::Result<()> {
+        let mut transformed = None;
+        if let Some(transform) = self.packet_transform.as_mut() {
+            let mut packet = packet.to_vec();
+            if !transform(&mut packet) {
+                self.transform_drops += 1;
+                return Ok(());
+            }
+            transformed = Some(packet);
+        }
+        let size = transformed.as_ref().map_or(packet.len(), Vec::len) + self.srtp_overhead();
+        // After the transform, so the pacer sees the bytes that go out, and
+        // before the send time is stamped and SRTP signs it.
+        if let Some(pacer) = self.pacer.as_mut() {
+            let now = self.clock.now();
+            let wait = if control {
+                pacer.reserve_control(size, now)
+            } else {
+                pacer.reserve(size, now)
+            };
+            if !wait.is_zero() {
+                self.clock
+                    .sleep(wait, self.cancel.as_ref())
+                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::Interrupted, err))?;
+                self.pacing_wait += wait;
+            }
+        }
+        if let Some(id) = self.abs_send_time_id {
+            let packet = transformed.get_or_insert_with(|| packet.to_vec());
+            extension::stamp_abs_send_time(packet, id, self.wall_clock_now());
+        }
+        match transformed {
+            Some(packet) => self.send_protected(&packet),
+            None => self.send_protected(packet)
+        }
+    }
+
+    #[cfg(feature = "srtp")]
+    fn send_protected(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        // Moved out for the call, like rtp_buffer in emit_packet.
+        let mut srtp = match self.srtp.take() {
+            Some(srtp) => srtp,
+            None => return self.send_on_all_paths(packet)
+        };
+        let sent = match srtp.protect(packet) {
+            Ok(packet) => self.send_on_all_paths(packet),
+            Err(err) => Err(err)
+        };
+        self.srtp = Some(srtp);
+        sent
+    }
+
+    #[cfg(not(feature = "srtp"))]
+    fn send_protected(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        self.send_on_all_paths(packet)
+    }
+
+    // Only the primary path's result matters to the caller.
+    fn send_on_all_paths(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        // The destination is read here, as apply_config may change it.
+        if self.capture.is_active() {
+            self.capture.write(self.capture.local(), self.destination, packet);
+        }
+        if let Some((socket, destination)) = &self.redundant_path {
+            if socket.send_to(packet, *destination).is_err() {
+                self.redundant_send_errors += 1;
+            }
+        }
+        if self.queue_packets {
+            if let Some(batch) = self.batch.as_mut() {
+                if batch.push(packet) {
+                    return self.send_queued();
+                }
+                return Ok(());
+            }
+        }
+        match &mut self.transport {
+            Transport::Udp => self.send_udp(packet),
+            Transport::Tcp(path) => path.send(packet, self.tcp_reconnect, self.cancel.as_ref()),
+            Transport::Sink(sink) => sink.send_packet(packet).map(drop)
+        }
+    }
+
+    // Some OSes refuse send_to on a connected socket, even to its peer.
+    fn send_udp(&self, packet: &[u8]) -> std::io::Result<()> {
+        if self.socket_peer == Some(self.destination) {
+            self.socket.send(packet).map(drop)
+        } else {
+            self.socket.send_to(packet, self.destination).map(drop)
+        }
+    }
+
+    /// Replaces the time source (see the `clock` module), e.g. with a
+    /// `ManualClock` in tests. The RTP clock is anchored again to the new
+    /// clock's wall time, so call this before the first frame.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.anchor_instant = clock.now();
+        self.anchor_wall_clock = clock.wall_clock();
+        self.anchor_ticks = anchor_ticks(self.anchor_wall_clock);
+        self.clock = clock;
+    }
+
+    fn since_anchor(&self) -> Duration {
+        self.clock.now().duration_since(self.anchor_instant)
+    }
+
+    // Sleeps on the pusher's clock until `due`.
+    pub(super) fn wait_until(&self, due: Instant, cancel: Option<&CancelToken>) -> Result<(), RtpError> {
+        let now = self.clock.now();
+        self.clock.sleep(due.saturating_duration_since(now), cancel)
+    }
+
+    // The wall clock as the pusher's anchor sees it, following the
+    // monotonic clock like the RTP timestamp does.
+    fn wall_clock_now(&self) -> NtpTimestamp {
+        NtpTimestamp::from_system_time(self.anchor_wall_clock + self.since_anchor())
+    }
+
+    // Wall-clock time is only read once, at construction. After that the
+    // timestamp follows the monotonic clock, so NTP steps can't move it
+    // backwards.
+    fn get_timestamp(&self) -> u32 {
+        self.anchor_ticks.add_duration(self.since_anchor()).wire()
+    }
+
+    // The RTP timestamp send_frame gives a frame submitted at `at`, before
+    // or after now, e.g. for frames held back and sent later.
+    pub(crate) fn timestamp_at(&self, at: Instant) -> u32 {
+        let ticks = match at.checked_duration_since(self.anchor_instant) {
+            Some(since) => self.anchor_ticks.add_duration(since),
+            None => self.anchor_ticks.saturating_sub_duration(self.anchor_instant.duration_since(at))
+        };
+        ticks.wire().wrapping_add(self.timestamp_offset)
+    }
+
+    /// The wall-clock time the RTP clock was anchored to and the RTP
+    /// timestamp at that instant. RTP time advances monotonically from here
+    /// even if the system clock is stepped later.
+    pub fn clock_anchor(&self) -> ClockAnchor {
+        ClockAnchor {
+            wall_clock: self.anchor_wall_clock,
+            rtp_timestamp: self.anchor_ticks.wire().wrapping_add(self.timestamp_offset)
+        }
+    }
+}
+
+impl Drop for H264RtpPusher {
+    fn drop(&mut self) {
+        self.tcp_reconnect = None;
+        let _ = self.close();
+    }
+}
+
+// RTP clock position of a wall-clock time. A clock set before 1970 just
+// anchors at zero.
+pub(crate) fn anchor_ticks(wall_clock: SystemTime) -> MediaTimestamp {
+    let since_epoch = wall_clock.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+    MediaTimestamp::from_duration(since_epoch, VIDEO_CLOCK_RATE)
+}
+
+// RtpError for a failed send_to_destination.
+pub(super) fn send_error(err: std::io::Error) -> RtpError {
+    if is_io_cancelled(&err) {
+        RtpError::Cancelled
+    } else {
+        RtpError::Send(err)
+    }
+}
+
+pub(crate) fn check_payload_type(payload_type: u8) -> Result<u8, RtpError> {
+    match payload_type {
+        0..=127 => Ok(payload_type),
+        _ => Err(RtpError::InvalidPayloadType(payload_type))
+    }
+}
+
+// Random value seeded by the OS through std's hasher keys.
+pub(crate) fn random_u32() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
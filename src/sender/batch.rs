@@ -0,0 +1,117 @@
+use super::{H264RtpPusher, Transport};
+use crate::cancel::is_io_cancelled;
+use crate::{platform, RtpError};
+
+// Packets of the frame being sent, held back so they can leave in a few
+// batched system calls. Buffers are kept between frames and reused.
+pub struct SendBatch {
+    buffers: Vec<Vec<u8>>,
+    // Buffers in use, from the front.
+    len: usize
+}
+
+impl SendBatch {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffers: (0..capacity).map(|_| Vec::new()).collect(),
+            len: 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Copies `packet` in; true once the batch is full and must be flushed.
+    pub fn push(&mut self, packet: &[u8]) -> bool {
+        let buffer = &mut self.buffers[self.len];
+        buffer.clear();
+        buffer.extend_from_slice(packet);
+        self.len += 1;
+        self.len == self.buffers.len()
+    }
+
+    pub fn packets(&self) -> Vec<&[u8]> {
+        self.buffers[..self.len].iter().map(Vec::as_slice).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl H264RtpPusher {
+    /// Queues the packets of each frame and sends them `batch_size` at a
+    /// time in one call: sendmmsg(2) on Linux over UDP, `RtpSink::send_batch`
+    /// with a sink. Elsewhere the batch still costs one send per packet. A
+    /// frame's packets leave by the time `send_frame` returns (after each
+    /// NAL with `push_nal`). Kernel buffer drops are counted without the
+    /// retry delay. Batching is off while pacing is on. `None` (the
+    /// default) sends every packet as it is built. Fails with
+    /// `InvalidBatchSize` for a batch of 0.
+    pub fn set_send_batching(&mut self, batch_size: Option<usize>) -> Result<(), RtpError> {
+        if batch_size == Some(0) {
+            return Err(RtpError::InvalidBatchSize(0));
+        }
+        self.batch = batch_size.map(SendBatch::new);
+        Ok(())
+    }
+
+    /// Batched send calls made so far, see `set_send_batching`.
+    pub fn send_batches(&self) -> u64 {
+        self.send_batches
+    }
+
+    // Sends the queued packets in as few batched calls as the transport
+    // allows. Kernel buffer drops are counted and skipped; any other error
+    // drops the rest of the batch.
+    pub(super) fn send_queued(&mut self) -> std::io::Result<()> {
+        let batch = match self.batch.as_mut() {
+            Some(batch) if !batch.is_empty() => batch,
+            _ => return Ok(())
+        };
+        let packets = batch.packets();
+        let mut sent = 0;
+        let mut result = Ok(());
+        while sent < packets.len() {
+            let remaining = &packets[sent..];
+            self.send_batches += 1;
+            let batch_sent = match &mut self.transport {
+                Transport::Udp => {
+                    let destination = (self.socket_peer != Some(self.destination)).then_some(self.destination);
+                    platform::send_batch(&self.socket, destination, remaining)
+                }
+                Transport::Tcp(path) => path.send(remaining[0], self.tcp_reconnect, self.cancel.as_ref()).map(|()| 1),
+                Transport::Sink(sink) => sink.send_batch(remaining)
+            };
+            match batch_sent {
+                // A sink claiming to have sent nothing still moves on.
+                Ok(count) => sent += count.max(1),
+                Err(err) if platform::is_kernel_drop(&err) && !matches!(self.transport, Transport::Tcp(_)) => {
+                    self.kernel_drops += 1;
+                    sent += 1;
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        batch.clear();
+        result
+    }
+
+    pub(super) fn flush_batch(&mut self) -> Result<(), RtpError> {
+        let err = match self.send_queued() {
+            Ok(()) => return Ok(()),
+            Err(err) => err
+        };
+        let err = match self.transport {
+            _ if is_io_cancelled(&err) => RtpError::Cancelled,
+            Transport::Tcp(_) => RtpError::Disconnected(err),
+            _ => RtpError::Send(err)
+        };
+        self.send_accounting.record_error(&err);
+        Err(err)
+    }
+}
@@ -0,0 +1,282 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use super::{destination, multicast, pacer, rtcp, H264RtpPusher, MarkerPolicy, PacingPolicy, Transport};
+use crate::RtpError;
+
+/// Settings to change on a running pusher with `apply_config`. Fields left
+/// at `None` are not touched.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConfigDelta {
+    /// Fails like `new` if it does not resolve or is of the other address
+    /// family than the socket, and cannot change while sending over TCP
+    /// (see `connect_tcp`).
+    pub destination: Option<String>,
+    pub keepalive: Option<Option<Duration>>,
+    pub parameter_set_injection: Option<bool>,
+    pub nri_rewrite: Option<bool>,
+    pub send_retry_delay: Option<Option<Duration>>,
+    pub pause_policy: Option<super::PausePolicy>,
+    /// The bitrate cap, as `set_pacing` takes it.
+    pub pacing: Option<Option<PacingPolicy>>,
+    /// Fails like `set_mtu` if out of range. Path MTU discovery, when
+    /// enabled, overrides it as usual.
+    pub mtu: Option<usize>,
+    pub marker_policy: Option<MarkerPolicy>,
+    pub stap_a_aggregation: Option<bool>,
+    /// Cannot change on a live stream; setting it fails the whole delta.
+    pub ssrc: Option<u32>,
+    /// Cannot change on a live stream; setting it fails the whole delta.
+    pub clock_rate: Option<u32>
+}
+
+/// Names a `ConfigDelta` field in results and errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigField {
+    Destination,
+    Keepalive,
+    ParameterSetInjection,
+    NriRewrite,
+    SendRetryDelay,
+    PausePolicy,
+    Pacing,
+    Mtu,
+    MarkerPolicy,
+    StapAAggregation,
+    Ssrc,
+    ClockRate
+}
+
+/// Fields whose value actually changed. Fields set to their current value
+/// are not listed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AppliedChanges {
+    pub changed: Vec<ConfigField>
+}
+
+/// A legal but suspicious combination of settings, from `config_warnings`.
+/// Applications decide whether to log it or refuse to start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigWarning {
+    /// Packets beyond the Ethernet MTU go unpaced to a destination outside
+    /// the local networks, where they fragment or overrun links in bursts.
+    JumboPacketsUnpaced { mtu: usize, destination: SocketAddr },
+    /// Sender Reports go to the RTP port itself, without RTCP multiplexing
+    /// negotiated, so the receiver takes them for RTP.
+    RtcpOnRtpPort { destination: SocketAddr }
+}
+
+// The largest packet a plain Ethernet path carries unfragmented.
+const ETHERNET_MTU: usize = 1500;
+
+impl ConfigDelta {
+    pub(crate) fn fixed_fields(&self, over_tcp: bool) -> Vec<ConfigField> {
+        let mut fields = Vec::new();
+        if over_tcp && self.destination.is_some() {
+            fields.push(ConfigField::Destination);
+        }
+        if self.ssrc.is_some() {
+            fields.push(ConfigField::Ssrc);
+        }
+        if self.clock_rate.is_some() {
+            fields.push(ConfigField::ClockRate);
+        }
+        fields
+    }
+}
+
+impl H264RtpPusher {
+    /// Checks the current settings for combinations that are accepted but
+    /// likely wrong, in `ConfigWarning` order; empty if there are none.
+    /// Parameter sets are only injected before IDR frames that lack them,
+    /// so no repetition interval can be shorter than a frame.
+    pub fn config_warnings(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        if self.pacer.is_none() && self.mtu > ETHERNET_MTU && !is_local(self.destination.ip()) {
+            warnings.push(ConfigWarning::JumboPacketsUnpaced {
+                mtu: self.mtu,
+                destination: self.destination
+            });
+        }
+        if let Some(reports) = self.sender_reports.as_ref().filter(|reports| reports.destination == self.destination) {
+            warnings.push(ConfigWarning::RtcpOnRtpPort {
+                destination: reports.destination
+            });
+        }
+        warnings
+    }
+
+    /// Applies several setting changes at once, between frames. Everything
+    /// is validated first, as the matching setters and `new` would, and
+    /// fields that cannot change on a live stream fail the whole delta
+    /// with `NotLiveConfigurable` listing them, so on error nothing has
+    /// changed. A new destination is used from the next packet; sequence
+    /// numbers and timestamps continue. Sender Reports going to the old
+    /// destination's port + 1 (the `enable_sender_reports` default) move
+    /// with it, and a running capture records the new address. The result
+    /// lists the fields that changed, in `ConfigField` order.
+    pub fn apply_config(&mut self, delta: ConfigDelta) -> Result<AppliedChanges, RtpError> {
+        let fixed = delta.fixed_fields(matches!(self.transport, Transport::Tcp(_)));
+        if !fixed.is_empty() {
+            return Err(RtpError::NotLiveConfigurable(fixed));
+        }
+
+        let destination = match &delta.destination {
+            Some(address) => {
+                let destination = destination::resolve(address, self.address_preference)?;
+                destination::check_families(self.socket.local_addr()?, destination)?;
+                Some(destination).filter(|&destination| destination != self.destination)
+            }
+            None => None
+        };
+        // Only reports that followed the RTP destination follow it again.
+        let rtcp_destination = match (destination, &self.sender_reports) {
+            (Some(destination), Some(reports))
+                if rtcp::rtcp_destination_for(self.destination).ok() == Some(reports.destination) =>
+            {
+                Some(rtcp::rtcp_destination_for(destination)?)
+            }
+            _ => None
+        };
+        if let Some(Some(policy)) = delta.pacing {
+            pacer::check_policy(policy)?;
+        }
+        if let Some(mtu) = delta.mtu {
+            self.check_mtu(mtu)?;
+        }
+
+        let mut applied = AppliedChanges::default();
+        if let Some(destination) = destination {
+            if let Some(options) = &self.multicast {
+                multicast::apply(&self.socket, destination, options)?;
+            }
+            self.destination = destination;
+            self.path_mtu_probed_at = None;
+            self.capture.set_peer(destination);
+            if let (Some(reports), Some(rtcp_destination)) = (self.sender_reports.as_mut(), rtcp_destination) {
+                reports.destination = rtcp_destination;
+            }
+            applied.changed.push(ConfigField::Destination);
+        }
+        if let Some(interval) = delta.keepalive.filter(|&interval| interval != self.keepalive_interval) {
+            self.keepalive_interval = interval;
+            applied.changed.push(ConfigField::Keepalive);
+        }
+        if let Some(enabled) = delta
+            .parameter_set_injection
+            .filter(|&enabled| enabled != self.inject_parameter_sets)
+        {
+            self.inject_parameter_sets = enabled;
+            applied.changed.push(ConfigField::ParameterSetInjection);
+        }
+        if let Some(enabled) = delta.nri_rewrite.filter(|&enabled| enabled != self.rewrite_nri) {
+            self.rewrite_nri = enabled;
+            applied.changed.push(ConfigField::NriRewrite);
+        }
+        if let Some(delay) = delta.send_retry_delay.filter(|&delay| delay != self.send_retry_delay) {
+            self.send_retry_delay = delay;
+            applied.changed.push(ConfigField::SendRetryDelay);
+        }
+        if let Some(policy) = delta.pause_policy.filter(|&policy| policy != self.pause_policy) {
+            self.pause_policy = policy;
+            applied.changed.push(ConfigField::PausePolicy);
+        }
+        if let Some(policy) = delta.pacing.filter(|&policy| policy != self.pacer.as_ref().map(pacer::Pacer::policy)) {
+            self.set_pacing(policy)?;
+            applied.changed.push(ConfigField::Pacing);
+        }
+        if let Some(mtu) = delta.mtu.filter(|&mtu| mtu != self.mtu) {
+            self.resize_packets(mtu);
+            applied.changed.push(ConfigField::Mtu);
+        }
+        if let Some(policy) = delta.marker_policy.filter(|&policy| policy != self.marker_policy) {
+            self.marker_policy = policy;
+            applied.changed.push(ConfigField::MarkerPolicy);
+        }
+        if let Some(enabled) = delta.stap_a_aggregation.filter(|&enabled| enabled != self.aggregate_nals) {
+            self.aggregate_nals = enabled;
+            applied.changed.push(ConfigField::StapAAggregation);
+        }
+        Ok(applied)
+    }
+}
+
+// Loopback, private, link-local and multicast destinations, where jumbo
+// frames are a deliberate network setting.
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_multicast(),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            // Unique local fc00::/7 and link-local fe80::/10.
+            ip.is_loopback() || ip.is_multicast() || first & 0xFE00 == 0xFC00 || first & 0xFFC0 == 0xFE80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sender::DEFAULT_SENDER_REPORT_INTERVAL;
+
+    fn jumbo(destination: &str) -> H264RtpPusher {
+        let mut pusher = H264RtpPusher::new(destination).unwrap();
+        pusher.set_mtu(9000).unwrap();
+        pusher
+    }
+
+    #[test]
+    fn unpaced_jumbo_packets_to_a_wan_destination_warn() {
+        let pusher = jumbo("203.0.113.5:5004");
+        assert_eq!(
+            pusher.config_warnings(),
+            [ConfigWarning::JumboPacketsUnpaced {
+                mtu: 9000,
+                destination: "203.0.113.5:5004".parse().unwrap()
+            }]
+        );
+    }
+
+    #[test]
+    fn paced_standard_or_local_jumbo_packets_do_not_warn() {
+        let mut paced = jumbo("203.0.113.5:5004");
+        paced
+            .set_pacing(Some(PacingPolicy {
+                bits_per_second: 20_000_000,
+                max_burst_bytes: 18_000
+            }))
+            .unwrap();
+        assert!(paced.config_warnings().is_empty());
+
+        let mut standard = jumbo("203.0.113.5:5004");
+        standard.set_mtu(ETHERNET_MTU).unwrap();
+        assert!(standard.config_warnings().is_empty());
+
+        assert!(jumbo("192.168.1.20:5004").config_warnings().is_empty());
+    }
+
+    #[test]
+    fn sender_reports_to_the_rtp_port_warn() {
+        let mut pusher = H264RtpPusher::new("127.0.0.1:5004").unwrap();
+        pusher.enable_sender_reports(DEFAULT_SENDER_REPORT_INTERVAL, Some("127.0.0.1:5004")).unwrap();
+        assert_eq!(
+            pusher.config_warnings(),
+            [ConfigWarning::RtcpOnRtpPort {
+                destination: "127.0.0.1:5004".parse().unwrap()
+            }]
+        );
+    }
+
+    #[test]
+    fn sender_reports_to_the_next_port_do_not_warn() {
+        for destination in [Some("127.0.0.1:5005"), None] {
+            let mut pusher = H264RtpPusher::new("127.0.0.1:5004").unwrap();
+            pusher.enable_sender_reports(DEFAULT_SENDER_REPORT_INTERVAL, destination).unwrap();
+            assert!(pusher.config_warnings().is_empty());
+        }
+    }
+}
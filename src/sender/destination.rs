@@ -0,0 +1,116 @@
+use std::fs;
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+
+use crate::platform;
+
+const SERVICES_FILE: &str = "/etc/services";
+
+/// Which address to use when a destination host resolves to several.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// Whatever the resolver returns first.
+    #[default]
+    First,
+    V4First,
+    V6First
+}
+
+// Accepts "host:port", "1.2.3.4:port", "[v6]:port" and scoped link-local
+// addresses such as "[fe80::1%eth0]:port" or "[fe80::1%2]:port", where port
+// is a number or a UDP service name from /etc/services. Host names are
+// looked up here, once; a failed lookup names the destination.
+pub fn resolve(destination: &str, preference: AddressPreference) -> io::Result<SocketAddr> {
+    let (host, port) = split_host_port(destination)?;
+    let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => service_port(port)?
+    };
+    if let Some((address, zone)) = host.split_once('%') {
+        return scoped_v6(address, zone, port).map_err(|err| context(destination, err));
+    }
+
+    let addresses: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| context(destination, err))?
+        .collect();
+    let preferred = match preference {
+        AddressPreference::First => None,
+        AddressPreference::V4First => addresses.iter().find(|address| address.is_ipv4()),
+        AddressPreference::V6First => addresses.iter().find(|address| address.is_ipv6())
+    };
+    preferred.or(addresses.first()).copied().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("{} resolved to no address", destination))
+    })
+}
+
+// A socket of one family cannot send to the other, and the failure would
+// otherwise only show on the first send.
+pub fn check_families(bind: SocketAddr, destination: SocketAddr) -> io::Result<()> {
+    if bind.is_ipv4() == destination.is_ipv4() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("cannot send from {} to {}: address families differ", bind, destination),
+    ))
+}
+
+// The resolver's messages ("Name or service not known") do not say what
+// was being resolved.
+fn context(destination: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("could not resolve {}: {}", destination, err))
+}
+
+// The zone is an interface name or index; link-local addresses are
+// ambiguous without one on hosts with several interfaces.
+fn scoped_v6(address: &str, zone: &str, port: u16) -> io::Result<SocketAddr> {
+    let address: Ipv6Addr = address
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "a zone (%...) needs an IPv6 address"))?;
+    let scope_id = match zone.parse::<u32>() {
+        Ok(index) => index,
+        Err(_) => platform::interface_index(zone)?
+    };
+    Ok(SocketAddrV6::new(address, port, 0, scope_id).into())
+}
+
+fn split_host_port(destination: &str) -> io::Result<(&str, &str)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("destination {:?} is not host:port or [ipv6]:port", destination),
+        )
+    };
+
+    if let Some(rest) = destination.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+        let port = rest.strip_prefix(':').ok_or_else(invalid)?;
+        return Ok((host, port));
+    }
+
+    let (host, port) = destination.rsplit_once(':').ok_or_else(invalid)?;
+    // A bare IPv6 address is ambiguous with a port.
+    if host.is_empty() || port.is_empty() || host.contains(':') {
+        return Err(invalid());
+    }
+    Ok((host, port))
+}
+
+// Lines look like "rtsp  554/udp  alias ...", with '#' comments.
+fn service_port(name: &str) -> io::Result<u16> {
+    let services = fs::read_to_string(SERVICES_FILE).unwrap_or_default();
+    services
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('#').next()?.split_whitespace();
+            let service = fields.next()?;
+            let (port, protocol) = fields.next()?.split_once('/')?;
+            let matches = service == name || fields.any(|alias| alias == name);
+            (matches && protocol == "udp").then(|| port.parse().ok())?
+        })
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown UDP service {:?}", name))
+        })
+}
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+
+use super::H264RtpPusher;
+
+const NAL_TYPE_IDR: u8 = 5;
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+
+/// Which packets are sent twice on links without NACK/RTX, and how far
+/// apart. Duplicates keep the original sequence number, so receivers treat
+/// them as duplicates rather than new packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DuplicationPolicy {
+    pub parameter_sets: bool,
+    /// The packet carrying the start of each IDR NAL.
+    pub idr_first_fragment: bool,
+    /// Every packet of each IDR NAL (implies `idr_first_fragment`).
+    pub all_idr_fragments: bool,
+    /// Packets sent between an original and its duplicate. Duplicates still
+    /// pending at the end of a frame are sent then.
+    pub spacing: usize
+}
+
+impl DuplicationPolicy {
+    fn qualifies(&self, nal_type: u8, first_fragment: bool) -> bool {
+        match nal_type {
+            NAL_TYPE_SPS | NAL_TYPE_PPS => self.parameter_sets,
+            NAL_TYPE_IDR => self.all_idr_fragments || (self.idr_first_fragment && first_fragment),
+            _ => false
+        }
+    }
+}
+
+// Copies of critical packets waiting for their turn to be resent.
+pub struct DuplicateScheduler {
+    policy: DuplicationPolicy,
+    // (packets still to send before this duplicate, packet)
+    pending: VecDeque<(usize, Vec<u8>)>
+}
+
+impl DuplicateScheduler {
+    pub fn new(policy: DuplicationPolicy) -> Self {
+        Self {
+            policy,
+            pending: VecDeque::new()
+        }
+    }
+
+    // Called once per original packet sent. Returns the duplicates now due.
+    pub fn on_packet_sent(&mut self, packet: &[u8], nal_type: u8, first_fragment: bool) -> Vec<Vec<u8>> {
+        for (countdown, _) in self.pending.iter_mut() {
+            *countdown = countdown.saturating_sub(1);
+        }
+
+        if self.policy.qualifies(nal_type, first_fragment) {
+            self.pending.push_back((self.policy.spacing, packet.to_vec()));
+        }
+
+        let mut due = Vec::new();
+        while self.pending.front().is_some_and(|(countdown, _)| *countdown == 0) {
+            if let Some((_, packet)) = self.pending.pop_front() {
+                due.push(packet);
+            }
+        }
+        due
+    }
+
+    pub fn drain(&mut self) -> Vec<Vec<u8>> {
+        self.pending.drain(..).map(|(_, packet)| packet).collect()
+    }
+}
+
+impl H264RtpPusher {
+    /// Sends selected critical packets (parameter sets, IDR fragments) a
+    /// second time for lossy one-way links. `None` turns it off.
+    pub fn set_critical_packet_duplication(&mut self, policy: Option<DuplicationPolicy>) {
+        self.duplicates = policy.map(DuplicateScheduler::new);
+    }
+
+    pub fn duplicates_sent(&self) -> u64 {
+        self.duplicates_sent
+    }
+
+    pub(super) fn send_duplicates(&mut self, packets: Vec<Vec<u8>>) {
+        // Queued like the originals, so a duplicate never overtakes one.
+        self.queue_packets = self.pacer.is_none();
+        for packet in packets {
+            if self.send_to_destination(&packet).is_ok() {
+                self.duplicates_sent += 1;
+            }
+        }
+        self.queue_packets = false;
+    }
+}
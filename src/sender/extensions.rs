@@ -0,0 +1,110 @@
+// Header extension setup (frame integrity, abs-send-time, caller-defined
+// elements) and the packet size budget they take.
+
+use super::{nal_header, parameter_set_type, H264RtpPusher};
+use crate::extension::{self, HeaderExtension};
+use crate::payload::h264::H264NalType;
+use crate::rtp::RTP_HEADER_SIZE;
+use crate::{integrity, RtpError};
+
+impl H264RtpPusher {
+    /// Enables shipping a CRC32 of every access unit in a one-byte header
+    /// extension (RFC 8285) on the frame's last packet. The CRC covers the
+    /// NAL units without their start codes, so it does not depend on which
+    /// start code length the receiver writes back. Fails with
+    /// `InvalidExtensionId` if the id is outside 1..=14 or taken by another
+    /// extension. `None` disables it.
+    pub fn set_frame_integrity(&mut self, extension_id: Option<u8>) -> Result<(), RtpError> {
+        if let Some(id) = extension_id {
+            let in_use = self.header_extensions.iter().map(HeaderExtension::id);
+            extension::check_id(id, in_use.chain(self.abs_send_time_id))?;
+        }
+        self.integrity_extension_id = extension_id;
+        Ok(())
+    }
+
+    /// Stamps every media packet with the abs-send-time header extension
+    /// under `extension_id`, for receiver-side bandwidth estimation (see
+    /// the `extension` module). The time is taken from the pusher's
+    /// wall-clock anchor as the packet is handed to the socket: after
+    /// pacing and the packet transform, which sees it zeroed, and before
+    /// SRTP. Batched packets are stamped as they are queued. Fails with
+    /// `InvalidExtensionId` if the id is outside 1..=14 or taken by another
+    /// extension, and with `InvalidMtu` if the MTU leaves too little room;
+    /// `None` disables it.
+    pub fn set_abs_send_time(&mut self, extension_id: Option<u8>) -> Result<(), RtpError> {
+        let mut elements = extension::elements_size(&self.header_extensions);
+        if let Some(id) = extension_id {
+            let in_use = self.header_extensions.iter().map(HeaderExtension::id);
+            extension::check_id(id, in_use.chain(self.integrity_extension_id))?;
+            elements += 1 + extension::ABS_SEND_TIME_SIZE;
+        }
+        if self.mtu < min_mtu(elements, self.wire_overhead()) {
+            return Err(RtpError::InvalidMtu(self.mtu));
+        }
+        self.abs_send_time_id = extension_id;
+        Ok(())
+    }
+
+    /// Puts `extensions` into the one-byte header extension (RFC 8285) of
+    /// every media packet from the next one on, in list order, after
+    /// abs-send-time and ahead of the frame CRC (see `set_abs_send_time`
+    /// and `set_frame_integrity`). Keepalives, probes and
+    /// RTCP go without. NALs are fragmented to leave room for them; fails
+    /// with `InvalidMtu` if the MTU leaves too little, and with
+    /// `InvalidExtensionId` if two elements share an id. An empty list
+    /// removes them.
+    pub fn set_header_extensions(&mut self, extensions: Vec<HeaderExtension>) -> Result<(), RtpError> {
+        for (index, extension) in extensions.iter().enumerate() {
+            let in_use = extensions[..index].iter().map(HeaderExtension::id);
+            extension::check_id(extension.id(), in_use.chain(self.integrity_extension_id).chain(self.abs_send_time_id))?;
+        }
+        if self.mtu < min_mtu(extension::elements_size(&extensions) + self.abs_send_time_size(), self.wire_overhead()) {
+            return Err(RtpError::InvalidMtu(self.mtu));
+        }
+        self.header_extensions = extensions;
+        Ok(())
+    }
+
+    // The largest header extension block a media packet gets: the
+    // elements of every packet, and the frame CRC on the last one.
+    pub(super) fn extension_size(&self) -> usize {
+        let crc = if self.integrity_extension_id.is_some() { integrity::ELEMENT_SIZE } else { 0 };
+        extension::block_size(self.media_elements_size() + crc)
+    }
+
+    // Header extension elements every media packet carries, in bytes.
+    pub(super) fn media_elements_size(&self) -> usize {
+        self.abs_send_time_size() + extension::elements_size(&self.header_extensions)
+    }
+
+    fn abs_send_time_size(&self) -> usize {
+        match self.abs_send_time_id {
+            Some(_) => 1 + extension::ABS_SEND_TIME_SIZE,
+            None => 0
+        }
+    }
+}
+
+// Smallest MTU with room for the RTP header, a header extension with
+// `elements_size` bytes of elements and the frame CRC, `wire_overhead`, a
+// FU-A header and one byte of NAL; MIN_MTU when there is nothing extra.
+pub(super) fn min_mtu(elements_size: usize, wire_overhead: usize) -> usize {
+    let extension_size = extension::block_size(elements_size + integrity::ELEMENT_SIZE);
+    RTP_HEADER_SIZE + extension_size + 2 + 1 + wire_overhead
+}
+
+// CRC32 over the NAL units of a frame as they go on the wire (injected
+// parameter sets first), start codes excluded.
+pub(super) fn frame_crc(injected: &[Vec<u8>], nals: &[(H264NalType, &[u8])], rewrite_nri: bool) -> u32 {
+    let mut crc = integrity::Crc32::new();
+    for nal in injected {
+        crc.update(&[nal_header(nal[0], &parameter_set_type(nal), rewrite_nri)]);
+        crc.update(&nal[1..]);
+    }
+    for &(nal_type, nal_buf) in nals {
+        crc.update(&[nal_header(nal_buf[0], &nal_type, rewrite_nri)]);
+        crc.update(&nal_buf[1..]);
+    }
+    crc.finish()
+}
@@ -0,0 +1,186 @@
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::{random_u32, RtpSink};
+use crate::clock::{Clock, SystemClock};
+use crate::testsupport::XorShift;
+
+/// What a `FaultInjector` does to the packets that pass through it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FaultPolicy {
+    /// Passes every packet on untouched.
+    PassThrough,
+    /// Drops each packet with this chance, in percent (at most 100).
+    Drop(u8),
+    /// Holds each send back for a delay drawn evenly from `min..=max`, on
+    /// the injector's clock. The pusher waits with it, as it would for a
+    /// slow network stack.
+    Delay { min: Duration, max: Duration },
+    /// Drops every packet for this long from when the policy is set, then
+    /// passes them on again.
+    Blackhole(Duration),
+    /// Fails every send with an error of this kind. `WouldBlock` counts
+    /// as a kernel drop in the pusher, other kinds fail the frame.
+    Error(io::ErrorKind)
+}
+
+/// The faults a `FaultInjector` put in since it was created.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FaultStats {
+    /// Packets handed to the inner sink, delayed ones included.
+    pub packets_passed: u64,
+    /// Dropped by `Drop`.
+    pub packets_dropped: u64,
+    /// Dropped by `Blackhole`.
+    pub packets_blackholed: u64,
+    pub packets_delayed: u64,
+    pub total_delay: Duration,
+    /// Sends failed by `Error`.
+    pub errors_injected: u64
+}
+
+/// An `RtpSink` that wraps another and puts faults in its path on
+/// command, for chaos testing a live sender: pass it to
+/// `H264RtpPusher::set_sink` and keep a `FaultControl` to change the
+/// policy from any thread while the pusher runs. A pusher without one
+/// pays nothing for it.
+pub struct FaultInjector<S> {
+    inner: S,
+    control: FaultControl,
+    rng: XorShift
+}
+
+/// Changes the policy of a `FaultInjector` and reads its counts. Clones
+/// control the same injector.
+#[derive(Clone)]
+pub struct FaultControl {
+    state: Arc<Mutex<FaultState>>
+}
+
+struct FaultState {
+    policy: FaultPolicy,
+    // When a blackhole ends.
+    blackhole_until: Option<Instant>,
+    clock: Arc<dyn Clock>,
+    stats: FaultStats
+}
+
+impl<S: RtpSink> FaultInjector<S> {
+    /// Starts out passing everything through, on the system clock and a
+    /// random seed.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            control: FaultControl {
+                state: Arc::new(Mutex::new(FaultState {
+                    policy: FaultPolicy::PassThrough,
+                    blackhole_until: None,
+                    clock: Arc::new(SystemClock),
+                    stats: FaultStats::default()
+                }))
+            },
+            rng: XorShift::new(random_u32() as u64)
+        }
+    }
+
+    /// A handle to change the policy after the injector moved into a
+    /// pusher.
+    pub fn control(&self) -> FaultControl {
+        self.control.clone()
+    }
+
+    pub fn set_policy(&self, policy: FaultPolicy) {
+        self.control.set_policy(policy);
+    }
+
+    /// Times delays and blackholes on `clock`, e.g. the pusher's
+    /// `ManualClock` in tests.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.control.lock().clock = clock;
+    }
+
+    /// Makes which packets `Drop` drops, and the delays of `Delay`, the
+    /// same from run to run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = XorShift::new(seed);
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl FaultControl {
+    pub fn set_policy(&self, policy: FaultPolicy) {
+        let mut state = self.lock();
+        state.policy = policy;
+        state.blackhole_until = match policy {
+            FaultPolicy::Blackhole(duration) => Some(state.clock.now() + duration),
+            _ => None
+        };
+    }
+
+    pub fn policy(&self) -> FaultPolicy {
+        self.lock().policy
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.lock().stats.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FaultState> {
+        // A panic elsewhere leaves the counters usable.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<S: RtpSink> RtpSink for FaultInjector<S> {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        let (delay, clock) = {
+            let mut state = self.control.lock();
+            let delay = match state.policy {
+                FaultPolicy::PassThrough => None,
+                FaultPolicy::Drop(percent) => {
+                    if self.rng.next_u64() % 100 < percent.min(100) as u64 {
+                        state.stats.packets_dropped += 1;
+                        return Ok(packet.len());
+                    }
+                    None
+                }
+                FaultPolicy::Delay { min, max } => {
+                    let spread = max.saturating_sub(min).as_nanos() as u64;
+                    let jitter = match spread {
+                        0 => 0,
+                        spread => self.rng.next_u64() % (spread + 1)
+                    };
+                    let delay = min + Duration::from_nanos(jitter);
+                    state.stats.packets_delayed += 1;
+                    state.stats.total_delay += delay;
+                    Some(delay)
+                }
+                FaultPolicy::Blackhole(_) => {
+                    if state.blackhole_until.is_some_and(|until| state.clock.now() < until) {
+                        state.stats.packets_blackholed += 1;
+                        return Ok(packet.len());
+                    }
+                    None
+                }
+                FaultPolicy::Error(kind) => {
+                    state.stats.errors_injected += 1;
+                    return Err(io::Error::new(kind, "injected fault"));
+                }
+            };
+            (delay, state.clock.clone())
+        };
+        // Outside the lock, so the handle stays responsive.
+        if let Some(delay) = delay {
+            let _ = clock.sleep(delay, None);
+        }
+        let sent = self.inner.send_packet(packet)?;
+        self.control.lock().stats.packets_passed += 1;
+        Ok(sent)
+    }
+}
@@ -0,0 +1,430 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::destination::{self, AddressPreference};
+use super::{anchor_ticks, random_u32, DEFAULT_MTU, DEFAULT_PAYLOAD_TYPE, MAX_MTU};
+use crate::payload::h265::{
+    is_irap, is_parameter_set, nal_type, NAL_HEADER_SIZE, NAL_TYPE_AP, NAL_TYPE_FU, NAL_TYPE_VPS,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::{annexb, platform};
+use crate::rtp::{RtpHeader, RTP_HEADER_SIZE};
+use crate::stats::StreamSummary;
+use crate::time::{wire_diff, MediaTimestamp, VIDEO_CLOCK_RATE};
+use crate::{CancelToken, RtpError};
+
+const FU_HEADER_SIZE: usize = 1;
+const FU_START: u8 = 1 << 7;
+const FU_END: u8 = 1 << 6;
+// Size of each aggregated NAL, ahead of it in an AP.
+const AP_UNIT_HEADER_SIZE: usize = 2;
+// Room for the RTP header, the FU payload and FU headers and one NAL byte.
+const MIN_MTU: usize = RTP_HEADER_SIZE + NAL_HEADER_SIZE + FU_HEADER_SIZE + 1;
+
+/// Packetizes H.265 access units and sends them as RTP over UDP (RFC 7798).
+///
+/// NALs that do not fit in one packet are sent as FU fragments; with
+/// `set_aggregation` small ones share Aggregation Packets. The marker bit is
+/// set on the last packet of each access unit. Decoding order numbers are
+/// never sent, i.e. the stream is for `sprop-max-don-diff=0`.
+///
+/// Like `H264RtpPusher`, the pusher is `Send` but not `Sync`.
+pub struct H265RtpPusher {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    // The socket from from_socket is connected to the destination.
+    connected: bool,
+
+    // Always `mtu` bytes long.
+    rtp_buffer: Vec<u8>,
+    mtu: usize,
+    rtp_seq: u16,
+    ssrc: u32,
+    payload_type: u8,
+    aggregate_nals: bool,
+
+    // Latest VPS, SPS and PPS seen, resent ahead of IRAP frames that arrive
+    // without them when injection is enabled.
+    inject_parameter_sets: bool,
+    parameter_sets: [Option<Vec<u8>>; 3],
+
+    // RTP timestamp for the frame being sent when the caller supplied one.
+    frame_ts: Option<u32>,
+    packets_sent: u64,
+    bytes_sent: u64,
+    kernel_drops: u64,
+
+    // RTP clock anchor, as in H264RtpPusher.
+    anchor_instant: Instant,
+    anchor_ticks: MediaTimestamp,
+    clock: Arc<dyn Clock>
+}
+
+impl H265RtpPusher {
+    /// `destination` is resolved as for `H264RtpPusher::new`.
+    pub fn new(destination: &str) -> Result<Self, RtpError> {
+        let destination = destination::resolve(destination, AddressPreference::First)?;
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        Self::build(bind, destination)
+    }
+
+    /// Like `new`, for a destination that is already an address.
+    pub fn with_socket_addr(destination: SocketAddr) -> Result<Self, RtpError> {
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        Self::build(bind, destination)
+    }
+
+    /// Like `new`, sending from the local address `bind`, of the same
+    /// address family.
+    pub fn with_bind(bind: &str, destination: &str) -> Result<Self, RtpError> {
+        let bind = destination::resolve(bind, AddressPreference::First)?;
+        let destination = destination::resolve(destination, AddressPreference::First)?;
+        Self::build(bind, destination)
+    }
+
+    /// Sends from a socket the caller bound, as
+    /// `H264RtpPusher::from_socket` does.
+    pub fn from_socket(socket: UdpSocket, destination: SocketAddr) -> Result<Self, RtpError> {
+        destination::check_families(socket.local_addr()?, destination)?;
+        Ok(Self::from_parts(socket, destination))
+    }
+
+    fn build(bind: SocketAddr, destination: SocketAddr) -> Result<Self, RtpError> {
+        destination::check_families(bind, destination)?;
+        let socket = UdpSocket::bind(bind).map_err(RtpError::Bind)?;
+        Ok(Self::from_parts(socket, destination))
+    }
+
+    fn from_parts(socket: UdpSocket, destination: SocketAddr) -> Self {
+        let connected = socket.peer_addr().ok() == Some(destination);
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Self {
+            socket,
+            destination,
+            connected,
+            rtp_buffer: vec![0u8; DEFAULT_MTU],
+            mtu: DEFAULT_MTU,
+            rtp_seq: random_u32() as u16,
+            ssrc: random_u32(),
+            payload_type: DEFAULT_PAYLOAD_TYPE,
+            aggregate_nals: false,
+            inject_parameter_sets: false,
+            parameter_sets: [None, None, None],
+            frame_ts: None,
+            packets_sent: 0,
+            bytes_sent: 0,
+            kernel_drops: 0,
+            anchor_instant: clock.now(),
+            anchor_ticks: anchor_ticks(clock.wall_clock()),
+            clock
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, RtpError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Replaces the time source, as `H264RtpPusher::set_clock` does.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.anchor_instant = clock.now();
+        self.anchor_ticks = anchor_ticks(clock.wall_clock());
+        self.clock = clock;
+    }
+
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+
+    pub fn set_ssrc(&mut self, ssrc: u32) {
+        self.ssrc = ssrc;
+    }
+
+    pub fn payload_type(&self) -> u8 {
+        self.payload_type
+    }
+
+    /// Fails with `InvalidPayloadType` if `payload_type` does not fit in 7
+    /// bits.
+    pub fn set_payload_type(&mut self, payload_type: u8) -> Result<(), RtpError> {
+        self.payload_type = super::check_payload_type(payload_type)?;
+        Ok(())
+    }
+
+    /// Random at construction, like the SSRC.
+    pub fn next_sequence(&self) -> u16 {
+        self.rtp_seq
+    }
+
+    /// Overrides the sequence number of the next packet, see the footguns
+    /// listed on `H264RtpPusher::set_next_sequence`. Frames are always
+    /// sent whole here, so this is always between frames.
+    pub fn set_next_sequence(&mut self, seq: u16) {
+        self.rtp_seq = seq;
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    /// Largest RTP packet to send, headers included.
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), RtpError> {
+        if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+            return Err(RtpError::InvalidMtu(mtu));
+        }
+        self.mtu = mtu;
+        self.rtp_buffer.resize(mtu, 0);
+        Ok(())
+    }
+
+    /// Packs runs of consecutive NALs that fit in one packet together (VPS,
+    /// SPS, PPS, SEI) into Aggregation Packets.
+    pub fn set_aggregation(&mut self, enabled: bool) {
+        self.aggregate_nals = enabled;
+    }
+
+    /// Sends the latest VPS, SPS and PPS ahead of IRAP frames that do not
+    /// carry all three, so receivers joining late can decode.
+    pub fn set_parameter_set_injection(&mut self, enabled: bool) {
+        self.inject_parameter_sets = enabled;
+    }
+
+    /// Packets the kernel refused (ENOBUFS/EAGAIN).
+    pub fn kernel_drops(&self) -> u64 {
+        self.kernel_drops
+    }
+
+    /// Sends `(access unit, pts)` pairs as `H264RtpPusher::stream_access_units`
+    /// does: `pts` in 90 kHz ticks becomes the RTP timestamp and, when
+    /// `realtime` is set, paces the frames.
+    pub fn stream_access_units<I, F>(
+        &mut self,
+        access_units: I,
+        realtime: bool,
+        cancel: Option<&CancelToken>,
+    ) -> Result<StreamSummary, RtpError>
+    where
+        I: IntoIterator<Item = (F, u32)>,
+        F: AsRef<[u8]>,
+    {
+        let start = self.clock.now();
+        let packets_before = self.packets_sent;
+        let bytes_before = self.bytes_sent;
+        let mut summary = StreamSummary::default();
+
+        let mut last_pts: Option<u32> = None;
+        let mut elapsed_ticks: i64 = 0;
+
+        for (frame, pts) in access_units {
+            if let Some(token) = cancel {
+                token.check()?;
+            }
+            if let Some(last) = last_pts {
+                elapsed_ticks += wire_diff(pts, last) as i64;
+            }
+            last_pts = Some(pts);
+
+            if realtime {
+                let offset = MediaTimestamp::new(elapsed_ticks.max(0) as u64, VIDEO_CLOCK_RATE);
+                let wait = (start + offset.to_duration()).saturating_duration_since(self.clock.now());
+                self.clock.sleep(wait, cancel)?;
+            }
+
+            self.frame_ts = Some(pts);
+            let result = self.send_frame(frame.as_ref());
+            self.frame_ts = None;
+            result?;
+            summary.frames += 1;
+        }
+
+        summary.packets = self.packets_sent - packets_before;
+        summary.bytes = self.bytes_sent - bytes_before;
+        summary.elapsed = self.clock.now().duration_since(start);
+        Ok(summary)
+    }
+
+    /// Packetizes and sends one Annex B access unit. All of its packets get
+    /// the same timestamp.
+    pub fn send_frame(&mut self, frame_buffer: &[u8]) -> Result<(), RtpError> {
+        let mut nals = Vec::new();
+        for nal in annexb::nal_units(frame_buffer).map(|unit| unit.data) {
+            if nal.len() < NAL_HEADER_SIZE {
+                return Err(RtpError::InvalidNal("NAL shorter than its header"));
+            }
+            if nal[0] & 0x80 != 0 {
+                return Err(RtpError::InvalidNal("forbidden_zero_bit is set"));
+            }
+            if nal[1] & 0x07 == 0 {
+                return Err(RtpError::InvalidNal("nuh_temporal_id_plus1 is zero"));
+            }
+            nals.push(nal);
+        }
+        if nals.is_empty() {
+            return Err(RtpError::InvalidNal("no start code followed by a NAL"));
+        }
+        // A NAL fragmented over more than half the sequence space could not
+        // be ordered by receivers.
+        let max_nal_size = (u16::MAX as usize / 2) * self.fragment_size();
+        if let Some(nal) = nals.iter().find(|nal| nal.len() > max_nal_size) {
+            return Err(RtpError::NalTooLarge(nal.len()));
+        }
+
+        let injected = self.parameter_sets_to_inject(&nals);
+        let mut all: Vec<&[u8]> = injected.iter().map(Vec::as_slice).collect();
+        all.extend_from_slice(&nals);
+
+        let ts = match self.frame_ts {
+            Some(ts) => ts,
+            None => self.anchor_ticks.add_duration(self.clock.now().duration_since(self.anchor_instant)).wire()
+        };
+
+        let mut rest = all.as_slice();
+        while !rest.is_empty() {
+            let run = if self.aggregate_nals { self.aggregation_run(rest) } else { 0 };
+            if run > 1 {
+                self.send_aggregate(&rest[..run], ts, run == rest.len())?;
+                rest = &rest[run..];
+            } else {
+                self.send_nal(rest[0], ts, rest.len() == 1)?;
+                rest = &rest[1..];
+            }
+        }
+        Ok(())
+    }
+
+    // Caches the frame's parameter sets and, for an IRAP frame missing some,
+    // returns the cached ones to send first.
+    fn parameter_sets_to_inject(&mut self, nals: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut present = [false; 3];
+        let mut irap = false;
+        for nal in nals {
+            let nal_type = nal_type(nal);
+            if is_parameter_set(nal_type) {
+                let index = (nal_type - NAL_TYPE_VPS) as usize;
+                self.parameter_sets[index] = Some(nal.to_vec());
+                present[index] = true;
+            }
+            irap |= is_irap(nal_type);
+        }
+
+        if !self.inject_parameter_sets || !irap || present.iter().all(|&present| present) {
+            return Vec::new();
+        }
+        // All three, in VPS, SPS, PPS order, so the frame's own ones that
+        // follow still take effect.
+        self.parameter_sets.iter().flatten().cloned().collect()
+    }
+
+    // Largest FU fragment that fits in a packet.
+    fn fragment_size(&self) -> usize {
+        self.mtu - RTP_HEADER_SIZE - NAL_HEADER_SIZE - FU_HEADER_SIZE
+    }
+
+    // Number of NALs from the start of `nals` that fit in one AP.
+    fn aggregation_run(&self, nals: &[&[u8]]) -> usize {
+        let mut size = RTP_HEADER_SIZE + NAL_HEADER_SIZE;
+        nals.iter()
+            .take_while(|nal| {
+                size += AP_UNIT_HEADER_SIZE + nal.len();
+                size <= self.mtu
+            })
+            .count()
+    }
+
+    fn send_nal(&mut self, nal: &[u8], ts: u32, last_in_frame: bool) -> Result<(), RtpError> {
+        let payload = RTP_HEADER_SIZE..RTP_HEADER_SIZE + nal.len();
+        if payload.end <= self.mtu {
+            self.rtp_buffer[payload.clone()].copy_from_slice(nal);
+            return self.send_packet(payload.end, ts, last_in_frame);
+        }
+
+        // FU payload header: the NAL's own, with the type replaced.
+        let payload_header = [(nal[0] & 0x81) | (NAL_TYPE_FU << 1), nal[1]];
+        let mut fu_header = FU_START | nal_type(nal);
+        let fragment_start = RTP_HEADER_SIZE + NAL_HEADER_SIZE + FU_HEADER_SIZE;
+
+        let mut remaining = &nal[NAL_HEADER_SIZE..];
+        while !remaining.is_empty() {
+            let size = remaining.len().min(self.fragment_size());
+            let last_fragment = size == remaining.len();
+            if last_fragment {
+                fu_header |= FU_END;
+            }
+
+            self.rtp_buffer[RTP_HEADER_SIZE..RTP_HEADER_SIZE + NAL_HEADER_SIZE]
+                .copy_from_slice(&payload_header);
+            self.rtp_buffer[RTP_HEADER_SIZE + NAL_HEADER_SIZE] = fu_header;
+            self.rtp_buffer[fragment_start..fragment_start + size].copy_from_slice(&remaining[..size]);
+            self.send_packet(fragment_start + size, ts, last_in_frame && last_fragment)?;
+
+            remaining = &remaining[size..];
+            fu_header &= !FU_START;
+        }
+        Ok(())
+    }
+
+    // RFC 7798 section 4.4.2: F is set if any aggregated NAL has it, LayerId
+    // and TID are the lowest of theirs.
+    fn send_aggregate(&mut self, nals: &[&[u8]], ts: u32, last_in_frame: bool) -> Result<(), RtpError> {
+        let forbidden = nals.iter().fold(0, |forbidden, nal| forbidden | (nal[0] & 0x80));
+        let layer_id = nals
+            .iter()
+            .map(|nal| (u16::from_be_bytes([nal[0], nal[1]]) >> 3) & 0x3F)
+            .min()
+            .unwrap_or(0);
+        let tid = nals.iter().map(|nal| nal[1] & 0x07).min().unwrap_or(1);
+
+        let header = ((NAL_TYPE_AP as u16) << 9) | (layer_id << 3) | tid as u16;
+        self.rtp_buffer[RTP_HEADER_SIZE..RTP_HEADER_SIZE + NAL_HEADER_SIZE]
+            .copy_from_slice(&header.to_be_bytes());
+        self.rtp_buffer[RTP_HEADER_SIZE] |= forbidden;
+
+        let mut offset = RTP_HEADER_SIZE + NAL_HEADER_SIZE;
+        for nal in nals {
+            self.rtp_buffer[offset..offset + AP_UNIT_HEADER_SIZE]
+                .copy_from_slice(&(nal.len() as u16).to_be_bytes());
+            offset += AP_UNIT_HEADER_SIZE;
+            self.rtp_buffer[offset..offset + nal.len()].copy_from_slice(nal);
+            offset += nal.len();
+        }
+        self.send_packet(offset, ts, last_in_frame)
+    }
+
+    // Sends rtp_buffer[..size] after filling in its RTP header.
+    fn send_packet(&mut self, size: usize, ts: u32, marker: bool) -> Result<(), RtpError> {
+        let header = RtpHeader {
+            byte1: 2 << 6,
+            byte2: ((marker as u8) << 7) | self.payload_type,
+            seq: self.rtp_seq,
+            ts,
+            ssrc: self.ssrc
+        };
+        self.rtp_buffer[..RTP_HEADER_SIZE].copy_from_slice(&header.copy_into_array());
+        self.rtp_seq = self.rtp_seq.wrapping_add(1);
+
+        let packet = &self.rtp_buffer[..size];
+        // Some OSes refuse send_to on a connected socket, even to its peer.
+        let sent = if self.connected {
+            self.socket.send(packet)
+        } else {
+            self.socket.send_to(packet, self.destination)
+        };
+        match sent {
+            Ok(_) => {}
+            Err(err) if platform::is_kernel_drop(&err) => {
+                self.kernel_drops += 1;
+                return Ok(());
+            }
+            Err(err) => return Err(RtpError::Send(err))
+        }
+        self.packets_sent += 1;
+        self.bytes_sent += size as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+// NAT keepalives: padding-only RTP packets while no frames are sent.
+use std::time::Duration;
+
+use super::H264RtpPusher;
+use crate::rtp::{RtpHeader, RTP_HEADER_SIZE};
+use crate::RtpError;
+
+// Padding-only keepalive payload: the last padding byte holds the count.
+const KEEPALIVE_PADDING: [u8; 4] = [0, 0, 0, 4];
+
+impl H264RtpPusher {
+    /// Keeps NAT mappings alive while no frames are sent. After `interval`
+    /// without any RTP packet, `poll_keepalive` sends a padding-only RTP
+    /// packet that reuses the last timestamp. `None` disables keepalives.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive_interval = interval;
+    }
+
+    /// Call periodically (e.g. from the capture loop) while the stream may
+    /// be idle. Returns true if a keepalive was sent.
+    pub fn poll_keepalive(&mut self) -> Result<bool, RtpError> {
+        let interval = match self.keepalive_interval {
+            Some(interval) if !self.stream_ended => interval,
+            _ => return Ok(false)
+        };
+        let idle = match self.last_packet_sent_at {
+            Some(sent_at) => self.clock.now().duration_since(sent_at) >= interval,
+            None => true
+        };
+        if !idle {
+            return Ok(false);
+        }
+
+        let rtp_header = RtpHeader {
+            byte1: (2 << 6) | (1 << 5), // version 2, P bit
+            byte2: self.payload_type,
+            seq: self.rtp_seq,
+            ts: self.rtp_ts,
+            ssrc: self.ssrc
+        };
+        self.rtp_seq = self.rtp_seq.wrapping_add(1);
+
+        let mut packet = [0u8; RTP_HEADER_SIZE + KEEPALIVE_PADDING.len()];
+        packet[..RTP_HEADER_SIZE].copy_from_slice(&rtp_header.copy_into_array());
+        packet[RTP_HEADER_SIZE..].copy_from_slice(&KEEPALIVE_PADDING);
+        self.send_control(&packet).map_err(super::send_error)?;
+
+        self.last_packet_sent_at = Some(self.clock.now());
+        self.keepalives_sent += 1;
+        Ok(true)
+    }
+
+    pub fn keepalives_sent(&self) -> u64 {
+        self.keepalives_sent
+    }
+
+    /// Marks the stream as finished. No keepalives are sent afterwards.
+    pub fn end_of_stream(&mut self) {
+        self.stream_ended = true;
+    }
+}
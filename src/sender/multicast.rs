@@ -0,0 +1,55 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use super::H264RtpPusher;
+use crate::{platform, RtpError};
+
+/// Socket settings for sending to an IPv4 multicast group. The default
+/// matches what the kernel does without them: TTL 1, the interface the
+/// routing table picks, and loopback on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MulticastOptions {
+    /// Router hops the packets survive; 1 keeps them on the local network.
+    pub ttl: u32,
+    /// Address of the interface to send from (IP_MULTICAST_IF).
+    pub interface: Option<Ipv4Addr>,
+    /// Whether receivers on this host get the packets too.
+    pub loopback: bool
+}
+
+impl Default for MulticastOptions {
+    fn default() -> Self {
+        Self {
+            ttl: 1,
+            interface: None,
+            loopback: true
+        }
+    }
+}
+
+// Applies `options` to `socket` if `destination` is an IPv4 multicast
+// address; other destinations leave the socket alone.
+pub fn apply(socket: &UdpSocket, destination: SocketAddr, options: &MulticastOptions) -> Result<(), RtpError> {
+    if !matches!(destination, SocketAddr::V4(v4) if v4.ip().is_multicast()) {
+        return Ok(());
+    }
+    socket.set_multicast_ttl_v4(options.ttl)?;
+    socket.set_multicast_loop_v4(options.loopback)?;
+    if let Some(interface) = options.interface {
+        platform::set_multicast_interface_v4(socket, interface)?;
+    }
+    Ok(())
+}
+
+impl H264RtpPusher {
+    /// Sets the multicast TTL, outgoing interface and loopback used while
+    /// the destination is an IPv4 multicast group, e.g. 239.1.2.3:5004.
+    /// They apply right away if it is one, and again whenever
+    /// `apply_config` moves the stream to a group. Without this call the
+    /// kernel defaults (TTL 1) keep multicast on the local network. Setting
+    /// the interface returns `RtpError::UnsupportedOnPlatform` on Windows.
+    pub fn set_multicast(&mut self, options: MulticastOptions) -> Result<(), RtpError> {
+        apply(&self.socket, self.destination, &options)?;
+        self.multicast = Some(options);
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use std::time::{Duration, Instant};
+
+use super::H264RtpPusher;
+use crate::payload::h264::{nal_header, H264NalType};
+use crate::stats::PriorityDrops;
+use crate::RtpError;
+
+/// Token-bucket limit on how fast a pusher sends. Packets go out at once
+/// while the bucket holds enough bytes for them; after that each waits
+/// just until the bucket has refilled by its size, so the fragments of a
+/// large IDR are spread out instead of arriving at a router in one burst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacingPolicy {
+    /// Long-term sending rate, RTP headers included.
+    pub bits_per_second: u64,
+    /// Bytes that may leave back to back after an idle period. A packet
+    /// larger than this still goes out, once the bucket is full.
+    pub max_burst_bytes: usize
+}
+
+pub struct Pacer {
+    policy: PacingPolicy,
+    // Bytes that may be sent right now; negative while a packet is waiting
+    // for the deficit to refill.
+    tokens: f64,
+    refilled_at: Instant,
+    // Lets control packets skip the wait, up to their own rate.
+    control: Option<ControlBudget>
+}
+
+// A second bucket, of the same burst size, that only limits how much
+// control traffic bypasses the media wait.
+struct ControlBudget {
+    bytes_per_second: f64,
+    tokens: f64,
+    refilled_at: Instant
+}
+
+impl Pacer {
+    // Starts with a full bucket.
+    pub fn new(policy: PacingPolicy, now: Instant) -> Self {
+        Self {
+            policy,
+            tokens: policy.max_burst_bytes as f64,
+            refilled_at: now,
+            control: None
+        }
+    }
+
+    pub fn policy(&self) -> PacingPolicy {
+        self.policy
+    }
+
+    // Up to `bits_per_second` of control packets skip the wait; `None`
+    // paces them like media.
+    pub fn set_control_cap(&mut self, bits_per_second: Option<u64>, now: Instant) {
+        self.control = bits_per_second.map(|bits_per_second| ControlBudget {
+            bytes_per_second: bits_per_second as f64 / 8.0,
+            tokens: self.policy.max_burst_bytes as f64,
+            refilled_at: now
+        });
+    }
+
+    // Takes `size` bytes from the bucket and returns how long to wait
+    // before sending them; zero when they fit now.
+    pub fn reserve(&mut self, size: usize, now: Instant) -> Duration {
+        let bytes_per_second = self.policy.bits_per_second as f64 / 8.0;
+        self.refill(now);
+        // A packet waits for the bucket to be full at most, however large.
+        let needed = (size as f64).min(self.policy.max_burst_bytes as f64);
+        let wait = if self.tokens >= needed || bytes_per_second <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((needed - self.tokens) / bytes_per_second)
+        };
+        self.tokens -= size as f64;
+        // The wait below is time the bucket refills during; start counting
+        // from when the packet actually leaves.
+        self.tokens += wait.as_secs_f64() * bytes_per_second;
+        self.refilled_at = now + wait;
+        wait
+    }
+
+    // Like `reserve` for a control packet. Within the control cap it goes
+    // out at once, its bytes still taken from the media bucket, so the
+    // media after it makes up for them.
+    pub fn reserve_control(&mut self, size: usize, now: Instant) -> Duration {
+        let burst = self.policy.max_burst_bytes as f64;
+        if let Some(control) = self.control.as_mut() {
+            let elapsed = now.saturating_duration_since(control.refilled_at).as_secs_f64();
+            control.refilled_at = control.refilled_at.max(now);
+            control.tokens = (control.tokens + elapsed * control.bytes_per_second).min(burst);
+            if control.tokens >= size as f64 {
+                control.tokens -= size as f64;
+                self.refill(now);
+                self.tokens -= size as f64;
+                return Duration::ZERO;
+            }
+        }
+        self.reserve(size, now)
+    }
+
+    // Adds what the bucket gained since it was last refilled. A packet
+    // still waiting has credited its wait already.
+    fn refill(&mut self, now: Instant) {
+        let bytes_per_second = self.policy.bits_per_second as f64 / 8.0;
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.refilled_at = self.refilled_at.max(now);
+        self.tokens = (self.tokens + elapsed * bytes_per_second).min(self.policy.max_burst_bytes as f64);
+    }
+
+    // How long from `now` until `bytes` more have been let through, if
+    // they are sent back to back: no later than this, their last packet
+    // leaves.
+    pub fn drain_time(&self, bytes: usize, now: Instant) -> Duration {
+        let bytes_per_second = self.policy.bits_per_second as f64 / 8.0;
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        let tokens = (self.tokens + elapsed * bytes_per_second).min(self.policy.max_burst_bytes as f64);
+        if bytes as f64 <= tokens || bytes_per_second <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((bytes as f64 - tokens) / bytes_per_second)
+        }
+    }
+}
+
+impl H264RtpPusher {
+    /// Limits the send rate with a token bucket (see `PacingPolicy`), so
+    /// large frames leave as a paced train of packets rather than a burst
+    /// that overflows small router queues. The send call sleeps for the
+    /// pacing delay. `None` (the default) sends as fast as the socket
+    /// takes packets. Every packet to the destination counts, duplicates
+    /// and keepalives included. Fails with `InvalidPacing` if the rate or
+    /// the burst is zero.
+    pub fn set_pacing(&mut self, policy: Option<PacingPolicy>) -> Result<(), RtpError> {
+        if let Some(policy) = policy {
+            check_policy(policy)?;
+        }
+        let now = self.clock.now();
+        self.pacer = policy.map(|policy| {
+            let mut pacer = Pacer::new(policy, now);
+            pacer.set_control_cap(self.control_priority, now);
+            pacer
+        });
+        Ok(())
+    }
+
+    /// Lets control packets (keepalives, path MTU probes, RTCP Sender
+    /// Reports and BYE) skip the pacing wait, so they are not stuck behind
+    /// the fragments of a large IDR. Their bytes still count against the
+    /// pacing rate: the media that follows waits for them. Beyond
+    /// `max_bits_per_second` of control traffic (bursts up to the pacing
+    /// burst) control packets are paced like media, so they cannot starve
+    /// it. `None` (the default) paces keepalives and probes like media and
+    /// leaves RTCP, which has its own socket, out of the pacer entirely.
+    /// Has no effect without pacing.
+    pub fn set_control_priority(&mut self, max_bits_per_second: Option<u64>) {
+        self.control_priority = max_bits_per_second;
+        let now = self.clock.now();
+        if let Some(pacer) = self.pacer.as_mut() {
+            pacer.set_control_cap(max_bits_per_second, now);
+        }
+    }
+
+    // Waits, if need be, before a control packet of `size` bytes goes out
+    // on a socket other than the media one. Only paced with control
+    // priority on.
+    pub(super) fn pace_control(&mut self, size: usize) -> Result<(), RtpError> {
+        if self.control_priority.is_none() {
+            return Ok(());
+        }
+        let now = self.clock.now();
+        let wait = match self.pacer.as_mut() {
+            Some(pacer) => pacer.reserve_control(size, now),
+            None => return Ok(())
+        };
+        if !wait.is_zero() {
+            self.clock.sleep(wait, self.cancel.as_ref())?;
+            self.pacing_wait += wait;
+        }
+        Ok(())
+    }
+
+    /// Total time the pacer has held packets back.
+    pub fn pacing_wait(&self) -> Duration {
+        self.pacing_wait
+    }
+
+    /// Under pacing, sheds the least important NALs of a frame the pacer
+    /// could not drain within `max_backlog`: first those with NRI 0 (SEI,
+    /// AUD, filler, disposable slices), then, if that is not enough, the
+    /// other non-IDR slices. SPS, PPS and IDR slices are never dropped.
+    /// The frame's last remaining NAL carries the marker bit. Dropping a
+    /// non-IDR slice with a non-zero NRI leaves the pusher waiting for the
+    /// next IDR (see `needs_keyframe`), since later frames refer to it.
+    /// The NRI is read after `set_nri_rewrite`, so encoders that mark every
+    /// NAL with NRI 3 can still be shed by type. Drops are counted per
+    /// class in `priority_drops`. `None` (the default) sends every NAL,
+    /// however long the pacer holds it; so does a pusher without pacing.
+    pub fn set_priority_dropping(&mut self, max_backlog: Option<Duration>) {
+        self.priority_dropping = max_backlog;
+    }
+
+    pub fn priority_drops(&self) -> PriorityDrops {
+        self.priority_drops
+    }
+
+    // The frame's NALs with the least important ones shed, or None when
+    // all of them can go out.
+    pub(super) fn shed_nals<'a>(
+        &mut self,
+        injected: &[Vec<u8>],
+        nals: &[(H264NalType, &'a [u8])],
+    ) -> Option<Vec<(H264NalType, &'a [u8])>> {
+        let max_backlog = self.priority_dropping?;
+        let now = self.clock.now();
+        let fits = |pusher: &Self, nals: &[(H264NalType, &[u8])]| {
+            pusher.pacer.as_ref().is_none_or(|pacer| {
+                pacer.drain_time(pusher.frame_wire_bytes(injected, nals), now) <= max_backlog
+            })
+        };
+        if fits(self, nals) {
+            return None;
+        }
+
+        let rewrite_nri = self.rewrite_nri;
+        let disposable = |&(nal_type, nal_buf): &(H264NalType, &[u8])| {
+            !never_shed(nal_type) && nal_header(nal_buf[0], &nal_type, rewrite_nri) & 0b0110_0000 == 0
+        };
+        let mut kept: Vec<_> = nals.iter().copied().filter(|nal| !disposable(nal)).collect();
+        self.priority_drops.disposable_nals += (nals.len() - kept.len()) as u64;
+        if fits(self, &kept) {
+            return Some(kept);
+        }
+
+        let before = kept.len();
+        kept.retain(|&(nal_type, _)| never_shed(nal_type));
+        if kept.len() < before {
+            self.priority_drops.non_idr_slices += (before - kept.len()) as u64;
+            self.awaiting_keyframe = true;
+        }
+        Some(kept)
+    }
+}
+
+fn never_shed(nal_type: H264NalType) -> bool {
+    matches!(nal_type, H264NalType::Sps | H264NalType::Pps | H264NalType::Idr)
+}
+
+pub(super) fn check_policy(policy: PacingPolicy) -> Result<(), RtpError> {
+    if policy.bits_per_second == 0 || policy.max_burst_bytes == 0 {
+        return Err(RtpError::InvalidPacing(policy));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8000 bit/s: one byte per millisecond.
+    fn pacer(max_burst_bytes: usize) -> (Pacer, Instant) {
+        let start = Instant::now();
+        let policy = PacingPolicy {
+            bits_per_second: 8_000,
+            max_burst_bytes
+        };
+        (Pacer::new(policy, start), start)
+    }
+
+    #[test]
+    fn burst_leaves_at_once_then_packets_wait_for_their_size() {
+        let (mut pacer, start) = pacer(300);
+        assert_eq!(pacer.reserve(100, start), Duration::ZERO);
+        assert_eq!(pacer.reserve(200, start), Duration::ZERO);
+        assert_eq!(pacer.reserve(50, start), Duration::from_millis(50));
+        // The next packet queues behind the one still waiting.
+        assert_eq!(pacer.reserve(50, start + Duration::from_millis(50)), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn idle_time_refills_no_more_than_the_burst() {
+        let (mut pacer, start) = pacer(300);
+        assert_eq!(pacer.reserve(300, start), Duration::ZERO);
+        let later = start + Duration::from_secs(10);
+        assert_eq!(pacer.reserve(300, later), Duration::ZERO);
+        assert_eq!(pacer.reserve(10, later), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn packet_larger_than_the_burst_waits_for_a_full_bucket() {
+        let (mut pacer, start) = pacer(100);
+        assert_eq!(pacer.reserve(1_000, start), Duration::ZERO);
+        // 900 bytes of debt plus a full bucket to wait for.
+        assert_eq!(pacer.reserve(1_000, start), Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn drain_time_bounds_when_the_last_packet_leaves() {
+        let (mut pacer, start) = pacer(300);
+        assert_eq!(pacer.drain_time(300, start), Duration::ZERO);
+        assert_eq!(pacer.drain_time(500, start), Duration::from_millis(200));
+        // Sent back to back, each packet after its wait.
+        let mut now = start;
+        for _ in 0..5 {
+            now += pacer.reserve(100, now);
+        }
+        assert_eq!(now - start, Duration::from_millis(200));
+        // The bucket is empty until it refills.
+        assert_eq!(pacer.drain_time(100, now), Duration::from_millis(100));
+        assert_eq!(pacer.drain_time(100, now + Duration::from_millis(100)), Duration::ZERO);
+    }
+}
@@ -0,0 +1,110 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use super::H264RtpPusher;
+use crate::rtp::{RtpHeader, RTP_HEADER_SIZE};
+use crate::{platform, RtpError};
+
+// Probe sizes are RTP packet (UDP payload) sizes. The floor is what any IPv4
+// path must carry (576 minus IP and UDP headers); the ceiling is an
+// Ethernet MTU minus the IPv4/IPv6 and UDP headers.
+pub const MIN_PROBE_SIZE: usize = 548;
+pub const MAX_PROBE_SIZE_V4: usize = 1472;
+pub const MAX_PROBE_SIZE_V6: usize = 1452;
+
+// RTP header plus the empty header extension a probe is padded with.
+const PROBE_OVERHEAD: usize = RTP_HEADER_SIZE + 4;
+
+// Binary search for the largest size `probe` accepts, between `low` (assumed
+// to work) and `high`. Only EMSGSIZE means "too large"; any other error ends
+// the search.
+pub fn search<F>(low: usize, high: usize, mut probe: F) -> io::Result<usize>
+where
+    F: FnMut(usize) -> io::Result<()>,
+{
+    let (mut low, mut high) = (probe_size(low), probe_size(high));
+    while low < high {
+        let mid = probe_size((low + high) / 2 + 4);
+        match probe(mid) {
+            Ok(()) => low = mid,
+            Err(err) if platform::is_message_too_large(&err) => high = mid - 4,
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(low)
+}
+
+// Probes are padded with whole 32-bit extension words.
+fn probe_size(size: usize) -> usize {
+    PROBE_OVERHEAD + (size.saturating_sub(PROBE_OVERHEAD) / 4) * 4
+}
+
+// An RTP packet of exactly `size` bytes with no payload: the header is
+// followed by an RFC 8285 extension block made only of padding bytes, which
+// receivers skip. `header` is the 12-byte RTP header to use; the X bit is
+// set here.
+pub fn probe_packet(header: [u8; RTP_HEADER_SIZE], size: usize) -> Vec<u8> {
+    let words = (size - PROBE_OVERHEAD) / 4;
+    let mut packet = vec![0u8; PROBE_OVERHEAD + words * 4];
+    packet[..RTP_HEADER_SIZE].copy_from_slice(&header);
+    packet[0] |= 1 << 4;
+    packet[RTP_HEADER_SIZE..RTP_HEADER_SIZE + 2].copy_from_slice(&[0xBE, 0xDE]);
+    packet[RTP_HEADER_SIZE + 2..RTP_HEADER_SIZE + 4].copy_from_slice(&(words as u16).to_be_bytes());
+    packet
+}
+
+impl H264RtpPusher {
+    /// Finds the largest packet that reaches the destination without IP
+    /// fragmentation and uses it as the packet size from the next frame on.
+    /// Sets Don't Fragment on the socket, then sends payload-less probe
+    /// packets (padding-only header extension, receivers skip them) of
+    /// increasing size until the kernel rejects one with EMSGSIZE. The result
+    /// reflects the interface MTU and any path MTU the kernel learned from
+    /// ICMP; routers that drop oversized packets silently are not detected.
+    /// Returns `UnsupportedOnPlatform` where DF can't be set, leaving the
+    /// packet size unchanged.
+    pub fn discover_path_mtu(&mut self) -> Result<usize, RtpError> {
+        platform::set_dont_fragment(&self.socket)?;
+
+        let high = match self.destination {
+            SocketAddr::V4(_) => MAX_PROBE_SIZE_V4,
+            SocketAddr::V6(_) => MAX_PROBE_SIZE_V6
+        };
+        let mtu = search(MIN_PROBE_SIZE, high, |size| {
+            let header = RtpHeader {
+                byte1: 2 << 6,
+                byte2: self.payload_type,
+                seq: self.rtp_seq,
+                ts: self.rtp_ts,
+                ssrc: self.ssrc
+            };
+            let probe = probe_packet(header.copy_into_array(), size);
+            self.send_control(&probe)?;
+            // Only probes that left the host use up a sequence number.
+            self.rtp_seq = self.rtp_seq.wrapping_add(1);
+            Ok(())
+        })
+        .map_err(super::send_error)?;
+
+        // Probes went through the packet transform (and SRTP), so `mtu` is
+        // what fits before it; the wire size includes its overhead.
+        let mtu = mtu + self.wire_overhead();
+        self.resize_packets(mtu);
+        self.path_mtu_probed_at = Some(self.clock.now());
+        Ok(mtu)
+    }
+
+    /// Runs `discover_path_mtu` before the next frame and then every
+    /// `interval`, and again after a destination change or an EMSGSIZE send
+    /// failure. `None` stops re-probing and keeps the current packet size.
+    /// Fails with `UnsupportedOnPlatform` where DF can't be set.
+    pub fn set_path_mtu_discovery(&mut self, interval: Option<Duration>) -> Result<(), RtpError> {
+        if interval.is_some() {
+            platform::set_dont_fragment(&self.socket)?;
+        }
+        self.path_mtu_interval = interval;
+        self.path_mtu_probed_at = None;
+        Ok(())
+    }
+}
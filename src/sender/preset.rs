@@ -0,0 +1,92 @@
+use super::ConfigDelta;
+
+/// Settings known to work for a common kind of receiver, as a
+/// `ConfigDelta` for `apply_config`. Fields of the delta can be changed
+/// before it is applied; a preset only sets what differs from a new
+/// pusher's defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Preset {
+    /// VLC or ffplay playing the pusher's `sdp` from a file. A file written
+    /// before the first frame has no `sprop-parameter-sets`, and players
+    /// opened mid-stream only see the stream from then on, so SPS and PPS
+    /// are injected before every IDR frame that lacks them.
+    VlcSdp,
+    /// A hardware or software decoder on the same switched LAN: packets as
+    /// large as a 1500-byte Ethernet frame carries in IPv4/UDP, NALs small
+    /// enough to share one aggregated into STAP-A packets, and parameter
+    /// sets injected so the decoder can start at any IDR.
+    LowLatencyLan
+}
+
+// 1500 bytes less the IPv4 and UDP headers.
+const LAN_MTU: usize = 1472;
+
+impl Preset {
+    pub fn config(self) -> ConfigDelta {
+        let mut delta = ConfigDelta::default();
+        match self {
+            Preset::VlcSdp => {
+                delta.parameter_set_injection = Some(true);
+            }
+            Preset::LowLatencyLan => {
+                delta.parameter_set_injection = Some(true);
+                delta.mtu = Some(LAN_MTU);
+                delta.stap_a_aggregation = Some(true);
+            }
+        }
+        delta
+    }
+
+    /// The settings `config` applies, one `name = value` per field in
+    /// `ConfigField` order, e.g. for a startup log.
+    pub fn describe(self) -> Vec<String> {
+        describe(&self.config())
+    }
+}
+
+fn describe(delta: &ConfigDelta) -> Vec<String> {
+    let mut settings = Vec::new();
+    let mut push = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            settings.push(format!("{} = {}", name, value));
+        }
+    };
+    push("destination", delta.destination.clone());
+    push("keepalive", delta.keepalive.map(|interval| format!("{:?}", interval)));
+    push("parameter_set_injection", delta.parameter_set_injection.map(|enabled| enabled.to_string()));
+    push("nri_rewrite", delta.nri_rewrite.map(|enabled| enabled.to_string()));
+    push("send_retry_delay", delta.send_retry_delay.map(|delay| format!("{:?}", delay)));
+    push("pause_policy", delta.pause_policy.map(|policy| format!("{:?}", policy)));
+    push("pacing", delta.pacing.map(|policy| format!("{:?}", policy)));
+    push("mtu", delta.mtu.map(|mtu| mtu.to_string()));
+    push("marker_policy", delta.marker_policy.map(|policy| format!("{:?}", policy)));
+    push("stap_a_aggregation", delta.stap_a_aggregation.map(|enabled| enabled.to_string()));
+    push("ssrc", delta.ssrc.map(|ssrc| format!("{:#010x}", ssrc)));
+    push("clock_rate", delta.clock_rate.map(|rate| rate.to_string()));
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sender::H264RtpPusher;
+
+    #[test]
+    fn presets_only_set_what_differs_from_the_defaults() {
+        for preset in [Preset::VlcSdp, Preset::LowLatencyLan] {
+            let mut pusher = H264RtpPusher::new("127.0.0.1:5004").unwrap();
+            let applied = pusher.apply_config(preset.config()).unwrap();
+            assert_eq!(applied.changed.len(), preset.describe().len(), "{:?}", preset);
+        }
+    }
+
+    #[test]
+    fn descriptions_name_each_setting() {
+        assert_eq!(Preset::VlcSdp.describe(), ["parameter_set_injection = true"]);
+        assert_eq!(
+            Preset::LowLatencyLan.describe(),
+            ["parameter_set_injection = true", "mtu = 1472", "stap_a_aggregation = true"]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use super::{random_u32, H264RtpPusher};
+use crate::{extension, CancelToken, RtpError};
+
+const NAL_TYPE_IDR: u8 = 5;
+const NAL_TYPE_SEI: u8 = 6;
+const NAL_TYPE_AUD: u8 = 9;
+
+pub struct RecordedPacket {
+    pub sent_at: Instant,
+    pub nal_type: u8,
+    pub data: Vec<u8>
+}
+
+// Rolling history of sent packets, bounded by age and total size.
+pub struct ReplayBuffer {
+    keep: Duration,
+    max_bytes: usize,
+    bytes: usize,
+    packets: VecDeque<RecordedPacket>
+}
+
+impl ReplayBuffer {
+    pub fn new(keep: Duration, max_bytes: usize) -> Self {
+        Self {
+            keep,
+            max_bytes,
+            bytes: 0,
+            packets: VecDeque::new()
+        }
+    }
+
+    pub fn record(&mut self, packet: RecordedPacket) {
+        self.bytes += packet.data.len();
+        let now = packet.sent_at;
+        self.packets.push_back(packet);
+
+        while let Some(oldest) = self.packets.front() {
+            let expired = now.duration_since(oldest.sent_at) > self.keep;
+            if !expired && self.bytes <= self.max_bytes {
+                break;
+            }
+            self.bytes -= oldest.data.len();
+            self.packets.pop_front();
+        }
+    }
+
+    // Packets sent within `last` of `now`, starting at the first frame that
+    // contains an IDR so the replay is decodable from its first packet.
+    pub fn keyframe_window(&self, now: Instant, last: Duration) -> Option<Vec<&RecordedPacket>> {
+        let in_window: Vec<&RecordedPacket> = self
+            .packets
+            .iter()
+            .filter(|packet| now.duration_since(packet.sent_at) <= last)
+            .collect();
+
+        let idr_index = in_window.iter().position(|packet| packet.nal_type == NAL_TYPE_IDR)?;
+
+        // Walk back over the SEI/SPS/PPS/AUD sent right before the IDR so the
+        // parameter sets come along.
+        let mut start = idr_index;
+        while start > 0 && (NAL_TYPE_SEI..=NAL_TYPE_AUD).contains(&in_window[start - 1].nal_type) {
+            start -= 1;
+        }
+
+        Some(in_window[start..].to_vec())
+    }
+}
+
+impl H264RtpPusher {
+    /// Keeps the packets sent during the last `keep` (but never more than
+    /// `max_bytes` of them) so they can be sent again with `replay_window`.
+    pub fn enable_replay_window(&mut self, keep: Duration, max_bytes: usize) {
+        self.replay_buffer = Some(ReplayBuffer::new(keep, max_bytes));
+    }
+
+    pub fn disable_replay_window(&mut self) {
+        self.replay_buffer = None;
+    }
+
+    /// Re-sends the packets of the last `last` to `to` as an independent
+    /// stream: new SSRC, sequence numbers from 0, original relative timing.
+    /// The replay starts at the first frame in the window that contains an
+    /// IDR. Blocks for roughly the replayed duration and returns the number
+    /// of packets sent.
+    ///
+    /// Returns `RtpError::ReplayWindowDisabled` unless
+    /// `enable_replay_window` was called, and `RtpError::NoKeyframeInWindow`
+    /// if the window holds no IDR. `cancel` aborts the replay between two
+    /// packets with `RtpError::Cancelled`; the live stream is not affected.
+    pub fn replay_window(
+        &mut self,
+        to: SocketAddr,
+        last: Duration,
+        cancel: Option<&CancelToken>,
+    ) -> Result<usize, RtpError> {
+        let buffer = self.replay_buffer.as_ref().ok_or(RtpError::ReplayWindowDisabled)?;
+        let packets = buffer
+            .keyframe_window(self.clock.now(), last)
+            .ok_or(RtpError::NoKeyframeInWindow)?;
+
+        let mut ssrc = random_u32();
+        while ssrc == self.ssrc {
+            ssrc = random_u32();
+        }
+
+        // With SRTP the replay is a stream of its own under the same keys.
+        #[cfg(feature = "srtp")]
+        let mut srtp = self.srtp.as_ref().map(|srtp| srtp.new_stream());
+
+        let first_sent_at = packets[0].sent_at;
+        let replay_start = self.clock.now();
+        for (seq, packet) in packets.iter().enumerate() {
+            let due = replay_start + packet.sent_at.duration_since(first_sent_at);
+            self.wait_until(due, cancel)?;
+
+            let mut data = packet.data.clone();
+            data[2..4].copy_from_slice(&(seq as u16).to_be_bytes());
+            data[8..12].copy_from_slice(&ssrc.to_be_bytes());
+            if let Some(id) = self.abs_send_time_id {
+                extension::stamp_abs_send_time(&mut data, id, self.wall_clock_now());
+            }
+            #[cfg(feature = "srtp")]
+            if let Some(srtp) = srtp.as_mut() {
+                data = srtp.protect(&data).map_err(RtpError::Send)?.to_vec();
+            }
+            self.socket.send_to(&data, to).map_err(RtpError::Send)?;
+        }
+
+        Ok(packets.len())
+    }
+}
@@ -0,0 +1,237 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::{destination, random_u32, H264RtpPusher};
+use crate::stats::ReceptionReport;
+use crate::time::{MediaTimestamp, NtpTimestamp, VIDEO_CLOCK_RATE};
+use crate::RtpError;
+
+// Sends Sender Reports from their own socket on a fixed interval, and reads
+// the Receiver Reports that come back to it.
+pub struct SenderReports {
+    pub socket: UdpSocket,
+    pub destination: SocketAddr,
+    pub interval: Duration,
+    pub cname: String,
+    // None until the first report, which goes out with the first frame.
+    pub sent_at: Option<Instant>,
+    pub reports_sent: u64,
+    // Latest report block about our stream.
+    pub feedback: Option<ReceptionReport>,
+    // A PLI arrived since take_keyframe_request last looked.
+    pub keyframe_requested: bool
+}
+
+impl SenderReports {
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.sent_at.is_none_or(|sent_at| now.duration_since(sent_at) >= self.interval)
+    }
+}
+
+// RTCP conventionally uses the port above the RTP port.
+pub(super) fn rtcp_destination_for(rtp: SocketAddr) -> Result<SocketAddr, RtpError> {
+    let port = rtp.port().checked_add(1).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "RTP port 65535 has no RTCP port")
+    })?;
+    Ok(SocketAddr::new(rtp.ip(), port))
+}
+
+impl H264RtpPusher {
+    /// Reason text for the RTCP BYE sent by `close`, at most 255 bytes.
+    pub fn set_bye_reason(&mut self, reason: Option<&str>) {
+        self.bye_reason = reason.map(str::to_owned);
+    }
+
+    pub(super) fn send_bye(&mut self) -> Result<(), RtpError> {
+        let packet = match &self.sender_reports {
+            Some(reports) => crate::rtcp::goodbye(self.ssrc, &reports.cname, self.bye_reason.as_deref()),
+            None => return Ok(())
+        };
+        self.pace_control(packet.len())?;
+        if let Some(reports) = &self.sender_reports {
+            reports.socket.send_to(&packet, reports.destination).map_err(RtpError::Send)?;
+        }
+        Ok(())
+    }
+
+    fn default_rtcp_destination(&self) -> Result<SocketAddr, RtpError> {
+        rtcp_destination_for(self.destination)
+    }
+
+    fn default_cname(&self) -> Result<String, RtpError> {
+        Ok(match self.socket.local_addr()?.ip() {
+            ip if ip.is_unspecified() => format!("{:08x}@rtp-transceive", random_u32()),
+            ip => ip.to_string()
+        })
+    }
+
+    /// Sends an RTCP Sender Report every `interval` (see
+    /// `DEFAULT_SENDER_REPORT_INTERVAL`) so receivers can map RTP time to
+    /// wall-clock time for lip sync and drift correction. Reports go to
+    /// `destination`, or by default to the RTP destination's port + 1, from a
+    /// separate ephemeral local port. They carry the pusher's SSRC and a
+    /// CNAME: the local IP when the pusher was bound to one, otherwise a
+    /// random name.
+    ///
+    /// Reports are sent after a frame once the interval has elapsed; an idle
+    /// sender can call `poll_sender_report` instead. Until this is called,
+    /// and after `disable_sender_reports`, no RTCP is sent at all.
+    pub fn enable_sender_reports(
+        &mut self,
+        interval: Duration,
+        destination: Option<&str>,
+    ) -> Result<(), RtpError> {
+        let destination = match destination {
+            Some(destination) => destination::resolve(destination, self.address_preference)?,
+            None => self.default_rtcp_destination()?
+        };
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind).map_err(RtpError::Bind)?;
+        // Polled for Receiver Reports between frames.
+        socket.set_nonblocking(true)?;
+        let cname = self.default_cname()?;
+        self.sender_reports = Some(SenderReports {
+            socket,
+            destination,
+            interval,
+            cname,
+            sent_at: None,
+            reports_sent: 0,
+            feedback: None,
+            keyframe_requested: false
+        });
+        Ok(())
+    }
+
+    pub fn disable_sender_reports(&mut self) {
+        self.sender_reports = None;
+    }
+
+    pub fn sender_reports_sent(&self) -> u64 {
+        self.sender_reports.as_ref().map_or(0, |reports| reports.reports_sent)
+    }
+
+    /// The latest RTCP reception report about this stream, read from the
+    /// Receiver Reports that arrive on the Sender Report socket (see
+    /// `enable_sender_reports`), for instance to adapt the bitrate to
+    /// `fraction_lost`. Reports are taken in after each frame and by
+    /// `poll_sender_report`, so `round_trip_time` can be up to one frame
+    /// interval (or poll interval) too high.
+    pub fn sender_feedback(&self) -> Option<ReceptionReport> {
+        self.sender_reports.as_ref().and_then(|reports| reports.feedback)
+    }
+
+    /// Whether a receiver asked for a keyframe with an RTCP PLI (RFC 4585
+    /// section 6.3.1) since the last call, for instance from
+    /// `H264RtpReceiver::set_refresh_policy`. The pusher cannot make one:
+    /// ask the encoder for an IDR when this returns true. Needs
+    /// `enable_sender_reports`.
+    pub fn take_keyframe_request(&mut self) -> bool {
+        self.take_feedback();
+        self.sender_reports.as_mut().is_some_and(|reports| std::mem::take(&mut reports.keyframe_requested))
+    }
+
+    /// Sends a Sender Report if one is due. Returns whether one was sent.
+    /// Nothing is sent before the first media packet.
+    pub fn poll_sender_report(&mut self) -> Result<bool, RtpError> {
+        self.take_feedback();
+        let (sent_at, rtp_ts) = match self.last_media_packet {
+            Some(last) => last,
+            None => return Ok(false)
+        };
+        let now = self.clock.now();
+        let reports = match self.sender_reports.as_ref() {
+            Some(reports) if reports.is_due(now) => reports,
+            _ => return Ok(false)
+        };
+
+        // Both clocks are read from the monotonic clock, so the pair stays
+        // consistent even if the system clock is stepped.
+        let wall_clock = self.anchor_wall_clock + now.duration_since(self.anchor_instant);
+        let since_last = MediaTimestamp::from_duration(now.duration_since(sent_at), VIDEO_CLOCK_RATE);
+        let ntp = NtpTimestamp::from_system_time(wall_clock);
+        let rtp_ts = rtp_ts.wrapping_add(since_last.wire());
+        let mut packet = crate::rtcp::sender_report(
+            self.ssrc,
+            ntp,
+            rtp_ts,
+            // Both counts wrap, as RFC 3550 section 6.4.1 specifies.
+            self.packets_sent as u32,
+            self.payload_bytes_sent as u32,
+            &reports.cname,
+        );
+        // The RTX stream shares the media clock (RFC 4588 section 4).
+        if let Some(rtx) = &self.retransmission {
+            packet.extend(crate::rtcp::sender_report(
+                rtx.ssrc,
+                ntp,
+                rtp_ts,
+                rtx.packets_sent as u32,
+                rtx.payload_bytes_sent as u32,
+                &reports.cname,
+            ));
+        }
+        self.pace_control(packet.len())?;
+        if let Some(reports) = self.sender_reports.as_mut() {
+            reports.socket.send_to(&packet, reports.destination).map_err(RtpError::Send)?;
+            reports.sent_at = Some(now);
+            reports.reports_sent += 1;
+        }
+        Ok(true)
+    }
+
+    // Reads pending Receiver Reports and keeps the last block about us,
+    // notes PLIs, then answers the NACKs among them if retransmission is
+    // on.
+    fn take_feedback(&mut self) {
+        let now_ntp = NtpTimestamp::from_system_time(self.anchor_wall_clock + self.since_anchor());
+        let ssrc = self.ssrc;
+        let answer_nacks = self.retransmission.is_some();
+        let mut nacks = Vec::new();
+        let mut plis = 0;
+        let reports = match self.sender_reports.as_mut() {
+            Some(reports) => reports,
+            None => return
+        };
+
+        let mut buffer = [0u8; 1500];
+        while let Ok(len) = reports.socket.recv(&mut buffer) {
+            if answer_nacks {
+                nacks.extend(crate::rtcp::nacks(&buffer[..len], ssrc));
+            }
+            let losses = crate::rtcp::picture_losses(&buffer[..len], ssrc);
+            reports.keyframe_requested |= losses > 0;
+            plis += losses;
+            let blocks = crate::rtcp::report_blocks(&buffer[..len]);
+            for mut report in blocks.into_iter().filter(|report| report.source_ssrc == ssrc) {
+                // RFC 3550 section 6.4.1: RTT = arrival - LSR - DLSR.
+                if report.last_sender_report != 0 {
+                    let rtt = now_ntp
+                        .short()
+                        .wrapping_sub(report.last_sender_report)
+                        .wrapping_sub(report.delay_since_last_sender_report);
+                    if rtt < 0x8000_0000 {
+                        report.round_trip_time = Some(Duration::from_secs_f64(rtt as f64 / 65536.0));
+                    }
+                }
+                reports.feedback = Some(report);
+            }
+        }
+        self.send_accounting.record_plis(plis);
+        if !nacks.is_empty() {
+            self.retransmit(nacks);
+        }
+    }
+
+    /// The address Sender Reports go out from and Receiver Reports are read
+    /// on; None until `enable_sender_reports`.
+    pub fn rtcp_local_addr(&self) -> Result<Option<SocketAddr>, RtpError> {
+        match &self.sender_reports {
+            Some(reports) => Ok(Some(reports.socket.local_addr()?)),
+            None => Ok(None)
+        }
+    }
+}
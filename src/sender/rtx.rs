@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+
+use super::{check_payload_type, random_u32, H264RtpPusher};
+use crate::rtp::RtpPacket;
+use crate::RtpError;
+
+// The RTX stream of RFC 4588 section 4: its own SSRC, payload type and
+// sequence numbers, and the original sequence number (OSN) in the first
+// two bytes of the payload. Nothing here touches the media stream's
+// sequence number or counts.
+pub struct Retransmission {
+    pub ssrc: u32,
+    pub payload_type: u8,
+    seq: u16,
+    // The latest media packets as sent, before the packet transform and
+    // SRTP, oldest first.
+    history: VecDeque<Vec<u8>>,
+    max_packets: usize,
+    // For the RTX Sender Reports, like the media counts never reset.
+    pub packets_sent: u64,
+    pub payload_bytes_sent: u64,
+    // Its own stream under the media keys, made on first use.
+    #[cfg(feature = "srtp")]
+    pub srtp: Option<crate::srtp::Context>
+}
+
+impl Retransmission {
+    pub fn new(payload_type: u8, max_packets: usize, media_ssrc: u32) -> Self {
+        let mut ssrc = random_u32();
+        while ssrc == media_ssrc {
+            ssrc = random_u32();
+        }
+        Self {
+            ssrc,
+            payload_type,
+            seq: random_u32() as u16,
+            history: VecDeque::with_capacity(max_packets),
+            max_packets,
+            packets_sent: 0,
+            payload_bytes_sent: 0,
+            #[cfg(feature = "srtp")]
+            srtp: None
+        }
+    }
+
+    // Keeps a media packet, reusing the buffer of the oldest once full.
+    pub fn record(&mut self, packet: &[u8]) {
+        let mut slot = if self.history.len() >= self.max_packets {
+            self.history.pop_front().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        slot.clear();
+        slot.extend_from_slice(packet);
+        self.history.push_back(slot);
+    }
+
+    // The RTX packet for media sequence number `seq`, if it is still kept:
+    // the original header with the RTX payload type, sequence number and
+    // SSRC, then the OSN and the original payload. The pusher never pads,
+    // so the payload runs to the end of the packet.
+    pub fn packet_for(&mut self, seq: u16) -> Option<Vec<u8>> {
+        let original = self.history.iter().rev().find(|packet| packet[2..4] == seq.to_be_bytes())?;
+        let payload = RtpPacket::parse(original)?.payload;
+        let header_size = original.len() - payload.len();
+
+        let mut packet = Vec::with_capacity(original.len() + 2);
+        packet.extend_from_slice(&original[..header_size]);
+        packet[1] = (original[1] & 0x80) | self.payload_type;
+        packet[2..4].copy_from_slice(&self.seq.to_be_bytes());
+        packet[8..12].copy_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.seq = self.seq.wrapping_add(1);
+        self.packets_sent += 1;
+        self.payload_bytes_sent += payload.len() as u64 + 2;
+        Some(packet)
+    }
+}
+
+impl H264RtpPusher {
+    /// Answers RTCP Generic NACKs (RFC 4585 section 6.2.1) with
+    /// retransmissions in an RTX stream (RFC 4588): its own SSRC
+    /// (`rtx_ssrc`), `payload_type` and sequence numbers, so the media
+    /// stream's sequence numbers and Sender Report counts only ever cover
+    /// original packets. The RTX stream gets a Sender Report of its own
+    /// next to the media one, and `sdp` announces it.
+    ///
+    /// NACKs are read on the Sender Report socket (see
+    /// `enable_sender_reports`) after each frame and by
+    /// `poll_sender_report`. The last `history` packets are kept for them;
+    /// a NACK for an older one is counted in
+    /// `SenderStats::rtx.packets_unavailable`. RTX packets are 2 bytes
+    /// larger than the original. `payload_type` must differ from the media
+    /// payload type; fails with `InvalidPayloadType` if it does not fit in
+    /// 7 bits.
+    pub fn enable_retransmission(&mut self, payload_type: u8, history: usize) -> Result<(), RtpError> {
+        let payload_type = check_payload_type(payload_type)?;
+        self.retransmission = Some(Retransmission::new(payload_type, history.max(1), self.ssrc));
+        Ok(())
+    }
+
+    pub fn disable_retransmission(&mut self) {
+        self.retransmission = None;
+    }
+
+    /// SSRC of the RTX stream, once `enable_retransmission` was called.
+    pub fn rtx_ssrc(&self) -> Option<u32> {
+        self.retransmission.as_ref().map(|rtx| rtx.ssrc)
+    }
+
+    // Sends what the NACKs ask for, in order. Best effort like the rest
+    // of RTCP: a failed send is recorded in the stats and the rest go on.
+    pub(super) fn retransmit(&mut self, nacks: Vec<Vec<u16>>) {
+        let mut rtx = match self.retransmission.take() {
+            Some(rtx) => rtx,
+            None => return
+        };
+        let media = (self.rtp_seq, self.packets_sent, self.stats().packets_sent);
+        for sequences in nacks {
+            let packets: Vec<Vec<u8>> = sequences.iter().filter_map(|&seq| rtx.packet_for(seq)).collect();
+            self.send_accounting.record_nack(sequences.len(), sequences.len() - packets.len());
+            for packet in packets {
+                match self.send_retransmission(&mut rtx, &packet) {
+                    Ok(()) => self.send_accounting.record_retransmission(packet.len()),
+                    Err(err) => self.send_accounting.record_error(&RtpError::Send(err))
+                }
+            }
+        }
+        debug_assert_eq!(
+            (self.rtp_seq, self.packets_sent, self.stats().packets_sent),
+            media,
+            "a retransmission moved the media stream's counters"
+        );
+        self.retransmission = Some(rtx);
+    }
+
+    // Swapped in for the call, so SRTP keeps a rollover counter per
+    // stream.
+    #[cfg(feature = "srtp")]
+    fn send_retransmission(&mut self, rtx: &mut Retransmission, packet: &[u8]) -> std::io::Result<()> {
+        match self.srtp.as_ref() {
+            Some(srtp) if rtx.srtp.is_none() => rtx.srtp = Some(srtp.new_stream()),
+            Some(_) => {}
+            None => rtx.srtp = None
+        }
+        std::mem::swap(&mut self.srtp, &mut rtx.srtp);
+        let sent = self.send_control(packet);
+        std::mem::swap(&mut self.srtp, &mut rtx.srtp);
+        sent
+    }
+
+    #[cfg(not(feature = "srtp"))]
+    fn send_retransmission(&mut self, _rtx: &mut Retransmission, packet: &[u8]) -> std::io::Result<()> {
+        self.send_control(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media_packet(seq: u16, marker: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, if marker { 0x80 | 96 } else { 96 }];
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&1234u32.to_be_bytes());
+        packet.extend_from_slice(&0xAAAA_AAAAu32.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn rtx_packets_carry_the_original_sequence_number() {
+        let mut rtx = Retransmission::new(97, 4, 0xAAAA_AAAA);
+        rtx.record(&media_packet(65_535, false, &[1, 2, 3]));
+        rtx.record(&media_packet(0, true, &[4, 5]));
+        let first_seq = rtx.seq;
+
+        let packet = rtx.packet_for(0).unwrap();
+        assert_eq!(packet[1], 0x80 | 97);
+        assert_eq!(packet[2..4], first_seq.to_be_bytes());
+        assert_eq!(packet[4..8], 1234u32.to_be_bytes());
+        assert_eq!(packet[8..12], rtx.ssrc.to_be_bytes());
+        assert_eq!(packet[12..], [0, 0, 4, 5]);
+
+        let packet = rtx.packet_for(65_535).unwrap();
+        assert_eq!(packet[1], 97);
+        assert_eq!(packet[2..4], first_seq.wrapping_add(1).to_be_bytes());
+        assert_eq!(packet[12..], [0xFF, 0xFF, 1, 2, 3]);
+        assert_eq!((rtx.packets_sent, rtx.payload_bytes_sent), (2, 9));
+    }
+
+    #[test]
+    fn only_the_latest_packets_are_kept() {
+        let mut rtx = Retransmission::new(97, 2, 1);
+        for seq in 10..14 {
+            rtx.record(&media_packet(seq, false, &[seq as u8]));
+        }
+        assert!(rtx.packet_for(11).is_none());
+        assert!(rtx.packet_for(12).is_some() && rtx.packet_for(13).is_some());
+        assert_eq!(rtx.history.len(), 2);
+    }
+}
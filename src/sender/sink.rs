@@ -0,0 +1,48 @@
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+use super::destination::{self, AddressPreference};
+use super::RtpSink;
+use crate::{platform, RtpError};
+
+/// Sends each packet as one UDP datagram to a fixed destination, which is
+/// what a pusher does without a sink. Meant as the inner sink of wrappers.
+pub struct UdpSink {
+    socket: UdpSocket,
+    destination: SocketAddr
+}
+
+impl UdpSink {
+    /// `destination` is resolved as for `H264RtpPusher::new`, and the
+    /// socket bound to an ephemeral port of the same family.
+    pub fn new(destination: &str) -> Result<Self, RtpError> {
+        let destination = destination::resolve(destination, AddressPreference::First)?;
+        let bind: SocketAddr = match destination {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind).map_err(RtpError::Bind)?;
+        Ok(Self::from_socket(socket, destination))
+    }
+
+    pub fn from_socket(socket: UdpSocket, destination: SocketAddr) -> Self {
+        Self {
+            socket,
+            destination
+        }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, RtpError> {
+        Ok(self.socket.local_addr()?)
+    }
+}
+
+impl RtpSink for UdpSink {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.socket.send_to(packet, self.destination)
+    }
+
+    fn send_batch(&mut self, packets: &[&[u8]]) -> io::Result<usize> {
+        platform::send_batch(&self.socket, Some(self.destination), packets)
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use super::{destination, H264RtpPusher, Transport};
+use crate::framing::{self, TcpFraming};
+use crate::{CancelToken, RtpError};
+
+/// How a pusher sending over TCP reconnects after the connection fails. The
+/// packet that hit the failure is sent again on the new connection; the
+/// rest of the stream continues from there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Connection attempts before the send fails.
+    pub attempts: u32,
+    /// Wait before each attempt.
+    pub delay: Duration
+}
+
+// Media path replacing the UDP socket once connect_tcp is called.
+pub struct TcpPath {
+    stream: TcpStream,
+    destination: SocketAddr,
+    framing: TcpFraming,
+    pub reconnects: u64,
+    // Reused for each framed packet.
+    frame: Vec<u8>
+}
+
+impl TcpPath {
+    pub fn connect(destination: SocketAddr, framing: TcpFraming) -> io::Result<Self> {
+        Ok(Self {
+            stream: open(destination)?,
+            destination,
+            framing,
+            reconnects: 0,
+            frame: Vec::new()
+        })
+    }
+
+    // Writes one framed packet. A failed write reconnects as `reconnect`
+    // allows, waiting between attempts unless `cancel` is cancelled; the
+    // error returned is the last one seen.
+    pub fn send(
+        &mut self,
+        packet: &[u8],
+        reconnect: Option<ReconnectPolicy>,
+        cancel: Option<&CancelToken>,
+    ) -> io::Result<()> {
+        self.frame.clear();
+        framing::encode(self.framing, packet, &mut self.frame);
+        let mut err = match self.stream.write_all(&self.frame) {
+            Ok(()) => return Ok(()),
+            Err(err) => err
+        };
+        let policy = match reconnect {
+            Some(policy) => policy,
+            None => return Err(err)
+        };
+        for _ in 0..policy.attempts {
+            match cancel {
+                Some(token) => token.sleep_io(policy.delay)?,
+                None => thread::sleep(policy.delay)
+            }
+            match open(self.destination).and_then(|stream| {
+                // A frame cut off on the old connection is not resumed: the
+                // new one starts at a frame boundary.
+                (&stream).write_all(&self.frame)?;
+                Ok(stream)
+            }) {
+                Ok(stream) => {
+                    self.stream = stream;
+                    self.reconnects += 1;
+                    return Ok(());
+                }
+                Err(retry_err) => err = retry_err
+            }
+        }
+        Err(err)
+    }
+}
+
+fn open(destination: SocketAddr) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(destination)?;
+    // Packets are written whole; waiting to coalesce them only adds latency.
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+impl H264RtpPusher {
+    /// Sends RTP over a TCP connection to `destination` from now on, for
+    /// networks that block UDP. Each packet is framed as `framing` says;
+    /// `H264RtpReceiver::accept_tcp` reads the stream back. RTCP, keepalive
+    /// probes and the redundant path stay on UDP. A failed write fails the
+    /// send with `RtpError::Disconnected` unless `set_tcp_reconnect` allows
+    /// a new connection. Call it again to replace the connection.
+    pub fn connect_tcp(&mut self, destination: &str, framing: TcpFraming) -> Result<(), RtpError> {
+        let destination = destination::resolve(destination, self.address_preference)?;
+        let path = TcpPath::connect(destination, framing).map_err(RtpError::Disconnected)?;
+        self.transport = Transport::Tcp(path);
+        Ok(())
+    }
+
+    /// How a failed TCP connection is re-established; `None` (the default)
+    /// fails the send instead. Has no effect while sending over UDP.
+    pub fn set_tcp_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.tcp_reconnect = policy;
+    }
+
+    /// Times the TCP connection was re-established after a failure.
+    pub fn tcp_reconnects(&self) -> u64 {
+        match &self.transport {
+            Transport::Tcp(path) => path.reconnects,
+            _ => 0
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! SRTP (RFC 3711) protection of RTP packets with externally provided
+//! keys, e.g. from SDES `a=crypto` lines (RFC 4568), for networks that
+//! require it. No key exchange (DTLS-SRTP, MIKEY) is done.
+//!
+//! Only the default transform is supported: AES_CM_128_HMAC_SHA1_80, i.e.
+//! AES-128 in counter mode and an 80-bit HMAC-SHA1 tag, without MKI and
+//! with a key derivation rate of 0. RTCP stays unprotected (no SRTCP). See
+//! `H264RtpPusher::set_srtp` and `H264RtpReceiver::set_srtp`.
+
+use std::fmt;
+use std::io;
+
+use crate::{sdp, RtpError};
+
+mod aes;
+mod sha1;
+
+use aes::Aes128;
+use sha1::HmacSha1;
+
+const CRYPTO_SUITE: &str = "AES_CM_128_HMAC_SHA1_80";
+const MASTER_KEY_SIZE: usize = 16;
+const MASTER_SALT_SIZE: usize = 14;
+const AUTH_KEY_SIZE: usize = 20;
+// Bytes appended to every packet.
+pub(crate) const TAG_SIZE: usize = 10;
+const BLOCK_SIZE: usize = 16;
+const MIN_HEADER_SIZE: usize = 12;
+
+// Key derivation labels (RFC 3711 section 4.3.1).
+const LABEL_ENCRYPTION: u8 = 0x00;
+const LABEL_AUTHENTICATION: u8 = 0x01;
+const LABEL_SALT: u8 = 0x02;
+
+/// Master key and master salt of an SRTP session. Both ends need the same
+/// one for a stream; use a fresh one per stream.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SrtpKey {
+    master_key: [u8; MASTER_KEY_SIZE],
+    master_salt: [u8; MASTER_SALT_SIZE]
+}
+
+impl SrtpKey {
+    pub fn new(master_key: [u8; MASTER_KEY_SIZE], master_salt: [u8; MASTER_SALT_SIZE]) -> Self {
+        Self {
+            master_key,
+            master_salt
+        }
+    }
+
+    /// Parses an SDES crypto attribute, with or without its `a=` prefix,
+    /// e.g. `a=crypto:1 AES_CM_128_HMAC_SHA1_80
+    /// inline:WVNfX19zZW1jdGwgKCkgewkyMjA7fQp9CnVubGVz|2^20`. The key
+    /// lifetime is not enforced. Other crypto suites, an MKI, several
+    /// keys and session parameters are rejected with
+    /// `RtpError::InvalidSrtpKey`.
+    pub fn from_sdes(attribute: &str) -> Result<Self, RtpError> {
+        let attribute = attribute.trim();
+        let attribute = attribute.strip_prefix("a=").unwrap_or(attribute);
+        let value = attribute
+            .strip_prefix("crypto:")
+            .ok_or(RtpError::InvalidSrtpKey("not a crypto attribute"))?;
+        let mut fields = value.split_ascii_whitespace();
+        let (_tag, suite, key_params) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(tag), Some(suite), Some(key_params)) => (tag, suite, key_params),
+            _ => return Err(RtpError::InvalidSrtpKey("incomplete crypto attribute"))
+        };
+        if suite != CRYPTO_SUITE {
+            return Err(RtpError::InvalidSrtpKey("unsupported crypto suite"));
+        }
+        if fields.next().is_some() {
+            return Err(RtpError::InvalidSrtpKey("session parameters are not supported"));
+        }
+        if key_params.contains(';') {
+            return Err(RtpError::InvalidSrtpKey("only one key is supported"));
+        }
+        let key_info = key_params
+            .strip_prefix("inline:")
+            .ok_or(RtpError::InvalidSrtpKey("key is not inline"))?;
+        let mut key_info = key_info.split('|');
+        let key_salt = key_info.next().unwrap_or_default();
+        // A lifetime, then an MKI, both optional; an MKI has a colon.
+        if key_info.any(|parameter| parameter.contains(':')) {
+            return Err(RtpError::InvalidSrtpKey("MKI is not supported"));
+        }
+        let key_salt = sdp::base64_decode(key_salt).ok_or(RtpError::InvalidSrtpKey("key is not base64"))?;
+        if key_salt.len() != MASTER_KEY_SIZE + MASTER_SALT_SIZE {
+            return Err(RtpError::InvalidSrtpKey("key and salt must be 30 bytes"));
+        }
+        let (master_key, master_salt) = key_salt.split_at(MASTER_KEY_SIZE);
+        Ok(Self::new(master_key.try_into().unwrap(), master_salt.try_into().unwrap()))
+    }
+}
+
+// Keys stay out of logs.
+impl fmt::Debug for SrtpKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrtpKey").finish_non_exhaustive()
+    }
+}
+
+// Session keys and packet index state for one direction of one stream.
+pub(crate) struct Context {
+    cipher: Aes128,
+    session_salt: [u8; MASTER_SALT_SIZE],
+    auth: HmacSha1,
+    // The stream's SSRC, highest sequence number and rollover counter
+    // (RFC 3711 section 3.3.1), once a packet went through.
+    ssrc: Option<u32>,
+    highest_seq: u16,
+    roc: u32,
+    // Reused for each protected or unprotected packet.
+    buffer: Vec<u8>
+}
+
+impl Context {
+    pub fn new(key: &SrtpKey) -> Self {
+        let (session_key, auth_key, session_salt) = session_keys(key);
+        Self {
+            cipher: Aes128::new(&session_key),
+            session_salt,
+            auth: HmacSha1::new(&auth_key),
+            ssrc: None,
+            highest_seq: 0,
+            roc: 0,
+            buffer: Vec::new()
+        }
+    }
+
+    // The same session keys for another stream, e.g. a replay with its own
+    // SSRC and sequence numbers.
+    pub fn new_stream(&self) -> Self {
+        Self {
+            cipher: self.cipher.clone(),
+            session_salt: self.session_salt,
+            auth: self.auth.clone(),
+            ssrc: None,
+            highest_seq: 0,
+            roc: 0,
+            buffer: Vec::new()
+        }
+    }
+
+    // Encrypts the payload of an RTP packet and appends its tag.
+    pub fn protect(&mut self, packet: &[u8]) -> io::Result<&[u8]> {
+        let header_size = header_size(packet)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not an RTP packet"))?;
+        let (ssrc, seq) = ssrc_and_seq(packet);
+        let roc = self.estimate_roc(ssrc, seq);
+        self.update_index(ssrc, seq, roc);
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(packet);
+        self.apply_packet_keystream(ssrc, roc, seq, header_size);
+        let tag = self.auth.mac(&[&self.buffer, &roc.to_be_bytes()]);
+        self.buffer.extend_from_slice(&tag[..TAG_SIZE]);
+        Ok(&self.buffer)
+    }
+
+    // Checks the tag of an SRTP packet and decrypts it; None if it is not
+    // authentic.
+    pub fn unprotect(&mut self, packet: &[u8]) -> Option<&[u8]> {
+        let (packet, tag) = packet.split_at_checked(packet.len().checked_sub(TAG_SIZE)?)?;
+        let header_size = header_size(packet)?;
+        let (ssrc, seq) = ssrc_and_seq(packet);
+        let roc = self.estimate_roc(ssrc, seq);
+        let expected = self.auth.mac(&[packet, &roc.to_be_bytes()]);
+        // Compared without an early exit, so timing tells nothing about
+        // how much of a forged tag was right.
+        let difference = expected[..TAG_SIZE].iter().zip(tag).fold(0, |acc, (a, b)| acc | (a ^ b));
+        if difference != 0 {
+            return None;
+        }
+        // Only authentic packets move the index on.
+        self.update_index(ssrc, seq, roc);
+
+        self.buffer.clear();
+        self.buffer.extend_from_slice(packet);
+        self.apply_packet_keystream(ssrc, roc, seq, header_size);
+        Some(&self.buffer)
+    }
+
+    // The rollover counter `seq` most likely belongs to (RFC 3711
+    // appendix A). A packet from before the first one counts as the
+    // first rollover.
+    fn estimate_roc(&self, ssrc: u32, seq: u16) -> u32 {
+        if self.ssrc != Some(ssrc) {
+            return 0;
+        }
+        let highest = self.highest_seq;
+        if highest < 0x8000 {
+            if seq > highest && seq - highest > 0x8000 {
+                return self.roc.saturating_sub(1);
+            }
+        } else if seq < highest - 0x8000 {
+            return self.roc.wrapping_add(1);
+        }
+        self.roc
+    }
+
+    fn update_index(&mut self, ssrc: u32, seq: u16, roc: u32) {
+        if self.ssrc != Some(ssrc) {
+            // A new stream starts over at rollover 0.
+            self.ssrc = Some(ssrc);
+            self.highest_seq = seq;
+            self.roc = roc;
+        } else if roc == self.roc.wrapping_add(1) {
+            self.roc = roc;
+            self.highest_seq = seq;
+        } else if roc == self.roc && seq > self.highest_seq {
+            self.highest_seq = seq;
+        }
+    }
+
+    // Counter-mode en- or decryption of `buffer` after its header; IV =
+    // (salt * 2^16) XOR (SSRC * 2^64) XOR (index * 2^16) (section 4.1.1).
+    fn apply_packet_keystream(&mut self, ssrc: u32, roc: u32, seq: u16, header_size: usize) {
+        let index = (roc as u64) << 16 | seq as u64;
+        let mut iv = [0u8; BLOCK_SIZE];
+        iv[..MASTER_SALT_SIZE].copy_from_slice(&self.session_salt);
+        for (byte, ssrc_byte) in iv[4..8].iter_mut().zip(ssrc.to_be_bytes()) {
+            *byte ^= ssrc_byte;
+        }
+        for (byte, index_byte) in iv[8..14].iter_mut().zip(&index.to_be_bytes()[2..]) {
+            *byte ^= index_byte;
+        }
+        apply_keystream(&self.cipher, &iv, &mut self.buffer[header_size..]);
+    }
+}
+
+// Session encryption key, authentication key and salt of a master key.
+fn session_keys(key: &SrtpKey) -> ([u8; MASTER_KEY_SIZE], [u8; AUTH_KEY_SIZE], [u8; MASTER_SALT_SIZE]) {
+    let master = Aes128::new(&key.master_key);
+    let derive = |label: u8, out: &mut [u8]| {
+        // IV = (master salt XOR label at byte 7) * 2^16 (section 4.3.3).
+        let mut iv = [0u8; BLOCK_SIZE];
+        iv[..MASTER_SALT_SIZE].copy_from_slice(&key.master_salt);
+        iv[7] ^= label;
+        apply_keystream(&master, &iv, out);
+    };
+    let mut session_key = [0u8; MASTER_KEY_SIZE];
+    derive(LABEL_ENCRYPTION, &mut session_key);
+    let mut auth_key = [0u8; AUTH_KEY_SIZE];
+    derive(LABEL_AUTHENTICATION, &mut auth_key);
+    let mut session_salt = [0u8; MASTER_SALT_SIZE];
+    derive(LABEL_SALT, &mut session_salt);
+    (session_key, auth_key, session_salt)
+}
+
+// XORs AES-CM keystream into `data`: block n is the encrypted IV with n in
+// its last 16 bits.
+fn apply_keystream(cipher: &Aes128, iv: &[u8; BLOCK_SIZE], data: &mut [u8]) {
+    for (counter, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+        let mut block = *iv;
+        block[14..].copy_from_slice(&(counter as u16).to_be_bytes());
+        cipher.encrypt_block(&mut block);
+        for (byte, key) in chunk.iter_mut().zip(block) {
+            *byte ^= key;
+        }
+    }
+}
+
+// Fixed header, CSRCs and header extension: what stays in the clear.
+fn header_size(packet: &[u8]) -> Option<usize> {
+    if packet.len() < MIN_HEADER_SIZE || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let mut size = MIN_HEADER_SIZE + 4 * (packet[0] & 0x0F) as usize;
+    if packet[0] & 0x10 != 0 {
+        let length = packet.get(size + 2..size + 4)?;
+        size += 4 + 4 * u16::from_be_bytes([length[0], length[1]]) as usize;
+    }
+    (size <= packet.len()).then_some(size)
+}
+
+fn ssrc_and_seq(packet: &[u8]) -> (u32, u16) {
+    (
+        u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+        u16::from_be_bytes([packet[2], packet[3]])
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(u8::is_ascii_hexdigit).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    // RFC 3711 appendix B.2.
+    #[test]
+    fn aes_cm_keystream_matches_rfc_3711() {
+        let cipher = Aes128::new(&hex("2B7E151628AED2A6ABF7158809CF4F3C").try_into().unwrap());
+        let mut iv = [0u8; BLOCK_SIZE];
+        iv[..MASTER_SALT_SIZE].copy_from_slice(&hex("F0F1F2F3F4F5F6F7F8F9FAFBFCFD"));
+        let mut keystream = vec![0u8; 3 * BLOCK_SIZE];
+        apply_keystream(&cipher, &iv, &mut keystream);
+        assert_eq!(
+            keystream,
+            hex("E03EAD0935C95E80E166B16DD92B4EB4 D23513162B02D0F72A43A2FE4A5F97AB 41E95B3BB0A2E8DD477901E4FCA894C0")
+        );
+    }
+
+    // RFC 3711 appendix B.3.
+    #[test]
+    fn key_derivation_matches_rfc_3711() {
+        let key = SrtpKey::new(
+            hex("E1F97A0D3E018BE0D64FA32C06DE4139").try_into().unwrap(),
+            hex("0EC675AD498AFEEBB6960B3AABE6").try_into().unwrap()
+        );
+        let (session_key, auth_key, session_salt) = session_keys(&key);
+        assert_eq!(session_key[..], hex("C61E7A93744F39EE10734AFE3FF7A087"));
+        assert_eq!(session_salt[..], hex("30CBBC08863D8C85D49DB34A9AE1"));
+        assert_eq!(auth_key[..], hex("CEBE321F6FF7716B6FD4AB49AF256A156D38BAA4"));
+    }
+
+    fn packet(seq: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, 96];
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0x0B, 0xB8, 0xCA, 0xFE, 0xBA, 0xBE]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn key() -> SrtpKey {
+        SrtpKey::new([0x11; MASTER_KEY_SIZE], [0x22; MASTER_SALT_SIZE])
+    }
+
+    #[test]
+    fn unprotect_undoes_protect_and_rejects_forgeries() {
+        let (mut sender, mut receiver) = (Context::new(&key()), Context::new(&key()));
+        let plain = packet(7, b"a payload longer than one AES block");
+        let protected = sender.protect(&plain).unwrap().to_vec();
+        assert_eq!(protected.len(), plain.len() + TAG_SIZE);
+        assert_eq!(protected[..MIN_HEADER_SIZE], plain[..MIN_HEADER_SIZE]);
+        assert_ne!(protected[MIN_HEADER_SIZE..plain.len()], plain[MIN_HEADER_SIZE..]);
+
+        let mut forged = protected.clone();
+        forged[MIN_HEADER_SIZE] ^= 1;
+        assert_eq!(receiver.unprotect(&forged), None);
+        assert_eq!(receiver.unprotect(&protected[..TAG_SIZE - 1]), None);
+        assert_eq!(receiver.unprotect(&protected), Some(&plain[..]));
+    }
+
+    #[test]
+    fn the_rollover_counter_follows_the_sequence_number_wrap() {
+        let (mut sender, mut receiver) = (Context::new(&key()), Context::new(&key()));
+        let sequence = (0xFFF0..=0xFFFF).chain(0..0x10).chain([0x8000, 0xFFFF, 0x0001]);
+        let mut protected = Vec::new();
+        for seq in sequence {
+            let plain = packet(seq, &seq.to_be_bytes());
+            protected.push(sender.protect(&plain).unwrap().to_vec());
+            assert_eq!(receiver.unprotect(protected.last().unwrap()), Some(&plain[..]));
+        }
+        // Sequence 0xFFFF then 0x0001 starts the second rollover.
+        assert_eq!((sender.roc, receiver.roc), (2, 2));
+        // A late packet from before the wrap still checks under the
+        // previous rollover counter.
+        assert!(receiver.unprotect(&protected[protected.len() - 2]).is_some());
+        assert_eq!(receiver.roc, 2);
+
+        // The same sequence number a rollover apart encrypts differently.
+        let (first, again) = (&protected[0xF], &protected[protected.len() - 2]);
+        assert_eq!(first[..MIN_HEADER_SIZE], again[..MIN_HEADER_SIZE]);
+        assert_ne!(first[MIN_HEADER_SIZE..], again[MIN_HEADER_SIZE..]);
+    }
+}
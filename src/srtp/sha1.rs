@@ -0,0 +1,192 @@
+// SHA-1 (FIPS 180-4) and HMAC-SHA1 (RFC 2104), for SRTP authentication
+// tags only.
+
+const BLOCK_SIZE: usize = 64;
+pub const DIGEST_SIZE: usize = 20;
+
+const INITIAL_STATE: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    // Bytes hashed so far.
+    length: u64
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            block: [0; BLOCK_SIZE],
+            block_len: 0,
+            length: 0
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if self.block_len > 0 {
+            let take = data.len().min(BLOCK_SIZE - self.block_len);
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < BLOCK_SIZE {
+                return;
+            }
+            let block = self.block;
+            self.compress(&block);
+            self.block_len = 0;
+        }
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in &mut blocks {
+            self.compress(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    pub fn finish(mut self) -> [u8; DIGEST_SIZE] {
+        let bits = self.length.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != BLOCK_SIZE - 8 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+
+        let mut digest = [0; DIGEST_SIZE];
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_SIZE]) {
+        let mut schedule = [0u32; 80];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for index in 16..80 {
+            schedule[index] =
+                (schedule[index - 3] ^ schedule[index - 8] ^ schedule[index - 14] ^ schedule[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (index, word) in schedule.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6)
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, word) in self.state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(word);
+        }
+    }
+}
+
+// HMAC-SHA1 with the key's inner and outer pads hashed once, up front.
+#[derive(Clone)]
+pub struct HmacSha1 {
+    inner: Sha1,
+    outer: Sha1
+}
+
+impl HmacSha1 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut padded = [0u8; BLOCK_SIZE];
+        if key.len() > BLOCK_SIZE {
+            let mut hash = Sha1::new();
+            hash.update(key);
+            padded[..DIGEST_SIZE].copy_from_slice(&hash.finish());
+        } else {
+            padded[..key.len()].copy_from_slice(key);
+        }
+        let mut inner = Sha1::new();
+        inner.update(&padded.map(|byte| byte ^ 0x36));
+        let mut outer = Sha1::new();
+        outer.update(&padded.map(|byte| byte ^ 0x5C));
+        Self { inner, outer }
+    }
+
+    // The MAC of the concatenation of `parts`.
+    pub fn mac(&self, parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+        let mut inner = self.inner.clone();
+        for part in parts {
+            inner.update(part);
+        }
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finish());
+        outer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha1(parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+        let mut hash = Sha1::new();
+        for part in parts {
+            hash.update(part);
+        }
+        hash.finish()
+    }
+
+    // FIPS 180 examples, the second one fed in pieces that straddle its
+    // blocks.
+    #[test]
+    fn hashes_the_fips_180_examples() {
+        assert_eq!(
+            sha1(&[b"abc"]),
+            [
+                0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0,
+                0xd8, 0x9d
+            ]
+        );
+        let message = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        let (first, rest) = message.split_at(5);
+        assert_eq!(
+            sha1(&[first, rest]),
+            [
+                0x84, 0x98, 0x3e, 0x44, 0x1c, 0x3b, 0xd2, 0x6e, 0xba, 0xae, 0x4a, 0xa1, 0xf9, 0x51, 0x29, 0xe5, 0xe5, 0x46,
+                0x70, 0xf1
+            ]
+        );
+    }
+
+    // RFC 2202 test cases 1, 2 and 6 (a key longer than a block).
+    #[test]
+    fn macs_the_rfc_2202_examples() {
+        assert_eq!(
+            HmacSha1::new(&[0x0b; 20]).mac(&[b"Hi ", b"There"]),
+            [
+                0xb6, 0x17, 0x31, 0x86, 0x55, 0x05, 0x72, 0x64, 0xe2, 0x8b, 0xc0, 0xb6, 0xfb, 0x37, 0x8c, 0x8e, 0xf1, 0x46,
+                0xbe, 0x00
+            ]
+        );
+        assert_eq!(
+            HmacSha1::new(b"Jefe").mac(&[b"what do ya want for nothing?"]),
+            [
+                0xef, 0xfc, 0xdf, 0x6a, 0xe5, 0xeb, 0x2f, 0xa2, 0xd2, 0x74, 0x16, 0xd5, 0xf1, 0x84, 0xdf, 0x9c, 0x25, 0x9a,
+                0x7c, 0x79
+            ]
+        );
+        assert_eq!(
+            HmacSha1::new(&[0xaa; 80]).mac(&[b"Test Using Larger Than Block-Size Key - Hash Key First"]),
+            [
+                0xaa, 0x4a, 0xe5, 0xe1, 0x52, 0x72, 0xd0, 0x0e, 0x95, 0x70, 0x56, 0x37, 0xce, 0x8a, 0x3b, 0x55, 0xed, 0x40,
+                0x21, 0x12
+            ]
+        );
+    }
+}
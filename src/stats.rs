@@ -0,0 +1,801 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::time::{self, MediaTimestamp, NtpTimestamp};
+use crate::{rtcp, RtpError};
+
+/// Totals for one `stream_access_units` call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StreamSummary {
+    /// Frames sent. Those the pusher dropped (paused, waiting for an IDR,
+    /// over the latency bound) are not counted.
+    pub frames: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub elapsed: Duration
+}
+
+// Number of recent frames of each kind the average sizes are taken over.
+const FRAME_SIZE_WINDOW: usize = 32;
+// Number of recent GOPs the average GOP length is taken over.
+const GOP_WINDOW: usize = 32;
+
+// RTCP APP name and subtype (layout version) of the frame stats report.
+const APP_NAME: [u8; 4] = *b"RTFS";
+const APP_SUBTYPE: u8 = 0;
+const APP_FIELDS: usize = 6;
+
+/// Cumulative NAL payload bytes (start codes and RTP/FU headers excluded),
+/// broken down by NAL type and by access unit type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PayloadStats {
+    /// Indexed by the 5-bit nal_unit_type.
+    pub bytes_per_nal_type: [u64; 32],
+    pub idr_frames: u64,
+    pub idr_frame_bytes: u64,
+    pub non_idr_frames: u64,
+    pub non_idr_frame_bytes: u64,
+    /// Average size of the last 32 IDR access units.
+    pub average_idr_frame_size: Option<u64>,
+    /// Average size of the last 32 non-IDR access units.
+    pub average_non_idr_frame_size: Option<u64>
+}
+
+impl PayloadStats {
+    pub fn bytes_for_nal_type(&self, nal_type: u8) -> u64 {
+        self.bytes_per_nal_type[(nal_type & 0x1F) as usize]
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PayloadAccounting {
+    stats: PayloadStats,
+    frame_bytes: u64,
+    frame_has_idr: bool,
+    recent_idr_sizes: VecDeque<u64>,
+    recent_non_idr_sizes: VecDeque<u64>
+}
+
+impl PayloadAccounting {
+    pub fn record_nal(&mut self, nal_type: u8, len: usize) {
+        self.stats.bytes_per_nal_type[(nal_type & 0x1F) as usize] += len as u64;
+        self.frame_bytes += len as u64;
+        self.frame_has_idr |= nal_type == 5; // IDR slice
+    }
+
+    pub fn finish_frame(&mut self) {
+        if self.frame_bytes == 0 {
+            return;
+        }
+
+        let recent = if self.frame_has_idr {
+            self.stats.idr_frames += 1;
+            self.stats.idr_frame_bytes += self.frame_bytes;
+            &mut self.recent_idr_sizes
+        } else {
+            self.stats.non_idr_frames += 1;
+            self.stats.non_idr_frame_bytes += self.frame_bytes;
+            &mut self.recent_non_idr_sizes
+        };
+        push_recent(recent, self.frame_bytes, FRAME_SIZE_WINDOW);
+
+        self.frame_bytes = 0;
+        self.frame_has_idr = false;
+    }
+
+    pub fn snapshot(&self) -> PayloadStats {
+        let mut stats = self.stats.clone();
+        stats.average_idr_frame_size = average(&self.recent_idr_sizes);
+        stats.average_non_idr_frame_size = average(&self.recent_non_idr_sizes);
+        stats
+    }
+}
+
+fn average(sizes: &VecDeque<u64>) -> Option<u64> {
+    if sizes.is_empty() {
+        return None;
+    }
+    Some(sizes.iter().sum::<u64>() / sizes.len() as u64)
+}
+
+/// Live counters of an `H264RtpPusher`, e.g. for a status display. Unlike
+/// the Sender Report counts they can be cleared with `reset_stats`. All
+/// are 64-bit and independent of the 16-bit sequence number, so they keep
+/// counting through its wraparound.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SenderStats {
+    /// Media packets, as counted in Sender Reports.
+    pub packets_sent: u64,
+    /// Their size, RTP headers included.
+    pub bytes_sent: u64,
+    /// Bits per second sent over the last bitrate window.
+    pub bitrate: u64,
+    pub frames_sent: u64,
+    /// NAL units too large for one packet, sent as FU-A fragments.
+    pub fragmented_nals: u64,
+    /// The latest error that failed a send, as displayed.
+    pub last_send_error: Option<String>,
+    /// The RTX stream, counted apart: none of the figures above include
+    /// retransmissions.
+    pub rtx: RetransmissionStats,
+    /// RTCP PLIs about the stream, each a receiver asking for a keyframe.
+    pub plis_received: u64
+}
+
+/// The retransmission stream of `H264RtpPusher::enable_retransmission`,
+/// with its own SSRC and counts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RetransmissionStats {
+    /// Generic NACK packets received about the media stream.
+    pub nacks_received: u64,
+    /// Packets they asked for.
+    pub packets_requested: u64,
+    /// RTX packets sent, also counted in the RTX Sender Reports.
+    pub packets_sent: u64,
+    /// Their size, RTP headers included.
+    pub bytes_sent: u64,
+    /// Requested packets no longer in the history, or never sent.
+    pub packets_unavailable: u64
+}
+
+/// NAL units dropped by `H264RtpPusher::set_priority_dropping`, by class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PriorityDrops {
+    /// NRI 0: SEI, AUD, filler and disposable slices.
+    pub disposable_nals: u64,
+    /// Non-IDR slices with a non-zero NRI, which later frames refer to.
+    pub non_idr_slices: u64
+}
+
+// The bitrate window is split into this many slots of byte counts.
+const BITRATE_SLOTS: u32 = 10;
+
+// Bits per second over a sliding window, from a small ring of timestamped
+// byte counts. Shared by the sender and receiver statistics.
+pub(crate) struct BitrateMeter {
+    window: Duration,
+    // Start of each slot and the bytes counted in it, oldest first.
+    recent_bytes: VecDeque<(Instant, u64)>
+}
+
+impl BitrateMeter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            recent_bytes: VecDeque::new()
+        }
+    }
+
+    // Starts over with `window`.
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+        self.recent_bytes.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.recent_bytes.clear();
+    }
+
+    pub fn record(&mut self, len: usize, now: Instant) {
+        let slot = self.window / BITRATE_SLOTS;
+        match self.recent_bytes.back_mut() {
+            Some((start, bytes)) if now.saturating_duration_since(*start) < slot => *bytes += len as u64,
+            _ => self.recent_bytes.push_back((now, len as u64))
+        }
+        while let Some(&(start, _)) = self.recent_bytes.front() {
+            if !self.expired(start, now) {
+                break;
+            }
+            self.recent_bytes.pop_front();
+        }
+    }
+
+    pub fn bits_per_second(&self, now: Instant) -> u64 {
+        let mut recent = self.recent_bytes.iter().filter(|&&(start, _)| !self.expired(start, now)).peekable();
+        // The oldest slot reaches back past the window by up to a slot, so
+        // the bytes are spread over the time they were actually counted in.
+        // A stream younger than the window is still averaged over all of it.
+        let span = match recent.peek() {
+            Some(&&(oldest, _)) => now.saturating_duration_since(oldest).max(self.window),
+            None => self.window
+        };
+        let bits = recent.map(|&(_, bytes)| bytes * 8).sum::<u64>();
+        match span.as_micros() {
+            0 => 0,
+            span => (bits as u128 * 1_000_000 / span) as u64
+        }
+    }
+
+    // Whether the slot starting at `start` ended before the window.
+    fn expired(&self, start: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(start + self.window / BITRATE_SLOTS) >= self.window
+    }
+}
+
+pub(crate) struct SenderAccounting {
+    stats: SenderStats,
+    bitrate: BitrateMeter
+}
+
+impl SenderAccounting {
+    pub fn new(bitrate_window: Duration) -> Self {
+        Self {
+            stats: SenderStats::default(),
+            bitrate: BitrateMeter::new(bitrate_window)
+        }
+    }
+
+    pub fn set_bitrate_window(&mut self, window: Duration) {
+        self.bitrate.set_window(window);
+    }
+
+    pub fn record_packet(&mut self, len: usize, now: Instant) {
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += len as u64;
+        self.bitrate.record(len, now);
+    }
+
+    pub fn record_nack(&mut self, requested: usize, unavailable: usize) {
+        self.stats.rtx.nacks_received += 1;
+        self.stats.rtx.packets_requested += requested as u64;
+        self.stats.rtx.packets_unavailable += unavailable as u64;
+    }
+
+    pub fn record_plis(&mut self, count: usize) {
+        self.stats.plis_received += count as u64;
+    }
+
+    pub fn record_retransmission(&mut self, len: usize) {
+        self.stats.rtx.packets_sent += 1;
+        self.stats.rtx.bytes_sent += len as u64;
+    }
+
+    pub fn record_frame(&mut self) {
+        self.stats.frames_sent += 1;
+    }
+
+    pub fn record_fragmented_nal(&mut self) {
+        self.stats.fragmented_nals += 1;
+    }
+
+    pub fn record_error(&mut self, err: &RtpError) {
+        self.stats.last_send_error = Some(err.to_string());
+    }
+
+    pub fn reset(&mut self) {
+        self.stats = SenderStats::default();
+        self.bitrate.clear();
+    }
+
+    pub fn snapshot(&self, now: Instant) -> SenderStats {
+        let mut stats = self.stats.clone();
+        stats.bitrate = self.bitrate.bits_per_second(now);
+        stats
+    }
+}
+
+/// What an `H264RtpReceiver` has seen of the current source, updated as
+/// packets arrive. A new SSRC starts the counts over. The same numbers go
+/// into RTCP Receiver Reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReceiverStats {
+    /// RTP packets, duplicates included.
+    pub packets_received: u64,
+    /// Sequence numbers from the first to the highest seen that never
+    /// arrived. A late packet lowers it again.
+    pub packets_lost: u64,
+    /// Packets older than one received before them, duplicates excluded.
+    pub out_of_order: u64,
+    /// Second and later copies of a sequence number among the last 1024.
+    /// They are dropped before reassembly.
+    pub duplicates: u64,
+    /// Interarrival jitter (RFC 3550 section 6.4.1), converted from RTP
+    /// units on the 90 kHz clock.
+    pub jitter: Duration,
+    /// RTP packet bytes, headers included.
+    pub bytes_received: u64,
+    /// Bits per second received over the last second.
+    pub bitrate: u64,
+    /// Packets the jitter buffer put back in order after a later one had
+    /// arrived; 0 without a jitter buffer.
+    pub reordered: u64,
+    /// Packets the jitter buffer dropped for arriving after their place was
+    /// released; 0 without a jitter buffer.
+    pub late: u64,
+    /// Packets dropped because their SRTP tag did not match, counted since
+    /// SRTP was turned on whatever their source; 0 without SRTP.
+    pub srtp_auth_failures: u64,
+    /// Frames whose CRC did not match the pusher's, see
+    /// `H264RtpReceiver::set_frame_integrity`.
+    pub crc_mismatches: u64,
+    /// End-to-end latency of the frames delivered so far, smoothed like
+    /// the jitter (gain 1/16). Like `LatencyEstimate` it includes the
+    /// offset between the sender's and the receiver's wall clocks. None
+    /// until a frame was delivered after a Sender Report.
+    pub end_to_end_latency: Option<Duration>,
+    /// How long the jitter buffer currently waits for a missing packet, as
+    /// `MetricsSummary::jitter_buffer_delay`; None without a jitter buffer
+    /// or with a depth in packets.
+    pub jitter_buffer_delay: Option<Duration>
+}
+
+/// Loss, discard and burst/gap metrics of the current source in the terms
+/// of RTCP XR (RFC 3611 section 4.7), from `H264RtpReceiver::metrics_summary`.
+/// Packets count as missing when they did not reach reassembly, lost on
+/// the way or discarded by the jitter buffer for arriving too late. A burst
+/// runs from a missing packet to the last missing one before `gmin`
+/// packets in a row arrived; a lone missing packet between gaps is a gap
+/// loss.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSummary {
+    /// None before the first packet.
+    pub source_ssrc: Option<u32>,
+    pub gmin: u8,
+    /// Packets that reached reassembly plus those missing in between.
+    pub packets_expected: u64,
+    /// As in `ReceiverStats::packets_lost`.
+    pub packets_lost: u64,
+    /// As in `ReceiverStats::late`.
+    pub packets_discarded: u64,
+    pub bursts: u64,
+    /// Packets within bursts, missing or not, and the missing ones.
+    pub burst_packets: u64,
+    pub burst_losses: u64,
+    pub gap_packets: u64,
+    pub gap_losses: u64,
+    /// From the packet before a burst's first missing one to the packet
+    /// after its last, on average.
+    pub mean_burst_duration: Duration,
+    pub mean_gap_duration: Duration,
+    /// How long the jitter buffer waits for a missing packet, when that is
+    /// a time (`JitterDepth::Time`, or the current target of
+    /// `JitterDepth::Adaptive`).
+    pub jitter_buffer_delay: Option<Duration>
+}
+
+impl MetricsSummary {
+    /// `packets_lost` in 1/256ths of `packets_expected`, as XR carries it.
+    pub fn loss_rate(&self) -> u8 {
+        fraction(self.packets_lost, self.packets_expected)
+    }
+
+    pub fn discard_rate(&self) -> u8 {
+        fraction(self.packets_discarded, self.packets_expected)
+    }
+
+    /// `burst_losses` in 1/256ths of `burst_packets`.
+    pub fn burst_density(&self) -> u8 {
+        fraction(self.burst_losses, self.burst_packets)
+    }
+
+    pub fn gap_density(&self) -> u8 {
+        fraction(self.gap_losses, self.gap_packets)
+    }
+}
+
+// part / whole in 1/256ths, 255 at most.
+fn fraction(part: u64, whole: u64) -> u8 {
+    match whole {
+        0 => 0,
+        _ => (part.saturating_mul(256) / whole).min(255) as u8
+    }
+}
+
+/// One RTCP reception report block (RFC 3550 section 6.4.1): how a
+/// receiver sees the stream of `source_ssrc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReceptionReport {
+    pub reporter_ssrc: u32,
+    pub source_ssrc: u32,
+    /// Packets lost since the previous report, in 1/256ths of those
+    /// expected.
+    pub fraction_lost: u8,
+    /// Packets expected minus packets received; negative with duplicates.
+    pub cumulative_lost: i32,
+    /// Highest sequence number received, extended with the wrap count in
+    /// the high 16 bits.
+    pub highest_sequence: u32,
+    /// Interarrival jitter in RTP timestamp units.
+    pub jitter: u32,
+    /// Middle 32 bits of the NTP timestamp of the last Sender Report
+    /// received, 0 if none.
+    pub last_sender_report: u32,
+    /// Time since that Sender Report, in 1/65536 s.
+    pub delay_since_last_sender_report: u32,
+    /// Filled in by the pusher from the two fields above when the report
+    /// refers to one of its Sender Reports.
+    pub round_trip_time: Option<Duration>
+}
+
+/// The sender information of an RTCP Sender Report (RFC 3550 section
+/// 6.4.1): which RTP timestamp the sender's wall clock `ntp` corresponds
+/// to, for lip sync and latency estimates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SenderReport {
+    pub ssrc: u32,
+    pub ntp: NtpTimestamp,
+    pub rtp_timestamp: u32,
+    /// Packets and payload bytes the sender had sent, wrapping at 2^32.
+    pub packet_count: u32,
+    pub octet_count: u32,
+    /// When the report arrived, on the receiver's clock.
+    pub arrival: Instant
+}
+
+impl SenderReport {
+    /// The sender's wall-clock time of `rtp_timestamp` on a `clock_rate`
+    /// clock, by this report's mapping. Timestamps up to half the 32-bit
+    /// wrap either side of the report's are told apart.
+    pub fn wall_clock_at(&self, rtp_timestamp: u32, clock_rate: u32) -> SystemTime {
+        let ticks = time::wire_diff(rtp_timestamp, self.rtp_timestamp);
+        let offset = MediaTimestamp::new(ticks.unsigned_abs() as u64, clock_rate).to_duration();
+        let reported = self.ntp.to_system_time();
+        if ticks < 0 {
+            reported - offset
+        } else {
+            reported + offset
+        }
+    }
+}
+
+/// A frame's end-to-end latency as the receiver estimates it from the
+/// sender's last Sender Report: capture (the sender's wall-clock time of
+/// the frame's RTP timestamp) to delivery (the receiver's wall clock). It
+/// is only as good as the two wall clocks agree; any offset between them
+/// is added to it. The components are kept for hosts whose clocks are
+/// synchronized by other means, e.g. PTP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LatencyEstimate {
+    /// The sender's wall-clock time of the frame, by `sender_report`.
+    pub sent_at: SystemTime,
+    /// The receiver's wall-clock time when the frame was delivered.
+    pub delivered_at: SystemTime,
+    /// The report the mapping came from.
+    pub sender_report: SenderReport
+}
+
+impl LatencyEstimate {
+    pub(crate) fn new(sender_report: SenderReport, timestamp: u32, delivered_at: SystemTime) -> Self {
+        Self {
+            sent_at: sender_report.wall_clock_at(timestamp, time::VIDEO_CLOCK_RATE),
+            delivered_at,
+            sender_report
+        }
+    }
+
+    /// `delivered_at - sent_at`; None when the clock offset makes the frame
+    /// look delivered before it was sent.
+    pub fn latency(&self) -> Option<Duration> {
+        self.delivered_at.duration_since(self.sent_at).ok()
+    }
+}
+
+/// An RTCP BYE (RFC 3550 section 6.6) naming the receiver's source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Goodbye {
+    pub ssrc: u32,
+    pub reason: Option<String>
+}
+
+/// A participant of the RTP session as its RTCP showed it, from
+/// `H264RtpReceiver::session_members`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SessionMember {
+    pub ssrc: u32,
+    /// From its SDES packets; None until one arrived.
+    pub cname: Option<String>,
+    /// When its last RTCP packet arrived, on the receiver's clock.
+    pub last_seen: Instant,
+    pub role: MemberRole
+}
+
+/// Whether a member's last report was a Sender Report or a Receiver
+/// Report. Members that sent neither yet count as receivers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MemberRole {
+    Sender,
+    Receiver
+}
+
+/// A change in session membership, from `H264RtpReceiver::member_events`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MemberEvent {
+    Joined { ssrc: u32 },
+    Left { ssrc: u32, reason: LeaveReason }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LeaveReason {
+    /// It sent a BYE, with a reason if it gave one.
+    Bye(Option<String>),
+    /// It sent no RTCP for five report intervals (RFC 3550 section 6.3.5).
+    Timeout,
+    /// It was the least recently seen when a new member would have gone
+    /// over the member limit.
+    Evicted
+}
+
+/// A change of the adaptive jitter buffer's wait, from
+/// `H264RtpReceiver::jitter_adaptations`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct JitterAdaptation {
+    /// Arrival of the packet that brought it about, on the receiver's
+    /// clock.
+    pub at: Instant,
+    pub from: Duration,
+    pub to: Duration,
+    /// The RFC 3550 jitter estimate the buffer had at the time.
+    pub jitter: Duration
+}
+
+/// A keyframe request the receiver made by itself, from
+/// `H264RtpReceiver::keyframe_requests`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KeyframeRequest {
+    /// On the receiver's clock.
+    pub at: Instant,
+    pub reason: RefreshReason,
+    /// 1 for the first request since the stream was last fine, counting up
+    /// while keyframes fail to arrive.
+    pub attempt: u32,
+    /// Whether a PLI went out: it needs `enable_receiver_reports` and a
+    /// place to send RTCP to.
+    pub sent: bool
+}
+
+/// What set off a `KeyframeRequest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RefreshReason {
+    /// Over the policy's share of the frames in its window were incomplete.
+    IncompleteFrames { incomplete: u32, frames: u32 },
+    /// A non-IDR frame that later frames refer to lost packets, and no
+    /// IDR has arrived since.
+    ReferenceLoss
+}
+
+/// A sender bug the stream doctor saw, from `H264RtpReceiver::diagnostics`.
+/// An occurrence is a run of packets showing it, ended by one that does
+/// not; for `SliceOutsideSps`, a run of frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Finding {
+    /// With the context of its first occurrence.
+    pub pathology: Pathology,
+    pub occurrences: u64,
+    /// Packets showing it, over all occurrences.
+    pub packets: u64,
+    /// The first packet showing it, and when it arrived on the receiver's
+    /// clock.
+    pub first_sequence: u16,
+    pub first_timestamp: u32,
+    pub first_seen: Instant
+}
+
+/// Stream bugs of the kind cameras are known for, each one a decoder shows
+/// as artifacts or stalls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Pathology {
+    /// FU-A fragments of a NAL whose Start fragment was never sent: no
+    /// packet was lost before them.
+    FuStartMissing,
+    /// A NAL header, or the FU indicator or STAP-A header, with
+    /// forbidden_zero_bit set.
+    ForbiddenZeroBit,
+    /// A slice starting past the last macroblock of the frame size its SPS
+    /// gives.
+    SliceOutsideSps { first_mb_in_slice: u32, pic_size_in_mbs: u32 },
+    /// Access units after a marker bit keep the timestamp of the one
+    /// before, or one timestamp lasts over two seconds of packets.
+    FrozenTimestamp
+}
+
+/// What `H264RtpReceiver::set_relay` forwarded, counted apart from the
+/// stream so a failing relay shows up here while frames keep coming.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RelayStats {
+    pub packets_relayed: u64,
+    /// Their size as received, RTP headers included.
+    pub bytes_relayed: u64,
+    /// Datagrams the relay sink refused, and RTCP packets the relay could
+    /// not send; they are not retried.
+    pub send_errors: u64,
+    /// The latest of those errors, as displayed.
+    pub last_error: Option<String>,
+    /// RTCP compound packets sent to the receivers behind the relay (see
+    /// `H264RtpReceiver::enable_relay_rtcp`), passed through or
+    /// regenerated.
+    pub rtcp_to_receivers: u64,
+    /// Compound packets from those receivers passed on to the origin.
+    pub rtcp_to_origin: u64,
+    /// Compound packets not passed on because that direction regenerates
+    /// reports instead.
+    pub rtcp_terminated: u64,
+    /// Compound packets dropped as malformed rather than passed on broken.
+    pub rtcp_malformed: u64
+}
+
+/// Datagrams the receiver refused as RTP, and packets with the header
+/// quirks of legacy encoders (see `H264RtpReceiver::set_lenient_headers`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeaderStats {
+    /// Datagrams too short, of an unknown version, or with a padding count
+    /// or header extension running past their end.
+    pub malformed_packets: u64,
+    /// Quirky packets dropped by a strict receiver.
+    pub quirks_rejected: u64,
+    /// Quirky packets a lenient receiver took; any at all means the stream
+    /// only plays thanks to lenience.
+    pub quirks_accepted: u64
+}
+
+/// RTP packets whose H.264 payload (RFC 6184) the receiver refused, by the
+/// rule they broke. Such a packet adds nothing to its frame, which counts
+/// as incomplete, like one that lost a packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PayloadErrors {
+    /// STAP-A packets with a unit that is not a single NAL (types 1-23),
+    /// e.g. a nested aggregate or a fragment.
+    pub invalid_aggregated_nals: u64,
+    /// STAP-A packets with a unit of length 0 or running past the packet,
+    /// or with no unit at all.
+    pub malformed_aggregation_units: u64,
+    /// STAP-A packets with more than 64 units.
+    pub oversized_aggregates: u64,
+    /// FU-A fragments whose FU header names a type outside 1-23.
+    pub invalid_fragmented_nals: u64,
+    /// Payload types the receiver does not take apart: 0, STAP-B, MTAP,
+    /// FU-B and 30-31, and FU-A packets too short for a FU header.
+    pub unsupported_packets: u64
+}
+
+/// Counters of one path of a redundant receiver, to watch path health.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PathStats {
+    pub packets_received: u64,
+    /// Sequence numbers this path skipped, whether or not the other path
+    /// filled them in.
+    pub packets_lost: u64,
+    /// Copies that arrived after the other path's, and repeats.
+    pub duplicates: u64
+}
+
+/// The stream's frame structure as seen by the receiver, for encoder
+/// feedback loops such as shortening GOPs on lossy links. `to_rtcp_app`
+/// encodes it for the sender, `from_rtcp_app` decodes it there.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReceivedFrameStats {
+    pub frames: u64,
+    pub idr_frames: u64,
+    /// Frames delivered after a packet of theirs was lost.
+    pub incomplete_idr_frames: u64,
+    pub incomplete_non_idr_frames: u64,
+    /// Frames from one IDR to the next, over the last 32 GOPs.
+    pub average_gop_length: Option<u64>,
+    /// Average size of the last 32 IDR frames as delivered (Annex B).
+    pub average_idr_frame_size: Option<u64>,
+    /// Frames that never arrived, counted from steps in RTP timestamps
+    /// beyond the nominal frame interval. Frames whose every packet was
+    /// lost only show up here.
+    pub missing_frames: u64,
+    /// Frames with the same timestamp as the one before.
+    pub duplicate_frames: u64,
+    /// The most common recent timestamp step, in RTP ticks; None over the
+    /// first few frames.
+    pub nominal_frame_interval: Option<u32>,
+    /// Frames cut off at the size cap (see
+    /// `H264RtpReceiver::set_max_frame_size`) and delivered incomplete.
+    pub evicted_frames: u64
+}
+
+impl ReceivedFrameStats {
+    /// An RTCP APP packet (name "RTFS") carrying these stats for the stream
+    /// `ssrc`, to send on its own or appended to a compound packet. The
+    /// continuity and eviction counters stay out of it.
+    pub fn to_rtcp_app(&self, ssrc: u32) -> Vec<u8> {
+        let fields: [u64; APP_FIELDS] = [
+            self.frames,
+            self.idr_frames,
+            self.incomplete_idr_frames,
+            self.incomplete_non_idr_frames,
+            // Neither can be zero when known.
+            self.average_gop_length.unwrap_or(0),
+            self.average_idr_frame_size.unwrap_or(0)
+        ];
+        let data: Vec<u8> = fields.iter().flat_map(|field| field.to_be_bytes()).collect();
+        rtcp::app_packet(APP_SUBTYPE, ssrc, APP_NAME, &data)
+    }
+
+    /// Finds the stats report in an RTCP packet, compound or not, and
+    /// returns the SSRC it is about along with the stats.
+    pub fn from_rtcp_app(packet: &[u8]) -> Option<(u32, Self)> {
+        let app = rtcp::find_app(packet, APP_NAME)?;
+        if app.subtype != APP_SUBTYPE || app.data.len() < APP_FIELDS * 8 {
+            return None;
+        }
+        let mut fields = app.data.chunks_exact(8).map(|field| {
+            u64::from_be_bytes([field[0], field[1], field[2], field[3], field[4], field[5], field[6], field[7]])
+        });
+        let mut next = || fields.next().unwrap_or(0);
+        let stats = ReceivedFrameStats {
+            frames: next(),
+            idr_frames: next(),
+            incomplete_idr_frames: next(),
+            incomplete_non_idr_frames: next(),
+            average_gop_length: Some(next()).filter(|&length| length != 0),
+            average_idr_frame_size: Some(next()).filter(|&size| size != 0),
+            ..Self::default()
+        };
+        Some((app.ssrc, stats))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FrameAccounting {
+    stats: ReceivedFrameStats,
+    // None until the first IDR.
+    frames_since_idr: Option<u64>,
+    recent_gop_lengths: VecDeque<u64>,
+    recent_idr_sizes: VecDeque<u64>
+}
+
+impl FrameAccounting {
+    pub fn record_frame(&mut self, size: usize, idr: bool, incomplete: bool) {
+        self.stats.frames += 1;
+        if !idr {
+            if incomplete {
+                self.stats.incomplete_non_idr_frames += 1;
+            }
+            if let Some(frames) = self.frames_since_idr.as_mut() {
+                *frames += 1;
+            }
+            return;
+        }
+
+        self.stats.idr_frames += 1;
+        if incomplete {
+            self.stats.incomplete_idr_frames += 1;
+        }
+        if let Some(length) = self.frames_since_idr.replace(1) {
+            push_recent(&mut self.recent_gop_lengths, length, GOP_WINDOW);
+        }
+        push_recent(&mut self.recent_idr_sizes, size as u64, FRAME_SIZE_WINDOW);
+    }
+
+    pub fn snapshot(&self) -> ReceivedFrameStats {
+        let mut stats = self.stats;
+        stats.average_gop_length = average(&self.recent_gop_lengths);
+        stats.average_idr_frame_size = average(&self.recent_idr_sizes);
+        stats
+    }
+}
+
+fn push_recent(recent: &mut VecDeque<u64>, value: u64, window: usize) {
+    if recent.len() == window {
+        recent.pop_front();
+    }
+    recent.push_back(value);
+}
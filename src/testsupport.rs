@@ -0,0 +1,212 @@
+//! Reproducible synthetic input for benchmarks and soak tests.
+//!
+//! [`SyntheticH264`] produces Annex B access units that look like a real
+//! stream to the packetizer: a small valid SPS/PPS pair (baseline profile,
+//! 320x240) ahead of every IDR, and slices with valid headers followed by
+//! pseudo-random payload. The slices do not decode. The output depends only
+//! on the seed and the settings.
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use crate::time::VIDEO_CLOCK_RATE;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+const SPS: [u8; 8] = [0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x05, 0x07, 0xE4];
+const PPS: [u8; 4] = [0x68, 0xCE, 0x3C, 0x80];
+// Macroblocks in a 320x240 picture, split between the slices of a frame.
+const MACROBLOCKS_PER_FRAME: u32 = 20 * 15;
+// slice_type values meaning "all slices of the picture are I / P".
+const SLICE_TYPE_I: u32 = 7;
+const SLICE_TYPE_P: u32 = 5;
+
+/// Deterministic generator of H.264 access units paired with their 90 kHz
+/// PTS, ready for `H264RtpPusher::stream_access_units`.
+/// Settings are chained from `new(seed)`, e.g.
+/// `SyntheticH264::new(7).gop_length(60).slices_per_frame(4)`.
+#[derive(Clone, Debug)]
+pub struct SyntheticH264 {
+    gop_length: u32,
+    slices_per_frame: u32,
+    slice_size: RangeInclusive<usize>,
+    idr_slice_size: RangeInclusive<usize>,
+    frame_rate: u32,
+    frame_count: u64,
+    frame_index: u64,
+    rng: XorShift
+}
+
+impl SyntheticH264 {
+    /// 30 fps, one IDR per second, one slice per frame, 10 seconds.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            gop_length: 30,
+            slices_per_frame: 1,
+            slice_size: 200..=4_000,
+            idr_slice_size: 10_000..=40_000,
+            frame_rate: 30,
+            frame_count: 300,
+            frame_index: 0,
+            rng: XorShift::new(seed)
+        }
+    }
+
+    /// Frames from one IDR to the next, at least 1.
+    pub fn gop_length(mut self, frames: u32) -> Self {
+        self.gop_length = frames.max(1);
+        self
+    }
+
+    /// Slice NALs per access unit, between 1 and the picture's 300
+    /// macroblocks.
+    pub fn slices_per_frame(mut self, slices: u32) -> Self {
+        self.slices_per_frame = slices.clamp(1, MACROBLOCKS_PER_FRAME);
+        self
+    }
+
+    /// Size range of each non-IDR slice NAL, start code excluded. Sizes are
+    /// drawn uniformly.
+    pub fn slice_size(mut self, bytes: RangeInclusive<usize>) -> Self {
+        self.slice_size = bytes;
+        self
+    }
+
+    /// Size range of each IDR slice NAL, start code excluded.
+    pub fn idr_slice_size(mut self, bytes: RangeInclusive<usize>) -> Self {
+        self.idr_slice_size = bytes;
+        self
+    }
+
+    /// Frame rate, at least 1 fps. The total duration is kept.
+    pub fn frame_rate(mut self, fps: u32) -> Self {
+        let duration = self.total_duration();
+        self.frame_rate = fps.max(1);
+        self.frame_count = frames_in(duration, self.frame_rate);
+        self
+    }
+
+    /// Total stream length, rounded down to whole frames.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.frame_count = frames_in(duration, self.frame_rate);
+        self
+    }
+
+    fn total_duration(&self) -> Duration {
+        Duration::from_secs(self.frame_count) / self.frame_rate
+    }
+
+    fn slice(&mut self, idr: bool, index: u32) -> Vec<u8> {
+        let range = if idr { &self.idr_slice_size } else { &self.slice_size };
+        let (min, max) = (*range.start(), *range.end());
+
+        let mut header = BitWriter::default();
+        header.write_ue(index * MACROBLOCKS_PER_FRAME / self.slices_per_frame);
+        header.write_ue(if idr { SLICE_TYPE_I } else { SLICE_TYPE_P });
+        header.write_ue(0); // pic_parameter_set_id
+        header.write_bit(true);
+
+        let mut nal = vec![if idr { 0x65 } else { 0x41 }];
+        nal.extend(header.into_bytes());
+        let size = self.rng.in_range(min.max(nal.len() + 1), max.max(min));
+        // Payload bytes are never zero, so no start code can appear inside
+        // a slice and no emulation prevention is needed.
+        while nal.len() < size {
+            nal.push((self.rng.next_u64() % 255) as u8 + 1);
+        }
+        nal
+    }
+}
+
+impl Iterator for SyntheticH264 {
+    type Item = (Vec<u8>, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_index >= self.frame_count {
+            return None;
+        }
+        let idr = self.frame_index.is_multiple_of(self.gop_length as u64);
+        let pts = (self.frame_index * VIDEO_CLOCK_RATE as u64 / self.frame_rate as u64) as u32;
+
+        let mut access_unit = Vec::new();
+        if idr {
+            for parameter_set in [&SPS[..], &PPS[..]] {
+                access_unit.extend_from_slice(&START_CODE);
+                access_unit.extend_from_slice(parameter_set);
+            }
+        }
+        for index in 0..self.slices_per_frame {
+            access_unit.extend_from_slice(&START_CODE);
+            let slice = self.slice(idr, index);
+            access_unit.extend(slice);
+        }
+
+        self.frame_index += 1;
+        Some((access_unit, pts))
+    }
+}
+
+fn frames_in(duration: Duration, frame_rate: u32) -> u64 {
+    (duration.as_nanos() * frame_rate as u128 / 1_000_000_000) as u64
+}
+
+// Also drives the sender's FaultInjector.
+#[derive(Clone, Debug)]
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        // xorshift never leaves the all-zero state.
+        XorShift(if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed })
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn in_range(&mut self, min: usize, max: usize) -> usize {
+        if max <= min {
+            return min;
+        }
+        min + (self.next_u64() % (max - min + 1) as u64) as usize
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bits.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            let last = self.bytes.len() - 1;
+            self.bytes[last] |= 0x80 >> (self.bits % 8);
+        }
+        self.bits += 1;
+    }
+
+    fn write_ue(&mut self, value: u32) {
+        let code = value as u64 + 1;
+        let len = 64 - code.leading_zeros();
+        for _ in 1..len {
+            self.write_bit(false);
+        }
+        for shift in (0..len).rev() {
+            self.write_bit(code >> shift & 1 == 1);
+        }
+    }
+
+    // Unused trailing bits are set so the header never ends in a zero byte.
+    fn into_bytes(mut self) -> Vec<u8> {
+        while !self.bits.is_multiple_of(8) {
+            self.write_bit(true);
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,167 @@
+//! Conversions between `Duration`, media clock ticks and NTP timestamps.
+//!
+//! Rounding is always toward zero: a `Duration` becomes the number of whole
+//! ticks it contains, and ticks become the whole nanoseconds they span. All
+//! math is done in integers, so nothing drifts over long streams.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The RTP clock rate for video.
+pub const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// A media clock position: `ticks` at `clock_rate` Hz, unwrapped to 64 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MediaTimestamp {
+    pub ticks: u64,
+    pub clock_rate: u32
+}
+
+impl MediaTimestamp {
+    pub const fn new(ticks: u64, clock_rate: u32) -> Self {
+        Self { ticks, clock_rate }
+    }
+
+    /// Whole ticks contained in `duration`; the sub-tick remainder is
+    /// dropped.
+    pub fn from_duration(duration: Duration, clock_rate: u32) -> Self {
+        Self {
+            ticks: duration_to_ticks(duration, clock_rate),
+            clock_rate
+        }
+    }
+
+    /// Whole nanoseconds spanned by the ticks.
+    pub fn to_duration(&self) -> Duration {
+        let nanos = self.ticks as u128 * NANOS_PER_SEC / self.clock_rate as u128;
+        Duration::new((nanos / NANOS_PER_SEC) as u64, (nanos % NANOS_PER_SEC) as u32)
+    }
+
+    /// Advances by the whole ticks in `duration`.
+    pub fn add_duration(self, duration: Duration) -> Self {
+        Self {
+            ticks: self.ticks + duration_to_ticks(duration, self.clock_rate),
+            clock_rate: self.clock_rate
+        }
+    }
+
+    /// Moves back by the whole ticks in `duration`, stopping at zero.
+    pub fn saturating_sub_duration(self, duration: Duration) -> Self {
+        Self {
+            ticks: self.ticks.saturating_sub(duration_to_ticks(duration, self.clock_rate)),
+            clock_rate: self.clock_rate
+        }
+    }
+
+    /// The 32-bit value carried in the RTP header.
+    pub fn wire(&self) -> u32 {
+        self.ticks as u32
+    }
+}
+
+/// RTP timestamp of a video presentation time, e.g. a file's PTS, for
+/// `H264RtpPusher::send_frame_with_timestamp`.
+pub fn video_rtp_timestamp(pts: Duration) -> u32 {
+    MediaTimestamp::from_duration(pts, VIDEO_CLOCK_RATE).wire()
+}
+
+/// `video_rtp_timestamp` for a PTS in microseconds.
+pub fn video_rtp_timestamp_from_micros(pts_micros: u64) -> u32 {
+    video_rtp_timestamp(Duration::from_micros(pts_micros))
+}
+
+fn duration_to_ticks(duration: Duration, clock_rate: u32) -> u64 {
+    (duration.as_nanos() * clock_rate as u128 / NANOS_PER_SEC) as u64
+}
+
+/// Signed distance from `earlier` to `later` between two wire timestamps,
+/// taking the shorter way around the 32-bit wrap.
+pub fn wire_diff(later: u32, earlier: u32) -> i32 {
+    later.wrapping_sub(earlier) as i32
+}
+
+/// 64-bit NTP timestamp: seconds since 1900 in the high 32 bits, binary
+/// fraction in the low 32 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NtpTimestamp(pub u64);
+
+impl NtpTimestamp {
+    /// Times before the Unix epoch map to the epoch. The fraction is rounded
+    /// down.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+        let seconds = (since_unix.as_secs() + NTP_UNIX_OFFSET_SECS) as u32;
+        let fraction = ((since_unix.subsec_nanos() as u128) << 32) / NANOS_PER_SEC;
+        NtpTimestamp(((seconds as u64) << 32) | fraction as u64)
+    }
+
+    /// Rounds the fraction down to whole nanoseconds. Assumes NTP era 0
+    /// (1900-2036).
+    pub fn to_system_time(&self) -> SystemTime {
+        let seconds = self.0 >> 32;
+        let nanos = ((self.0 & 0xFFFF_FFFF) as u128 * NANOS_PER_SEC) >> 32;
+        let since_ntp_epoch = Duration::new(seconds, nanos as u32);
+        let offset = Duration::from_secs(NTP_UNIX_OFFSET_SECS);
+        UNIX_EPOCH + since_ntp_epoch.saturating_sub(offset)
+    }
+
+    /// The middle 32 bits (16.16 fixed point) used by LSR/DLSR in RTCP
+    /// receiver reports.
+    pub fn short(&self) -> u32 {
+        (self.0 >> 16) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_round_down_to_whole_ticks_and_nanoseconds() {
+        // 1/90000 s is 11111.1 ns: one nanosecond short is no tick at all.
+        assert_eq!(MediaTimestamp::from_duration(Duration::from_nanos(11_111), VIDEO_CLOCK_RATE).ticks, 0);
+        assert_eq!(MediaTimestamp::from_duration(Duration::from_nanos(11_112), VIDEO_CLOCK_RATE).ticks, 1);
+        assert_eq!(MediaTimestamp::new(1, VIDEO_CLOCK_RATE).to_duration(), Duration::from_nanos(11_111));
+        assert_eq!(video_rtp_timestamp_from_micros(33_366), 3002);
+        assert_eq!(MediaTimestamp::new(5, VIDEO_CLOCK_RATE).saturating_sub_duration(Duration::from_secs(1)).ticks, 0);
+    }
+
+    #[test]
+    fn long_spans_convert_exactly() {
+        let year = Duration::from_secs(365 * 24 * 3600);
+        assert_eq!(MediaTimestamp::from_duration(year, VIDEO_CLOCK_RATE).ticks, 365 * 24 * 3600 * 90_000);
+        assert_eq!(MediaTimestamp::new(365 * 24 * 3600 * 90_000, VIDEO_CLOCK_RATE).to_duration(), year);
+        // A day of 29.97 fps frames, each 3003 ticks, added one by one.
+        let frames = 24 * 3600 * 30_000 / 1001;
+        let pts = (0..frames).fold(MediaTimestamp::new(0, VIDEO_CLOCK_RATE), |pts, _| {
+            pts.add_duration(Duration::from_nanos(33_366_667))
+        });
+        assert_eq!(pts.ticks, 3003 * frames);
+    }
+
+    #[test]
+    fn wire_timestamps_wrap_at_32_bits() {
+        let late = MediaTimestamp::new(u32::MAX as u64 + 3001, VIDEO_CLOCK_RATE);
+        assert_eq!(late.wire(), 3000);
+        assert_eq!(wire_diff(late.wire(), u32::MAX - 2), 3003);
+        assert_eq!(wire_diff(u32::MAX - 2, late.wire()), -3003);
+        assert_eq!(wire_diff(7, 7), 0);
+        // Past half the space it reads as the other direction.
+        assert_eq!(wire_diff(1 << 31, 0), i32::MIN);
+        // About 13.25 hours at 90 kHz: the wire value starts over.
+        assert_eq!(video_rtp_timestamp(Duration::from_secs(47_722)), 4_294_980_000u64 as u32);
+    }
+
+    #[test]
+    fn ntp_timestamps_round_trip_to_the_nanosecond_below() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let ntp = NtpTimestamp::from_system_time(time);
+        assert_eq!(ntp.0 >> 32, 1_700_000_000 + NTP_UNIX_OFFSET_SECS);
+        let back = ntp.to_system_time();
+        assert!(back <= time && time.duration_since(back).unwrap() < Duration::from_nanos(2));
+        assert_eq!(NtpTimestamp(0x0123_4567_89AB_CDEF).short(), 0x4567_89AB);
+        assert_eq!(NtpTimestamp::from_system_time(UNIX_EPOCH - Duration::from_secs(1)).0, NTP_UNIX_OFFSET_SECS << 32);
+    }
+}
@@ -0,0 +1,342 @@
+//! Receiver input traces, for reproducing exactly what a receiver did.
+//!
+//! A pcap capture holds the packets of a stream; a trace also holds when the
+//! receiver read each one and when its reads timed out, on the receiver's
+//! clock. Those are all the inputs its decisions depend on: reassembly,
+//! the jitter buffer, loss and burst accounting all run on them. Attached
+//! with `H264RtpReceiver::enable_trace`, a [`TraceWriter`] records them in a
+//! compact binary log. `H264RtpReceiver::from_trace` feeds a
+//! [`TraceSource`] through a fresh receiver on a `ManualClock` that steps to
+//! each recorded time, so a receiver with the same settings delivers the
+//! same frames and counts the same statistics as the one recorded.
+//!
+//! The log starts with the 8 bytes `RTPTRACE` and a version byte, followed
+//! by events: a kind byte, the time since the receiver's clock started in
+//! nanoseconds (u64), then for a datagram its length (u32) and bytes, and for the start
+//! event the wall-clock time in nanoseconds since the Unix epoch (u64). All
+//! integers are little-endian.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::clock::{Clock, ManualClock};
+use crate::receiver::RtpSource;
+use crate::RtpError;
+
+const MAGIC: &[u8; 8] = b"RTPTRACE";
+const VERSION: u8 = 1;
+
+const KIND_STARTED: u8 = 0;
+const KIND_DATAGRAM: u8 = 1;
+const KIND_TIMEOUT: u8 = 2;
+
+// Larger records mean a corrupt file rather than a datagram.
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+/// One recorded input of a receiver, as [`TraceSource`] yields it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TraceEvent {
+    /// Tracing started, with the receiver's wall clock at the time.
+    Started { elapsed: Duration, wall_clock: SystemTime },
+    /// A datagram was read, before SRTP, the packet transform or any check.
+    Datagram { elapsed: Duration, data: Vec<u8> },
+    /// A read returned without a datagram, e.g. at a jitter buffer
+    /// deadline.
+    Timeout { elapsed: Duration }
+}
+
+/// Writes a receiver's inputs into a trace file. Output is buffered; the
+/// receiver flushes it after every frame, and dropping the writer gets the
+/// rest to disk.
+pub struct TraceWriter {
+    file: BufWriter<File>,
+    // Reused for each event.
+    record: Vec<u8>
+}
+
+impl TraceWriter {
+    /// Creates (or truncates) the trace file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, RtpError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        Ok(Self {
+            file,
+            record: Vec::new()
+        })
+    }
+
+    pub fn flush(&mut self) -> Result<(), RtpError> {
+        Ok(self.file.flush()?)
+    }
+
+    fn write_event(&mut self, kind: u8, elapsed: Duration, fields: &[&[u8]]) -> io::Result<()> {
+        let record = &mut self.record;
+        record.clear();
+        record.push(kind);
+        record.extend_from_slice(&(elapsed.as_nanos() as u64).to_le_bytes());
+        for field in fields {
+            record.extend_from_slice(field);
+        }
+        self.file.write_all(record)
+    }
+}
+
+// A writer attached to a receiver. Like a capture, tracing is best effort:
+// a failed write ends the trace, never the stream, and the error is kept
+// for the owner to report.
+pub(crate) struct Trace {
+    writer: Option<TraceWriter>,
+    // When the receiver's clock started, see ReceptionStatistics; events
+    // count from it. Arrivals are converted to RTP units from the same
+    // point, so a replay starting there converts them alike.
+    started: Instant,
+    error: Option<RtpError>
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self {
+            writer: None,
+            started: Instant::now(),
+            error: None
+        }
+    }
+
+    pub fn set(&mut self, writer: Option<TraceWriter>, started: Instant, clock: &dyn Clock) {
+        self.writer = writer;
+        self.started = started;
+        self.error = None;
+        let wall_clock = clock.wall_clock().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.write(KIND_STARTED, clock.now(), &[&(wall_clock.as_nanos() as u64).to_le_bytes()]);
+    }
+
+    pub fn error(&self) -> Option<&RtpError> {
+        self.error.as_ref()
+    }
+
+    pub fn datagram(&mut self, arrival: Instant, datagram: &[u8]) {
+        self.write(KIND_DATAGRAM, arrival, &[&(datagram.len() as u32).to_le_bytes(), datagram]);
+    }
+
+    pub fn timeout(&mut self, now: Instant) {
+        self.write(KIND_TIMEOUT, now, &[]);
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(err) = writer.flush() {
+                self.stop(err);
+            }
+        }
+    }
+
+    fn write(&mut self, kind: u8, at: Instant, fields: &[&[u8]]) {
+        let elapsed = at.saturating_duration_since(self.started);
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(err) = writer.write_event(kind, elapsed, fields) {
+                self.stop(err.into());
+            }
+        }
+    }
+
+    fn stop(&mut self, err: RtpError) {
+        self.writer = None;
+        self.error = Some(err);
+    }
+}
+
+/// Reads a trace file back. As an [`RtpSource`] it hands out the recorded
+/// datagrams and read timeouts in order, first stepping its clock (see
+/// `clock`) to the time each one happened; `H264RtpReceiver::from_trace`
+/// wires the two up. As an iterator it yields the events themselves.
+pub struct TraceSource {
+    input: Box<dyn Read + Send>,
+    clock: ManualClock,
+    // The clock's time when the recorded receiver's clock started.
+    started: Instant,
+    done: bool
+}
+
+impl TraceSource {
+    /// Opens the trace at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RtpError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a trace from `input`, e.g. a fixture in memory.
+    pub fn from_reader(input: impl Read + Send + 'static) -> Result<Self, RtpError> {
+        let mut input: Box<dyn Read + Send> = Box::new(input);
+        let mut header = [0; 9];
+        input.read_exact(&mut header)?;
+        if &header[..8] != MAGIC || header[8] != VERSION {
+            return Err(invalid("not a version 1 receiver trace").into());
+        }
+        let clock = ManualClock::new();
+        Ok(Self {
+            input,
+            started: clock.now(),
+            clock,
+            done: false
+        })
+    }
+
+    /// The clock the source steps as it is read. A replaying receiver runs
+    /// on it.
+    pub fn clock(&self) -> ManualClock {
+        self.clock.clone()
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<TraceEvent>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut kind = [0; 1];
+        if self.input.read(&mut kind)? == 0 {
+            self.done = true;
+            return Ok(None);
+        }
+        let elapsed = Duration::from_nanos(read_u64(&mut self.input)?);
+        let event = match kind[0] {
+            KIND_STARTED => {
+                let wall_clock = UNIX_EPOCH + Duration::from_nanos(read_u64(&mut self.input)?);
+                TraceEvent::Started { elapsed, wall_clock }
+            }
+            KIND_DATAGRAM => {
+                let mut len = [0; 4];
+                self.input.read_exact(&mut len)?;
+                let len = u32::from_le_bytes(len) as usize;
+                if len > MAX_DATAGRAM_SIZE {
+                    return Err(invalid("trace datagram too large"));
+                }
+                let mut data = vec![0; len];
+                self.input.read_exact(&mut data)?;
+                TraceEvent::Datagram { elapsed, data }
+            }
+            KIND_TIMEOUT => TraceEvent::Timeout { elapsed },
+            _ => return Err(invalid("unknown trace event"))
+        };
+        Ok(Some(event))
+    }
+
+    // Moves the clock to `elapsed` after the start, never backwards.
+    fn step_to(&self, elapsed: Duration) {
+        let due = self.started + elapsed;
+        self.clock.advance(due.saturating_duration_since(self.clock.now()));
+    }
+}
+
+impl Iterator for TraceSource {
+    type Item = Result<TraceEvent, RtpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_event() {
+            Ok(event) => event.map(Ok),
+            Err(err) => {
+                self.done = true;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for TraceSource {}
+
+impl RtpSource for TraceSource {
+    // The recorded timeouts stand in for the waits, so `timeout` is not
+    // used: a replay runs as fast as the trace can be read.
+    fn recv_packet(&mut self, out: &mut [u8], _timeout: Option<Duration>) -> io::Result<usize> {
+        loop {
+            match self.next_event()? {
+                Some(TraceEvent::Started { elapsed, wall_clock }) => {
+                    self.step_to(elapsed);
+                    self.clock.set_wall_clock(wall_clock);
+                }
+                Some(TraceEvent::Datagram { elapsed, data }) => {
+                    self.step_to(elapsed);
+                    let len = data.len().min(out.len());
+                    out[..len].copy_from_slice(&data[..len]);
+                    return Ok(len);
+                }
+                Some(TraceEvent::Timeout { elapsed }) => {
+                    self.step_to(elapsed);
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                None => return Err(io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+    }
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_read_back_as_written_and_step_the_clock() {
+        let path = std::env::temp_dir().join(format!("rtp_transceive_trace_unit_{}.rtptrace", std::process::id()));
+        let clock = ManualClock::new();
+        let start = clock.now();
+        clock.advance(Duration::from_millis(5));
+        let wall_clock = clock.wall_clock();
+        let mut trace = Trace::new();
+        trace.set(Some(TraceWriter::create(&path).unwrap()), start, &clock);
+        trace.datagram(start + Duration::from_millis(7), &[0x80, 96, 0, 1]);
+        trace.timeout(start + Duration::from_millis(20));
+        trace.set(None, start, &clock);
+        assert!(trace.error().is_none());
+
+        let events: Vec<TraceEvent> = TraceSource::open(&path).unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            events,
+            [
+                TraceEvent::Started {
+                    elapsed: Duration::from_millis(5),
+                    wall_clock
+                },
+                TraceEvent::Datagram {
+                    elapsed: Duration::from_millis(7),
+                    data: vec![0x80, 96, 0, 1]
+                },
+                TraceEvent::Timeout {
+                    elapsed: Duration::from_millis(20)
+                }
+            ]
+        );
+
+        let mut source = TraceSource::open(&path).unwrap();
+        let replay = source.clock();
+        let origin = replay.now();
+        let mut out = [0; 16];
+        assert_eq!(source.recv_packet(&mut out, None).unwrap(), 4);
+        assert_eq!(replay.now() - origin, Duration::from_millis(7));
+        assert_eq!(replay.wall_clock(), wall_clock + Duration::from_millis(2));
+        assert_eq!(source.recv_packet(&mut out, None).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(replay.now() - origin, Duration::from_millis(20));
+        assert_eq!(source.recv_packet(&mut out, None).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn other_files_are_not_taken_for_traces() {
+        for input in [&b"RTPTRACE\x02"[..], &[0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0, 0][..], &b"RTP"[..]] {
+            assert!(TraceSource::from_reader(input).is_err());
+        }
+        let mut truncated = TraceSource::from_reader(&b"RTPTRACE\x01\x01\x00"[..]).unwrap();
+        assert!(matches!(truncated.next(), Some(Err(_))));
+        assert!(truncated.next().is_none());
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime};
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+use rtp_transceive::time::NtpTimestamp;
+
+const ID: u8 = 3;
+// abs-send-time wraps every 64 seconds.
+const WRAP: Duration = Duration::from_secs(64);
+
+// `time` as abs-send-time reads it: seconds of the NTP clock modulo 64.
+fn ntp_mod_64(time: SystemTime) -> Duration {
+    let ntp = NtpTimestamp::from_system_time(time).0;
+    let seconds = (ntp >> 32) % 64;
+    let nanos = ((ntp & 0xFFFF_FFFF) * 1_000_000_000) >> 32;
+    Duration::new(seconds, nanos as u32)
+}
+
+// Distance from `from` forward to `to` on the 64 s circle.
+fn forward(from: Duration, to: Duration) -> Duration {
+    let wrap = WRAP.as_nanos();
+    Duration::from_nanos(((to.as_nanos() + 2 * wrap - from.as_nanos()) % wrap) as u64)
+}
+
+#[test]
+fn abs_send_time_decodes_to_within_a_millisecond_of_the_send() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.set_abs_send_time(Some(ID)).unwrap();
+
+    for (frame, pts) in SyntheticH264::new(11).duration(Duration::from_millis(200)) {
+        let before = ntp_mod_64(SystemTime::now());
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let after = ntp_mod_64(SystemTime::now());
+
+        let (received, extensions) = receiver.recv_frame_with_extensions().unwrap();
+        assert_eq!(received, frame);
+        assert!(!extensions.is_empty());
+        for packet in &extensions {
+            let stamp = packet.abs_send_time(ID).expect("packet without abs-send-time");
+            // The stamp is rounded down to 1/262144 s and comes from the
+            // pusher's anchored clock, not this one: allow 1 ms either way.
+            let slack = Duration::from_millis(1);
+            let window = forward(before, after) + 2 * slack;
+            let offset = forward(before + WRAP - slack, stamp);
+            assert!(offset <= window, "{:?} not within {:?}..{:?}", stamp, before, after);
+        }
+    }
+}
+
+#[test]
+fn abs_send_time_rejects_bad_and_taken_ids() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(socket.local_addr().unwrap()).unwrap();
+    for id in [0, 15] {
+        assert!(matches!(pusher.set_abs_send_time(Some(id)), Err(RtpError::InvalidExtensionId(bad)) if bad == id));
+    }
+    pusher.set_frame_integrity(Some(4)).unwrap();
+    assert!(matches!(pusher.set_abs_send_time(Some(4)), Err(RtpError::InvalidExtensionId(4))));
+
+    // Setting the same id again just keeps it.
+    pusher.set_abs_send_time(Some(ID)).unwrap();
+    pusher.set_abs_send_time(Some(ID)).unwrap();
+    pusher.set_abs_send_time(None).unwrap();
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "net")]
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::RtpSource;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+const MIN: Duration = Duration::from_millis(10);
+const MAX: Duration = Duration::from_millis(150);
+// Steady, then every other frame held up 40 ms, then steady again.
+const BURSTY: std::ops::Range<u32> = 90..180;
+// 20 s at 30 fps.
+const FRAMES: u32 = 600;
+
+// Hands out packets at their due times on a manual clock, which it moves
+// on as the receiver waits, then reports the end of the stream.
+struct Scheduled {
+    clock: ManualClock,
+    start: std::time::Instant,
+    packets: VecDeque<(Duration, Vec<u8>)>
+}
+
+impl RtpSource for Scheduled {
+    fn recv_packet(&mut self, out: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let (due, _) = self.packets.front().ok_or(io::ErrorKind::UnexpectedEof)?;
+        let wait = (self.start + *due).saturating_duration_since(self.clock.now());
+        if let Some(timeout) = timeout.filter(|&timeout| timeout < wait) {
+            self.clock.advance(timeout);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.clock.advance(wait);
+        let (_, packet) = self.packets.pop_front().unwrap();
+        out[..packet.len()].copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+}
+
+// The packets of the stream in arrival order, each frame's sent
+// FRAME_INTERVAL after the one before and delayed as the phase says.
+fn packets() -> Vec<(Duration, Vec<u8>)> {
+    let stream = SyntheticH264::new(6).gop_length(30).slice_size(300..=600).duration(Duration::from_secs(20));
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_mtu(400).unwrap();
+    pusher.set_sink(Some(Box::new(sink)));
+    let mut packets = Vec::new();
+    for (index, (frame, pts)) in stream.enumerate() {
+        let index = index as u32;
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let delay = if BURSTY.contains(&index) && index % 2 == 1 { Duration::from_millis(40) } else { Duration::ZERO };
+        packets.extend(sent.try_iter().map(|packet| (FRAME_INTERVAL * index + delay, packet)));
+    }
+    packets.sort_by_key(|&(due, _)| due);
+    packets
+}
+
+#[test]
+fn the_wait_follows_the_measured_jitter_within_its_bounds() {
+    let clock = ManualClock::new();
+    let start = clock.now();
+    let source = Scheduled {
+        clock: clock.clone(),
+        start,
+        packets: packets().into()
+    };
+    let mut receiver = H264RtpReceiver::from_source(Box::new(source)).unwrap();
+    receiver.set_clock(Arc::new(clock.clone()));
+    receiver.set_jitter_buffer(Some(JitterDepth::Adaptive { min: MIN, max: MAX }));
+
+    // The wait after each frame, by when the frame was sent.
+    let mut waits = Vec::new();
+    loop {
+        match receiver.recv_annotated_frame() {
+            Ok(frame) => waits.push((frame.timestamp / 3000, receiver.stats().jitter_buffer_delay.unwrap())),
+            Err(RtpError::IdleTimeout) => break,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    assert_eq!(waits.len(), FRAMES as usize);
+    let during = |range: std::ops::Range<u32>| waits.iter().filter(move |(frame, _)| range.contains(frame)).map(|&(_, wait)| wait);
+
+    assert!(during(0..BURSTY.start).all(|wait| wait == MIN));
+    let peak = during(BURSTY).max().unwrap();
+    assert!(peak >= Duration::from_millis(40) && peak <= MAX, "{:?}", peak);
+    // Back on the minimum once the network has been steady for a while.
+    assert_eq!(waits.last().unwrap().1, MIN);
+
+    // It went up within the bursts only, then down step by step.
+    let adaptations = receiver.jitter_adaptations();
+    let calm = start + FRAME_INTERVAL * BURSTY.end + MAX;
+    assert!(adaptations.iter().filter(|adaptation| adaptation.to > adaptation.from).all(|adaptation| adaptation.at < calm));
+    let lowering: Vec<_> = adaptations.iter().filter(|adaptation| adaptation.to < adaptation.from).collect();
+    assert!(lowering.len() >= 2, "{:?}", adaptations);
+    assert!(lowering.windows(2).all(|pair| pair[1].at - pair[0].at >= Duration::from_secs(2)));
+    assert_eq!(lowering.last().unwrap().to, MIN);
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::{ConfigDelta, ConfigField};
+use rtp_transceive::testsupport::SyntheticH264;
+
+fn listener() -> (UdpSocket, String) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    (socket, address)
+}
+
+// Packets waiting on `socket`, in arrival order.
+fn drain(socket: &UdpSocket) -> Vec<Vec<u8>> {
+    socket.set_nonblocking(true).unwrap();
+    let mut packets = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(len) = socket.recv(&mut buf) {
+        packets.push(buf[..len].to_vec());
+    }
+    packets
+}
+
+fn sequence(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[2], packet[3]])
+}
+
+#[test]
+fn destination_moves_mid_stream_without_a_sequence_gap() {
+    let (first, first_address) = listener();
+    let (second, second_address) = listener();
+    let mut pusher = H264RtpPusher::new(&first_address).unwrap();
+    pusher.set_parameter_set_injection(false);
+    let mut frames = SyntheticH264::new(3);
+
+    for (frame, pts) in frames.by_ref().take(3) {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    let second_port = second.local_addr().unwrap().port();
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some(second_address);
+    delta.keepalive = Some(Some(Duration::ZERO));
+    delta.nri_rewrite = Some(false);
+    delta.pacing = Some(Some(PacingPolicy {
+        bits_per_second: 100_000_000,
+        max_burst_bytes: 64_000
+    }));
+    let applied = pusher.apply_config(delta).unwrap();
+    // The unchanged NRI setting is not reported.
+    assert_eq!(
+        applied.changed,
+        [ConfigField::Destination, ConfigField::Keepalive, ConfigField::Pacing]
+    );
+    for (frame, pts) in frames.take(3) {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    assert!(pusher.poll_keepalive().unwrap());
+
+    // Give the loopback a moment to deliver everything.
+    std::thread::sleep(Duration::from_millis(50));
+    let before = drain(&first);
+    let after = drain(&second);
+    assert!(!before.is_empty() && !after.is_empty());
+    // The keepalive went to the new destination too.
+    assert!(after.last().unwrap()[0] & 0x20 != 0);
+    let sequences: Vec<u16> = before.iter().chain(&after).map(|packet| sequence(packet)).collect();
+    for pair in sequences.windows(2) {
+        assert_eq!(pair[1], pair[0].wrapping_add(1));
+    }
+    assert!(pusher.sdp().contains(&format!("m=video {} ", second_port)));
+}
+
+#[test]
+fn sender_reports_on_the_default_port_follow_the_destination() {
+    let (_first, first_address) = listener();
+    let (_second, second_address) = listener();
+    let mut pusher = H264RtpPusher::new(&first_address).unwrap();
+    pusher.enable_sender_reports(Duration::from_secs(5), None).unwrap();
+
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some(second_address.clone());
+    pusher.apply_config(delta).unwrap();
+    // Still RTP port + 1, so the SDP names no separate RTCP port.
+    assert!(!pusher.sdp().contains("a=rtcp:"));
+
+    // An explicitly chosen RTCP destination stays where it is.
+    pusher.enable_sender_reports(Duration::from_secs(5), Some("127.0.0.1:7000")).unwrap();
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some(first_address);
+    pusher.apply_config(delta).unwrap();
+    assert!(pusher.sdp().contains("a=rtcp:7000"));
+}
+
+#[test]
+fn invalid_deltas_change_nothing() {
+    let (_socket, address) = listener();
+    let mut pusher = H264RtpPusher::new(&address).unwrap();
+    let sdp = pusher.sdp();
+
+    // An IPv4 socket cannot reach an IPv6 destination.
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some("[::1]:5004".to_string());
+    delta.keepalive = Some(Some(Duration::from_secs(1)));
+    assert!(pusher.apply_config(delta).is_err());
+
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some("127.0.0.1:5004".to_string());
+    delta.pacing = Some(Some(PacingPolicy {
+        bits_per_second: 0,
+        max_burst_bytes: 1_500
+    }));
+    assert!(matches!(pusher.apply_config(delta), Err(RtpError::InvalidPacing(_))));
+
+    let mut delta = ConfigDelta::default();
+    delta.destination = Some("127.0.0.1:5004".to_string());
+    delta.ssrc = Some(1);
+    delta.clock_rate = Some(48_000);
+    match pusher.apply_config(delta) {
+        Err(RtpError::NotLiveConfigurable(fields)) => assert_eq!(fields, [ConfigField::Ssrc, ConfigField::ClockRate]),
+        other => panic!("{:?}", other)
+    }
+
+    assert_eq!(pusher.sdp(), sdp);
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::RtpSink;
+
+// Cancels `token` from another thread after `delay`.
+fn cancel_after(token: &CancelToken, delay: Duration) -> thread::JoinHandle<()> {
+    let token = token.clone();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        token.cancel();
+    })
+}
+
+// Well under the waits being interrupted, well over thread wake-up.
+const PROMPT: Duration = Duration::from_millis(500);
+
+fn large_idr() -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend((0..20_000).map(|i| (i % 255) as u8 + 1));
+    frame
+}
+
+const NON_IDR: [u8; 7] = [0, 0, 0, 1, 0x41, 0x9A, 0x02];
+
+// 1 kB/s: the large IDR would take about 20 s to pace out.
+fn slow_pacing(pusher: &mut H264RtpPusher) {
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 8_000,
+            max_burst_bytes: 1_500
+        }))
+        .unwrap();
+}
+
+#[test]
+fn paced_send_frame_is_cancelled_from_another_thread() {
+    let (queue, packets) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(queue)));
+    slow_pacing(&mut pusher);
+    let token = CancelToken::new();
+    pusher.set_cancel_token(Some(token.clone()));
+
+    let canceller = cancel_after(&token, Duration::from_millis(50));
+    let start = Instant::now();
+    assert!(matches!(pusher.send_frame(&large_idr()), Err(RtpError::Cancelled)));
+    assert!(start.elapsed() < PROMPT, "{:?}", start.elapsed());
+    canceller.join().unwrap();
+
+    // The frame was cut short, so only an IDR may follow.
+    let cut = packets.try_iter().count();
+    assert!(pusher.needs_keyframe());
+    pusher.set_cancel_token(None);
+    pusher.set_pacing(None).unwrap();
+    pusher.send_frame(&NON_IDR).unwrap();
+    assert_eq!(packets.try_iter().count(), 0);
+    pusher.send_frame(&large_idr()).unwrap();
+    assert!(!pusher.needs_keyframe());
+    let whole = packets.try_iter().count();
+    assert!(cut > 0 && cut < whole, "{} of {}", cut, whole);
+}
+
+#[test]
+fn streaming_is_cancelled_inside_a_paced_frame() {
+    let (queue, _packets) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(queue)));
+    slow_pacing(&mut pusher);
+    let token = CancelToken::new();
+
+    let canceller = cancel_after(&token, Duration::from_millis(50));
+    let start = Instant::now();
+    let frames = (0..3).map(|i| (large_idr(), i * 3_000));
+    let result = pusher.stream_access_units(frames, false, Some(&token));
+    assert!(matches!(result, Err(RtpError::Cancelled)));
+    assert!(start.elapsed() < PROMPT, "{:?}", start.elapsed());
+    canceller.join().unwrap();
+}
+
+// Claims the kernel's send buffer is always full.
+struct FullBuffer;
+
+impl RtpSink for FullBuffer {
+    fn send_packet(&mut self, _packet: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+#[test]
+fn send_retry_delay_is_cancelled_from_another_thread() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(FullBuffer)));
+    pusher.set_send_retry_delay(Some(Duration::from_secs(10)));
+    let token = CancelToken::new();
+    pusher.set_cancel_token(Some(token.clone()));
+
+    let canceller = cancel_after(&token, Duration::from_millis(50));
+    let start = Instant::now();
+    assert!(matches!(pusher.send_frame(&large_idr()), Err(RtpError::Cancelled)));
+    assert!(start.elapsed() < PROMPT, "{:?}", start.elapsed());
+    canceller.join().unwrap();
+}
+
+#[test]
+fn receive_is_cancelled_from_another_thread() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    let destination = receiver.local_addr().unwrap().to_string();
+    let token = CancelToken::new();
+
+    let canceller = cancel_after(&token, Duration::from_millis(50));
+    let start = Instant::now();
+    assert!(matches!(receiver.recv_frame_cancellable(&token), Err(RtpError::Cancelled)));
+    assert!(start.elapsed() < PROMPT, "{:?}", start.elapsed());
+    canceller.join().unwrap();
+
+    // The receiver carries on with a fresh token, and the idle timeout
+    // still ends a wait with nothing to receive.
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    let frame = large_idr();
+    pusher.send_frame(&frame).unwrap();
+    let token = CancelToken::new();
+    assert_eq!(receiver.recv_frame_cancellable(&token).unwrap(), frame);
+
+    receiver.set_idle_timeout(Some(Duration::from_millis(250))).unwrap();
+    let start = Instant::now();
+    assert!(matches!(receiver.recv_frame_cancellable(&token), Err(RtpError::IdleTimeout)));
+    let waited = start.elapsed();
+    assert!(waited >= Duration::from_millis(250) && waited < Duration::from_secs(2), "{:?}", waited);
+}
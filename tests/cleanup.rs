@@ -0,0 +1,129 @@
+#![cfg(feature = "net")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::RtcpRelayMode;
+use rtp_transceive::rtsp::RtspServer;
+use rtp_transceive::testsupport::SyntheticH264;
+use rtp_transceive::trace::{TraceEvent, TraceSource};
+
+// Runs `body`, which panics once it has set things up, and returns what it
+// recorded before the panic.
+fn panicking<T>(body: impl FnOnce(&mut Vec<T>)) -> Vec<T> {
+    let mut recorded = Vec::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        body(&mut recorded);
+        panic!("set up, now unwinding");
+    }));
+    assert!(result.is_err());
+    recorded
+}
+
+fn rebind_udp(addresses: &[SocketAddr]) {
+    for address in addresses {
+        UdpSocket::bind(address).unwrap_or_else(|err| panic!("{}: {}", address, err));
+    }
+}
+
+#[test]
+fn a_panicking_pusher_says_goodbye_and_frees_its_ports() {
+    let rtp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let rtcp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    rtcp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let destination = rtp.local_addr().unwrap().to_string();
+    let rtcp_destination = rtcp.local_addr().unwrap().to_string();
+
+    let addresses = panicking(|addresses| {
+        let mut pusher = H264RtpPusher::new(&destination).unwrap();
+        pusher.enable_sender_reports(Duration::from_secs(5), Some(&rtcp_destination)).unwrap();
+        let (frame, _) = SyntheticH264::new(1).next().unwrap();
+        pusher.send_frame(&frame).unwrap();
+        addresses.push(pusher.local_addr().unwrap());
+        addresses.push(pusher.rtcp_local_addr().unwrap().unwrap());
+    });
+
+    // The unwinding drop closed the pusher: some compound packet after the
+    // Sender Report carries a BYE (type 203).
+    let mut buffer = [0u8; 1500];
+    let mut bye = false;
+    while !bye {
+        let len = rtcp.recv(&mut buffer).expect("no BYE from the dropped pusher");
+        let mut offset = 0;
+        while offset + 4 <= len {
+            bye |= buffer[offset + 1] == 203;
+            offset += (u16::from_be_bytes([buffer[offset + 2], buffer[offset + 3]]) as usize + 1) * 4;
+        }
+    }
+    rebind_udp(&addresses);
+}
+
+#[test]
+fn a_panicking_receiver_frees_its_ports_and_flushes_its_trace() {
+    let path = std::env::temp_dir().join(format!("rtp_transceive_cleanup_{}.rtptrace", std::process::id()));
+    let downstream = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+    let addresses = panicking(|addresses| {
+        let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+        receiver.enable_receiver_reports(Duration::from_secs(5), None).unwrap();
+        let (relayed, _) = mpsc::channel::<Vec<u8>>();
+        receiver.set_relay(Some(Box::new(relayed)));
+        receiver
+            .enable_relay_rtcp("127.0.0.1:0", &downstream, RtcpRelayMode::PassThrough, RtcpRelayMode::PassThrough)
+            .unwrap();
+        receiver.enable_trace(&path).unwrap();
+        addresses.push(receiver.local_addr().unwrap());
+        addresses.push(receiver.rtcp_local_addr().unwrap());
+        addresses.push(receiver.relay_rtcp_local_addr().unwrap());
+    });
+
+    rebind_udp(&addresses);
+    // Nothing was flushed before the panic; the drop wrote the trace out.
+    let events: Vec<TraceEvent> = TraceSource::open(&path).unwrap().map(Result::unwrap).collect();
+    assert!(matches!(events[..], [TraceEvent::Started { .. }]));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn a_receiver_on_a_panicking_thread_frees_its_ports() {
+    let (addresses_tx, addresses) = mpsc::channel();
+    let thread = thread::spawn(move || {
+        let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+        receiver.enable_receiver_reports(Duration::from_secs(5), None).unwrap();
+        addresses_tx
+            .send([receiver.local_addr().unwrap(), receiver.rtcp_local_addr().unwrap()])
+            .unwrap();
+        panic!("set up, now unwinding");
+    });
+    assert!(thread.join().is_err());
+    rebind_udp(&addresses.recv().unwrap());
+}
+
+#[test]
+fn a_panic_with_a_serving_rtsp_server_joins_its_threads_and_frees_the_port() {
+    let mut client = None;
+    let addresses = panicking(|addresses| {
+        let server = RtspServer::bind("127.0.0.1:0", "stream").unwrap();
+        let address = server.local_addr();
+        // A connection thread is blocked reading the client's next request.
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        stream.write_all(b"OPTIONS * RTSP/1.0\r\nCSeq: 1\r\n\r\n").unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!(status, "RTSP/1.0 200 OK\r\n");
+        client = Some(reader);
+        addresses.push(address);
+    });
+
+    // The server's drop shut the connection down before the unwind went on.
+    let mut rest = Vec::new();
+    client.unwrap().read_to_end(&mut rest).unwrap();
+    TcpListener::bind(addresses[0]).unwrap();
+}
@@ -0,0 +1,355 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::RtpSink;
+
+type Sent = Vec<(Instant, Vec<u8>)>;
+
+// Records each packet with the manual clock's time when it reached the sink.
+#[derive(Clone)]
+struct ClockedSink {
+    clock: ManualClock,
+    sent: Arc<Mutex<Sent>>
+}
+
+impl ClockedSink {
+    fn new(clock: &ManualClock) -> Self {
+        Self { clock: clock.clone(), sent: Arc::default() }
+    }
+
+    fn take(&self) -> Sent {
+        std::mem::take(&mut self.sent.lock().unwrap())
+    }
+}
+
+impl RtpSink for ClockedSink {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.sent.lock().unwrap().push((self.clock.now(), packet.to_vec()));
+        Ok(packet.len())
+    }
+}
+
+fn pusher_on(clock: &ManualClock) -> H264RtpPusher {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_clock(Arc::new(clock.clone()));
+    pusher
+}
+
+fn timestamp(packet: &[u8]) -> u32 {
+    u32::from_be_bytes(packet[4..8].try_into().unwrap())
+}
+
+const FRAME: [u8; 8] = [0, 0, 0, 1, 0x65, 0x88, 0x84, 0x21];
+
+#[test]
+fn keepalives_only_fill_idle_gaps() {
+    let clock = ManualClock::new();
+    let (queue, packets) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(queue)));
+    pusher.set_keepalive(Some(Duration::from_secs(1)));
+
+    pusher.send_frame(&FRAME).unwrap();
+    let media = packets.try_recv().unwrap();
+    assert!(!pusher.poll_keepalive().unwrap());
+    clock.advance(Duration::from_millis(999));
+    assert!(!pusher.poll_keepalive().unwrap());
+    clock.advance(Duration::from_millis(1));
+    assert!(pusher.poll_keepalive().unwrap());
+    // The keepalive restarts the idle interval.
+    assert!(!pusher.poll_keepalive().unwrap());
+    clock.advance(Duration::from_secs(1));
+    assert!(pusher.poll_keepalive().unwrap());
+
+    // Frames keep the link busy, so no keepalive is due while they flow.
+    for _ in 0..5 {
+        clock.advance(Duration::from_millis(500));
+        pusher.send_frame(&FRAME).unwrap();
+        assert!(!pusher.poll_keepalive().unwrap());
+    }
+    assert_eq!(pusher.keepalives_sent(), 2);
+
+    pusher.end_of_stream();
+    clock.advance(Duration::from_secs(10));
+    assert!(!pusher.poll_keepalive().unwrap());
+
+    let sent: Vec<_> = packets.try_iter().collect();
+    let keepalives: Vec<_> = sent.iter().filter(|packet| packet[0] & 0x20 != 0).collect();
+    assert_eq!(keepalives.len(), 2);
+    assert_eq!(sent.len(), 2 + 5);
+    for keepalive in keepalives {
+        // Padding only, reusing the timestamp of the frame before the gap.
+        assert_eq!(keepalive.len(), 12 + 4);
+        assert_eq!(timestamp(keepalive), timestamp(&media));
+    }
+}
+
+#[test]
+fn realtime_streaming_follows_a_variable_frame_rate_schedule() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+
+    // 30 fps, a dropped frame, a 60 fps burst, a 20 fps tail.
+    let pts = [90_000u32, 93_000, 99_000, 100_500, 102_000, 106_500, 111_000];
+    let frames = pts.iter().map(|&pts| (FRAME.to_vec(), pts));
+    let start = clock.now();
+    let summary = pusher.stream_access_units(frames, true, None).unwrap();
+
+    let sent = sink.take();
+    assert_eq!(summary.frames, pts.len() as u64);
+    assert_eq!(sent.len(), pts.len());
+    for ((at, packet), &pts) in sent.iter().zip(&pts) {
+        let offset = MediaTimestamp::new((pts - 90_000) as u64, 90_000).to_duration();
+        assert_eq!(*at - start, offset);
+        assert_eq!(timestamp(packet), pts);
+    }
+    assert_eq!(summary.elapsed, MediaTimestamp::new(21_000, 90_000).to_duration());
+}
+
+#[test]
+fn rtp_timestamps_stay_monotonic_across_wall_clock_steps() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    let anchor = pusher.clock_anchor();
+
+    for step in 0..20 {
+        match step {
+            // NTP steps the system clock back by an hour, then forward by a day.
+            7 => clock.set_wall_clock(clock.wall_clock() - Duration::from_secs(3_600)),
+            14 => clock.set_wall_clock(clock.wall_clock() + Duration::from_secs(86_400)),
+            _ => {}
+        }
+        pusher.send_frame(&FRAME).unwrap();
+        clock.advance(Duration::from_millis(40));
+    }
+
+    let timestamps: Vec<u32> = sink.take().iter().map(|(_, packet)| timestamp(packet)).collect();
+    for pair in timestamps.windows(2) {
+        // 40 ms at 90 kHz, whatever the wall clock did.
+        assert_eq!(pair[1].wrapping_sub(pair[0]), 3_600);
+    }
+    assert_eq!(timestamps[0], anchor.rtp_timestamp);
+    assert_eq!(pusher.clock_anchor(), anchor);
+}
+
+#[test]
+fn pacing_spaces_packets_on_the_pusher_clock() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher.set_mtu(1_000).unwrap();
+    // 100 kB/s, two packets back to back.
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend((0..20_000).map(|i| (i % 255) as u8 + 1));
+    let start = clock.now();
+    pusher.send_frame_with_timestamp(&frame, 0).unwrap();
+
+    let sent = sink.take();
+    let mut bytes = 0usize;
+    for (at, packet) in &sent {
+        // Exactly when the bucket has refilled enough for this packet, give
+        // or take the pacer's floating point rounding.
+        bytes += packet.len();
+        let due = Duration::from_secs_f64(bytes.saturating_sub(2_000) as f64 / 100_000.0);
+        let elapsed = *at - start;
+        assert!(elapsed.abs_diff(due) < Duration::from_micros(1), "{:?} vs {:?}", elapsed, due);
+    }
+    assert_eq!(pusher.pacing_wait(), sent.last().unwrap().0 - start);
+}
+
+// An access unit of one NAL of `size` bytes.
+fn access_unit(nal_header: u8, size: usize) -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 1, nal_header];
+    frame.extend((1..size).map(|i| (i % 255) as u8 + 1));
+    frame
+}
+
+// 100 kB/s, 2 kB back to back.
+fn paced_with_bound(pusher: &mut H264RtpPusher, bound: Duration) {
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+    pusher.set_latency_bound(Some(bound));
+}
+
+const BOUND: Duration = Duration::from_millis(50);
+
+#[test]
+fn latency_bound_drops_a_frame_the_pacer_could_not_drain_in_time() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    paced_with_bound(&mut pusher, BOUND);
+
+    // A 20 kB IDR needs about 180 ms at this rate: none of it goes out,
+    // and the pusher waits for the next IDR.
+    let start = clock.now();
+    pusher.send_frame(&access_unit(0x65, 20_000)).unwrap();
+    assert!(sink.take().is_empty());
+    assert_eq!(clock.now(), start);
+    assert_eq!(pusher.late_frames_dropped(), 1);
+    assert!(pusher.needs_keyframe());
+    pusher.send_frame(&access_unit(0x41, 500)).unwrap();
+    assert!(sink.take().is_empty());
+    assert_eq!(pusher.late_frames_dropped(), 1);
+
+    // One that drains within the bound goes out, burst first, then paced.
+    let submitted = clock.now();
+    pusher.send_frame(&access_unit(0x65, 6_000)).unwrap();
+    let sent = sink.take();
+    assert!(sent.len() > 1);
+    assert!(sent[0].0 == submitted && sent.last().unwrap().0 > submitted);
+    assert!(sent.iter().all(|(at, _)| *at - submitted <= BOUND));
+    assert!(!pusher.needs_keyframe());
+
+    // The next frame finds the bucket empty and would take some 55 ms to
+    // drain; 20 ms later the bucket holds 2 kB again and it fits.
+    pusher.send_frame(&access_unit(0x41, 5_500)).unwrap();
+    assert!(sink.take().is_empty());
+    assert_eq!(pusher.late_frames_dropped(), 2);
+    // Dropping a non-IDR leaves the stream decodable.
+    assert!(!pusher.needs_keyframe());
+    clock.advance(Duration::from_millis(20));
+    let submitted = clock.now();
+    pusher.send_frame(&access_unit(0x41, 5_500)).unwrap();
+    let sent = sink.take();
+    assert!(!sent.is_empty());
+    assert!(sent.iter().all(|(at, _)| *at - submitted <= BOUND));
+}
+
+#[test]
+fn realtime_streaming_never_sends_a_packet_later_than_the_bound() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    paced_with_bound(&mut pusher, BOUND);
+
+    // 30 fps: (NAL header, size) per frame.
+    let frames = [
+        (0x65, 4_000),
+        (0x41, 3_000),
+        // About 80 ms of pacing: dropped, the stream stays decodable.
+        (0x41, 8_000),
+        (0x41, 3_000),
+        // An IDR burst the pacer cannot drain: dropped with the frames
+        // up to the next IDR.
+        (0x65, 20_000),
+        (0x41, 1_000),
+        (0x41, 1_000),
+        (0x65, 3_000),
+        (0x41, 1_000),
+    ];
+    let start = clock.now();
+    let units = frames.iter().enumerate().map(|(i, &(header, size))| (access_unit(header, size), i as u32 * 3_000));
+    let summary = pusher.stream_access_units(units, true, None).unwrap();
+
+    let sent = sink.take();
+    let mut frames_sent: Vec<u32> = sent.iter().map(|(_, packet)| timestamp(packet) / 3_000).collect();
+    frames_sent.dedup();
+    assert_eq!(frames_sent, [0, 1, 3, 7, 8]);
+    assert_eq!(summary.frames, 5);
+    assert_eq!(pusher.late_frames_dropped(), 2);
+    for (at, packet) in &sent {
+        let due = start + MediaTimestamp::new(timestamp(packet) as u64, 90_000).to_duration();
+        assert!(*at >= due && *at - due <= BOUND, "{:?}", *at - due);
+    }
+}
+
+#[test]
+fn control_packets_skip_the_media_wait_up_to_their_cap() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher.set_mtu(1_000).unwrap();
+    // 100 kB/s, 2 kB back to back; 1 kB/s of control traffic may skip it.
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+    pusher.set_control_priority(Some(8_000));
+    pusher.set_keepalive(Some(Duration::ZERO));
+
+    pusher.send_frame_with_timestamp(&access_unit(0x65, 20_000), 0).unwrap();
+    let media_done = sink.take().last().unwrap().0;
+    assert_eq!(clock.now(), media_done);
+
+    // The bucket is empty, but keepalives leave at once until they have
+    // used up the control burst: 2 kB of 16-byte packets.
+    for _ in 0..125 {
+        assert!(pusher.poll_keepalive().unwrap());
+    }
+    assert!(sink.take().iter().all(|&(at, _)| at == media_done));
+    // The next one is paced like media behind the debt the others left.
+    assert!(pusher.poll_keepalive().unwrap());
+    let (at, _) = sink.take()[0];
+    let debt = Duration::from_secs_f64((2_000.0 + 16.0) / 100_000.0);
+    assert!((at - media_done).abs_diff(debt) < Duration::from_micros(1), "{:?}", at - media_done);
+}
+
+#[test]
+fn without_control_priority_keepalives_queue_behind_media() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher.set_mtu(1_000).unwrap();
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+    pusher.set_keepalive(Some(Duration::ZERO));
+
+    pusher.send_frame_with_timestamp(&access_unit(0x65, 20_000), 0).unwrap();
+    let media_done = sink.take().last().unwrap().0;
+    assert!(pusher.poll_keepalive().unwrap());
+    assert!(sink.take()[0].0 > media_done);
+}
+
+#[test]
+fn sender_reports_with_control_priority_do_not_wait_for_media() {
+    let clock = ManualClock::new();
+    let sink = ClockedSink::new(&clock);
+    let mut pusher = pusher_on(&clock);
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+    pusher.set_control_priority(Some(8_000));
+    pusher.enable_sender_reports(Duration::from_secs(5), None).unwrap();
+
+    // The first report goes out right after the frame's last packet.
+    pusher.send_frame_with_timestamp(&access_unit(0x65, 20_000), 0).unwrap();
+    let media_done = sink.take().last().unwrap().0;
+    assert_eq!(pusher.sender_reports_sent(), 1);
+    assert_eq!(clock.now(), media_done);
+}
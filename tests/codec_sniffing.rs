@@ -0,0 +1,72 @@
+#![cfg(feature = "net")]
+
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+fn sniffing_receiver() -> H264RtpReceiver {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200))).unwrap();
+    receiver.set_codec_sniffing(true);
+    receiver
+}
+
+// One H.265 access unit: VPS, SPS, PPS and an IDR_W_RADL slice large
+// enough to be fragmented, or a TRAIL_R slice.
+fn h265_frame(idr: bool) -> Vec<u8> {
+    let mut frame = Vec::new();
+    let nals: Vec<Vec<u8>> = if idr {
+        vec![vec![0x40, 0x01, 0x0C, 0x01], vec![0x42, 0x01, 0x01, 0x01], vec![0x44, 0x01, 0xC1, 0x72], [&[0x26, 0x01][..], &[0xAF; 3_000]].concat()]
+    } else {
+        vec![[&[0x02, 0x01][..], &[0xD0; 600]].concat()]
+    };
+    for nal in nals {
+        frame.extend_from_slice(&[0, 0, 0, 1]);
+        frame.extend(nal);
+    }
+    frame
+}
+
+// Reads until the stream ends, with the codec mismatches seen.
+fn read_all(receiver: &mut H264RtpReceiver) -> Vec<(Codec, Codec)> {
+    let mut mismatches = Vec::new();
+    loop {
+        match receiver.recv_frame() {
+            Ok(_) => {}
+            Err(RtpError::CodecMismatch(configured, detected)) => mismatches.push((configured, detected)),
+            Err(RtpError::IdleTimeout) => return mismatches,
+            Err(err) => panic!("{}", err)
+        }
+    }
+}
+
+#[test]
+fn h265_sent_to_an_h264_receiver_is_reported() {
+    let mut receiver = sniffing_receiver();
+    let mut pusher = H265RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    for index in 0..10 {
+        pusher.send_frame(&h265_frame(index % 5 == 0)).unwrap();
+    }
+    assert_eq!(read_all(&mut receiver), [(Codec::H264, Codec::H265)]);
+}
+
+#[test]
+fn h264_passes_the_check_and_sniffing_can_be_turned_off() {
+    let mut receiver = sniffing_receiver();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    for (frame, pts) in SyntheticH264::new(4).gop_length(5).slice_size(300..=2_000).duration(Duration::from_millis(400)) {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    assert!(read_all(&mut receiver).is_empty());
+    assert!(receiver.frame_stats().frames > 0);
+
+    // Configured explicitly, the receiver takes the H.265 stream as it is.
+    let mut receiver = sniffing_receiver();
+    receiver.set_codec_sniffing(false);
+    let mut pusher = H265RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    for index in 0..10 {
+        pusher.send_frame(&h265_frame(index % 5 == 0)).unwrap();
+    }
+    assert!(read_all(&mut receiver).is_empty());
+}
@@ -0,0 +1,34 @@
+// Fixtures shared by the loopback tests. Each test binary uses some of
+// them.
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+// A receiver on a loopback port that gives up after 500 ms without a
+// packet, and a pusher sending to it.
+pub fn pair() -> (H264RtpPusher, H264RtpReceiver) {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    let pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    (pusher, receiver)
+}
+
+// 12 frames of two slices, an IDR every 5: quick to send and check frame
+// by frame.
+pub fn short_stream() -> SyntheticH264 {
+    SyntheticH264::new(5).gop_length(5).slices_per_frame(2).duration(Duration::from_millis(400))
+}
+
+// A second of two-slice frames whose IDR slices need FU-A below a 6000-byte
+// MTU and whose P slices fit a packet or take a few.
+pub fn fragmenting_stream() -> SyntheticH264 {
+    SyntheticH264::new(9)
+        .gop_length(15)
+        .slices_per_frame(2)
+        .slice_size(200..=4_000)
+        .idr_slice_size(6_000..=12_000)
+        .duration(Duration::from_secs(1))
+}
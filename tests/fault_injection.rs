@@ -0,0 +1,105 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::{FaultControl, FaultInjector, FaultPolicy, UdpSink};
+
+const FRAME: [u8; 8] = [0, 0, 0, 1, 0x65, 1, 2, 3];
+
+// A pusher sending through an injector to a local socket, on a manual
+// clock shared by both.
+fn harness() -> (H264RtpPusher, FaultControl, UdpSocket, ManualClock) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let destination = socket.local_addr().unwrap().to_string();
+    let clock = ManualClock::new();
+    let mut injector = FaultInjector::new(UdpSink::new(&destination).unwrap());
+    injector.set_clock(Arc::new(clock.clone()));
+    injector.set_seed(5);
+    let control = injector.control();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    pusher.set_clock(Arc::new(clock.clone()));
+    pusher.set_sink(Some(Box::new(injector)));
+    (pusher, control, socket, clock)
+}
+
+fn received(socket: &UdpSocket) -> usize {
+    let mut buf = [0u8; 1500];
+    let mut count = 0;
+    while socket.recv(&mut buf).is_ok() {
+        count += 1;
+    }
+    count
+}
+
+#[test]
+fn drop_loses_about_the_share_asked_for() {
+    let (mut pusher, control, socket, _) = harness();
+    control.set_policy(FaultPolicy::Drop(30));
+    for _ in 0..200 {
+        pusher.send_frame(&FRAME).unwrap();
+    }
+    let stats = control.stats();
+    assert_eq!(stats.packets_dropped + stats.packets_passed, 200);
+    assert!((40..=80).contains(&stats.packets_dropped), "{:?}", stats);
+    assert_eq!(received(&socket) as u64, stats.packets_passed);
+}
+
+#[test]
+fn delay_holds_each_send_back() {
+    let (mut pusher, control, socket, clock) = harness();
+    control.set_policy(FaultPolicy::Delay {
+        min: Duration::from_millis(10),
+        max: Duration::from_millis(30)
+    });
+    let start = clock.now();
+    for _ in 0..20 {
+        pusher.send_frame(&FRAME).unwrap();
+    }
+    let stats = control.stats();
+    assert_eq!((stats.packets_delayed, stats.packets_passed), (20, 20));
+    assert!(stats.total_delay >= Duration::from_millis(200) && stats.total_delay <= Duration::from_millis(600));
+    // The pusher waited them out.
+    assert_eq!(clock.now() - start, stats.total_delay);
+    assert_eq!(received(&socket), 20);
+}
+
+#[test]
+fn blackhole_drops_everything_for_its_duration() {
+    let (mut pusher, control, socket, clock) = harness();
+    control.set_policy(FaultPolicy::Blackhole(Duration::from_secs(5)));
+    for _ in 0..4 {
+        pusher.send_frame(&FRAME).unwrap();
+        clock.advance(Duration::from_secs(1));
+    }
+    assert_eq!(received(&socket), 0);
+    clock.advance(Duration::from_secs(1));
+    pusher.send_frame(&FRAME).unwrap();
+    assert_eq!(received(&socket), 1);
+    let stats = control.stats();
+    assert_eq!((stats.packets_blackholed, stats.packets_passed), (4, 1));
+}
+
+#[test]
+fn errors_fail_the_send_until_the_policy_changes_from_another_thread() {
+    let (mut pusher, control, socket, _) = harness();
+    control.set_policy(FaultPolicy::Error(io::ErrorKind::ConnectionRefused));
+    match pusher.send_frame(&FRAME) {
+        Err(RtpError::Send(err)) => assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused),
+        other => panic!("{:?}", other)
+    }
+    assert!(pusher.stats().last_send_error.is_some());
+
+    let remote = control.clone();
+    thread::spawn(move || remote.set_policy(FaultPolicy::PassThrough)).join().unwrap();
+    pusher.send_frame(&FRAME).unwrap();
+    assert_eq!(received(&socket), 1);
+    let stats = control.stats();
+    assert_eq!((stats.errors_injected, stats.packets_passed), (1, 1));
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "net")]
+
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+// Needs ffprobe on the PATH: cargo test --test ffprobe -- --ignored
+#[test]
+#[ignore]
+fn ffprobe_recognizes_a_recorded_stream() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    let destination = receiver.local_addr().unwrap();
+    let pusher = thread::spawn(move || {
+        let mut pusher = H264RtpPusher::with_socket_addr(destination).unwrap();
+        // Give save_to_file time to start reading.
+        thread::sleep(Duration::from_millis(100));
+        for (frame, pts) in SyntheticH264::new(3).gop_length(10).duration(Duration::from_secs(1)) {
+            pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        }
+    });
+    let path = std::env::temp_dir().join(format!("rtp_transceive_ffprobe_{}.h264", std::process::id()));
+    let frames = receiver.save_to_file(&path, Duration::from_millis(1_500), None).unwrap();
+    pusher.join().unwrap();
+    assert!(frames > 0);
+
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-f", "h264", "-show_entries", "stream=codec_name,width,height", "-of", "csv=p=0"])
+        .arg(&path)
+        .output()
+        .expect("ffprobe on the PATH");
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "h264,320,240");
+}
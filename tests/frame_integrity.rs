@@ -0,0 +1,101 @@
+#![cfg(feature = "net")]
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::IntegrityResult;
+
+mod common;
+
+const ID: u8 = 5;
+
+// Sends the synthetic stream and returns each frame's check result, after
+// asserting the frames that passed came through unchanged.
+fn send_and_check(pusher: &mut H264RtpPusher, receiver: &mut H264RtpReceiver) -> Vec<IntegrityResult> {
+    let mut results = Vec::new();
+    for (frame, pts) in common::short_stream() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let received = receiver.recv_annotated_frame().unwrap();
+        assert_eq!(received.timestamp, pts);
+        if received.integrity != IntegrityResult::Mismatch {
+            assert_eq!(received.data, frame);
+        }
+        results.push(received.integrity);
+    }
+    results
+}
+
+#[test]
+fn intact_frames_match_their_crc() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(ID)).unwrap();
+    receiver.set_frame_integrity(Some(ID)).unwrap();
+
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert!(results.iter().all(|&result| result == IntegrityResult::Ok));
+    assert_eq!(receiver.stats().crc_mismatches, 0);
+}
+
+#[test]
+fn crc_covers_rewritten_and_aggregated_nals() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(ID)).unwrap();
+    pusher.set_stap_a_aggregation(true);
+    pusher.set_mtu(600).unwrap();
+    receiver.set_frame_integrity(Some(ID)).unwrap();
+
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert!(results.iter().all(|&result| result == IntegrityResult::Ok));
+}
+
+#[test]
+fn corrupted_payload_is_flagged_and_counted() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(ID)).unwrap();
+    receiver.set_frame_integrity(Some(ID)).unwrap();
+    // Impairment on the way in: flip the last payload byte of packet 10.
+    let mut packets = 0;
+    receiver.set_packet_transform(Some(Box::new(move |packet: &mut Vec<u8>| {
+        packets += 1;
+        if packets == 10 {
+            *packet.last_mut().unwrap() ^= 0x01;
+        }
+        true
+    })));
+
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert_eq!(results.iter().filter(|&&result| result == IntegrityResult::Mismatch).count(), 1);
+    assert_eq!(receiver.stats().crc_mismatches, 1);
+}
+
+#[test]
+fn lost_packet_is_flagged() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(ID)).unwrap();
+    pusher.set_mtu(500).unwrap();
+    receiver.set_frame_integrity(Some(ID)).unwrap();
+    // The first fragment of the first IDR never arrives.
+    let mut packets = 0;
+    receiver.set_packet_transform(Some(Box::new(move |_: &mut Vec<u8>| {
+        packets += 1;
+        packets != 3
+    })));
+
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert_eq!(results[0], IntegrityResult::Mismatch);
+    assert!(results[1..].iter().all(|&result| result == IntegrityResult::Ok));
+}
+
+#[test]
+fn frames_without_a_crc_are_absent() {
+    let (mut pusher, mut receiver) = common::pair();
+    receiver.set_frame_integrity(Some(ID)).unwrap();
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert!(results.iter().all(|&result| result == IntegrityResult::Absent));
+
+    // The pusher sends a CRC, but the receiver does not check it.
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(ID)).unwrap();
+    let results = send_and_check(&mut pusher, &mut receiver);
+    assert!(results.iter().all(|&result| result == IntegrityResult::Absent));
+
+    assert!(matches!(receiver.set_frame_integrity(Some(15)), Err(RtpError::InvalidExtensionId(15))));
+}
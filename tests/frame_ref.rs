@@ -0,0 +1,156 @@
+#![cfg(feature = "net")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use rtp_transceive::receiver::IntegrityResult;
+use rtp_transceive::testsupport::SyntheticH264;
+
+mod common;
+
+// Counts allocations made by the current thread while `COUNTING` is set.
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn counted<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    let before = ALLOCATIONS.with(Cell::get);
+    COUNTING.with(|counting| counting.set(true));
+    let result = f();
+    COUNTING.with(|counting| counting.set(false));
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+// Same-sized frames, so a recycled buffer always fits the next one. The
+// only IDR comes first: each one adds to the GOP statistics, which grow
+// their windows for a while.
+fn uniform_stream() -> SyntheticH264 {
+    SyntheticH264::new(8)
+        .gop_length(1_000)
+        .slice_size(3_000..=3_000)
+        .idr_slice_size(3_000..=3_000)
+        .duration(Duration::from_secs(2))
+}
+
+#[test]
+fn lent_frames_match_owned_ones() {
+    let (mut pusher, mut receiver) = common::pair();
+    for (frame, pts) in uniform_stream().slices_per_frame(3) {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let (timestamp, nals) = receiver
+            .recv_frame_with(|lent| {
+                assert_eq!(lent.data, &frame[..]);
+                (lent.timestamp, lent.nals().map(<[u8]>::to_vec).collect::<Vec<_>>())
+            })
+            .unwrap();
+        assert_eq!(timestamp, pts);
+        let expected: Vec<Vec<u8>> = rtp_transceive::annexb::nal_units(&frame).map(|nal| nal.data.to_vec()).collect();
+        assert_eq!(nals, expected);
+    }
+}
+
+// Allocations per frame while receiving `frames` of the stream, after a
+// warm-up in which buffers and queues grow to size.
+fn allocations_per_frame(lend: bool) -> f64 {
+    let (mut pusher, mut receiver) = common::pair();
+    let (mut allocations, mut frames) = (0, 0);
+    for (index, (frame, pts)) in uniform_stream().enumerate() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let (len, count) = if lend {
+            counted(|| receiver.recv_frame_with(|lent| lent.data.len()).unwrap())
+        } else {
+            counted(|| receiver.recv_frame().unwrap().len())
+        };
+        assert_eq!(len, frame.len());
+        if index >= 10 {
+            allocations += count;
+            frames += 1;
+        }
+    }
+    allocations as f64 / frames as f64
+}
+
+#[test]
+fn lent_frames_stop_allocating_per_frame() {
+    // Each owned frame takes its buffer along; lent ones give theirs back.
+    let owned = allocations_per_frame(false);
+    let lent = allocations_per_frame(true);
+    assert!(owned >= 1.0, "{} allocations per owned frame", owned);
+    assert_eq!(lent, 0.0, "{} allocations per lent frame", lent);
+}
+
+#[test]
+fn frames_of_one_packet_are_lent_as_they_came() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_frame_integrity(Some(3)).unwrap();
+    receiver.set_frame_integrity(Some(3)).unwrap();
+    // P slices fit a packet each; IDRs and their parameter sets take more.
+    let stream = SyntheticH264::new(2).gop_length(10).slice_size(100..=1_000).duration(Duration::from_secs(1));
+    for (frame, pts) in stream {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let (timestamp, integrity) = receiver
+            .recv_frame_with(|lent| {
+                assert_eq!(lent.data, &frame[..]);
+                (lent.timestamp, lent.integrity)
+            })
+            .unwrap();
+        assert_eq!((timestamp, integrity), (pts, IntegrityResult::Ok));
+    }
+    assert_eq!(receiver.stats().crc_mismatches, 0);
+}
+
+// One read takes in every datagram waiting, each into a slot of its own:
+// frames lent from there sit a slot apart. Copied ones would share the
+// few buffers the receiver recycles.
+#[cfg(unix)]
+#[test]
+fn frames_of_one_packet_are_lent_from_the_receive_buffer() {
+    let (mut pusher, mut receiver) = common::pair();
+    let stream = SyntheticH264::new(2).gop_length(1_000).slice_size(100..=1_000).duration(Duration::from_secs(1));
+    let frames: Vec<(Vec<u8>, u32)> = stream.skip(1).take(6).collect();
+    for (frame, pts) in &frames {
+        pusher.send_frame_with_timestamp(frame, *pts).unwrap();
+    }
+    // Everything has arrived before the first read.
+    std::thread::sleep(Duration::from_millis(50));
+    let addresses: Vec<usize> = frames
+        .iter()
+        .map(|(frame, _)| {
+            receiver
+                .recv_frame_with(|lent| {
+                    assert_eq!(lent.data, &frame[..]);
+                    lent.data.as_ptr() as usize
+                })
+                .unwrap()
+        })
+        .collect();
+    let strides: Vec<usize> = addresses.windows(2).map(|pair| pair[1].wrapping_sub(pair[0])).collect();
+    assert!(strides.iter().all(|&stride| stride == strides[0] && stride >= 64 << 10), "{:?}", addresses);
+}
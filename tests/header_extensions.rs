@@ -0,0 +1,110 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::extension::HeaderExtension;
+use rtp_transceive::prelude::*;
+
+const SSRC: u32 = 0x1122_3344;
+
+fn pusher_into_queue() -> (H264RtpPusher, mpsc::Receiver<Vec<u8>>) {
+    let (sender, packets) = mpsc::channel();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sender)));
+    pusher.set_ssrc(SSRC);
+    pusher.set_next_sequence(7).unwrap();
+    (pusher, packets)
+}
+
+#[test]
+fn single_nal_packet_carries_the_block_and_padding() {
+    let (mut pusher, packets) = pusher_into_queue();
+    pusher
+        .set_header_extensions(vec![
+            HeaderExtension::fixed(1, &[0xAA]).unwrap(),
+            HeaderExtension::per_packet(2, 2, |info, data| data.copy_from_slice(&info.sequence.to_be_bytes())).unwrap(),
+        ])
+        .unwrap();
+    pusher.send_frame_with_timestamp(&[0, 0, 0, 1, 0x65, 1, 2, 3], 0x0A0B_0C0D).unwrap();
+
+    #[rustfmt::skip]
+    let expected = [
+        0x90, 0x80 | 96, 0x00, 0x07, 0x0A, 0x0B, 0x0C, 0x0D, 0x11, 0x22, 0x33, 0x44,
+        // Profile, 2 words of elements: id 1 (1 byte), id 2 (2 bytes), padding.
+        0xBE, 0xDE, 0x00, 0x02,
+        0x10, 0xAA, 0x21, 0x00, 0x07, 0x00, 0x00, 0x00,
+        0x65, 1, 2, 3,
+    ];
+    assert_eq!(packets.try_recv().unwrap(), expected);
+    assert!(packets.try_recv().is_err());
+}
+
+#[test]
+fn every_fu_a_fragment_carries_the_block_within_the_mtu() {
+    let (mut pusher, packets) = pusher_into_queue();
+    pusher.set_mtu(40).unwrap();
+    pusher
+        .set_header_extensions(vec![HeaderExtension::fixed(5, &[1, 2, 3, 4]).unwrap()])
+        .unwrap();
+    // A 40-byte IDR: 12 header + 12 extension + 2 FU bytes leave 14 bytes
+    // per fragment for the 39 bytes after the NAL header.
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend(1..40u8);
+    pusher.send_frame_with_timestamp(&frame, 0).unwrap();
+
+    let sent: Vec<Vec<u8>> = packets.try_iter().collect();
+    assert_eq!(sent.len(), 3);
+    for (index, packet) in sent.iter().enumerate() {
+        assert!(packet.len() <= 40);
+        assert_eq!(packet[0], 0x90);
+        assert_eq!(packet[12..24], [0xBE, 0xDE, 0x00, 0x02, 0x53, 1, 2, 3, 4, 0, 0, 0]);
+        let fu_header = 0x05 | if index == 0 { 0x80 } else { 0 } | if index == 2 { 0x40 } else { 0 };
+        assert_eq!(packet[24..26], [0x7C, fu_header]);
+    }
+    let payload: Vec<u8> = sent.iter().flat_map(|packet| packet[26..].to_vec()).collect();
+    assert_eq!(payload, frame[5..]);
+}
+
+#[test]
+fn duplicate_ids_and_bad_elements_are_errors() {
+    let (mut pusher, _packets) = pusher_into_queue();
+    let twice = vec![HeaderExtension::fixed(3, &[1]).unwrap(), HeaderExtension::fixed(3, &[2]).unwrap()];
+    assert!(matches!(pusher.set_header_extensions(twice), Err(RtpError::InvalidExtensionId(3))));
+
+    pusher.set_abs_send_time(Some(4)).unwrap();
+    let taken = vec![HeaderExtension::fixed(4, &[1]).unwrap()];
+    assert!(matches!(pusher.set_header_extensions(taken), Err(RtpError::InvalidExtensionId(4))));
+
+    assert!(matches!(HeaderExtension::fixed(15, &[1]), Err(RtpError::InvalidExtensionId(15))));
+    assert!(matches!(HeaderExtension::fixed(1, &[]), Err(RtpError::InvalidExtensionLength(0))));
+    assert!(matches!(HeaderExtension::per_packet(1, 17, |_, _| ()), Err(RtpError::InvalidExtensionLength(17))));
+}
+
+#[test]
+fn receiver_hands_back_the_elements_of_each_packet() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.set_mtu(300).unwrap();
+    pusher
+        .set_header_extensions(vec![
+            HeaderExtension::fixed(1, b"cam-7").unwrap(),
+            HeaderExtension::per_packet(9, 1, |info, data| data[0] = info.frame_start as u8).unwrap(),
+        ])
+        .unwrap();
+
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend((0..1_000).map(|i| (i % 250) as u8 + 1));
+    pusher.send_frame_with_timestamp(&frame, 90).unwrap();
+
+    let (received, extensions) = receiver.recv_frame_with_extensions().unwrap();
+    assert_eq!(received, frame);
+    assert!(extensions.len() > 1);
+    for (index, packet) in extensions.iter().enumerate() {
+        assert_eq!(packet.timestamp, 90);
+        assert_eq!(packet.marker, index == extensions.len() - 1);
+        assert_eq!(packet.get(1), Some(&b"cam-7"[..]));
+        assert_eq!(packet.get(9), Some(&[(index == 0) as u8][..]));
+    }
+}
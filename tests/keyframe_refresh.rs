@@ -0,0 +1,106 @@
+#![cfg(feature = "net")]
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::{RefreshPolicy, RtpSource};
+use rtp_transceive::sender::{FaultInjector, FaultPolicy};
+use rtp_transceive::stats::RefreshReason;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+// 30 fps for 5 s, with IDRs at 0 and 4 s. Packets are lost from 1 s until
+// the second IDR.
+const FRAMES: usize = 150;
+const GOP_LENGTH: u32 = 120;
+const LOSS: std::ops::Range<usize> = 30..120;
+
+// Hands out packets at their due times on a manual clock, which it moves
+// on as the receiver waits, then reports the end of the stream.
+struct Scheduled {
+    clock: ManualClock,
+    start: Instant,
+    packets: VecDeque<(Duration, Vec<u8>)>
+}
+
+impl RtpSource for Scheduled {
+    fn recv_packet(&mut self, out: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let (due, _) = self.packets.front().ok_or(io::ErrorKind::UnexpectedEof)?;
+        let wait = (self.start + *due).saturating_duration_since(self.clock.now());
+        if let Some(timeout) = timeout.filter(|&timeout| timeout < wait) {
+            self.clock.advance(timeout);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.clock.advance(wait);
+        let (_, packet) = self.packets.pop_front().unwrap();
+        out[..packet.len()].copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+}
+
+#[test]
+fn sustained_loss_requests_keyframes_with_backoff_until_one_arrives() {
+    // A pusher whose packets pass through an injector that drops a fifth
+    // of them over LOSS. Its RTCP socket reads the PLIs.
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut injector = FaultInjector::new(sink);
+    injector.set_seed(3);
+    let control = injector.control();
+    let reports = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_mtu(500).unwrap();
+    pusher.set_sink(Some(Box::new(injector)));
+    pusher.enable_sender_reports(Duration::from_secs(5), Some(&reports.local_addr().unwrap().to_string())).unwrap();
+    let stream = SyntheticH264::new(6).gop_length(GOP_LENGTH).slice_size(1_500..=2_500).idr_slice_size(3_000..=4_000).duration(FRAME_INTERVAL * FRAMES as u32 + Duration::from_millis(100));
+    let mut packets = VecDeque::new();
+    for (index, (frame, pts)) in stream.take(FRAMES).enumerate() {
+        control.set_policy(if LOSS.contains(&index) { FaultPolicy::Drop(20) } else { FaultPolicy::PassThrough });
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        packets.extend(sent.try_iter().map(|packet| (FRAME_INTERVAL * index as u32, packet)));
+    }
+    assert!(control.stats().packets_dropped > 0);
+
+    let clock = ManualClock::new();
+    let start = clock.now();
+    let source = Scheduled { clock: clock.clone(), start, packets };
+    let mut receiver = H264RtpReceiver::from_source(Box::new(source)).unwrap();
+    receiver.set_clock(Arc::new(clock.clone()));
+    // Without receiver reports there is nowhere to send a PLI.
+    assert!(!receiver.request_keyframe());
+    receiver.set_rtcp_port(Some(0));
+    let pusher_rtcp = pusher.rtcp_local_addr().unwrap().unwrap().to_string();
+    receiver.enable_receiver_reports(Duration::from_secs(60), Some(&pusher_rtcp)).unwrap();
+    receiver.set_refresh_policy(Some(RefreshPolicy::default()));
+    loop {
+        match receiver.recv_annotated_frame() {
+            Ok(_) => {}
+            Err(RtpError::IdleTimeout) => break,
+            Err(err) => panic!("{}", err)
+        }
+    }
+
+    // The first damaged reference, a few frames into the loss, asks at
+    // once, then 500 ms and 1 s later; the IDR at 4 s arrives before the
+    // next one, 2 s later, is due.
+    let requests = receiver.keyframe_requests();
+    let times: Vec<Duration> = requests.iter().map(|request| request.at - start).collect();
+    assert_eq!(requests.iter().map(|request| request.attempt).collect::<Vec<_>>(), [1, 2, 3], "{:?}", times);
+    assert!(requests.iter().all(|request| request.sent && request.reason == RefreshReason::ReferenceLoss));
+    let loss_start = FRAME_INTERVAL * LOSS.start as u32;
+    assert!(times[0] >= loss_start && times[0] < loss_start + Duration::from_millis(200), "{:?}", times);
+    for (pair, backoff) in times.windows(2).zip([500, 1_000]) {
+        let gap = pair[1] - pair[0];
+        let backoff = Duration::from_millis(backoff);
+        assert!(gap >= backoff && gap < backoff + FRAME_INTERVAL, "{:?}", times);
+    }
+
+    // Each one reached the pusher as a PLI about its stream.
+    assert!(pusher.take_keyframe_request());
+    assert!(!pusher.take_keyframe_request());
+    assert_eq!(pusher.stats().plis_received, 3);
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::annexb::nal_units;
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+// 30 fps in 90 kHz ticks.
+const FRAME_DURATION_90K: u32 = 3000;
+const GOP_LENGTH: u32 = 5;
+
+// The packets of three GOPs, with the timestamp each carries.
+fn packets() -> Vec<(u32, Vec<u8>)> {
+    let stream = SyntheticH264::new(2)
+        .gop_length(GOP_LENGTH)
+        .slice_size(200..=400)
+        .idr_slice_size(2_000..=3_000)
+        .duration(Duration::from_millis(500));
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sink)));
+    for (frame, pts) in stream {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    sent.try_iter().map(|packet| (u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]), packet)).collect()
+}
+
+#[test]
+fn the_snapshot_follows_the_last_whole_idr_frame() {
+    let mut packets = packets();
+    // The second IDR loses a packet: the first one's snapshot stays.
+    let damaged = packets.iter().position(|&(timestamp, _)| timestamp == GOP_LENGTH * FRAME_DURATION_90K).unwrap();
+    packets.remove(damaged + 1);
+
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200))).unwrap();
+    receiver.set_keyframe_snapshots(true);
+    let (snapshots, taken) = mpsc::channel();
+    receiver.set_keyframe_callback(Some(Box::new(move |snapshot| snapshots.send(snapshot.timestamp).unwrap())));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for (_, packet) in &packets {
+        socket.send_to(packet, receiver.local_addr().unwrap()).unwrap();
+    }
+
+    let mut latest = Vec::new();
+    loop {
+        match receiver.recv_annotated_frame() {
+            Ok(_) => latest.push(receiver.latest_keyframe().unwrap().timestamp / FRAME_DURATION_90K),
+            Err(RtpError::IdleTimeout) => break,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    assert_eq!(latest, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10, 10, 10, 10, 10]);
+    assert_eq!(taken.try_iter().collect::<Vec<_>>(), [0, 10 * FRAME_DURATION_90K]);
+
+    // It decodes on its own: parameter sets first, then the IDR slice.
+    let snapshot = receiver.latest_keyframe().unwrap();
+    let types: Vec<u8> = nal_units(&snapshot.annexb).map(|nal| nal.h264_type()).collect();
+    assert_eq!(types, [7, 8, 5]);
+    assert!(snapshot.annexb.starts_with(&[0, 0, 0, 1, 0x67]));
+}
+
+#[test]
+fn no_snapshot_is_kept_while_it_is_off() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200))).unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for (_, packet) in &packets() {
+        socket.send_to(packet, receiver.local_addr().unwrap()).unwrap();
+    }
+    receiver.recv_frame().unwrap();
+    assert_eq!(receiver.latest_keyframe(), None);
+}
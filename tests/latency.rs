@@ -0,0 +1,109 @@
+#![cfg(feature = "net")]
+
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::time::NtpTimestamp;
+
+const FRAME: [u8; 8] = [0, 0, 0, 1, 0x65, 1, 2, 3];
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+fn receiver_on(clock: &ManualClock) -> H264RtpReceiver {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    receiver.set_clock(Arc::new(clock.clone()));
+    receiver.set_rtcp_port(Some(0));
+    receiver.enable_receiver_reports(Duration::from_secs(5), None).unwrap();
+    receiver
+}
+
+fn assert_close(actual: Duration, expected: Duration) {
+    let error = actual.abs_diff(expected);
+    assert!(error < Duration::from_micros(50), "{:?}, expected {:?}", actual, expected);
+}
+
+#[test]
+fn latency_follows_the_sender_report_mapping() {
+    let delay = Duration::from_millis(40);
+    let sender_clock = ManualClock::new();
+    let receiver_clock = ManualClock::starting_at(sender_clock.wall_clock());
+    let mut receiver = receiver_on(&receiver_clock);
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.set_clock(Arc::new(sender_clock.clone()));
+    let rtcp = receiver.rtcp_local_addr().unwrap().to_string();
+    pusher.enable_sender_reports(Duration::from_millis(1), Some(&rtcp)).unwrap();
+
+    // The network takes `delay`: the receiver's clock runs that far ahead.
+    receiver_clock.advance(delay);
+    for index in 0..10 {
+        pusher.send_frame(&FRAME).unwrap();
+        // The Sender Report goes out after the frame.
+        thread::sleep(Duration::from_millis(5));
+        let frame = receiver.recv_annotated_frame().unwrap();
+        match frame.latency {
+            None => assert_eq!(index, 0, "no estimate after the first Sender Report"),
+            Some(estimate) => {
+                assert_eq!(estimate.sender_report.ssrc, pusher.ssrc());
+                assert_eq!(estimate.delivered_at, receiver_clock.wall_clock());
+                assert_close(estimate.latency().unwrap(), delay);
+            }
+        }
+        sender_clock.advance(FRAME_INTERVAL);
+        receiver_clock.advance(FRAME_INTERVAL);
+    }
+    assert_close(receiver.stats().end_to_end_latency.unwrap(), delay);
+}
+
+// An RTCP Sender Report claiming `ntp` for `rtp_timestamp`.
+fn send_sender_report(to: SocketAddr, ssrc: u32, ntp: SystemTime, rtp_timestamp: u32) {
+    let mut packet = vec![0x80, 200, 0, 6];
+    packet.extend_from_slice(&ssrc.to_be_bytes());
+    packet.extend_from_slice(&NtpTimestamp::from_system_time(ntp).0.to_be_bytes());
+    packet.extend_from_slice(&rtp_timestamp.to_be_bytes());
+    packet.extend_from_slice(&[0; 8]);
+    UdpSocket::bind("127.0.0.1:0").unwrap().send_to(&packet, to).unwrap();
+    thread::sleep(Duration::from_millis(20));
+}
+
+#[test]
+fn latency_uses_the_latest_sender_report_and_exposes_its_parts() {
+    let clock = ManualClock::new();
+    let start = clock.wall_clock();
+    let mut receiver = receiver_on(&clock);
+    let rtcp = receiver.rtcp_local_addr().unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    let ssrc = pusher.ssrc();
+
+    // Without a Sender Report there is nothing to estimate from.
+    pusher.send_frame_with_timestamp(&FRAME, 0).unwrap();
+    assert!(receiver.recv_annotated_frame().unwrap().latency.is_none());
+    assert!(receiver.stats().end_to_end_latency.is_none());
+
+    // Half a second of media after the mapping, delivered at `start`.
+    send_sender_report(rtcp, ssrc, start - Duration::from_secs(1), 1_000);
+    pusher.send_frame_with_timestamp(&FRAME, 1_000 + 45_000).unwrap();
+    let estimate = receiver.recv_annotated_frame().unwrap().latency.unwrap();
+    assert_close(estimate.delivered_at.duration_since(estimate.sent_at).unwrap(), Duration::from_millis(500));
+    assert_close(estimate.latency().unwrap(), Duration::from_millis(500));
+    assert_eq!(estimate.sender_report.rtp_timestamp, 1_000);
+
+    // A new report mid-stream replaces the mapping.
+    clock.advance(Duration::from_millis(10_200));
+    send_sender_report(rtcp, ssrc, start + Duration::from_secs(10), 2_000_000);
+    pusher.send_frame_with_timestamp(&FRAME, 2_000_000 + 9_000).unwrap();
+    let estimate = receiver.recv_annotated_frame().unwrap().latency.unwrap();
+    assert_eq!(estimate.sender_report.rtp_timestamp, 2_000_000);
+    assert_close(estimate.latency().unwrap(), Duration::from_millis(100));
+
+    // A sender clock running ahead can put capture after delivery.
+    pusher.send_frame_with_timestamp(&FRAME, 2_000_000 + 5 * 90_000).unwrap();
+    let estimate = receiver.recv_annotated_frame().unwrap().latency.unwrap();
+    assert_eq!(estimate.latency(), None);
+    assert_close(estimate.sent_at.duration_since(estimate.delivered_at).unwrap(), Duration::from_millis(4_800));
+    // Smoothed from the two usable estimates: 500 ms, then 100 ms.
+    assert_close(receiver.stats().end_to_end_latency.unwrap(), Duration::from_millis(475));
+}
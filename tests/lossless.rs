@@ -0,0 +1,130 @@
+#![cfg(feature = "net")]
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use rtp_transceive::clock::{Clock, ManualClock};
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::RtpSource;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+const JITTER_DEPTH: Duration = Duration::from_millis(20);
+// In the 300 ms stream at 30 fps.
+const FRAMES: u32 = 9;
+
+// Hands out packets at their due times on a manual clock, which it moves
+// on as the receiver waits, then reports the end of the stream.
+struct Scheduled {
+    clock: ManualClock,
+    start: std::time::Instant,
+    packets: VecDeque<(Duration, Vec<u8>)>
+}
+
+impl RtpSource for Scheduled {
+    fn recv_packet(&mut self, out: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        let (due, _) = self.packets.front().ok_or(io::ErrorKind::UnexpectedEof)?;
+        let wait = (self.start + *due).saturating_duration_since(self.clock.now());
+        if let Some(timeout) = timeout.filter(|&timeout| timeout < wait) {
+            self.clock.advance(timeout);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.clock.advance(wait);
+        let (_, packet) = self.packets.pop_front().unwrap();
+        out[..packet.len()].copy_from_slice(&packet);
+        Ok(packet.len())
+    }
+}
+
+// The packets of a few frames, each frame's sent FRAME_INTERVAL after the
+// one before.
+fn packets() -> Vec<(Duration, Vec<u8>)> {
+    let stream = SyntheticH264::new(5).gop_length(5).slice_size(300..=600).duration(Duration::from_millis(300));
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_mtu(400).unwrap();
+    pusher.set_sink(Some(Box::new(sink)));
+    let mut packets = Vec::new();
+    for (index, (frame, pts)) in stream.enumerate() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        packets.extend(sent.try_iter().map(|packet| (FRAME_INTERVAL * index as u32, packet)));
+    }
+    packets
+}
+
+fn seq(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[2], packet[3]])
+}
+
+fn receiver(packets: Vec<(Duration, Vec<u8>)>, reliability: Reliability) -> (H264RtpReceiver, ManualClock, std::time::Instant) {
+    let clock = ManualClock::new();
+    let start = clock.now();
+    let source = Scheduled {
+        clock: clock.clone(),
+        start,
+        packets: packets.into()
+    };
+    let mut receiver = H264RtpReceiver::from_source(Box::new(source)).unwrap();
+    receiver.set_clock(Arc::new(clock.clone()));
+    receiver.set_jitter_buffer(Some(JitterDepth::Time(JITTER_DEPTH)));
+    receiver.set_reliability(reliability);
+    (receiver, clock, start)
+}
+
+#[test]
+fn a_gap_fails_a_lossless_read_but_is_loss_otherwise() {
+    let mut packets = packets();
+    let (_, lost) = packets.remove(6);
+    let expected = seq(&lost);
+
+    let (mut lossless, _, _) = receiver(packets.clone(), Reliability::Lossless);
+    let mut gaps = Vec::new();
+    let mut frames = 0;
+    loop {
+        match lossless.recv_annotated_frame() {
+            Ok(_) => frames += 1,
+            Err(RtpError::SequenceGap(expected, received)) => gaps.push((expected, received)),
+            Err(RtpError::IdleTimeout) => break,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    assert_eq!(gaps, [(expected, expected.wrapping_add(1))]);
+    // Reading went on after the error, around the missing packet.
+    assert_eq!(frames, FRAMES);
+    assert_eq!(lossless.frame_stats().incomplete_idr_frames + lossless.frame_stats().incomplete_non_idr_frames, 1);
+
+    let (mut best_effort, _, _) = receiver(packets, Reliability::BestEffort);
+    let mut frames = 0;
+    loop {
+        match best_effort.recv_annotated_frame() {
+            Ok(_) => frames += 1,
+            Err(RtpError::IdleTimeout) => break,
+            Err(err) => panic!("{}", err)
+        }
+    }
+    assert_eq!(frames, FRAMES);
+    assert_eq!(best_effort.stats().packets_lost, 1);
+}
+
+#[test]
+fn lossless_frames_skip_the_jitter_buffer_delay() {
+    // How long after its last packet was sent each frame came out.
+    let latencies = |reliability| {
+        let (mut receiver, clock, start) = receiver(packets(), reliability);
+        (0..FRAMES)
+            .map(|index| {
+                receiver.recv_annotated_frame().unwrap();
+                clock.now() - start - FRAME_INTERVAL * index
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The buffer holds the first packet for its depth, in case earlier
+    // ones are still on their way; in order, the rest pass straight on.
+    let best_effort = latencies(Reliability::BestEffort);
+    assert_eq!(best_effort[0], JITTER_DEPTH);
+    assert!(best_effort[1..].iter().all(|latency| latency.is_zero()));
+    assert!(latencies(Reliability::Lossless).iter().all(|latency| latency.is_zero()));
+}
@@ -0,0 +1,97 @@
+#![cfg(all(unix, feature = "mmap"))]
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::annexb::MmapAnnexBSource;
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+// 30 fps in 90 kHz ticks, as SyntheticH264 stamps its frames.
+const FRAME_DURATION_90K: u32 = 3000;
+
+fn long_stream() -> SyntheticH264 {
+    SyntheticH264::new(8)
+        .gop_length(30)
+        .slices_per_frame(2)
+        .slice_size(2_000..=6_000)
+        .idr_slice_size(20_000..=40_000)
+        .duration(Duration::from_secs(600))
+}
+
+// Writes `frames` of the stream to a file of its own and returns its path.
+fn write_file(name: &str, frames: usize) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rtp_transceive_{}_{}.h264", name, std::process::id()));
+    let mut file = BufWriter::new(File::create(&path).unwrap());
+    for (frame, _) in long_stream().take(frames) {
+        file.write_all(&frame).unwrap();
+    }
+    file.flush().unwrap();
+    path
+}
+
+// What a pusher sends for `access_units`, without the random initial
+// sequence number.
+fn packets<F: AsRef<[u8]>>(access_units: impl IntoIterator<Item = (F, u32)>) -> Vec<Vec<u8>> {
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_ssrc(0x0BAD_CAFE);
+    pusher.set_sink(Some(Box::new(sink)));
+    pusher.stream_access_units(access_units, false, None).unwrap();
+    sent.try_iter().map(|packet| [&packet[..2], &packet[4..]].concat()).collect()
+}
+
+#[test]
+fn mapped_access_units_send_the_same_packets_as_read_ones() {
+    let path = write_file("mmap_packets", 600);
+    let source = MmapAnnexBSource::open(&path).unwrap();
+    assert!(source.len() > 4 << 20, "{} bytes", source.len());
+
+    let mapped = packets(source.access_units(FRAME_DURATION_90K));
+    let read = packets(long_stream().take(600));
+    assert_eq!(mapped.len(), read.len());
+    assert!(mapped == read);
+    drop(source);
+    std::fs::remove_file(&path).unwrap();
+}
+
+// Resident pages backed by files, mapped ones included (RssShmem for a
+// temp_dir on tmpfs).
+#[cfg(target_os = "linux")]
+fn resident_file_bytes() -> usize {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    status
+        .lines()
+        .filter(|line| line.starts_with("RssFile:") || line.starts_with("RssShmem:"))
+        .map(|line| line.split_whitespace().nth(1).unwrap().parse::<usize>().unwrap() * 1024)
+        .sum()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn the_resident_set_stays_within_a_few_windows() {
+    const WINDOW: usize = 1 << 20;
+    let path = write_file("mmap_resident", 4_000);
+    let mut source = MmapAnnexBSource::open(&path).unwrap();
+    source.set_window(WINDOW);
+    assert!(source.len() > 32 << 20, "{} bytes", source.len());
+
+    let baseline = resident_file_bytes();
+    let mut peak = baseline;
+    let mut checksum = 0u64;
+    for (index, (access_unit, _)) in source.access_units(FRAME_DURATION_90K).enumerate() {
+        // Every byte is read, as packetizing reads it.
+        checksum = access_unit.iter().fold(checksum, |sum, &byte| sum.wrapping_add(byte as u64));
+        if index % 50 == 0 {
+            peak = peak.max(resident_file_bytes());
+        }
+    }
+    assert_ne!(checksum, 0);
+    // Four windows ahead and behind, plus what other tests map meanwhile.
+    assert!(peak - baseline < 4 * WINDOW + (8 << 20), "{} bytes resident over {}", peak - baseline, baseline);
+    drop(source);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,125 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rtp_transceive::clock::ManualClock;
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::RtpSink;
+
+// Records when each packet reached the sink, and its size.
+#[derive(Clone, Default)]
+struct TimedSink(Arc<Mutex<Vec<(Instant, usize)>>>);
+
+impl RtpSink for TimedSink {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().push((Instant::now(), packet.len()));
+        Ok(packet.len())
+    }
+}
+
+#[test]
+fn large_frame_is_spread_out_at_the_rate_limit() {
+    let sink = TimedSink::default();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher.set_mtu(1_000).unwrap();
+    // 100 kB/s with room for two packets back to back.
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend((0..20_000).map(|i| (i % 255) as u8 + 1));
+    pusher.send_frame_with_timestamp(&frame, 0).unwrap();
+
+    let sent = sink.0.lock().unwrap().clone();
+    assert!(sent.len() >= 20);
+    // After the burst every packet waits until the bucket has refilled by
+    // what went before it: none may leave ahead of that schedule, and none
+    // should be held much past it.
+    let start = sent[0].0;
+    let mut bytes = 0usize;
+    for &(at, size) in &sent {
+        let earliest = Duration::from_secs_f64(bytes.saturating_sub(2_000) as f64 / 100_000.0);
+        let elapsed = at - start;
+        assert!(elapsed + Duration::from_millis(1) >= earliest, "{:?} < {:?}", elapsed, earliest);
+        assert!(elapsed < earliest + Duration::from_millis(100), "{:?} > {:?}", elapsed, earliest);
+        bytes += size;
+    }
+    assert!(pusher.pacing_wait() > Duration::ZERO);
+}
+
+#[test]
+fn zero_rate_or_burst_is_rejected() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    for (bits_per_second, max_burst_bytes) in [(0, 1_000), (1_000, 0)] {
+        let policy = PacingPolicy {
+            bits_per_second,
+            max_burst_bytes
+        };
+        assert!(matches!(pusher.set_pacing(Some(policy)), Err(RtpError::InvalidPacing(bad)) if bad == policy));
+    }
+    pusher.set_pacing(None).unwrap();
+}
+
+// An Annex B access unit of NALs given by header byte and size.
+fn access_unit(nals: &[(u8, usize)]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    for &(header, size) in nals {
+        frame.extend([0, 0, 0, 1, header]);
+        frame.extend((1..size).map(|i| (i % 255) as u8 + 1));
+    }
+    frame
+}
+
+#[test]
+fn priority_dropping_sheds_nri_0_then_non_idr_slices_but_never_idrs() {
+    let clock = ManualClock::new();
+    let (queue, packets) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_clock(Arc::new(clock));
+    pusher.set_sink(Some(Box::new(queue)));
+    // An encoder that marks every NAL with NRI 3.
+    pusher.set_nri_rewrite(true);
+    // 100 kB/s, 2 kB back to back; shed what would take over 20 ms.
+    pusher
+        .set_pacing(Some(PacingPolicy {
+            bits_per_second: 800_000,
+            max_burst_bytes: 2_000
+        }))
+        .unwrap();
+    pusher.set_priority_dropping(Some(Duration::from_millis(20)));
+    let nal_types = |packets: &mpsc::Receiver<Vec<u8>>| -> Vec<(u8, bool)> {
+        packets.try_iter().map(|packet| (packet[12] & 0x1F, packet[1] & 0x80 != 0)).collect()
+    };
+
+    // Within the burst: everything goes out.
+    pusher.send_frame(&access_unit(&[(0x66, 300), (0x61, 1_000)])).unwrap();
+    assert_eq!(nal_types(&packets), [(6, false), (1, true)]);
+
+    // The SEI and filler (NRI 0 once rewritten) go, the slice fits then.
+    pusher.send_frame(&access_unit(&[(0x66, 1_000), (0x6C, 1_000), (0x61, 1_000)])).unwrap();
+    assert_eq!(nal_types(&packets), [(1, true)]);
+    assert!(!pusher.needs_keyframe());
+
+    // Nothing disposable: the slices go too, and the stream waits for an
+    // IDR.
+    pusher.send_frame(&access_unit(&[(0x61, 1_000), (0x61, 1_000), (0x61, 1_000)])).unwrap();
+    assert!(nal_types(&packets).is_empty());
+    assert!(pusher.needs_keyframe());
+
+    // Parameter sets and IDR slices always go out, however far behind.
+    pusher
+        .send_frame(&access_unit(&[(0x67, 12), (0x68, 4), (0x65, 1_000), (0x65, 1_000)]))
+        .unwrap();
+    assert_eq!(nal_types(&packets), [(7, false), (8, false), (5, false), (5, true)]);
+    assert!(!pusher.needs_keyframe());
+
+    let drops = pusher.priority_drops();
+    assert_eq!((drops.disposable_nals, drops.non_idr_slices), (2, 3));
+}
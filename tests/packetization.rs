@@ -0,0 +1,99 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+
+use rtp_transceive::packetizer::H264Packetizer;
+use rtp_transceive::prelude::*;
+
+mod common;
+
+// The packets each access unit of the stream went out as.
+fn packets_per_frame(pusher: &mut H264RtpPusher) -> Vec<(u32, Vec<Vec<u8>>)> {
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    pusher.set_sink(Some(Box::new(sink)));
+    common::fragmenting_stream()
+        .map(|(frame, pts)| {
+            pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+            (pts, sent.try_iter().collect())
+        })
+        .collect()
+}
+
+fn timestamp(packet: &[u8]) -> u32 {
+    u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]])
+}
+
+fn sequence(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[2], packet[3]])
+}
+
+fn marker(packet: &[u8]) -> bool {
+    packet[1] & 0x80 != 0
+}
+
+#[test]
+fn one_marker_and_one_timestamp_per_access_unit() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_mtu(1_000).unwrap();
+    let frames = packets_per_frame(&mut pusher);
+    assert_eq!(frames.len(), 30);
+    for (pts, packets) in &frames {
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|packet| timestamp(packet) == *pts));
+        let markers: Vec<bool> = packets.iter().map(|packet| marker(packet)).collect();
+        assert_eq!(markers.iter().filter(|&&marker| marker).count(), 1);
+        assert!(markers.last().unwrap());
+    }
+    assert!(frames.windows(2).all(|pair| pair[1].0 > pair[0].0));
+}
+
+#[test]
+fn sequence_numbers_wrap_without_a_gap() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_next_sequence(u16::MAX - 20).unwrap();
+    let packets: Vec<Vec<u8>> = packets_per_frame(&mut pusher).into_iter().flat_map(|(_, packets)| packets).collect();
+    assert!(packets.len() > 40);
+    assert_eq!(sequence(&packets[0]), u16::MAX - 20);
+    assert!(packets.windows(2).all(|pair| sequence(&pair[1]) == sequence(&pair[0]).wrapping_add(1)));
+    assert_eq!(pusher.next_sequence(), sequence(packets.last().unwrap()).wrapping_add(1));
+}
+
+#[test]
+fn no_packet_exceeds_the_mtu() {
+    for mtu in [100, 576, 1_200, 1_400, 9_000] {
+        let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+        pusher.set_mtu(mtu).unwrap();
+        let packets: Vec<Vec<u8>> = packets_per_frame(&mut pusher).into_iter().flat_map(|(_, packets)| packets).collect();
+        let largest = packets.iter().map(Vec::len).max().unwrap();
+        assert!(largest <= mtu, "{} bytes at MTU {}", largest, mtu);
+        // Fragments fill the packet up to the MTU.
+        if mtu < 9_000 {
+            assert_eq!(largest, mtu);
+        }
+    }
+}
+
+#[test]
+fn an_mtu_too_small_for_a_fragment_is_rejected() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    assert!(matches!(pusher.set_mtu(20), Err(RtpError::InvalidMtu(20))));
+    assert!(matches!(pusher.set_mtu(70_000), Err(RtpError::InvalidMtu(70_000))));
+    assert_eq!(pusher.mtu(), 1_400);
+}
+
+#[test]
+fn the_packetizer_packetizes_like_the_pusher() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_mtu(1_000).unwrap();
+    pusher.set_fragment_sizing(FragmentSizing::Balanced);
+    let sent = packets_per_frame(&mut pusher);
+
+    let mut packetizer = H264Packetizer::new(pusher.ssrc(), pusher.payload_type(), 1_000).unwrap();
+    packetizer.set_fragment_sizing(FragmentSizing::Balanced);
+    packetizer.set_next_sequence(sequence(&sent[0].1[0]));
+    let (mut sink, packets) = mpsc::channel();
+    for ((frame, pts), (_, sent)) in common::fragmenting_stream().zip(&sent) {
+        packetizer.send_frame(&frame, pts, &mut sink).unwrap();
+        assert_eq!(&packets.try_iter().collect::<Vec<_>>(), sent);
+    }
+}
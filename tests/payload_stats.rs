@@ -0,0 +1,53 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::annexb::nal_units;
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+#[test]
+fn bytes_add_up_per_nal_type_and_frame_type() {
+    let stream = SyntheticH264::new(3).gop_length(10).slices_per_frame(2).duration(Duration::from_secs(1));
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    let (sink, _sent) = mpsc::channel::<Vec<u8>>();
+    pusher.set_sink(Some(Box::new(sink)));
+
+    let mut expected = [0u64; 32];
+    let (mut idr_bytes, mut non_idr_bytes) = (0, 0);
+    for (frame, pts) in stream {
+        let sizes: Vec<(u8, usize)> = nal_units(&frame).map(|nal| (nal.h264_type(), nal.data.len())).collect();
+        for &(nal_type, len) in &sizes {
+            expected[nal_type as usize] += len as u64;
+        }
+        let total: u64 = sizes.iter().map(|&(_, len)| len as u64).sum();
+        if sizes.iter().any(|&(nal_type, _)| nal_type == 5) {
+            idr_bytes += total;
+        } else {
+            non_idr_bytes += total;
+        }
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+
+    let stats = pusher.payload_stats();
+    assert_eq!(stats.bytes_per_nal_type, expected);
+    assert_eq!((stats.idr_frames, stats.non_idr_frames), (3, 27));
+    assert_eq!((stats.idr_frame_bytes, stats.non_idr_frame_bytes), (idr_bytes, non_idr_bytes));
+    assert_eq!(stats.average_idr_frame_size, Some(idr_bytes / 3));
+}
+
+#[test]
+fn types_without_a_name_keep_their_own_slot() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    let (sink, _sent) = mpsc::channel::<Vec<u8>>();
+    pusher.set_sink(Some(Box::new(sink)));
+    // A slice data partition A (type 2) and a type 13 NAL (SPS extension).
+    let frame = [&[0, 0, 0, 1, 0x62][..], &[0xAB; 40], &[0, 0, 0, 1, 0x6D], &[0xCD; 9]].concat();
+    pusher.send_frame_with_timestamp(&frame, 0).unwrap();
+
+    let stats = pusher.payload_stats();
+    assert_eq!(stats.bytes_per_nal_type[2], 41);
+    assert_eq!(stats.bytes_per_nal_type[13], 10);
+    assert_eq!(stats.bytes_per_nal_type[0], 0);
+}
@@ -0,0 +1,118 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::Preset;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+fn listener() -> (UdpSocket, String) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    (socket, address)
+}
+
+fn drain(socket: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Ok(len) = socket.recv(&mut buf) {
+        packets.push(buf[..len].to_vec());
+    }
+    packets
+}
+
+// The frame as an encoder that sends parameter sets only once would.
+fn without_parameter_sets(frame: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::new();
+    for nal in nals(frame) {
+        if !matches!(nal[0] & 0x1F, 7 | 8) {
+            stripped.extend_from_slice(&START_CODE);
+            stripped.extend_from_slice(nal);
+        }
+    }
+    stripped
+}
+
+fn nals(frame: &[u8]) -> Vec<&[u8]> {
+    let starts: Vec<usize> = (0..frame.len().saturating_sub(3)).filter(|&i| frame[i..i + 4] == START_CODE).collect();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| &frame[start + 4..starts.get(index + 1).copied().unwrap_or(frame.len())])
+        .collect()
+}
+
+// NAL type of an RTP packet's payload: 24 for STAP-A, 28 for FU-A.
+fn payload_type(packet: &[u8]) -> u8 {
+    packet[12] & 0x1F
+}
+
+#[test]
+fn vlc_sdp_repeats_parameter_sets_the_sdp_file_may_lack() {
+    let (socket, address) = listener();
+    let mut pusher = H264RtpPusher::new(&address).unwrap();
+    pusher.apply_config(Preset::VlcSdp.config()).unwrap();
+    // Written before the first frame, as a player's .sdp file usually is.
+    let sdp = pusher.sdp();
+    assert!(sdp.contains("a=fmtp:96 packetization-mode=1\r\n"), "{}", sdp);
+    assert!(!sdp.contains("sprop-parameter-sets"));
+
+    // The stream itself carries SPS and PPS once, at the start.
+    for (index, (frame, pts)) in SyntheticH264::new(7).gop_length(5).duration(Duration::from_millis(400)).enumerate() {
+        let frame = if index == 0 { frame } else { without_parameter_sets(&frame) };
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    let packets = drain(&socket);
+    // Every IDR is preceded by both, in separate packets.
+    let idr_starts: Vec<usize> = (0..packets.len())
+        .filter(|&i| payload_type(&packets[i]) == 5 || (payload_type(&packets[i]) == 28 && packets[i][13] & 0x9F == 0x85))
+        .collect();
+    assert_eq!(idr_starts.len(), 3);
+    for start in idr_starts {
+        assert_eq!([payload_type(&packets[start - 2]), payload_type(&packets[start - 1])], [7, 8]);
+    }
+}
+
+#[test]
+fn low_latency_lan_fills_ethernet_frames_and_aggregates_small_nals() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200))).unwrap();
+    let (socket, address) = listener();
+    let mut pusher = H264RtpPusher::new(&address).unwrap();
+    let mut delta = Preset::LowLatencyLan.config();
+    assert_eq!(delta.mtu, Some(1472));
+    pusher.apply_config(delta.clone()).unwrap();
+
+    let frames: Vec<(Vec<u8>, u32)> = SyntheticH264::new(9)
+        .gop_length(4)
+        .idr_slice_size(4_000..=4_000)
+        .slice_size(600..=900)
+        .duration(Duration::from_millis(300))
+        .collect();
+    for (frame, pts) in &frames {
+        pusher.send_frame_with_timestamp(frame, *pts).unwrap();
+    }
+    let packets = drain(&socket);
+    assert_eq!(packets.iter().map(Vec::len).max(), Some(1472));
+    // SPS and PPS share a STAP-A packet ahead of each IDR.
+    let aggregates = packets.iter().filter(|packet| payload_type(packet) == 24).count();
+    assert_eq!(aggregates, frames.len().div_ceil(4));
+
+    // A receiver takes the packets back into the frames sent.
+    let destination = receiver.local_addr().unwrap();
+    for packet in &packets {
+        socket.send_to(packet, destination).unwrap();
+    }
+    for (frame, _) in &frames {
+        assert_eq!(&receiver.recv_frame().unwrap(), frame);
+    }
+
+    // Fields of the preset can still be overridden before it is applied.
+    delta.mtu = Some(1200);
+    pusher.apply_config(delta).unwrap();
+    assert_eq!(pusher.mtu(), 1200);
+}
@@ -0,0 +1,353 @@
+#![cfg(feature = "net")]
+
+// Compile-time snapshot of the documented public API. Every path and
+// signature below is one users may rely on; removing or changing one
+// breaks this file instead of a downstream build.
+#![allow(deprecated, clippy::type_complexity)]
+
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rtp_transceive::annexb::{self, AnnexBWriter, NalUnit, NalUnits, StartCodeStyle};
+use rtp_transceive::capture::{CaptureRotation, CapturedPacket, PcapSource, PcapWriter};
+use rtp_transceive::clock::{Clock, ManualClock, SystemClock};
+use rtp_transceive::conformance::{self, Rule, Violation};
+use rtp_transceive::extension::{HeaderExtension, PacketExtensions, PacketInfo};
+use rtp_transceive::receiver::{Codec, Frame, FrameRef, Frames, H264RtpReceiver, IntegrityResult, JitterDepth, KeyframeCallback, KeyframeSnapshot, RefreshPolicy, Reliability, RtcpRelayMode, RtpSource};
+use rtp_transceive::rtsp::{FrameSink, RtspServer};
+use rtp_transceive::sender::{
+    AddressPreference, AppliedChanges, ClockAnchor, ConfigDelta, ConfigField, ConfigWarning, DuplicationPolicy, FaultControl, FaultInjector, FaultPolicy, FaultStats, Preset, FragmentSizing,
+    H264RtpPusher, H265RtpPusher, MarkerPolicy, MulticastOptions, PacingPolicy, PausePolicy, ReconnectPolicy,
+    RtpSink, SenderState, UdpSink, DEFAULT_BITRATE_WINDOW, DEFAULT_SENDER_REPORT_INTERVAL,
+};
+use rtp_transceive::stats::{
+    Finding, Goodbye, HeaderStats, JitterAdaptation, KeyframeRequest, LatencyEstimate, LeaveReason, MemberEvent, MemberRole, MetricsSummary, PathStats, Pathology, PayloadErrors, PayloadStats, PriorityDrops, ReceivedFrameStats, RefreshReason, ReceiverStats, ReceptionReport, RelayStats, RetransmissionStats, SenderReport,
+    SenderStats, SessionMember, StreamSummary,
+};
+use rtp_transceive::testsupport::SyntheticH264;
+use rtp_transceive::time::{self, MediaTimestamp, NtpTimestamp, VIDEO_CLOCK_RATE};
+use rtp_transceive::trace::{TraceEvent, TraceSource, TraceWriter};
+use rtp_transceive::{CancelToken, PacketTransform, RtpError, TcpFraming};
+
+type Result<T> = std::result::Result<T, RtpError>;
+
+#[test]
+fn prelude_names_resolve() {
+    use rtp_transceive::prelude as p;
+
+    let _: fn(&str) -> Result<p::H264RtpPusher> = p::H264RtpPusher::new;
+    let _: fn(&str) -> Result<p::H264RtpReceiver> = p::H264RtpReceiver::new;
+    let _: fn(Duration) -> u32 = p::video_rtp_timestamp;
+    let _: fn() -> p::CancelToken = p::CancelToken::new;
+    let _: Option<(
+        p::JitterDepth,
+        p::ClockAnchor,
+        p::DuplicationPolicy,
+        p::FragmentSizing,
+        p::H265RtpPusher,
+        p::MarkerPolicy,
+        p::MulticastOptions,
+        p::PacingPolicy,
+        p::PausePolicy,
+        p::SenderState,
+    )> = None;
+    let _: Option<(
+        p::PayloadStats,
+        p::ReceivedFrameStats,
+        p::ReceiverStats,
+        p::ReceptionReport,
+        p::SenderStats,
+        p::StreamSummary,
+        p::MediaTimestamp,
+        p::PacketTransform,
+        p::RtpError,
+        p::TcpFraming,
+    )> = None;
+    // Kept for older code that named the pusher at the crate root.
+    let _: Option<rtp_transceive::H264RtpPusher> = None;
+}
+
+#[test]
+fn pusher_api() {
+    let _: fn(&str) -> Result<H264RtpPusher> = H264RtpPusher::new;
+    let _: fn(&str, AddressPreference) -> Result<H264RtpPusher> = H264RtpPusher::with_address_preference;
+    let _: fn(SocketAddr) -> Result<H264RtpPusher> = H264RtpPusher::with_socket_addr;
+    let _: fn(&str, &str) -> Result<H264RtpPusher> = H264RtpPusher::with_bind;
+    let _: fn(UdpSocket, SocketAddr) -> Result<H264RtpPusher> = H264RtpPusher::from_socket;
+    let _: fn(&str, SenderState) -> Result<H264RtpPusher> = H264RtpPusher::resume;
+
+    let _: fn(&mut H264RtpPusher, &[u8]) -> Result<()> = H264RtpPusher::send_frame;
+    let _: fn(&mut H264RtpPusher, &[u8], u32) -> Result<()> = H264RtpPusher::send_frame_with_timestamp;
+    let _: fn(&mut H264RtpPusher, &[u8], u8) -> Result<()> = H264RtpPusher::send_frame_avcc;
+    let _: fn(&mut H264RtpPusher, &[u8], bool, Option<u32>) -> Result<()> = H264RtpPusher::push_nal;
+    let _: fn(&mut H264RtpPusher) -> Result<()> = H264RtpPusher::close;
+    let _: fn(&H264RtpPusher) -> bool = H264RtpPusher::is_closed;
+    let _: fn(&mut H264RtpPusher, bool) -> Result<()> = H264RtpPusher::warm_up;
+
+    let _: fn(&H264RtpPusher) -> u32 = H264RtpPusher::ssrc;
+    let _: fn(&mut H264RtpPusher, u32) = H264RtpPusher::set_ssrc;
+    let _: fn(&H264RtpPusher) -> u8 = H264RtpPusher::payload_type;
+    let _: fn(&mut H264RtpPusher, u8) -> Result<()> = H264RtpPusher::set_payload_type;
+    let _: fn(&H264RtpPusher) -> u16 = H264RtpPusher::next_sequence;
+    let _: fn(&mut H264RtpPusher, u16) -> Result<()> = H264RtpPusher::set_next_sequence;
+    let _: fn(&H264RtpPusher) -> u32 = H264RtpPusher::current_timestamp_offset;
+    let _: fn(&mut H264RtpPusher, u32) -> Result<()> = H264RtpPusher::set_timestamp_offset;
+    let _: fn(&H264RtpPusher) -> usize = H264RtpPusher::mtu;
+    let _: fn(&mut H264RtpPusher, usize) -> Result<()> = H264RtpPusher::set_mtu;
+    let _: fn(&H264RtpPusher) -> Result<SocketAddr> = H264RtpPusher::local_addr;
+    let _: fn(&H264RtpPusher) -> String = H264RtpPusher::sdp;
+    let _: fn(&H264RtpPusher) -> ClockAnchor = H264RtpPusher::clock_anchor;
+    let _: fn(&mut H264RtpPusher, Arc<dyn Clock>) = H264RtpPusher::set_clock;
+    let _: fn(&H264RtpPusher) -> SenderState = H264RtpPusher::save_state;
+
+    let _: fn(&mut H264RtpPusher, bool) = H264RtpPusher::set_nri_rewrite;
+    let _: fn(&mut H264RtpPusher, bool) = H264RtpPusher::set_stap_a_aggregation;
+    let _: fn(&mut H264RtpPusher, bool) = H264RtpPusher::set_end_of_stream_nal;
+    let _: fn(&mut H264RtpPusher, bool) = H264RtpPusher::set_parameter_set_injection;
+    let _: fn(&mut H264RtpPusher, MarkerPolicy) = H264RtpPusher::set_marker_policy;
+    let _: fn(&mut H264RtpPusher, FragmentSizing) = H264RtpPusher::set_fragment_sizing;
+    let _: fn(&mut H264RtpPusher, Option<PacketTransform>, usize) -> Result<()> =
+        H264RtpPusher::set_packet_transform;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::transform_drops;
+    let _: fn(&mut H264RtpPusher, Option<Box<dyn RtpSink>>) = H264RtpPusher::set_sink;
+    let _: fn(&mut H264RtpPusher, Option<PcapWriter>) = H264RtpPusher::set_capture;
+    let _: fn(&mut H264RtpPusher, PathBuf) -> Result<()> = H264RtpPusher::enable_capture::<PathBuf>;
+    let _: fn(&H264RtpPusher) -> Option<&RtpError> = H264RtpPusher::capture_error;
+
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::pause;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::unpause;
+    let _: fn(&H264RtpPusher) -> bool = H264RtpPusher::is_paused;
+    let _: fn(&H264RtpPusher) -> bool = H264RtpPusher::needs_keyframe;
+    let _: fn(&mut H264RtpPusher, PausePolicy) = H264RtpPusher::set_pause_policy;
+
+    let _: fn(&H264RtpPusher, usize) -> Result<usize> = H264RtpPusher::set_send_buffer_size;
+    let _: fn(&mut H264RtpPusher, Option<Duration>) = H264RtpPusher::set_send_retry_delay;
+    let _: fn(&mut H264RtpPusher, Option<CancelToken>) = H264RtpPusher::set_cancel_token;
+    let _: fn(&mut H264RtpPusher, Option<Duration>) = H264RtpPusher::set_latency_bound;
+    let _: fn(&mut H264RtpPusher, Option<Duration>) = H264RtpPusher::set_priority_dropping;
+    let _: fn(&mut H264RtpPusher, Option<u64>) = H264RtpPusher::set_control_priority;
+    let _: fn(&H264RtpPusher) -> PriorityDrops = H264RtpPusher::priority_drops;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::late_frames_dropped;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::kernel_drops;
+    let _: fn(&mut H264RtpPusher, Option<&str>) -> Result<()> = H264RtpPusher::set_redundant_destination;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::redundant_send_errors;
+    let _: fn(&H264RtpPusher, u8) -> Result<()> = H264RtpPusher::set_dscp;
+    let _: fn(&H264RtpPusher, &str) -> Result<()> = H264RtpPusher::bind_to_device;
+    let _: fn(&mut H264RtpPusher, MulticastOptions) -> Result<()> = H264RtpPusher::set_multicast;
+    let _: fn(&mut H264RtpPusher, &str, TcpFraming) -> Result<()> = H264RtpPusher::connect_tcp;
+    let _: fn(&mut H264RtpPusher, Option<ReconnectPolicy>) = H264RtpPusher::set_tcp_reconnect;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::tcp_reconnects;
+    let _: fn(&mut H264RtpPusher) -> Result<usize> = H264RtpPusher::discover_path_mtu;
+    let _: fn(&mut H264RtpPusher, Option<Duration>) -> Result<()> = H264RtpPusher::set_path_mtu_discovery;
+    let _: fn(&mut H264RtpPusher, Option<PacingPolicy>) -> Result<()> = H264RtpPusher::set_pacing;
+    let _: fn(&H264RtpPusher) -> Duration = H264RtpPusher::pacing_wait;
+    let _: fn(&mut H264RtpPusher, Option<usize>) -> Result<()> = H264RtpPusher::set_send_batching;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::send_batches;
+    let _: fn(&mut H264RtpPusher, Option<DuplicationPolicy>) = H264RtpPusher::set_critical_packet_duplication;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::duplicates_sent;
+    let _: fn(&mut H264RtpPusher, ConfigDelta) -> Result<AppliedChanges> = H264RtpPusher::apply_config;
+    let _: fn(&H264RtpPusher) -> Vec<ConfigWarning> = H264RtpPusher::config_warnings;
+    let _: fn(Preset) -> ConfigDelta = Preset::config;
+    let _: fn(Preset) -> Vec<String> = Preset::describe;
+
+    let _: fn(&mut H264RtpPusher, Option<Duration>) = H264RtpPusher::set_keepalive;
+    let _: fn(&mut H264RtpPusher) -> Result<bool> = H264RtpPusher::poll_keepalive;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::keepalives_sent;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::end_of_stream;
+
+    let _: fn(&mut H264RtpPusher, Option<&str>) = H264RtpPusher::set_bye_reason;
+    let _: fn(&mut H264RtpPusher, Duration, Option<&str>) -> Result<()> = H264RtpPusher::enable_sender_reports;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::disable_sender_reports;
+    let _: fn(&H264RtpPusher) -> u64 = H264RtpPusher::sender_reports_sent;
+    let _: fn(&H264RtpPusher) -> Option<ReceptionReport> = H264RtpPusher::sender_feedback;
+    let _: fn(&mut H264RtpPusher) -> bool = H264RtpPusher::take_keyframe_request;
+    let _: fn(&mut H264RtpPusher) -> Result<bool> = H264RtpPusher::poll_sender_report;
+    let _: fn(&H264RtpPusher) -> Result<Option<SocketAddr>> = H264RtpPusher::rtcp_local_addr;
+
+    let _: fn(&mut H264RtpPusher, Duration, usize) = H264RtpPusher::enable_replay_window;
+    let _: fn(&mut H264RtpPusher, u8, usize) -> Result<()> = H264RtpPusher::enable_retransmission;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::disable_retransmission;
+    let _: fn(&H264RtpPusher) -> Option<u32> = H264RtpPusher::rtx_ssrc;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::disable_replay_window;
+    let _: fn(&mut H264RtpPusher, SocketAddr, Duration, Option<&CancelToken>) -> Result<usize> =
+        H264RtpPusher::replay_window;
+
+    let _: fn(&mut H264RtpPusher, Option<u8>) -> Result<()> = H264RtpPusher::set_frame_integrity;
+    let _: fn(&mut H264RtpPusher, Option<u8>) -> Result<()> = H264RtpPusher::set_abs_send_time;
+    let _: fn(&mut H264RtpPusher, Vec<HeaderExtension>) -> Result<()> = H264RtpPusher::set_header_extensions;
+
+    let _: fn(&H264RtpPusher) -> PayloadStats = H264RtpPusher::payload_stats;
+    let _: fn(&H264RtpPusher) -> SenderStats = H264RtpPusher::stats;
+    let _: fn(&mut H264RtpPusher) = H264RtpPusher::reset_stats;
+    let _: fn(&mut H264RtpPusher, Duration) = H264RtpPusher::set_bitrate_window;
+
+    let _: (Duration, Duration) = (DEFAULT_BITRATE_WINDOW, DEFAULT_SENDER_REPORT_INTERVAL);
+    let _: fn(&SenderState) -> Result<Vec<u8>> = SenderState::to_bytes;
+    let _: fn(&[u8]) -> Option<SenderState> = SenderState::from_bytes;
+    let _: Option<(ConfigField, UdpSink)> = None;
+    let _: fn(UdpSink) -> FaultInjector<UdpSink> = FaultInjector::new;
+    let _: fn(&FaultInjector<UdpSink>) -> FaultControl = FaultInjector::control;
+    let _: fn(&FaultControl, FaultPolicy) = FaultControl::set_policy;
+    let _: fn(&FaultControl) -> FaultStats = FaultControl::stats;
+}
+
+#[test]
+fn h265_pusher_api() {
+    let _: fn(&str) -> Result<H265RtpPusher> = H265RtpPusher::new;
+    let _: fn(SocketAddr) -> Result<H265RtpPusher> = H265RtpPusher::with_socket_addr;
+    let _: fn(&str, &str) -> Result<H265RtpPusher> = H265RtpPusher::with_bind;
+    let _: fn(UdpSocket, SocketAddr) -> Result<H265RtpPusher> = H265RtpPusher::from_socket;
+    let _: fn(&mut H265RtpPusher, &[u8]) -> Result<()> = H265RtpPusher::send_frame;
+    let _: fn(&mut H265RtpPusher, u8) -> Result<()> = H265RtpPusher::set_payload_type;
+    let _: fn(&mut H265RtpPusher, usize) -> Result<()> = H265RtpPusher::set_mtu;
+    let _: fn(&mut H265RtpPusher, bool) = H265RtpPusher::set_aggregation;
+    let _: fn(&mut H265RtpPusher, Arc<dyn Clock>) = H265RtpPusher::set_clock;
+}
+
+#[test]
+fn packetizer_api() {
+    use rtp_transceive::packetizer::{self, H264Packetizer};
+
+    let _: fn(u32, u8, usize) -> Result<H264Packetizer> = H264Packetizer::new;
+    let _: fn(&mut H264Packetizer, FragmentSizing) = H264Packetizer::set_fragment_sizing;
+    let _: fn(&H264Packetizer) -> u16 = H264Packetizer::next_sequence;
+    let _: fn(&mut H264Packetizer, u16) = H264Packetizer::set_next_sequence;
+    let _: fn(&mut H264Packetizer, &[u8], u32, &mut dyn RtpSink) -> Result<usize> = H264Packetizer::send_frame;
+    // The pusher's names are the packetizer's.
+    let _: fn(packetizer::FragmentSizing) -> FragmentSizing = |sizing| sizing;
+    let _: fn(Box<dyn packetizer::RtpSink>) -> Box<dyn RtpSink> = |sink| sink;
+}
+
+#[test]
+fn receiver_api() {
+    let _: fn(&str) -> Result<H264RtpReceiver> = H264RtpReceiver::new;
+    let _: fn(UdpSocket) -> H264RtpReceiver = H264RtpReceiver::from_socket;
+    let _: fn(SocketAddr) -> Result<H264RtpReceiver> = H264RtpReceiver::with_socket_addr;
+    let _: fn(&str, TcpFraming) -> Result<H264RtpReceiver> = H264RtpReceiver::accept_tcp;
+    let _: fn(Box<dyn RtpSource>) -> Result<H264RtpReceiver> = H264RtpReceiver::from_source;
+    let _: fn(TraceSource) -> Result<H264RtpReceiver> = H264RtpReceiver::from_trace;
+
+    let _: fn(&mut H264RtpReceiver) -> Result<Vec<u8>> = H264RtpReceiver::recv_frame;
+    let _: fn(&mut H264RtpReceiver, &CancelToken) -> Result<Vec<u8>> = H264RtpReceiver::recv_frame_cancellable;
+    let _: fn(&mut H264RtpReceiver) -> Result<(Vec<u8>, Vec<PacketExtensions>)> =
+        H264RtpReceiver::recv_frame_with_extensions;
+    let _: fn(&mut H264RtpReceiver) -> Result<Frame> = H264RtpReceiver::recv_annotated_frame;
+    let _ = |receiver: &mut H264RtpReceiver| -> Result<usize> {
+        receiver.recv_frame_with(|frame: FrameRef<'_>| {
+            let _: (&[u8], u32, &[PacketExtensions], IntegrityResult) =
+                (frame.data, frame.timestamp, frame.extensions, frame.integrity);
+            frame.nals().count()
+        })
+    };
+    let _: fn(&mut H264RtpReceiver) -> Frames<'_> = H264RtpReceiver::frames;
+    let _: fn(&mut H264RtpReceiver, Option<Duration>) -> Result<()> = H264RtpReceiver::set_idle_timeout;
+    let _: fn(&mut H264RtpReceiver, Option<JitterDepth>) = H264RtpReceiver::set_jitter_buffer;
+    let _: fn(&mut H264RtpReceiver, Reliability) = H264RtpReceiver::set_reliability;
+    let _: fn(&mut H264RtpReceiver, bool) = H264RtpReceiver::set_codec_sniffing;
+    let _ = RtpError::CodecMismatch(Codec::H264, Codec::H265);
+    let _: fn(&mut H264RtpReceiver, bool) = H264RtpReceiver::set_stream_doctor;
+    let _: fn(&H264RtpReceiver) -> Vec<Finding> = H264RtpReceiver::diagnostics;
+    let _: Option<Pathology> = None;
+    let _: fn(&mut H264RtpReceiver, Option<RefreshPolicy>) = H264RtpReceiver::set_refresh_policy;
+    let _: fn(&mut H264RtpReceiver) -> Vec<KeyframeRequest> = H264RtpReceiver::keyframe_requests;
+    let _ = JitterDepth::Adaptive { min: Duration::ZERO, max: Duration::ZERO };
+    let _: fn(&mut H264RtpReceiver) -> Vec<JitterAdaptation> = H264RtpReceiver::jitter_adaptations;
+    let adaptation: Option<JitterAdaptation> = None;
+    let _ = adaptation.map(|adaptation| (adaptation.at, adaptation.from, adaptation.to, adaptation.jitter));
+    let _: Option<Duration> = ReceiverStats::default().jitter_buffer_delay;
+    let _: fn(&mut H264RtpReceiver) -> bool = H264RtpReceiver::request_keyframe;
+    let _: Option<RefreshReason> = None;
+    let _ = RefreshPolicy::default();
+    let _: fn(&mut H264RtpReceiver, bool) = H264RtpReceiver::set_keyframe_snapshots;
+    let _: fn(&H264RtpReceiver) -> Option<KeyframeSnapshot> = H264RtpReceiver::latest_keyframe;
+    let _: fn(&mut H264RtpReceiver, Option<KeyframeCallback>) = H264RtpReceiver::set_keyframe_callback;
+    let snapshot: Option<KeyframeSnapshot> = None;
+    let _ = snapshot.map(|snapshot| (snapshot.annexb, snapshot.timestamp, snapshot.received_at));
+    let _ = [Reliability::BestEffort, Reliability::Lossless, Reliability::default()];
+    let _: fn(&mut H264RtpReceiver, Option<u8>) -> Result<()> = H264RtpReceiver::set_frame_integrity;
+    let _: fn(&mut H264RtpReceiver, Option<PacketTransform>) = H264RtpReceiver::set_packet_transform;
+    let _: fn(&mut H264RtpReceiver, &str, Duration) -> Result<()> = H264RtpReceiver::add_redundant_path;
+    let _: fn(&mut H264RtpReceiver, Duration, Option<&str>) -> Result<()> = H264RtpReceiver::enable_receiver_reports;
+    let _: fn(&H264RtpReceiver) -> Option<ReceptionReport> = H264RtpReceiver::last_receiver_report;
+    let _: fn(&mut H264RtpReceiver, Option<u16>) = H264RtpReceiver::set_rtcp_port;
+    let _: fn(&mut H264RtpReceiver, Arc<dyn Clock>) = H264RtpReceiver::set_clock;
+    let _: fn(&H264RtpReceiver) -> Option<SocketAddr> = H264RtpReceiver::rtcp_local_addr;
+    let _: fn(&H264RtpReceiver) -> Option<SenderReport> = H264RtpReceiver::last_sender_report;
+    let _: fn(&H264RtpReceiver) -> Option<&Goodbye> = H264RtpReceiver::goodbye;
+    let _: fn(&H264RtpReceiver) -> Vec<SessionMember> = H264RtpReceiver::session_members;
+    let _: fn(&mut H264RtpReceiver) -> Vec<MemberEvent> = H264RtpReceiver::member_events;
+    let _: fn(&mut H264RtpReceiver, usize) = H264RtpReceiver::set_member_limit;
+    let _: Option<(MemberRole, LeaveReason)> = None;
+    let _: fn(&SenderStats) -> &RetransmissionStats = |stats| &stats.rtx;
+    let _: fn(&SenderReport, u32, u32) -> std::time::SystemTime = SenderReport::wall_clock_at;
+    let _: fn(&H264RtpReceiver) -> ReceiverStats = H264RtpReceiver::stats;
+    let _: fn(&H264RtpReceiver) -> ReceivedFrameStats = H264RtpReceiver::frame_stats;
+    let _: fn(&mut H264RtpReceiver, u32) = H264RtpReceiver::set_gap_tolerance;
+    let _: fn(&mut H264RtpReceiver, usize) = H264RtpReceiver::set_max_frame_size;
+    let _: fn(&H264RtpReceiver) -> PayloadErrors = H264RtpReceiver::payload_errors;
+    let _: fn(&mut H264RtpReceiver, bool) = H264RtpReceiver::set_lenient_headers;
+    let _: fn(&H264RtpReceiver) -> HeaderStats = H264RtpReceiver::header_stats;
+    let _: fn(&H264RtpReceiver) -> MetricsSummary = H264RtpReceiver::metrics_summary;
+    let _: fn(&mut H264RtpReceiver, u8) = H264RtpReceiver::set_burst_gap_threshold;
+    let _: fn(&mut H264RtpReceiver, bool) = H264RtpReceiver::set_xr_reports;
+    let _: [fn(&MetricsSummary) -> u8; 4] =
+        [MetricsSummary::loss_rate, MetricsSummary::discard_rate, MetricsSummary::burst_density, MetricsSummary::gap_density];
+    let _: fn(&H264RtpReceiver) -> Vec<PathStats> = H264RtpReceiver::path_stats;
+    let _: fn(&mut H264RtpReceiver, Option<Box<dyn RtpSink>>) = H264RtpReceiver::set_relay;
+    let _: fn(&H264RtpReceiver) -> RelayStats = H264RtpReceiver::relay_stats;
+    let _: fn(&mut H264RtpReceiver, Option<u32>) -> Result<()> = H264RtpReceiver::set_relay_ssrc;
+    let _: fn(&mut H264RtpReceiver, &str, &str, RtcpRelayMode, RtcpRelayMode) -> Result<()> = H264RtpReceiver::enable_relay_rtcp;
+    let _: fn(&H264RtpReceiver) -> Option<SocketAddr> = H264RtpReceiver::relay_rtcp_local_addr;
+    let _: fn(&mut H264RtpReceiver, PathBuf) -> Result<()> = H264RtpReceiver::enable_trace::<PathBuf>;
+    let _: fn(&mut H264RtpReceiver, Option<TraceWriter>) = H264RtpReceiver::set_trace;
+    let _: fn(&H264RtpReceiver) -> Option<&RtpError> = H264RtpReceiver::trace_error;
+
+    let frame: Option<Frame> = None;
+    let _ = frame.map(|frame| (frame.data, frame.timestamp, frame.extensions, frame.integrity, frame.preceded_by_gap, frame.latency));
+    let _: fn(&LatencyEstimate) -> Option<Duration> = LatencyEstimate::latency;
+    let _ = [IntegrityResult::Ok, IntegrityResult::Mismatch, IntegrityResult::Absent];
+}
+
+#[cfg(all(unix, feature = "mmap"))]
+#[test]
+fn mmap_source() {
+    use rtp_transceive::annexb::{MappedAccessUnits, MappedNalUnits, MmapAnnexBSource};
+
+    let _: fn(PathBuf) -> Result<MmapAnnexBSource> = MmapAnnexBSource::open::<PathBuf>;
+    let _: fn(&mut MmapAnnexBSource, usize) = MmapAnnexBSource::set_window;
+    let _: fn(&MmapAnnexBSource) -> (usize, bool) = |source| (source.len(), source.is_empty());
+    let _: fn(&MmapAnnexBSource) -> &[u8] = MmapAnnexBSource::as_bytes;
+    let _: fn(&MmapAnnexBSource) -> MappedNalUnits<'_> = MmapAnnexBSource::nal_units;
+    let _: fn(&MmapAnnexBSource, u32) -> MappedAccessUnits<'_> = MmapAnnexBSource::access_units;
+}
+
+#[test]
+fn supporting_modules() {
+    let _: fn(&[u8]) -> NalUnits<'_> = annexb::nal_units;
+    let _: Option<NalUnit<'_>> = None;
+    let mut writer = AnnexBWriter::new(Vec::new(), StartCodeStyle::Short);
+    let _: std::io::Result<()> = writer.write_frame(&[]).and_then(|()| writer.write_nal(&[0x41]));
+    let _: Vec<u8> = writer.into_inner();
+    let _ = [StartCodeStyle::Long, StartCodeStyle::default()];
+    let _: fn(Duration) -> u32 = time::video_rtp_timestamp;
+    let _: fn(u64) -> u32 = time::video_rtp_timestamp_from_micros;
+    let _: fn(u32, u32) -> i32 = time::wire_diff;
+    let _: (u32, Option<(MediaTimestamp, NtpTimestamp)>) = (VIDEO_CLOCK_RATE, None);
+    let _: fn(u64) -> SyntheticH264 = SyntheticH264::new;
+    let _: fn() -> ManualClock = ManualClock::new;
+    let _: fn(&ManualClock, Duration) = ManualClock::advance;
+    let _: fn(&ManualClock, std::time::SystemTime) = ManualClock::set_wall_clock;
+    let _: Arc<dyn Clock> = Arc::new(SystemClock);
+    let _: fn(&str, &str) -> Result<RtspServer> = RtspServer::bind;
+    let _: fn(&RtspServer, Option<u32>) = RtspServer::set_late_join_burst;
+    let _: fn(&FrameSink, &[u8]) -> Result<()> = FrameSink::send_frame;
+    let _: fn(PathBuf) -> Result<PcapWriter> = PcapWriter::create::<PathBuf>;
+    let _: fn(PathBuf) -> Result<TraceWriter> = TraceWriter::create::<PathBuf>;
+    let _: fn(PathBuf) -> Result<TraceSource> = TraceSource::open::<PathBuf>;
+    let _: fn(&TraceSource) -> ManualClock = TraceSource::clock;
+    let _: Option<TraceEvent> = None;
+    let _: Option<(CaptureRotation, CapturedPacket, PcapSource, PacketInfo, Rule, Violation, StreamSummary)> = None;
+    let _ = conformance::check_h264::<Vec<u8>>;
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+
+use rtp_transceive::prelude::*;
+
+const SPS: [u8; 8] = [0x67, 0x42, 0xC0, 0x1E, 0xDA, 0x05, 0x07, 0xE4];
+const PPS: [u8; 4] = [0x68, 0xCE, 0x3C, 0x80];
+const IDR: [u8; 5] = [0x65, 0x88, 0x84, 0x00, 0x33];
+
+fn sequence(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[2], packet[3]])
+}
+
+#[test]
+fn counters_only_move_between_frames() {
+    let (sink, sent) = mpsc::channel::<Vec<u8>>();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sink)));
+    pusher.set_next_sequence(100).unwrap();
+    pusher.set_timestamp_offset(0).unwrap();
+
+    pusher.push_nal(&SPS, false, Some(9000)).unwrap();
+    assert!(matches!(pusher.set_next_sequence(5), Err(RtpError::FrameInProgress)));
+    assert!(matches!(pusher.set_timestamp_offset(5), Err(RtpError::FrameInProgress)));
+    assert_eq!((pusher.next_sequence(), pusher.current_timestamp_offset()), (101, 0));
+    pusher.push_nal(&PPS, false, None).unwrap();
+    pusher.push_nal(&IDR, true, None).unwrap();
+
+    // The frame went out unbroken; the next one starts where it was set.
+    pusher.set_next_sequence(500).unwrap();
+    pusher.push_nal(&IDR, true, Some(12_000)).unwrap();
+    let packets: Vec<u16> = sent.try_iter().map(|packet| sequence(&packet)).collect();
+    assert_eq!(packets, [100, 101, 102, 500]);
+}
@@ -0,0 +1,234 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::{RtpSink, UdpSink};
+use rtp_transceive::stats::{LeaveReason, MemberEvent, MemberRole};
+use rtp_transceive::testsupport::SyntheticH264;
+use rtp_transceive::time::VIDEO_CLOCK_RATE;
+
+fn receiver_with_rtcp() -> H264RtpReceiver {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    receiver.set_rtcp_port(Some(0));
+    receiver.enable_receiver_reports(Duration::from_millis(1), None).unwrap();
+    receiver
+}
+
+#[test]
+fn sender_reports_are_captured_and_answered_from_the_rtcp_port() {
+    let mut receiver = receiver_with_rtcp();
+    let rtcp = receiver.rtcp_local_addr().unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.enable_sender_reports(Duration::from_millis(1), Some(&rtcp.to_string())).unwrap();
+
+    let mut last = None;
+    for (frame, _) in SyntheticH264::new(4).duration(Duration::from_millis(300)) {
+        pusher.send_frame(&frame).unwrap();
+        last = Some(receiver.recv_annotated_frame().unwrap());
+        thread::sleep(Duration::from_millis(2));
+    }
+    let report = receiver.last_sender_report().expect("no Sender Report captured");
+    assert_eq!(report.ssrc, pusher.ssrc());
+    assert!(report.packet_count > 0 && report.octet_count > 0);
+    assert_eq!(report.wall_clock_at(report.rtp_timestamp, VIDEO_CLOCK_RATE), report.ntp.to_system_time());
+
+    // Both ends share this host's clock, so the mapping puts the last
+    // frame at about the time it was sent.
+    let sent = report.wall_clock_at(last.unwrap().timestamp, VIDEO_CLOCK_RATE);
+    let skew = SystemTime::now().duration_since(sent).unwrap_or_else(|err| err.duration());
+    assert!(skew < Duration::from_millis(200), "{:?}", skew);
+
+    // The pusher matched the Receiver Reports to its Sender Reports.
+    pusher.poll_sender_report().unwrap();
+    let feedback = pusher.sender_feedback().expect("no Receiver Report reached the pusher");
+    assert!(feedback.last_sender_report != 0 && feedback.round_trip_time.is_some());
+}
+
+#[test]
+fn goodbye_from_the_source_is_kept() {
+    let mut receiver = receiver_with_rtcp();
+    let rtcp = receiver.rtcp_local_addr().unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.enable_sender_reports(Duration::from_secs(5), Some(&rtcp.to_string())).unwrap();
+    let frame = [0, 0, 0, 1, 0x65, 1, 2, 3];
+    pusher.send_frame(&frame).unwrap();
+    receiver.recv_frame().unwrap();
+    assert!(receiver.goodbye().is_none());
+
+    // A BYE for another SSRC is not about this stream.
+    let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut bye = vec![0x81, 203, 0, 1];
+    bye.extend_from_slice(&pusher.ssrc().wrapping_add(1).to_be_bytes());
+    other.send_to(&bye, rtcp).unwrap();
+    pusher.send_frame(&frame).unwrap();
+    receiver.recv_frame().unwrap();
+    assert!(receiver.goodbye().is_none());
+
+    let ssrc = pusher.ssrc();
+    pusher.set_bye_reason(Some("camera off"));
+    pusher.close().unwrap();
+    // Reports are read while receiving; nothing else is coming.
+    assert!(matches!(receiver.recv_frame(), Err(RtpError::IdleTimeout)));
+    let goodbye = receiver.goodbye().expect("BYE not seen");
+    assert_eq!(goodbye.ssrc, ssrc);
+    assert_eq!(goodbye.reason.as_deref(), Some("camera off"));
+}
+
+// An SDES packet with one chunk: the CNAME of `ssrc`.
+fn sdes(ssrc: u32, cname: &str) -> Vec<u8> {
+    let mut chunk = ssrc.to_be_bytes().to_vec();
+    chunk.extend_from_slice(&[1, cname.len() as u8]);
+    chunk.extend_from_slice(cname.as_bytes());
+    chunk.push(0);
+    chunk.resize(chunk.len().div_ceil(4) * 4, 0);
+    let mut packet = vec![0x81, 202, 0, (chunk.len() / 4) as u8];
+    packet.extend(chunk);
+    packet
+}
+
+#[test]
+fn session_members_are_tracked_from_all_rtcp() {
+    let mut receiver = receiver_with_rtcp();
+    receiver.set_idle_timeout(Some(Duration::from_millis(100))).unwrap();
+    let rtcp = receiver.rtcp_local_addr().unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let (camera, viewer, monitor) = (0x1111_1111u32, 0x2222_2222u32, 0x3333_3333u32);
+
+    // A sender, a receiver, and one that only sent APP so far.
+    let mut sender_report = vec![0x80, 200, 0, 6];
+    sender_report.extend_from_slice(&camera.to_be_bytes());
+    sender_report.extend_from_slice(&[0; 20]);
+    sender_report.extend(sdes(camera, "camera@10.0.0.1"));
+    let mut receiver_report = vec![0x80, 201, 0, 1];
+    receiver_report.extend_from_slice(&viewer.to_be_bytes());
+    receiver_report.extend(sdes(viewer, "viewer@10.0.0.2"));
+    let mut app = vec![0x80, 204, 0, 2];
+    app.extend_from_slice(&monitor.to_be_bytes());
+    app.extend_from_slice(b"TEST");
+    for packet in [&sender_report, &receiver_report, &app] {
+        socket.send_to(packet, rtcp).unwrap();
+    }
+    // No media: RTCP is read while waiting for it.
+    assert!(matches!(receiver.recv_frame(), Err(RtpError::IdleTimeout)));
+
+    let members = receiver.session_members();
+    let summary: Vec<_> = members.iter().map(|member| (member.ssrc, member.cname.as_deref(), member.role)).collect();
+    assert_eq!(
+        summary,
+        [
+            (camera, Some("camera@10.0.0.1"), MemberRole::Sender),
+            (viewer, Some("viewer@10.0.0.2"), MemberRole::Receiver),
+            (monitor, None, MemberRole::Receiver)
+        ]
+    );
+    assert_eq!(
+        receiver.member_events(),
+        [camera, viewer, monitor].map(|ssrc| MemberEvent::Joined { ssrc })
+    );
+
+    let mut bye = vec![0x81, 203, 0, 3];
+    bye.extend_from_slice(&monitor.to_be_bytes());
+    bye.extend_from_slice(&[4, b'd', b'o', b'n', b'e', 0, 0, 0]);
+    socket.send_to(&bye, rtcp).unwrap();
+    assert!(matches!(receiver.recv_frame(), Err(RtpError::IdleTimeout)));
+
+    let ssrcs: Vec<u32> = receiver.session_members().iter().map(|member| member.ssrc).collect();
+    assert_eq!(ssrcs, [camera, viewer]);
+    assert_eq!(
+        receiver.member_events(),
+        [MemberEvent::Left {
+            ssrc: monitor,
+            reason: LeaveReason::Bye(Some("done".into()))
+        }]
+    );
+}
+
+// Sends over UDP, except the packets at the given positions.
+struct Lossy {
+    udp: UdpSink,
+    sent: usize,
+    dropped: &'static [usize]
+}
+
+impl RtpSink for Lossy {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.sent += 1;
+        if self.dropped.contains(&(self.sent - 1)) {
+            return Ok(packet.len());
+        }
+        self.udp.send_packet(packet)
+    }
+}
+
+#[test]
+fn receiver_reports_carry_burst_and_gap_metrics_in_xr() {
+    let monitor = UdpSocket::bind("127.0.0.1:0").unwrap();
+    monitor.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(300))).unwrap();
+    receiver.set_rtcp_port(Some(0));
+    receiver.enable_receiver_reports(Duration::from_millis(1), Some(&monitor.local_addr().unwrap().to_string())).unwrap();
+    receiver.set_burst_gap_threshold(4);
+    receiver.set_xr_reports(true);
+
+    let destination = receiver.local_addr().unwrap().to_string();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    // A burst of 3 losses in 4 packets, and one loss on its own.
+    pusher.set_sink(Some(Box::new(Lossy {
+        udp: UdpSink::new(&destination).unwrap(),
+        sent: 0,
+        dropped: &[10, 11, 13, 40]
+    })));
+    let frame = [0, 0, 0, 1, 0x65, 1, 2, 3];
+    for _ in 0..60 {
+        pusher.send_frame(&frame).unwrap();
+    }
+    while receiver.recv_frame().is_ok() {}
+
+    let summary = receiver.metrics_summary();
+    assert_eq!(summary.source_ssrc, Some(pusher.ssrc()));
+    assert_eq!((summary.packets_expected, summary.packets_lost, summary.packets_discarded), (60, 4, 0));
+    assert_eq!((summary.bursts, summary.burst_packets, summary.burst_losses), (1, 4, 3));
+    assert_eq!((summary.gap_packets, summary.gap_losses), (56, 1));
+    assert_eq!((summary.burst_density(), summary.gap_density()), (192, 4));
+
+    // RR, SDES, then the XR packet with its VoIP Metrics block.
+    let mut buffer = [0u8; 1500];
+    let (len, _) = monitor.recv_from(&mut buffer).unwrap();
+    let compound = &buffer[..len];
+    let xr_start = 32 + 4 * (u16::from_be_bytes([compound[34], compound[35]]) as usize + 1);
+    let xr = &compound[xr_start..];
+    assert_eq!((xr[1], u16::from_be_bytes([xr[2], xr[3]]), xr.len()), (207, 10, 44));
+    assert_eq!((xr[8], u16::from_be_bytes([xr[10], xr[11]])), (7, 8));
+    assert_eq!(u32::from_be_bytes([xr[12], xr[13], xr[14], xr[15]]), pusher.ssrc());
+    // Gmin.
+    assert_eq!(xr[31], 4);
+}
+
+#[test]
+fn report_destinations_resolve_like_the_pushers() {
+    let monitor = UdpSocket::bind("127.0.0.1:0").unwrap();
+    monitor.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_rtcp_port(Some(0));
+    let interval = Duration::from_millis(1);
+
+    let err = receiver.enable_receiver_reports(interval, Some("[::1]:5005")).unwrap_err();
+    assert!(err.to_string().contains("address families differ"), "{}", err);
+    let err = receiver.enable_receiver_reports(interval, Some("no-such-host.invalid:5005")).unwrap_err();
+    assert!(err.to_string().contains("no-such-host.invalid"), "{}", err);
+
+    // "localhost" may resolve to ::1 first; reports go to 127.0.0.1.
+    let destination = format!("localhost:{}", monitor.local_addr().unwrap().port());
+    receiver.enable_receiver_reports(interval, Some(&destination)).unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.send_frame(&[0, 0, 0, 1, 0x65, 1, 2, 3]).unwrap();
+    receiver.recv_frame().unwrap();
+    let mut report = [0u8; 1500];
+    assert!(monitor.recv(&mut report).unwrap() > 0);
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, TryLockError};
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+#[test]
+fn callbacks_cannot_reach_a_pusher_that_is_sending() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let pusher = H264RtpPusher::with_socket_addr(socket.local_addr().unwrap()).unwrap();
+    let shared = Arc::new(Mutex::new(pusher));
+
+    // The transform runs once per packet, while send_frame holds the lock.
+    let packets = Arc::new(AtomicUsize::new(0));
+    let (weak, seen) = (Arc::downgrade(&shared), packets.clone());
+    let transform: PacketTransform = Box::new(move |_: &mut Vec<u8>| {
+        let pusher = weak.upgrade().unwrap();
+        assert!(matches!(pusher.try_lock(), Err(TryLockError::WouldBlock)));
+        seen.fetch_add(1, Ordering::Relaxed);
+        true
+    });
+    shared.lock().unwrap().set_packet_transform(Some(transform), 0).unwrap();
+
+    let (frame, _) = SyntheticH264::new(4).next().unwrap();
+    shared.lock().unwrap().send_frame(&frame).unwrap();
+    assert!(packets.load(Ordering::Relaxed) > 0);
+    // Once the frame is out, the pusher is free again.
+    assert!(shared.try_lock().is_ok());
+}
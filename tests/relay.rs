@@ -0,0 +1,185 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::extension::HeaderExtension;
+use rtp_transceive::prelude::*;
+use rtp_transceive::receiver::RtcpRelayMode;
+use rtp_transceive::sender::{RtpSink, UdpSink};
+
+mod common;
+
+// Sends over UDP and keeps a copy of every packet as it left.
+struct Tee {
+    udp: UdpSink,
+    sent: mpsc::Sender<Vec<u8>>
+}
+
+impl RtpSink for Tee {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        self.sent.send_packet(packet)?;
+        self.udp.send_packet(packet)
+    }
+}
+
+// Refuses everything, like a recorder on a full disk.
+struct FullDisk;
+
+impl RtpSink for FullDisk {
+    fn send_packet(&mut self, _packet: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("no space left on device"))
+    }
+}
+
+#[test]
+fn relay_forwards_datagrams_byte_exact_while_frames_assemble() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    let (relay, relayed) = mpsc::channel::<Vec<u8>>();
+    receiver.set_relay(Some(Box::new(relay)));
+
+    let (sent, on_wire) = mpsc::channel();
+    let destination = receiver.local_addr().unwrap().to_string();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    pusher.set_sink(Some(Box::new(Tee {
+        udp: UdpSink::new(&destination).unwrap(),
+        sent
+    })));
+    // Extensions and padding must come through as they were sent.
+    pusher.set_header_extensions(vec![HeaderExtension::fixed(3, &[0xAB, 0xCD]).unwrap()]).unwrap();
+    for (frame, pts) in common::short_stream() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap(), frame);
+    }
+
+    let sent: Vec<Vec<u8>> = on_wire.try_iter().collect();
+    assert!(!sent.is_empty());
+    assert_eq!(relayed.try_iter().collect::<Vec<_>>(), sent);
+    let stats = receiver.relay_stats();
+    assert_eq!(stats.packets_relayed, sent.len() as u64);
+    assert_eq!(stats.bytes_relayed, sent.iter().map(|packet| packet.len() as u64).sum::<u64>());
+    assert_eq!(stats.send_errors, 0);
+}
+
+#[test]
+fn failing_relay_does_not_stop_the_stream() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    receiver.set_relay(Some(Box::new(FullDisk)));
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+
+    for (frame, pts) in common::short_stream() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap(), frame);
+    }
+    let stats = receiver.relay_stats();
+    assert_eq!(stats.packets_relayed, 0);
+    assert_eq!(stats.send_errors, pusher.stats().packets_sent);
+    assert_eq!(stats.last_error.as_deref(), Some("no space left on device"));
+    assert_eq!(receiver.stats().packets_received, pusher.stats().packets_sent);
+}
+
+const RELAYED_SSRC: u32 = 0x5EED_0001;
+
+// Origin -> relay -> receiver, with RTCP on at every hop. The relay reads
+// its own RTCP from the origin; the receiver sends its reports back to the
+// relay, where the origin's Sender Reports come from.
+fn relay_chain(to_receivers: RtcpRelayMode, to_origin: RtcpRelayMode) -> (H264RtpPusher, H264RtpReceiver, H264RtpReceiver) {
+    let mut downstream = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    downstream.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    downstream.set_rtcp_port(Some(0));
+    downstream.enable_receiver_reports(Duration::from_millis(1), None).unwrap();
+
+    let mut relay = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    relay.set_idle_timeout(Some(Duration::from_millis(500))).unwrap();
+    relay.set_rtcp_port(Some(0));
+    // Its own reports go out once, so the origin's latest feedback is the
+    // relayed one.
+    relay.enable_receiver_reports(Duration::from_secs(60), None).unwrap();
+    relay.set_relay(Some(Box::new(UdpSink::new(&downstream.local_addr().unwrap().to_string()).unwrap())));
+    relay.set_relay_ssrc(Some(RELAYED_SSRC)).unwrap();
+    let downstream_rtcp = downstream.rtcp_local_addr().unwrap().to_string();
+    relay.enable_relay_rtcp("127.0.0.1:0", &downstream_rtcp, to_receivers, to_origin).unwrap();
+
+    let mut pusher = H264RtpPusher::with_socket_addr(relay.local_addr().unwrap()).unwrap();
+    pusher.enable_sender_reports(Duration::from_millis(1), Some(&relay.rtcp_local_addr().unwrap().to_string())).unwrap();
+    (pusher, relay, downstream)
+}
+
+// Passes the stream down the chain a frame at a time, returning the
+// reporters of the feedback the origin got after each frame.
+fn run(pusher: &mut H264RtpPusher, relay: &mut H264RtpReceiver, downstream: &mut H264RtpReceiver) -> Vec<u32> {
+    let mut reporters = Vec::new();
+    for (frame, pts) in common::short_stream() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        assert_eq!(relay.recv_frame().unwrap(), frame);
+        assert_eq!(downstream.recv_frame().unwrap(), frame);
+        std::thread::sleep(Duration::from_millis(2));
+        pusher.poll_sender_report().unwrap();
+        reporters.extend(pusher.sender_feedback().map(|report| report.reporter_ssrc));
+    }
+    reporters
+}
+
+#[test]
+fn rtcp_is_relayed_both_ways_with_the_rewritten_ssrc() {
+    let (mut pusher, mut relay, mut downstream) = relay_chain(RtcpRelayMode::PassThrough, RtcpRelayMode::PassThrough);
+    let reporters = run(&mut pusher, &mut relay, &mut downstream);
+
+    // The receiver sees the origin's Sender Reports under the SSRC its RTP
+    // has, with the origin's CNAME in the rewritten SDES chunk.
+    let report = downstream.last_sender_report().expect("no SR made it through the relay");
+    assert_eq!(report.ssrc, RELAYED_SSRC);
+    let origin = relay.last_sender_report().unwrap();
+    assert_eq!((report.ntp, report.rtp_timestamp, report.packet_count), (origin.ntp, origin.rtp_timestamp, origin.packet_count));
+    let member = downstream.session_members().into_iter().find(|member| member.ssrc == RELAYED_SSRC).unwrap();
+    assert!(member.cname.is_some());
+
+    // Its Receiver Reports reach the origin as reports about the origin's
+    // SSRC: the origin only keeps those.
+    let downstream_ssrc = downstream.last_receiver_report().unwrap().reporter_ssrc;
+    assert!(reporters.contains(&downstream_ssrc), "{:x?}", reporters);
+    let stats = relay.relay_stats();
+    assert!(stats.rtcp_to_receivers > 0 && stats.rtcp_to_origin > 0, "{:?}", stats);
+    assert_eq!((stats.rtcp_terminated, stats.rtcp_malformed), (0, 0));
+}
+
+#[test]
+fn regenerating_relays_speak_for_themselves() {
+    let (mut pusher, mut relay, mut downstream) = relay_chain(RtcpRelayMode::Regenerate, RtcpRelayMode::Regenerate);
+    let reporters = run(&mut pusher, &mut relay, &mut downstream);
+
+    // The Sender Reports are the relay's: its counts, the origin's timing.
+    let report = downstream.last_sender_report().expect("no regenerated SR");
+    let origin = relay.last_sender_report().unwrap();
+    assert_eq!(report.ssrc, RELAYED_SSRC);
+    assert_eq!((report.ntp, report.rtp_timestamp), (origin.ntp, origin.rtp_timestamp));
+    assert!(report.packet_count as u64 <= relay.relay_stats().packets_relayed);
+
+    // The receiver's reports end at the relay.
+    let downstream_ssrc = downstream.last_receiver_report().unwrap().reporter_ssrc;
+    assert!(!reporters.contains(&downstream_ssrc));
+    let stats = relay.relay_stats();
+    assert!(stats.rtcp_terminated > 0 && stats.rtcp_to_origin == 0, "{:?}", stats);
+}
+
+#[test]
+fn relay_rtcp_addresses_resolve_like_the_pushers() {
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut relay = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    relay.set_relay(Some(Box::new(UdpSink::new(&sink.local_addr().unwrap().to_string()).unwrap())));
+
+    let pass = RtcpRelayMode::PassThrough;
+    // "localhost" may resolve to ::1 first; the IPv4 one is taken.
+    let downstream = format!("localhost:{}", sink.local_addr().unwrap().port());
+    relay.enable_relay_rtcp("127.0.0.1:0", &downstream, pass, pass).unwrap();
+    assert!(relay.relay_rtcp_local_addr().unwrap().is_ipv4());
+
+    let err = relay.enable_relay_rtcp("127.0.0.1:0", "[::1]:5004", pass, pass).unwrap_err();
+    assert!(err.to_string().contains("address families differ"), "{}", err);
+    let err = relay.enable_relay_rtcp("127.0.0.1:0", "no-such-host.invalid:5004", pass, pass).unwrap_err();
+    assert!(err.to_string().contains("no-such-host.invalid"), "{}", err);
+}
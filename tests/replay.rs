@@ -0,0 +1,70 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+// NAL types of an Annex B frame with 4-byte start codes.
+fn nal_types(frame: &[u8]) -> Vec<u8> {
+    let mut types = Vec::new();
+    for i in 0..frame.len().saturating_sub(4) {
+        if frame[i..i + 4] == [0, 0, 0, 1] {
+            types.push(frame[i + 4] & 0x1F);
+        }
+    }
+    types
+}
+
+#[test]
+fn replay_starts_at_a_keyframe_and_decodes_in_a_second_receiver() {
+    let live = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(live.local_addr().unwrap()).unwrap();
+    // 25 frames with an IDR every 10, each slice in one packet; the byte cap
+    // keeps only the last dozen frames, so the window starts in the second
+    // GOP and the replay has to skip ahead to the third IDR.
+    pusher.enable_replay_window(Duration::from_secs(60), 9_000);
+    let frames: Vec<_> = SyntheticH264::new(3)
+        .gop_length(10)
+        .slice_size(500..=500)
+        .idr_slice_size(2_000..=2_000)
+        .duration(Duration::from_secs(1))
+        .frame_rate(25)
+        .collect();
+    for (frame, pts) in &frames {
+        pusher.send_frame_with_timestamp(frame, *pts).unwrap();
+    }
+
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(300))).unwrap();
+    let sent = pusher
+        .replay_window(receiver.local_addr().unwrap(), Duration::from_secs(60), None)
+        .unwrap();
+    assert!(sent > 0);
+
+    let mut replayed = Vec::new();
+    while let Ok(frame) = receiver.recv_frame() {
+        replayed.push(frame);
+    }
+    assert_eq!(replayed.len(), 5);
+    assert_eq!(nal_types(&replayed[0]), [7, 8, 5]);
+    assert_eq!(replayed, frames[20..].iter().map(|(frame, _)| frame.clone()).collect::<Vec<_>>());
+}
+
+#[test]
+fn replay_without_a_window_is_an_error_of_its_own() {
+    let live = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(live.local_addr().unwrap()).unwrap();
+    let to = live.local_addr().unwrap();
+    assert!(matches!(
+        pusher.replay_window(to, Duration::from_secs(1), None),
+        Err(RtpError::ReplayWindowDisabled)
+    ));
+
+    pusher.enable_replay_window(Duration::from_secs(1), 1 << 20);
+    assert!(matches!(
+        pusher.replay_window(to, Duration::from_secs(1), None),
+        Err(RtpError::NoKeyframeInWindow)
+    ));
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+#[test]
+fn resumed_session_continues_with_a_small_gap() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_secs(2))).unwrap();
+    let destination = receiver.local_addr().unwrap().to_string();
+    let mut frames = SyntheticH264::new(4).gop_length(10);
+
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    // Starts close to the wrap so the gap crosses it.
+    pusher.set_next_sequence(0xFFF0).unwrap();
+    for (frame, _) in frames.by_ref().take(10) {
+        pusher.send_frame(&frame).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap(), frame);
+    }
+    let saved = SenderState::from_bytes(&pusher.save_state().to_bytes().unwrap()).unwrap();
+    let sent_before = receiver.stats().packets_received;
+    drop(pusher);
+
+    let mut pusher = H264RtpPusher::resume(&destination, saved.clone()).unwrap();
+    assert_eq!(pusher.ssrc(), saved.ssrc);
+    assert_eq!(pusher.next_sequence(), saved.next_seq.wrapping_add(64));
+    for (frame, _) in frames.take(10) {
+        pusher.send_frame(&frame).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap(), frame);
+    }
+
+    // One source throughout: the skipped numbers count as lost, nothing
+    // looks like a restart.
+    let stats = receiver.stats();
+    assert!(stats.packets_received > sent_before);
+    assert_eq!(stats.packets_lost, 64);
+}
+
+#[test]
+fn resumed_pusher_injects_the_saved_parameter_sets() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    let (first, _) = SyntheticH264::new(8).next().unwrap();
+    pusher.send_frame(&first).unwrap();
+    let state = pusher.save_state();
+    assert!(state.sps.is_some() && state.pps.is_some());
+
+    let (sender, packets) = mpsc::channel();
+    let mut pusher = H264RtpPusher::resume("127.0.0.1:9", state.clone()).unwrap();
+    pusher.set_sink(Some(Box::new(sender)));
+    pusher.set_parameter_set_injection(true);
+    // An IDR on its own: the SPS and PPS from before the restart go first.
+    let idr_start = first.windows(5).position(|window| window == [0, 0, 0, 1, 0x65]).unwrap();
+    pusher.send_frame(&first[idr_start..]).unwrap();
+
+    let sent: Vec<Vec<u8>> = packets.try_iter().collect();
+    assert_eq!(sent[0][12..], state.sps.unwrap()[..]);
+    assert_eq!(sent[1][12..], state.pps.unwrap()[..]);
+    assert!(pusher.sdp().contains("sprop-parameter-sets="));
+}
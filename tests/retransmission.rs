@@ -0,0 +1,151 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::{RtpSink, UdpSink};
+
+const FIRST_SEQ: u16 = 65_530;
+// Lost once on the way, across the sequence number wrap.
+const LOST: [u16; 2] = [65_533, 1];
+const FRAMES: u16 = 16;
+
+// Sends over UDP, except the first copy of the media packets in LOST.
+struct Lossy {
+    udp: UdpSink,
+    media_ssrc: u32,
+    dropped: Vec<u16>
+}
+
+impl RtpSink for Lossy {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        let seq = u16::from_be_bytes([packet[2], packet[3]]);
+        if ssrc(packet) == self.media_ssrc && LOST.contains(&seq) && !self.dropped.contains(&seq) {
+            self.dropped.push(seq);
+            return Ok(packet.len());
+        }
+        self.udp.send_packet(packet)
+    }
+}
+
+fn ssrc(packet: &[u8]) -> u32 {
+    u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]])
+}
+
+fn drain(socket: &UdpSocket) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut buffer = [0u8; 1500];
+    while let Ok(len) = socket.recv(&mut buffer) {
+        packets.push(buffer[..len].to_vec());
+    }
+    packets
+}
+
+// A Generic NACK for `media_ssrc`: PID `first`, and BLP bits for others
+// up to 16 after it.
+fn nack(media_ssrc: u32, first: u16, others: &[u16]) -> Vec<u8> {
+    let blp = others.iter().fold(0u16, |blp, seq| blp | 1 << (seq.wrapping_sub(first) - 1));
+    let mut packet = vec![0x81, 205, 0, 3, 0, 0, 0, 7];
+    packet.extend_from_slice(&media_ssrc.to_be_bytes());
+    packet.extend_from_slice(&first.to_be_bytes());
+    packet.extend_from_slice(&blp.to_be_bytes());
+    packet
+}
+
+#[test]
+fn nacked_packets_are_resent_as_rtx_without_touching_the_media_stream() {
+    let media = UdpSocket::bind("127.0.0.1:0").unwrap();
+    media.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    let rtcp = UdpSocket::bind("127.0.0.1:0").unwrap();
+    rtcp.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    let destination = media.local_addr().unwrap().to_string();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    pusher.set_sink(Some(Box::new(Lossy {
+        udp: UdpSink::new(&destination).unwrap(),
+        media_ssrc: pusher.ssrc(),
+        dropped: Vec::new()
+    })));
+    pusher.set_next_sequence(FIRST_SEQ).unwrap();
+    pusher.enable_sender_reports(Duration::from_millis(1), Some(&rtcp.local_addr().unwrap().to_string())).unwrap();
+    pusher.enable_retransmission(97, 64).unwrap();
+    let rtx_ssrc = pusher.rtx_ssrc().unwrap();
+    assert_ne!(rtx_ssrc, pusher.ssrc());
+    assert!(pusher.sdp().contains("a=fmtp:97 apt=96\r\n"));
+
+    let frame = |index: u16| [0, 0, 0, 1, 0x65, index as u8, 2, 3];
+    for index in 0..FRAMES / 2 {
+        pusher.send_frame(&frame(index)).unwrap();
+    }
+    let mut received = drain(&media);
+
+    // The receiver asks for what it missed, and one packet it never could
+    // have had.
+    let media_ssrc = pusher.ssrc();
+    let received_seqs: Vec<u16> = received.iter().map(|packet| u16::from_be_bytes([packet[2], packet[3]])).collect();
+    let missing: Vec<u16> = (0..FRAMES / 2).map(|index| FIRST_SEQ.wrapping_add(index)).filter(|seq| !received_seqs.contains(seq)).collect();
+    assert_eq!(missing, LOST);
+    let pusher_rtcp: SocketAddr = (std::net::Ipv4Addr::LOCALHOST, pusher.rtcp_local_addr().unwrap().unwrap().port()).into();
+    rtcp.send_to(&nack(media_ssrc, LOST[0], &[LOST[1], LOST[1].wrapping_add(10)]), pusher_rtcp).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while pusher.stats().rtx.nacks_received == 0 && Instant::now() < deadline {
+        pusher.poll_sender_report().unwrap();
+        thread::sleep(Duration::from_millis(1));
+    }
+    for index in FRAMES / 2..FRAMES {
+        pusher.send_frame(&frame(index)).unwrap();
+    }
+    received.extend(drain(&media));
+
+    // The media stream runs on without a jump; only the lost packets are
+    // missing from it.
+    let media_seqs: Vec<u16> = received
+        .iter()
+        .filter(|packet| ssrc(packet) == media_ssrc)
+        .map(|packet| u16::from_be_bytes([packet[2], packet[3]]))
+        .collect();
+    let expected: Vec<u16> = (0..FRAMES).map(|index| FIRST_SEQ.wrapping_add(index)).filter(|seq| !LOST.contains(seq)).collect();
+    assert_eq!(media_seqs, expected);
+
+    // The RTX packets have their own consecutive sequence numbers, and the
+    // original sequence number before the original payload.
+    let rtx: Vec<&Vec<u8>> = received.iter().filter(|packet| ssrc(packet) == rtx_ssrc).collect();
+    assert_eq!(rtx.len(), 2);
+    let rtx_seq = |packet: &[u8]| u16::from_be_bytes([packet[2], packet[3]]);
+    assert_eq!(rtx_seq(rtx[1]), rtx_seq(rtx[0]).wrapping_add(1));
+    for (packet, (lost, index)) in rtx.iter().zip([(LOST[0], 3u8), (LOST[1], 7)]) {
+        assert_eq!(packet[1] & 0x7F, 97);
+        assert_eq!(packet[12..14], lost.to_be_bytes());
+        assert_eq!(packet[14..], [0x65, index, 2, 3]);
+    }
+
+    let stats = pusher.stats();
+    assert_eq!(stats.packets_sent, FRAMES as u64);
+    assert_eq!(
+        (stats.rtx.nacks_received, stats.rtx.packets_requested, stats.rtx.packets_sent, stats.rtx.packets_unavailable),
+        (1, 3, 2, 1)
+    );
+    assert_eq!(stats.rtx.bytes_sent, 2 * (12 + 2 + 4));
+
+    // Each stream's Sender Report counts its own packets.
+    thread::sleep(Duration::from_millis(2));
+    drain(&rtcp);
+    assert!(pusher.poll_sender_report().unwrap());
+    let mut buffer = [0u8; 1500];
+    let len = rtcp.recv(&mut buffer).unwrap();
+    let mut reports = Vec::new();
+    let mut rest = &buffer[..len];
+    while rest.len() >= 4 {
+        let length = 4 * (u16::from_be_bytes([rest[2], rest[3]]) as usize + 1);
+        if rest[1] == 200 {
+            let read_u32 = |offset: usize| u32::from_be_bytes([rest[offset], rest[offset + 1], rest[offset + 2], rest[offset + 3]]);
+            reports.push((read_u32(4), read_u32(20)));
+        }
+        rest = &rest[length..];
+    }
+    assert_eq!(reports, [(media_ssrc, FRAMES as u32), (rtx_ssrc, 2)]);
+}
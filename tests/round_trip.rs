@@ -0,0 +1,48 @@
+#![cfg(feature = "net")]
+
+use rtp_transceive::prelude::*;
+
+mod common;
+
+// Sends the stream one frame at a time and checks each comes out as it
+// went in, at its timestamp.
+fn send_and_receive(pusher: &mut H264RtpPusher, receiver: &mut H264RtpReceiver) {
+    for (frame, pts) in common::fragmenting_stream() {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+        let received = receiver.recv_annotated_frame().unwrap();
+        assert_eq!(received.timestamp, pts);
+        assert!(received.data == frame, "frame at {} differs", pts);
+    }
+    let stats = receiver.stats();
+    assert_eq!((stats.packets_lost, stats.out_of_order, stats.duplicates), (0, 0, 0));
+    assert_eq!(stats.packets_received, pusher.stats().packets_sent);
+}
+
+#[test]
+fn frames_come_through_loopback_unchanged() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_mtu(1_200).unwrap();
+    send_and_receive(&mut pusher, &mut receiver);
+}
+
+#[test]
+fn a_sequence_number_wrap_loses_nothing() {
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_next_sequence(u16::MAX - 50).unwrap();
+    send_and_receive(&mut pusher, &mut receiver);
+    assert!(pusher.next_sequence() < u16::MAX - 50);
+}
+
+#[cfg(feature = "srtp")]
+#[test]
+fn srtp_frames_come_through_across_a_rollover() {
+    use rtp_transceive::srtp::SrtpKey;
+
+    let key = SrtpKey::new([0x5A; 16], [0xA5; 14]);
+    let (mut pusher, mut receiver) = common::pair();
+    pusher.set_srtp(Some(&key)).unwrap();
+    receiver.set_srtp(Some(&key));
+    pusher.set_next_sequence(u16::MAX - 50).unwrap();
+    send_and_receive(&mut pusher, &mut receiver);
+    assert_eq!(receiver.stats().srtp_auth_failures, 0);
+}
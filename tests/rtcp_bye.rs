@@ -0,0 +1,49 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use rtp_transceive::sender::H264RtpPusher;
+
+// A socket on the RTCP port (RTP port + 1) of a destination on localhost.
+fn rtcp_listener() -> (UdpSocket, String) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let rtp_port = socket.local_addr().unwrap().port() - 1;
+    (socket, format!("127.0.0.1:{}", rtp_port))
+}
+
+#[test]
+fn close_without_sender_reports_sends_no_rtcp() {
+    let (listener, destination) = rtcp_listener();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    pusher.close().unwrap();
+
+    let mut buffer = [0u8; 1500];
+    assert!(listener.recv(&mut buffer).is_err());
+}
+
+#[test]
+fn close_with_sender_reports_sends_bye() {
+    let (listener, destination) = rtcp_listener();
+    let mut pusher = H264RtpPusher::new(&destination).unwrap();
+    pusher.enable_sender_reports(Duration::from_secs(5), None).unwrap();
+    pusher.set_bye_reason(Some("done"));
+    pusher.close().unwrap();
+
+    let mut buffer = [0u8; 1500];
+    let len = listener.recv(&mut buffer).unwrap();
+    // A compound packet: RR, SDES with the CNAME, then BYE (type 203).
+    let mut offset = 0;
+    let mut bye = None;
+    while offset + 4 <= len {
+        let end = offset + (u16::from_be_bytes([buffer[offset + 2], buffer[offset + 3]]) as usize + 1) * 4;
+        if buffer[offset + 1] == 203 {
+            bye = Some(&buffer[offset..end]);
+        }
+        offset = end;
+    }
+    let bye = bye.expect("no BYE in the compound packet");
+    assert_eq!(bye[4..8], pusher.ssrc().to_be_bytes());
+    assert_eq!(&bye[9..9 + bye[8] as usize], b"done");
+}
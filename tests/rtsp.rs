@@ -0,0 +1,94 @@
+#![cfg(feature = "net")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::rtsp::RtspServer;
+use rtp_transceive::testsupport::SyntheticH264;
+use rtp_transceive::time;
+
+// Sends one request and returns the response's status line and headers.
+fn request(client: &mut TcpStream, reader: &mut impl BufRead, request: &str) -> (String, Vec<String>) {
+    client.write_all(request.as_bytes()).unwrap();
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            return (status, headers);
+        }
+        headers.push(line.trim().to_owned());
+    }
+}
+
+#[test]
+fn dropping_the_server_closes_connections_and_frees_the_port() {
+    let server = RtspServer::bind("127.0.0.1:0", "stream").unwrap();
+    let address = server.local_addr();
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    client.write_all(b"OPTIONS * RTSP/1.0\r\nCSeq: 1\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert_eq!(status, "RTSP/1.0 200 OK\r\n");
+
+    // The connection thread is blocked reading the next request.
+    drop(server);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert!(String::from_utf8_lossy(&rest).contains("Public:"));
+    TcpListener::bind(address).unwrap();
+}
+
+#[test]
+fn late_joiner_gets_the_gop_so_far_before_the_live_stream() {
+    let server = RtspServer::bind("127.0.0.1:0", "stream").unwrap();
+    server.set_late_join_burst(Some(8));
+    let sink = server.frame_sink();
+    let frames: Vec<Vec<u8>> = SyntheticH264::new(3).gop_length(30).map(|(frame, _)| frame).take(40).collect();
+    // Joins at frame 35, five frames after the last IDR.
+    for frame in &frames[..35] {
+        sink.send_frame(frame).unwrap();
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_secs(2))).unwrap();
+    let rtp_port = receiver.local_addr().unwrap().port();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut reader = BufReader::new(client.try_clone().unwrap());
+    let url = format!("rtsp://{}/stream", server.local_addr());
+    let (status, headers) = request(
+        &mut client,
+        &mut reader,
+        &format!("SETUP {url}/trackID=0 RTSP/1.0\r\nCSeq: 1\r\nTransport: RTP/AVP;unicast;client_port={}-{}\r\n\r\n", rtp_port, rtp_port + 1)
+    );
+    assert_eq!(status, "RTSP/1.0 200 OK\r\n");
+    let session = headers.iter().find_map(|header| header.strip_prefix("Session: ")).unwrap();
+    let session = session.split(';').next().unwrap().to_owned();
+    let (status, _) = request(&mut client, &mut reader, &format!("PLAY {url} RTSP/1.0\r\nCSeq: 2\r\nSession: {session}\r\n\r\n"));
+    assert_eq!(status, "RTSP/1.0 200 OK\r\n");
+    // Live frames line up behind the burst.
+    for frame in &frames[35..] {
+        sink.send_frame(frame).unwrap();
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let mut previous_timestamp = None;
+    for expected in &frames[30..] {
+        let frame = receiver.recv_annotated_frame().unwrap();
+        assert_eq!(&frame.data, expected);
+        if let Some(previous) = previous_timestamp {
+            assert!(time::wire_diff(frame.timestamp, previous) > 0);
+        }
+        previous_timestamp = Some(frame.timestamp);
+    }
+    assert_eq!(receiver.stats().packets_lost, 0);
+}
@@ -0,0 +1,110 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::sender::RtpSink;
+
+#[derive(Clone, Default)]
+struct SinkLog {
+    single_calls: usize,
+    batch_calls: usize,
+    packets: Vec<Vec<u8>>
+}
+
+// Counts calls and keeps the packets; accepts at most `accept` per batch.
+#[derive(Clone)]
+struct CountingSink {
+    log: Arc<Mutex<SinkLog>>,
+    accept: usize
+}
+
+impl CountingSink {
+    fn new(accept: usize) -> Self {
+        Self {
+            log: Arc::default(),
+            accept
+        }
+    }
+}
+
+impl RtpSink for CountingSink {
+    fn send_packet(&mut self, packet: &[u8]) -> io::Result<usize> {
+        let mut log = self.log.lock().unwrap();
+        log.single_calls += 1;
+        log.packets.push(packet.to_vec());
+        Ok(packet.len())
+    }
+
+    fn send_batch(&mut self, packets: &[&[u8]]) -> io::Result<usize> {
+        let mut log = self.log.lock().unwrap();
+        log.batch_calls += 1;
+        let accepted = packets.len().min(self.accept);
+        log.packets.extend(packets[..accepted].iter().map(|packet| packet.to_vec()));
+        Ok(accepted)
+    }
+}
+
+fn frame(size: usize) -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 1, 0x65];
+    frame.extend((0..size).map(|i| (i % 255) as u8 + 1));
+    frame
+}
+
+// Packets the pusher sends for `frame` with the given batching, along with
+// the single and batched send calls it took.
+fn send(batch_size: Option<usize>, accept: usize) -> (usize, usize, Vec<Vec<u8>>) {
+    let sink = CountingSink::new(accept);
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sink.clone())));
+    pusher.set_ssrc(1);
+    pusher.set_next_sequence(0).unwrap();
+    pusher.set_mtu(1_000).unwrap();
+    pusher.set_send_batching(batch_size).unwrap();
+    pusher.send_frame_with_timestamp(&frame(20_000), 0).unwrap();
+    let log = sink.log.lock().unwrap();
+    (log.single_calls, log.batch_calls, log.packets.clone())
+}
+
+#[test]
+fn a_frame_goes_out_in_few_batched_calls() {
+    let (single, batched, unbatched_packets) = send(None, usize::MAX);
+    assert_eq!(batched, 0);
+    let packets = single;
+    assert!(packets > 20);
+
+    let (single, batched, batched_packets) = send(Some(8), usize::MAX);
+    assert_eq!(single, 0);
+    assert_eq!(batched, packets.div_ceil(8));
+    assert_eq!(batched_packets, unbatched_packets);
+}
+
+#[test]
+fn packets_a_sink_did_not_take_are_passed_again() {
+    let (_, _, unbatched_packets) = send(None, usize::MAX);
+    let (_, batched, packets) = send(Some(8), 3);
+    assert_eq!(packets, unbatched_packets);
+    assert!(batched >= unbatched_packets.len().div_ceil(3));
+}
+
+#[test]
+fn batched_udp_stream_reaches_a_receiver() {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut pusher = H264RtpPusher::with_socket_addr(receiver.local_addr().unwrap()).unwrap();
+    pusher.set_send_batching(Some(16)).unwrap();
+    for ts in 0..5 {
+        let frame = frame(10_000 + ts as usize);
+        pusher.send_frame_with_timestamp(&frame, ts * 3_000).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap(), frame);
+    }
+    assert!(pusher.send_batches() >= 5);
+}
+
+#[test]
+fn batch_size_zero_is_rejected() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    assert!(matches!(pusher.set_send_batching(Some(0)), Err(RtpError::InvalidBatchSize(0))));
+}
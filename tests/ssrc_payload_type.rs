@@ -0,0 +1,47 @@
+#![cfg(feature = "net")]
+
+use std::sync::mpsc;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::testsupport::SyntheticH264;
+
+#[test]
+fn packets_carry_the_configured_payload_type_and_ssrc() {
+    let (sender, packets) = mpsc::channel();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sender)));
+    pusher.set_ssrc(0xDEAD_BEEF);
+    pusher.set_payload_type(102).unwrap();
+    pusher.set_keepalive(Some(std::time::Duration::ZERO));
+
+    for (frame, pts) in SyntheticH264::new(1).take(3) {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    pusher.poll_keepalive().unwrap();
+
+    let sent: Vec<Vec<u8>> = packets.try_iter().collect();
+    assert!(sent.len() > 3);
+    for packet in &sent {
+        assert_eq!(packet[1] & 0x7F, 102);
+        assert_eq!(packet[8..12], [0xDE, 0xAD, 0xBE, 0xEF]);
+    }
+    assert!(pusher.sdp().contains("a=rtpmap:102 H264/90000"));
+}
+
+#[test]
+fn payload_types_above_127_are_rejected() {
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    assert!(matches!(pusher.set_payload_type(128), Err(RtpError::InvalidPayloadType(128))));
+    assert_eq!(pusher.payload_type(), 96);
+
+    let mut pusher = H265RtpPusher::new("127.0.0.1:9").unwrap();
+    assert!(matches!(pusher.set_payload_type(200), Err(RtpError::InvalidPayloadType(200))));
+    pusher.set_payload_type(127).unwrap();
+    assert_eq!(pusher.payload_type(), 127);
+}
+
+#[test]
+fn ssrc_is_random_unless_set() {
+    let ssrcs: Vec<u32> = (0..4).map(|_| H264RtpPusher::new("127.0.0.1:9").unwrap().ssrc()).collect();
+    assert!(ssrcs.windows(2).any(|pair| pair[0] != pair[1]));
+}
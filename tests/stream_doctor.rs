@@ -0,0 +1,74 @@
+#![cfg(feature = "net")]
+
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use rtp_transceive::prelude::*;
+use rtp_transceive::stats::Pathology;
+use rtp_transceive::testsupport::SyntheticH264;
+
+const RTP_HEADER_SIZE: usize = 12;
+const FU_START: u8 = 1 << 7;
+
+// The packets of a short stream whose IDR slices are fragmented.
+fn packets() -> Vec<Vec<u8>> {
+    let stream = SyntheticH264::new(8).gop_length(5).slice_size(200..=400).idr_slice_size(2_000..=3_000).duration(Duration::from_millis(400));
+    let (sink, sent) = mpsc::channel();
+    let mut pusher = H264RtpPusher::new("127.0.0.1:9").unwrap();
+    pusher.set_sink(Some(Box::new(sink)));
+    for (frame, pts) in stream {
+        pusher.send_frame_with_timestamp(&frame, pts).unwrap();
+    }
+    sent.try_iter().collect()
+}
+
+fn is_fu_start(packet: &[u8]) -> bool {
+    packet[RTP_HEADER_SIZE] & 0x1F == 28 && packet[RTP_HEADER_SIZE + 1] & FU_START != 0
+}
+
+fn receive(packets: &[Vec<u8>], doctor: bool) -> H264RtpReceiver {
+    let mut receiver = H264RtpReceiver::new("127.0.0.1:0").unwrap();
+    receiver.set_idle_timeout(Some(Duration::from_millis(200))).unwrap();
+    receiver.set_stream_doctor(doctor);
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for packet in packets {
+        socket.send_to(packet, receiver.local_addr().unwrap()).unwrap();
+    }
+    loop {
+        match receiver.recv_frame() {
+            Ok(_) => {}
+            Err(RtpError::IdleTimeout) => return receiver,
+            Err(err) => panic!("{}", err)
+        }
+    }
+}
+
+#[test]
+fn a_clean_stream_has_no_findings() {
+    let receiver = receive(&packets(), true);
+    assert!(receiver.frame_stats().frames > 0);
+    assert!(receiver.diagnostics().is_empty());
+}
+
+#[test]
+fn a_camera_dropping_start_bits_is_diagnosed() {
+    // The camera clears the Start bit of the first IDR's fragments and
+    // sets forbidden_zero_bit on the packet after the second IDR's.
+    let mut packets = packets();
+    let starts: Vec<usize> = (0..packets.len()).filter(|&index| is_fu_start(&packets[index])).collect();
+    packets[starts[0]][RTP_HEADER_SIZE + 1] &= !FU_START;
+    packets[starts[1] + 1][RTP_HEADER_SIZE] |= 0x80;
+    let receiver = receive(&packets, true);
+
+    let findings = receiver.diagnostics();
+    let pathologies: Vec<(Pathology, u64)> = findings.iter().map(|finding| (finding.pathology, finding.occurrences)).collect();
+    assert_eq!(pathologies, [(Pathology::FuStartMissing, 1), (Pathology::ForbiddenZeroBit, 1)]);
+    let sequence = |index: usize| u16::from_be_bytes([packets[index][2], packets[index][3]]);
+    // The fragment whose bit was cleared is the first orphan.
+    assert_eq!(findings[0].first_sequence, sequence(starts[0]));
+    assert_eq!(findings[1].first_sequence, sequence(starts[1] + 1));
+
+    // Off, nothing is looked at.
+    assert!(receive(&packets, false).diagnostics().is_empty());
+}